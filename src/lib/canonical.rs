@@ -0,0 +1,158 @@
+//! Canonical URLs, which real servers redirect everything else to: plain
+//! HTTP to HTTPS, and hosts to or from their `www.` prefix. Directories
+//! are redirected to with their trailing slash by the router, as only it
+//! knows what's a directory.
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{
+    http::{
+        request::Request,
+        response::{Response, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    persona::Persona,
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CanonicalConfig {
+    /// whether plain HTTP requests are redirected to HTTPS. httpot doesn't
+    /// speak TLS, so the redirect only leads somewhere if something in
+    /// front of it does.
+    pub https: bool,
+    pub www: Www,
+}
+
+/// What's done with a host's `www.` prefix. Addresses and single label
+/// hosts such as localhost are left alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Www {
+    /// hosts are answered as requested
+    #[default]
+    Keep,
+    /// hosts without the prefix are redirected to it
+    Add,
+    /// hosts with the prefix are redirected without it
+    Remove,
+}
+
+/// Redirects requests to their canonical URL, as the persona would.
+#[derive(Debug, Clone)]
+pub struct Canonicalize {
+    config: Arc<CanonicalConfig>,
+    persona: Persona,
+}
+
+impl Canonicalize {
+    pub fn new(config: CanonicalConfig, persona: Persona) -> Self {
+        Self {
+            config: Arc::new(config),
+            persona,
+        }
+    }
+
+    /// the request's canonical URL, if it isn't already.
+    fn canonical(&self, req: &Request) -> Option<url::Url> {
+        if req.is_asterisk_form() {
+            return None;
+        }
+        let mut url = req.url.clone();
+        if self.config.https && url.scheme() == "http" {
+            url.set_scheme("https").ok()?;
+            // the default port, since the requested one spoke plain HTTP
+            url.set_port(None).ok()?;
+        }
+        if let Some(url::Host::Domain(host)) = req.url.host() {
+            let www = host.to_lowercase().starts_with("www.");
+            let host = match self.config.www {
+                Www::Add if !www && host.contains('.') => Some(format!("www.{}", host)),
+                Www::Remove if www && host[4..].contains('.') => Some(host[4..].to_string()),
+                _ => None,
+            };
+            if let Some(host) = host {
+                url.set_host(Some(&host)).ok()?;
+            }
+        }
+        (url != req.url).then_some(url)
+    }
+}
+
+impl Middleware for Canonicalize {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.canonical(req) {
+                Some(url) => Ok(self
+                    .persona
+                    .redirect(
+                        next.into_conn(),
+                        StatusCode::MovedPermanently,
+                        url.as_str(),
+                        req,
+                    )
+                    .build()?),
+                None => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("canonical")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{http::request::Method, persona::Family};
+
+    fn stub_request(url: &str) -> Request {
+        let mut req = Request::stub(Method::GET, "/", "");
+        req.url = url.parse().unwrap();
+        req.target = req.url.path().to_string();
+        req
+    }
+
+    #[test]
+    fn test_canonical() {
+        let canonical = |config, url| {
+            Canonicalize::new(config, Persona::from(Family::Apache))
+                .canonical(&stub_request(url))
+                .map(|u| u.to_string())
+        };
+        let https = CanonicalConfig {
+            https: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Some("https://example.com/a?b=c".to_string()),
+            canonical(https.clone(), "http://example.com:8080/a?b=c")
+        );
+        assert_eq!(None, canonical(https, "https://example.com/a"));
+
+        let add = CanonicalConfig {
+            www: Www::Add,
+            ..Default::default()
+        };
+        assert_eq!(
+            Some("http://www.example.com/".to_string()),
+            canonical(add.clone(), "http://example.com/")
+        );
+        assert_eq!(None, canonical(add.clone(), "http://www.example.com/"));
+        assert_eq!(None, canonical(add.clone(), "http://localhost/"));
+        assert_eq!(None, canonical(add, "http://203.0.113.7/"));
+
+        let remove = CanonicalConfig {
+            www: Www::Remove,
+            ..Default::default()
+        };
+        assert_eq!(
+            Some("http://example.com/x/".to_string()),
+            canonical(remove, "http://www.example.com/x/")
+        );
+        assert_eq!(None, canonical(Default::default(), "http://example.com/"));
+    }
+}
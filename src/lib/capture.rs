@@ -0,0 +1,634 @@
+//! Captures of each connection's raw bytes, written as pcap files so
+//! Wireshark and IDS rulesets can be run over honeypot traffic offline.
+//! Only payloads are seen, so IP and TCP headers are synthesized around
+//! them, with a handshake before and a close after. Trickled tarpit
+//! bodies aren't captured. With privacy configured, forwarding headers'
+//! addresses are replaced in what the client sent, as they are in
+//! requests. Captures, and other pcaps of TCP traffic, can be read back
+//! into the bytes each connection carried.
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use chrono::{DateTime, TimeZone, Utc};
+use rand::prelude::*;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::{prelude::*, privacy::Anonymizer};
+
+// classic pcap, with microsecond timestamps
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+// packets start at their IP header
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_ETHERNET: u32 = 1;
+const MSS: usize = 1448;
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// directory captures are written to, one file per connection
+    pub dir: PathBuf,
+    /// bytes captured per connection, in both directions; the rest is
+    /// dropped
+    pub max_bytes: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("captures"),
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    at: DateTime<Utc>,
+    direction: Direction,
+    data: Vec<u8>,
+}
+
+/// The bytes of a connection, in the order they were sent.
+#[derive(Debug)]
+pub struct Capture {
+    client: SocketAddr,
+    server: SocketAddr,
+    opened: DateTime<Utc>,
+    max_bytes: usize,
+    segments: Mutex<Vec<Segment>>,
+    discarded: AtomicBool,
+    privacy: Option<Anonymizer>,
+}
+
+impl Capture {
+    pub fn new(client: SocketAddr, server: SocketAddr, max_bytes: usize) -> Self {
+        Self {
+            client,
+            server,
+            opened: Utc::now(),
+            max_bytes,
+            segments: Default::default(),
+            discarded: Default::default(),
+            privacy: None,
+        }
+    }
+
+    /// the capture, replacing forwarding headers' addresses in what the
+    /// client sent when written.
+    pub fn anonymized(self, privacy: Option<Anonymizer>) -> Self {
+        Self { privacy, ..self }
+    }
+
+    /// records bytes sent by the client.
+    pub fn inbound(&self, data: &[u8]) {
+        self.push(Direction::Inbound, data)
+    }
+
+    /// records bytes sent to the client.
+    pub fn outbound(&self, data: &[u8]) {
+        self.push(Direction::Outbound, data)
+    }
+
+    /// marks the capture as not worth writing.
+    pub fn discard(&self) {
+        self.discarded.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_discarded(&self) -> bool {
+        self.discarded.load(Ordering::Relaxed)
+    }
+
+    fn push(&self, direction: Direction, data: &[u8]) {
+        let mut segments = self.segments.lock().unwrap();
+        let captured: usize = segments.iter().map(|s| s.data.len()).sum();
+        let data = &data[..data.len().min(self.max_bytes.saturating_sub(captured))];
+        if data.is_empty() {
+            return;
+        }
+        segments.push(Segment {
+            at: Utc::now(),
+            direction,
+            data: data.to_vec(),
+        });
+    }
+
+    // the segments, anonymized. Runs of inbound segments are joined first,
+    // so headers split across reads are replaced whole.
+    fn segments(&self) -> Vec<Segment> {
+        let segments = self.segments.lock().unwrap().clone();
+        let privacy = match &self.privacy {
+            Some(privacy) => privacy,
+            None => return segments,
+        };
+        let mut joined: Vec<Segment> = vec![];
+        for segment in segments {
+            match joined.last_mut() {
+                Some(last)
+                    if last.direction == Direction::Inbound
+                        && segment.direction == Direction::Inbound =>
+                {
+                    last.data.extend(segment.data)
+                }
+                _ => joined.push(segment),
+            }
+        }
+        for segment in &mut joined {
+            if segment.direction == Direction::Inbound {
+                segment.data = privacy.head(&segment.data);
+            }
+        }
+        joined
+    }
+
+    /// writes the capture to a new file in the directory, named for when
+    /// the connection opened and who opened it.
+    pub fn write(&self, dir: &Path) -> Result<PathBuf> {
+        let name = format!(
+            "{}-{}-{}.pcap",
+            self.opened.format("%Y%m%dT%H%M%S%.6fZ"),
+            self.client.ip().to_string().replace(':', "_"),
+            self.client.port()
+        );
+        let path = dir.join(name);
+        std::fs::write(&path, self.to_pcap())?;
+        Ok(path)
+    }
+
+    /// the capture as a pcap file.
+    pub fn to_pcap(&self) -> Vec<u8> {
+        let mut pcap = vec![];
+        pcap.extend(PCAP_MAGIC.to_le_bytes());
+        pcap.extend(2u16.to_le_bytes());
+        pcap.extend(4u16.to_le_bytes());
+        pcap.extend(0i32.to_le_bytes());
+        pcap.extend(0u32.to_le_bytes());
+        pcap.extend(65535u32.to_le_bytes());
+        pcap.extend(LINKTYPE_RAW.to_le_bytes());
+
+        let segments = self.segments();
+        let closed = segments.last().map(|s| s.at).unwrap_or(self.opened);
+        let mut flow = Flow::new(self.client, self.server);
+        let mut packets = flow.open(self.opened);
+        for segment in &segments {
+            for chunk in segment.data.chunks(MSS) {
+                packets.push(flow.data(segment.at, segment.direction, chunk));
+            }
+        }
+        packets.extend(flow.close(closed));
+
+        for (at, packet) in packets {
+            pcap.extend((at.timestamp() as u32).to_le_bytes());
+            pcap.extend(at.timestamp_subsec_micros().to_le_bytes());
+            pcap.extend((packet.len() as u32).to_le_bytes());
+            pcap.extend((packet.len() as u32).to_le_bytes());
+            pcap.extend(packet);
+        }
+        pcap
+    }
+}
+
+/// The bytes a connection carried in each direction, as read from a pcap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    /// when the connection's first packet was captured
+    pub opened: DateTime<Utc>,
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub inbound: Vec<u8>,
+    pub outbound: Vec<u8>,
+}
+
+/// reads the TCP connections in a pcap, in the order they were opened.
+/// Whoever sent a connection's first packet is taken to be its client;
+/// segments are assumed to be in order and aren't deduplicated.
+pub fn read_pcap(pcap: &[u8]) -> Result<Vec<Connection>> {
+    if pcap.len() < 24 {
+        bail!("pcap is too short for its header");
+    }
+    let magic = u32::from_le_bytes(pcap[..4].try_into()?);
+    let swapped = match magic {
+        PCAP_MAGIC | 0xa1b2_3c4d => false,
+        _ if magic.swap_bytes() == PCAP_MAGIC || magic.swap_bytes() == 0xa1b2_3c4d => true,
+        _ => bail!("not a pcap file, magic {:#x}", magic),
+    };
+    let u32_at = |b: &[u8]| {
+        let n = u32::from_le_bytes(b[..4].try_into().unwrap());
+        if swapped {
+            n.swap_bytes()
+        } else {
+            n
+        }
+    };
+    let skip = match u32_at(&pcap[20..]) {
+        LINKTYPE_RAW => 0,
+        LINKTYPE_ETHERNET => 14,
+        link => bail!("unsupported pcap link type {}", link),
+    };
+
+    let mut connections: Vec<Connection> = vec![];
+    let mut flows: HashMap<(SocketAddr, SocketAddr), usize> = HashMap::new();
+    let mut rest = &pcap[24..];
+    while rest.len() >= 16 {
+        let at = Utc
+            .timestamp_opt(u32_at(rest) as i64, 0)
+            .single()
+            .unwrap_or_default();
+        let len = u32_at(&rest[8..]) as usize;
+        let packet = rest
+            .get(16..16 + len)
+            .ok_or_else(|| anyhow!("pcap is truncated"))?;
+        rest = &rest[16 + len..];
+
+        let (src, dst, payload) = match packet.get(skip..).and_then(tcp_payload) {
+            Some(tcp) => tcp,
+            None => continue,
+        };
+        let i = match flows.get(&(dst, src)) {
+            Some(&i) => {
+                connections[i].outbound.extend(payload);
+                continue;
+            }
+            None => *flows.entry((src, dst)).or_insert_with(|| {
+                connections.push(Connection {
+                    opened: at,
+                    client: src,
+                    server: dst,
+                    inbound: vec![],
+                    outbound: vec![],
+                });
+                connections.len() - 1
+            }),
+        };
+        connections[i].inbound.extend(payload);
+    }
+    Ok(connections)
+}
+
+/// the addresses and payload of a TCP packet starting at its IP header.
+fn tcp_payload(packet: &[u8]) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+    let (src, dst, tcp): (IpAddr, IpAddr, _) = match packet.first()? >> 4 {
+        4 => {
+            let header = ((packet[0] & 0x0f) as usize) * 4;
+            let total = u16::from_be_bytes(packet.get(2..4)?.try_into().ok()?) as usize;
+            if *packet.get(9)? != 6 {
+                return None;
+            }
+            let src: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            // ethernet frames may be padded past the end of the packet
+            (
+                src.into(),
+                dst.into(),
+                packet.get(header..total.min(packet.len()))?,
+            )
+        }
+        6 => {
+            if *packet.get(6)? != 6 {
+                return None;
+            }
+            let src: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            let len = u16::from_be_bytes(packet.get(4..6)?.try_into().ok()?) as usize;
+            (
+                src.into(),
+                dst.into(),
+                packet.get(40..(40 + len).min(packet.len()))?,
+            )
+        }
+        _ => return None,
+    };
+
+    let sport = u16::from_be_bytes(tcp.get(0..2)?.try_into().ok()?);
+    let dport = u16::from_be_bytes(tcp.get(2..4)?.try_into().ok()?);
+    let offset = ((tcp.get(12)? >> 4) as usize) * 4;
+    Some((
+        SocketAddr::new(src, sport),
+        SocketAddr::new(dst, dport),
+        tcp.get(offset..)?,
+    ))
+}
+
+/// TCP state for synthesizing a connection's packets.
+struct Flow {
+    client: (IpAddr, u16),
+    server: (IpAddr, u16),
+    // the next sequence number each side sends
+    client_seq: u32,
+    server_seq: u32,
+    ip_id: u16,
+}
+
+impl Flow {
+    fn new(client: SocketAddr, server: SocketAddr) -> Self {
+        let (client_ip, server_ip) = match (canonical(client.ip()), canonical(server.ip())) {
+            (IpAddr::V4(c), IpAddr::V6(s)) => (IpAddr::V6(c.to_ipv6_mapped()), IpAddr::V6(s)),
+            (IpAddr::V6(c), IpAddr::V4(s)) => (IpAddr::V6(c), IpAddr::V6(s.to_ipv6_mapped())),
+            ips => ips,
+        };
+        let mut rng = thread_rng();
+        Self {
+            client: (client_ip, client.port()),
+            server: (server_ip, server.port()),
+            client_seq: rng.gen(),
+            server_seq: rng.gen(),
+            ip_id: rng.gen(),
+        }
+    }
+
+    fn open(&mut self, at: DateTime<Utc>) -> Vec<(DateTime<Utc>, Vec<u8>)> {
+        let syn = self.packet(Direction::Inbound, SYN, &[]);
+        self.client_seq = self.client_seq.wrapping_add(1);
+        let syn_ack = self.packet(Direction::Outbound, SYN | ACK, &[]);
+        self.server_seq = self.server_seq.wrapping_add(1);
+        let ack = self.packet(Direction::Inbound, ACK, &[]);
+        vec![(at, syn), (at, syn_ack), (at, ack)]
+    }
+
+    fn data(
+        &mut self,
+        at: DateTime<Utc>,
+        direction: Direction,
+        payload: &[u8],
+    ) -> (DateTime<Utc>, Vec<u8>) {
+        let packet = self.packet(direction, PSH | ACK, payload);
+        let seq = match direction {
+            Direction::Inbound => &mut self.client_seq,
+            Direction::Outbound => &mut self.server_seq,
+        };
+        *seq = seq.wrapping_add(payload.len() as u32);
+        (at, packet)
+    }
+
+    fn close(&mut self, at: DateTime<Utc>) -> Vec<(DateTime<Utc>, Vec<u8>)> {
+        let server_fin = self.packet(Direction::Outbound, FIN | ACK, &[]);
+        self.server_seq = self.server_seq.wrapping_add(1);
+        let client_fin = self.packet(Direction::Inbound, FIN | ACK, &[]);
+        self.client_seq = self.client_seq.wrapping_add(1);
+        let ack = self.packet(Direction::Outbound, ACK, &[]);
+        vec![(at, server_fin), (at, client_fin), (at, ack)]
+    }
+
+    fn packet(&mut self, direction: Direction, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let ((src, sport), (dst, dport), seq, ack) = match direction {
+            Direction::Inbound => (self.client, self.server, self.client_seq, self.server_seq),
+            Direction::Outbound => (self.server, self.client, self.server_seq, self.client_seq),
+        };
+        let ack = match flags & ACK {
+            0 => 0,
+            _ => ack,
+        };
+
+        let mut tcp = vec![];
+        tcp.extend(sport.to_be_bytes());
+        tcp.extend(dport.to_be_bytes());
+        tcp.extend(seq.to_be_bytes());
+        tcp.extend(ack.to_be_bytes());
+        tcp.push(5 << 4);
+        tcp.push(flags);
+        tcp.extend(65535u16.to_be_bytes());
+        tcp.extend([0, 0, 0, 0]);
+        tcp.extend(payload);
+
+        let mut pseudo = vec![];
+        match (src, dst) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                pseudo.extend(s.octets());
+                pseudo.extend(d.octets());
+                pseudo.extend([0, 6]);
+                pseudo.extend((tcp.len() as u16).to_be_bytes());
+            }
+            (IpAddr::V6(s), IpAddr::V6(d)) => {
+                pseudo.extend(s.octets());
+                pseudo.extend(d.octets());
+                pseudo.extend((tcp.len() as u32).to_be_bytes());
+                pseudo.extend([0, 0, 0, 6]);
+            }
+            _ => unreachable!("flow addresses are the same family"),
+        }
+        pseudo.extend(&tcp);
+        tcp[16..18].copy_from_slice(&checksum(&pseudo).to_be_bytes());
+
+        let mut ip = vec![];
+        match (src, dst) {
+            (IpAddr::V4(s), IpAddr::V4(d)) => {
+                self.ip_id = self.ip_id.wrapping_add(1);
+                ip.push(0x45);
+                ip.push(0);
+                ip.extend((20 + tcp.len() as u16).to_be_bytes());
+                ip.extend(self.ip_id.to_be_bytes());
+                // don't fragment
+                ip.extend(0x4000u16.to_be_bytes());
+                ip.push(64);
+                ip.push(6);
+                ip.extend([0, 0]);
+                ip.extend(s.octets());
+                ip.extend(d.octets());
+                let sum = checksum(&ip);
+                ip[10..12].copy_from_slice(&sum.to_be_bytes());
+            }
+            (IpAddr::V6(s), IpAddr::V6(d)) => {
+                ip.extend(0x6000_0000u32.to_be_bytes());
+                ip.extend((tcp.len() as u16).to_be_bytes());
+                ip.push(6);
+                ip.push(64);
+                ip.extend(s.octets());
+                ip.extend(d.octets());
+            }
+            _ => unreachable!("flow addresses are the same family"),
+        }
+        ip.extend(tcp);
+        ip
+    }
+}
+
+/// the address, unwrapping IPv4 addresses mapped into IPv6.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+/// the Internet checksum.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// A reader which captures what's read from it as inbound bytes, or a
+/// writer which captures what's written to it as outbound.
+pub struct Tee<'a, R> {
+    inner: R,
+    capture: Option<&'a Capture>,
+}
+
+impl<'a, R> Tee<'a, R> {
+    pub fn new(inner: R, capture: Option<&'a Capture>) -> Self {
+        Self { inner, capture }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Tee<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(capture)) = (&poll, self.capture) {
+            capture.inbound(&buf.filled()[before..]);
+        }
+        poll
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for Tee<'_, W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let (Poll::Ready(Ok(n)), Some(capture)) = (&poll, self.capture) {
+            capture.outbound(&buf[..*n]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        // a commonly cited example header, whose checksum is 0xb861
+        let header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        assert_eq!(0xb861, checksum(&header));
+    }
+
+    #[test]
+    fn test_pcap() {
+        let capture = Capture::new(
+            "[::ffff:203.0.113.7]:51234".parse().unwrap(),
+            "192.0.2.1:80".parse().unwrap(),
+            4,
+        );
+        capture.inbound(b"GET / HTTP/1.1\r\n");
+        capture.outbound(b"HTTP/1.1 200 OK\r\n");
+        let pcap = capture.to_pcap();
+
+        assert_eq!(&PCAP_MAGIC.to_le_bytes(), &pcap[..4]);
+        assert_eq!(&LINKTYPE_RAW.to_le_bytes(), &pcap[20..24]);
+
+        // handshake, the capped payload, then the close
+        let mut packets = vec![];
+        let mut rest = &pcap[24..];
+        while !rest.is_empty() {
+            let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+            packets.push(&rest[16..16 + len]);
+            rest = &rest[16 + len..];
+        }
+        assert_eq!(7, packets.len());
+        let data = packets[3];
+        assert_eq!(0x45, data[0], "mapped address wasn't unwrapped");
+        assert_eq!([203, 0, 113, 7], data[12..16]);
+        assert_eq!(b"GET ", &data[40..]);
+        assert_eq!(0, checksum(&data[..20]), "bad IP checksum");
+        assert_eq!(PSH | ACK, data[33]);
+        assert_eq!(SYN, packets[0][33]);
+    }
+
+    #[test]
+    fn test_anonymized() {
+        let privacy = Anonymizer::new(Default::default()).unwrap();
+        let capture = Capture::new(
+            "203.0.113.0:51234".parse().unwrap(),
+            "192.0.2.1:80".parse().unwrap(),
+            usize::MAX,
+        )
+        .anonymized(Some(privacy));
+        capture.inbound(b"GET / HTTP/1.1\r\nX-Forwarded-For: 198.51.");
+        capture.inbound(b"100.7\r\nForwarded: for=198.51.100.8\r\n\r\n");
+        capture.outbound(b"HTTP/1.1 200 OK\r\n\r\n");
+        capture.inbound(b"x-forwarded-for: 198.51.100.9\r\n");
+
+        let connections = read_pcap(&capture.to_pcap()).unwrap();
+        assert_eq!(
+            b"GET / HTTP/1.1\r\nX-Forwarded-For: 198.51.100.0\r\n\
+                Forwarded: for=198.51.100.0\r\n\r\n\
+                x-forwarded-for: 198.51.100.0\r\n"
+                .to_vec(),
+            connections[0].inbound
+        );
+        assert_eq!(b"HTTP/1.1 200 OK\r\n\r\n".to_vec(), connections[0].outbound);
+    }
+
+    #[test]
+    fn test_read_pcap() {
+        let capture = Capture::new(
+            "[2001:db8::7]:51234".parse().unwrap(),
+            "[2001:db8::1]:80".parse().unwrap(),
+            1024 * 1024,
+        );
+        let body = vec![b'a'; MSS * 2];
+        capture.inbound(b"GET / HTTP/1.1\r\n\r\n");
+        capture.outbound(&body);
+
+        let connections = read_pcap(&capture.to_pcap()).unwrap();
+        assert_eq!(
+            vec![Connection {
+                opened: Utc.timestamp_opt(capture.opened.timestamp(), 0).unwrap(),
+                client: "[2001:db8::7]:51234".parse().unwrap(),
+                server: "[2001:db8::1]:80".parse().unwrap(),
+                inbound: b"GET / HTTP/1.1\r\n\r\n".to_vec(),
+                outbound: body,
+            }],
+            connections
+        );
+        assert!(read_pcap(b"GET / HTTP/1.1\r\n\r\n and then some").is_err());
+    }
+}
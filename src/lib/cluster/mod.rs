@@ -0,0 +1,81 @@
+//! State shared by a fleet of instances through Redis, so actors can be
+//! correlated across sensors on different addresses. Each source's
+//! profile is counted fleet-wide, along with which sensors it was seen by.
+//! Sharing needs the `cluster` feature, which brings in the redis client.
+//!
+//! Honeytokens and API tokens need no sharing: they're derived from the
+//! seed every instance shares, so a token leaked by one sensor is
+//! recognized by all of them. Only profiles are shared, and only through
+//! Redis.
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::profile;
+
+#[cfg(feature = "cluster")]
+mod redis;
+#[cfg(feature = "cluster")]
+pub use self::redis::Cluster;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    /// Redis server, as host:port
+    pub redis: String,
+    pub password: Option<String>,
+    /// Redis database number
+    pub db: u32,
+    /// prefix of every key, so a server can be shared
+    pub prefix: String,
+    /// name the rest of the fleet knows this instance by, by default its
+    /// listen address
+    pub sensor: Option<String>,
+    /// how long a source's shared profile is kept after it's last seen
+    pub ttl_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            redis: "127.0.0.1:6379".to_string(),
+            password: None,
+            db: 0,
+            prefix: "httpot".to_string(),
+            sensor: None,
+            ttl_secs: 7 * 24 * 60 * 60,
+            timeout_secs: 5,
+        }
+    }
+}
+
+/// What the fleet has seen from a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedProfile {
+    pub ip: IpAddr,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub requests: u64,
+    /// distinct paths visited
+    pub paths: u64,
+    pub credentials_submitted: u64,
+    pub uploads: u64,
+    /// sensors which have seen it, including this one
+    pub sensors: Vec<String>,
+    /// whether this sensor hadn't seen it before
+    pub new_here: bool,
+}
+
+impl SharedProfile {
+    /// the fleet-wide engagement score, as [profile::Profile::score].
+    pub fn score(&self) -> u64 {
+        profile::engagement(
+            self.paths,
+            self.credentials_submitted,
+            self.uploads,
+            self.last_seen - self.first_seen,
+        )
+    }
+}
@@ -0,0 +1,263 @@
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use ::redis::{
+    aio::Connection, Client, ConnectionAddr, ConnectionInfo, Pipeline, RedisConnectionInfo,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use tokio::{sync::Mutex, time};
+
+use crate::{
+    cluster::{ClusterConfig, SharedProfile},
+    prelude::*,
+    profile::Activity,
+};
+
+// the replies to the pipeline which aren't ignored: requests, credentials,
+// uploads, first seen, distinct paths, whether the sensor was added, and
+// the sensors
+type Replies = (u64, u64, u64, i64, u64, u64, Vec<String>);
+
+/// where the config's host:port Redis is, and how to log into it.
+fn connection_info(config: &ClusterConfig) -> Result<ConnectionInfo> {
+    let (host, port) = match config.redis.rsplit_once(':') {
+        Some((host, port)) => (host, port),
+        None => bail!("cluster redis {:?} isn't host:port", config.redis),
+    };
+    let port = port
+        .parse()
+        .map_err(|e| anyhow!("cluster redis {:?} has a bad port: {}", config.redis, e))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Ok(ConnectionInfo {
+        addr: ConnectionAddr::Tcp(host.to_string(), port),
+        redis: RedisConnectionInfo {
+            db: config.db.into(),
+            username: None,
+            password: config.password.clone(),
+        },
+    })
+}
+
+/// A connection to Redis, opened on first use and reopened after errors.
+struct Redis {
+    config: ClusterConfig,
+    client: Client,
+    conn: Mutex<Option<Connection>>,
+}
+
+impl Redis {
+    async fn query(&self, pipeline: &Pipeline) -> Result<Replies> {
+        let mut conn = self.conn.lock().await;
+        let timeout = Duration::from_secs(self.config.timeout_secs);
+        let replies = match time::timeout(timeout, self.exchange(&mut conn, pipeline)).await {
+            Ok(replies) => replies,
+            Err(_) => Err(anyhow!("redis timed out after {:?}", timeout)),
+        };
+        // a connection which failed mid-pipeline can't be trusted to be
+        // in step with its replies
+        if replies.is_err() {
+            *conn = None;
+        }
+        replies
+    }
+
+    async fn exchange(
+        &self,
+        conn: &mut Option<Connection>,
+        pipeline: &Pipeline,
+    ) -> Result<Replies> {
+        let conn = match conn {
+            Some(conn) => conn,
+            None => {
+                let opened =
+                    self.client.get_async_connection().await.map_err(|e| {
+                        anyhow!("failed to connect to {}: {}", self.config.redis, e)
+                    })?;
+                conn.insert(opened)
+            }
+        };
+        Ok(pipeline.query_async(conn).await?)
+    }
+}
+
+#[derive(Clone)]
+pub struct Cluster {
+    redis: Arc<Redis>,
+    sensor: String,
+}
+
+impl Cluster {
+    pub fn new(config: ClusterConfig) -> Result<Self> {
+        let client = Client::open(connection_info(&config)?)?;
+        Ok(Self {
+            sensor: config.sensor.clone().unwrap_or_default(),
+            redis: Arc::new(Redis {
+                config,
+                client,
+                conn: Mutex::new(None),
+            }),
+        })
+    }
+
+    pub fn sensor(&self) -> &str {
+        &self.sensor
+    }
+
+    /// records a request from the source against its shared profile,
+    /// returning the fleet's view of it.
+    pub async fn record(
+        &self,
+        ip: IpAddr,
+        path: &str,
+        activity: Activity,
+    ) -> Result<SharedProfile> {
+        let now = Utc::now();
+        let replies = self
+            .redis
+            .query(&self.pipeline(ip, path, activity, now))
+            .await?;
+        shared_profile(ip, replies, now)
+    }
+
+    fn pipeline(&self, ip: IpAddr, path: &str, activity: Activity, now: DateTime<Utc>) -> Pipeline {
+        let config = &self.redis.config;
+        let key = |kind| format!("{}:{}:{}", config.prefix, kind, ip);
+        let (profile, paths, sensors) = (key("profile"), key("paths"), key("sensors"));
+
+        let mut pipeline = ::redis::pipe();
+        let credentials = activity.credentials as u8;
+        let uploads = activity.upload as u8;
+        pipeline.cmd("HINCRBY").arg(&profile).arg("requests").arg(1);
+        pipeline
+            .cmd("HINCRBY")
+            .arg(&profile)
+            .arg("credentials")
+            .arg(credentials);
+        pipeline
+            .cmd("HINCRBY")
+            .arg(&profile)
+            .arg("uploads")
+            .arg(uploads);
+        pipeline
+            .cmd("HSETNX")
+            .arg(&profile)
+            .arg("first_seen")
+            .arg(now.timestamp())
+            .ignore();
+        pipeline.cmd("HGET").arg(&profile).arg("first_seen");
+        pipeline.cmd("SADD").arg(&paths).arg(path).ignore();
+        pipeline.cmd("SCARD").arg(&paths);
+        pipeline.cmd("SADD").arg(&sensors).arg(&self.sensor);
+        pipeline.cmd("SMEMBERS").arg(&sensors);
+        for key in [&profile, &paths, &sensors] {
+            pipeline
+                .cmd("EXPIRE")
+                .arg(key)
+                .arg(config.ttl_secs)
+                .ignore();
+        }
+        pipeline
+    }
+}
+
+/// the profile in the replies to [Cluster::pipeline].
+fn shared_profile(ip: IpAddr, replies: Replies, now: DateTime<Utc>) -> Result<SharedProfile> {
+    let (requests, credentials, uploads, first_seen, paths, added, mut sensors) = replies;
+    let first_seen = Utc
+        .timestamp_opt(first_seen, 0)
+        .single()
+        .ok_or_else(|| anyhow!("first_seen is out of range"))?;
+    sensors.sort();
+
+    Ok(SharedProfile {
+        ip,
+        first_seen,
+        last_seen: now,
+        requests,
+        credentials_submitted: credentials,
+        uploads,
+        paths,
+        sensors,
+        new_here: added == 1,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile;
+
+    #[test]
+    fn test_connection_info() {
+        let config = |redis: &str| ClusterConfig {
+            redis: redis.to_string(),
+            password: Some("hunter2".to_string()),
+            db: 3,
+            ..Default::default()
+        };
+        let info = connection_info(&config("10.0.0.5:6380")).unwrap();
+        assert!(matches!(info.addr, ConnectionAddr::Tcp(ref h, 6380) if h == "10.0.0.5"));
+        assert_eq!(3, info.redis.db);
+        assert_eq!(Some("hunter2".to_string()), info.redis.password);
+        let info = connection_info(&config("[::1]:6379")).unwrap();
+        assert!(matches!(info.addr, ConnectionAddr::Tcp(ref h, 6379) if h == "::1"));
+        assert!(connection_info(&config("10.0.0.5")).is_err());
+        assert!(connection_info(&config("10.0.0.5:redis")).is_err());
+    }
+
+    #[test]
+    fn test_shared_profile() {
+        let cluster = Cluster::new(ClusterConfig {
+            sensor: Some("203.0.113.1:80".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let ip = "198.51.100.7".parse().unwrap();
+        let now = Utc.timestamp_opt(1_700_000_600, 0).unwrap();
+        let activity = Activity {
+            credentials: true,
+            upload: false,
+        };
+        let pipeline = cluster.pipeline(ip, "/wp-login.php", activity, now);
+        let commands = pipeline
+            .cmd_iter()
+            .map(|c| c.get_packed_command())
+            .collect::<Vec<_>>();
+        assert_eq!(12, commands.len());
+        assert_eq!(
+            ::redis::cmd("HINCRBY")
+                .arg("httpot:profile:198.51.100.7")
+                .arg("credentials")
+                .arg(1)
+                .get_packed_command(),
+            commands[1]
+        );
+        assert_eq!(
+            ::redis::cmd("SADD")
+                .arg("httpot:sensors:198.51.100.7")
+                .arg("203.0.113.1:80")
+                .get_packed_command(),
+            commands[7]
+        );
+
+        let replies = (
+            5,
+            2,
+            0,
+            1_700_000_000,
+            3,
+            1,
+            vec!["sensor-b".to_string(), "203.0.113.1:80".to_string()],
+        );
+        let shared = shared_profile(ip, replies, now).unwrap();
+        assert_eq!(5, shared.requests);
+        assert!(shared.new_here);
+        assert_eq!(vec!["203.0.113.1:80", "sensor-b"], shared.sensors);
+        assert_eq!(
+            profile::engagement(3, 2, 0, chrono::Duration::minutes(10)),
+            shared.score()
+        );
+
+        let replies = (5, 2, 0, i64::MAX, 3, 1, vec![]);
+        assert!(shared_profile(ip, replies, now).is_err());
+    }
+}
@@ -0,0 +1,532 @@
+//! httpot's optional TOML configuration file. Every section is optional
+//! and defaults to the behavior without a config.
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    canonical::CanonicalConfig,
+    capture::CaptureConfig,
+    cluster::ClusterConfig,
+    dedup::DedupConfig,
+    enrich::EnrichConfig,
+    eventlog::EventLogConfig,
+    fetch::FetchConfig,
+    fs::{fake::FakeFsConfig, overlay::OverlayConfig},
+    har::HarConfig,
+    honeypot::{
+        api::ApiConfig,
+        appserver::AppServerConfig,
+        favicon::FaviconConfig,
+        forge::ForgeConfig,
+        iot::Device,
+        panel::Panel,
+        php::PhpConfig,
+        plugin::PluginConfig,
+        redirect::RedirectConfig,
+        route::{Handler, RouteConfig},
+        s3::S3Config,
+        script::ScriptConfig,
+        shell::CommandInjectionConfig,
+        ssrf::SsrfConfig,
+        tarpit::TarpitConfig,
+        template::TemplatesConfig,
+        upload::UploadConfig,
+        webmail::WebmailConfig,
+    },
+    http::{egress::EgressConfig, request::ParserConfig},
+    hub::{HubConfig, SensorConfig},
+    middleware::MethodOverrideConfig,
+    persona::{BannerConfig, Persona},
+    prelude::*,
+    privacy::PrivacyConfig,
+    report::ReportConfig,
+    retention::{self, RetentionConfig},
+    seed::VariationConfig,
+    util::Cidr,
+    vhost::VhostConfig,
+};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// capabilities enabled together, besides those configured below
+    pub interaction: Option<Interaction>,
+    /// the web server to pretend to be
+    pub persona: Persona,
+    /// URLs redirected to their canonical scheme and host
+    pub canonical: Option<CanonicalConfig>,
+    /// whether TRACE is echoed, by default as the persona's server does
+    pub trace: Option<bool>,
+    /// versions the persona claims, picked per deployment
+    pub banner: Option<BannerConfig>,
+    /// how incoming requests are read
+    pub parser: ParserConfig,
+    /// how fast responses are written, across and per connection
+    pub egress: Option<EgressConfig>,
+    /// whether tunneled methods are routed on
+    pub method_override: MethodOverrideConfig,
+    /// whether injected shell commands are answered with emulated output
+    pub command_injection: CommandInjectionConfig,
+    /// follow-up fetches of URLs found in payloads
+    pub fetch: Option<FetchConfig>,
+    /// hash lookups for fetched and uploaded samples
+    pub enrich: Option<EnrichConfig>,
+    /// exchanges exported as HTTP Archives
+    pub har: Option<HarConfig>,
+    /// raw connections written as pcap files
+    pub capture: Option<CaptureConfig>,
+    /// source addresses anonymized before anything sees them
+    pub privacy: Option<PrivacyConfig>,
+    /// repeated probes collapsed before they're recorded by the above
+    pub dedup: Option<DedupConfig>,
+    /// exchanges chained into a tamper-evident log
+    pub event_log: Option<EventLogConfig>,
+    /// summaries of the above written on a schedule
+    pub report: Option<ReportConfig>,
+    /// how long the above, quarantined uploads, and samples are kept
+    pub retention: Option<RetentionConfig>,
+    /// state shared with other instances in a fleet
+    pub cluster: Option<ClusterConfig>,
+    /// hub this instance registers with as a sensor
+    pub sensor: Option<SensorConfig>,
+    /// sensors this instance manages when run as a hub
+    pub hub: Option<HubConfig>,
+    /// routes answered by built-in handlers, checked before scripts
+    pub routes: Vec<RouteConfig>,
+    /// scripted bait routes, checked before the built-in routes
+    pub scripts: Vec<ScriptConfig>,
+    /// WASM plugin routes, requires the wasm feature
+    pub plugins: Vec<PluginConfig>,
+    /// directory of bait page templates
+    pub templates: Option<TemplatesConfig>,
+    /// real directories mounted at URL prefixes
+    pub overlays: Vec<OverlayConfig>,
+    /// icons served as favicons, in place of the built-in ones
+    pub favicons: Option<FaviconConfig>,
+    /// the PHP which PHP baits claim to run
+    pub php: PhpConfig,
+    /// huge fake downloads trickled out slowly
+    pub tarpit: Option<TarpitConfig>,
+    /// PUT and DELETE accepted like a WebDAV share
+    pub uploads: Option<UploadConfig>,
+    /// fake JSON REST API
+    pub api: Option<ApiConfig>,
+    /// versions shown by the fake Java application server consoles
+    pub app_servers: AppServerConfig,
+    /// versions shown by the fake Roundcube and SquirrelMail logins
+    pub webmail: WebmailConfig,
+    /// which Git forge is served, and the version it claims
+    pub forge: ForgeConfig,
+    /// hostnames and region of the fake S3 object store
+    pub s3: S3Config,
+    /// where open redirect baits send requesters
+    pub redirects: RedirectConfig,
+    /// whether SSRF baits claim to have fetched what they were asked to
+    pub ssrf: SsrfConfig,
+    /// who may read the metrics listener, if it's enabled
+    pub metrics: MetricsConfig,
+    /// listeners in addition to the command line's
+    pub listeners: Vec<ListenerConfig>,
+    /// sites selected by the requested Host, checked before the above
+    pub vhosts: Vec<VhostConfig>,
+    /// how generated content varies between sources
+    pub variation: VariationConfig,
+    /// what fake directory trees look like
+    pub filesystem: FakeFsConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub addr: SocketAddr,
+    /// control panel which answers every request to the listener
+    pub panel: Option<Panel>,
+    /// IoT device which answers every request to the listener
+    pub device: Option<Device>,
+}
+
+/// How much attackers get to do, as bundles of the capabilities otherwise
+/// configured one by one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interaction {
+    /// static pages from the persona only: no decoy applications,
+    /// captured credentials, fake directory listings, uploads, scripts,
+    /// plugins, emulated commands, fetches, or tarpit
+    Low,
+    /// decoy applications, captured credentials, fake directory listings,
+    /// and uploads accepted into quarantine
+    Medium,
+    /// injected commands emulated, and the tarpit's maze of slowly
+    /// trickled files, on top of medium. Downloads are only fetched when
+    /// `[fetch]` sends them through a proxy.
+    High,
+}
+
+/// Limits on the metrics listener, so exposing it doesn't expose more
+/// than metrics.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// bearer token scrapers must send
+    pub token: Option<String>,
+    /// address blocks scrapers must connect from. Empty allows any.
+    pub allow: Vec<Cidr>,
+    pub max_connections: usize,
+    /// requests larger than this, head and body, are dropped
+    pub max_request_bytes: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            allow: vec![],
+            max_connections: 16,
+            max_request_bytes: 8 * 1024,
+            timeout_secs: 5,
+        }
+    }
+}
+
+impl Config {
+    /// reads the config at the provided path. Relative paths within the
+    /// config are resolved relative to the config's directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let raw = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read config {}: {}", path.display(), e))?;
+        let mut config = Self::parse(&raw)
+            .map_err(|e| anyhow!("failed to parse config {}: {}", path.display(), e))?;
+
+        config.resolve_paths(path.parent().unwrap_or_else(|| Path::new(".")));
+        Ok(config)
+    }
+
+    /// checks the config names no local files and runs no code, as configs
+    /// pushed by a hub mustn't: whoever runs the hub would run code on
+    /// every sensor.
+    pub fn ensure_pushable(&self) -> Result<()> {
+        let mut routes = self.routes.iter().flat_map(|route| {
+            let responses = route.responses.iter().map(|r| &r.handler);
+            route.handler.iter().chain(responses)
+        });
+        for (section, configured) in [
+            ("[[scripts]]", !self.scripts.is_empty()),
+            ("[templates]", self.templates.is_some()),
+            (
+                "[[vhosts]] scripts or templates",
+                self.vhosts
+                    .iter()
+                    .any(|v| !v.scripts.is_empty() || v.templates.is_some()),
+            ),
+            (
+                "[[routes]] files or scripts",
+                routes.any(|h| matches!(h, Handler::File(_) | Handler::Script(_))),
+            ),
+            ("[[plugins]]", !self.plugins.is_empty()),
+            ("[[overlays]]", !self.overlays.is_empty()),
+            ("[parser.spool]", self.parser.spool.is_some()),
+            ("[favicons]", self.favicons.is_some()),
+            ("[php] logos", self.php.logos.is_some()),
+            (
+                "[fetch] store",
+                self.fetch.as_ref().is_some_and(|f| f.store.is_some()),
+            ),
+            ("[capture]", self.capture.is_some()),
+            ("[har]", self.har.is_some()),
+            // interaction levels upload into the default quarantine
+            (
+                "[uploads] quarantine",
+                self.uploads
+                    .as_ref()
+                    .is_some_and(|u| u.quarantine != UploadConfig::default().quarantine),
+            ),
+            ("[report]", self.report.is_some()),
+            ("[event_log]", self.event_log.is_some()),
+            ("[hub]", self.hub.is_some()),
+        ] {
+            ensure!(
+                !configured,
+                "{} may only be configured locally, not pushed by a hub",
+                section
+            );
+        }
+        Ok(())
+    }
+
+    /// resolves relative paths within the config relative to the
+    /// directory.
+    pub fn resolve_paths(&mut self, dir: &Path) {
+        resolve_site_paths(dir, &mut self.scripts, &mut self.templates);
+        for vhost in self.vhosts.iter_mut() {
+            resolve_site_paths(dir, &mut vhost.scripts, &mut vhost.templates);
+        }
+        for route in self.routes.iter_mut() {
+            let responses = route.responses.iter_mut().map(|r| &mut r.handler);
+            for handler in route.handler.iter_mut().chain(responses) {
+                if let Handler::File(file) | Handler::Script(file) = handler {
+                    resolve(dir, file);
+                }
+            }
+        }
+        for plugin in self.plugins.iter_mut() {
+            resolve(dir, &mut plugin.module);
+        }
+        for overlay in self.overlays.iter_mut() {
+            resolve(dir, &mut overlay.dir);
+        }
+        if let Some(spool) = self.parser.spool.as_mut() {
+            resolve(dir, &mut spool.dir);
+        }
+        if let Some(favicons) = self.favicons.as_mut() {
+            resolve(dir, &mut favicons.dir);
+        }
+        if let Some(logos) = self.php.logos.as_mut() {
+            resolve(dir, logos);
+        }
+        if let Some(store) = self.fetch.as_mut().and_then(|f| f.store.as_mut()) {
+            resolve(dir, store);
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            resolve(dir, &mut capture.dir);
+        }
+        if let Some(har) = self.har.as_mut() {
+            resolve(dir, &mut har.dir);
+        }
+        if let Some(uploads) = self.uploads.as_mut() {
+            resolve(dir, &mut uploads.quarantine);
+        }
+        if let Some(report) = self.report.as_mut() {
+            resolve(dir, &mut report.dir);
+        }
+        if let Some(event_log) = self.event_log.as_mut() {
+            resolve(dir, &mut event_log.path);
+        }
+        if let Some(hub) = self.hub.as_mut() {
+            resolve(dir, &mut hub.configs);
+            resolve(dir, &mut hub.events);
+        }
+    }
+
+    pub fn parse(raw: &str) -> Result<Self> {
+        let mut config: Self = toml::from_str(raw)?;
+        config.apply_interaction()?;
+        for listener in &config.listeners {
+            ensure!(
+                listener.panel.is_none() || listener.device.is_none(),
+                "listener {} has both a panel and a device",
+                listener.addr
+            );
+        }
+        for store in config.retention.iter().flat_map(|r| r.stores.keys()) {
+            ensure!(
+                retention::STORES.contains(&store.as_str()),
+                "unknown retention store {:?}, expected one of {:?}",
+                store,
+                retention::STORES
+            );
+        }
+        if let Some(sensor) = &config.sensor {
+            ensure!(!sensor.key.is_empty(), "sensor requires its key");
+        }
+        if let Some(hub) = &config.hub {
+            ensure!(!hub.sensors.is_empty(), "hub requires its sensors' keys");
+            let mut keys = HashSet::new();
+            for (sensor, key) in &hub.sensors {
+                ensure!(!key.is_empty(), "hub sensor {:?} requires a key", sensor);
+                // a key held by two sensors would let either pose as the other
+                ensure!(keys.insert(key), "hub sensor {:?} shares a key", sensor);
+            }
+        }
+        if let Some(banner) = &config.banner {
+            banner.since()?;
+        }
+        if let Some(cluster) = &config.cluster {
+            ensure!(cluster.ttl_secs > 0, "cluster ttl_secs must be positive");
+        }
+        Ok(config)
+    }
+
+    /// enables the capabilities the interaction level bundles, with their
+    /// defaults unless they're configured, or for low, checks none are.
+    fn apply_interaction(&mut self) -> Result<()> {
+        let interaction = match self.interaction {
+            Some(interaction) => interaction,
+            None => return Ok(()),
+        };
+        if interaction == Interaction::Low {
+            for (section, enabled) in [
+                ("[uploads]", self.uploads.is_some()),
+                ("[[scripts]]", !self.scripts.is_empty()),
+                ("[[plugins]]", !self.plugins.is_empty()),
+                (
+                    "[command_injection] emulate",
+                    self.command_injection.emulate,
+                ),
+                ("[fetch]", self.fetch.is_some()),
+                ("[tarpit]", self.tarpit.is_some()),
+            ] {
+                ensure!(
+                    !enabled,
+                    "interaction = \"low\" doesn't allow {}, raise it to enable them",
+                    section
+                );
+            }
+        }
+        if interaction >= Interaction::Medium {
+            self.uploads.get_or_insert_with(Default::default);
+        }
+        if interaction >= Interaction::High {
+            if let Some(fetch) = &self.fetch {
+                ensure!(
+                    fetch.proxy.is_some(),
+                    "interaction = \"high\" only fetches through a [fetch] proxy, which must refuse private destinations"
+                );
+            }
+            self.command_injection.emulate = true;
+            self.tarpit.get_or_insert_with(Default::default);
+        }
+        Ok(())
+    }
+
+    /// whether decoy applications, credential capture, and fake directory
+    /// listings run, which they do at every level but low.
+    pub fn decoys(&self) -> bool {
+        self.interaction
+            .is_none_or(|interaction| interaction >= Interaction::Medium)
+    }
+}
+
+fn resolve(dir: &Path, path: &mut PathBuf) {
+    if path.is_relative() {
+        *path = dir.join(&path);
+    }
+}
+
+fn resolve_site_paths(
+    dir: &Path,
+    scripts: &mut [ScriptConfig],
+    templates: &mut Option<TemplatesConfig>,
+) {
+    for file in scripts.iter_mut().filter_map(|s| s.file.as_mut()) {
+        resolve(dir, file);
+    }
+    if let Some(templates) = templates.as_mut() {
+        resolve(dir, &mut templates.dir);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_empty_config() {
+        let config = Config::parse("").unwrap();
+        assert!(config.scripts.is_empty());
+    }
+
+    #[test]
+    fn test_listeners() {
+        let config = Config::parse(
+            r#"
+            [[listeners]]
+            addr = "0.0.0.0:2082"
+            panel = "cpanel"
+
+            [[listeners]]
+            addr = "0.0.0.0:8081"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(Some(Panel::Cpanel), config.listeners[0].panel);
+        assert_eq!(None, config.listeners[1].panel);
+
+        let both = r#"
+            [[listeners]]
+            addr = "0.0.0.0:80"
+            panel = "cpanel"
+            device = "dvr"
+            "#;
+        assert!(Config::parse(both).is_err());
+    }
+
+    #[test]
+    fn test_interaction() {
+        let config = Config::parse("").unwrap();
+        assert!(config.uploads.is_none() && config.fetch.is_none());
+
+        assert!(config.decoys());
+
+        let config = Config::parse("interaction = \"low\"").unwrap();
+        assert!(config.uploads.is_none() && !config.command_injection.emulate);
+        assert!(!config.decoys());
+        let err = Config::parse("interaction = \"low\"\n[uploads]").unwrap_err();
+        assert!(err.to_string().contains("[uploads]"), "{}", err);
+
+        let config = Config::parse("interaction = \"medium\"").unwrap();
+        assert!(config.uploads.is_some() && config.tarpit.is_none());
+        assert!(config.decoys());
+
+        // fetching is left off unless it goes through a proxy
+        let config = Config::parse("interaction = \"high\"").unwrap();
+        assert!(config.command_injection.emulate && config.tarpit.is_some());
+        assert!(config.fetch.is_none());
+        let err = Config::parse("interaction = \"high\"\n[fetch]").unwrap_err();
+        assert!(err.to_string().contains("proxy"), "{}", err);
+
+        // configured sections are kept
+        let config = Config::parse(
+            r#"
+            interaction = "high"
+            [fetch]
+            proxy = "127.0.0.1:3128"
+            per_minute = 1
+            "#,
+        )
+        .unwrap();
+        assert_eq!(1, config.fetch.unwrap().per_minute);
+    }
+
+    #[test]
+    fn test_ensure_pushable() {
+        let config = Config::parse("[[routes]]\npath = \"/\"\nhandler = { body = \"\" }");
+        config.unwrap().ensure_pushable().unwrap();
+        let config = Config::parse("interaction = \"medium\"").unwrap();
+        config.ensure_pushable().unwrap();
+        let config = Config::parse("[uploads]\nquarantine = \"/etc/cron.d\"").unwrap();
+        let err = config.ensure_pushable().unwrap_err();
+        assert!(err.to_string().contains("[uploads]"), "{}", err);
+
+        for local in [
+            "[[overlays]]\nprefix = \"/\"\ndir = \"/etc\"",
+            "[[scripts]]\npath = \"/\"\nsource = \"\"",
+            "[[routes]]\npath = \"/\"\nhandler = { file = \"/etc/shadow\" }",
+        ] {
+            let config = Config::parse(local).unwrap();
+            assert!(config.ensure_pushable().is_err(), "{}", local);
+        }
+    }
+
+    #[test]
+    fn test_hub_keys() {
+        let config = Config::parse("[hub.sensors]\nedge-1 = \"a\"\nedge-2 = \"b\"").unwrap();
+        assert_eq!(2, config.hub.unwrap().sensors.len());
+        assert!(Config::parse("[hub]").is_err());
+        assert!(Config::parse("[hub.sensors]\nedge-1 = \"\"").is_err());
+        assert!(Config::parse("[hub.sensors]\nedge-1 = \"a\"\nedge-2 = \"a\"").is_err());
+        assert!(Config::parse("[sensor]\nhub = \"10.0.0.5:7000\"").is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys_rejected() {
+        assert!(Config::parse("foo = 1").is_err());
+    }
+}
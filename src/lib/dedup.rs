@@ -0,0 +1,241 @@
+//! Deduplication of repeated probes. Mass scanners send the same request
+//! from the same address again and again; only the first in each window is
+//! recorded in full, and the rest are collapsed into a count logged once
+//! the window ends. A sample of the repeats can still be recorded.
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::http::request::Request;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DedupConfig {
+    /// how long repeats are collapsed after a probe is first seen
+    pub window_secs: u64,
+    /// fraction of repeats which are still recorded in full
+    pub sample: f64,
+    /// probes tracked at once. Probes beyond it are always recorded.
+    pub max_tracked: usize,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 5 * 60,
+            sample: 0.0,
+            max_tracked: 100_000,
+        }
+    }
+}
+
+/// What makes probes identical.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Probe {
+    pub ip: IpAddr,
+    pub path: String,
+    pub user_agent: String,
+}
+
+impl Probe {
+    pub fn of(req: &Request) -> Self {
+        Self {
+            ip: req.requester_ip(),
+            path: req.url.path().to_string(),
+            user_agent: req
+                .headers
+                .get_first("User-Agent")
+                .unwrap_or_default()
+                .to_string(),
+        }
+    }
+}
+
+/// Whether a request should be recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// the first of its window, or untracked
+    First,
+    /// a repeat picked by sampling
+    Sampled,
+    /// a repeat which is only counted
+    Repeat,
+}
+
+impl Verdict {
+    pub fn is_recorded(&self) -> bool {
+        *self != Verdict::Repeat
+    }
+}
+
+/// A probe's repeats within a window which has ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collapsed {
+    pub probe: Probe,
+    /// repeats after the first, sampled or not
+    pub repeats: u64,
+    pub window: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct Window {
+    opened: Instant,
+    repeats: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    windows: HashMap<Probe, Window>,
+    // ended windows reopened before they were swept
+    ended: Vec<Collapsed>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dedup {
+    config: Arc<DedupConfig>,
+    state: Arc<Mutex<State>>,
+}
+
+impl Dedup {
+    pub fn new(config: DedupConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            state: Default::default(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.config.window_secs)
+    }
+
+    /// counts the request against its probe's window, opening one if
+    /// there's none or it has ended.
+    pub fn check(&self, req: &Request) -> Verdict {
+        let probe = Probe::of(req);
+        let window = self.window();
+        let mut state = self.state.lock().unwrap();
+        let State { windows, ended } = &mut *state;
+        match windows.get_mut(&probe) {
+            Some(w) if w.opened.elapsed() < window => {
+                w.repeats += 1;
+                match thread_rng().gen_bool(self.config.sample.clamp(0.0, 1.0)) {
+                    true => Verdict::Sampled,
+                    false => Verdict::Repeat,
+                }
+            }
+            Some(w) => {
+                if w.repeats > 0 {
+                    ended.push(Collapsed {
+                        probe,
+                        repeats: w.repeats,
+                        window,
+                    });
+                }
+                *w = Window {
+                    opened: Instant::now(),
+                    repeats: 0,
+                };
+                Verdict::First
+            }
+            None => {
+                if windows.len() < self.config.max_tracked {
+                    windows.insert(
+                        probe,
+                        Window {
+                            opened: Instant::now(),
+                            repeats: 0,
+                        },
+                    );
+                }
+                Verdict::First
+            }
+        }
+    }
+
+    /// forgets probes whose windows have ended, returning those which were
+    /// repeated.
+    pub fn sweep(&self) -> Vec<Collapsed> {
+        let window = self.window();
+        let mut state = self.state.lock().unwrap();
+        let mut collapsed = std::mem::take(&mut state.ended);
+        state.windows.retain(|probe, w| {
+            if w.opened.elapsed() < window {
+                return true;
+            }
+            if w.repeats > 0 {
+                collapsed.push(Collapsed {
+                    probe: probe.clone(),
+                    repeats: w.repeats,
+                    window,
+                });
+            }
+            false
+        });
+        collapsed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(path: &str) -> Request {
+        let mut req = Request::stub(Method::GET, path, "");
+        req.headers.add("User-Agent", "zgrab/0.x");
+        req.remote_ip = "203.0.113.7:62012".parse().unwrap();
+        req
+    }
+
+    #[test]
+    fn test_dedup() {
+        let dedup = Dedup::new(DedupConfig {
+            max_tracked: 2,
+            ..Default::default()
+        });
+        let env = stub_request("/.env");
+        assert_eq!(Verdict::First, dedup.check(&env));
+        assert_eq!(Verdict::Repeat, dedup.check(&env));
+        assert_eq!(Verdict::Repeat, dedup.check(&env));
+        assert_eq!(Verdict::First, dedup.check(&stub_request("/.git/config")));
+        // untracked beyond max_tracked, so never collapsed
+        assert_eq!(Verdict::First, dedup.check(&stub_request("/admin")));
+        assert_eq!(Verdict::First, dedup.check(&stub_request("/admin")));
+        assert!(dedup.sweep().is_empty(), "open windows were swept");
+
+        let dedup = Dedup::new(DedupConfig {
+            window_secs: 0,
+            sample: 1.0,
+            ..Default::default()
+        });
+        dedup.state.lock().unwrap().windows.insert(
+            Probe::of(&env),
+            Window {
+                opened: Instant::now(),
+                repeats: 2,
+            },
+        );
+        assert_eq!(
+            vec![Collapsed {
+                probe: Probe::of(&env),
+                repeats: 2,
+                window: Duration::ZERO,
+            }],
+            dedup.sweep()
+        );
+        assert!(dedup.state.lock().unwrap().windows.is_empty());
+
+        let dedup = Dedup::new(DedupConfig {
+            sample: 1.0,
+            ..Default::default()
+        });
+        dedup.check(&env);
+        assert_eq!(Verdict::Sampled, dedup.check(&env));
+    }
+}
@@ -0,0 +1,167 @@
+//! Formatting of requester-supplied strings for console logs. User agents,
+//! paths, and bodies are chosen by whoever sent them, so control characters
+//! which could move the cursor or recolor an operator's terminal are escaped,
+//! and strings are cut and padded by display width on grapheme boundaries
+//! rather than by bytes.
+//!
+//! JSON written to disk gets the same care: serde_json already escapes C0
+//! controls, and [`to_json`] also escapes DEL, C1 controls such as the 8-bit
+//! CSI, and invisible characters, so tailing a log is safe too. Decoded
+//! values are unchanged.
+use std::io;
+
+use serde::Serialize;
+use serde_json::ser::{Formatter, Serializer};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::prelude::*;
+
+const ELLIPSIS: &str = "...";
+
+/// escapes control characters and invisible formatting characters, such as
+/// bidi overrides, leaving everything else as is.
+pub fn escape(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '\t' | '\r' | '\n' => c.escape_default().to_string(),
+            c if c.is_control() || is_invisible(c) => c.escape_unicode().to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// zero width and bidi control characters, which can hide or reorder text
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' | '\u{feff}'
+    )
+}
+
+/// the display width of `s` once escaped.
+pub fn width(s: &str) -> usize {
+    escape(s).width()
+}
+
+/// escapes `s` and, when wider than `max_width` columns, keeps its start
+/// and end around an ellipsis.
+pub fn truncate(s: &str, max_width: usize) -> String {
+    let s = escape(s);
+    if s.width() <= max_width {
+        return s;
+    }
+    if max_width <= ELLIPSIS.len() {
+        return take_width(s.graphemes(true), max_width).concat();
+    }
+
+    let budget = max_width - ELLIPSIS.len();
+    let head = take_width(s.graphemes(true), budget - budget / 2);
+    let mut tail = take_width(s.graphemes(true).rev(), budget / 2);
+    tail.reverse();
+    format!("{}{}{}", head.concat(), ELLIPSIS, tail.concat())
+}
+
+/// truncates `s` to exactly `width` columns, padding it with spaces.
+pub fn column(s: &str, width: usize) -> String {
+    let s = truncate(s, width);
+    let pad = width.saturating_sub(s.width());
+    format!("{}{}", s, " ".repeat(pad))
+}
+
+// the leading graphemes which fit in `max_width` columns
+fn take_width<'a>(graphemes: impl Iterator<Item = &'a str>, max_width: usize) -> Vec<&'a str> {
+    let mut width = 0;
+    graphemes
+        .take_while(|g| {
+            width += g.width();
+            width <= max_width
+        })
+        .collect()
+}
+
+/// serializes `value` as compact JSON safe to print to a terminal.
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut out = vec![];
+    value.serialize(&mut Serializer::with_formatter(&mut out, TerminalSafe))?;
+    Ok(out)
+}
+
+// compact JSON which also escapes characters terminals act on
+struct TerminalSafe;
+
+impl Formatter for TerminalSafe {
+    fn write_string_fragment<W: ?Sized + io::Write>(
+        &mut self,
+        writer: &mut W,
+        fragment: &str,
+    ) -> io::Result<()> {
+        let mut start = 0;
+        for (i, c) in fragment.char_indices() {
+            if c.is_control() || is_invisible(c) {
+                writer.write_all(&fragment.as_bytes()[start..i])?;
+                write!(writer, "\\u{:04x}", c as u32)?;
+                start = i + c.len_utf8();
+            }
+        }
+        writer.write_all(&fragment.as_bytes()[start..])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("curl/8.0"), "curl/8.0");
+        assert_eq!(escape("a\x1b[31mb"), "a\\u{1b}[31mb");
+        assert_eq!(escape("line\r\nnext\t"), "line\\r\\nnext\\t");
+        assert_eq!(escape("exe.\u{202e}txt"), "exe.\\u{202e}txt");
+        assert_eq!(escape("café 日本"), "café 日本");
+    }
+
+    #[test]
+    fn test_to_json() {
+        let value = serde_json::json!({"ua": "a\x1b[2J\u{9b}31m\u{202e}b", "path": "/日本"});
+        let json = to_json(&value).unwrap();
+        assert_eq!(
+            String::from_utf8(json.clone()).unwrap(),
+            r#"{"path":"/日本","ua":"a\u001b[2J\u009b31m\u202eb"}"#
+        );
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&json).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 20), "short");
+        assert_eq!(truncate("abcdefghij", 9), "abc...hij");
+        assert_eq!(truncate("abcdefghij", 8), "abc...ij");
+        assert_eq!(truncate("abcdefghij", 2), "ab");
+        assert_eq!(truncate("ab", 0), "");
+
+        // multi-byte characters are never split
+        assert_eq!(truncate("ééééééééé", 7), "éé...éé");
+        // wide characters take two columns
+        assert_eq!(truncate("日本語のユーザー", 9), "日...ー");
+        // nor are combining sequences
+        assert_eq!(
+            truncate("e\u{301}e\u{301}e\u{301}e\u{301}e\u{301}", 4),
+            "e\u{301}..."
+        );
+
+        // escapes count towards the width
+        assert_eq!(truncate("\x1b\x1b\x1b", 8), "\\u{...b}");
+    }
+
+    #[test]
+    fn test_column() {
+        assert_eq!(column("curl", 8), "curl    ");
+        assert_eq!(column("日本", 6), "日本  ");
+        assert_eq!(column("Mozilla/5.0 (X11; Linux)", 10), "Mozi...ux)");
+        assert_eq!(width(&column("日本語のユーザー", 9)), 9);
+    }
+}
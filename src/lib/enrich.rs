@@ -0,0 +1,357 @@
+//! Verdicts for sample hashes from VirusTotal and MalwareBazaar. Both
+//! APIs are HTTPS only, so lookups are sent through a proxy which
+//! originates TLS, as with fetches. Results are cached and lookups are
+//! rate limited to stay within free API quotas. Uploads are looked up in
+//! the background, from a bounded queue.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{sync::mpsc, time};
+use url::Url;
+
+use crate::{
+    fetch::{self, RawRequest},
+    http::request::Request,
+    prelude::*,
+    sink::{EventSink, Payload},
+};
+
+const VIRUSTOTAL_URL: &str = "https://www.virustotal.com/api/v3/files/";
+const MALWAREBAZAAR_URL: &str = "https://mb-api.abuse.ch/api/v1/";
+// API responses are small; anything larger is cut off and fails to parse
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+// uploads waiting beyond this for a lookup are dropped
+const QUEUE_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnrichConfig {
+    pub virustotal_key: Option<String>,
+    /// abuse.ch Auth-Key
+    pub malwarebazaar_key: Option<String>,
+    /// HTTP proxy, as host:port, which originates TLS for lookups
+    pub proxy: String,
+    /// lookups per minute, shared by both services. VirusTotal's public
+    /// API allows 4.
+    pub per_minute: u32,
+    /// hashes whose verdicts are remembered
+    pub cache_size: usize,
+    pub timeout_secs: u64,
+}
+
+impl Default for EnrichConfig {
+    fn default() -> Self {
+        Self {
+            virustotal_key: None,
+            malwarebazaar_key: None,
+            proxy: "127.0.0.1:3128".to_string(),
+            per_minute: 4,
+            cache_size: 10_000,
+            timeout_secs: 30,
+        }
+    }
+}
+
+/// What a service knows of a hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Verdict {
+    pub source: &'static str,
+    /// the service has seen the sample
+    pub known: bool,
+    /// engines which flagged the sample, of those which scanned it
+    pub detections: Option<(u64, u64)>,
+    /// malware family or threat label
+    pub label: Option<String>,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}=", self.source)?;
+        if !self.known {
+            return write!(f, "unknown");
+        }
+        if let Some((flagged, scanned)) = self.detections {
+            write!(f, "{}/{}", flagged, scanned)?;
+        }
+        if let Some(label) = &self.label {
+            if self.detections.is_some() {
+                write!(f, ":")?;
+            }
+            write!(f, "{}", label)?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks up hashes with the configured services.
+#[derive(Debug, Clone)]
+pub struct Enricher {
+    lookups: Lookups,
+    // uploads waiting to be looked up, as their hash and requester
+    tx: mpsc::Sender<(String, String)>,
+    // hashes of uploads queued or being looked up
+    pending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Enricher {
+    /// starts the task looking up uploads on the current runtime.
+    pub fn spawn(config: EnrichConfig) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let enricher = Self {
+            lookups: Lookups {
+                config: Arc::new(config),
+                cache: Default::default(),
+                next: Arc::new(tokio::sync::Mutex::new(Instant::now())),
+            },
+            tx,
+            pending: Default::default(),
+        };
+        tokio::spawn(run(enricher.lookups.clone(), enricher.pending.clone(), rx));
+        enricher
+    }
+
+    /// the verdicts of every configured service on the SHA-256 hash.
+    /// Services which fail are logged and left out.
+    pub async fn lookup(&self, sha256: &str) -> Vec<Verdict> {
+        self.lookups.lookup(sha256).await
+    }
+
+    /// queues a lookup of the upload unless one is already pending,
+    /// dropping it if the queue is full. Returns whether it was queued.
+    fn submit(&self, sha256: &str, requester: String) -> bool {
+        if !self.pending.lock().unwrap().insert(sha256.to_string()) {
+            return false;
+        }
+        match self.tx.try_send((sha256.to_string(), requester)) {
+            Ok(()) => true,
+            Err(e) => {
+                debug!("dropped a lookup of {}: {}", sha256, e);
+                self.pending.lock().unwrap().remove(sha256);
+                false
+            }
+        }
+    }
+}
+
+/// The lookup state shared by every clone of an enricher and its task.
+#[derive(Debug, Clone)]
+struct Lookups {
+    config: Arc<EnrichConfig>,
+    // verdicts of lookups every service answered
+    cache: Arc<Mutex<HashMap<String, Vec<Verdict>>>>,
+    // the earliest a lookup can start
+    next: Arc<tokio::sync::Mutex<Instant>>,
+}
+
+impl Lookups {
+    async fn lookup(&self, sha256: &str) -> Vec<Verdict> {
+        if let Some(verdicts) = self.cache.lock().unwrap().get(sha256) {
+            return verdicts.clone();
+        }
+
+        // failed lookups aren't cached, so they're retried next time
+        let mut verdicts = vec![];
+        let mut failed = false;
+        if let Some(key) = &self.config.virustotal_key {
+            match self.virustotal(key, sha256).await {
+                Ok(v) => verdicts.push(v),
+                Err(e) => {
+                    info!("virustotal lookup of {} failed: {}", sha256, e);
+                    failed = true;
+                }
+            }
+        }
+        if let Some(key) = &self.config.malwarebazaar_key {
+            match self.malwarebazaar(key, sha256).await {
+                Ok(v) => verdicts.push(v),
+                Err(e) => {
+                    info!("malwarebazaar lookup of {} failed: {}", sha256, e);
+                    failed = true;
+                }
+            }
+        }
+
+        if !failed {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.len() >= self.config.cache_size {
+                cache.clear();
+            }
+            cache.insert(sha256.to_string(), verdicts.clone());
+        }
+        verdicts
+    }
+
+    async fn virustotal(&self, key: &str, sha256: &str) -> Result<Verdict> {
+        let url = Url::parse(VIRUSTOTAL_URL)?.join(sha256)?;
+        let req = RawRequest {
+            method: "GET",
+            url: &url,
+            headers: vec![("x-apikey", key.to_string())],
+            body: &[],
+        };
+        let (status, body) = self.send(&req).await?;
+        virustotal_verdict(status, &body)
+    }
+
+    async fn malwarebazaar(&self, key: &str, sha256: &str) -> Result<Verdict> {
+        let url = Url::parse(MALWAREBAZAAR_URL)?;
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("query", "get_info")
+            .append_pair("hash", sha256)
+            .finish();
+        let req = RawRequest {
+            method: "POST",
+            url: &url,
+            headers: vec![
+                ("Auth-Key", key.to_string()),
+                (
+                    "Content-Type",
+                    "application/x-www-form-urlencoded".to_string(),
+                ),
+            ],
+            body: body.as_bytes(),
+        };
+        let (status, body) = self.send(&req).await?;
+        ensure!(status == 200, "status {}", status);
+        malwarebazaar_verdict(&body)
+    }
+
+    /// waits for the next lookup slot, then sends the request.
+    async fn send(&self, req: &RawRequest<'_>) -> Result<(u16, Value)> {
+        {
+            let mut next = self.next.lock().await;
+            time::sleep_until((*next).into()).await;
+            *next = Instant::now() + Duration::from_secs(60) / self.config.per_minute.max(1);
+        }
+
+        let resp = time::timeout(
+            Duration::from_secs(self.config.timeout_secs),
+            fetch::exchange(req, Some(&self.config.proxy), true, MAX_RESPONSE_BYTES),
+        )
+        .await
+        .map_err(|_| anyhow!("timed out"))??;
+        Ok((resp.status, serde_json::from_slice(&resp.body)?))
+    }
+}
+
+impl EventSink for Enricher {
+    fn on_payload(&self, req: &Request, payload: &Payload) {
+        let sha256 = match payload {
+            Payload::Upload { sha256 } => sha256.clone(),
+            _ => return,
+        };
+        self.submit(&sha256, req.requester());
+    }
+}
+
+async fn run(
+    lookups: Lookups,
+    pending: Arc<Mutex<HashSet<String>>>,
+    mut rx: mpsc::Receiver<(String, String)>,
+) {
+    while let Some((sha256, requester)) = rx.recv().await {
+        for verdict in lookups.lookup(&sha256).await {
+            info!("{: <8} upload sha256={} {}", requester, sha256, verdict);
+        }
+        pending.lock().unwrap().remove(&sha256);
+    }
+}
+
+fn virustotal_verdict(status: u16, body: &Value) -> Result<Verdict> {
+    let mut verdict = Verdict {
+        source: "virustotal",
+        known: false,
+        detections: None,
+        label: None,
+    };
+    match status {
+        404 => return Ok(verdict),
+        200 => (),
+        _ => bail!("status {}", status),
+    }
+
+    let attributes = &body["data"]["attributes"];
+    let stats = &attributes["last_analysis_stats"];
+    let scanned = stats
+        .as_object()
+        .map(|s| s.values().filter_map(Value::as_u64).sum())
+        .unwrap_or_default();
+    verdict.known = true;
+    verdict.detections = stats["malicious"].as_u64().map(|m| (m, scanned));
+    verdict.label = attributes["popular_threat_classification"]["suggested_threat_label"]
+        .as_str()
+        .map(str::to_string);
+    Ok(verdict)
+}
+
+fn malwarebazaar_verdict(body: &Value) -> Result<Verdict> {
+    let mut verdict = Verdict {
+        source: "malwarebazaar",
+        known: false,
+        detections: None,
+        label: None,
+    };
+    match body["query_status"].as_str() {
+        Some("ok") => (),
+        Some("hash_not_found") | Some("no_results") => return Ok(verdict),
+        other => bail!("query status {:?}", other),
+    }
+
+    verdict.known = true;
+    verdict.label = body["data"][0]["signature"].as_str().map(str::to_string);
+    Ok(verdict)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_lookups() {
+        let enricher = Enricher::spawn(EnrichConfig {
+            virustotal_key: Some("key".to_string()),
+            // nothing listens here, so every lookup fails
+            proxy: "127.0.0.1:1".to_string(),
+            per_minute: 60_000,
+            ..Default::default()
+        });
+        assert!(enricher.lookup("abc").await.is_empty());
+        assert!(enricher.lookups.cache.lock().unwrap().is_empty());
+
+        // a hash already pending isn't queued again
+        assert!(enricher.submit("abc", "1.1.1.1".to_string()));
+        assert!(!enricher.submit("abc", "1.1.1.1".to_string()));
+        assert!(enricher.submit("def", "1.1.1.1".to_string()));
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let vt = json!({"data": {"attributes": {
+            "last_analysis_stats": {"malicious": 45, "suspicious": 0, "undetected": 20, "harmless": 0},
+            "popular_threat_classification": {"suggested_threat_label": "trojan.mirai/gafgyt"},
+        }}});
+        let verdict = virustotal_verdict(200, &vt).unwrap();
+        assert_eq!(Some((45, 65)), verdict.detections);
+        assert_eq!("virustotal=45/65:trojan.mirai/gafgyt", verdict.to_string());
+        assert!(!virustotal_verdict(404, &Value::Null).unwrap().known);
+        assert!(virustotal_verdict(401, &Value::Null).is_err());
+
+        let mb = json!({"query_status": "ok", "data": [{"signature": "Mirai"}]});
+        assert_eq!(
+            "malwarebazaar=Mirai",
+            malwarebazaar_verdict(&mb).unwrap().to_string()
+        );
+        let mb = json!({"query_status": "hash_not_found"});
+        assert_eq!(
+            "malwarebazaar=unknown",
+            malwarebazaar_verdict(&mb).unwrap().to_string()
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! A tamper-evident log of exchanges, for when honeypot data backs abuse
+//! reports or complaints. Records are appended as JSON lines, each chained
+//! to the one before by a MAC over the previous record's MAC and its own
+//! contents, so altering, removing, or reordering a record breaks every
+//! MAC after it. With a key the MACs are HMAC-SHA256, otherwise plain
+//! SHA-256, which only shows tampering by someone who didn't recompute
+//! the chain.
+//!
+//! The chain's head is periodically anchored: appended to a separate file
+//! and logged, so copies kept elsewhere pin down what the log held then.
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    display,
+    fetch::sha256_hex,
+    http::{request::Request, response::Response},
+    prelude::*,
+    session::Ending,
+    sink::EventSink,
+    tls::client_hello::{self, ClientHello},
+    util::hmac_sha256,
+};
+
+// the MAC the first record chains from
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+// how far back from the end the last record is looked for
+const TAIL: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventLogConfig {
+    /// the log, which anchors are written beside with an .anchors suffix
+    pub path: PathBuf,
+    /// secret keying record MACs. Without one, records are chained by
+    /// plain hashes.
+    pub key: Option<String>,
+    /// how often the chain's head is anchored
+    pub anchor_secs: u64,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("events.jsonl"),
+            key: None,
+            anchor_secs: 60 * 60,
+        }
+    }
+}
+
+/// An exchange as it's logged. Bodies are logged by their hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub source: String,
+    pub method: String,
+    pub target: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body_len: usize,
+    pub body_sha256: String,
+    pub status: u16,
+    pub response_len: usize,
+    pub response_sha256: String,
+    pub tags: Vec<String>,
+    /// how the session ended, absent from events logged before it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ending: Option<Ending>,
+    /// the TLS ClientHello the session sent in place of a request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<Tls>,
+}
+
+/// A TLS ClientHello as it's logged. Without a handshake, these are what
+/// the client offered rather than what was negotiated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tls {
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+    /// the newest version offered, such as `TLSv1.3`
+    pub version: String,
+    pub ja3: String,
+    pub ja4: String,
+}
+
+impl Event {
+    pub fn new(req: &Request, resp: &Response, tags: Vec<String>, ending: Ending) -> Self {
+        Self {
+            source: req.requester(),
+            method: req.method.to_string(),
+            target: req.target.clone(),
+            version: req.version.clone(),
+            // as sent, keeping repeats, unless the request was built without
+            headers: match req.syntax.raw_headers.is_empty() {
+                false => req.syntax.raw_headers.clone(),
+                true => req
+                    .headers
+                    .iter()
+                    .flat_map(|(k, values)| values.iter().map(move |v| (k.clone(), v.clone())))
+                    .collect(),
+            },
+            body_len: req.body.len(),
+            body_sha256: sha256_hex(&req.body),
+            status: resp.code(),
+            response_len: resp.body().len(),
+            response_sha256: sha256_hex(resp.body()),
+            tags,
+            ending: Some(ending),
+            tls: None,
+        }
+    }
+
+    /// a session which sent a TLS ClientHello, and was closed without a
+    /// request.
+    pub fn client_hello(source: String, hello: &ClientHello) -> Self {
+        Self {
+            source,
+            method: String::new(),
+            target: String::new(),
+            version: String::new(),
+            headers: vec![],
+            body_len: 0,
+            body_sha256: sha256_hex(b""),
+            status: 0,
+            response_len: 0,
+            response_sha256: sha256_hex(b""),
+            tags: vec![],
+            ending: Some(Ending::Closed),
+            tls: Some(Tls {
+                sni: hello.sni.clone(),
+                alpn: hello.alpn.clone(),
+                version: client_hello::version_name(hello.max_version()),
+                ja3: hello.ja3(),
+                ja4: hello.ja4(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Record {
+    seq: u64,
+    at: String,
+    event: serde_json::Value,
+    prev: String,
+    mac: String,
+}
+
+/// The chain's head as of a record.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Anchor {
+    pub seq: u64,
+    pub at: String,
+    pub head: String,
+}
+
+/// What verifying a log found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verified {
+    pub records: u64,
+    pub head: String,
+    pub anchors: usize,
+}
+
+fn mac(key: &[u8], seq: u64, at: &str, event: &serde_json::Value, prev: &str) -> String {
+    let message = format!("{}\n{}\n{}\n{}", seq, at, event, prev);
+    let digest: [u8; 32] = match key.is_empty() {
+        true => Sha256::digest(message.as_bytes()).into(),
+        false => hmac_sha256(key, message.as_bytes()),
+    };
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn anchors_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.anchors", path.display()))
+}
+
+#[derive(Debug)]
+struct State {
+    file: File,
+    // the last record's
+    seq: u64,
+    head: String,
+    anchored: u64,
+}
+
+/// Appends events to the log.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path: PathBuf,
+    key: Arc<Vec<u8>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl EventLog {
+    /// opens the log, continuing the chain of any records already in it.
+    pub fn open(config: &EventLogConfig) -> Result<Self> {
+        let open_err = |e| anyhow!("failed to open event log {}: {}", config.path.display(), e);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&config.path)
+            .map_err(open_err)?;
+        let (seq, head) = match last_record(&mut file)? {
+            Some(record) => (record.seq, record.mac),
+            None => (0, GENESIS.to_string()),
+        };
+        let anchored = read_anchors(&anchors_path(&config.path))?
+            .last()
+            .map_or(0, |a| a.seq);
+
+        Ok(Self {
+            path: config.path.clone(),
+            key: Arc::new(config.key.clone().unwrap_or_default().into_bytes()),
+            state: Arc::new(Mutex::new(State {
+                file,
+                seq,
+                head,
+                anchored,
+            })),
+        })
+    }
+
+    /// appends the event, returning its sequence number.
+    pub fn append(&self, event: &Event) -> Result<u64> {
+        let event = serde_json::to_value(event)?;
+        let at = Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true);
+
+        let mut state = self.state.lock().unwrap();
+        let seq = state.seq + 1;
+        let record = Record {
+            mac: mac(&self.key, seq, &at, &event, &state.head),
+            seq,
+            at,
+            event,
+            prev: state.head.clone(),
+        };
+        // a record is only chained from once it's fully written
+        let mut line = display::to_json(&record)?;
+        line.push(b'\n');
+        state.file.write_all(&line)?;
+        state.file.flush()?;
+        state.seq = seq;
+        state.head = record.mac;
+        Ok(seq)
+    }
+
+    /// anchors the chain's head, if there are records since the last
+    /// anchor.
+    pub fn anchor(&self) -> Result<Option<Anchor>> {
+        let mut state = self.state.lock().unwrap();
+        if state.seq == state.anchored {
+            return Ok(None);
+        }
+        state.file.sync_data()?;
+
+        let anchor = Anchor {
+            seq: state.seq,
+            at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            head: state.head.clone(),
+        };
+        let mut anchors = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(anchors_path(&self.path))?;
+        writeln!(anchors, "{}", serde_json::to_string(&anchor)?)?;
+        anchors.sync_data()?;
+        state.anchored = anchor.seq;
+        Ok(Some(anchor))
+    }
+}
+
+impl EventSink for EventLog {
+    fn on_response(&self, event: &Event) {
+        if let Err(e) = self.append(event) {
+            warn!("failed to log event from {}: {}", event.source, e);
+        }
+    }
+}
+
+/// the last record in the log, which may be empty.
+fn last_record(file: &mut File) -> Result<Option<Record>> {
+    let len = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL)))?;
+    let mut tail = vec![];
+    file.read_to_end(&mut tail)?;
+
+    if tail.is_empty() {
+        return Ok(None);
+    }
+    if !tail.ends_with(b"\n") {
+        bail!("event log ends in a partial record; verify it and remove the partial record");
+    }
+    let line = tail[..tail.len() - 1]
+        .rsplit(|b| *b == b'\n')
+        .next()
+        .unwrap_or_default();
+    Ok(Some(serde_json::from_slice(line).map_err(|e| {
+        anyhow!("event log's last record is unreadable: {}", e)
+    })?))
+}
+
+fn read_anchors(path: &Path) -> Result<Vec<Anchor>> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    data.lines()
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// checks every record's MAC and its chaining from the one before, and
+/// that each anchor names a record's MAC. The key must be the one the log
+/// was written with.
+pub fn verify(path: &Path, key: Option<&str>) -> Result<Verified> {
+    let key = key.unwrap_or_default().as_bytes();
+    let file = File::open(path)?;
+    let mut heads = vec![];
+    let mut head = GENESIS.to_string();
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let seq = i as u64 + 1;
+        let record: Record = serde_json::from_str(&line?)
+            .map_err(|e| anyhow!("record {} is unreadable: {}", seq, e))?;
+        ensure!(
+            record.seq == seq,
+            "record {} is numbered {}, so records were removed or reordered",
+            seq,
+            record.seq
+        );
+        ensure!(
+            record.prev == head,
+            "record {} doesn't chain from record {}",
+            seq,
+            seq - 1
+        );
+        ensure!(
+            record.mac == mac(key, record.seq, &record.at, &record.event, &record.prev),
+            "record {} was altered, or the key is wrong",
+            seq
+        );
+        head = record.mac;
+        heads.push(head.clone());
+    }
+
+    let anchors = read_anchors(&anchors_path(path))?;
+    for anchor in &anchors {
+        let recorded = heads.get((anchor.seq as usize).wrapping_sub(1));
+        ensure!(
+            recorded == Some(&anchor.head),
+            "anchor at {} for record {} doesn't match the log",
+            anchor.at,
+            anchor.seq
+        );
+    }
+
+    Ok(Verified {
+        records: heads.len() as u64,
+        head,
+        anchors: anchors.len(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event(target: &str) -> Event {
+        Event {
+            source: "203.0.113.7:62012".to_string(),
+            method: "GET".to_string(),
+            target: target.to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: vec![("Host".to_string(), "example.com".to_string())],
+            body_len: 0,
+            body_sha256: sha256_hex(b""),
+            status: 200,
+            response_len: 0,
+            response_sha256: sha256_hex(b""),
+            tags: vec![],
+            ending: Some(Ending::Closed),
+            tls: None,
+        }
+    }
+
+    #[test]
+    fn test_client_hello() {
+        let hello = ClientHello {
+            version: 0x0303,
+            cipher_suites: vec![0x1301],
+            sni: Some("example.com".to_string()),
+            alpn: vec!["h2".to_string(), "\x1b[2J".to_string()],
+            supported_versions: vec![0x0304],
+            ..Default::default()
+        };
+        let session = Event::client_hello("203.0.113.7:62012".to_string(), &hello);
+        let logged = serde_json::to_value(&session).unwrap();
+        assert_eq!(hello.ja3(), logged["tls"]["ja3"]);
+        assert_eq!(hello.ja4(), logged["tls"]["ja4"]);
+        assert_eq!("example.com", logged["tls"]["sni"]);
+        assert_eq!("TLSv1.3", logged["tls"]["version"]);
+        let written = String::from_utf8(display::to_json(&session).unwrap()).unwrap();
+        assert!(
+            written.contains(r#""alpn":["h2","\u001b[2J"]"#),
+            "{}",
+            written
+        );
+        assert_eq!(session, serde_json::from_value(logged).unwrap());
+        // records without a hello are logged as before
+        let logged = serde_json::to_value(event("/")).unwrap();
+        assert!(logged.get("tls").is_none());
+    }
+
+    #[test]
+    fn test_chain() {
+        let dir = std::env::temp_dir().join(format!("httpot-events-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = EventLogConfig {
+            path: dir.join("events.jsonl"),
+            key: Some("secret".to_string()),
+            ..Default::default()
+        };
+
+        let log = EventLog::open(&config).unwrap();
+        log.append(&event("/")).unwrap();
+        log.append(&event("/.env")).unwrap();
+        let anchor = log.anchor().unwrap().unwrap();
+        assert_eq!(2, anchor.seq);
+        assert_eq!(None, log.anchor().unwrap(), "unchanged head was anchored");
+
+        // reopening continues the chain
+        let log = EventLog::open(&config).unwrap();
+        assert_eq!(3, log.append(&event("/admin")).unwrap());
+        let verified = verify(&config.path, config.key.as_deref()).unwrap();
+        assert_eq!(3, verified.records);
+        assert_eq!(1, verified.anchors);
+        assert!(verify(&config.path, Some("wrong")).is_err());
+
+        let original = fs::read_to_string(&config.path).unwrap();
+        fs::write(&config.path, original.replace("/.env", "/.git")).unwrap();
+        let err = verify(&config.path, config.key.as_deref()).unwrap_err();
+        assert!(err.to_string().contains("record 2 was altered"), "{}", err);
+
+        let mut lines = original.lines().collect::<Vec<_>>();
+        lines.remove(1);
+        fs::write(&config.path, lines.join("\n") + "\n").unwrap();
+        assert!(verify(&config.path, config.key.as_deref()).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,529 @@
+//! Follow-up fetcher for second-stage URLs found in payloads, such as the
+//! downloads of injected commands. Samples are fetched at a limited rate,
+//! optionally through a proxy, and only their hashes and headers are
+//! kept, so operators get sample hashes without retrieving them by hand.
+use std::{
+    collections::HashSet,
+    fs::OpenOptions,
+    io::Write,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{self, TcpStream},
+    sync::mpsc,
+    time,
+};
+use url::Url;
+
+use crate::{
+    display,
+    enrich::{Enricher, Verdict},
+    http::request::Request,
+    prelude::*,
+    sink::{EventSink, Payload},
+    util::Cidr,
+};
+
+// URLs waiting beyond this are dropped rather than queued
+const QUEUE_SIZE: usize = 64;
+// bounds memory used to skip URLs which were already fetched
+const MAX_SEEN: usize = 10_000;
+// response headers beyond this are not a sample worth keeping
+const MAX_HEADER_BYTES: usize = 16 * 1024;
+// fetched samples look like the bots' own downloads
+const USER_AGENT: &str = "Wget/1.20.3 (linux-gnu)";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchMethod {
+    /// headers only
+    #[default]
+    Head,
+    /// headers and a hash of the body, up to `max_bytes`
+    Get,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchConfig {
+    pub method: FetchMethod,
+    /// HTTP proxy, as host:port, which every fetch is sent through.
+    /// Without one, only plain HTTP URLs are fetched directly. Names are
+    /// checked before they're sent to it, but it resolves them again, so
+    /// it should refuse private destinations itself.
+    pub proxy: Option<String>,
+    /// bodies are hashed up to this many bytes
+    pub max_bytes: usize,
+    /// fetches started per minute
+    pub per_minute: u32,
+    pub timeout_secs: u64,
+    /// whether URLs naming private, loopback, or link-local addresses are
+    /// fetched
+    pub allow_private: bool,
+    /// JSON lines file samples are appended to, besides being logged
+    pub store: Option<PathBuf>,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            method: FetchMethod::Head,
+            proxy: None,
+            max_bytes: 10 * 1024 * 1024,
+            per_minute: 6,
+            timeout_secs: 30,
+            allow_private: false,
+            store: None,
+        }
+    }
+}
+
+/// What was learned about a fetched URL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Sample {
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// bytes of body read, at most `max_bytes`
+    pub size: usize,
+    /// the body was longer than `max_bytes`, so the hash is of a prefix
+    pub truncated: bool,
+    pub sha256: Option<String>,
+    pub md5: Option<String>,
+    /// lookups of the hash, when enrichment is configured
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub verdicts: Vec<Verdict>,
+}
+
+/// Queues URLs for a background task which fetches them.
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    tx: mpsc::Sender<Url>,
+    seen: Arc<Mutex<HashSet<String>>>,
+    allow_private: bool,
+}
+
+impl Fetcher {
+    /// starts the fetch task on the current runtime. Hashes of fetched
+    /// bodies are looked up with the enricher, if any.
+    pub fn spawn(config: FetchConfig, enricher: Option<Enricher>) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let fetcher = Self {
+            tx,
+            seen: Default::default(),
+            allow_private: config.allow_private,
+        };
+        tokio::spawn(run(config, enricher, rx));
+        fetcher
+    }
+
+    /// queues the URL if it can be fetched and hasn't been already.
+    /// Returns whether it was queued.
+    pub fn submit(&self, url: &str) -> bool {
+        let url = match Url::parse(url) {
+            Ok(url) if self.permitted(&url) => url,
+            _ => return false,
+        };
+
+        {
+            let mut seen = self.seen.lock().unwrap();
+            if seen.len() >= MAX_SEEN {
+                seen.clear();
+            }
+            if !seen.insert(url.to_string()) {
+                return false;
+            }
+        }
+
+        match self.tx.try_send(url) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("dropped a fetch: {}", e);
+                false
+            }
+        }
+    }
+
+    fn permitted(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        match url.host() {
+            Some(url::Host::Domain(d)) => self.allow_private || d != "localhost",
+            Some(url::Host::Ipv4(ip)) => self.allow_private || is_public(ip.into()),
+            Some(url::Host::Ipv6(ip)) => self.allow_private || is_public(ip.into()),
+            None => false,
+        }
+    }
+}
+
+impl EventSink for Fetcher {
+    fn on_payload(&self, _req: &Request, payload: &Payload) {
+        for url in payload.download_urls() {
+            self.submit(&url);
+        }
+    }
+}
+
+lazy_static! {
+    // special-purpose blocks which aren't the public internet's
+    static ref NON_PUBLIC: Vec<Cidr> = [
+        "0.0.0.0/8",
+        "10.0.0.0/8",
+        "100.64.0.0/10",
+        "127.0.0.0/8",
+        "169.254.0.0/16",
+        "172.16.0.0/12",
+        "192.0.0.0/24",
+        "192.168.0.0/16",
+        "198.18.0.0/15",
+        "224.0.0.0/4",
+        "240.0.0.0/4",
+        "fc00::/7",
+        "fe80::/10",
+        "ff00::/8",
+    ]
+    .iter()
+    .map(|block| block.parse().unwrap())
+    .collect();
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    // v4-mapped addresses, ::ffff:a.b.c.d, are canonicalized by contains;
+    // v4-compatible ones, ::a.b.c.d, and NAT64's, 64:ff9b::a.b.c.d, reach
+    // the v4 address too. :: and ::1 are 0.0.0.0/8's
+    let ip = match ip {
+        IpAddr::V6(v6)
+            if matches!(
+                v6.segments(),
+                [0, 0, 0, 0, 0, 0, ..] | [0x64, 0xff9b, 0, 0, 0, 0, ..]
+            ) =>
+        {
+            IpAddr::V4(Ipv4Addr::from(v6.to_bits() as u32))
+        }
+        ip => ip,
+    };
+    !NON_PUBLIC.iter().any(|block| block.contains(ip))
+}
+
+async fn run(config: FetchConfig, enricher: Option<Enricher>, mut rx: mpsc::Receiver<Url>) {
+    let interval = Duration::from_secs(60) / config.per_minute.max(1);
+    let timeout = Duration::from_secs(config.timeout_secs);
+
+    while let Some(url) = rx.recv().await {
+        match time::timeout(timeout, fetch(&config, &url)).await {
+            Ok(Ok(mut sample)) => {
+                if let (Some(enricher), Some(sha256)) = (&enricher, &sample.sha256) {
+                    sample.verdicts = enricher.lookup(sha256).await;
+                }
+                info!(
+                    "fetched {} status {} {} bytes sha256={} {}",
+                    sample.url,
+                    sample.status,
+                    sample.size,
+                    sample.sha256.as_deref().unwrap_or("-"),
+                    sample
+                        .verdicts
+                        .iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+                if let Some(path) = &config.store {
+                    if let Err(e) = store(path, &sample) {
+                        warn!("failed to store sample of {}: {}", sample.url, e);
+                    }
+                }
+            }
+            Ok(Err(e)) => info!("failed to fetch {}: {}", url, e),
+            Err(_) => info!("fetch of {} timed out", url),
+        }
+
+        time::sleep(interval).await;
+    }
+}
+
+fn store(path: &Path, sample: &Sample) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = display::to_json(sample)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
+
+async fn fetch(config: &FetchConfig, url: &Url) -> Result<Sample> {
+    let method = match config.method {
+        FetchMethod::Head => "HEAD",
+        FetchMethod::Get => "GET",
+    };
+    let req = RawRequest {
+        method,
+        url,
+        headers: vec![],
+        body: &[],
+    };
+    let resp = exchange(
+        &req,
+        config.proxy.as_deref(),
+        config.allow_private,
+        config.max_bytes + 1,
+    )
+    .await?;
+
+    let mut sample = Sample {
+        url: url.to_string(),
+        status: resp.status,
+        headers: resp.headers,
+        size: 0,
+        truncated: false,
+        sha256: None,
+        md5: None,
+        verdicts: vec![],
+    };
+    if config.method == FetchMethod::Get {
+        let body = &resp.body[..resp.body.len().min(config.max_bytes)];
+        sample.truncated = resp.body.len() > config.max_bytes;
+        sample.size = body.len();
+        sample.sha256 = Some(sha256_hex(body));
+        sample.md5 = Some(format!("{:x}", md5::compute(body)));
+    }
+
+    Ok(sample)
+}
+
+/// the lowercase hex SHA-256 of the data, as samples are known by.
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// A request sent by `exchange`.
+pub(crate) struct RawRequest<'a> {
+    pub method: &'a str,
+    pub url: &'a Url,
+    /// sent in addition to Host, User-Agent, Accept, and Connection
+    pub headers: Vec<(&'a str, String)>,
+    pub body: &'a [u8],
+}
+
+/// A response read by `exchange`.
+#[derive(Debug)]
+pub(crate) struct RawResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// sends the request directly, or through the proxy, and reads up to
+/// `max_bytes` of the response's body. HTTP/1.0 is spoken so the body
+/// is never chunked. Without a proxy, only plain HTTP is supported.
+pub(crate) async fn exchange(
+    req: &RawRequest<'_>,
+    proxy: Option<&str>,
+    allow_private: bool,
+    max_bytes: usize,
+) -> Result<RawResponse> {
+    let host = req.url.host_str().ok_or_else(|| anyhow!("no host"))?;
+    let port = req
+        .url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("no port"))?;
+
+    let mut stream = match proxy {
+        // proxies are sent the absolute URL, https included. The name is
+        // checked here, but the proxy resolves it again, so only its own
+        // egress rules can refuse a name rebound in between
+        Some(proxy) => {
+            resolve(host, port, allow_private).await?;
+            TcpStream::connect(proxy).await?
+        }
+        None => {
+            ensure!(
+                req.url.scheme() == "http",
+                "https is only fetched through a proxy"
+            );
+            let addr = resolve(host, port, allow_private).await?;
+            TcpStream::connect(addr).await?
+        }
+    };
+
+    stream
+        .write_all(request_head(req, proxy.is_some()).as_bytes())
+        .await?;
+    stream.write_all(req.body).await?;
+
+    let mut raw = Vec::new();
+    (&mut stream)
+        .take((MAX_HEADER_BYTES + max_bytes) as u64)
+        .read_to_end(&mut raw)
+        .await?;
+
+    parse_response(&raw)
+}
+
+/// resolves the host, refusing it if any of its addresses aren't public
+/// unless they're allowed. Names can point anywhere, so this is checked
+/// after resolution.
+async fn resolve(host: &str, port: u16, allow_private: bool) -> Result<SocketAddr> {
+    let host = host.trim_matches(|c| c == '[' || c == ']');
+    let addrs: Vec<SocketAddr> = net::lookup_host((host, port)).await?.collect();
+    if let Some(addr) = addrs.iter().find(|a| !allow_private && !is_public(a.ip())) {
+        bail!("{} resolved to non-public {}", host, addr.ip());
+    }
+    addrs
+        .first()
+        .copied()
+        .ok_or_else(|| anyhow!("{} did not resolve", host))
+}
+
+fn request_head(req: &RawRequest, proxied: bool) -> String {
+    let url = req.url;
+    let target = match (proxied, url.query()) {
+        (true, _) => url.as_str().to_string(),
+        (false, Some(q)) => format!("{}?{}", url.path(), q),
+        (false, None) => url.path().to_string(),
+    };
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let mut head = format!(
+        "{} {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: {}\r\nAccept: */*\r\nConnection: close\r\n",
+        req.method, target, host, USER_AGENT
+    );
+    for (k, v) in &req.headers {
+        head += &format!("{}: {}\r\n", k, v);
+    }
+    if !req.body.is_empty() {
+        head += &format!("Content-Length: {}\r\n", req.body.len());
+    }
+    head + "\r\n"
+}
+
+fn parse_response(raw: &[u8]) -> Result<RawResponse> {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("response headers did not end"))?;
+    let head = String::from_utf8_lossy(&raw[..end]);
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|l| l.split_whitespace().nth(1))
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!("malformed status line"))?;
+    let headers = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+
+    Ok(RawResponse {
+        status,
+        headers,
+        body: raw[end + 4..].to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_request_head() {
+        let url = Url::parse("http://203.0.113.9:8080/bins/x86?v=1").unwrap();
+        let mut req = RawRequest {
+            method: "HEAD",
+            url: &url,
+            headers: vec![],
+            body: &[],
+        };
+        assert_eq!(
+            "HEAD /bins/x86?v=1 HTTP/1.0\r\nHost: 203.0.113.9:8080\r\nUser-Agent: Wget/1.20.3 (linux-gnu)\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+            request_head(&req, false)
+        );
+
+        req.method = "POST";
+        req.headers.push(("Auth-Key", "secret".to_string()));
+        req.body = b"a=b";
+        let head = request_head(&req, true);
+        assert!(head.starts_with("POST http://203.0.113.9:8080/bins/x86?v=1 HTTP/1.0\r\n"));
+        assert!(head.ends_with("Auth-Key: secret\r\nContent-Length: 3\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: 4\r\n\r\n\x7fELF";
+        let resp = parse_response(raw).unwrap();
+        assert_eq!(200, resp.status);
+        assert_eq!(b"\x7fELF".to_vec(), resp.body);
+        assert_eq!(
+            (
+                "Content-Type".to_string(),
+                "application/octet-stream".to_string()
+            ),
+            resp.headers[0]
+        );
+
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+
+    #[test]
+    fn test_is_public() {
+        for ip in [
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "::127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            "100.64.0.1",
+            "100.127.255.254",
+            "0.1.2.3",
+            "192.0.0.170",
+            "198.18.0.1",
+            "240.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "fd00::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "203.0.113.9",
+            "100.128.0.1",
+            "::ffff:8.8.8.8",
+            "2001:4860::8888",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve() {
+        assert!(resolve("localhost", 80, false).await.is_err());
+        assert!(resolve("localhost", 80, true).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_submit() {
+        let fetcher = Fetcher::spawn(FetchConfig::default(), None);
+        assert!(!fetcher.submit("tftp://203.0.113.9/mips"));
+        assert!(!fetcher.submit("http://192.168.1.1/mips"));
+        assert!(!fetcher.submit("http://[::1]/mips"));
+        assert!(!fetcher.submit("http://localhost/mips"));
+        assert!(!fetcher.submit("http://[::ffff:127.0.0.1]/mips"));
+        assert!(fetcher.submit("http://203.0.113.9/mips"));
+        assert!(!fetcher.submit("http://203.0.113.9/mips"), "fetched twice");
+    }
+}
@@ -0,0 +1,164 @@
+//! Client fingerprints derived from how a request is written rather than
+//! what it claims to be, akin to JA3 for HTTP. Tools which rotate their
+//! User-Agent usually keep their header order and syntax.
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::http::request::Request;
+
+// hex characters of the hash kept
+const HASH_LEN: usize = 12;
+// hex characters of the hash used as a low cardinality bucket
+const BUCKET_LEN: usize = 3;
+
+/// How a client capitalizes header names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Casing {
+    /// `user-agent`
+    Lower,
+    /// `User-Agent`
+    Title,
+    /// `USER-AGENT`
+    Upper,
+    /// some combination of the above
+    Mixed,
+}
+
+impl Casing {
+    fn of(name: &str) -> Self {
+        if name.chars().all(|c| !c.is_ascii_uppercase()) {
+            Casing::Lower
+        } else if name.chars().all(|c| !c.is_ascii_lowercase()) {
+            Casing::Upper
+        } else if name
+            .split('-')
+            .all(|w| w.chars().skip(1).all(|c| !c.is_ascii_uppercase()))
+        {
+            Casing::Title
+        } else {
+            Casing::Mixed
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Casing::Lower => "lower",
+            Casing::Title => "title",
+            Casing::Upper => "upper",
+            Casing::Mixed => "mixed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// lowercase header names in the order sent
+    pub names: Vec<String>,
+    pub casing: Casing,
+    pub bare_lf: bool,
+    pub no_space_after_colon: bool,
+    hash: String,
+}
+
+impl Fingerprint {
+    pub fn new(req: &Request) -> Self {
+        let names = req
+            .headers
+            .names()
+            .map(|n| n.to_lowercase())
+            .collect::<Vec<_>>();
+
+        let casing = req
+            .headers
+            .names()
+            .map(Casing::of)
+            .reduce(|l, r| if l == r { l } else { Casing::Mixed })
+            .unwrap_or(Casing::Lower);
+
+        let raw = format!(
+            "{}|{}|{}|{}",
+            casing.as_str(),
+            req.syntax.bare_lf as u8,
+            req.syntax.no_space_after_colon as u8,
+            names.join(",")
+        );
+        let hash = Sha256::digest(raw.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()[..HASH_LEN]
+            .to_string();
+
+        Self {
+            names,
+            casing,
+            bare_lf: req.syntax.bare_lf,
+            no_space_after_colon: req.syntax.no_space_after_colon,
+            hash,
+        }
+    }
+
+    /// stable hash of the fingerprint's components.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    /// a prefix of the hash with bounded cardinality, suitable for
+    /// metric labels.
+    pub fn bucket(&self) -> &str {
+        &self.hash[..BUCKET_LEN]
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.hash)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::parse_request;
+    use tokio::io::BufReader;
+
+    async fn fingerprint(input: &str) -> Fingerprint {
+        let peer = "127.0.0.1:8000".parse().unwrap();
+        let req = parse_request(&peer, &mut BufReader::new(input.as_bytes()))
+            .await
+            .unwrap();
+        Fingerprint::new(&req)
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint() {
+        let curl = fingerprint(
+            "GET / HTTP/1.1\r\nHost: a.com\r\nUser-Agent: curl/7.83.1\r\nAccept: */*\r\n\r\n",
+        )
+        .await;
+        assert_eq!(Casing::Title, curl.casing);
+        assert_eq!(vec!["host", "user-agent", "accept"], curl.names);
+        assert!(!curl.bare_lf);
+
+        // the user agent doesn't matter, only how the request is written
+        let rotated = fingerprint(
+            "GET /x HTTP/1.1\r\nHost: b.com\r\nUser-Agent: Mozilla/5.0\r\nAccept: */*\r\n\r\n",
+        )
+        .await;
+        assert_eq!(curl.hash(), rotated.hash());
+        assert_eq!(HASH_LEN, curl.hash().len());
+
+        let reordered = fingerprint(
+            "GET / HTTP/1.1\r\nUser-Agent: curl/7.83.1\r\nHost: a.com\r\nAccept: */*\r\n\r\n",
+        )
+        .await;
+        assert_ne!(curl.hash(), reordered.hash());
+
+        let scripted =
+            fingerprint("GET / HTTP/1.1\nhost:a.com\nuser-agent:curl/7.83.1\naccept:*/*\n\n").await;
+        assert_eq!(Casing::Lower, scripted.casing);
+        assert!(scripted.bare_lf);
+        assert!(scripted.no_space_after_colon);
+        assert_ne!(curl.hash(), scripted.hash());
+    }
+}
@@ -1,74 +1,345 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
+use std::{collections::HashSet, hash::Hash};
 
 use chrono::{offset::Utc, DateTime, Datelike, TimeZone, Timelike};
 use rand::{
     distributions::{Alphanumeric, DistString},
     prelude::*,
-    Fill,
 };
+use serde::{Deserialize, Serialize};
 use typed_html::{dom::DOMTree, html, text, types::Metadata};
 
-use crate::prelude::*;
+use crate::{
+    honeypot::{bait, tarpit},
+    locale::Language,
+    prelude::*,
+    util::hash_seed,
+};
+
+// trees are generated as of this date, then changed by the timeline
+const TIMELINE_START: &str = "2025-01-01T00:00:00Z";
+// a directory changes about once in this many hours, whatever the cadence
+const HOURS_PER_CHANGE: u64 = 14 * 24;
+
+/// When fake trees change. Each period of the cadence since the timeline
+/// started may add a file to a directory or touch one, so trees grow over
+/// days of crawling while everything listed before stays listed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timeline {
+    /// hours between changes, or 0 for trees which never change
+    pub cadence_hours: u64,
+}
+
+impl Timeline {
+    fn start() -> DateTime<Utc> {
+        TIMELINE_START.parse().unwrap()
+    }
+
+    /// the start of every period which has begun by now.
+    fn periods(&self, now: DateTime<Utc>) -> impl Iterator<Item = (u64, DateTime<Utc>)> {
+        let cadence = chrono::Duration::hours(self.cadence_hours as i64);
+        let elapsed = (now - Self::start()).num_hours().max(0) as u64;
+        let count = match self.cadence_hours {
+            0 => 0,
+            hours => elapsed / hours + 1,
+        };
+        (0..count).map(move |i| (i, Self::start() + cadence * i as i32))
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FakeFsConfig {
+    /// what generated trees look like
+    pub theme: Theme,
+}
+
+/// What generated names look like. Themes other than random draw names
+/// from bundled wordlists of patterns, where `{year}`, `{month}`, and
+/// `{day}` are filled from the file's modification date, `{n}` is a
+/// four digit number, and `{v}` a small one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// random alphanumerics
+    #[default]
+    Random,
+    /// a company file share
+    Corporate,
+    /// website and database backups
+    Backups,
+    /// a home media server
+    Media,
+    /// a source code dump
+    Source,
+}
+
+impl Theme {
+    /// the folder and file wordlists of the theme.
+    fn wordlists(&self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Theme::Random => None,
+            Theme::Corporate => Some((
+                include_str!("wordlists/corporate_dirs.txt"),
+                include_str!("wordlists/corporate_files.txt"),
+            )),
+            Theme::Backups => Some((
+                include_str!("wordlists/backups_dirs.txt"),
+                include_str!("wordlists/backups_files.txt"),
+            )),
+            Theme::Media => Some((
+                include_str!("wordlists/media_dirs.txt"),
+                include_str!("wordlists/media_files.txt"),
+            )),
+            Theme::Source => Some((
+                include_str!("wordlists/source_dirs.txt"),
+                include_str!("wordlists/source_files.txt"),
+            )),
+        }
+    }
+
+    fn folder_name<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        match self.wordlists() {
+            Some((folders, _)) => choose_line(rng, folders).to_string(),
+            None => string_of_size(rng, 6, 15),
+        }
+    }
+
+    fn file_name<R: Rng + ?Sized>(&self, rng: &mut R, modified_at: DateTime<Utc>) -> String {
+        match self.wordlists() {
+            Some((_, files)) => choose_line(rng, files)
+                .replace("{year}", &modified_at.format("%Y").to_string())
+                .replace("{month}", &modified_at.format("%m").to_string())
+                .replace("{day}", &modified_at.format("%d").to_string())
+                .replace("{n}", &rng.gen_range(1000..10000).to_string())
+                .replace("{v}", &rng.gen_range(1..=4).to_string()),
+            // as_str, as `+ &String` is ambiguous with rhai's smartstring
+            None => string_of_size(rng, 4, 10) + "." + string_of_size(rng, 1, 3).as_str(),
+        }
+    }
+}
 
-// hashes path and seed together
-fn hash_path_seed<T: Hash>(seed: T, path: &str) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    seed.hash(&mut hasher);
-    path.hash(&mut hasher);
-    hasher.finish()
+fn choose_line<'a, R: Rng + ?Sized>(rng: &mut R, wordlist: &'a str) -> &'a str {
+    wordlist
+        .lines()
+        .filter(|l| !l.is_empty())
+        .choose(rng)
+        .unwrap_or_default()
+}
+
+/// How fake trees are generated, besides their seed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tree {
+    pub theme: Theme,
+    pub timeline: Timeline,
+    /// list tarpit files, which are only served when the tarpit is on
+    pub tarpit: bool,
 }
 
 /// Return a rendered listing links provided with the same named
 /// subpath. The seed is used with the provided path to deterministically
-/// generate random directories and folders.
-fn gen_fake_nodes<T: Hash>(seed: T, path: &str) -> Vec<Node> {
-    let mut rng = StdRng::seed_from_u64(hash_path_seed(seed, path));
+/// generate random directories and folders, which change with the
+/// timeline up to now.
+fn gen_fake_nodes<T: Hash>(seed: T, path: &str, tree: Tree, now: DateTime<Utc>) -> Vec<Node> {
+    let mut rng = StdRng::seed_from_u64(hash_seed(&seed, path));
+    let until = Timeline::start().min(now);
+    let timeline = tree.timeline;
 
     let files = rng.gen_range(2..=8);
     let folders = rng.gen_range(4..=15);
 
-    (0..folders)
+    let mut nodes: Vec<Node> = (0..folders)
         .map(|_| Node::Right(Default::default()))
         .chain((0..files).map(|_| Node::Left(Default::default())))
         .map(|mut n| {
-            rng.fill(&mut n);
+            let modified_at = plausible_datetime(&mut rng, until).unwrap_or_default();
+            fill(&mut rng, &mut n, tree.theme, modified_at);
             n
         })
+        .collect();
+    // some directories leak something worth downloading
+    if rng.gen_ratio(1, 4) {
+        let mut file = File {
+            name: bait::FILES.choose(&mut rng).unwrap().to_string(),
+            modified_at: plausible_datetime(&mut rng, until).unwrap_or_default(),
+            size: 0,
+        };
+        file.size = bait::file(&seed, &format!("{}{}", dir(path), file.name))
+            .map(|(_, body)| body.len())
+            .unwrap_or_default();
+        nodes.push(Node::Left(file));
+    }
+    if tree.tarpit && rng.gen_ratio(1, 3) {
+        let name = tarpit::FILES.choose(&mut rng).unwrap().to_string();
+        nodes.push(Node::Left(File {
+            size: tarpit::size(&seed, &format!("{}{}", dir(path), name)),
+            name,
+            modified_at: plausible_datetime(&mut rng, until).unwrap_or_default(),
+        }));
+    }
+    // wordlists are short, so names can repeat
+    let mut seen = HashSet::new();
+    nodes.retain(|n| seen.insert(n.name()));
+
+    let odds = (HOURS_PER_CHANGE / timeline.cadence_hours.max(1)).max(1);
+    for (period, begins) in timeline.periods(now) {
+        let roll = hash_seed((&seed, period), path);
+        if roll % odds != 0 {
+            continue;
+        }
+
+        let mut rng = StdRng::seed_from_u64(roll);
+        let at = begins
+            + chrono::Duration::seconds(rng.gen_range(0..timeline.cadence_hours as i64 * 3600));
+        if at > now {
+            continue;
+        }
+
+        let files = nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| matches!(n, Node::Left(_)))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let touched = match rng.gen_bool(0.3) {
+            true => files.choose(&mut rng).copied(),
+            false => None,
+        };
+        match touched {
+            Some(i) => {
+                if let Node::Left(f) = &mut nodes[i] {
+                    f.modified_at = at;
+                    f.size = rng.gen_range(0..=(32 * 1024 * 1024));
+                }
+            }
+            None => {
+                let mut file = Node::Left(Default::default());
+                fill(&mut rng, &mut file, tree.theme, at);
+                if seen.insert(file.name()) {
+                    nodes.push(file);
+                }
+            }
+        }
+    }
+
+    nodes
+}
+
+/// A generated directory entry, for rendering listings elsewhere.
+#[derive(Debug, Clone, Serialize)]
+pub struct Entry {
+    pub name: String,
+    pub modified_at: String,
+    pub size: Option<usize>,
+    pub is_dir: bool,
+}
+
+impl Entry {
+    /// an entry for a real file or directory.
+    pub fn new(name: &str, is_dir: bool, modified_at: DateTime<Utc>, size: Option<usize>) -> Self {
+        Self {
+            name: match is_dir {
+                true => format!("{}/", name),
+                false => name.to_string(),
+            },
+            modified_at: modified_at.format(MODIFIED_FORMAT).to_string(),
+            size,
+            is_dir,
+        }
+    }
+}
+
+/// Returns the same entries gen_fake_listing renders for the seed and path.
+pub fn gen_fake_entries<T: Hash>(seed: T, path: &str, tree: Tree) -> Vec<Entry> {
+    gen_fake_nodes(seed, path, tree, Utc::now())
+        .into_iter()
+        .map(|n| Entry {
+            name: n.name(),
+            modified_at: n.modified_at(),
+            size: n.size(),
+            is_dir: matches!(n, Node::Right(_)),
+        })
         .collect()
 }
 
-pub fn gen_fake_listing<T: Hash>(seed: T, path: &str) -> String {
-    let nodes = gen_fake_nodes(seed, path);
-    let basepath = if path == "" {
-        "/".to_string()
-    } else if path.ends_with("/") {
-        path.to_string()
-    } else {
-        path.to_owned() + "/"
+/// A generated file, as its directory lists it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FakeFile {
+    pub name: String,
+    pub modified_at: DateTime<Utc>,
+    pub size: usize,
+}
+
+/// Returns the file at the path if its directory's listing has one there,
+/// so anything fetched matches what was listed.
+pub fn gen_fake_file<T: Hash>(seed: T, path: &str, tree: Tree) -> Option<FakeFile> {
+    let (parent, name) = path.rsplit_once('/')?;
+    gen_fake_nodes(seed, &dir(parent), tree, Utc::now())
+        .into_iter()
+        .find_map(|n| match n {
+            Node::Left(f) if f.name == name => Some(FakeFile {
+                name: f.name,
+                modified_at: f.modified_at,
+                size: f.size,
+            }),
+            _ => None,
+        })
+}
+
+/// Returns whether the path, without its trailing slash, is a directory
+/// its parent's listing has, which servers redirect to with the slash.
+pub fn is_fake_dir<T: Hash>(seed: T, path: &str, tree: Tree) -> bool {
+    let (parent, name) = match path.rsplit_once('/') {
+        Some((parent, name)) if !name.is_empty() => (parent, name),
+        _ => return false,
     };
+    gen_fake_nodes(seed, &dir(parent), tree, Utc::now())
+        .into_iter()
+        .any(|n| matches!(&n, Node::Right(d) if d.name == name))
+}
+
+/// The contents of a generated file of the size. They're the same on
+/// every request for the path, so checksums published alongside verify.
+pub fn gen_fake_content<T: Hash>(seed: T, path: &str, size: usize) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(hash_seed((&seed, "content"), path));
+    (0..size)
+        .map(|i| match i % 77 {
+            76 => b'\n',
+            _ => Alphanumeric.sample(&mut rng),
+        })
+        .collect()
+}
+
+/// The listing's text is in the provided language; names and dates are
+/// the same in every language.
+pub fn gen_fake_listing<T: Hash>(seed: T, path: &str, lang: Language, tree: Tree) -> String {
+    render_listing(path, lang, gen_fake_entries(seed, path, tree))
+}
+
+/// Renders a listing of the entries at the path, as for generated ones.
+pub fn render_listing(path: &str, lang: Language, entries: Vec<Entry>) -> String {
+    let basepath = dir(path);
 
     let doc: DOMTree<String> = html!(
         <html>
           <head>
-            <title>{ text!("Index of {}", basepath) }</title>
+            <title>{ text!("{} {}", lang.index_of(), basepath) }</title>
             <meta name=Metadata::Description content="Generated Directory Listing"/>
           </head>
           <body>
 
-            <h1>{ text!("Index of {}", basepath) }</h1>
+            <h1>{ text!("{} {}", lang.index_of(), basepath) }</h1>
             <hr />
             <pre>
               <a href="../">"../"</a> "\n"
-              { nodes.into_iter().map(|n| {
-                  let line = format!("{: <40}{: >20}{: >20}", n.name(), n.modified_at(), n.size().map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()));
+              { entries.into_iter().map(|n| {
+                  let line = format!("{: <40}{: >20}{: >20}", n.name, n.modified_at, n.size.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()));
                   // take the non-name portion of the line which is appropriately
                   // space padded now
-                  let line = line[n.name().len()..].to_string();
+                  let line = line[n.name.len()..].to_string();
 
                   html!(
                     <span>
-                      <a href=n.name()>{ text!("{}", n.name()) }</a>
+                      <a href=n.name.clone()>{ text!("{}", n.name) }</a>
                       <span>
                       { text!("{}", line) }
                       </span>
@@ -86,6 +357,17 @@ pub fn gen_fake_listing<T: Hash>(seed: T, path: &str) -> String {
     doc.to_string()
 }
 
+/// the path as a directory, ending in a slash.
+fn dir(path: &str) -> String {
+    if path.is_empty() {
+        "/".to_string()
+    } else if path.ends_with("/") {
+        path.to_string()
+    } else {
+        path.to_owned() + "/"
+    }
+}
+
 type Node = Either<File, Folder>;
 
 #[derive(Debug)]
@@ -134,21 +416,18 @@ impl Node {
     }
 }
 
-impl Fill for Node {
-    fn try_fill<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Result<(), rand::Error> {
-        match self {
-            Node::Left(ref mut f) => {
-                f.name = string_of_size(rng, 4, 10) + "." + &string_of_size(rng, 1, 3);
-                f.modified_at = plausible_datetime(rng).unwrap_or_default();
-                f.size = rng.gen_range(0..=(32 * 1024 * 1024));
-            }
-            Node::Right(ref mut n) => {
-                n.name = string_of_size(rng, 6, 15);
-                n.modified_at = plausible_datetime(rng).unwrap_or_default();
-            }
+/// fills the node with random values in the theme.
+fn fill<R: Rng + ?Sized>(rng: &mut R, node: &mut Node, theme: Theme, modified_at: DateTime<Utc>) {
+    match node {
+        Node::Left(ref mut f) => {
+            f.name = theme.file_name(rng, modified_at);
+            f.modified_at = modified_at;
+            f.size = rng.gen_range(0..=(32 * 1024 * 1024));
+        }
+        Node::Right(ref mut n) => {
+            n.name = theme.folder_name(rng);
+            n.modified_at = modified_at;
         }
-
-        Ok(())
     }
 }
 
@@ -157,9 +436,9 @@ fn string_of_size<R: Rng + ?Sized>(rng: &mut R, min: usize, max: usize) -> Strin
     Alphanumeric.sample_string(rng, size)
 }
 
-/// creates a plausible datetime from 2000 to now which does not go into the future.
-fn plausible_datetime<R: Rng + ?Sized>(rng: &mut R) -> Result<DateTime<Utc>> {
-    let now = Utc::now();
+/// creates a plausible datetime from 2000 to now which does not go past
+/// now.
+fn plausible_datetime<R: Rng + ?Sized>(rng: &mut R, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
     let year = rng.gen_range(2000..=now.year());
     let month = if year == now.year() {
         rng.gen_range(1..=now.month())
@@ -226,3 +505,94 @@ fn get_days_in_month(year: i32, month: u32) -> Result<u32> {
     .try_into()
     .map_err(|e| anyhow!("failed to subtract for yy/mm {}/{}: {}", year, month, e))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn names(nodes: &[Node]) -> Vec<String> {
+        nodes.iter().map(|n| n.name()).collect()
+    }
+
+    #[test]
+    fn test_timeline() {
+        let timeline = Tree {
+            timeline: Timeline { cadence_hours: 24 },
+            ..Default::default()
+        };
+        let start = Timeline::start();
+        let day = chrono::Duration::days(1);
+
+        let before = gen_fake_nodes("seed", "/", timeline, start + day * 30);
+        let after = gen_fake_nodes("seed", "/", timeline, start + day * 365);
+        assert!(after.len() > before.len(), "a year added no files");
+        assert_eq!(names(&before), names(&after)[..before.len()]);
+        for node in &before {
+            if let Node::Left(f) = node {
+                assert!(f.modified_at <= start + day * 30, "modified in the future");
+            }
+        }
+
+        let frozen = Tree::default();
+        assert_eq!(
+            names(&gen_fake_nodes("seed", "/", frozen, start + day * 30)),
+            names(&gen_fake_nodes("seed", "/", frozen, start + day * 365))
+        );
+    }
+
+    #[test]
+    fn test_files() {
+        let tree = Tree::default();
+        let entry = gen_fake_entries("seed", "/backups/", tree)
+            .into_iter()
+            .find(|e| !e.is_dir)
+            .unwrap();
+        let path = format!("/backups/{}", entry.name);
+
+        let file = gen_fake_file("seed", &path, tree).unwrap();
+        assert_eq!(entry.size, Some(file.size));
+        assert_eq!(
+            entry.modified_at,
+            file.modified_at.format(MODIFIED_FORMAT).to_string()
+        );
+        assert!(gen_fake_file("seed", "/backups/missing.zip", tree).is_none());
+
+        let content = gen_fake_content("seed", &path, file.size);
+        assert_eq!(file.size, content.len());
+        assert_eq!(content, gen_fake_content("seed", &path, file.size));
+
+        let dir = gen_fake_entries("seed", "/backups/", tree)
+            .into_iter()
+            .find(|e| e.is_dir)
+            .unwrap();
+        let dir = format!("/backups/{}", dir.name.trim_end_matches('/'));
+        assert!(is_fake_dir("seed", &dir, tree));
+        assert!(!is_fake_dir("seed", &path, tree));
+        assert!(!is_fake_dir("seed", "/backups/", tree));
+    }
+
+    #[test]
+    fn test_themes() {
+        let tree = Tree {
+            theme: Theme::Backups,
+            ..Default::default()
+        };
+        let nodes = gen_fake_nodes("seed", "/", tree, Timeline::start());
+        let (folders, files) = Theme::Backups.wordlists().unwrap();
+        for node in nodes {
+            match node {
+                Node::Left(f) => {
+                    assert!(!f.name.contains('{'), "unfilled pattern {}", f.name);
+                    assert!(
+                        files
+                            .lines()
+                            .any(|l| f.name.starts_with(l.split('{').next().unwrap_or(l))),
+                        "{} is not from the wordlist",
+                        f.name
+                    );
+                }
+                Node::Right(d) => assert!(folders.lines().any(|l| l == d.name)),
+            }
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! Real directories mounted read-only at URL prefixes, so curated decoy
+//! documents can sit alongside the generated tree. Files are served as-is
+//! and directories are listed like generated ones. Paths which don't
+//! exist under the mount are passed on to the generated tree.
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    fs::fake::{render_listing, Entry},
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    persona::Persona,
+    prelude::*,
+    util::{content_type_for_path, percent_decode},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlayConfig {
+    /// URL prefix the directory is mounted at, such as `/docs/`
+    pub prefix: String,
+    /// local directory served under the prefix
+    pub dir: PathBuf,
+}
+
+/// What a request resolved to under a mount.
+#[derive(Debug, PartialEq, Eq)]
+enum Resolved {
+    File(PathBuf),
+    Dir(PathBuf),
+}
+
+#[derive(Debug, Clone)]
+struct Mount {
+    prefix: String,
+    /// canonical, so resolved paths can be checked against it
+    root: PathBuf,
+}
+
+impl Mount {
+    /// resolves the request path within the mount. Anything which
+    /// could step outside it, including symlinks, resolves to nothing.
+    fn resolve(&self, path: &str) -> Option<Resolved> {
+        let rest = path.strip_prefix(self.prefix.trim_end_matches('/'))?;
+        if !(rest.is_empty() || rest.starts_with('/')) {
+            return None;
+        }
+
+        let decoded = String::from_utf8(percent_decode(rest)).ok()?;
+        if decoded.contains(['\0', '\\']) {
+            return None;
+        }
+        let relative = Path::new(decoded.trim_start_matches('/'));
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return None;
+        }
+
+        let resolved = self.root.join(relative).canonicalize().ok()?;
+        if !resolved.starts_with(&self.root) {
+            return None;
+        }
+        match resolved.is_dir() {
+            true => Some(Resolved::Dir(resolved)),
+            false => Some(Resolved::File(resolved)),
+        }
+    }
+}
+
+/// Middleware serving the configured mounts. The first matching mount
+/// wins.
+#[derive(Debug, Clone)]
+pub struct Overlays {
+    mounts: Vec<Mount>,
+    persona: Persona,
+}
+
+impl Overlays {
+    pub fn new(configs: &[OverlayConfig], persona: Persona) -> Result<Self> {
+        let mounts = configs
+            .iter()
+            .map(|c| {
+                let root = c.dir.canonicalize().map_err(|e| {
+                    anyhow!("failed to open overlay dir {}: {}", c.dir.display(), e)
+                })?;
+                ensure!(
+                    root.is_dir(),
+                    "overlay {} is not a directory",
+                    c.dir.display()
+                );
+                Ok(Mount {
+                    prefix: c.prefix.clone(),
+                    root,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { mounts, persona })
+    }
+
+    fn resolve(&self, path: &str) -> Option<Resolved> {
+        self.mounts.iter().find_map(|m| m.resolve(path))
+    }
+}
+
+fn listing(dir: &Path) -> Result<Vec<Entry>> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let meta = e.metadata().ok()?;
+            let modified: DateTime<Utc> = meta.modified().ok()?.into();
+            Some(Entry::new(
+                &e.file_name().to_string_lossy(),
+                meta.is_dir(),
+                modified,
+                Some(meta.len() as usize).filter(|_| meta.is_file()),
+            ))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| (!a.is_dir, &a.name).cmp(&(!b.is_dir, &b.name)));
+    Ok(entries)
+}
+
+impl Middleware for Overlays {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            if req.method != Method::GET {
+                return next.run(req).await;
+            }
+
+            let path = req.url.path();
+            let (content_type, body) = match self.resolve(path) {
+                Some(Resolved::File(file)) => (
+                    content_type_for_path(&file.to_string_lossy()),
+                    tokio::fs::read(&file).await?,
+                ),
+                // directories are only listed at their canonical path
+                Some(Resolved::Dir(dir)) if path.ends_with('/') => (
+                    "text/html",
+                    render_listing(path, self.persona.language(Some(req)), listing(&dir)?)
+                        .into_bytes(),
+                ),
+                _ => return next.run(req).await,
+            };
+
+            Ok(ResponseBuilder::ok(Arc::new(next.into_conn()))
+                .add_header("Content-Type", content_type)
+                .body(body)
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("overlay")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::persona::Family;
+
+    #[test]
+    fn test_resolve() {
+        let root = std::env::temp_dir().join(format!("httpot-overlay-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs/reports")).unwrap();
+        fs::write(root.join("docs/reports/q3 plan.pdf"), b"%PDF").unwrap();
+        fs::write(root.join("secret.txt"), b"outside").unwrap();
+
+        let overlays = Overlays::new(
+            &[OverlayConfig {
+                prefix: "/files/".to_string(),
+                dir: root.join("docs"),
+            }],
+            Persona::from(Family::Apache),
+        )
+        .unwrap();
+        let docs = root.join("docs").canonicalize().unwrap();
+
+        assert_eq!(
+            Some(Resolved::File(docs.join("reports/q3 plan.pdf"))),
+            overlays.resolve("/files/reports/q3%20plan.pdf")
+        );
+        assert_eq!(
+            Some(Resolved::Dir(docs.clone())),
+            overlays.resolve("/files/")
+        );
+        assert_eq!(
+            Some(Resolved::Dir(docs.clone())),
+            overlays.resolve("/files")
+        );
+        assert_eq!(None, overlays.resolve("/filesystem/"));
+        assert_eq!(None, overlays.resolve("/files/missing.pdf"));
+        assert_eq!(None, overlays.resolve("/files/%2e%2e/secret.txt"));
+        assert_eq!(None, overlays.resolve("/files/..%2fsecret.txt"));
+        assert_eq!(
+            None,
+            overlays.resolve("/files/reports%5c..%5c..%5csecret.txt")
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("secret.txt"), root.join("docs/link")).unwrap();
+            assert_eq!(None, overlays.resolve("/files/link"));
+        }
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
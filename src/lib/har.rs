@@ -0,0 +1,392 @@
+//! HTTP Archive (HAR) export of exchanges, so traffic can be loaded into
+//! browser devtools and existing HAR tooling. Exchanges are appended to a
+//! file per source, or per window of time, which is rotated once it holds
+//! `max_entries`.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    display,
+    http::{headers::Headers, request::Request, response::Response},
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HarConfig {
+    /// directory archives are written to
+    pub dir: PathBuf,
+    pub split: Split,
+    /// length of each archive's window, when split by window
+    pub window_secs: u64,
+    /// entries an archive holds before it's rotated
+    pub max_entries: usize,
+}
+
+impl Default for HarConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("har"),
+            split: Split::default(),
+            window_secs: 60 * 60,
+            max_entries: 1000,
+        }
+    }
+}
+
+/// How exchanges are split between archives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    /// an archive per source address
+    #[default]
+    Session,
+    /// an archive per window of time
+    Window,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Log {
+    version: String,
+    creator: Creator,
+    entries: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Creator {
+    name: String,
+    version: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: String,
+    time: f64,
+    request: HarRequest,
+    response: HarResponse,
+    cache: serde_json::Value,
+    timings: Timings,
+    #[serde(rename = "_clientIPAddress")]
+    client_ip_address: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    http_version: String,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    query_string: Vec<NameValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_data: Option<PostData>,
+    headers_size: i64,
+    body_size: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostData {
+    mime_type: String,
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct HarResponse {
+    status: u16,
+    status_text: String,
+    http_version: String,
+    cookies: Vec<NameValue>,
+    headers: Vec<NameValue>,
+    content: Content,
+    #[serde(rename = "redirectURL")]
+    redirect_url: String,
+    headers_size: i64,
+    body_size: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Content {
+    size: usize,
+    mime_type: String,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+struct Timings {
+    send: f64,
+    wait: f64,
+    receive: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct NameValue {
+    name: String,
+    value: String,
+}
+
+fn name_values(headers: &Headers) -> Vec<NameValue> {
+    headers
+        .iter()
+        .flat_map(|(k, values)| {
+            values.iter().map(|v| NameValue {
+                name: k.clone(),
+                value: v.clone(),
+            })
+        })
+        .collect()
+}
+
+fn header(headers: &Headers, name: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .and_then(|(_, v)| v.first().cloned())
+}
+
+fn entry(req: &Request, resp: &Response, started: DateTime<Utc>, took: Duration) -> Entry {
+    let ms = took.as_secs_f64() * 1000.0;
+    let (text, encoding) = match std::str::from_utf8(resp.body()) {
+        Ok(text) => (text.to_string(), None),
+        Err(_) => (STANDARD.encode(resp.body()), Some("base64")),
+    };
+
+    Entry {
+        started_date_time: started.to_rfc3339_opts(SecondsFormat::Millis, true),
+        time: ms,
+        request: HarRequest {
+            method: req.method.to_string(),
+            url: req.url.to_string(),
+            http_version: req.version.clone(),
+            cookies: vec![],
+            // as sent, keeping repeats, unless the request was built without
+            headers: match req.syntax.raw_headers.is_empty() {
+                true => name_values(&req.headers),
+                false => req
+                    .syntax
+                    .raw_headers
+                    .iter()
+                    .map(|(name, value)| NameValue {
+                        name: name.clone(),
+                        value: value.clone(),
+                    })
+                    .collect(),
+            },
+            query_string: req
+                .url
+                .query_pairs()
+                .map(|(name, value)| NameValue {
+                    name: name.into_owned(),
+                    value: value.into_owned(),
+                })
+                .collect(),
+            post_data: (!req.body.is_empty()).then(|| PostData {
+                mime_type: header(&req.headers, "Content-Type").unwrap_or_default(),
+                text: String::from_utf8_lossy(&req.body).into_owned(),
+            }),
+            headers_size: match req.raw_head.is_empty() {
+                true => -1,
+                false => req.raw_head.len() as i64,
+            },
+            body_size: req.body.len(),
+        },
+        response: HarResponse {
+            status: resp.code(),
+            status_text: resp.reason().to_string(),
+            http_version: resp.version().unwrap_or("HTTP/1.1").to_string(),
+            cookies: vec![],
+            headers: name_values(resp.headers()),
+            content: Content {
+                size: resp.len(),
+                mime_type: header(resp.headers(), "Content-Type").unwrap_or_default(),
+                text,
+                encoding,
+            },
+            redirect_url: header(resp.headers(), "Location").unwrap_or_default(),
+            headers_size: -1,
+            body_size: resp.len(),
+        },
+        cache: serde_json::json!({}),
+        timings: Timings {
+            send: 0.0,
+            wait: ms,
+            receive: 0.0,
+        },
+        client_ip_address: req.requester_ip().to_string(),
+    }
+}
+
+/// Appends exchanges to archives.
+#[derive(Debug, Clone)]
+pub struct HarRecorder {
+    config: Arc<HarConfig>,
+    // archives are rewritten whole, so appends are serialized
+    lock: Arc<Mutex<()>>,
+}
+
+impl HarRecorder {
+    pub fn new(config: HarConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir).map_err(|e| {
+            anyhow!(
+                "failed to create HAR directory {}: {}",
+                config.dir.display(),
+                e
+            )
+        })?;
+
+        Ok(Self {
+            config: Arc::new(config),
+            lock: Default::default(),
+        })
+    }
+
+    /// the archive the exchange belongs in.
+    fn path(&self, req: &Request, started: DateTime<Utc>) -> PathBuf {
+        let name = match self.config.split {
+            // colons in IPv6 addresses aren't allowed everywhere
+            Split::Session => req.requester_ip().to_string().replace(':', "_"),
+            Split::Window => {
+                let window = self.config.window_secs.max(1) as i64;
+                let start = started.timestamp() - started.timestamp().rem_euclid(window);
+                Utc.timestamp_opt(start, 0)
+                    .single()
+                    .unwrap_or_default()
+                    .format("%Y%m%dT%H%M%SZ")
+                    .to_string()
+            }
+        };
+        self.config.dir.join(name + ".har")
+    }
+
+    /// appends the exchange, which started at started and took took.
+    pub fn record(
+        &self,
+        req: &Request,
+        resp: &Response,
+        started: DateTime<Utc>,
+        took: Duration,
+    ) -> Result<()> {
+        let path = self.path(req, started);
+        let entry = serde_json::to_value(entry(req, resp, started, took))?;
+
+        let _lock = self.lock.lock().unwrap();
+        let mut har = read(&path)?;
+        if har.log.entries.len() >= self.config.max_entries {
+            rotate(&path)?;
+            har.log.entries.clear();
+        }
+        har.log.entries.push(entry);
+
+        // written aside then moved, so readers never see half an archive
+        let tmp = path.with_extension("har.tmp");
+        fs::write(&tmp, display::to_json(&har)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> Result<Har> {
+    if !path.exists() {
+        return Ok(Har {
+            log: Log {
+                version: "1.2".to_string(),
+                creator: Creator {
+                    name: "httpot".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+                entries: vec![],
+            },
+        });
+    }
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// moves a full archive aside, named for when it was rotated.
+fn rotate(path: &Path) -> Result<()> {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let rotated = path.with_file_name(format!(
+        "{}-{}.har",
+        stem,
+        Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ));
+    fs::rename(path, rotated)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{request::Method, response::ResponseBuilder};
+
+    fn stub_request() -> Request {
+        let mut req = Request::stub(Method::POST, "/login?next=%2Fadmin", "user=root");
+        req.headers.add("Host", "example.com");
+        req.headers
+            .add("Content-Type", "application/x-www-form-urlencoded");
+        req.url = "http://example.com/login?next=%2Fadmin".parse().unwrap();
+        req.remote_ip = "203.0.113.7:62012".parse().unwrap();
+        req
+    }
+
+    #[tokio::test]
+    async fn test_record() {
+        let dir = std::env::temp_dir().join(format!("httpot-har-{}", std::process::id()));
+        let recorder = HarRecorder::new(HarConfig {
+            dir: dir.clone(),
+            max_entries: 2,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let conn = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let resp = ResponseBuilder::ok(Arc::new(conn))
+            .add_header("Content-Type", "text/html")
+            .body([0xff, 0xfe])
+            .build()
+            .unwrap();
+
+        let req = stub_request();
+        let now = Utc::now();
+        for _ in 0..3 {
+            recorder
+                .record(&req, &resp, now, Duration::from_millis(5))
+                .unwrap();
+        }
+
+        let har: serde_json::Value =
+            serde_json::from_slice(&fs::read(dir.join("203.0.113.7.har")).unwrap()).unwrap();
+        let entries = har["log"]["entries"].as_array().unwrap();
+        assert_eq!(1, entries.len(), "full archive wasn't rotated");
+        assert_eq!("POST", entries[0]["request"]["method"]);
+        assert_eq!("/admin", entries[0]["request"]["queryString"][0]["value"]);
+        assert_eq!("user=root", entries[0]["request"]["postData"]["text"]);
+        assert_eq!(-1, entries[0]["request"]["headersSize"]);
+        assert_eq!(200, entries[0]["response"]["status"]);
+        assert_eq!("base64", entries[0]["response"]["content"]["encoding"]);
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
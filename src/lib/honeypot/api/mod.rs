@@ -0,0 +1,516 @@
+//! A fake JSON REST API. Records are fabricated from the seed, paginated,
+//! and guarded by a bearer token flow whose tokens are honeytokens: each
+//! names the network it was issued to, so a token replayed from elsewhere
+//! reveals actors sharing loot.
+//!
+//! Under the configured prefix (`/api/v1` by default):
+//!  * `POST auth/login` accepts any credentials and issues a token
+//!  * `GET <resource>` lists records, paginated with `page` and `per_page`
+//!  * `GET <resource>/<id>` returns a single record
+//!  * `POST <resource>` "creates" a record from the submitted JSON
+//!
+//! It's documented at `/swagger.json`, `/openapi.json`, and `/swagger-ui/`.
+//! Other requests outside of the prefix are passed on.
+pub mod openapi;
+pub mod schema;
+
+use std::{net::IpAddr, sync::Arc};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    seed::Seeder,
+    util::path_match,
+};
+
+const DEFAULT_PER_PAGE: u64 = 20;
+const MAX_PER_PAGE: u64 = 100;
+const TOKEN_LIFETIME_SECS: i64 = 3600;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiConfig {
+    /// path the API is served under
+    pub prefix: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "/api/v1".to_string(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Body {
+    Json(Value),
+    Html(String),
+}
+
+/// A response from the API, before it's bound to a connection.
+#[derive(Debug)]
+struct ApiResponse {
+    status: StatusCode,
+    headers: Vec<(&'static str, String)>,
+    body: Body,
+}
+
+impl ApiResponse {
+    fn new(status: StatusCode, body: Value) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: Body::Json(body),
+        }
+    }
+
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            headers: vec![],
+            body: Body::Html(body),
+        }
+    }
+
+    fn error(status: StatusCode, message: &str) -> Self {
+        Self::new(
+            status,
+            json!({ "error": status.reason(), "message": message }),
+        )
+    }
+
+    fn header<S: ToString>(mut self, name: &'static str, value: S) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// The claims carried by an issued token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Claims {
+    sub: String,
+    /// network the token was issued to
+    net: IpAddr,
+}
+
+/// Middleware which serves the fake API.
+#[derive(Clone)]
+pub struct FakeApi {
+    prefix: String,
+    seeder: Seeder,
+}
+
+impl FakeApi {
+    pub fn new(config: &ApiConfig, seeder: Seeder) -> Self {
+        Self {
+            prefix: config.prefix.trim_end_matches('/').to_string(),
+            seeder,
+        }
+    }
+
+    /// the API's response to the request, if it's under the prefix.
+    fn respond(&self, req: &Request) -> Option<ApiResponse> {
+        let path = req.url.path();
+        if openapi::DOCUMENT_PATHS.contains(&path) {
+            return Some(ApiResponse::new(
+                StatusCode::Ok,
+                openapi::document(&self.prefix),
+            ));
+        }
+        if openapi::UI_PATHS.contains(&path) {
+            return Some(ApiResponse::html(openapi::ui()));
+        }
+
+        let rest = req.url.path().strip_prefix(&self.prefix)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        let rest = rest.trim_end_matches('/');
+        if path_match("/auth/login", rest).is_some() {
+            return Some(match req.method {
+                Method::POST => self.login(req),
+                _ => method_not_allowed("POST"),
+            });
+        }
+
+        let params = path_match("/:resource", rest).or_else(|| path_match("/:resource/:id", rest));
+        Some(match params {
+            Some(params) => {
+                let resource = match schema::find(&params["resource"]) {
+                    Some(r) => r,
+                    None => return Some(not_found()),
+                };
+                if let Err(resp) = self.authorize(req) {
+                    return Some(resp);
+                }
+
+                let seed = self.seeder.for_request(req);
+                match (&req.method, params.get("id")) {
+                    (Method::GET, None) => list(req, resource, &seed),
+                    (Method::POST, None) => create(req, resource, &seed),
+                    (Method::GET, Some(id)) => id
+                        .parse()
+                        .ok()
+                        .and_then(|id| resource.record(&seed, id))
+                        .map(|r| ApiResponse::new(StatusCode::Ok, r))
+                        .unwrap_or_else(not_found),
+                    (_, None) => method_not_allowed("GET, POST"),
+                    (_, Some(_)) => method_not_allowed("GET"),
+                }
+            }
+            None => not_found(),
+        })
+    }
+
+    fn login(&self, req: &Request) -> ApiResponse {
+        let fields: Map<String, Value> = match serde_json::from_slice(&req.body) {
+            Ok(Value::Object(fields)) => fields,
+            _ => url::form_urlencoded::parse(&req.body)
+                .map(|(k, v)| (k.into_owned(), Value::String(v.into_owned())))
+                .collect(),
+        };
+        let username = ["username", "email", "user", "login"]
+            .iter()
+            .find_map(|k| fields.get(*k).and_then(|v| v.as_str()))
+            .filter(|u| !u.is_empty());
+        let username = match username {
+            Some(u) => u,
+            None => {
+                return ApiResponse::error(StatusCode::BadRequest, "username and password required")
+            }
+        };
+
+        info!(
+            "{: <8} logged into the fake API as {:?}",
+            req.requester(),
+            username
+        );
+        let claims = Claims {
+            sub: username.to_string(),
+            net: self.seeder.network(req.requester_ip()),
+        };
+        ApiResponse::new(
+            StatusCode::Ok,
+            json!({
+                "access_token": self.issue(&claims),
+                "token_type": "Bearer",
+                "expires_in": TOKEN_LIFETIME_SECS,
+            }),
+        )
+    }
+
+    /// checks the request's bearer token, logging tokens replayed from a
+    /// network other than the one they were issued to.
+    fn authorize(&self, req: &Request) -> std::result::Result<Claims, ApiResponse> {
+        let token = req
+            .headers
+            .get("Authorization")
+            .into_iter()
+            .flatten()
+            .find_map(|v| v.strip_prefix("Bearer ").map(|t| t.trim()));
+        let claims = token.and_then(|t| self.verify(t));
+
+        match claims {
+            Some(claims) => {
+                let net = self.seeder.network(req.requester_ip());
+                if claims.net != net {
+                    info!(
+                        "{: <8} used the API token for {:?} issued to {}",
+                        req.requester(),
+                        claims.sub,
+                        claims.net
+                    );
+                }
+                Ok(claims)
+            }
+            None => {
+                if let Some(token) = token {
+                    debug!(
+                        "{: <8} sent an invalid API token {:?}",
+                        req.requester(),
+                        token
+                    );
+                }
+                Err(
+                    ApiResponse::error(StatusCode::Unauthorized, "missing or invalid access token")
+                        .header("WWW-Authenticate", "Bearer realm=\"api\""),
+                )
+            }
+        }
+    }
+
+    /// a JWT carrying the claims, signed with the global seed so it
+    /// verifies from any network.
+    fn issue(&self, claims: &Claims) -> String {
+        let header = URL_SAFE_NO_PAD.encode(json!({"alg": "HS256", "typ": "JWT"}).to_string());
+        let now = chrono::Utc::now().timestamp();
+        let payload = URL_SAFE_NO_PAD.encode(
+            json!({
+                "sub": claims.sub,
+                "net": claims.net.to_string(),
+                "iat": now,
+                "exp": now + TOKEN_LIFETIME_SECS,
+            })
+            .to_string(),
+        );
+        let signed = format!("{}.{}", header, payload);
+        let signature = self.sign(&signed);
+        format!("{}.{}", signed, signature)
+    }
+
+    /// the claims of a token this API issued, regardless of expiry.
+    fn verify(&self, token: &str) -> Option<Claims> {
+        let (signed, signature) = token.rsplit_once('.')?;
+        if self.sign(signed) != signature {
+            return None;
+        }
+
+        let (_, payload) = signed.split_once('.')?;
+        let payload: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        Some(Claims {
+            sub: payload["sub"].as_str()?.to_string(),
+            net: payload["net"].as_str()?.parse().ok()?,
+        })
+    }
+
+    fn sign(&self, data: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seeder.seed());
+        hasher.update(data);
+        URL_SAFE_NO_PAD.encode(hasher.finalize())
+    }
+}
+
+impl Middleware for FakeApi {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let resp = match self.respond(req) {
+                Some(resp) => resp,
+                None => return next.run(req).await,
+            };
+
+            let (content_type, body) = match resp.body {
+                Body::Json(v) => ("application/json", v.to_string()),
+                Body::Html(s) => ("text/html; charset=utf-8", s),
+            };
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            builder
+                .status_code(resp.status)
+                .add_header("Content-Type", content_type);
+            for (name, value) in resp.headers {
+                builder.add_header(name, value);
+            }
+            Ok(builder.body(body).build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("api")
+    }
+}
+
+fn list(req: &Request, resource: &schema::Resource, seed: &str) -> ApiResponse {
+    let param = |name| {
+        req.url
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .and_then(|(_, v)| v.parse::<u64>().ok())
+    };
+    let page = param("page").unwrap_or(1).max(1);
+    let per_page = param("per_page")
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+
+    let total = resource.total(seed);
+    let total_pages = total.div_ceil(per_page);
+    let first = (page - 1).saturating_mul(per_page) + 1;
+    let data = (first..first.saturating_add(per_page))
+        .map_while(|id| resource.record(seed, id))
+        .collect::<Vec<_>>();
+
+    let mut links = vec![];
+    let link = |page, rel| {
+        format!(
+            "<{}?page={}&per_page={}>; rel=\"{}\"",
+            req.url.path(),
+            page,
+            per_page,
+            rel
+        )
+    };
+    if page < total_pages {
+        links.push(link(page + 1, "next"));
+    }
+    if page > 1 {
+        links.push(link(page.min(total_pages + 1) - 1, "prev"));
+    }
+    links.push(link(1, "first"));
+    links.push(link(total_pages, "last"));
+
+    ApiResponse::new(
+        StatusCode::Ok,
+        json!({
+            "data": data,
+            "meta": {
+                "page": page,
+                "per_page": per_page,
+                "total": total,
+                "total_pages": total_pages,
+            },
+        }),
+    )
+    .header("Link", links.join(", "))
+}
+
+fn create(req: &Request, resource: &schema::Resource, seed: &str) -> ApiResponse {
+    let mut record = match serde_json::from_slice(&req.body) {
+        Ok(Value::Object(record)) => record,
+        _ => {
+            return ApiResponse::error(StatusCode::BadRequest, "request body must be a JSON object")
+        }
+    };
+
+    let id = resource.total(seed) + 1;
+    record.insert("id".to_string(), id.into());
+    record.insert(
+        "created_at".to_string(),
+        chrono::Utc::now().to_rfc3339().into(),
+    );
+
+    ApiResponse::new(StatusCode::Created, Value::Object(record)).header(
+        "Location",
+        format!("{}/{}", req.url.path().trim_end_matches('/'), id),
+    )
+}
+
+fn not_found() -> ApiResponse {
+    ApiResponse::error(StatusCode::NotFound, "resource not found")
+}
+
+fn method_not_allowed(allow: &str) -> ApiResponse {
+    ApiResponse::error(StatusCode::MethodNotAllowed, "method not allowed").header("Allow", allow)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stub_request(method: Method, path: &str, remote: &str) -> Request {
+        let mut req = Request::stub(method, path, "");
+        req.remote_ip = remote.parse().unwrap();
+        req
+    }
+
+    fn json(resp: &ApiResponse) -> &Value {
+        match &resp.body {
+            Body::Json(v) => v,
+            Body::Html(_) => panic!("expected a JSON response"),
+        }
+    }
+
+    fn api() -> FakeApi {
+        FakeApi::new(
+            &ApiConfig::default(),
+            Seeder::new("seed", Default::default()),
+        )
+    }
+
+    fn login(api: &FakeApi, remote: &str) -> String {
+        let mut req = stub_request(Method::POST, "/api/v1/auth/login", remote);
+        req.body = br#"{"username": "admin", "password": "hunter2"}"#.to_vec();
+        let resp = api.respond(&req).unwrap();
+        assert_eq!(StatusCode::Ok, resp.status);
+        json(&resp)["access_token"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_passes_other_paths() {
+        let api = api();
+        for path in ["/", "/api/v10/users", "/index.php"] {
+            assert!(api
+                .respond(&stub_request(Method::GET, path, "1.1.1.1:1"))
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_docs() {
+        let api = api();
+        let resp = api
+            .respond(&stub_request(Method::GET, "/openapi.json", "1.1.1.1:1"))
+            .unwrap();
+        assert_eq!("/api/v1", json(&resp)["servers"][0]["url"]);
+
+        let resp = api
+            .respond(&stub_request(Method::GET, "/swagger-ui/", "1.1.1.1:1"))
+            .unwrap();
+        assert!(matches!(resp.body, Body::Html(_)));
+    }
+
+    #[test]
+    fn test_token_flow() {
+        let api = api();
+        let mut req = stub_request(Method::GET, "/api/v1/users?page=2&per_page=5", "1.1.1.1:1");
+        assert_eq!(StatusCode::Unauthorized, api.respond(&req).unwrap().status);
+
+        req.headers.add("Authorization", "Bearer nope");
+        assert_eq!(StatusCode::Unauthorized, api.respond(&req).unwrap().status);
+
+        let token = login(&api, "1.1.1.1:1");
+        let claims = api.verify(&token).unwrap();
+        assert_eq!("admin", claims.sub);
+        assert_eq!("1.1.1.0".parse::<IpAddr>().unwrap(), claims.net);
+
+        req.headers.remove("Authorization");
+        req.headers
+            .add("Authorization", format!("Bearer {}", token));
+        let resp = api.respond(&req).unwrap();
+        assert_eq!(StatusCode::Ok, resp.status);
+        assert_eq!(5, json(&resp)["data"].as_array().unwrap().len());
+        assert_eq!(6, json(&resp)["data"][0]["id"]);
+        assert!(resp
+            .headers
+            .iter()
+            .any(|(k, v)| *k == "Link" && v.contains("rel=\"next\"")));
+
+        // valid from anywhere, it's only logged
+        req.remote_ip = "8.8.8.8:1".parse().unwrap();
+        assert_eq!(StatusCode::Ok, api.respond(&req).unwrap().status);
+    }
+
+    #[test]
+    fn test_records() {
+        let api = api();
+        let token = format!("Bearer {}", login(&api, "1.1.1.1:1"));
+        let get = |path| {
+            let mut req = stub_request(Method::GET, path, "1.1.1.1:1");
+            req.headers.add("Authorization", &token);
+            api.respond(&req).unwrap()
+        };
+
+        let resp = get("/api/v1/orders/3");
+        assert_eq!(StatusCode::Ok, resp.status);
+        assert_eq!(3, json(&resp)["id"]);
+        assert_eq!(json(&resp), json(&get("/api/v1/orders/3")));
+
+        assert_eq!(StatusCode::NotFound, get("/api/v1/orders/0").status);
+        assert_eq!(StatusCode::NotFound, get("/api/v1/widgets").status);
+
+        let mut req = stub_request(Method::POST, "/api/v1/users", "1.1.1.1:1");
+        req.headers.add("Authorization", &token);
+        req.body = br#"{"username": "eve"}"#.to_vec();
+        let resp = api.respond(&req).unwrap();
+        assert_eq!(StatusCode::Created, resp.status);
+        assert_eq!("eve", json(&resp)["username"]);
+    }
+}
@@ -0,0 +1,250 @@
+//! OpenAPI documents describing the fake API, generated from its schema.
+//! Exposed API docs are among the first things recon tools request, and
+//! they point straight at the endpoints worth probing.
+use serde_json::{json, Map, Value};
+
+use super::schema::{Resource, RESOURCES};
+
+/// paths the document is served at.
+pub const DOCUMENT_PATHS: &[&str] = &["/swagger.json", "/openapi.json"];
+/// paths the documentation browser is served at.
+pub const UI_PATHS: &[&str] = &["/swagger-ui/", "/swagger-ui/index.html"];
+
+/// the OpenAPI document for the API served under the prefix.
+pub fn document(prefix: &str) -> Value {
+    let mut paths = Map::new();
+    paths.insert(
+        "/auth/login".to_string(),
+        json!({
+            "post": {
+                "tags": ["auth"],
+                "summary": "Exchange credentials for an access token",
+                "security": [],
+                "requestBody": {
+                    "required": true,
+                    "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Credentials"}}},
+                },
+                "responses": {
+                    "200": {
+                        "description": "Authenticated",
+                        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Token"}}},
+                    },
+                    "400": {"$ref": "#/components/responses/Error"},
+                },
+            },
+        }),
+    );
+
+    let mut schemas = Map::new();
+    schemas.insert(
+        "Credentials".to_string(),
+        json!({
+            "type": "object",
+            "required": ["username", "password"],
+            "properties": {
+                "username": {"type": "string"},
+                "password": {"type": "string", "format": "password"},
+            },
+        }),
+    );
+    schemas.insert(
+        "Token".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "access_token": {"type": "string"},
+                "token_type": {"type": "string", "enum": ["Bearer"]},
+                "expires_in": {"type": "integer"},
+            },
+        }),
+    );
+    schemas.insert(
+        "Error".to_string(),
+        json!({
+            "type": "object",
+            "properties": {
+                "error": {"type": "string"},
+                "message": {"type": "string"},
+            },
+        }),
+    );
+
+    for resource in RESOURCES {
+        schemas.insert(resource.singular.to_string(), resource_schema(resource));
+        let (collection, item) = resource_paths(resource);
+        paths.insert(format!("/{}", resource.name), collection);
+        paths.insert(format!("/{}/{{id}}", resource.name), item);
+    }
+
+    let tags = ["auth"]
+        .iter()
+        .chain(RESOURCES.iter().map(|r| &r.name))
+        .map(|name| json!({ "name": name }))
+        .collect::<Vec<_>>();
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Internal API",
+            "description": "Internal API for account and order management. Do not expose publicly.",
+            "version": "1.4.2",
+        },
+        "servers": [{"url": prefix}],
+        "security": [{"bearerAuth": []}],
+        "tags": tags,
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "bearerAuth": {"type": "http", "scheme": "bearer", "bearerFormat": "JWT"},
+            },
+            "schemas": schemas,
+            "responses": {
+                "Error": {
+                    "description": "Error",
+                    "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Error"}}},
+                },
+            },
+        },
+    })
+}
+
+/// a documentation browser which loads the document.
+pub fn ui() -> String {
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Swagger UI</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+<div id="swagger-ui"></div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>
+window.onload = function() {{
+  window.ui = SwaggerUIBundle({{ url: "{}", dom_id: "#swagger-ui" }});
+}};
+</script>
+</body>
+</html>
+"##,
+        DOCUMENT_PATHS[1]
+    )
+}
+
+fn resource_schema(resource: &Resource) -> Value {
+    let properties = resource
+        .fields
+        .iter()
+        .map(|f| {
+            let (kind, format) = f.kind.json_type();
+            let mut property = json!({ "type": kind });
+            if let Some(format) = format {
+                property["format"] = format.into();
+            }
+            if let Some(values) = f.kind.values() {
+                let mut values = values.to_vec();
+                values.sort();
+                values.dedup();
+                property["enum"] = values.into();
+            }
+            (f.name.to_string(), property)
+        })
+        .collect::<Map<_, _>>();
+
+    json!({ "type": "object", "properties": properties })
+}
+
+fn resource_paths(resource: &Resource) -> (Value, Value) {
+    let schema = json!({ "$ref": format!("#/components/schemas/{}", resource.singular) });
+    let page_param = |name, description| {
+        json!({
+            "name": name,
+            "in": "query",
+            "description": description,
+            "schema": {"type": "integer", "minimum": 1},
+        })
+    };
+
+    let collection = json!({
+        "get": {
+            "tags": [resource.name],
+            "summary": format!("List {}", resource.name),
+            "parameters": [
+                page_param("page", "page number, starting at 1"),
+                page_param("per_page", "results per page, at most 100"),
+            ],
+            "responses": {
+                "200": {
+                    "description": format!("A page of {}", resource.name),
+                    "content": {"application/json": {"schema": {
+                        "type": "object",
+                        "properties": {
+                            "data": {"type": "array", "items": schema},
+                            "meta": {"type": "object"},
+                        },
+                    }}},
+                },
+                "401": {"$ref": "#/components/responses/Error"},
+            },
+        },
+        "post": {
+            "tags": [resource.name],
+            "summary": format!("Create a {}", resource.singular.to_lowercase()),
+            "requestBody": {
+                "required": true,
+                "content": {"application/json": {"schema": schema}},
+            },
+            "responses": {
+                "201": {
+                    "description": "Created",
+                    "content": {"application/json": {"schema": schema}},
+                },
+                "400": {"$ref": "#/components/responses/Error"},
+                "401": {"$ref": "#/components/responses/Error"},
+            },
+        },
+    });
+
+    let item = json!({
+        "get": {
+            "tags": [resource.name],
+            "summary": format!("Get a {} by id", resource.singular.to_lowercase()),
+            "parameters": [{
+                "name": "id",
+                "in": "path",
+                "required": true,
+                "schema": {"type": "integer", "format": "int64"},
+            }],
+            "responses": {
+                "200": {
+                    "description": resource.singular,
+                    "content": {"application/json": {"schema": schema}},
+                },
+                "401": {"$ref": "#/components/responses/Error"},
+                "404": {"$ref": "#/components/responses/Error"},
+            },
+        },
+    });
+
+    (collection, item)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_document_covers_schema() {
+        let doc = document("/api/v1");
+        assert_eq!("/api/v1", doc["servers"][0]["url"]);
+        for resource in RESOURCES {
+            assert!(doc["paths"][format!("/{}", resource.name)]["get"].is_object());
+            let properties = &doc["components"]["schemas"][resource.singular]["properties"];
+            for field in resource.fields {
+                assert!(properties[field.name].is_object(), "{}", field.name);
+            }
+        }
+    }
+}
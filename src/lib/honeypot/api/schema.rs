@@ -0,0 +1,224 @@
+//! The fake API's resources. Records are generated from the seed, so the
+//! same id always returns the same record for a source.
+use chrono::{TimeZone, Utc};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    prelude::*,
+};
+use serde_json::{Map, Value};
+
+use crate::util::hash_seed;
+
+const FIRST_NAMES: &[&str] = &[
+    "James",
+    "Mary",
+    "Robert",
+    "Patricia",
+    "John",
+    "Jennifer",
+    "Michael",
+    "Linda",
+    "David",
+    "Elizabeth",
+    "William",
+    "Barbara",
+    "Richard",
+    "Susan",
+    "Joseph",
+    "Jessica",
+    "Thomas",
+    "Sarah",
+    "Charles",
+    "Karen",
+    "Daniel",
+    "Nancy",
+    "Matthew",
+    "Lisa",
+];
+const LAST_NAMES: &[&str] = &[
+    "Smith",
+    "Johnson",
+    "Williams",
+    "Brown",
+    "Jones",
+    "Garcia",
+    "Miller",
+    "Davis",
+    "Rodriguez",
+    "Martinez",
+    "Hernandez",
+    "Lopez",
+    "Wilson",
+    "Anderson",
+    "Thomas",
+    "Taylor",
+    "Moore",
+    "Jackson",
+    "Martin",
+    "Lee",
+];
+const EMAIL_DOMAINS: &[&str] = &["example.com", "corp.example.com", "mail.example.net"];
+const ROLES: &[&str] = &[
+    "user", "user", "user", "user", "support", "manager", "admin",
+];
+const ORDER_STATUSES: &[&str] = &["pending", "paid", "shipped", "delivered", "refunded"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Id,
+    Username,
+    FullName,
+    Email,
+    Role,
+    Timestamp,
+    /// an id of the named resource
+    Reference(&'static str),
+    Sku,
+    /// cents
+    Amount,
+    Status,
+}
+
+impl FieldKind {
+    /// the JSON schema type and format of the field.
+    pub fn json_type(&self) -> (&'static str, Option<&'static str>) {
+        use FieldKind::*;
+        match self {
+            Id | Reference(_) | Amount => ("integer", Some("int64")),
+            Email => ("string", Some("email")),
+            Timestamp => ("string", Some("date-time")),
+            Username | FullName | Role | Sku | Status => ("string", None),
+        }
+    }
+
+    /// the values a field may take, when limited.
+    pub fn values(&self) -> Option<&'static [&'static str]> {
+        match self {
+            FieldKind::Role => Some(ROLES),
+            FieldKind::Status => Some(ORDER_STATUSES),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: FieldKind,
+}
+
+const fn field(name: &'static str, kind: FieldKind) -> Field {
+    Field { name, kind }
+}
+
+#[derive(Debug)]
+pub struct Resource {
+    /// path segment and plural name
+    pub name: &'static str,
+    pub singular: &'static str,
+    pub fields: &'static [Field],
+}
+
+pub const RESOURCES: &[Resource] = &[
+    Resource {
+        name: "users",
+        singular: "User",
+        fields: &[
+            field("id", FieldKind::Id),
+            field("username", FieldKind::Username),
+            field("name", FieldKind::FullName),
+            field("email", FieldKind::Email),
+            field("role", FieldKind::Role),
+            field("created_at", FieldKind::Timestamp),
+            field("last_login", FieldKind::Timestamp),
+        ],
+    },
+    Resource {
+        name: "orders",
+        singular: "Order",
+        fields: &[
+            field("id", FieldKind::Id),
+            field("user_id", FieldKind::Reference("users")),
+            field("sku", FieldKind::Sku),
+            field("amount", FieldKind::Amount),
+            field("status", FieldKind::Status),
+            field("created_at", FieldKind::Timestamp),
+        ],
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Resource> {
+    RESOURCES.iter().find(|r| r.name == name)
+}
+
+impl Resource {
+    /// how many records exist.
+    pub fn total(&self, seed: &str) -> u64 {
+        StdRng::seed_from_u64(hash_seed(seed, self.name)).gen_range(40..=500)
+    }
+
+    /// the record with the id, if it exists.
+    pub fn record(&self, seed: &str, id: u64) -> Option<Value> {
+        if id == 0 || id > self.total(seed) {
+            return None;
+        }
+
+        let mut rng = StdRng::seed_from_u64(hash_seed(seed, &format!("{}/{}", self.name, id)));
+        let first = *FIRST_NAMES.choose(&mut rng).unwrap();
+        let last = *LAST_NAMES.choose(&mut rng).unwrap();
+        let username = format!("{}.{}", first, last).to_lowercase();
+
+        let mut record = Map::new();
+        for f in self.fields {
+            let value: Value = match f.kind {
+                FieldKind::Id => id.into(),
+                FieldKind::Username => username.clone().into(),
+                FieldKind::FullName => format!("{} {}", first, last).into(),
+                FieldKind::Email => {
+                    format!("{}@{}", username, EMAIL_DOMAINS.choose(&mut rng).unwrap()).into()
+                }
+                FieldKind::Role | FieldKind::Status => {
+                    (*f.kind.values().unwrap().choose(&mut rng).unwrap()).into()
+                }
+                FieldKind::Timestamp => Utc
+                    .timestamp_opt(rng.gen_range(1_546_300_800..1_672_531_200), 0)
+                    .unwrap()
+                    .to_rfc3339()
+                    .into(),
+                FieldKind::Reference(other) => find(other)
+                    .map(|r| rng.gen_range(1..=r.total(seed)))
+                    .unwrap_or(1)
+                    .into(),
+                FieldKind::Sku => format!(
+                    "SKU-{}",
+                    Alphanumeric.sample_string(&mut rng, 8).to_uppercase()
+                )
+                .into(),
+                FieldKind::Amount => rng.gen_range(199..250_000u64).into(),
+            };
+            record.insert(f.name.to_string(), value);
+        }
+
+        Some(Value::Object(record))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_records_deterministic() {
+        let users = find("users").unwrap();
+        let total = users.total("seed");
+        assert!(total >= 40);
+
+        let user = users.record("seed", 1).unwrap();
+        assert_eq!(user, users.record("seed", 1).unwrap());
+        assert_eq!(1, user["id"]);
+        assert!(user["email"].as_str().unwrap().contains('@'));
+
+        assert!(users.record("seed", 0).is_none());
+        assert!(users.record("seed", total + 1).is_none());
+    }
+}
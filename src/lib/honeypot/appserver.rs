@@ -0,0 +1,364 @@
+//! Fake Java application server consoles: JBoss's JMX console and
+//! invokers, WebLogic's admin console and WS-AT service, and WebSphere's
+//! admin console. These are classic RCE targets, so their payloads,
+//! serialized objects and XMLDecoder documents, are detected by
+//! `honeypot::deserialize`. WebLogic's T3 protocol is answered as well,
+//! since scanners probe for it on the HTTP port.
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::escape_html,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppServerConfig {
+    /// JBoss AS version in banners
+    pub jboss: String,
+    /// WebLogic Server version in banners and T3 handshakes
+    pub weblogic: String,
+    /// WebSphere Application Server version in banners
+    pub websphere: String,
+}
+
+impl Default for AppServerConfig {
+    fn default() -> Self {
+        Self {
+            jboss: "4.2.3.GA".to_string(),
+            weblogic: "10.3.6.0".to_string(),
+            websphere: "8.5.5.9".to_string(),
+        }
+    }
+}
+
+/// A console page, before it's bound to a connection.
+#[derive(Debug)]
+struct Page {
+    status: StatusCode,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Page {
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "text/html;charset=UTF-8",
+            headers: vec![],
+            body: body.into_bytes(),
+        }
+    }
+
+    fn redirect(location: &str) -> Self {
+        Self {
+            status: StatusCode::Found,
+            content_type: "text/html;charset=UTF-8",
+            headers: vec![("Location", location.to_string())],
+            body: vec![],
+        }
+    }
+
+    fn header<S: ToString>(mut self, name: &'static str, value: S) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+}
+
+/// Middleware which serves the fake consoles.
+#[derive(Debug, Clone, Default)]
+pub struct AppServers {
+    config: AppServerConfig,
+}
+
+impl AppServers {
+    pub fn new(config: AppServerConfig) -> Self {
+        Self { config }
+    }
+
+    /// whether the start of a connection is a WebLogic T3 handshake, such
+    /// as `t3 12.2.1\nAS:255\nHL:19\n\n`.
+    pub fn is_t3_handshake(buf: &[u8]) -> bool {
+        buf.starts_with(b"t3 ") || buf.starts_with(b"t3s ")
+    }
+
+    /// WebLogic's answer to a T3 handshake, which scanners read the
+    /// version from.
+    pub fn t3_response(&self) -> String {
+        format!(
+            "HELO:{}.false\nAS:2048\nHL:19\nMS:10000000\n\n",
+            self.config.weblogic
+        )
+    }
+
+    fn respond(&self, req: &Request) -> Option<Page> {
+        let path = req.url.path();
+        let lower = path.to_lowercase();
+
+        if lower.starts_with("/jmx-console") || lower.starts_with("/invoker/") {
+            return self.jboss(req, &lower);
+        }
+        if lower.starts_with("/wls-wsat/") {
+            return Some(self.wls_wsat(req));
+        }
+        if lower == "/console" || lower.starts_with("/console/") {
+            return Some(self.weblogic_console(req, &lower));
+        }
+        if lower == "/ibm/console" || lower.starts_with("/ibm/console/") {
+            return Some(self.websphere_console(req, &lower));
+        }
+        None
+    }
+
+    fn jboss(&self, req: &Request, lower: &str) -> Option<Page> {
+        let powered_by = format!(
+            "Servlet 2.4; JBoss-{} (build: SVNTag=JBoss_{} date=200807181417)/JBossWeb-2.0",
+            self.config.jboss,
+            self.config.jboss.replace('.', "_")
+        );
+
+        let page = match lower {
+            "/jmx-console" => Page::redirect("/jmx-console/"),
+            "/jmx-console/" | "/jmx-console/index.jsp" => Page::html(self.jmx_agent_view()),
+            "/jmx-console/htmladaptor" => {
+                let action = req
+                    .url
+                    .query_pairs()
+                    .find(|(k, _)| k == "action")
+                    .map(|(_, v)| v.into_owned())
+                    .unwrap_or_default();
+                if action.starts_with("invokeOp") {
+                    info!(
+                        "{: <8} invoked a JMX operation: {:?}",
+                        req.requester(),
+                        req.url.query().unwrap_or_default()
+                    );
+                    Page::html(
+                        "<html><head><title>Operation Results</title></head><body>\
+                         <h1>JMX MBean Operation Result</h1><hr><pre>Operation completed successfully without a return value.</pre>\
+                         <a href=\"/jmx-console/\">Back to Agent View</a></body></html>"
+                            .to_string(),
+                    )
+                } else {
+                    Page::html(self.jmx_agent_view())
+                }
+            }
+            // serialized invocations are answered by the deserialization
+            // detector; anything else gets an empty serialized stream
+            "/invoker/jmxinvokerservlet" | "/invoker/ejbinvokerservlet" | "/invoker/readonly" => {
+                Page {
+                    status: StatusCode::Ok,
+                    content_type: "application/x-java-serialized-object; class=org.jboss.invocation.MarshalledValue",
+                    headers: vec![],
+                    body: vec![0xac, 0xed, 0x00, 0x05],
+                }
+            }
+            _ => return None,
+        };
+
+        Some(page.header("X-Powered-By", powered_by))
+    }
+
+    fn jmx_agent_view(&self) -> String {
+        let domains = [
+            (
+                "jboss",
+                vec![
+                    "database=localDB,service=Hypersonic",
+                    "name=PropertyEditorManager,type=Service",
+                    "readonly=true,service=invoker,target=Naming,type=http",
+                ],
+            ),
+            (
+                "jboss.deployment",
+                vec![
+                    "flavor=URL,type=DeploymentScanner",
+                    "type=DeploymentScanner",
+                ],
+            ),
+            (
+                "jboss.system",
+                vec!["service=MainDeployer", "type=Server", "type=ServerInfo"],
+            ),
+        ];
+        let mut body = format!(
+            "<html><head><title>JBoss JMX Management Console</title></head><body>\
+             <h1>JMX Agent View</h1><p>JBoss AS {}</p>\
+             <form action=\"HtmlAdaptor?action=displayMBeans\" method=\"post\">ObjectName Filter (e.g. \"jboss:*\", \"*:service=invoker,*\"): \
+             <input type=\"text\" name=\"filter\" size=\"40\" value=\"\"><input type=\"submit\" value=\"ApplyFilter\"></form>",
+            escape_html(&self.config.jboss)
+        );
+        for (domain, names) in domains {
+            body += &format!("<h2 class=\"DomainName\">{}</h2><ul>", domain);
+            for name in names {
+                body += &format!(
+                    "<li><a href=\"HtmlAdaptor?action=inspectMBean&amp;name={}%3A{}\">{}</a></li>",
+                    domain,
+                    name.replace('=', "%3D").replace(',', "%2C"),
+                    name
+                );
+            }
+            body += "</ul>";
+        }
+        body + "</body></html>"
+    }
+
+    fn weblogic_console(&self, req: &Request, lower: &str) -> Page {
+        if lower == "/console" || lower == "/console/" {
+            return Page::redirect("/console/login/LoginForm.jsp");
+        }
+        if lower.ends_with("/j_security_check") && req.method == Method::POST {
+            return Page::redirect("/console/login/LoginForm.jsp?error=true");
+        }
+
+        let error = req.url.query_pairs().any(|(k, _)| k == "error");
+        let version = escape_html(&self.config.weblogic);
+        Page::html(format!(
+            "<!DOCTYPE html><html><head><title>Oracle WebLogic Server Administration Console</title></head>\
+             <body><div id=\"login-header\"><h1>Oracle WebLogic Server Administration Console</h1></div>\
+             <div id=\"login-content\"><p>Log in to work with the WebLogic Server domain</p>{}\
+             <form method=\"post\" action=\"/console/j_security_check\">\
+             <label for=\"j_username\">Username:</label><input type=\"text\" id=\"j_username\" name=\"j_username\">\
+             <label for=\"j_password\">Password:</label><input type=\"password\" id=\"j_password\" name=\"j_password\">\
+             <input type=\"hidden\" name=\"j_character_encoding\" value=\"UTF-8\">\
+             <input type=\"submit\" value=\"Login\"></form></div>\
+             <div id=\"info\"><p id=\"footerVersion\">WebLogic Server Version: {}</p>\
+             <p id=\"copyright\">Copyright &copy; 1996,2011, Oracle and/or its affiliates. All rights reserved.</p></div></body></html>",
+            if error {
+                "<div class=\"loginFailed\">Authentication Denied</div>"
+            } else {
+                ""
+            },
+            version
+        ))
+    }
+
+    /// the WS-AT coordinator services, targeted with XMLDecoder payloads.
+    fn wls_wsat(&self, req: &Request) -> Page {
+        if req.method == Method::POST {
+            return Page {
+                status: StatusCode::InternalServerError,
+                content_type: "text/xml; charset=utf-8",
+                headers: vec![],
+                body: "<?xml version='1.0' encoding='UTF-8'?><S:Envelope xmlns:S=\"http://schemas.xmlsoap.org/soap/envelope/\">\
+                       <S:Body><S:Fault xmlns:ns4=\"http://www.w3.org/2003/05/soap-envelope\"><faultcode>S:Server</faultcode>\
+                       <faultstring>0</faultstring></S:Fault></S:Body></S:Envelope>"
+                    .as_bytes()
+                    .to_vec(),
+            };
+        }
+
+        let name = req
+            .url
+            .path()
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or("CoordinatorPortType");
+        Page::html(format!(
+            "<html><head><title>Web Services</title></head><body><table border=\"1\">\
+             <tr><th>Endpoint</th><th>Information</th></tr>\
+             <tr><td><table><tr><td>Service Name:</td><td>{{http://docs.oasis-open.org/ws-tx/wscoor/2006/06}}{}Service</td></tr>\
+             <tr><td>Port Name:</td><td>{{http://docs.oasis-open.org/ws-tx/wscoor/2006/06}}{}Port</td></tr></table></td>\
+             <td><table><tr><td>Address:</td><td>{}</td></tr><tr><td>WSDL:</td><td><a href=\"{}?wsdl\">{}?wsdl</a></td></tr>\
+             <tr><td>Implementation class:</td><td>weblogic.wsee.wstx.wsat.v10.endpoint.{}PortImpl</td></tr></table></td></tr>\
+             </table></body></html>",
+            escape_html(name),
+            escape_html(name),
+            escape_html(req.url.as_str()),
+            escape_html(req.url.path()),
+            escape_html(req.url.path()),
+            escape_html(name),
+        ))
+    }
+
+    fn websphere_console(&self, req: &Request, lower: &str) -> Page {
+        if !lower.ends_with("logon.jsp") && !lower.ends_with("j_security_check") {
+            return Page::redirect("/ibm/console/logon.jsp");
+        }
+        if req.method == Method::POST {
+            return Page::redirect("/ibm/console/logon.jsp?error=1");
+        }
+
+        Page::html(format!(
+            "<!DOCTYPE html><html><head><title>WebSphere Integrated Solutions Console</title></head><body>\
+             <h1>WebSphere Integrated Solutions Console</h1>\
+             <form method=\"post\" action=\"j_security_check\" name=\"LoginForm\">\
+             <label for=\"j_username\">User ID:</label><input type=\"text\" id=\"j_username\" name=\"j_username\">\
+             <label for=\"j_password\">Password:</label><input type=\"password\" id=\"j_password\" name=\"j_password\">\
+             <input type=\"submit\" name=\"action\" value=\"Log in\"></form>\
+             <p>WebSphere Application Server {}<br>Licensed Materials - Property of IBM (c) Copyright IBM Corp. 1997, 2016</p></body></html>",
+            escape_html(&self.config.websphere)
+        ))
+    }
+}
+
+impl Middleware for AppServers {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let page = match self.respond(req) {
+                Some(page) => page,
+                None => return next.run(req).await,
+            };
+
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            builder
+                .status_code(page.status)
+                .add_header("Content-Type", page.content_type);
+            for (name, value) in page.headers {
+                builder.add_header(name, value);
+            }
+            Ok(builder.body(page.body).build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("app_server")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stub_request(method: Method, path: &str) -> Request {
+        Request::stub(method, path, "")
+    }
+
+    #[test]
+    fn test_banners() {
+        let servers = AppServers::new(AppServerConfig {
+            weblogic: "12.2.1.3.0".to_string(),
+            ..Default::default()
+        });
+
+        let page = servers
+            .respond(&stub_request(Method::GET, "/console/login/LoginForm.jsp"))
+            .unwrap();
+        assert!(String::from_utf8(page.body)
+            .unwrap()
+            .contains("WebLogic Server Version: 12.2.1.3.0"));
+
+        let page = servers
+            .respond(&stub_request(Method::GET, "/jmx-console/"))
+            .unwrap();
+        assert!(page
+            .headers
+            .iter()
+            .any(|(k, v)| *k == "X-Powered-By" && v.contains("JBoss-4.2.3.GA")));
+
+        assert!(servers
+            .respond(&stub_request(Method::GET, "/consoles"))
+            .is_none());
+        assert!(servers.t3_response().starts_with("HELO:12.2.1.3.0.false\n"));
+        assert!(AppServers::is_t3_handshake(b"t3 12.2.1\nAS:255\nHL:19\n\n"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Decoys for the infrastructure botnets scan for: exposed XMRig miner
+//! APIs, C2 panel logins, bot check-in gates such as `count.php`, and the
+//! per-architecture payloads loaders fetch. Check-ins and component
+//! requests are logged to map botnet scanning, but nothing malicious is
+//! ever served: components are passed on to be answered as missing.
+use std::{sync::Arc, time::Instant};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::json;
+
+use crate::{
+    honeypot::php::Php,
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+};
+
+const XMRIG_VERSION: &str = "6.16.4";
+const GATES: &[&str] = &["count.php", "gate.php", "tasks.php", "bot.php"];
+const PANELS: &[&str] = &[
+    "/panel/",
+    "/panel/login.php",
+    "/cnc/",
+    "/c2/",
+    "/admin/panel.php",
+];
+
+lazy_static! {
+    static ref STARTED: Instant = Instant::now();
+    /// loader payloads, named by architecture, and known droppers
+    static ref COMPONENT: Regex = Regex::new(
+        r"(?i)(?:\.(?:x86|x86_64|i[3-6]86|mips|mpsl|mipsel|arm[4-7]?|m68k|ppc|sh4|spc|sparc|arc)$|/bins?(?:\.sh|/)|/mozi\.[am]$|/(?:ohshit|8UsA|wget|tftp|curl)\.sh$)"
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decoy {
+    /// XMRig's HTTP API
+    MinerApi,
+    /// a C2 panel login
+    Panel,
+    /// a bot check-in gate
+    Gate,
+    /// a payload fetched by a loader
+    Component,
+}
+
+fn decoy(path: &str) -> Option<Decoy> {
+    let file = path.rsplit('/').next().unwrap_or_default();
+    if matches!(
+        path,
+        "/1/summary" | "/2/summary" | "/1/threads" | "/1/config" | "/api.json"
+    ) {
+        Some(Decoy::MinerApi)
+    } else if PANELS.contains(&path) {
+        Some(Decoy::Panel)
+    } else if GATES.contains(&file) {
+        Some(Decoy::Gate)
+    } else if COMPONENT.is_match(path) {
+        Some(Decoy::Component)
+    } else {
+        None
+    }
+}
+
+/// Middleware which serves the decoys.
+#[derive(Debug, Clone, Default)]
+pub struct C2Decoys {
+    php: Php,
+}
+
+impl C2Decoys {
+    pub fn new(php: Php) -> Self {
+        Self { php }
+    }
+}
+
+impl Middleware for C2Decoys {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let decoy = match decoy(req.url.path()) {
+                Some(d) => d,
+                None => return next.run(req).await,
+            };
+
+            info!(
+                "{: <8} hit {:?} decoy {:?} with {:?}",
+                req.requester(),
+                decoy,
+                req.target,
+                String::from_utf8_lossy(&req.body)
+            );
+
+            let (status, content_type, body) = match decoy {
+                Decoy::Component => return next.run(req).await,
+                Decoy::MinerApi => miner_api(req),
+                Decoy::Panel => (
+                    StatusCode::Ok,
+                    "text/html; charset=UTF-8",
+                    PANEL_LOGIN.to_string(),
+                ),
+                // an empty task list
+                Decoy::Gate => (StatusCode::Ok, "text/html; charset=UTF-8", "0".to_string()),
+            };
+
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            builder
+                .status_code(status)
+                .add_header("Content-Type", content_type);
+            // panels and gates are PHP, and panels keep their logins in
+            // PHP's sessions
+            match decoy {
+                Decoy::Panel => {
+                    self.php.start_session(self.php.expose(&mut builder));
+                }
+                Decoy::Gate => {
+                    self.php.expose(&mut builder);
+                }
+                _ => (),
+            }
+            Ok(builder.body(body).build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("c2")
+    }
+}
+
+fn miner_api(req: &Request) -> (StatusCode, &'static str, String) {
+    let body = match req.url.path() {
+        "/1/config" => {
+            return (
+                StatusCode::Unauthorized,
+                "application/json",
+                json!({"status": 401, "error": "Unauthorized"}).to_string(),
+            )
+        }
+        "/1/threads" => json!({
+            "hugepages": true,
+            "cpu": {"threads": 4, "intensity": 1},
+        }),
+        _ => {
+            let uptime = STARTED.elapsed().as_secs() + 86_400 * 3;
+            json!({
+                "id": "92f3104f9a2ee78c",
+                "worker_id": "x86_64",
+                "uptime": uptime,
+                "restricted": true,
+                "resources": {"memory": {"total": 8_254_918_656u64}, "hardware_concurrency": 4},
+                "features": ["api", "asm", "http", "hwloc", "tls"],
+                "results": {
+                    "diff_current": 120_045,
+                    "shares_good": uptime / 97,
+                    "shares_total": uptime / 97 + 3,
+                    "avg_time": 97,
+                    "hashes_total": uptime * 1_204,
+                },
+                "algo": "rx/0",
+                "connection": {
+                    "pool": "pool.supportxmr.com:443",
+                    "ip": "104.243.33.118",
+                    "uptime": uptime,
+                    "ping": 74,
+                    "failures": 0,
+                    "tls": "TLSv1.3",
+                    "accepted": uptime / 97,
+                    "rejected": 0,
+                },
+                "version": XMRIG_VERSION,
+                "kind": "miner",
+                "ua": format!("XMRig/{} (Linux x86_64) libuv/1.38.0 gcc/9.3.0", XMRIG_VERSION),
+                "cpu": {"brand": "Intel(R) Xeon(R) CPU E5-2630 v4 @ 2.20GHz", "aes": true, "x64": true},
+                "hugepages": true,
+                "donate_level": 0,
+                "hashrate": {"total": [1204.3, 1198.7, 1201.9], "highest": 1254.6},
+            })
+        }
+    };
+
+    (StatusCode::Ok, "application/json", body.to_string())
+}
+
+const PANEL_LOGIN: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Login</title>
+<link rel="stylesheet" href="css/style.css">
+</head>
+<body>
+<div class="login">
+<h2>Panel</h2>
+<form method="post" action="login.php">
+<input type="text" name="username" placeholder="Username">
+<input type="password" name="password" placeholder="Password">
+<button type="submit">Sign in</button>
+</form>
+</div>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decoy() {
+        let cases = vec![
+            ("/1/summary", Some(Decoy::MinerApi)),
+            ("/panel/login.php", Some(Decoy::Panel)),
+            ("/count.php", Some(Decoy::Gate)),
+            ("/wp-content/gate.php", Some(Decoy::Gate)),
+            ("/bins/mirai.arm7", Some(Decoy::Component)),
+            ("/bins.sh", Some(Decoy::Component)),
+            ("/Mozi.m", Some(Decoy::Component)),
+            ("/index.php", None),
+            ("/static/app.js", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(expected, decoy(path), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Database tooling left on web servers: Adminer, phpPgAdmin, and the
+//! drop-in log viewer scripts found next to them. Each shows its login with
+//! the version footer scanners fingerprint, and refuses every login. Adminer
+//! is also abused to connect out to a rogue MySQL server, which then reads
+//! local files through `LOAD DATA LOCAL`, so the server it's asked to
+//! connect to is logged too.
+use std::sync::Arc;
+
+use crate::{
+    honeypot::php::Php,
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::escape_html,
+};
+
+/// before the SSRF fix of CVE-2021-21311
+const ADMINER_VERSION: &str = "4.7.8";
+const PHPPGADMIN_VERSION: &str = "5.1";
+
+const PHPPGADMIN_PREFIXES: &[&str] = &["/phppgadmin"];
+/// drop-in log viewer scripts
+const LOG_VIEWERS: &[&str] = &[
+    "/logviewer.php",
+    "/log_viewer.php",
+    "/log-viewer.php",
+    "/viewlog.php",
+    "/logs.php",
+    "/log-viewer",
+];
+/// the fields log viewers take the log to show from
+const LOG_FIELDS: &[&str] = &["file", "log", "f", "path"];
+
+/// the value of a query or urlencoded form field.
+fn param(req: &Request, name: &str) -> Option<String> {
+    req.url
+        .query_pairs()
+        .chain(url::form_urlencoded::parse(&req.body))
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// whether the path is an Adminer script, which is usually renamed after
+/// its version or driver, as `adminer-4.7.8-mysql.php`.
+fn is_adminer(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    path == "/adminer/" || (name.starts_with("adminer") && name.ends_with(".php"))
+}
+
+/// Middleware which serves the fake database tools.
+#[derive(Debug, Clone, Default)]
+pub struct DbAdmins {
+    php: Php,
+}
+
+impl DbAdmins {
+    pub fn new(php: Php) -> Self {
+        Self { php }
+    }
+
+    fn respond(&self, req: &Request) -> Option<String> {
+        let lower = req.url.path().to_lowercase();
+        if is_adminer(&lower) {
+            return Some(adminer(req));
+        }
+        let pgadmin = PHPPGADMIN_PREFIXES
+            .iter()
+            .any(|p| lower == *p || lower.starts_with(&format!("{}/", p)));
+        if pgadmin {
+            return Some(phppgadmin(req, &self.php));
+        }
+        if LOG_VIEWERS.contains(&lower.as_str()) {
+            return Some(log_viewer(req, &self.php));
+        }
+        None
+    }
+}
+
+impl Middleware for DbAdmins {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let body = match self.respond(req) {
+                Some(body) => body,
+                None => return next.run(req).await,
+            };
+            let mut builder = ResponseBuilder::ok(Arc::new(next.into_conn()));
+            self.php
+                .expose(&mut builder)
+                .add_header("Content-Type", "text/html; charset=utf-8");
+            // Adminer and phpPgAdmin name their own sessions, and start
+            // them on login
+            if LOG_VIEWERS.contains(&req.url.path().to_lowercase().as_str()) {
+                self.php.start_session(&mut builder);
+            }
+            Ok(builder.body(body).build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("dbadmin")
+    }
+}
+
+fn adminer(req: &Request) -> String {
+    let user = param(req, "auth[username]");
+    let error = match (&req.method, &user) {
+        (Method::POST, Some(user)) => {
+            let driver = param(req, "auth[driver]").unwrap_or_else(|| "server".to_string());
+            let server = param(req, "auth[server]").unwrap_or_default();
+            info!(
+                "{: <8} tried to log into Adminer's {} at {:?} as {:?} with {:?}",
+                req.requester(),
+                driver,
+                server,
+                user,
+                param(req, "auth[password]").unwrap_or_default()
+            );
+            // nothing is ever connected to, local or not
+            Some(match server.as_str() {
+                "" | "localhost" | "127.0.0.1" => format!(
+                    "Access denied for user '{}'@'localhost' (using password: YES)",
+                    escape_html(user)
+                ),
+                _ => "Connection refused".to_string(),
+            })
+        }
+        _ => None,
+    };
+    let error = error
+        .map(|e| format!("<div class='error'>{}</div>\n", e))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en" dir="ltr">
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<meta name="robots" content="noindex">
+<title>Login - Adminer</title>
+<link rel="stylesheet" type="text/css" href="?file=default.css&amp;version={version}">
+<script src='?file=functions.js&amp;version={version}' nonce=""></script>
+<link rel="shortcut icon" type="image/x-icon" href="?file=favicon.ico&amp;version={version}">
+<body class="ltr nojs">
+<div id="help" class="jush-sql jsonly hidden"></div>
+<div id="content">
+<h2>Login</h2>
+<div id='ajaxstatus' class='jsonly hidden'></div>
+{error}<form action='' method='post'>
+<div></div>
+<table cellspacing="0" class="layout">
+<tr><th>System<td><select name='auth[driver]'><option value="server" selected>MySQL<option value="sqlite">SQLite 3<option value="sqlite2">SQLite 2<option value="pgsql">PostgreSQL<option value="oracle">Oracle (beta)<option value="mssql">MS SQL (beta)<option value="mongo">MongoDB<option value="elastic">Elasticsearch (beta)</select>
+<tr><th>Server<td><input name="auth[server]" value="" title="hostname[:port]" placeholder="localhost" autocapitalize="off">
+<tr><th>Username<td><input name="auth[username]" id="username" value="{user}" autocomplete="username" autocapitalize="off">
+<tr><th>Password<td><input type="password" name="auth[password]" autocomplete="current-password">
+<tr><th>Database<td><input name="auth[db]" value="" autocapitalize="off">
+</table>
+<p><input type='submit' value='Login'>
+<label><input type='checkbox' name='auth[permanent]' value='1'>Permanent login</label>
+</form>
+</div>
+<div id="menu">
+<h1>
+<a href='https://www.adminer.org/' target="_blank" rel="noreferrer noopener" id='h1'>Adminer</a> <span class="version">{version}</span>
+<a href="https://www.adminer.org/#download" target="_blank" rel="noreferrer noopener" id="version"></a>
+</h1>
+</div>
+</body>
+</html>
+"#,
+        version = ADMINER_VERSION,
+        user = escape_html(&user.unwrap_or_default()),
+        error = error,
+    )
+}
+
+fn phppgadmin(req: &Request, php: &Php) -> String {
+    // the password field is suffixed with a hash of the server
+    let password = req
+        .url
+        .query_pairs()
+        .chain(url::form_urlencoded::parse(&req.body))
+        .find(|(k, _)| k.starts_with("loginPassword"))
+        .map(|(_, v)| v.into_owned());
+    let message = match (&req.method, param(req, "loginUsername")) {
+        (Method::POST, Some(user)) => {
+            info!(
+                "{: <8} tried to log into phpPgAdmin on {:?} as {:?} with {:?}",
+                req.requester(),
+                param(req, "loginServer").unwrap_or_default(),
+                user,
+                password.unwrap_or_default()
+            );
+            "<p class=\"message\">Login failed</p>\n"
+        }
+        _ => "",
+    };
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en" lang="en" dir="ltr">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8" />
+<link rel="stylesheet" href="themes/default/global.css" type="text/css" id="csstheme" />
+<link rel="shortcut icon" href="images/themes/default/Favicon.ico" type="image/vnd.microsoft.icon" />
+<title>phpPgAdmin</title>
+</head>
+<body class="browser">
+<div class="topbar"><table style="width: 100%"><tr><td><span class="appname">phpPgAdmin</span> <span class="version">{version}</span></td></tr></table></div>
+<h1>phpPgAdmin {version} (PHP {php})</h1>
+{message}<form id="login_form" action="redirect.php" method="post" name="login_form">
+<input type="hidden" name="subject" value="server" />
+<input type="hidden" name="server" value="localhost:5432:allow" />
+<input type="hidden" name="loginServer" value="localhost:5432:allow" />
+<table class="navbar" border="0" cellpadding="5" cellspacing="3">
+<tr><td>Username</td><td><input type="text" name="loginUsername" value="" size="24" /></td></tr>
+<tr><td>Password</td><td><input id="loginPassword" type="password" name="loginPassword_8d6a8f4f0e8b9d0f4c1f1d2a3b4c5d6e" size="24" /></td></tr>
+</table>
+<p><input type="submit" name="loginSubmit" value="Login" /></p>
+</form>
+</body>
+</html>
+"#,
+        version = PHPPGADMIN_VERSION,
+        php = php.version(),
+        message = message,
+    )
+}
+
+fn log_viewer(req: &Request, php: &Php) -> String {
+    let wanted = LOG_FIELDS.iter().find_map(|f| param(req, f));
+    if let Some(file) = &wanted {
+        info!(
+            "{: <8} asked the log viewer at {} for {:?}",
+            req.requester(),
+            req.url.path(),
+            file
+        );
+    }
+    // every log is behind the login
+    let error = match (&req.method, param(req, "username")) {
+        (Method::POST, Some(user)) => {
+            info!(
+                "{: <8} tried to log into the log viewer at {} as {:?} with {:?}",
+                req.requester(),
+                req.url.path(),
+                user,
+                param(req, "password").unwrap_or_default()
+            );
+            "<div class=\"alert alert-danger\">Invalid username or password.</div>\n"
+        }
+        _ => "",
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Log Viewer</title>
+<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/bootstrap@3.4.1/dist/css/bootstrap.min.css">
+</head>
+<body>
+<div class="container">
+<h2>Log Viewer</h2>
+{error}<form method="post" class="form-signin">
+<input type="hidden" name="file" value="{file}">
+<input type="text" name="username" class="form-control" placeholder="Username" required autofocus>
+<input type="password" name="password" class="form-control" placeholder="Password" required>
+<button class="btn btn-primary" type="submit">Sign in</button>
+</form>
+<footer><small>Log Viewer &middot; PHP {php}</small></footer>
+</div>
+</body>
+</html>
+"#,
+        file = escape_html(&wanted.unwrap_or_default()),
+        php = php.version(),
+        error = error,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_adminer() {
+        for path in ["/adminer.php", "/db/adminer-4.7.8-mysql.php", "/adminer/"] {
+            assert!(is_adminer(path), "{}", path);
+        }
+        assert!(!is_adminer("/admin.php"));
+
+        let body = DbAdmins::default()
+            .respond(&Request::stub(Method::GET, "/adminer.php", ""))
+            .unwrap();
+        assert!(body.contains(&format!(
+            "<span class=\"version\">{}</span>",
+            ADMINER_VERSION
+        )));
+
+        let req = Request::stub(
+            Method::POST,
+            "/adminer.php",
+            "auth%5Bdriver%5D=server&auth%5Bserver%5D=203.0.113.7%3A3306&auth%5Busername%5D=root&auth%5Bpassword%5D=x",
+        );
+        let body = DbAdmins::default().respond(&req).unwrap();
+        assert!(body.contains("Connection refused"));
+        assert!(body.contains("value=\"root\""));
+    }
+
+    #[test]
+    fn test_phppgadmin_and_log_viewers() {
+        let req = Request::stub(
+            Method::POST,
+            "/phpPgAdmin/redirect.php",
+            "subject=server&loginServer=localhost&loginUsername=postgres&loginPassword_abc=postgres",
+        );
+        let body = DbAdmins::default().respond(&req).unwrap();
+        assert!(body.contains("Login failed"));
+        assert!(body.contains(&format!("phpPgAdmin {}", PHPPGADMIN_VERSION)));
+
+        let body = DbAdmins::default()
+            .respond(&Request::stub(
+                Method::GET,
+                "/logviewer.php?file=../../../etc/passwd",
+                "",
+            ))
+            .unwrap();
+        assert!(body.contains("value=\"../../../etc/passwd\""));
+
+        assert!(DbAdmins::default()
+            .respond(&Request::stub(Method::GET, "/logs/app.log", ""))
+            .is_none());
+    }
+}
@@ -0,0 +1,362 @@
+//! Detects serialized object payloads, as thrown by ysoserial and
+//! ysoserial.net, and answers them with the stack trace a vulnerable
+//! server would print. A trace showing the object was read, but the
+//! gadget chain didn't fire, invites the next gadget.
+//!
+//! Payloads are looked for raw and base64 encoded in the body, query and
+//! form fields, cookies, and other headers. Java XMLDecoder documents, as
+//! sent to WebLogic, are detected too but left for the endpoint to answer.
+use std::sync::Arc;
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE},
+    Engine,
+};
+
+use crate::{
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::escape_html,
+};
+
+/// the stream magic of java.io.ObjectOutputStream
+const JAVA_MAGIC: &[u8] = &[0xac, 0xed, 0x00, 0x05];
+/// the header of a .NET BinaryFormatter stream
+const BINARY_FORMATTER_MAGIC: &[u8] = &[0x00, 0x01, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff];
+/// the marker of an ObjectStateFormatter stream, used by ViewState
+const OBJECT_STATE_MAGIC: &[u8] = &[0xff, 0x01];
+
+/// class names which identify gadget chains, most specific first.
+const JAVA_GADGETS: &[(&str, &str)] = &[
+    (
+        "org.apache.commons.collections4.functors",
+        "CommonsCollections4",
+    ),
+    (
+        "org.apache.commons.collections.functors",
+        "CommonsCollections",
+    ),
+    ("org.apache.commons.beanutils", "CommonsBeanutils"),
+    ("org.codehaus.groovy.runtime", "Groovy"),
+    ("org.springframework", "Spring"),
+    ("org.hibernate", "Hibernate"),
+    ("com.mchange.v2.c3p0", "C3P0"),
+    ("sun.rmi.server.UnicastRef", "JRMPClient"),
+    ("com.sun.rowset.JdbcRowSetImpl", "JdbcRowSet"),
+    ("org.python.core", "Jython"),
+    ("bsh.", "BeanShell"),
+    (
+        "com.sun.org.apache.xalan.internal.xsltc.trax.TemplatesImpl",
+        "Jdk7u21",
+    ),
+    ("java.net.URL", "URLDNS"),
+];
+const DOTNET_GADGETS: &[(&str, &str)] = &[
+    (
+        "System.Windows.Data.ObjectDataProvider",
+        "ObjectDataProvider",
+    ),
+    ("System.DelegateSerializationHolder", "TypeConfuseDelegate"),
+    ("TextFormattingRunProperties", "TextFormattingRunProperties"),
+    (
+        "System.Security.Principal.WindowsIdentity",
+        "WindowsIdentity",
+    ),
+    ("ActivitySurrogateSelector", "ActivitySurrogateSelector"),
+    ("System.Security.Claims.ClaimsIdentity", "ClaimsIdentity"),
+    ("System.Data.DataSet", "DataSet"),
+    ("System.Web.Security.RolePrincipal", "RolePrincipal"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Java,
+    DotNet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// a binary serialization stream
+    Binary,
+    /// an XMLDecoder document
+    Xml,
+}
+
+/// A serialized object found in a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub platform: Platform,
+    pub format: Format,
+    /// the gadget chain family, when recognized
+    pub gadget: Option<&'static str>,
+    /// where the payload was found, such as `body` or `cookie JSESSIONID`
+    pub location: String,
+    /// whether the payload was sent as a `__VIEWSTATE` field
+    pub viewstate: bool,
+}
+
+/// the first serialized object payload in the request, if any.
+pub fn detect(req: &Request) -> Option<Payload> {
+    let mut candidates = vec![("body".to_string(), req.body.clone())];
+
+    let form = url::form_urlencoded::parse(&req.body).filter(|(_, v)| !v.is_empty());
+    for (k, v) in req.url.query_pairs().chain(form) {
+        candidates.push((format!("field {}", k), v.as_bytes().to_vec()));
+    }
+
+    for (name, values) in req.headers.iter() {
+        for value in values {
+            if name.eq_ignore_ascii_case("Cookie") {
+                for (k, v) in value.split(';').filter_map(|c| c.trim().split_once('=')) {
+                    candidates.push((format!("cookie {}", k), v.as_bytes().to_vec()));
+                }
+            } else {
+                candidates.push((format!("header {}", name), value.as_bytes().to_vec()));
+            }
+        }
+    }
+
+    candidates.into_iter().find_map(|(location, data)| {
+        let viewstate = location == "field __VIEWSTATE";
+        let mut payload = inspect(&data)?;
+        payload.location = location;
+        payload.viewstate = viewstate;
+        Some(payload)
+    })
+}
+
+/// checks data raw and base64 decoded.
+fn inspect(data: &[u8]) -> Option<Payload> {
+    if let Some(payload) = inspect_raw(data) {
+        return Some(payload);
+    }
+
+    let trimmed = data
+        .iter()
+        .filter(|b| !b.is_ascii_whitespace())
+        .cloned()
+        .collect::<Vec<_>>();
+    let decoded = STANDARD
+        .decode(&trimmed)
+        .or_else(|_| URL_SAFE.decode(&trimmed))
+        .ok()?;
+    inspect_raw(&decoded)
+}
+
+fn inspect_raw(data: &[u8]) -> Option<Payload> {
+    if contains(data, b"java.beans.XMLDecoder")
+        || (contains(data, b"<java") && contains(data, b"class=\"java.lang.ProcessBuilder\""))
+    {
+        return Some(Payload {
+            platform: Platform::Java,
+            format: Format::Xml,
+            gadget: Some("XMLDecoder"),
+            location: String::new(),
+            viewstate: false,
+        });
+    }
+
+    let platform = if contains(data, JAVA_MAGIC) {
+        Platform::Java
+    } else if data.starts_with(OBJECT_STATE_MAGIC) || contains(data, BINARY_FORMATTER_MAGIC) {
+        Platform::DotNet
+    } else {
+        return None;
+    };
+
+    let gadgets = match platform {
+        Platform::Java => JAVA_GADGETS,
+        Platform::DotNet => DOTNET_GADGETS,
+    };
+    let gadget = gadgets
+        .iter()
+        .find(|(marker, _)| contains(data, marker.as_bytes()))
+        .map(|(_, family)| *family);
+
+    Some(Payload {
+        platform,
+        format: Format::Binary,
+        gadget,
+        location: String::new(),
+        viewstate: false,
+    })
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Middleware which answers serialized object payloads with a stack trace.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Deserialization;
+
+impl Middleware for Deserialization {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let payload = match detect(req) {
+                Some(p) => p,
+                None => return next.run(req).await,
+            };
+
+            info!(
+                "{: <8} sent a serialized {:?} object in {} with gadget {}",
+                req.requester(),
+                payload.platform,
+                payload.location,
+                payload.gadget.unwrap_or("unknown"),
+            );
+            if payload.format == Format::Xml {
+                return next.run(req).await;
+            }
+
+            let body = match payload.platform {
+                Platform::Java => java_error_page(&payload),
+                Platform::DotNet => dotnet_error_page(req, &payload),
+            };
+            Ok(ResponseBuilder::default(Arc::new(next.into_conn()))
+                .status_code(StatusCode::InternalServerError)
+                .add_header("Content-Type", "text/html;charset=utf-8")
+                .body(body)
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("deserialization")
+    }
+}
+
+/// a Tomcat error page for a ClassCastException after readObject.
+fn java_error_page(payload: &Payload) -> String {
+    let cast = match payload.gadget {
+        Some("URLDNS") => "java.util.HashMap",
+        Some("CommonsCollections") | Some("CommonsCollections4") => {
+            "javax.management.BadAttributeValueExpException"
+        }
+        Some("CommonsBeanutils") => "java.util.PriorityQueue",
+        _ => "java.lang.Object",
+    };
+    let trace = format!(
+        "java.lang.ClassCastException: {} cannot be cast to com.corp.session.UserSession\n\
+         \tcom.corp.session.SessionFilter.restore(SessionFilter.java:118)\n\
+         \tcom.corp.session.SessionFilter.doFilter(SessionFilter.java:64)\n\
+         \torg.apache.catalina.core.ApplicationFilterChain.internalDoFilter(ApplicationFilterChain.java:193)\n\
+         \torg.apache.catalina.core.ApplicationFilterChain.doFilter(ApplicationFilterChain.java:166)\n\
+         \torg.apache.catalina.core.StandardWrapperValve.invoke(StandardWrapperValve.java:199)\n\
+         \torg.apache.catalina.core.StandardContextValve.invoke(StandardContextValve.java:96)\n\
+         \torg.apache.catalina.authenticator.AuthenticatorBase.invoke(AuthenticatorBase.java:490)\n\
+         \torg.apache.catalina.core.StandardHostValve.invoke(StandardHostValve.java:139)\n\
+         \torg.apache.catalina.valves.ErrorReportValve.invoke(ErrorReportValve.java:92)\n\
+         \torg.apache.catalina.core.StandardEngineValve.invoke(StandardEngineValve.java:74)\n\
+         \torg.apache.catalina.connector.CoyoteAdapter.service(CoyoteAdapter.java:343)\n\
+         \torg.apache.coyote.http11.Http11Processor.service(Http11Processor.java:408)",
+        cast
+    );
+
+    format!(
+        "<!doctype html><html lang=\"en\"><head><title>HTTP Status 500 \u{2013} Internal Server Error</title></head>\
+         <body><h1>HTTP Status 500 \u{2013} Internal Server Error</h1><hr class=\"line\" />\
+         <p><b>Type</b> Exception Report</p>\
+         <p><b>Message</b> {cast} cannot be cast to com.corp.session.UserSession</p>\
+         <p><b>Description</b> The server encountered an unexpected condition that prevented it from fulfilling the request.</p>\
+         <p><b>Exception</b></p><pre>{trace}</pre>\
+         <p><b>Note</b> The full stack trace of the root cause is available in the server logs.</p>\
+         <hr class=\"line\" /><h3>Apache Tomcat/8.5.32</h3></body></html>"
+    )
+}
+
+/// an ASP.NET error page for an InvalidCastException after deserializing.
+fn dotnet_error_page(req: &Request, payload: &Payload) -> String {
+    let formatter = if payload.viewstate {
+        "System.Web.UI.ObjectStateFormatter"
+    } else {
+        "System.Runtime.Serialization.Formatters.Binary.BinaryFormatter"
+    };
+    let path = escape_html(req.url.path());
+
+    format!(
+        "<!DOCTYPE html><html><head><title>Specified cast is not valid.</title></head><body bgcolor=\"white\">\
+         <span><H1>Server Error in '/' Application.<hr width=100% size=1 color=silver></H1>\
+         <h2><i>Specified cast is not valid.</i></h2></span>\
+         <b>Description: </b>An unhandled exception occurred during the execution of the current web request. \
+         Please review the stack trace for more information about the error and where it originated in the code.<br><br>\
+         <b>Exception Details: </b>System.InvalidCastException: Specified cast is not valid.<br><br>\
+         <b>Source File: </b>{path}<br><br>\
+         <b>Stack Trace:</b><br><br><table width=100% bgcolor=\"#ffffcc\"><tr><td><code><pre>\n\
+[InvalidCastException: Specified cast is not valid.]\n\
+   System.Web.UI.ObjectStateFormatter.DeserializeValue(SerializerBinaryReader reader) +2251\n\
+   {formatter}.Deserialize(Stream inputStream) +141\n\
+   System.Web.UI.Util.DeserializeWithAssert(IStateFormatter2 formatter, String serializedState) +39\n\
+   System.Web.UI.HiddenFieldPageStatePersister.Load() +190\n\
+   System.Web.UI.Page.LoadPageStateFromPersistenceMedium() +106\n\
+   System.Web.UI.Page.ProcessRequestMain(Boolean includeStagesBeforeAsyncPoint, Boolean includeStagesAfterAsyncPoint) +4214\n\
+</pre></code></td></tr></table><br><hr width=100% size=1 color=silver>\
+         <b>Version Information:</b>&nbsp;Microsoft .NET Framework Version:4.0.30319; ASP.NET Version:4.7.3930.0</body></html>"
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(path: &str, body: Vec<u8>) -> Request {
+        Request::stub(Method::POST, path, body)
+    }
+
+    fn java_object(class: &str) -> Vec<u8> {
+        let mut obj = JAVA_MAGIC.to_vec();
+        obj.extend_from_slice(&[0x73, 0x72, 0x00, class.len() as u8]);
+        obj.extend_from_slice(class.as_bytes());
+        obj
+    }
+
+    #[test]
+    fn test_detect() {
+        let raw = java_object("org.apache.commons.collections.functors.InvokerTransformer");
+        let payload = detect(&stub_request("/invoker/readonly", raw.clone())).unwrap();
+        assert_eq!(Platform::Java, payload.platform);
+        assert_eq!(Some("CommonsCollections"), payload.gadget);
+        assert_eq!("body", payload.location);
+
+        let mut req = stub_request("/", vec![]);
+        req.headers.add(
+            "Cookie",
+            format!(
+                "lang=en; rememberMe={}",
+                STANDARD.encode(java_object("java.net.URL"))
+            ),
+        );
+        let payload = detect(&req).unwrap();
+        assert_eq!(Some("URLDNS"), payload.gadget);
+        assert_eq!("cookie rememberMe", payload.location);
+
+        let mut viewstate = OBJECT_STATE_MAGIC.to_vec();
+        viewstate.extend_from_slice(b"System.Windows.Data.ObjectDataProvider");
+        let body = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("__VIEWSTATE", &STANDARD.encode(viewstate))
+            .finish();
+        let payload = detect(&stub_request("/default.aspx", body.into_bytes())).unwrap();
+        assert_eq!(Platform::DotNet, payload.platform);
+        assert_eq!(Some("ObjectDataProvider"), payload.gadget);
+        assert!(payload.viewstate);
+
+        let body = br#"<soapenv:Envelope><soapenv:Header><work:WorkContext><java version="1.4.0" class="java.beans.XMLDecoder"><void class="java.lang.ProcessBuilder"></void></java></work:WorkContext></soapenv:Header></soapenv:Envelope>"#;
+        let payload = detect(&stub_request(
+            "/wls-wsat/CoordinatorPortType",
+            body.to_vec(),
+        ))
+        .unwrap();
+        assert_eq!(Format::Xml, payload.format);
+        assert_eq!(Some("XMLDecoder"), payload.gadget);
+
+        assert_eq!(
+            None,
+            detect(&stub_request("/", b"user=admin&pass=rO0".to_vec()))
+        );
+    }
+}
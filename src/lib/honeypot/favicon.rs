@@ -0,0 +1,215 @@
+//! Favicons, whose hashes are how Shodan and honeypot hunters fingerprint
+//! panels and devices. Apache, nginx, and IIS ship without one, so a
+//! persona's favicon is its 404 unless the operator provides one. Panels
+//! and devices link theirs from their login pages, so they always serve
+//! one: the operator's, or a built-in stand-in. Stand-ins only avoid a
+//! broken link, as their hashes aren't the real products'; operators who
+//! expect hunters should provide icons captured from the real thing.
+use std::{fs, path::PathBuf, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{
+    honeypot::{iot::Device, panel::Panel},
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    persona::Persona,
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaviconConfig {
+    /// icons named for whoever serves them, such as `apache.ico`,
+    /// `cpanel.ico`, or `tplink.ico`
+    pub dir: PathBuf,
+}
+
+impl Default for FaviconConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("favicons"),
+        }
+    }
+}
+
+/// Answers requests for a listener's favicon.
+#[derive(Debug, Clone)]
+pub struct Favicon {
+    paths: &'static [&'static str],
+    icon: Option<Arc<Vec<u8>>>,
+    // panels and devices answer before the persona can dress responses,
+    // and some devices send no Server at all
+    server: Option<String>,
+}
+
+impl Favicon {
+    pub fn persona(persona: Persona, config: Option<&FaviconConfig>) -> Result<Self> {
+        Ok(Self {
+            paths: &["/favicon.ico"],
+            icon: load(config, &name(persona.family))?,
+            server: Some(persona.server()),
+        })
+    }
+
+    pub fn panel(panel: Panel, config: Option<&FaviconConfig>) -> Result<Self> {
+        let (paths, color): (&'static [&'static str], _) = match panel {
+            Panel::Cpanel => (
+                &[
+                    "/favicon.ico",
+                    "/cPanel_magic_revision_1579116395/unprotected/cpanel/favicon.ico",
+                ],
+                [0xff, 0x6c, 0x2c],
+            ),
+            Panel::Webmin => (
+                &["/favicon.ico", "/images/favicon-webmin.ico"],
+                [0x3b, 0x6e, 0xa5],
+            ),
+            Panel::Plesk => (&["/favicon.ico"], [0x53, 0xbc, 0xe6]),
+        };
+        Ok(Self {
+            paths,
+            icon: Some(load(config, &name(panel))?.unwrap_or_else(|| Arc::new(ico(color)))),
+            server: Some(panel.server().to_string()),
+        })
+    }
+
+    pub fn device(device: Device, config: Option<&FaviconConfig>) -> Result<Self> {
+        let color = match device {
+            Device::Dvr => [0xc8, 0x10, 0x2e],
+            Device::Camera => [0x5a, 0x5a, 0x5a],
+            Device::Tplink => [0x4a, 0xcb, 0xd6],
+            Device::Mikrotik => [0x29, 0x3b, 0x4f],
+            Device::Boa => [0x00, 0x66, 0xcc],
+            Device::Jetdirect => [0x00, 0x96, 0xd6],
+            Device::Cups => [0x1d, 0x4e, 0x89],
+            Device::Synology => [0x1a, 0x6e, 0xb4],
+            Device::Qnap => [0x00, 0x5b, 0xac],
+        };
+        Ok(Self {
+            paths: &["/favicon.ico"],
+            icon: Some(load(config, &name(device))?.unwrap_or_else(|| Arc::new(ico(color)))),
+            server: device.server().map(str::to_string),
+        })
+    }
+}
+
+/// the name config files use for the persona, panel, or device.
+fn name<T: std::fmt::Debug>(owner: T) -> String {
+    format!("{:?}", owner).to_lowercase()
+}
+
+fn load(config: Option<&FaviconConfig>, name: &str) -> Result<Option<Arc<Vec<u8>>>> {
+    let path = match config {
+        Some(config) => config.dir.join(format!("{}.ico", name)),
+        None => return Ok(None),
+    };
+    match fs::read(&path) {
+        Ok(icon) => Ok(Some(Arc::new(icon))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow!("failed to read favicon {}: {}", path.display(), e)),
+    }
+}
+
+/// a 16x16 icon of one color, as a 32-bit BMP within an ICO.
+fn ico([r, g, b]: [u8; 3]) -> Vec<u8> {
+    const SIZE: u32 = 16;
+    let pixels = SIZE * SIZE * 4;
+    // one bit a pixel, rows padded to 4 bytes, all opaque
+    let mask = SIZE * 4;
+    let image = 40 + pixels + mask;
+
+    let mut ico = vec![];
+    // ICONDIR: reserved, type icon, one image
+    ico.extend([0, 0, 1, 0, 1, 0]);
+    // ICONDIRENTRY: size, no palette, one plane, 32 bits, then where it is
+    ico.extend([SIZE as u8, SIZE as u8, 0, 0, 1, 0, 32, 0]);
+    ico.extend(image.to_le_bytes());
+    ico.extend(22u32.to_le_bytes());
+    // BITMAPINFOHEADER, whose height counts the mask too
+    ico.extend(40u32.to_le_bytes());
+    ico.extend(SIZE.to_le_bytes());
+    ico.extend((SIZE * 2).to_le_bytes());
+    ico.extend([1, 0, 32, 0]);
+    ico.extend(0u32.to_le_bytes());
+    ico.extend((pixels + mask).to_le_bytes());
+    ico.extend([0; 16]);
+    for _ in 0..SIZE * SIZE {
+        ico.extend([b, g, r, 0xff]);
+    }
+    ico.resize(ico.len() + mask as usize, 0);
+    ico
+}
+
+impl Middleware for Favicon {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let icon = match &self.icon {
+                Some(icon) if self.paths.contains(&req.url.path()) => icon,
+                _ => return next.run(req).await,
+            };
+            if !matches!(req.method, Method::GET | Method::HEAD) {
+                return next.run(req).await;
+            }
+
+            let mut builder = ResponseBuilder::ok(Arc::new(next.into_conn()));
+            builder
+                .add_header("Content-Type", "image/x-icon")
+                .body(icon.to_vec());
+            if let Some(server) = &self.server {
+                builder.set_header("Server", server);
+            }
+            let mut resp = builder.build()?;
+            if self.server.is_none() {
+                resp.headers_mut().remove("Server");
+            }
+            Ok(match req.method {
+                Method::HEAD => resp.without_body(),
+                _ => resp,
+            })
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("favicon")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::persona::Family;
+
+    #[test]
+    fn test_ico() {
+        let icon = ico([0xff, 0x6c, 0x2c]);
+        assert_eq!(22 + 40 + 16 * 16 * 4 + 64, icon.len());
+        assert_eq!([0, 0, 1, 0], icon[..4]);
+        assert_eq!([0x2c, 0x6c, 0xff, 0xff], icon[62..66]);
+    }
+
+    #[test]
+    fn test_favicons() {
+        let dir = std::env::temp_dir().join(format!("httpot-favicons-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("nginx.ico"), b"\0\0\x01\0real").unwrap();
+        let config = FaviconConfig { dir: dir.clone() };
+
+        let apache = Favicon::persona(Persona::from(Family::Apache), Some(&config)).unwrap();
+        assert!(apache.icon.is_none(), "apache ships without a favicon");
+        let nginx = Favicon::persona(Persona::from(Family::Nginx), Some(&config)).unwrap();
+        assert_eq!(b"\0\0\x01\0real".to_vec(), *nginx.icon.unwrap());
+
+        // panels link theirs, so they get a stand-in
+        let webmin = Favicon::panel(Panel::Webmin, Some(&config)).unwrap();
+        assert_eq!(ico([0x3b, 0x6e, 0xa5]), *webmin.icon.unwrap());
+        assert!(webmin.paths.contains(&"/images/favicon-webmin.ico"));
+        let mikrotik = Favicon::device(Device::Mikrotik, None).unwrap();
+        assert_eq!(None, mikrotik.server);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
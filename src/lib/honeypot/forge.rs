@@ -0,0 +1,855 @@
+//! A fake Git forge, Gitea or GitLab, for scanners and people hunting for
+//! leaked source. Its login, repository explorer, and API are served at the
+//! forge's usual paths. Each source sees an organization of its own whose
+//! repositories hold the fake filesystem's files, and whose history has a
+//! commit adding credentials and a later one removing them. Both diffs are
+//! full of honeytokens, and reading them is logged. Logins are refused.
+//! Cloning isn't supported, since Git's smart HTTP protocol isn't served.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use rand::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    fs::fake::{gen_fake_content, gen_fake_entries, gen_fake_file, Theme, Tree},
+    honeypot::{bait, honeytoken::Honeytokens},
+    http::{
+        query::Query,
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    seed::Seeder,
+    util::{escape_html, hash_seed},
+};
+
+const ORGS: &[&str] = &["ops", "infra", "devops", "platform", "backend", "it-admin"];
+/// repositories an organization may have, and their descriptions
+const REPOS: &[(&str, &str)] = &[
+    ("deploy-scripts", "Scripts for production deploys"),
+    ("terraform-aws", "AWS infrastructure as code"),
+    ("billing-service", "Invoicing and payment processing"),
+    ("api-gateway", "Public API gateway config"),
+    ("backup-tools", "Nightly database backup jobs"),
+    ("customer-portal", "Customer self service portal"),
+    ("k8s-manifests", "Cluster manifests and Helm values"),
+    ("monitoring", "Alerting rules and dashboards"),
+];
+const AUTHORS: &[&str] = &["j.miller", "a.nowak", "s.chen", "r.patel", "deploy-bot"];
+/// every repository's history, newest first
+const HISTORY: &[&str] = &[
+    "Remove credentials from deploy config",
+    "Bump dependencies",
+    "Add deploy config",
+    "Initial commit",
+];
+// the commits of HISTORY which add and remove the credentials
+const LEAK_REMOVED: usize = 0;
+const LEAK_ADDED: usize = 2;
+/// the file the credentials were committed to
+const LEAKED_FILE: &str = "config/deploy.yml";
+// histories end around this date
+const HISTORY_END: &str = "2025-03-14T16:02:11Z";
+// generated files are cut off at this size
+const MAX_RAW: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Forge {
+    #[default]
+    Gitea,
+    Gitlab,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForgeConfig {
+    /// which forge is served
+    pub kind: Forge,
+    /// Gitea's version, in its footer and API. GitLab only tells users
+    /// who are signed in.
+    pub gitea_version: String,
+}
+
+impl Default for ForgeConfig {
+    fn default() -> Self {
+        Self {
+            kind: Forge::default(),
+            gitea_version: "1.21.11".to_string(),
+        }
+    }
+}
+
+/// A repository of a source's organization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Repo {
+    id: usize,
+    org: &'static str,
+    name: &'static str,
+    description: &'static str,
+    commits: Vec<Commit>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Commit {
+    sha: String,
+    author: &'static str,
+    message: &'static str,
+    at: DateTime<Utc>,
+}
+
+impl Repo {
+    fn path(&self) -> String {
+        format!("/{}/{}", self.org, self.name)
+    }
+
+    fn updated(&self) -> DateTime<Utc> {
+        self.commits[0].at
+    }
+
+    fn commit(&self, sha: &str) -> Option<(usize, &Commit)> {
+        self.commits
+            .iter()
+            .enumerate()
+            .find(|(_, c)| c.sha.eq_ignore_ascii_case(sha))
+    }
+}
+
+// the organization and repositories the seed sees
+fn repos(seed: &str) -> Vec<Repo> {
+    let mut rng = StdRng::seed_from_u64(hash_seed(seed, "forge"));
+    let org = *ORGS.choose(&mut rng).unwrap();
+    let count = rng.gen_range(3..=5);
+    let end: DateTime<Utc> = HISTORY_END.parse().unwrap();
+
+    let mut repos = REPOS
+        .iter()
+        .enumerate()
+        .choose_multiple(&mut rng, count)
+        .into_iter()
+        .map(|(i, (name, description))| {
+            let mut at = end - Duration::hours(rng.gen_range(0..24 * 60));
+            let commits = HISTORY
+                .iter()
+                .map(|message| {
+                    let commit = Commit {
+                        sha: sha(seed, &format!("/{}/{}/{}", org, name, message)),
+                        author: AUTHORS.choose(&mut rng).unwrap(),
+                        message,
+                        at,
+                    };
+                    at -= Duration::minutes(rng.gen_range(30..60 * 24 * 20));
+                    commit
+                })
+                .collect();
+            Repo {
+                id: 40 + i,
+                org,
+                name,
+                description,
+                commits,
+            }
+        })
+        .collect::<Vec<_>>();
+    repos.sort_by_key(|r| std::cmp::Reverse(r.updated()));
+    repos
+}
+
+fn sha(seed: &str, context: &str) -> String {
+    format!(
+        "{:016x}{:016x}{:08x}",
+        hash_seed(seed, context),
+        hash_seed((seed, 1), context),
+        hash_seed((seed, 2), context) as u32
+    )
+}
+
+// the deploy config, with the credentials or with what replaced them
+fn deploy_config(seed: &str, repo: &Repo, leaked: bool) -> Vec<String> {
+    let tokens = Honeytokens::generate(seed, &repo.path());
+    let secrets = match leaked {
+        true => vec![
+            format!("  user: {}", tokens.username),
+            format!("  password: {}", tokens.password),
+            format!(
+                "  database_url: postgres://{}:{}@10.20.0.21:5432/app",
+                tokens.username, tokens.db_password
+            ),
+            format!("  aws_access_key_id: {}", tokens.aws_access_key_id),
+            format!("  aws_secret_access_key: {}", tokens.aws_secret_access_key),
+        ],
+        false => vec![
+            "  user: ${DEPLOY_USER}".to_string(),
+            "  password: ${DEPLOY_PASSWORD}".to_string(),
+            "  database_url: ${DATABASE_URL}".to_string(),
+            "  aws_access_key_id: ${AWS_ACCESS_KEY_ID}".to_string(),
+            "  aws_secret_access_key: ${AWS_SECRET_ACCESS_KEY}".to_string(),
+        ],
+    };
+    ["production:", "  host: 10.20.0.15"]
+        .iter()
+        .map(|l| l.to_string())
+        .chain(secrets)
+        .collect()
+}
+
+/// A change to one file by a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileDiff {
+    path: String,
+    new_file: bool,
+    /// the unified diff's hunks
+    hunks: String,
+}
+
+impl FileDiff {
+    fn unified(&self) -> String {
+        let old = match self.new_file {
+            true => "/dev/null".to_string(),
+            false => format!("a/{}", self.path),
+        };
+        format!(
+            "diff --git a/{path} b/{path}\n--- {}\n+++ b/{path}\n{}",
+            old,
+            self.hunks,
+            path = self.path
+        )
+    }
+}
+
+// what the commit at the index of HISTORY changed
+fn diff(seed: &str, repo: &Repo, index: usize) -> FileDiff {
+    let lines = |prefix: &str, lines: &[String]| {
+        lines
+            .iter()
+            .map(|l| format!("{}{}\n", prefix, l))
+            .collect::<String>()
+    };
+    match index {
+        LEAK_REMOVED => {
+            let old = deploy_config(seed, repo, true);
+            let new = deploy_config(seed, repo, false);
+            FileDiff {
+                path: LEAKED_FILE.to_string(),
+                new_file: false,
+                hunks: format!(
+                    "@@ -1,{len} +1,{len} @@\n{}{}{}",
+                    lines(" ", &old[..2]),
+                    lines("-", &old[2..]),
+                    lines("+", &new[2..]),
+                    len = old.len()
+                ),
+            }
+        }
+        LEAK_ADDED => {
+            let new = deploy_config(seed, repo, true);
+            FileDiff {
+                path: LEAKED_FILE.to_string(),
+                new_file: true,
+                hunks: format!("@@ -0,0 +1,{} @@\n{}", new.len(), lines("+", &new)),
+            }
+        }
+        1 => FileDiff {
+            path: "requirements.txt".to_string(),
+            new_file: false,
+            hunks: "@@ -1,3 +1,3 @@\n-requests==2.31.0\n+requests==2.32.3\n\
+                    -PyYAML==6.0.1\n+PyYAML==6.0.2\n boto3==1.34.69\n"
+                .to_string(),
+        },
+        _ => {
+            let readme = readme(repo).lines().map(str::to_string).collect::<Vec<_>>();
+            FileDiff {
+                path: "README.md".to_string(),
+                new_file: true,
+                hunks: format!("@@ -0,0 +1,{} @@\n{}", readme.len(), lines("+", &readme)),
+            }
+        }
+    }
+}
+
+fn readme(repo: &Repo) -> String {
+    format!("# {}\n\n{}\n", repo.name, repo.description)
+}
+
+/// Middleware which serves the fake forge.
+#[derive(Debug, Clone)]
+pub struct GitHosting {
+    config: ForgeConfig,
+    seeder: Seeder,
+}
+
+/// A forge page, before it's bound to a connection.
+#[derive(Debug)]
+struct Page {
+    status: StatusCode,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Page {
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "text/html; charset=UTF-8",
+            body,
+        }
+    }
+
+    fn text(body: String) -> Self {
+        Self {
+            content_type: "text/plain; charset=UTF-8",
+            ..Self::html(body)
+        }
+    }
+
+    fn json(status: StatusCode, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+}
+
+impl GitHosting {
+    pub fn new(config: ForgeConfig, seeder: Seeder) -> Self {
+        Self { config, seeder }
+    }
+
+    fn respond(&self, req: &Request) -> Option<Page> {
+        let seed = self.seeder.for_request(req);
+        let repos = repos(&seed);
+        let base = format!("http://{}", req.host_header().unwrap_or("localhost"));
+        let path = req.url.path();
+
+        match (self.config.kind, path) {
+            (Forge::Gitea, "/user/login") => Some(self.login(req, "user_name")),
+            (Forge::Gitlab, "/users/sign_in") => Some(self.login(req, "user[login]")),
+            (Forge::Gitea, "/explore/repos")
+            | (Forge::Gitlab, "/explore" | "/explore/projects") => {
+                Some(Page::html(self.explore(&repos)))
+            }
+            (Forge::Gitea, "/api/v1/version") => Some(Page::json(
+                StatusCode::Ok,
+                json!({"version": self.config.gitea_version}),
+            )),
+            (Forge::Gitea, "/api/v1/repos/search") => Some(Page::json(
+                StatusCode::Ok,
+                json!({
+                    "ok": true,
+                    "data": repos.iter().map(|r| gitea_repo(&base, r)).collect::<Vec<_>>(),
+                }),
+            )),
+            (Forge::Gitlab, "/api/v4/version") => Some(Page::json(
+                StatusCode::Unauthorized,
+                json!({"message": "401 Unauthorized"}),
+            )),
+            (Forge::Gitlab, "/api/v4/projects") => Some(Page::json(
+                StatusCode::Ok,
+                repos.iter().map(|r| gitlab_project(&base, r)).collect(),
+            )),
+            (Forge::Gitlab, p) if p.starts_with("/api/v4/projects/") => {
+                self.gitlab_api(req, &seed, &base, &repos, &p["/api/v4/projects/".len()..])
+            }
+            _ => self.repo_page(req, &seed, &repos, path),
+        }
+    }
+
+    fn login(&self, req: &Request, user_field: &str) -> Page {
+        let mut error = None;
+        if req.method == Method::POST {
+            let form = Query::parse(&String::from_utf8_lossy(&req.body));
+            info!(
+                "{: <8} tried to log into {:?} as {:?}",
+                req.requester(),
+                self.config.kind,
+                form.get(user_field).unwrap_or_default()
+            );
+            error = Some(match self.config.kind {
+                Forge::Gitea => "Username or password is incorrect.",
+                Forge::Gitlab => "Invalid login or password.",
+            });
+        }
+        let page = match self.config.kind {
+            Forge::Gitea => self.gitea_page("Sign In", &gitea_login(error)),
+            Forge::Gitlab => gitlab_page("Sign in", &gitlab_login(error)),
+        };
+        Page {
+            status: match error {
+                Some(_) if self.config.kind == Forge::Gitlab => StatusCode::Unauthorized,
+                _ => StatusCode::Ok,
+            },
+            ..Page::html(page)
+        }
+    }
+
+    fn explore(&self, repos: &[Repo]) -> String {
+        let items = repos
+            .iter()
+            .map(|r| {
+                format!(
+                    "<div class=\"item\"><a class=\"name\" href=\"{path}\">{org} / {name}</a>\
+                     <p class=\"description\">{}</p><span class=\"time\">Updated {}</span></div>\n",
+                    escape_html(r.description),
+                    r.updated().format("%b %-d, %Y"),
+                    path = r.path(),
+                    org = r.org,
+                    name = r.name,
+                )
+            })
+            .collect::<String>();
+        match self.config.kind {
+            Forge::Gitea => self.gitea_page(
+                "Explore",
+                &format!("<div class=\"repos\">\n{}</div>", items),
+            ),
+            Forge::Gitlab => gitlab_page(
+                "Explore projects",
+                &format!("<ul class=\"projects-list\">\n{}</ul>", items),
+            ),
+        }
+    }
+
+    /// the pages of a repository, if the path is to one the source's
+    /// organization has.
+    fn repo_page(&self, req: &Request, seed: &str, repos: &[Repo], path: &str) -> Option<Page> {
+        let mut parts = path.trim_start_matches('/').splitn(3, '/');
+        let (org, name) = (parts.next()?, parts.next()?);
+        let repo = repos
+            .iter()
+            .find(|r| r.org.eq_ignore_ascii_case(org) && r.name.eq_ignore_ascii_case(name))?;
+        let rest = parts.next().unwrap_or_default();
+        let rest = match self.config.kind {
+            Forge::Gitea => rest,
+            Forge::Gitlab => rest.strip_prefix("-/").unwrap_or(rest),
+        };
+
+        let page = match rest.split_once('/') {
+            None if rest.is_empty() => Page::html(self.tree(seed, repo)),
+            Some(("src" | "tree", _)) => Page::html(self.tree(seed, repo)),
+            Some(("commits", _)) => Page::html(self.commits(repo)),
+            Some(("commit", sha)) => {
+                let (sha, raw) = match sha.rsplit_once('.') {
+                    Some((sha, "diff" | "patch")) => (sha, true),
+                    _ => (sha, false),
+                };
+                let (index, commit) = repo.commit(sha)?;
+                self.log_leak(req, repo, index);
+                let diff = diff(seed, repo, index).unified();
+                match raw {
+                    true => Page::text(diff),
+                    false => Page::html(self.commit(repo, commit, &diff)),
+                }
+            }
+            Some(("raw", file)) => {
+                // past the branch, as in branch/main/ or main/
+                let file = file.strip_prefix("branch/").unwrap_or(file);
+                let (_, file) = file.split_once('/')?;
+                Page::text(raw(seed, self.seeder.tree(), repo, file)?)
+            }
+            _ => return None,
+        };
+        Some(page)
+    }
+
+    fn log_leak(&self, req: &Request, repo: &Repo, index: usize) {
+        if index == LEAK_ADDED || index == LEAK_REMOVED {
+            info!(
+                "{: <8} read the credentials leaked in the history of {}",
+                req.requester(),
+                repo.path()
+            );
+        }
+    }
+
+    fn gitlab_api(
+        &self,
+        req: &Request,
+        seed: &str,
+        base: &str,
+        repos: &[Repo],
+        rest: &str,
+    ) -> Option<Page> {
+        let mut parts = rest.split('/');
+        let id = parts.next()?.parse::<usize>().ok()?;
+        let repo = repos.iter().find(|r| r.id == id)?;
+        let parts = parts.collect::<Vec<_>>();
+
+        let body = match parts.as_slice() {
+            [] => gitlab_project(base, repo),
+            ["repository", "commits"] => repo
+                .commits
+                .iter()
+                .map(|c| gitlab_commit(base, repo, c))
+                .collect(),
+            ["repository", "commits", sha] => gitlab_commit(base, repo, repo.commit(sha)?.1),
+            ["repository", "commits", sha, "diff"] => {
+                let (index, _) = repo.commit(sha)?;
+                self.log_leak(req, repo, index);
+                let diff = diff(seed, repo, index);
+                json!([{
+                    "old_path": diff.path,
+                    "new_path": diff.path,
+                    "diff": diff.hunks,
+                    "new_file": diff.new_file,
+                    "renamed_file": false,
+                    "deleted_file": false,
+                }])
+            }
+            _ => {
+                return Some(Page::json(
+                    StatusCode::NotFound,
+                    json!({"message": "404 Not Found"}),
+                ))
+            }
+        };
+        Some(Page::json(StatusCode::Ok, body))
+    }
+
+    fn tree(&self, seed: &str, repo: &Repo) -> String {
+        let mut entries = vec![
+            ("config/".to_string(), true),
+            ("README.md".to_string(), false),
+        ];
+        entries.extend(
+            gen_fake_entries(
+                seed,
+                &format!("{}/", repo.path()),
+                source(self.seeder.tree()),
+            )
+            .into_iter()
+            .map(|e| (e.name, e.is_dir)),
+        );
+        entries.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        entries.dedup();
+
+        let raw = match self.config.kind {
+            Forge::Gitea => "raw/branch/main",
+            Forge::Gitlab => "-/raw/main",
+        };
+        let rows = entries
+            .iter()
+            .map(|(name, is_dir)| match is_dir {
+                true => format!(
+                    "<tr><td class=\"name\"><span class=\"dir\">{}</span></td></tr>\n",
+                    escape_html(name)
+                ),
+                false => format!(
+                    "<tr><td class=\"name\"><a href=\"{}/{}/{}\">{}</a></td></tr>\n",
+                    repo.path(),
+                    raw,
+                    escape_html(name),
+                    escape_html(name)
+                ),
+            })
+            .collect::<String>();
+        let latest = &repo.commits[0];
+        let content = format!(
+            "<h2>{org} / {name}</h2><p class=\"description\">{description}</p>\n\
+             <div class=\"latest-commit\"><a href=\"{commit}\">{short}</a> {message} \
+             by {author}</div>\n\
+             <table class=\"files\">\n{rows}</table>\n<pre class=\"readme\">{readme}</pre>",
+            org = repo.org,
+            name = repo.name,
+            description = escape_html(repo.description),
+            commit = self.commit_url(repo, latest),
+            short = &latest.sha[..10],
+            message = latest.message,
+            author = latest.author,
+            rows = rows,
+            readme = escape_html(&readme(repo)),
+        );
+        self.page(&format!("{}/{}", repo.org, repo.name), &content)
+    }
+
+    fn commits(&self, repo: &Repo) -> String {
+        let rows = repo
+            .commits
+            .iter()
+            .map(|c| {
+                format!(
+                    "<tr><td class=\"author\">{}</td><td class=\"sha\"><a href=\"{}\">{}</a></td>\
+                     <td class=\"message\">{}</td><td class=\"date\">{}</td></tr>\n",
+                    c.author,
+                    self.commit_url(repo, c),
+                    &c.sha[..10],
+                    c.message,
+                    c.at.to_rfc2822()
+                )
+            })
+            .collect::<String>();
+        self.page(
+            &format!("Commits - {}/{}", repo.org, repo.name),
+            &format!("<table class=\"commits\">\n{}</table>", rows),
+        )
+    }
+
+    fn commit(&self, repo: &Repo, commit: &Commit, diff: &str) -> String {
+        self.page(
+            &format!("{} - {}/{}", commit.message, repo.org, repo.name),
+            &format!(
+                "<h3>{}</h3><p>{} committed {}</p><p class=\"sha\">{}</p>\n\
+                 <pre class=\"diff\">{}</pre>",
+                commit.message,
+                commit.author,
+                commit.at.to_rfc2822(),
+                commit.sha,
+                escape_html(diff)
+            ),
+        )
+    }
+
+    fn commit_url(&self, repo: &Repo, commit: &Commit) -> String {
+        match self.config.kind {
+            Forge::Gitea => format!("{}/commit/{}", repo.path(), commit.sha),
+            Forge::Gitlab => format!("{}/-/commit/{}", repo.path(), commit.sha),
+        }
+    }
+
+    fn page(&self, title: &str, content: &str) -> String {
+        match self.config.kind {
+            Forge::Gitea => self.gitea_page(title, content),
+            Forge::Gitlab => gitlab_page(title, content),
+        }
+    }
+
+    fn gitea_page(&self, title: &str, content: &str) -> String {
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en-US" class="theme-auto">
+<head>
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} - Gitea: Git with a cup of tea</title>
+<link rel="manifest" href="data:application/json;base64,eyJuYW1lIjoiR2l0ZWE6IEdpdCB3aXRoIGEgY3VwIG9mIHRlYSJ9">
+<meta name="author" content="Gitea - Git with a cup of tea">
+<meta name="description" content="Gitea (Git with a cup of tea) is a painless self-hosted Git service written in Go">
+<meta name="keywords" content="go,git,self-hosted,gitea">
+<link rel="icon" href="/assets/img/favicon.svg" type="image/svg+xml">
+<link rel="stylesheet" href="/assets/css/index.css?v={version}">
+</head>
+<body>
+<div class="full height">
+<nav id="navbar" role="navigation"><a class="item" href="/">Home</a> <a class="item" href="/explore/repos">Explore</a> <a class="item" href="/user/login">Sign In</a></nav>
+<div role="main" class="page-content">
+{content}
+</div>
+</div>
+<footer class="page-footer" role="group"><div class="left-links">Powered by Gitea Version: {version} Page: <strong>2ms</strong> Template: <strong>1ms</strong></div></footer>
+</body>
+</html>
+"#,
+            title = escape_html(title),
+            content = content,
+            version = escape_html(&self.config.gitea_version),
+        )
+    }
+}
+
+impl Middleware for GitHosting {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let page = match self.respond(req) {
+                Some(page) => page,
+                None => return next.run(req).await,
+            };
+
+            let cookie = match self.config.kind {
+                Forge::Gitea => "i_like_gitea",
+                Forge::Gitlab => "_gitlab_session",
+            };
+            let session = hash_seed(self.seeder.for_request(req), "forge session");
+            Ok(ResponseBuilder::default(Arc::new(next.into_conn()))
+                .status_code(page.status)
+                .add_header("Content-Type", page.content_type)
+                .add_header(
+                    "Set-Cookie",
+                    format!(
+                        "{}={:016x}; Path=/; HttpOnly; SameSite=Lax",
+                        cookie, session
+                    ),
+                )
+                .body(page.body)
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("forge")
+    }
+}
+
+// repositories are trees of source, whatever the fake filesystem's theme
+fn source(tree: Tree) -> Tree {
+    Tree {
+        theme: Theme::Source,
+        ..tree
+    }
+}
+
+// a file at the head of the repository
+fn raw(seed: &str, tree: Tree, repo: &Repo, file: &str) -> Option<String> {
+    match file {
+        "README.md" => return Some(readme(repo)),
+        LEAKED_FILE => return Some(deploy_config(seed, repo, false).join("\n") + "\n"),
+        _ => {}
+    }
+    let path = format!("{}/{}", repo.path(), file);
+    if let Some((_, body)) = bait::file(seed, &path) {
+        return Some(String::from_utf8_lossy(&body).into_owned());
+    }
+    let file = gen_fake_file(seed, &path, source(tree))?;
+    let content = gen_fake_content(seed, &path, file.size.min(MAX_RAW));
+    Some(String::from_utf8_lossy(&content).into_owned())
+}
+
+fn gitea_repo(base: &str, repo: &Repo) -> Value {
+    json!({
+        "id": repo.id,
+        "owner": {"id": 2, "login": repo.org, "username": repo.org},
+        "name": repo.name,
+        "full_name": format!("{}/{}", repo.org, repo.name),
+        "description": repo.description,
+        "private": false,
+        "html_url": format!("{}{}", base, repo.path()),
+        "clone_url": format!("{}{}.git", base, repo.path()),
+        "default_branch": "main",
+        "updated_at": repo.updated().to_rfc3339(),
+    })
+}
+
+fn gitlab_project(base: &str, repo: &Repo) -> Value {
+    json!({
+        "id": repo.id,
+        "name": repo.name,
+        "path": repo.name,
+        "path_with_namespace": format!("{}/{}", repo.org, repo.name),
+        "description": repo.description,
+        "default_branch": "main",
+        "visibility": "public",
+        "web_url": format!("{}{}", base, repo.path()),
+        "http_url_to_repo": format!("{}{}.git", base, repo.path()),
+        "last_activity_at": repo.updated().to_rfc3339(),
+        "namespace": {"id": 2, "name": repo.org, "path": repo.org, "kind": "group"},
+    })
+}
+
+fn gitlab_commit(base: &str, repo: &Repo, commit: &Commit) -> Value {
+    json!({
+        "id": commit.sha,
+        "short_id": &commit.sha[..8],
+        "title": commit.message,
+        "message": commit.message,
+        "author_name": commit.author,
+        "author_email": format!("{}@{}.internal", commit.author, repo.org),
+        "authored_date": commit.at.to_rfc3339(),
+        "web_url": format!("{}{}/-/commit/{}", base, repo.path(), commit.sha),
+    })
+}
+
+fn gitea_login(error: Option<&str>) -> String {
+    format!(
+        r#"<div class="page-content user signin">
+<h4 class="ui top attached header center">Sign In</h4>
+{}<form class="ui form" action="/user/login" method="post">
+<div class="required field"><label for="user_name">Username or Email Address</label><input id="user_name" type="text" name="user_name" autofocus required></div>
+<div class="required field"><label for="password">Password</label><input id="password" name="password" type="password" autocomplete="current-password" required></div>
+<div class="inline field"><div class="ui checkbox"><label>Remember This Device</label><input name="remember" type="checkbox"></div></div>
+<button class="ui primary button">Sign In</button> <a href="/user/forgot_password">Forgot password?</a>
+</form>
+</div>"#,
+        error
+            .map(|e| format!(
+                "<div class=\"ui negative message flash-error\"><p>{}</p></div>\n",
+                e
+            ))
+            .unwrap_or_default()
+    )
+}
+
+fn gitlab_login(error: Option<&str>) -> String {
+    format!(
+        r#"{}<form class="new_user gl-show-field-errors" id="new_user" action="/users/sign_in" accept-charset="UTF-8" method="post">
+<div class="form-group"><label for="user_login">Username or primary email</label><input class="form-control gl-form-input" autocomplete="username" autofocus="autofocus" required="required" type="text" name="user[login]" id="user_login"></div>
+<div class="form-group"><label for="user_password">Password</label><input class="form-control gl-form-input" autocomplete="current-password" required="required" type="password" name="user[password]" id="user_password"></div>
+<div class="form-group"><input name="user[remember_me]" type="hidden" value="0"><input type="checkbox" value="1" name="user[remember_me]" id="user_remember_me"><label for="user_remember_me">Remember me</label></div>
+<button type="submit" class="btn btn-confirm btn-md gl-button">Sign in</button>
+</form>"#,
+        error
+            .map(|e| format!("<div class=\"flash-container flash-container-page\"><div class=\"flash-alert\">{}</div></div>\n", e))
+            .unwrap_or_default()
+    )
+}
+
+fn gitlab_page(title: &str, content: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html class="" lang="en">
+<head prefix="og: http://ogp.me/ns#">
+<meta charset="utf-8">
+<meta content="IE=edge" http-equiv="X-UA-Compatible">
+<meta content="width=device-width, initial-scale=1" name="viewport">
+<meta content="object" property="og:type">
+<meta content="GitLab" property="og:site_name">
+<meta content="{title} · GitLab" property="og:title">
+<title>{title} · GitLab</title>
+<link rel="stylesheet" href="/assets/application-2ec3a1e8e5a2d0da1e4c1c7c0e5d1e7f.css" media="all">
+<link rel="icon" type="image/png" href="/assets/favicon-72a2cad5025aa931d6ea56c3201d1f18e68a8cd39788c7c80d5b2b82aa5143ef.png" id="favicon">
+</head>
+<body class="ui-indigo tab-width-8 gl-browser-firefox gl-platform-linux" data-page="sessions:new">
+<header class="navbar navbar-gitlab"><a class="brand-logo" href="/"><span class="logo-text">GitLab</span></a> <a href="/explore">Explore</a> <a href="/users/sign_in">Sign in</a></header>
+<div class="content-wrapper">
+<main class="content" id="content-body">
+{content}
+</main>
+</div>
+</body>
+</html>
+"#,
+        title = escape_html(title),
+        content = content,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_repos() {
+        assert_eq!(repos("seed"), repos("seed"));
+        assert_ne!(repos("seed"), repos("other"));
+
+        let repos = repos("seed");
+        assert!((3..=5).contains(&repos.len()));
+        for repo in &repos {
+            assert_eq!(HISTORY.len(), repo.commits.len());
+            assert!(repo.commits.windows(2).all(|c| c[0].at > c[1].at));
+            assert_eq!(40, repo.commits[0].sha.len());
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let repo = &repos("seed")[0];
+        let tokens = Honeytokens::generate("seed", &repo.path());
+
+        let removed = diff("seed", repo, LEAK_REMOVED).unified();
+        assert!(removed.contains(&format!("-  password: {}\n", tokens.password)));
+        assert!(removed.contains("+  password: ${DEPLOY_PASSWORD}\n"));
+        assert!(removed.starts_with(&format!(
+            "diff --git a/{f} b/{f}\n--- a/{f}\n+++ b/{f}\n@@ -1,7 +1,7 @@\n",
+            f = LEAKED_FILE
+        )));
+
+        let added = diff("seed", repo, LEAK_ADDED);
+        assert!(added.new_file);
+        assert!(added.hunks.contains(&tokens.aws_secret_access_key));
+
+        // the head no longer has them
+        let head = raw("seed", Default::default(), repo, LEAKED_FILE).unwrap();
+        assert!(!head.contains(&tokens.password));
+    }
+}
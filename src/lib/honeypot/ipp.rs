@@ -0,0 +1,295 @@
+//! The Internet Printing Protocol, which printers and CUPS serve over HTTP
+//! at `/ipp/print` and `/printers/...`. Requests are parsed for their
+//! operation and attributes, so print jobs, which paper spam campaigns
+//! send to every open printer, are logged along with their documents, and
+//! so are CUPS printer additions as used to chain CVE-2024-47176. Every
+//! operation succeeds, and nothing is printed.
+use crate::{
+    display,
+    fetch::sha256_hex,
+    http::request::{Method, Request},
+    prelude::*,
+};
+
+pub const CONTENT_TYPE: &str = "application/ipp";
+
+// delimiter and value tags, RFC 8010 section 3.5
+const OPERATION_ATTRIBUTES: u8 = 0x01;
+const JOB_ATTRIBUTES: u8 = 0x02;
+const END_OF_ATTRIBUTES: u8 = 0x03;
+const PRINTER_ATTRIBUTES: u8 = 0x04;
+const INTEGER: u8 = 0x21;
+const BOOLEAN: u8 = 0x22;
+const ENUM: u8 = 0x23;
+const URI: u8 = 0x45;
+const KEYWORD: u8 = 0x44;
+const NAME: u8 = 0x42;
+const TEXT: u8 = 0x41;
+const CHARSET: u8 = 0x47;
+const NATURAL_LANGUAGE: u8 = 0x48;
+const MIME_MEDIA_TYPE: u8 = 0x49;
+
+const PRINT_JOB: u16 = 0x0002;
+const GET_PRINTER_ATTRIBUTES: u16 = 0x000b;
+// attributes kept from a single request
+const MAX_ATTRIBUTES: usize = 256;
+
+/// An IPP operation request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub operation: u16,
+    pub request_id: u32,
+    /// attributes with string values, such as `job-name` and
+    /// `printer-uri`, in the order sent
+    pub attributes: Vec<(String, String)>,
+    /// the document following the attributes, as with Print-Job
+    pub document: Vec<u8>,
+}
+
+impl Operation {
+    pub fn parse(body: &[u8]) -> Result<Self> {
+        ensure!(body.len() >= 9, "IPP request is only {} bytes", body.len());
+        let operation = u16::from_be_bytes([body[2], body[3]]);
+        let request_id = u32::from_be_bytes([body[4], body[5], body[6], body[7]]);
+
+        let mut attributes = vec![];
+        let mut name = String::new();
+        let mut at = 8;
+        loop {
+            let tag = *body
+                .get(at)
+                .ok_or_else(|| anyhow!("IPP attributes aren't terminated"))?;
+            at += 1;
+            match tag {
+                END_OF_ATTRIBUTES => break,
+                // the start of another attribute group
+                0x00..=0x0f => continue,
+                _ => {}
+            }
+
+            let (attr_name, rest) = field(body, at)?;
+            let (value, rest) = field(body, rest)?;
+            at = rest;
+            // additional values of an attribute have an empty name
+            if !attr_name.is_empty() {
+                name = String::from_utf8_lossy(attr_name).into_owned();
+            }
+            if (TEXT..=MIME_MEDIA_TYPE).contains(&tag) && attributes.len() < MAX_ATTRIBUTES {
+                attributes.push((name.clone(), String::from_utf8_lossy(value).into_owned()));
+            }
+        }
+
+        Ok(Self {
+            operation,
+            request_id,
+            attributes,
+            document: body[at..].to_vec(),
+        })
+    }
+
+    /// the operation's name, RFC 8011 and CUPS's own.
+    pub fn name(&self) -> &'static str {
+        match self.operation {
+            0x0002 => "Print-Job",
+            0x0003 => "Print-URI",
+            0x0004 => "Validate-Job",
+            0x0005 => "Create-Job",
+            0x0006 => "Send-Document",
+            0x0008 => "Cancel-Job",
+            0x0009 => "Get-Job-Attributes",
+            0x000a => "Get-Jobs",
+            0x000b => "Get-Printer-Attributes",
+            0x4001 => "CUPS-Get-Default",
+            0x4002 => "CUPS-Get-Printers",
+            0x4003 => "CUPS-Add-Modify-Printer",
+            0x4004 => "CUPS-Delete-Printer",
+            _ => "unknown",
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+// a length-prefixed field at `at`, and where the next begins
+fn field(body: &[u8], at: usize) -> Result<(&[u8], usize)> {
+    let len = match body.get(at..at + 2) {
+        Some(len) => u16::from_be_bytes([len[0], len[1]]) as usize,
+        None => bail!("IPP field at {} is truncated", at),
+    };
+    let value = body
+        .get(at + 2..at + 2 + len)
+        .ok_or_else(|| anyhow!("IPP field at {} is truncated", at))?;
+    Ok((value, at + 2 + len))
+}
+
+/// whether the request is an IPP operation.
+pub fn is_ipp(req: &Request) -> bool {
+    req.method == Method::POST
+        && req
+            .headers
+            .get("Content-Type")
+            .into_iter()
+            .flatten()
+            .any(|v| v.eq_ignore_ascii_case(CONTENT_TYPE))
+}
+
+/// the response to an IPP request to the printer, logging its operation
+/// and any document sent.
+pub fn respond(req: &Request, make_and_model: &str) -> Vec<u8> {
+    let op = match Operation::parse(&req.body) {
+        Ok(op) => op,
+        Err(e) => {
+            debug!("{: <8} sent unparsable IPP: {}", req.requester(), e);
+            // client-error-bad-request
+            return Response::new(0x0400, 0).finish();
+        }
+    };
+    info!(
+        "{: <8} sent IPP {} with {:?}",
+        req.requester(),
+        op.name(),
+        op.attributes
+    );
+    if !op.document.is_empty() {
+        info!(
+            "{: <8} printed {} bytes sha256={}: {}",
+            req.requester(),
+            op.document.len(),
+            sha256_hex(&op.document),
+            display::truncate(&String::from_utf8_lossy(&op.document), 120)
+        );
+    }
+
+    let mut resp = Response::new(0x0000, op.request_id);
+    match op.operation {
+        GET_PRINTER_ATTRIBUTES => {
+            resp.group(PRINTER_ATTRIBUTES)
+                .string(NAME, "printer-name", "HP_LaserJet")
+                .string(TEXT, "printer-make-and-model", make_and_model)
+                .string(TEXT, "printer-info", make_and_model)
+                .integer(ENUM, "printer-state", 3)
+                .string(KEYWORD, "printer-state-reasons", "none")
+                .boolean("printer-is-accepting-jobs", true)
+                .string(URI, "printer-uri-supported", "ipp://localhost/ipp/print")
+                .string(
+                    MIME_MEDIA_TYPE,
+                    "document-format-supported",
+                    "application/pdf",
+                )
+                .string(MIME_MEDIA_TYPE, "", "application/postscript")
+                .string(MIME_MEDIA_TYPE, "", "text/plain")
+                .integer(INTEGER, "queued-job-count", 0);
+        }
+        // every job is accepted and left pending
+        PRINT_JOB | 0x0003 | 0x0005 | 0x0006 => {
+            let job_id = (op.request_id % 900) as i32 + 100;
+            resp.group(JOB_ATTRIBUTES)
+                .integer(INTEGER, "job-id", job_id)
+                .string(
+                    URI,
+                    "job-uri",
+                    &format!("ipp://localhost/ipp/print/job-{}", job_id),
+                )
+                .integer(ENUM, "job-state", 3)
+                .string(KEYWORD, "job-state-reasons", "none");
+        }
+        _ => {}
+    }
+    resp.finish()
+}
+
+/// An IPP response being encoded.
+struct Response(Vec<u8>);
+
+impl Response {
+    fn new(status: u16, request_id: u32) -> Self {
+        let mut out = vec![2, 0];
+        out.extend(status.to_be_bytes());
+        out.extend(request_id.to_be_bytes());
+        let mut resp = Self(out);
+        resp.group(OPERATION_ATTRIBUTES)
+            .string(CHARSET, "attributes-charset", "utf-8")
+            .string(NATURAL_LANGUAGE, "attributes-natural-language", "en");
+        resp
+    }
+
+    fn group(&mut self, tag: u8) -> &mut Self {
+        self.0.push(tag);
+        self
+    }
+
+    fn attribute(&mut self, tag: u8, name: &str, value: &[u8]) -> &mut Self {
+        self.0.push(tag);
+        self.0.extend((name.len() as u16).to_be_bytes());
+        self.0.extend(name.as_bytes());
+        self.0.extend((value.len() as u16).to_be_bytes());
+        self.0.extend(value);
+        self
+    }
+
+    fn string(&mut self, tag: u8, name: &str, value: &str) -> &mut Self {
+        self.attribute(tag, name, value.as_bytes())
+    }
+
+    fn integer(&mut self, tag: u8, name: &str, value: i32) -> &mut Self {
+        self.attribute(tag, name, &value.to_be_bytes())
+    }
+
+    fn boolean(&mut self, name: &str, value: bool) -> &mut Self {
+        self.attribute(BOOLEAN, name, &[value as u8])
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.0.push(END_OF_ATTRIBUTES);
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn print_job(document: &[u8]) -> Vec<u8> {
+        let mut req = Response(vec![2, 0, 0x00, 0x02, 0, 0, 0, 7]);
+        req.group(OPERATION_ATTRIBUTES)
+            .string(CHARSET, "attributes-charset", "utf-8")
+            .string(URI, "printer-uri", "ipp://203.0.113.9/ipp/print")
+            .string(NAME, "job-name", "URGENT")
+            .string(NAME, "requesting-user-name", "anon");
+        let mut body = req.finish();
+        body.extend(document);
+        body
+    }
+
+    #[test]
+    fn test_parse() {
+        let op = Operation::parse(&print_job(b"BUY NOW\x0c")).unwrap();
+        assert_eq!("Print-Job", op.name());
+        assert_eq!(7, op.request_id);
+        assert_eq!(Some("URGENT"), op.attribute("job-name"));
+        assert_eq!(
+            Some("ipp://203.0.113.9/ipp/print"),
+            op.attribute("printer-uri")
+        );
+        assert_eq!(b"BUY NOW\x0c".to_vec(), op.document);
+
+        assert!(Operation::parse(&[2, 0, 0, 2, 0, 0, 0, 1, 1, 0x42, 0, 9]).is_err());
+    }
+
+    #[test]
+    fn test_response() {
+        let resp = Response::new(0, 7).finish();
+        // responses parse as requests do: the status is where the operation
+        // would be
+        let parsed = Operation::parse(&resp).unwrap();
+        assert_eq!(0, parsed.operation);
+        assert_eq!(7, parsed.request_id);
+        assert_eq!(Some("utf-8"), parsed.attribute("attributes-charset"));
+        assert!(parsed.document.is_empty());
+    }
+}
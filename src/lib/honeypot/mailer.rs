@@ -0,0 +1,316 @@
+//! Mail-sending scripts spammers look for, such as Matt Wright's
+//! `formmail.pl` and PHP `mailer.php` drops, and the PHPMailer version file
+//! probed before CVE-2016-10033. Every message posted is logged for its
+//! recipients, sender, and content, as intel on the campaign, and claimed
+//! to have been sent. Nothing is ever mailed.
+use std::sync::Arc;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    display,
+    honeypot::php::Php,
+    http::{
+        query::Query,
+        request::{Method, Request},
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::escape_html,
+};
+
+/// mail scripts, lowercased
+const SCRIPTS: &[&str] = &[
+    "/cgi-bin/formmail.pl",
+    "/cgi-bin/formmail.cgi",
+    "/cgi-bin/formmail",
+    "/formmail.pl",
+    "/formmail.php",
+    "/mailer.php",
+    "/mail.php",
+    "/sendmail.php",
+    "/send_mail.php",
+    "/phpmailer.php",
+];
+/// PHPMailer's version file, at the paths it's probed
+const PHPMAILER_VERSIONS: &[&str] = &["/vendor/phpmailer/phpmailer/version", "/phpmailer/version"];
+// the last release before CVE-2016-10033 was fixed
+const PHPMAILER_VERSION: &str = "5.2.16";
+
+const RECIPIENT_FIELDS: &[&str] = &[
+    "recipient",
+    "recipients",
+    "to",
+    "mailto",
+    "email_to",
+    "send_to",
+    "rcpt",
+    "cc",
+    "bcc",
+];
+const SENDER_FIELDS: &[&str] = &[
+    "from",
+    "email",
+    "sender",
+    "from_email",
+    "reply_to",
+    "replyto",
+];
+const BODY_FIELDS: &[&str] = &[
+    "message", "body", "msg", "content", "text", "comments", "html",
+];
+// recipients kept from a single message
+const MAX_RECIPIENTS: usize = 1_000;
+
+lazy_static! {
+    /// a header smuggled into a field after a line break
+    static ref INJECTED_HEADER: Regex =
+        Regex::new(r"(?im)[\r\n]+\s*(to|cc|bcc)\s*:([^\r\n]*)").unwrap();
+    /// sendmail arguments smuggled into the sender, as in CVE-2016-10033
+    static ref SENDMAIL_ARGS: Regex =
+        Regex::new(r#"(^|[\s"\\])-(X|C|oQ|OQueueDirectory=)\s*\S"#).unwrap();
+}
+
+/// A message a mail script was asked to send.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Message {
+    pub recipients: Vec<String>,
+    pub sender: Option<String>,
+    pub subject: Option<String>,
+    pub body: String,
+    /// headers or sendmail arguments were smuggled into the fields
+    pub injected: bool,
+}
+
+/// the message a request posts to a mail script, if it names any
+/// recipients or content.
+pub fn message(req: &Request) -> Option<Message> {
+    if req.method != Method::POST || !SCRIPTS.contains(&req.url.path().to_lowercase().as_str()) {
+        return None;
+    }
+    let query = Query::parse(req.url.query().unwrap_or_default());
+    let form = Query::parse(&String::from_utf8_lossy(&req.body));
+    let fields = query
+        .iter()
+        .chain(form.iter())
+        .map(|p| (p.name.to_lowercase(), p.value.as_str()))
+        .collect::<Vec<_>>();
+    let first = |names: &[&str]| {
+        fields
+            .iter()
+            .find(|(k, v)| !v.trim().is_empty() && names.contains(&k.as_str()))
+            .map(|(_, v)| v.trim().to_string())
+    };
+
+    let mut message = Message {
+        sender: first(SENDER_FIELDS),
+        subject: first(&["subject"]),
+        body: first(BODY_FIELDS).unwrap_or_default(),
+        ..Default::default()
+    };
+    for (name, value) in &fields {
+        if RECIPIENT_FIELDS.contains(&name.as_str()) {
+            message.recipients.extend(addresses(value));
+        }
+        for header in INJECTED_HEADER.captures_iter(value) {
+            message.injected = true;
+            message.recipients.extend(addresses(&header[2]));
+        }
+    }
+    if message.sender.as_deref().is_some_and(has_sendmail_args) {
+        message.injected = true;
+    }
+    message.recipients.dedup();
+    message.recipients.truncate(MAX_RECIPIENTS);
+
+    match message.recipients.is_empty() && message.body.is_empty() {
+        true => None,
+        false => Some(message),
+    }
+}
+
+/// whether the value smuggles sendmail arguments, such as `-X` to write a
+/// log file, behind an email address.
+pub fn has_sendmail_args(value: &str) -> bool {
+    SENDMAIL_ARGS.is_match(value)
+}
+
+// the email addresses in a field, which may list several
+fn addresses(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .map(|a| a.trim_matches(|c| c == '<' || c == '>' || c == '"'))
+        .filter(|a| a.contains('@'))
+        .map(str::to_string)
+}
+
+/// Middleware which pretends to send mail for spammers.
+#[derive(Debug, Clone, Default)]
+pub struct Mailers {
+    php: Php,
+}
+
+impl Mailers {
+    pub fn new(php: Php) -> Self {
+        Self { php }
+    }
+}
+
+impl Middleware for Mailers {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let path = req.url.path().to_lowercase();
+            if req.method == Method::GET && PHPMAILER_VERSIONS.contains(&path.as_str()) {
+                // a static file, which PHP never sees
+                return Ok(ResponseBuilder::ok(Arc::new(next.into_conn()))
+                    .add_header("Content-Type", "text/plain")
+                    .body(format!("{}\n", PHPMAILER_VERSION))
+                    .build()?);
+            }
+            if !SCRIPTS.contains(&path.as_str()) {
+                return next.run(req).await;
+            }
+
+            let body = match (req.method.clone(), message(req)) {
+                (Method::POST, Some(message)) => {
+                    info!(
+                        "{: <8} asked {} to mail {} recipients {:?} from {:?} about {:?}: {}",
+                        req.requester(),
+                        req.url.path(),
+                        message.recipients.len(),
+                        message.recipients.iter().take(5).collect::<Vec<_>>(),
+                        message.sender.as_deref().unwrap_or_default(),
+                        message.subject.as_deref().unwrap_or_default(),
+                        display::truncate(&message.body, 120)
+                    );
+                    if message.injected {
+                        info!(
+                            "{: <8} injected mail headers or sendmail arguments into {}",
+                            req.requester(),
+                            req.url.path()
+                        );
+                    }
+                    sent(&path, &message)
+                }
+                (Method::GET | Method::POST, _) => contact_form(req.url.path()),
+                _ => return next.run(req).await,
+            };
+            let mut builder = ResponseBuilder::ok(Arc::new(next.into_conn()));
+            Ok(self
+                .php
+                .expose(&mut builder)
+                .add_header("Content-Type", "text/html; charset=UTF-8")
+                .body(body)
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("mailer")
+    }
+}
+
+// what each script says once it's "sent" the message
+fn sent(path: &str, message: &Message) -> String {
+    if !path.contains("formmail") {
+        return "Message has been sent".to_string();
+    }
+    let to = message.recipients.first().map(String::as_str);
+    format!(
+        "<html><head><title>Thank You</title></head><body><center>\
+         <h1>Thank You For Filling Out This Form</h1></center>\
+         Below is what you submitted to {} on {}<p><hr size=1 width=75%><p>\
+         <b>subject:</b> {}<p><b>message:</b> {}<p><hr size=1 width=75%><p>\
+         <center><font size=-1><a href=\"http://www.worldwidemart.com/scripts/formmail.shtml\">FormMail</a> \
+         V1.6 &copy; 1995 - 1997  Matt Wright</font></center></body></html>",
+        escape_html(to.unwrap_or_default()),
+        Utc::now().format("%A, %B %-d, %Y at %H:%M:%S"),
+        escape_html(message.subject.as_deref().unwrap_or_default()),
+        escape_html(&message.body)
+    )
+}
+
+fn contact_form(action: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><title>Contact us</title></head><body>\
+         <h1>Contact us</h1><form action=\"{}\" method=\"post\">\
+         <input type=\"hidden\" name=\"recipient\" value=\"\">\
+         <p>Email: <input type=\"text\" name=\"email\"></p>\
+         <p>Subject: <input type=\"text\" name=\"subject\"></p>\
+         <p><textarea name=\"message\"></textarea></p>\
+         <button type=\"submit\">Send</button></form></body></html>",
+        escape_html(action)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stub_request(path: &str, body: &str) -> Request {
+        Request::stub(Method::POST, path, body)
+    }
+
+    #[test]
+    fn test_message() {
+        let req = stub_request(
+            "/cgi-bin/FormMail.pl",
+            "recipient=a%40victim.example%2C+b%40victim.example&email=spam%40bulk.example\
+             &subject=Cheap+meds&message=Visit+http%3A%2F%2Fpills.example",
+        );
+        assert_eq!(
+            Some(Message {
+                recipients: vec![
+                    "a@victim.example".to_string(),
+                    "b@victim.example".to_string()
+                ],
+                sender: Some("spam@bulk.example".to_string()),
+                subject: Some("Cheap meds".to_string()),
+                body: "Visit http://pills.example".to_string(),
+                injected: false,
+            }),
+            message(&req)
+        );
+
+        // recipients smuggled into the subject as a header
+        let req = stub_request(
+            "/mailer.php",
+            "to=a%40victim.example&subject=hi%0D%0ABcc%3A+c%40victim.example%2Cd%40victim.example",
+        );
+        let smuggled = message(&req).unwrap();
+        assert!(smuggled.injected);
+        assert_eq!(
+            vec!["a@victim.example", "c@victim.example", "d@victim.example"],
+            smuggled.recipients
+        );
+
+        // CVE-2016-10033
+        let req = stub_request(
+            "/mail.php",
+            "email=%22attacker%5C%22+-oQ%2Ftmp%2F+-X%2Fvar%2Fwww%2Fshell.php++some%22%40email.com&message=%3C%3Fphp+phpinfo()%3B+%3F%3E",
+        );
+        assert!(message(&req).unwrap().injected);
+
+        assert_eq!(None, message(&stub_request("/mailer.php", "submit=Send")));
+        assert_eq!(
+            None,
+            message(&stub_request("/contact.php", "to=a%40victim.example"))
+        );
+    }
+
+    #[test]
+    fn test_sent() {
+        let message = Message {
+            recipients: vec!["a@victim.example".to_string()],
+            body: "<script>".to_string(),
+            ..Default::default()
+        };
+        let page = sent("/cgi-bin/formmail.pl", &message);
+        assert!(page.contains("submitted to a@victim.example"));
+        assert!(page.contains("&lt;script&gt;"));
+        assert_eq!("Message has been sent", sent("/mailer.php", &message));
+    }
+}
@@ -1 +1,33 @@
+pub mod api;
+pub mod appserver;
+pub mod bait;
+pub mod c2;
+pub mod dbadmin;
+pub mod deserialize;
+pub mod favicon;
+pub mod forge;
+pub mod frameworks;
+pub mod honeytoken;
+pub mod iot;
+pub mod ipp;
+pub mod mailer;
+pub mod monitoring;
+pub mod nas;
+pub mod panel;
 pub mod php;
+pub mod plugin;
+pub mod redirect;
+pub mod route;
+pub mod s3;
+pub mod script;
+pub mod search;
+pub mod shell;
+pub mod soap;
+pub mod ssrf;
+pub mod tarpit;
+pub mod template;
+pub mod upload;
+pub mod upnp;
+pub mod vpn;
+pub mod webmail;
+pub mod xmlrpc;
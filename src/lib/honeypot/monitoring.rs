@@ -0,0 +1,876 @@
+//! Fake observability stacks: a Grafana login and API, an exposed
+//! Prometheus UI and query API, and Zabbix and Nagios frontends. Exposed
+//! monitoring is heavily hunted for the credentials and internal topology
+//! it leaks, and for the agents on every host it can run commands through,
+//! so the logins, queries, and API calls sent to them are logged.
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+
+use crate::{
+    honeypot::php::Php,
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+};
+
+/// vulnerable to plugin path traversal, CVE-2021-43798
+const GRAFANA_VERSION: &str = "8.3.0";
+const GRAFANA_COMMIT: &str = "914fcedb72";
+const PROMETHEUS_VERSION: &str = "2.31.1";
+const ZABBIX_VERSION: &str = "5.0.17";
+const NAGIOS_XI_VERSION: &str = "5.8.4";
+
+/// jobs and instances the fake Prometheus scrapes, leaked by its API.
+const TARGETS: &[(&str, &str)] = &[
+    ("prometheus", "localhost:9090"),
+    ("node", "db-01.internal:9100"),
+    ("node", "web-01.internal:9100"),
+    ("node", "web-02.internal:9100"),
+    ("mysql", "db-01.internal:9104"),
+    ("redis", "cache-01.internal:9121"),
+];
+const METRIC_NAMES: &[&str] = &[
+    "up",
+    "node_cpu_seconds_total",
+    "node_filesystem_avail_bytes",
+    "node_memory_MemAvailable_bytes",
+    "mysql_global_status_connections",
+    "redis_connected_clients",
+    "prometheus_build_info",
+];
+
+const FAKE_PASSWD: &str = "root:x:0:0:root:/root:/bin/bash\n\
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin\n\
+bin:x:2:2:bin:/bin:/usr/sbin/nologin\n\
+sys:x:3:3:sys:/dev:/usr/sbin/nologin\n\
+www-data:x:33:33:www-data:/var/www:/usr/sbin/nologin\n\
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin\n\
+grafana:x:472:0::/home/grafana:/sbin/nologin\n";
+
+/// A response from a fake service, before it's bound to a connection.
+#[derive(Debug)]
+struct Reply {
+    status: StatusCode,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    // the PHP of a PHP frontend, which exposes it
+    php: Option<Php>,
+    body: String,
+}
+
+impl Reply {
+    fn json(status: StatusCode, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            headers: vec![],
+            php: None,
+            body: body.to_string(),
+        }
+    }
+
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "text/html; charset=UTF-8",
+            headers: vec![],
+            php: None,
+            body,
+        }
+    }
+
+    fn header(mut self, name: &'static str, value: &str) -> Self {
+        self.headers.push((name, value.to_string()));
+        self
+    }
+
+    fn php(self, php: &Php) -> Self {
+        Self {
+            php: Some(php.clone()),
+            ..self
+        }
+    }
+
+    fn send(self, next: Next) -> Result<Response> {
+        let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+        builder
+            .status_code(self.status)
+            .add_header("Content-Type", self.content_type);
+        if let Some(php) = &self.php {
+            php.expose(&mut builder);
+        }
+        for (name, value) in self.headers {
+            builder.add_header(name, value);
+        }
+        Ok(builder.body(self.body).build()?)
+    }
+}
+
+/// the value of a query or urlencoded form field.
+fn param(req: &Request, name: &str) -> Option<String> {
+    req.url
+        .query_pairs()
+        .chain(url::form_urlencoded::parse(&req.body))
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// Middleware which serves a fake Grafana.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Grafana;
+
+impl Grafana {
+    fn respond(&self, req: &Request) -> Option<Reply> {
+        let path = req.url.path();
+
+        // the url's dot segments are already resolved, so look at the target
+        let target = req.target.replace("%2e", ".").replace("%2E", ".");
+        if target.starts_with("/public/plugins/") && target.contains("..") {
+            info!(
+                "{: <8} tried Grafana plugin traversal to {:?}",
+                req.requester(),
+                req.target
+            );
+            let body = if target.ends_with("/etc/passwd") {
+                FAKE_PASSWD.to_string()
+            } else {
+                String::new()
+            };
+            return Some(Reply {
+                status: StatusCode::Ok,
+                content_type: "text/plain; charset=utf-8",
+                headers: vec![],
+                php: None,
+                body,
+            });
+        }
+
+        Some(match (&req.method, path) {
+            (Method::GET, "/api/health") => Reply::json(
+                StatusCode::Ok,
+                json!({
+                    "commit": GRAFANA_COMMIT,
+                    "database": "ok",
+                    "version": GRAFANA_VERSION,
+                }),
+            ),
+            (Method::GET, "/login") => Reply::html(grafana_login_page()),
+            (Method::POST, "/login") => {
+                let user = serde_json::from_slice::<Value>(&req.body)
+                    .ok()
+                    .and_then(|v| v["user"].as_str().map(String::from));
+                info!(
+                    "{: <8} tried to log into Grafana as {:?}",
+                    req.requester(),
+                    user.unwrap_or_default()
+                );
+                Reply::json(
+                    StatusCode::Unauthorized,
+                    json!({"message": "Invalid username or password"}),
+                )
+            }
+            (Method::GET, "/api/frontend/settings") => Reply::json(
+                StatusCode::Ok,
+                json!({
+                    "authProxyEnabled": false,
+                    "ldapEnabled": false,
+                    "anonymousEnabled": false,
+                    "buildInfo": {
+                        "version": GRAFANA_VERSION,
+                        "commit": GRAFANA_COMMIT,
+                        "edition": "Open Source",
+                        "env": "production",
+                    },
+                }),
+            ),
+            (_, path) if is_grafana_api(path) => {
+                info!(
+                    "{: <8} probed the Grafana API {} {}",
+                    req.requester(),
+                    req.method.to_string(),
+                    path
+                );
+                Reply::json(StatusCode::Unauthorized, json!({"message": "Unauthorized"}))
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// API paths which require a session, such as datasources and their proxy.
+fn is_grafana_api(path: &str) -> bool {
+    [
+        "/api/datasources",
+        "/api/users",
+        "/api/org",
+        "/api/admin",
+        "/api/snapshots",
+        "/api/dashboards",
+        "/api/search",
+        "/api/plugins",
+    ]
+    .iter()
+    .any(|p| path == *p || path.starts_with(&format!("{}/", p)))
+}
+
+impl Middleware for Grafana {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.respond(req) {
+                Some(reply) => reply.send(next),
+                None => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("grafana")
+    }
+}
+
+fn grafana_login_page() -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width">
+<title>Grafana</title>
+<base href="/" />
+<link rel="icon" type="image/png" href="public/img/fav32.png">
+<link rel="stylesheet" href="public/build/grafana.dark.{commit}.css">
+</head>
+<body class="theme-dark app-grafana">
+<div id="reactRoot"></div>
+<script nonce="">
+window.grafanaBootData = {{
+  user: {{"isSignedIn":false,"id":0,"login":"","orgId":1,"orgRole":""}},
+  settings: {{"appSubUrl":"","buildInfo":{{"version":"{version}","commit":"{commit}","edition":"Open Source"}},"disableLoginForm":false}},
+  navTree: []
+}};
+</script>
+<script nonce="" src="public/build/runtime.{commit}.js" type="text/javascript"></script>
+<script nonce="" src="public/build/app.{commit}.js" type="text/javascript"></script>
+</body>
+</html>
+"#,
+        version = GRAFANA_VERSION,
+        commit = GRAFANA_COMMIT,
+    )
+}
+
+/// Middleware which serves a fake Prometheus.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Prometheus;
+
+impl Prometheus {
+    fn respond(&self, req: &Request) -> Option<Reply> {
+        let success =
+            |data: Value| Reply::json(StatusCode::Ok, json!({"status": "success", "data": data}));
+
+        Some(match req.url.path() {
+            "/graph" => Reply::html(prometheus_graph_page()),
+            "/api/v1/query" | "/api/v1/query_range" => {
+                let query = param(req, "query").unwrap_or_default();
+                info!("{: <8} queried Prometheus for {:?}", req.requester(), query);
+                if query.trim().is_empty() {
+                    return Some(Reply::json(
+                        StatusCode::BadRequest,
+                        json!({
+                            "status": "error",
+                            "errorType": "bad_data",
+                            "error": "invalid parameter \"query\": 1:1: parse error: no expression found in input",
+                        }),
+                    ));
+                }
+                success(query_result(&query, req.url.path().ends_with("_range")))
+            }
+            "/api/v1/targets" => success(json!({
+                "activeTargets": TARGETS.iter().map(|(job, instance)| json!({
+                    "discoveredLabels": {"__address__": instance, "job": job},
+                    "labels": {"instance": instance, "job": job},
+                    "scrapePool": job,
+                    "scrapeUrl": format!("http://{}/metrics", instance),
+                    "lastError": "",
+                    "health": "up",
+                })).collect::<Vec<_>>(),
+                "droppedTargets": [],
+            })),
+            "/api/v1/label/__name__/values" => success(json!(METRIC_NAMES)),
+            "/api/v1/status/buildinfo" => success(json!({
+                "version": PROMETHEUS_VERSION,
+                "revision": "42d3b0d7c8b3c4f0a1b8e0d61e8d5e8a4a3a6c2c",
+                "branch": "HEAD",
+                "goVersion": "go1.17.3",
+            })),
+            "/api/v1/status/config" => success(json!({"yaml": prometheus_config()})),
+            _ => return None,
+        })
+    }
+}
+
+impl Middleware for Prometheus {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.respond(req) {
+                Some(reply) => reply.send(next),
+                None => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("prometheus")
+    }
+}
+
+/// an `up` like sample for every target, whatever was asked for.
+fn query_result(query: &str, range: bool) -> Value {
+    let name = metric_name(query).unwrap_or("up");
+    let now = chrono::Utc::now().timestamp();
+
+    let result = TARGETS
+        .iter()
+        .map(|(job, instance)| {
+            let metric = json!({"__name__": name, "instance": instance, "job": job});
+            if range {
+                let values = (0..5)
+                    .map(|i| json!([now - (4 - i) * 60, "1"]))
+                    .collect::<Vec<_>>();
+                json!({"metric": metric, "values": values})
+            } else {
+                json!({"metric": metric, "value": [now, "1"]})
+            }
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "resultType": if range { "matrix" } else { "vector" },
+        "result": result,
+    })
+}
+
+/// the first metric selected by a PromQL query, skipping functions,
+/// aggregations, and their grouping labels.
+fn metric_name(query: &str) -> Option<&str> {
+    const AGGREGATIONS: &[&str] = &[
+        "sum",
+        "min",
+        "max",
+        "avg",
+        "group",
+        "stddev",
+        "stdvar",
+        "count",
+        "count_values",
+        "bottomk",
+        "topk",
+        "quantile",
+    ];
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':';
+
+    let mut rest = query;
+    while let Some(start) = rest.find(is_ident) {
+        let end = rest[start..]
+            .find(|c: char| !is_ident(c))
+            .map_or(rest.len(), |e| start + e);
+        let token = &rest[start..end];
+        rest = &rest[end..];
+
+        if token == "by" || token == "without" {
+            rest = rest.split_once(')').map_or("", |(_, r)| r);
+            continue;
+        }
+        let is_call = rest.trim_start().starts_with('(');
+        let is_number = token.starts_with(|c: char| c.is_ascii_digit());
+        if !is_call && !is_number && !AGGREGATIONS.contains(&token) {
+            return Some(token);
+        }
+    }
+    None
+}
+
+fn prometheus_config() -> String {
+    let mut config =
+        "global:\n  scrape_interval: 15s\n  evaluation_interval: 15s\nscrape_configs:\n"
+            .to_string();
+    let mut jobs: Vec<&str> = TARGETS.iter().map(|(job, _)| *job).collect();
+    jobs.dedup();
+    for job in jobs {
+        config += &format!("- job_name: {}\n  static_configs:\n  - targets:\n", job);
+        for (_, instance) in TARGETS.iter().filter(|(j, _)| *j == job) {
+            config += &format!("    - {}\n", instance);
+        }
+    }
+    config
+}
+
+fn prometheus_graph_page() -> String {
+    format!(
+        r##"<!doctype html><html lang="en"><head><meta charset="utf-8"/>
+<link rel="shortcut icon" href="./favicon.ico"/>
+<meta name="viewport" content="width=device-width,initial-scale=1,shrink-to-fit=no"/>
+<meta name="theme-color" content="#000000"/>
+<script>const GLOBAL_CONSOLES_LINK="",GLOBAL_AGENT_MODE="false",GLOBAL_READY="true"</script>
+<link rel="manifest" href="./manifest.json" crossorigin="use-credentials"/>
+<title>Prometheus Time Series Collection and Processing Server</title>
+<link href="./static/css/main.{version}.chunk.css" rel="stylesheet">
+</head><body class="bootstrap"><noscript>You need to enable JavaScript to run this app.</noscript>
+<div id="root"></div>
+<script src="./static/js/main.{version}.chunk.js"></script></body></html>
+"##,
+        version = PROMETHEUS_VERSION,
+    )
+}
+
+/// Middleware which serves a fake Zabbix frontend and its JSON-RPC API
+/// under `/zabbix`.
+#[derive(Debug, Clone, Default)]
+pub struct Zabbix {
+    php: Php,
+}
+
+impl Zabbix {
+    pub fn new(php: Php) -> Self {
+        Self { php }
+    }
+
+    fn respond(&self, req: &Request) -> Option<Reply> {
+        match req.url.path() {
+            "/zabbix" | "/zabbix/" | "/zabbix/index.php" => {
+                if req.method != Method::POST {
+                    return Some(Reply::html(zabbix_login_page(None)));
+                }
+                info!(
+                    "{: <8} tried to log into Zabbix as {:?} with {:?}",
+                    req.requester(),
+                    param(req, "name").unwrap_or_default(),
+                    param(req, "password").unwrap_or_default()
+                );
+                Some(Reply::html(zabbix_login_page(Some(
+                    "Login name or password is incorrect.",
+                ))))
+            }
+            "/zabbix/api_jsonrpc.php" => Some(zabbix_api(req)),
+            _ => None,
+        }
+    }
+}
+
+impl Middleware for Zabbix {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.respond(req) {
+                Some(reply) => reply.php(&self.php).send(next),
+                None => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("zabbix")
+    }
+}
+
+/// answers a JSON-RPC request, or a batch of them, to the Zabbix API. Only
+/// the version is public; logins fail and everything else needs one.
+fn zabbix_api(req: &Request) -> Reply {
+    let body = match serde_json::from_slice::<Value>(&req.body) {
+        Ok(body) => body,
+        Err(_) => {
+            debug!("{: <8} sent unparsable Zabbix API JSON", req.requester());
+            return Reply::json(
+                StatusCode::Ok,
+                json!({
+                    "jsonrpc": "2.0",
+                    "error": {
+                        "code": -32700,
+                        "message": "Parse error",
+                        "data": "Invalid JSON. An error occurred on the server while parsing the JSON text.",
+                    },
+                    "id": null,
+                }),
+            );
+        }
+    };
+
+    let call = |call: &Value| {
+        let method = call["method"].as_str().unwrap_or_default();
+        let params = &call["params"];
+        let error = |data: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "error": {"code": -32602, "message": "Invalid params.", "data": data},
+                "id": call["id"],
+            })
+        };
+        match method {
+            "apiinfo.version" => {
+                info!("{: <8} fingerprinted the Zabbix API", req.requester());
+                json!({"jsonrpc": "2.0", "result": ZABBIX_VERSION, "id": call["id"]})
+            }
+            "user.login" => {
+                // "user" was renamed to "username" in 5.4
+                let user = params["user"].as_str().or(params["username"].as_str());
+                info!(
+                    "{: <8} tried to log into the Zabbix API as {:?} with {:?}",
+                    req.requester(),
+                    user.unwrap_or_default(),
+                    params["password"].as_str().unwrap_or_default()
+                );
+                error("Login name or password is incorrect.")
+            }
+            _ => {
+                info!(
+                    "{: <8} called the Zabbix API {} with {} as {:?}",
+                    req.requester(),
+                    method,
+                    params,
+                    call["auth"].as_str().unwrap_or_default()
+                );
+                error("Not authorised.")
+            }
+        }
+    };
+
+    Reply::json(
+        StatusCode::Ok,
+        match &body {
+            Value::Array(batch) => Value::Array(batch.iter().map(call).collect()),
+            _ => call(&body),
+        },
+    )
+}
+
+fn zabbix_login_page(error: Option<&str>) -> String {
+    let error = error
+        .map(|e| {
+            format!(
+                r#"<output class="msg-bad msg-global" role="contentinfo">{}</output>"#,
+                e
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="X-UA-Compatible" content="IE=Edge"/>
+<meta charset="utf-8" />
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="Author" content="Zabbix SIA" />
+<title>Zabbix</title>
+<link rel="icon" href="favicon.ico">
+<link rel="stylesheet" type="text/css" href="assets/styles/blue-theme.css?{version}" />
+</head>
+<body>
+<div class="wrapper">
+<main>
+<div class="signin-container">
+<div class="signin-logo"></div>
+<form method="post" action="index.php" accept-charset="utf-8">
+<ul>
+<li><label for="name">Username</label><input type="text" id="name" name="name" value="" maxlength="255" autofocus="autofocus"></li>
+<li><label for="password">Password</label><input type="password" id="password" name="password" value="" maxlength="255">{error}</li>
+<li><input type="checkbox" id="autologin" name="autologin" value="1" checked="checked"><label for="autologin">Remember me for 30 days</label></li>
+<li><button type="submit" id="enter" name="enter" value="Sign in">Sign in</button></li>
+</ul>
+</form>
+</div>
+<div class="signin-links"><a target="_blank" class="grey link-alt" href="https://www.zabbix.com/documentation/5.0/">Help</a>&nbsp;&nbsp;&bull;&nbsp;&nbsp;<a target="_blank" class="grey link-alt" href="https://www.zabbix.com/support">Support</a></div>
+</main>
+<footer role="contentinfo">Zabbix {version}. &copy; 2001&ndash;2021, <a class="grey link-alt" target="_blank" href="https://www.zabbix.com/">Zabbix SIA</a></footer>
+</div>
+</body>
+</html>
+"#,
+        version = ZABBIX_VERSION,
+        error = error,
+    )
+}
+
+/// Middleware which serves a fake Nagios Core behind its basic auth at
+/// `/nagios`, and a Nagios XI login at `/nagiosxi`.
+#[derive(Debug, Clone, Default)]
+pub struct Nagios {
+    php: Php,
+}
+
+impl Nagios {
+    pub fn new(php: Php) -> Self {
+        Self { php }
+    }
+
+    fn respond(&self, req: &Request) -> Option<Reply> {
+        let path = req.url.path();
+        if path == "/nagios" || path.starts_with("/nagios/") {
+            match req.basic_auth() {
+                Some((user, pass)) => info!(
+                    "{: <8} tried to log into Nagios at {} as {:?} with {:?}",
+                    req.requester(),
+                    path,
+                    user,
+                    pass
+                ),
+                None => debug!("{: <8} requested Nagios at {}", req.requester(), path),
+            }
+            return Some(
+                Reply {
+                    status: StatusCode::Unauthorized,
+                    ..Reply::html(nagios_unauthorized_page())
+                }
+                .header("WWW-Authenticate", "Basic realm=\"Nagios Access\""),
+            );
+        }
+
+        // Nagios XI is a PHP frontend, where Nagios Core's are CGIs
+        match path {
+            "/nagiosxi" | "/nagiosxi/" | "/nagiosxi/login.php" if req.method != Method::POST => {
+                Some(Reply::html(nagios_xi_login_page(None)))
+            }
+            "/nagiosxi" | "/nagiosxi/" | "/nagiosxi/login.php" => {
+                info!(
+                    "{: <8} tried to log into Nagios XI as {:?} with {:?}",
+                    req.requester(),
+                    param(req, "username").unwrap_or_default(),
+                    param(req, "password").unwrap_or_default()
+                );
+                Some(Reply::html(nagios_xi_login_page(Some(
+                    "Invalid username or password.",
+                ))))
+            }
+            path if path.starts_with("/nagiosxi/api/") => {
+                info!(
+                    "{: <8} called the Nagios XI API {} with key {:?}",
+                    req.requester(),
+                    path,
+                    param(req, "apikey").unwrap_or_default()
+                );
+                Some(Reply::json(
+                    StatusCode::Ok,
+                    json!({"error": "Invalid API Key"}),
+                ))
+            }
+            _ => None,
+        }
+        .map(|reply| reply.php(&self.php))
+    }
+}
+
+impl Middleware for Nagios {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.respond(req) {
+                Some(reply) => reply.send(next),
+                None => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("nagios")
+    }
+}
+
+// Apache's, as Nagios Core's htpasswd protection sends it
+fn nagios_unauthorized_page() -> String {
+    "<!DOCTYPE HTML PUBLIC \"-//IETF//DTD HTML 2.0//EN\">\n\
+<html><head>\n\
+<title>401 Unauthorized</title>\n\
+</head><body>\n\
+<h1>Unauthorized</h1>\n\
+<p>This server could not verify that you\n\
+are authorized to access the document\n\
+requested.  Either you supplied the wrong\n\
+credentials (e.g., bad password), or your\n\
+browser doesn't understand how to supply\n\
+the credentials required.</p>\n\
+</body></html>\n"
+        .to_string()
+}
+
+fn nagios_xi_login_page(error: Option<&str>) -> String {
+    let error = error
+        .map(|e| {
+            format!(
+                r#"<div class="message"><ul class="errorMessage"><li>{}</li></ul></div>"#,
+                e
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<title>Login &middot; Nagios XI</title>
+<link rel="icon" type="image/png" href="/nagiosxi/images/favicon-32x32.png">
+<link rel="stylesheet" href="/nagiosxi/includes/css/nagiosxi.css?{version}" type="text/css">
+<script type="text/javascript" src="/nagiosxi/includes/js/jquery/jquery-3.5.1.min.js?{version}"></script>
+</head>
+<body>
+<div id="loginscreen">
+<h2>Login</h2>
+{error}
+<form method="post" action="/nagiosxi/login.php">
+<input type="hidden" name="nsp" value="">
+<input type="hidden" name="page" value="auth">
+<input type="hidden" name="pageopt" value="login">
+<label for="usernameBox">Username</label><input type="text" id="usernameBox" name="username" class="form-control">
+<label for="passwordBox">Password</label><input type="password" id="passwordBox" name="password" class="form-control">
+<button type="submit" id="loginButton" name="loginButton" class="btn btn-sm btn-primary">Login</button>
+</form>
+</div>
+<div id="footer">Nagios XI {version} &bull; Copyright &copy; 2008-2021 <a href="https://www.nagios.com/" target="_blank">Nagios Enterprises, LLC</a></div>
+</body>
+</html>
+"#,
+        version = NAGIOS_XI_VERSION,
+        error = error,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stub_request(method: Method, path: &str) -> Request {
+        Request::stub(method, path, "")
+    }
+
+    fn json_body(reply: Reply) -> Value {
+        serde_json::from_str(&reply.body).unwrap()
+    }
+
+    #[test]
+    fn test_grafana() {
+        let reply = Grafana
+            .respond(&stub_request(Method::GET, "/api/health"))
+            .unwrap();
+        assert_eq!(GRAFANA_VERSION, json_body(reply)["version"]);
+
+        let reply = Grafana
+            .respond(&stub_request(Method::GET, "/api/datasources/proxy/1/"))
+            .unwrap();
+        assert_eq!(StatusCode::Unauthorized, reply.status);
+
+        let reply = Grafana
+            .respond(&stub_request(
+                Method::GET,
+                "/public/plugins/alertlist/../../../../../../../../etc/passwd",
+            ))
+            .unwrap();
+        assert!(reply.body.starts_with("root:x:0:0"));
+
+        assert!(Grafana
+            .respond(&stub_request(Method::GET, "/api/v1/users"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_prometheus() {
+        let reply = Prometheus
+            .respond(&stub_request(
+                Method::GET,
+                "/api/v1/query?query=rate(node_cpu_seconds_total%5B5m%5D)",
+            ))
+            .unwrap();
+        let body = json_body(reply);
+        assert_eq!("vector", body["data"]["resultType"]);
+        assert_eq!(
+            "node_cpu_seconds_total",
+            body["data"]["result"][1]["metric"]["__name__"]
+        );
+
+        let mut req = stub_request(Method::POST, "/api/v1/query_range");
+        req.body = b"query=up&start=0&end=300&step=60".to_vec();
+        let body = json_body(Prometheus.respond(&req).unwrap());
+        assert_eq!("matrix", body["data"]["resultType"]);
+
+        let reply = Prometheus
+            .respond(&stub_request(Method::GET, "/api/v1/query"))
+            .unwrap();
+        assert_eq!(StatusCode::BadRequest, reply.status);
+
+        assert!(prometheus_config().contains("- job_name: node\n"));
+
+        let cases = vec![
+            ("up", Some("up")),
+            (
+                "sum by (instance) (rate(http_requests_total[5m]))",
+                Some("http_requests_total"),
+            ),
+            (
+                "count without (job) (redis_up{instance=\"a\"})",
+                Some("redis_up"),
+            ),
+            ("1 + 1", None),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(expected, metric_name(query), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_zabbix() {
+        let reply = Zabbix::default()
+            .respond(&stub_request(Method::GET, "/zabbix/index.php"))
+            .unwrap();
+        assert!(reply.body.contains(&format!("Zabbix {}", ZABBIX_VERSION)));
+
+        let mut req = stub_request(Method::POST, "/zabbix/api_jsonrpc.php");
+        req.body = br#"[
+            {"jsonrpc":"2.0","method":"apiinfo.version","params":{},"id":1},
+            {"jsonrpc":"2.0","method":"user.login","params":{"user":"Admin","password":"zabbix"},"id":2},
+            {"jsonrpc":"2.0","method":"host.get","params":{},"auth":"x","id":3}
+        ]"#
+        .to_vec();
+        let body = json_body(Zabbix::default().respond(&req).unwrap());
+        assert_eq!(ZABBIX_VERSION, body[0]["result"]);
+        assert_eq!(2, body[1]["id"]);
+        assert_eq!(-32602, body[1]["error"]["code"]);
+        assert_eq!("Not authorised.", body[2]["error"]["data"]);
+
+        req.body = b"{".to_vec();
+        let body = json_body(Zabbix::default().respond(&req).unwrap());
+        assert_eq!(-32700, body["error"]["code"]);
+
+        assert!(Zabbix::default()
+            .respond(&stub_request(Method::GET, "/index.php"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_nagios() {
+        let reply = Nagios::default()
+            .respond(&stub_request(Method::GET, "/nagios/cgi-bin/status.cgi"))
+            .unwrap();
+        assert_eq!(StatusCode::Unauthorized, reply.status);
+        assert_eq!(
+            vec![(
+                "WWW-Authenticate",
+                "Basic realm=\"Nagios Access\"".to_string()
+            )],
+            reply.headers
+        );
+        assert!(reply.php.is_none());
+
+        let mut req = stub_request(Method::POST, "/nagiosxi/login.php");
+        req.body = b"username=nagiosadmin&password=nagiosadmin".to_vec();
+        let reply = Nagios::default().respond(&req).unwrap();
+        assert!(reply.body.contains("Invalid username or password."));
+        assert!(Nagios::default()
+            .respond(&stub_request(Method::GET, "/nagiosxi/"))
+            .unwrap()
+            .php
+            .is_some());
+
+        assert!(Nagios::default()
+            .respond(&stub_request(Method::GET, "/nagiosql"))
+            .is_none());
+    }
+}
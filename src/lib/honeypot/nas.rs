@@ -0,0 +1,228 @@
+//! NAS web UIs for the Synology and QNAP devices of `honeypot::iot`, which
+//! ransomware campaigns such as Qlocker and DeadBolt scan for. Both log in
+//! through CGI APIs, whose credentials are logged, and QNAP's login API
+//! leaks its firmware version as it does on the real devices. Requests to
+//! the endpoints of well known NAS exploits are named by their CVE, see
+//! `cve`.
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    honeypot::iot::Device,
+    http::{query::Query, request::Request},
+};
+
+/// the DSM version the Synology claims
+pub const DSM_VERSION: &str = "6.2.3-25426";
+/// the QTS version and build the QNAP claims
+pub const QTS_VERSION: (&str, &str) = ("4.3.6", "20190610");
+
+/// exploited endpoints, lowercased, and the CVE each is exploited by
+const CVES: &[(&str, &str)] = &[
+    // Synology DSM
+    ("/webman/imageselector.cgi", "CVE-2013-6955"),
+    ("/webman/forget_passwd.cgi", "CVE-2017-9554"),
+    // QNAP Photo Station, chained by the pre-auth RCE of 2019
+    ("/photo/p/api/album.php", "CVE-2019-7192"),
+    ("/photo/p/api/video.php", "CVE-2019-7195"),
+    // QNAP QTS
+    ("/cgi-bin/quick/quick.cgi", "CVE-2023-47218"),
+];
+
+/// the CVE a request's endpoint is exploited by, if it's a known NAS
+/// exploit's.
+pub fn cve(req: &Request) -> Option<&'static str> {
+    let path = req.url.path().to_lowercase();
+    if let Some((_, cve)) = CVES.iter().find(|(p, _)| *p == path) {
+        return Some(cve);
+    }
+    // share.cgi is a legitimate sharing link; its stack overflow is in
+    // get_file_size
+    let overflow = path == "/cgi-bin/filemanager/share.cgi"
+        && req.query().get("func") == Some("get_file_size");
+    overflow.then_some("CVE-2024-27130")
+}
+
+/// the username and password of a login through the NAS's API, if the
+/// request is one.
+pub fn credentials(device: Device, req: &Request) -> Option<(String, String)> {
+    let path = req.url.path().to_lowercase();
+    let query = Query::parse(req.url.query().unwrap_or_default());
+    let form = Query::parse(&String::from_utf8_lossy(&req.body));
+    let field = |name: &str| {
+        form.get(name)
+            .or_else(|| query.get(name))
+            .map(str::to_string)
+    };
+
+    match device {
+        Device::Qnap if path == "/cgi-bin/authlogin.cgi" => {
+            let user = field("user")?;
+            // QTS base64 encodes the password, as "ezEncode"
+            let pwd = field("pwd").unwrap_or_default();
+            let pass = STANDARD
+                .decode(&pwd)
+                .ok()
+                .and_then(|p| String::from_utf8(p).ok())
+                .unwrap_or(pwd);
+            Some((user, pass))
+        }
+        Device::Synology if path == "/webapi/auth.cgi" || path == "/webapi/entry.cgi" => {
+            Some((field("account")?, field("passwd").unwrap_or_default()))
+        }
+        _ => None,
+    }
+}
+
+/// the content type and body of an API response, if the request is to
+/// the NAS's API rather than for its login page.
+pub fn api(device: Device, req: &Request) -> Option<(&'static str, String)> {
+    let path = req.url.path().to_lowercase();
+    match device {
+        Device::Qnap if path == "/cgi-bin/authlogin.cgi" => Some(("text/xml", qts_auth())),
+        // 400 is a wrong account or password
+        Device::Synology if path.starts_with("/webapi/") => Some((
+            "application/json; charset=\"UTF-8\"",
+            r#"{"error":{"code":400},"success":false}"#.to_string(),
+        )),
+        _ => None,
+    }
+}
+
+/// the NAS's login page, the Synology's for other devices.
+pub fn login(device: Device) -> String {
+    match device {
+        Device::Qnap => format!(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>NAS5E2A1B</title>
+<link rel="stylesheet" type="text/css" href="/cgi-bin/css/login.css?{build}">
+<script type="text/javascript" src="/cgi-bin/js/ezEncode.js?{build}"></script>
+</head>
+<body>
+<div id="loginBox">
+<div class="title">QTS <span class="version">{version}</span></div>
+<form id="loginForm" method="post" action="/cgi-bin/authLogin.cgi">
+<input type="text" id="username" name="user" placeholder="Username" autocomplete="off">
+<input type="password" id="pwd" name="pwd" placeholder="Password">
+<input type="checkbox" id="remember_me" name="remme" value="1"><label for="remember_me">Remember me</label>
+<button type="submit" id="submit">Login</button>
+</form>
+</div>
+<div class="footer">&copy; QNAP Systems, Inc. All Rights Reserved.</div>
+</body>
+</html>
+"#,
+            version = QTS_VERSION.0,
+            build = QTS_VERSION.1,
+        ),
+        _ => format!(
+            r#"<!DOCTYPE html>
+<html class="img-no-doc">
+<head>
+<meta http-equiv="X-UA-Compatible" content="IE=edge">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<meta name="description" content="DiskStation provides a full-featured network attached storage (NAS) solution to help you manage, backup and share data among Windows, Mac and Linux easily.">
+<title>DiskStation&nbsp;-&nbsp;Synology&nbsp;DiskStation</title>
+<link rel="stylesheet" type="text/css" href="/scripts/ext-3/resources/css/ext-all.css?v={build}">
+<link rel="stylesheet" type="text/css" href="/webman/resources/css/desktop.css?v={build}">
+<script type="text/javascript" src="/webman/synodefs.cgi"></script>
+<script type="text/javascript" src="/webman/sds.js?v={build}"></script>
+</head>
+<body>
+<form id="login-form" method="post" action="/webapi/auth.cgi">
+<input type="hidden" name="api" value="SYNO.API.Auth">
+<input type="hidden" name="version" value="6">
+<input type="hidden" name="method" value="login">
+<input type="text" name="account" placeholder="Username" autocomplete="username">
+<input type="password" name="passwd" placeholder="Password" autocomplete="current-password">
+<button type="submit" id="login-btn">Sign In</button>
+</form>
+</body>
+</html>
+"#,
+            build = DSM_VERSION.rsplit('-').next().unwrap_or_default(),
+        ),
+    }
+}
+
+// authLogin.cgi's answer, without credentials or with wrong ones; scanners
+// read the firmware from it
+fn qts_auth() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" ?>
+<QDocRoot version="1.0">
+<doQuick><![CDATA[]]></doQuick>
+<is_booting><![CDATA[0]]></is_booting>
+<mediaReady><![CDATA[1]]></mediaReady>
+<SMBFW><![CDATA[0]]></SMBFW>
+<authPassed><![CDATA[0]]></authPassed>
+<model>
+<modelName><![CDATA[TS-X51]]></modelName>
+<internalModelName><![CDATA[TS-X51]]></internalModelName>
+<platform><![CDATA[TS-NASX86]]></platform>
+<displayModelName><![CDATA[TS-451]]></displayModelName>
+</model>
+<firmware>
+<version><![CDATA[{}]]></version>
+<number><![CDATA[1013]]></number>
+<build><![CDATA[{}]]></build>
+</firmware>
+<hostname><![CDATA[NAS5E2A1B]]></hostname>
+<customLogo>
+<customFrontLogo><![CDATA[]]></customFrontLogo>
+<customLoginLogo><![CDATA[]]></customLoginLogo>
+</customLogo>
+</QDocRoot>
+"#,
+        QTS_VERSION.0, QTS_VERSION.1
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(target: &str, body: &str) -> Request {
+        Request::stub(Method::POST, target, body)
+    }
+
+    #[test]
+    fn test_cve() {
+        let cases = vec![
+            ("/photo/p/api/album.php", Some("CVE-2019-7192")),
+            ("/webman/imageSelector.cgi", Some("CVE-2013-6955")),
+            (
+                "/cgi-bin/filemanager/share.cgi?ssid=x&func=get_file_size&total=1",
+                Some("CVE-2024-27130"),
+            ),
+            ("/cgi-bin/filemanager/share.cgi?ssid=x", None),
+            ("/cgi-bin/authLogin.cgi", None),
+        ];
+        for (target, expected) in cases {
+            assert_eq!(expected, cve(&stub_request(target, "")), "{}", target);
+        }
+    }
+
+    #[test]
+    fn test_credentials() {
+        let req = stub_request("/cgi-bin/authLogin.cgi", "user=admin&pwd=YWRtaW4xMjM%3D");
+        assert_eq!(
+            Some(("admin".to_string(), "admin123".to_string())),
+            credentials(Device::Qnap, &req)
+        );
+        assert_eq!(None, credentials(Device::Synology, &req));
+
+        let req = stub_request(
+            "/webapi/auth.cgi?api=SYNO.API.Auth&method=login&account=admin&passwd=synology",
+            "",
+        );
+        assert_eq!(
+            Some(("admin".to_string(), "synology".to_string())),
+            credentials(Device::Synology, &req)
+        );
+    }
+}
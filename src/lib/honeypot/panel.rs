@@ -0,0 +1,295 @@
+//! Hosting control panel login portals. A panel takes over every path of
+//! the listener it's configured on, as the real ones do on their own
+//! ports: cPanel on 2082/2083, Webmin on 10000, and Plesk on 8443.
+//! Submitted credentials are logged and always rejected.
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Panel {
+    Cpanel,
+    Webmin,
+    Plesk,
+}
+
+/// A panel page, before it's bound to a connection.
+#[derive(Debug)]
+struct Page {
+    status: StatusCode,
+    content_type: &'static str,
+    location: Option<&'static str>,
+    body: String,
+}
+
+impl Page {
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "text/html; charset=UTF-8",
+            location: None,
+            body,
+        }
+    }
+
+    fn redirect(location: &'static str) -> Self {
+        Self {
+            status: StatusCode::Found,
+            content_type: "text/html; charset=UTF-8",
+            location: Some(location),
+            body: String::new(),
+        }
+    }
+}
+
+impl Panel {
+    pub fn server(&self) -> &'static str {
+        match self {
+            Panel::Cpanel => "cpsrvd/11.96.0.11",
+            Panel::Webmin => "MiniServ/1.890",
+            Panel::Plesk => "sw-cp-server",
+        }
+    }
+
+    /// the form fields the panel's login posts.
+    fn credential_fields(&self) -> (&'static str, &'static str) {
+        match self {
+            Panel::Cpanel | Panel::Webmin => ("user", "pass"),
+            Panel::Plesk => ("login_name", "passwd"),
+        }
+    }
+
+    fn respond(&self, req: &Request) -> Page {
+        if req.method == Method::POST {
+            let (user_field, pass_field) = self.credential_fields();
+            let field = |name| {
+                url::form_urlencoded::parse(&req.body)
+                    .find(|(k, _)| k == name)
+                    .map(|(_, v)| v.into_owned())
+            };
+            if let Some(user) = field(user_field) {
+                info!(
+                    "{: <8} tried to log into {:?} as {:?} with {:?}",
+                    req.requester(),
+                    self,
+                    user,
+                    field(pass_field).unwrap_or_default()
+                );
+                return self.login_failed(req);
+            }
+        }
+
+        match self {
+            Panel::Cpanel if req.url.path().starts_with("/webmail") => {
+                Page::html(cpanel_login("Webmail", None))
+            }
+            Panel::Cpanel => Page::html(cpanel_login("cPanel", None)),
+            Panel::Webmin if req.url.path() == "/" => Page::redirect("/session_login.cgi"),
+            Panel::Webmin => Page::html(webmin_login(None)),
+            Panel::Plesk if req.url.path() == "/" => Page::redirect("/login_up.php"),
+            Panel::Plesk => Page::html(plesk_login(None)),
+        }
+    }
+
+    fn login_failed(&self, req: &Request) -> Page {
+        match self {
+            // the login form posts with fetch and expects JSON
+            Panel::Cpanel if req.url.query_pairs().any(|(k, _)| k == "login_only") => Page {
+                status: StatusCode::Unauthorized,
+                content_type: "application/json",
+                location: None,
+                body: json!({
+                    "status": 0,
+                    "message": "invalid_login",
+                    "redirect": null,
+                    "notices": [],
+                })
+                .to_string(),
+            },
+            Panel::Cpanel => Page {
+                status: StatusCode::Unauthorized,
+                ..Page::html(cpanel_login("cPanel", Some("The login is invalid.")))
+            },
+            Panel::Webmin => Page::html(webmin_login(Some("Login failed. Please try again."))),
+            Panel::Plesk => Page::html(plesk_login(Some("Incorrect username or password."))),
+        }
+    }
+}
+
+impl Middleware for Panel {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let page = self.respond(req);
+
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            builder
+                .status_code(page.status)
+                .set_header("Server", self.server())
+                .add_header("Content-Type", page.content_type);
+            if let Some(location) = page.location {
+                builder.add_header("Location", location);
+            }
+            if *self == Panel::Webmin {
+                builder.add_header("Set-Cookie", "testing=1; path=/; secure");
+            }
+            Ok(builder.body(page.body).build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("panel")
+    }
+}
+
+fn error_div(class: &str, error: Option<&str>) -> String {
+    error
+        .map(|e| format!("<div id=\"login-status\" class=\"{}\">{}</div>", class, e))
+        .unwrap_or_default()
+}
+
+fn cpanel_login(app: &str, error: Option<&str>) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en" dir="ltr">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1.0">
+<meta name="google" content="notranslate" />
+<title>{app} Login</title>
+<link rel="shortcut icon" href="/cPanel_magic_revision_1579116395/unprotected/cpanel/favicon.ico" />
+<link rel="stylesheet" href="/cPanel_magic_revision_1579116395/unprotected/cpanel/style_v2_optimized.css" />
+</head>
+<body class="cpanel">
+<div id="login-wrapper" class="group">
+  <div class="wrapper">
+    <div id="content-container">
+      <div id="login-container">
+        <div id="login-sub-container">
+          <div id="login-sub-header"><img class="main-logo" src="/cPanel_magic_revision_1579116395/unprotected/cpanel/images/cpanel-logo.svg" alt="logo" /></div>
+          {error}
+          <div id="login-sub">
+            <form id="login_form" action="/login/" method="post" target="_top">
+              <div class="input-req-login"><label for="user">Username</label></div>
+              <div class="input-field-login icon username-container"><input name="user" id="user" autofocus="autofocus" value="" placeholder="Enter your username." class="std_textbox" type="text" tabindex="1" required></div>
+              <div class="input-req-login login-password-field-label"><label for="pass">Password</label></div>
+              <div class="input-field-login icon password-container"><input name="pass" id="pass" placeholder="Enter your account password." class="std_textbox" type="password" tabindex="2" required></div>
+              <div class="controls"><div class="login-btn"><button name="login" type="submit" id="login_submit" tabindex="3">Log in</button></div></div>
+            </form>
+          </div>
+        </div>
+      </div>
+    </div>
+    <div id="locale-footer"><div class="copyright">Copyright&copy; 2021 cPanel, L.L.C.</div></div>
+  </div>
+</div>
+</body>
+</html>
+"#,
+        error = error_div("error-notice", error),
+    )
+}
+
+fn webmin_login(error: Option<&str>) -> String {
+    format!(
+        r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN" "http://www.w3.org/TR/html4/loose.dtd">
+<html data-background-style="gainsboro" class="session_login">
+<head>
+<meta name="robots" content="noindex, nofollow" />
+<meta http-equiv="Content-Type" content="text/html; charset=UTF-8" />
+<link rel="shortcut icon" href="/images/favicon-webmin.ico" />
+<title>Login to Webmin</title>
+</head>
+<body class="session_login">
+<div class="container session_login" data-dcontainer="1">
+{error}
+<form method="post" target="_top" action="/session_login.cgi" class="form-signin session_login clearfix" role="form">
+<i class="wbm-webmin"></i><h2 class="form-signin-heading"><span>Webmin</span></h2>
+<p class="form-signin-paragraph">You must enter a username and password to login to the server on <strong>localhost</strong></p>
+<div class="input-group form-group"><span class="input-group-addon"><i class="fa fa-fw fa-user"></i></span>
+<input type="text" class="form-control session_login" name="user" autocomplete="off" autocapitalize="none" placeholder="Username" autofocus></div>
+<div class="input-group form-group"><span class="input-group-addon"><i class="fa fa-fw fa-lock"></i></span>
+<input type="password" class="form-control session_login" name="pass" autocomplete="off" placeholder="Password"></div>
+<div class="form-group form-signin-group"><label class="checkbox"><input type="checkbox" value="1" name="save"><span>Remember me</span></label></div>
+<div class="form-group form-signin-group"><button class="btn btn-primary" type="submit"><i class="fa fa-sign-in"></i>&nbsp;&nbsp;Sign in</button></div>
+</form>
+</div>
+</body>
+</html>
+"#,
+        error = error_div("alert alert-warning", error),
+    )
+}
+
+fn plesk_login(error: Option<&str>) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en-US">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1, shrink-to-fit=no">
+<title>Plesk Obsidian 18.0.34</title>
+<link rel="shortcut icon" href="/favicon.ico">
+<link rel="stylesheet" href="/ui-library/plesk-ui-library.css?3.24.3">
+<script src="/cp/javascript/vendors.js?v=18.0.34"></script>
+</head>
+<body class="sid-login">
+<div class="login-page">
+<div class="login-page__content">
+<div class="login-page-header"><img src="/images/logos/plesk/logo.svg" alt="Plesk"></div>
+{error}
+<form method="post" action="/login_up.php" id="form-login">
+<div class="form-row"><label for="login_name">Username</label><input type="text" name="login_name" id="login_name" value="" autocomplete="username"></div>
+<div class="form-row"><label for="passwd">Password</label><input type="password" name="passwd" id="passwd" value="" autocomplete="current-password"></div>
+<div class="form-row"><select name="locale_id" id="locale_id"><option value="default">Default</option></select></div>
+<button type="submit" class="pul-button pul-button--primary">Log in</button>
+</form>
+<a href="/get_password.php">Forgot your password?</a>
+</div>
+</div>
+</body>
+</html>
+"#,
+        error = error_div("msg-box msg-error", error),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_login() {
+        let page = Panel::Cpanel.respond(&Request::stub(Method::GET, "/anything", ""));
+        assert_eq!(StatusCode::Ok, page.status);
+        assert!(page.body.contains("<title>cPanel Login</title>"));
+
+        let page = Panel::Cpanel.respond(&Request::stub(
+            Method::POST,
+            "/login/?login_only=1",
+            "user=root&pass=toor",
+        ));
+        assert_eq!(StatusCode::Unauthorized, page.status);
+        assert!(page.body.contains("invalid_login"));
+
+        let page = Panel::Webmin.respond(&Request::stub(Method::GET, "/", ""));
+        assert_eq!(Some("/session_login.cgi"), page.location);
+
+        let page = Panel::Plesk.respond(&Request::stub(
+            Method::POST,
+            "/login_up.php",
+            "login_name=admin&passwd=admin",
+        ));
+        assert!(page.body.contains("Incorrect username or password."));
+    }
+}
@@ -1,15 +1,24 @@
-use std::sync::Arc;
+//! PHP's easter eggs: its logos and credits, which PHP before 5.5 served
+//! to any script given their GUIDs, and which scanners request to learn
+//! whether and which PHP runs. The logos differ between releases, and some
+//! fingerprinting tools hash them, so operators can provide logos taken
+//! from the PHP releases their persona claims. Otherwise a stand-in is
+//! served, which only avoids a broken image.
+use std::{collections::HashMap, fmt, fs, path::PathBuf, sync::Arc};
 
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::Deserialize;
 use tokio::net::TcpStream;
 use url::Url;
 
 use crate::{
     http::{
+        query::Query,
         request::Request,
-        response::{Response, ResponseBuilder},
+        response::{BaseResponseBuilder, Response, ResponseBuilder},
     },
+    persona::Persona,
     prelude::*,
 };
 
@@ -30,8 +39,212 @@ Connection: Keep-Alive
 Content-Type: image/gif"#;
 */
 
-pub fn is_easter_egg(req: &Request) -> bool {
-    is_easter_egg_url(&req.url)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhpConfig {
+    /// the version claimed, rather than the one the persona's server
+    /// shipped with
+    pub version: Option<String>,
+    /// whether the version is sent in X-Powered-By and the easter eggs are
+    /// served, as PHP's `expose_php`
+    pub expose_php: bool,
+    /// the session cookie's name, as PHP's `session.name`
+    pub session_name: String,
+    /// logos named for the PHP version and logo, such as `5.3.10/php.gif`,
+    /// `5.3.10/zend.gif`, and `5.3.10/egg.gif`
+    pub logos: Option<PathBuf>,
+}
+
+impl Default for PhpConfig {
+    fn default() -> Self {
+        Self {
+            version: None,
+            expose_php: true,
+            session_name: "PHPSESSID".to_string(),
+            logos: None,
+        }
+    }
+}
+
+/// claimed by PHP baits behind a persona whose server ships without PHP;
+/// what CentOS 7 shipped
+const FALLBACK_VERSION: &str = "5.4.16";
+
+/// The images PHP serves as easter eggs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Logo {
+    Php,
+    Zend,
+    /// the release's joke image, which replaced the PHP logo in phpinfo on
+    /// April Fools' Day
+    Egg,
+}
+
+impl Logo {
+    const ALL: [Logo; 3] = [Logo::Php, Logo::Zend, Logo::Egg];
+
+    fn file(&self) -> &'static str {
+        match self {
+            Logo::Php => "php.gif",
+            Logo::Zend => "zend.gif",
+            Logo::Egg => "egg.gif",
+        }
+    }
+}
+
+// images by the PHP version and logo
+type Logos = HashMap<(String, Logo), Arc<Vec<u8>>>;
+
+// a transparent 1x1 GIF
+const STAND_IN: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+/// The PHP a honeypot's PHP baits claim to run.
+#[derive(Debug, Clone, Default)]
+pub struct Php {
+    persona: Persona,
+    config: PhpConfig,
+    // the operator's logos
+    logos: Arc<Logos>,
+}
+
+impl Php {
+    /// the PHP behind the persona, loading the operator's logos for every
+    /// version it can claim.
+    pub fn new(persona: Persona, config: &PhpConfig) -> Result<Self> {
+        let versions = match &config.version {
+            Some(version) => vec![version.clone()],
+            None => persona
+                .php_versions()
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+        };
+        let mut logos = HashMap::new();
+        if let Some(dir) = &config.logos {
+            for version in versions {
+                for logo in Logo::ALL {
+                    let path = dir.join(&version).join(logo.file());
+                    match fs::read(&path) {
+                        Ok(image) => {
+                            logos.insert((version.clone(), logo), Arc::new(image));
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                        Err(e) => bail!("failed to read PHP logo {}: {}", path.display(), e),
+                    }
+                }
+            }
+        }
+        Ok(Php {
+            persona,
+            config: config.clone(),
+            logos: Arc::new(logos),
+        })
+    }
+
+    // the configured version, or the persona's if its server runs PHP
+    fn claimed(&self) -> Option<String> {
+        self.config
+            .version
+            .clone()
+            .or_else(|| self.persona.php_version().map(str::to_string))
+    }
+
+    /// the PHP version PHP baits claim.
+    pub fn version(&self) -> String {
+        self.claimed()
+            .unwrap_or_else(|| FALLBACK_VERSION.to_string())
+    }
+
+    /// adds X-Powered-By, unless PHP is configured not to expose itself.
+    pub fn expose<'a, T: fmt::Debug>(
+        &self,
+        builder: &'a mut BaseResponseBuilder<T>,
+    ) -> &'a mut BaseResponseBuilder<T> {
+        if self.config.expose_php {
+            builder.add_header("X-Powered-By", format!("PHP/{}", self.version()));
+        }
+        builder
+    }
+
+    /// adds the headers `session_start` sends with a new session: its cookie,
+    /// and the default `nocache` limiter's headers.
+    pub fn start_session<'a, T: fmt::Debug>(
+        &self,
+        builder: &'a mut BaseResponseBuilder<T>,
+    ) -> &'a mut BaseResponseBuilder<T> {
+        // 26 characters of 5 bits each, as php.ini-production configures
+        let id: String = (0..26)
+            .map(|_| std::char::from_digit(rand::random::<u32>() % 32, 32).unwrap())
+            .collect();
+        builder
+            .add_header(
+                "Set-Cookie",
+                format!("{}={}; path=/", self.config.session_name, id),
+            )
+            .add_header("Expires", "Thu, 19 Nov 1981 08:52:00 GMT")
+            .add_header(
+                "Cache-Control",
+                "no-store, no-cache, must-revalidate, post-check=0, pre-check=0",
+            )
+            .add_header("Pragma", "no-cache")
+    }
+
+    // the operator's logo for the PHP version, or the stand-in
+    fn image(&self, version: &str, logo: Logo) -> Arc<Vec<u8>> {
+        self.logos
+            .get(&(version.to_string(), logo))
+            .cloned()
+            .unwrap_or_else(|| Arc::new(STAND_IN.to_vec()))
+    }
+
+    /// whether the request is for an easter egg the claimed PHP would serve.
+    pub fn is_easter_egg(&self, req: &Request) -> bool {
+        self.config.expose_php
+            && self.claimed().is_some_and(|v| has_easter_eggs(&v))
+            && is_easter_egg_url(&req.url)
+    }
+
+    /// Returns a php easter egg response relevant to the requested easter egg.
+    /// An error is returned if no known easter egg is requested.
+    pub fn easter_egg(&self, out: TcpStream, req: &Request) -> Result<Response> {
+        let v = easter_egg_param(&req.url).ok_or_else(|| anyhow!("failed to find PHP easter egg queryparam in order to build easter egg response in url: {}", req.url))?;
+
+        match v.as_str() {
+            "PHPE9568F36-D428-11d2-A769-00AA001ACF42" => self.php_image_resp(out, Logo::Egg),
+            "PHPE9568F34-D428-11d2-A769-00AA001ACF42" => self.php_image_resp(out, Logo::Php),
+            "PHPE9568F35-D428-11d2-A769-00AA001ACF42" => self.php_image_resp(out, Logo::Zend),
+            "PHPB8B5F2A0-3C92-11d3-A3A9-4C7B08C10000" => self.php_credits(out),
+            v => bail!(
+                "unknown php easter egg querystring '{}' in url '{}'",
+                v,
+                req.url
+            ),
+        }
+    }
+
+    // every release with eggs served its logos as GIFs
+    fn php_image_resp(&self, out: TcpStream, logo: Logo) -> Result<Response> {
+        Ok(self
+            .expose(&mut ResponseBuilder::ok(Arc::new(out)))
+            .body(self.image(&self.version(), logo).to_vec())
+            .add_header("Content-Type", "image/gif")
+            .build()?)
+    }
+
+    // php 4.4.0 credits html though the claimed version may be later
+    fn php_credits(&self, out: TcpStream) -> Result<Response> {
+        Ok(self
+            .expose(&mut ResponseBuilder::ok(Arc::new(out)))
+            .body(include_str!("php_credits.html"))
+            .add_header("Content-Type", "text/html")
+            .build()?)
+    }
+}
+
+// the eggs were removed in PHP 5.5
+fn has_easter_eggs(version: &str) -> bool {
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0)) < (5, 5)
 }
 
 lazy_static! {
@@ -42,50 +255,21 @@ lazy_static! {
 }
 
 fn is_easter_egg_url(url: &Url) -> bool {
-    url.query_pairs()
-        .any(|(k, v)| k == "" && RE.is_match(v.as_ref()))
+    easter_egg_param(url).is_some()
 }
 
-/// Returns a php easter egg response relevant to the requested easter egg.
-/// An error is returned if no known easter egg is requested.
-pub fn easter_egg(out: TcpStream, req: &Request) -> Result<Response> {
-    let (_, v) = req.url.query_pairs().find(|(k, v)| k == "" && RE.is_match(v)).ok_or_else(|| anyhow!("failed to find PHP easter egg queryparam in order to build easter egg response in url: {}", req.url))?;
-
-    match v.as_ref() {
-        "PHPE9568F36-D428-11d2-A769-00AA001ACF42" => php_image_resp(out),
-        "PHPE9568F34-D428-11d2-A769-00AA001ACF42" => php_image_resp(out),
-        "PHPE9568F35-D428-11d2-A769-00AA001ACF42" => php_image_resp(out),
-        "PHPB8B5F2A0-3C92-11d3-A3A9-4C7B08C10000" => php_credits(out),
-        v => bail!(
-            "unknown php easter egg querystring '{}' in url '{}'",
-            v,
-            req.url
-        ),
-    }
-}
-
-// the image responses are different, but I doubt scrapers are sniffing for content length or rendering
-// them
-fn php_image_resp(out: TcpStream) -> Result<Response> {
-    Ok(ResponseBuilder::ok(Arc::new(out))
-        .body((0..2985).map(|_| 'a' as u8).collect::<Vec<u8>>())
-        .add_header("Content-Type", "image/gif")
-        .add_header("X-Powered-By", "PHP/4.0.1")
-        .build()?)
-}
-
-// php 4.4.0 credits html though pretending to be an earlier version
-fn php_credits(out: TcpStream) -> Result<Response> {
-    Ok(ResponseBuilder::ok(Arc::new(out))
-        .body(include_str!("php_credits.html"))
-        .add_header("Content-Type", "text/html")
-        .add_header("X-Powered-By", "PHP/4.0.1")
-        .build()?)
+// PHP names the egg with an unnamed parameter, as in `?=PHPE9568F36-...`
+fn easter_egg_param(url: &Url) -> Option<String> {
+    Query::parse(url.query().unwrap_or_default())
+        .iter()
+        .find(|p| p.name.is_empty() && p.subscript.is_none() && RE.is_match(&p.value))
+        .map(|p| p.value.clone())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::persona::Family;
 
     #[test]
     fn test_php_easter_egg_hit() {
@@ -120,4 +304,53 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_easter_egg_versions() {
+        assert!(has_easter_eggs("4.0.1"));
+        assert!(has_easter_eggs("5.3.10"));
+        assert!(!has_easter_eggs("5.5.9"));
+        assert!(!has_easter_eggs("7.4.3"));
+    }
+
+    #[test]
+    fn test_php() {
+        let dir = std::env::temp_dir().join(format!("httpot-php-logos-{}", std::process::id()));
+        fs::create_dir_all(dir.join("5.3.10")).unwrap();
+        fs::write(dir.join("5.3.10").join("zend.gif"), b"GIF89a zend").unwrap();
+
+        let config = PhpConfig {
+            logos: Some(dir.clone()),
+            ..Default::default()
+        };
+        let php = Php::new(Persona::from(Family::Apache), &config).unwrap();
+        assert_eq!(b"GIF89a zend".to_vec(), *php.image("5.3.10", Logo::Zend));
+        assert_eq!(STAND_IN.to_vec(), *php.image("5.3.10", Logo::Php));
+        assert_eq!(STAND_IN.to_vec(), *php.image("5.3.6", Logo::Zend));
+        assert!(STAND_IN.starts_with(b"GIF89a") && STAND_IN.ends_with(b";"));
+
+        // IIS runs no PHP of its own
+        let php = Php::new(Persona::from(Family::Iis), &config).unwrap();
+        assert_eq!(FALLBACK_VERSION, php.version());
+        assert_eq!(None, php.claimed());
+
+        let config = PhpConfig {
+            version: Some("7.4.33".to_string()),
+            session_name: "sid".to_string(),
+            ..Default::default()
+        };
+        let php = Php::new(Persona::from(Family::Iis), &config).unwrap();
+        assert_eq!("7.4.33", php.version());
+        let mut builder = BaseResponseBuilder::ok(());
+        php.expose(&mut builder);
+        php.start_session(&mut builder);
+        let resp = builder.body("").build().unwrap();
+        let headers = resp.headers();
+        assert_eq!(Some("PHP/7.4.33"), headers.get_first("X-Powered-By"));
+        let cookie = headers.get_first("Set-Cookie").unwrap();
+        assert!(cookie.starts_with("sid=") && cookie.ends_with("; path=/"));
+        assert_eq!(26, cookie.len() - "sid=; path=/".len());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -0,0 +1,135 @@
+//! Honeypot plugins are WASM modules which answer requests, letting third
+//! parties ship personas without linking them into httpot. Running them
+//! requires the `wasm` feature.
+//!
+//! A module must export:
+//!  * `memory`
+//!  * `httpot_alloc(len: i32) -> i32`, returning a pointer to `len` free bytes
+//!  * `httpot_handle(ptr: i32, len: i32) -> i64`
+//!
+//! httpot allocates and writes the JSON serialized `PluginRequest` into the
+//! module's memory, then calls `httpot_handle` with it. The module returns
+//! `ptr << 32 | len` of a JSON serialized `PluginResponse` in its memory, or
+//! 0 to decline the request.
+//!
+//! Each request gets a fresh instance with bounded fuel, memory, and wall
+//! time.
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+use crate::{
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder},
+    },
+    prelude::*,
+};
+
+#[cfg(feature = "wasm")]
+mod wasm;
+#[cfg(feature = "wasm")]
+pub use wasm::Plugins;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// exact request path, or a prefix when ending in '*'
+    pub path: String,
+    /// compiled WASM module or WAT source
+    pub module: PathBuf,
+    /// instructions a single request may use
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    /// bytes of linear memory an instance may grow to
+    #[serde(default = "default_max_memory")]
+    pub max_memory: usize,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_fuel() -> u64 {
+    50_000_000
+}
+
+fn default_max_memory() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_timeout_ms() -> u64 {
+    100
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginRequest {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub version: String,
+    pub remote: String,
+    /// lossily converted to utf8
+    pub body: String,
+    pub headers: BTreeMap<String, Vec<String>>,
+}
+
+impl From<&Request> for PluginRequest {
+    fn from(req: &Request) -> Self {
+        Self {
+            method: req.method.to_string(),
+            path: req.url.path().to_string(),
+            query: req.url.query().unwrap_or_default().to_string(),
+            version: req.version.clone(),
+            remote: req.requester(),
+            body: String::from_utf8_lossy(&req.body).into_owned(),
+            headers: req
+                .headers
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginResponse {
+    #[serde(default = "default_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub body: String,
+    /// noteworthy things the plugin saw, logged by httpot
+    #[serde(default)]
+    pub events: Vec<PluginEvent>,
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PluginEvent {
+    pub kind: String,
+    #[serde(default)]
+    pub detail: String,
+}
+
+impl PluginResponse {
+    pub fn into_response(self, conn: TcpStream) -> Result<Response> {
+        let mut builder = ResponseBuilder::default(Arc::new(conn));
+        builder.status_code(self.status);
+        for (k, v) in self.headers {
+            // a plugin's Content-Length is taken as declared, lying or not
+            match (k.eq_ignore_ascii_case("Content-Length"), v.as_slice()) {
+                (true, [len]) => builder.content_length(len),
+                _ => builder.add_headers(&k, v),
+            };
+        }
+
+        builder
+            .body(self.body)
+            .build()
+            .map_err(|e| anyhow!("failed to build plugin response: {}", e))
+    }
+}
@@ -0,0 +1,221 @@
+use std::{sync::Arc, thread, time::Duration};
+
+use wasmtime::{Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use super::{PluginConfig, PluginRequest, PluginResponse};
+use crate::{
+    http::{request::Request, response::Response},
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::path_glob_match,
+};
+
+// granularity of plugin timeouts
+const EPOCH_TICK: Duration = Duration::from_millis(5);
+const MAX_OUTPUT_SIZE: usize = 4 * 1024 * 1024;
+
+struct Plugin {
+    config: PluginConfig,
+    module: Module,
+}
+
+/// Middleware which answers requests matching a plugin's path with the
+/// plugin's response.
+#[derive(Clone)]
+pub struct Plugins {
+    engine: Engine,
+    plugins: Arc<Vec<Plugin>>,
+}
+
+impl Plugins {
+    pub fn new(configs: Vec<PluginConfig>) -> Result<Self> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true).epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+
+        let weak = engine.weak();
+        thread::spawn(move || loop {
+            thread::sleep(EPOCH_TICK);
+            match weak.upgrade() {
+                Some(engine) => engine.increment_epoch(),
+                None => return,
+            }
+        });
+
+        let plugins = configs
+            .into_iter()
+            .map(|config| {
+                let module = Module::from_file(&engine, &config.module).map_err(|e| {
+                    anyhow!(
+                        "failed to load plugin module {}: {}",
+                        config.module.display(),
+                        e
+                    )
+                })?;
+                Ok(Plugin { config, module })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            engine,
+            plugins: Arc::new(plugins),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    fn find(&self, path: &str) -> Option<usize> {
+        self.plugins
+            .iter()
+            .position(|p| path_glob_match(&p.config.path, path))
+    }
+
+    /// runs the plugin in a fresh instance. Blocks until complete or out
+    /// of time.
+    fn handle(&self, idx: usize, input: &[u8]) -> Result<Option<PluginResponse>> {
+        let plugin = &self.plugins[idx];
+        let mut store: Store<StoreLimits> = Store::new(
+            &self.engine,
+            StoreLimitsBuilder::new()
+                .memory_size(plugin.config.max_memory)
+                .instances(1)
+                .build(),
+        );
+        store.limiter(|limits| limits);
+        store.set_fuel(plugin.config.fuel)?;
+        store.set_epoch_deadline((plugin.config.timeout_ms / EPOCH_TICK.as_millis() as u64).max(1));
+
+        let instance = Instance::new(&mut store, &plugin.module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("plugin does not export memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "httpot_alloc")?;
+        let handle = instance.get_typed_func::<(i32, i32), i64>(&mut store, "httpot_handle")?;
+
+        let len: i32 = input.len().try_into()?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+
+        let packed = handle.call(&mut store, (ptr, len))?;
+        if packed == 0 {
+            return Ok(None);
+        }
+
+        let (out_ptr, out_len) = ((packed >> 32) as u32 as usize, packed as u32 as usize);
+        ensure!(
+            out_len <= MAX_OUTPUT_SIZE,
+            "plugin response len {} in excess of allowed size {}",
+            out_len,
+            MAX_OUTPUT_SIZE
+        );
+        let mut out = vec![0; out_len];
+        memory.read(&store, out_ptr, &mut out)?;
+
+        Ok(Some(serde_json::from_slice(&out).map_err(|e| {
+            anyhow!("plugin returned an invalid response: {}", e)
+        })?))
+    }
+}
+
+impl Middleware for Plugins {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let idx = match self.find(req.url.path()) {
+                Some(idx) => idx,
+                None => return next.run(req).await,
+            };
+
+            let input = serde_json::to_vec(&PluginRequest::from(req))?;
+            let plugins = self.clone();
+            let resp = tokio::task::spawn_blocking(move || plugins.handle(idx, &input))
+                .await?
+                .map_err(|e| {
+                    anyhow!("plugin for {} failed: {}", self.plugins[idx].config.path, e)
+                })?;
+
+            match resp {
+                None => next.run(req).await,
+                Some(resp) => {
+                    for event in &resp.events {
+                        info!(
+                            "{} plugin {} event {}: {}",
+                            req.requester(),
+                            self.plugins[idx].config.path,
+                            event.kind,
+                            event.detail
+                        );
+                    }
+                    resp.into_response(next.into_conn())
+                }
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("plugin")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn module(name: &str, wat: &str) -> PluginConfig {
+        let path =
+            std::env::temp_dir().join(format!("httpot-test-{}-{}.wat", name, std::process::id()));
+        std::fs::write(&path, wat).unwrap();
+
+        PluginConfig {
+            path: "/plugin".to_string(),
+            module: path,
+            fuel: 1_000_000,
+            max_memory: 1024 * 1024,
+            timeout_ms: 50,
+        }
+    }
+
+    fn constant_module(json: &str) -> String {
+        format!(
+            r#"(module
+              (memory (export "memory") 1)
+              (global $next (mut i32) (i32.const 4096))
+              (func (export "httpot_alloc") (param $len i32) (result i32)
+                (local $p i32)
+                (local.set $p (global.get $next))
+                (global.set $next (i32.add (global.get $next) (local.get $len)))
+                (local.get $p))
+              (data (i32.const 0) "{}")
+              (func (export "httpot_handle") (param $ptr i32) (param $len i32) (result i64)
+                (i64.const {})))"#,
+            json.replace('"', "\\\""),
+            json.len()
+        )
+    }
+
+    #[test]
+    fn test_plugin_response() {
+        let json = r#"{"status":418,"headers":{"X-Plugin":["teapot"]},"body":"short and stout","events":[{"kind":"probe","detail":"tea"}]}"#;
+        let plugins = Plugins::new(vec![module("resp", &constant_module(json))]).unwrap();
+
+        let resp = plugins.handle(0, b"{}").unwrap().unwrap();
+        assert_eq!(418, resp.status);
+        assert_eq!("short and stout", resp.body);
+        assert_eq!(vec!["teapot".to_string()], resp.headers["X-Plugin"]);
+        assert_eq!("probe", resp.events[0].kind);
+    }
+
+    #[test]
+    fn test_runaway_plugin() {
+        let wat = r#"(module
+          (memory (export "memory") 1)
+          (func (export "httpot_alloc") (param $len i32) (result i32) (i32.const 0))
+          (func (export "httpot_handle") (param $ptr i32) (param $len i32) (result i64)
+            (loop $l (br $l))
+            (i64.const 0)))"#;
+        let plugins = Plugins::new(vec![module("loop", wat)]).unwrap();
+
+        assert!(plugins.handle(0, b"{}").is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Open redirect bait at paths such as `/redirect?url=` and `/out?target=`.
+//! The destination asked for is logged, as it's often the phishing or
+//! malware site the scanner means to launder links to. Nothing is ever
+//! redirected there: requesters are sent to a configured sinkhole, or shown
+//! an interstitial which doesn't link anywhere.
+use std::sync::Arc;
+
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    persona::Persona,
+    prelude::*,
+    util::escape_html,
+};
+
+const PATHS: &[&str] = &[
+    "/redirect",
+    "/redirect.php",
+    "/out",
+    "/out.php",
+    "/go",
+    "/url",
+    "/link",
+];
+/// parameters naming where to go, as redirect scanners fill in
+const PARAMS: &[&str] = &[
+    "url",
+    "target",
+    "to",
+    "dest",
+    "destination",
+    "next",
+    "redirect",
+    "redirect_uri",
+    "redirect_url",
+    "return",
+    "return_to",
+    "returnUrl",
+    "continue",
+    "goto",
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedirectConfig {
+    /// where redirects lead instead of the requested destination. Without
+    /// one, an interstitial is shown instead.
+    pub sinkhole: Option<String>,
+}
+
+/// the off-site destination a request asks to be redirected to, if any:
+/// an absolute, scheme-relative, or script URL in a redirect-like
+/// parameter.
+pub fn destination(req: &Request) -> Option<String> {
+    req.query()
+        .iter()
+        .filter(|p| PARAMS.iter().any(|name| p.name.eq_ignore_ascii_case(name)))
+        .map(|p| p.value.trim().to_string())
+        .find(|v| is_off_site(v))
+}
+
+fn is_off_site(value: &str) -> bool {
+    let lower = value.to_lowercase();
+    // browsers treat backslashes as slashes, so `/\evil.com` leaves too
+    lower.starts_with("//")
+        || lower.starts_with("/\\")
+        || lower.starts_with("\\\\")
+        || lower.starts_with("http://")
+        || lower.starts_with("https://")
+        || lower.starts_with("javascript:")
+        || lower.starts_with("data:")
+}
+
+/// Middleware which answers open redirect probes.
+#[derive(Debug, Clone)]
+pub struct Redirects {
+    sinkhole: Option<Url>,
+    persona: Persona,
+}
+
+impl Redirects {
+    pub fn new(config: RedirectConfig, persona: Persona) -> Result<Self> {
+        let sinkhole = config
+            .sinkhole
+            .map(|s| {
+                Url::parse(&s).map_err(|e| anyhow!("invalid redirect sinkhole {:?}: {}", s, e))
+            })
+            .transpose()?;
+        Ok(Self { sinkhole, persona })
+    }
+}
+
+impl Middleware for Redirects {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            if req.method != Method::GET || !PATHS.contains(&req.url.path()) {
+                return next.run(req).await;
+            }
+            let destination = match destination(req) {
+                Some(d) => d,
+                None => return next.run(req).await,
+            };
+            info!(
+                "{: <8} asked {} to redirect to {:?}",
+                req.requester(),
+                req.url.path(),
+                destination
+            );
+
+            let resp = match &self.sinkhole {
+                Some(sinkhole) => self.persona.redirect(
+                    next.into_conn(),
+                    StatusCode::Found,
+                    sinkhole.as_str(),
+                    req,
+                ),
+                None => {
+                    let mut resp = ResponseBuilder::ok(Arc::new(next.into_conn()));
+                    resp.add_header("Content-Type", "text/html; charset=UTF-8")
+                        .body(interstitial(&destination));
+                    resp
+                }
+            };
+            Ok(resp.build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("redirect")
+    }
+}
+
+// the destination is shown, escaped, but never linked
+fn interstitial(destination: &str) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><title>Leaving this site</title></head><body>\
+         <h1>You are leaving this site</h1>\
+         <p>The link you followed goes to <code>{}</code>, which we can't vouch for.</p>\
+         <p>Copy the address into your browser if you'd like to continue.</p></body></html>",
+        escape_html(destination)
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::persona::Family;
+
+    fn stub_request(target: &str) -> Request {
+        Request::stub(Method::GET, target, "")
+    }
+
+    #[test]
+    fn test_destination() {
+        let cases = vec![
+            (
+                "/redirect?url=https://evil.example/",
+                Some("https://evil.example/"),
+            ),
+            ("/out?target=//evil.example", Some("//evil.example")),
+            ("/login?next=%2F%5Cevil.example", Some("/\\evil.example")),
+            (
+                "/out?returnUrl=javascript:alert(1)",
+                Some("javascript:alert(1)"),
+            ),
+            ("/redirect?url=/account", None),
+            ("/search?q=https://evil.example/", None),
+        ];
+        for (target, expected) in cases {
+            assert_eq!(
+                expected.map(str::to_string),
+                destination(&stub_request(target)),
+                "{}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let config = RedirectConfig {
+            sinkhole: Some("not a url".to_string()),
+        };
+        assert!(Redirects::new(config, Persona::from(Family::Apache)).is_err());
+        assert!(interstitial("https://e.x/\"><script>")
+            .contains("https://e.x/&quot;&gt;&lt;script&gt;"));
+    }
+}
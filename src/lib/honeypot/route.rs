@@ -0,0 +1,471 @@
+//! Routes declared in the config, mapping a path and methods to one of the
+//! built-in handlers, so a new probe can be answered without a new
+//! release or a script:
+//! ```toml
+//! [[routes]]
+//! path = "/.env.production"
+//! handler = { body = "APP_KEY=base64:..." }
+//! headers = { "Content-Type" = "text/plain" }
+//! delay_ms = 250
+//! ```
+//! Paths may extract parameters, as in `/users/:id` or `/files/*path`,
+//! which bodies and redirect locations include as `{id}` or `{path}`.
+//!
+//! Routes can answer with one of several `responses` in place of a handler,
+//! picked by weight. Each source sticks to its pick unless `sticky` is
+//! false, so a scanner sees the same thing on every visit while the
+//! internet as a whole sees a believable mix:
+//! ```toml
+//! [[routes]]
+//! path = "/admin/*"
+//! responses = [
+//!     { weight = 9, handler = { error = 404 } },
+//!     { weight = 1, handler = { file = "bait/login.html" } },
+//! ]
+//! ```
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+
+use num::traits::FromPrimitive;
+use rand::Rng;
+use serde::Deserialize;
+use tokio::time::sleep;
+
+use crate::{
+    fs::fake::gen_fake_listing,
+    honeypot::script::{ScriptConfig, Scripts},
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    persona::Persona,
+    prelude::*,
+    seed::Seeder,
+    util::{content_type_for_path, hash_seed, path_match},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// exact request path, a prefix when ending in '*', or a pattern
+    pub path: String,
+    /// what metrics count the route's responses under, by default its path
+    #[serde(default)]
+    pub name: Option<String>,
+    /// methods answered, or any when empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// how the route answers, unless it has responses
+    #[serde(default)]
+    pub handler: Option<Handler>,
+    /// status of body and file responses
+    #[serde(default)]
+    pub status: Option<u16>,
+    /// headers added to the response, replacing the handler's
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// responses picked between by weight, in place of a handler
+    #[serde(default)]
+    pub responses: Vec<ResponseConfig>,
+    /// whether each source is always given the same response
+    #[serde(default = "default_sticky")]
+    pub sticky: bool,
+    /// how long to wait before answering
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+fn default_sticky() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseConfig {
+    /// how often it's picked, relative to the route's other responses
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub handler: Handler,
+    /// status of body and file responses, overriding the route's
+    #[serde(default)]
+    pub status: Option<u16>,
+    /// headers added to the route's
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase", deny_unknown_fields)]
+pub enum Handler {
+    /// answers with the body, with parameters filled in
+    Body(String),
+    /// answers with the file's contents, read on every request
+    File(PathBuf),
+    /// answers with the persona's error page for the status
+    Error(u16),
+    /// answers with a fake listing of the path
+    Listing,
+    /// redirects to the location, with parameters filled in, with a 302
+    /// unless a status is given
+    Redirect(String),
+    /// answers with a script's response, as scripted routes do
+    Script(PathBuf),
+}
+
+struct Route {
+    config: RouteConfig,
+    candidates: Vec<Candidate>,
+}
+
+struct Candidate {
+    response: ResponseConfig,
+    script: Option<Scripts>,
+}
+
+impl Route {
+    /// the parameters extracted from the request's path, if it matches.
+    fn matches(&self, req: &Request) -> Option<BTreeMap<String, String>> {
+        let method = req.method.to_string();
+        if !self.config.methods.is_empty()
+            && !self
+                .config
+                .methods
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(&method))
+        {
+            return None;
+        }
+        path_match(&self.config.path, req.url.path())
+    }
+
+    /// picks the candidate answering the request.
+    fn pick(&self, seed: &str) -> &Candidate {
+        let total = self
+            .candidates
+            .iter()
+            .map(|c| c.response.weight as u64)
+            .sum::<u64>();
+        let mut n = match self.config.sticky {
+            true => hash_seed(seed, &self.config.path) % total,
+            false => rand::thread_rng().gen_range(0..total),
+        };
+        for candidate in &self.candidates {
+            match n.checked_sub(candidate.response.weight as u64) {
+                Some(rest) => n = rest,
+                None => return candidate,
+            }
+        }
+        unreachable!("picked past the total weight")
+    }
+}
+
+/// fills `{name}` in with the parameter's value.
+fn fill(s: &str, params: &BTreeMap<String, String>) -> String {
+    params.iter().fold(s.to_string(), |s, (name, value)| {
+        s.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// Middleware which answers requests matching a route with its handler.
+/// Routes are checked in order, and requests matching none are passed on.
+#[derive(Clone)]
+pub struct Routes {
+    routes: Arc<Vec<Route>>,
+    persona: Persona,
+    seeder: Seeder,
+}
+
+impl Routes {
+    pub fn new(configs: Vec<RouteConfig>, persona: Persona, seeder: Seeder) -> Result<Self> {
+        let routes = configs
+            .into_iter()
+            .map(|mut config| {
+                let responses = match config.handler.take() {
+                    Some(handler) if config.responses.is_empty() => vec![ResponseConfig {
+                        weight: 1,
+                        handler,
+                        status: None,
+                        headers: Default::default(),
+                    }],
+                    Some(_) => bail!("route {} has both a handler and responses", config.path),
+                    None => std::mem::take(&mut config.responses),
+                };
+                ensure!(
+                    responses.iter().any(|r| r.weight > 0),
+                    "route {} needs a handler or responses with weight",
+                    config.path
+                );
+
+                let candidates = responses
+                    .into_iter()
+                    .map(|mut response| {
+                        response.status = response.status.or(config.status);
+                        let mut headers = config.headers.clone();
+                        headers.append(&mut response.headers);
+                        response.headers = headers;
+                        let script = match &response.handler {
+                            Handler::Script(file) => Some(Scripts::new(vec![ScriptConfig {
+                                path: config.path.clone(),
+                                file: Some(file.clone()),
+                                source: None,
+                            }])?),
+                            _ => None,
+                        };
+                        Ok(Candidate { response, script })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Route { config, candidates })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            routes: Arc::new(routes),
+            persona,
+            seeder,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// answers the request with the response's built-in handler.
+    fn answer(
+        &self,
+        route: &Route,
+        config: &ResponseConfig,
+        params: &BTreeMap<String, String>,
+        req: &Request,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let path = req.url.path();
+
+        let mut resp = match &config.handler {
+            Handler::Body(body) => {
+                let mut resp = ResponseBuilder::ok(Arc::new(next.into_conn()));
+                resp.add_header("Content-Type", content_type_for_path(path))
+                    .body(fill(body, params));
+                resp
+            }
+            Handler::File(file) => {
+                let body = std::fs::read(file).map_err(|e| {
+                    anyhow!(
+                        "failed to read {} for {}: {}",
+                        file.display(),
+                        route.config.path,
+                        e
+                    )
+                })?;
+                let mut resp = ResponseBuilder::ok(Arc::new(next.into_conn()));
+                resp.add_header(
+                    "Content-Type",
+                    content_type_for_path(&file.to_string_lossy()),
+                )
+                .body(body);
+                resp
+            }
+            Handler::Error(code) => {
+                let status = StatusCode::from_u16(*code).unwrap_or(StatusCode::for_class(*code));
+                let mut resp = self.persona.error(next.into_conn(), status, Some(req));
+                resp.status_code(*code);
+                resp
+            }
+            Handler::Listing => {
+                let seed = self.seeder.for_request(req);
+                let lang = self.persona.language(Some(req));
+                let dir = &path[..=path.rfind('/').unwrap_or_default()];
+                let mut resp = ResponseBuilder::ok(Arc::new(next.into_conn()));
+                resp.add_header("Content-Type", "text/html")
+                    .body(gen_fake_listing(&seed, dir, lang, self.seeder.tree()));
+                resp
+            }
+            Handler::Redirect(location) => {
+                let code = config.status.unwrap_or(302);
+                let status = StatusCode::from_u16(code).unwrap_or(StatusCode::for_class(code));
+                let mut resp =
+                    self.persona
+                        .redirect(next.into_conn(), status, &fill(location, params), req);
+                resp.status_code(code);
+                resp
+            }
+            Handler::Script(_) => unreachable!("scripted routes are answered by their script"),
+        };
+        if let (Some(status), Handler::Body(_) | Handler::File(_)) =
+            (config.status, &config.handler)
+        {
+            resp.status_code(status);
+        }
+        for (name, value) in &config.headers {
+            resp.set_header(name, value);
+        }
+        let resp = resp.build()?;
+        Ok(match req.method {
+            Method::HEAD => resp.without_body(),
+            _ => resp,
+        })
+    }
+}
+
+impl Middleware for Routes {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let (route, params) = match self.routes.iter().find_map(|r| Some((r, r.matches(req)?)))
+            {
+                Some(found) => found,
+                None => return next.run(req).await,
+            };
+            if route.config.delay_ms > 0 {
+                sleep(Duration::from_millis(route.config.delay_ms)).await;
+            }
+
+            let candidate = route.pick(&self.seeder.for_request(req));
+            let name = route.config.name.as_ref().unwrap_or(&route.config.path);
+            let resp = match &candidate.script {
+                Some(script) => {
+                    let mut resp = script.call(req, next).await?;
+                    for (name, value) in &candidate.response.headers {
+                        resp.headers_mut().remove(name);
+                        resp.headers_mut().add(name, value);
+                    }
+                    resp
+                }
+                None => self.answer(route, &candidate.response, &params, req, next)?,
+            };
+            Ok(resp.with_route(name))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::persona::Family;
+
+    fn stub_request(method: Method, path: &str) -> Request {
+        Request::stub(method, path, "")
+    }
+
+    #[derive(Deserialize)]
+    struct Table {
+        routes: Vec<RouteConfig>,
+    }
+
+    fn parse(toml: &str) -> Result<Routes> {
+        let configs = toml::from_str::<Table>(toml)?.routes;
+        Routes::new(
+            configs,
+            Persona::from(Family::Apache),
+            Seeder::new("seed", Default::default()),
+        )
+    }
+
+    #[test]
+    fn test_routes() {
+        let configs = toml::from_str::<Table>(
+            r#"
+            [[routes]]
+            path = "/.env*"
+            methods = ["get"]
+            handler = { body = "APP_KEY=" }
+            status = 200
+            headers = { "Content-Type" = "text/plain" }
+
+            [[routes]]
+            path = "/uploads/*"
+            handler = "listing"
+
+            [[routes]]
+            path = "/old"
+            handler = { redirect = "/new" }
+            status = 301
+            "#,
+        )
+        .unwrap()
+        .routes;
+        assert!(matches!(&configs[0].handler, Some(Handler::Body(b)) if b == "APP_KEY="));
+        assert!(matches!(configs[1].handler, Some(Handler::Listing)));
+        assert!(matches!(&configs[2].handler, Some(Handler::Redirect(l)) if l == "/new"));
+
+        let routes = Routes::new(
+            configs,
+            Persona::from(Family::Apache),
+            Seeder::new("seed", Default::default()),
+        )
+        .unwrap()
+        .routes;
+        let matching = |method, path| {
+            let req = stub_request(method, path);
+            routes.iter().position(|r| r.matches(&req).is_some())
+        };
+        assert_eq!(Some(0), matching(Method::GET, "/.env.production"));
+        assert_eq!(None, matching(Method::POST, "/.env"));
+        assert_eq!(Some(1), matching(Method::POST, "/uploads/2023/"));
+        assert_eq!(None, matching(Method::GET, "/old/"));
+
+        let params = BTreeMap::from([("id".to_string(), "42".to_string())]);
+        assert_eq!("/users/42/{x}", fill("/users/{id}/{x}", &params));
+
+        let status = routes[0].candidates[0].response.status;
+        let headers = &routes[0].candidates[0].response.headers;
+        assert_eq!(
+            (Some(200), Some("text/plain")),
+            (status, headers.get("Content-Type").map(String::as_str))
+        );
+
+        assert!(
+            toml::from_str::<Table>("[[routes]]\npath = \"/\"\nhandler = { shell = \"id\" }")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_weighted_responses() {
+        let routes = parse(
+            r#"
+            [[routes]]
+            path = "/admin"
+            headers = { "X-Route" = "admin" }
+            responses = [
+                { weight = 9, handler = { error = 404 } },
+                { weight = 1, handler = { body = "login" }, headers = { "X-Pick" = "login" } },
+                { weight = 0, handler = "listing" },
+            ]
+            "#,
+        )
+        .unwrap();
+        let route = &routes.routes[0];
+        assert_eq!(
+            Some("admin"),
+            route.candidates[1]
+                .response
+                .headers
+                .get("X-Route")
+                .map(String::as_str)
+        );
+
+        let mut picks = [0; 3];
+        for i in 0..1000 {
+            let seed = format!("seed/10.0.{}.0", i);
+            let pick = route.pick(&seed);
+            assert!(std::ptr::eq(pick, route.pick(&seed)), "sticky per source");
+            picks[route
+                .candidates
+                .iter()
+                .position(|c| std::ptr::eq(c, pick))
+                .unwrap()] += 1;
+        }
+        assert!((850..950).contains(&picks[0]), "{:?}", picks);
+        assert_eq!(0, picks[2]);
+
+        assert!(parse("[[routes]]\npath = \"/\"").is_err());
+        assert!(parse("[[routes]]\npath = \"/\"\nhandler = \"listing\"\nresponses = [{ handler = \"listing\" }]").is_err());
+        assert!(parse(
+            "[[routes]]\npath = \"/\"\nresponses = [{ weight = 0, handler = \"listing\" }]"
+        )
+        .is_err());
+    }
+}
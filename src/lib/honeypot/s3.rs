@@ -0,0 +1,483 @@
+//! An S3-compatible object store, for bucket enumeration tools. Requests
+//! are taken to be for a bucket when the Host is Amazon's S3 endpoint or a
+//! configured one, with the bucket as a subdomain or the first path
+//! segment, or when they're signed or addressed like S3 API calls. Every
+//! bucket name asked for is logged, which reveals the tool's wordlist.
+//! Some buckets exist and list their objects, some deny access, and the
+//! rest don't exist, the same for each source and name. Some objects are
+//! leaked credentials full of honeytokens, and downloading one is logged.
+//! Writes are denied. Responses keep the persona's Server header, as from
+//! a self-hosted store behind a proxy.
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use rand::prelude::*;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    fs::fake::gen_fake_content,
+    honeypot::{bait, honeytoken::Honeytokens},
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    seed::Seeder,
+    util::{escape_html, hash_seed},
+};
+
+/// objects a bucket may hold, where `{date}` is filled from the object's
+/// modification date
+const KEYS: &[&str] = &[
+    "backups/db-{date}.sql.gz",
+    "backups/site-{date}.tar.gz",
+    "logs/access-{date}.log",
+    "exports/customers-{date}.csv",
+    "index.html",
+    "assets/logo.png",
+    ".env",
+    "id_rsa",
+    ".git-credentials",
+    "config/credentials",
+    "terraform.tfstate",
+];
+// objects are modified before this date
+const MODIFIED_BEFORE: &str = "2025-03-01T00:00:00Z";
+
+lazy_static! {
+    /// Amazon's endpoints, capturing the bucket of virtual-hosted requests
+    static ref AMAZON_ENDPOINT: Regex =
+        Regex::new(r"^(?:(.+)\.)?s3(?:[.-][a-z0-9-]+)*?\.amazonaws\.com$").unwrap();
+    static ref BUCKET_NAME: Regex = Regex::new(r"^[a-z0-9][a-z0-9.-]{1,61}[a-z0-9]$").unwrap();
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct S3Config {
+    /// hostnames served as S3 endpoints besides Amazon's, such as
+    /// `s3.example.com`, whose buckets are subdomains or path segments
+    pub endpoints: Vec<String>,
+    /// the region buckets are in
+    pub region: String,
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            endpoints: vec![],
+            region: "us-east-1".to_string(),
+        }
+    }
+}
+
+/// What a bucket name turns out to be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bucket {
+    /// listable by anyone
+    Public,
+    /// exists, but isn't readable
+    Private,
+    Missing,
+}
+
+/// An object of a public bucket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Object {
+    key: String,
+    modified_at: DateTime<Utc>,
+    body: Vec<u8>,
+}
+
+impl Object {
+    fn etag(&self) -> String {
+        format!("\"{:x}\"", md5::compute(&self.body))
+    }
+
+    /// whether the object is leaked credentials.
+    fn is_leak(&self) -> bool {
+        let name = self.key.rsplit('/').next().unwrap_or_default();
+        bait::is_bait(&self.key) || name == "credentials" || name == "terraform.tfstate"
+    }
+}
+
+/// the bucket and object key a request is for, if it's addressed to the
+/// object store. The key is empty for the bucket itself, and the bucket
+/// for the service.
+pub fn address<'a>(req: &'a Request, endpoints: &[String]) -> Option<(String, &'a str)> {
+    let host = req.url.host_str().unwrap_or_default().to_lowercase();
+    let path = req.url.path().trim_start_matches('/');
+
+    let virtual_hosted = AMAZON_ENDPOINT
+        .captures(&host)
+        .map(|c| c.get(1).map(|b| b.as_str().to_string()))
+        .or_else(|| {
+            endpoints
+                .iter()
+                .find_map(|e| match host.strip_suffix(e.as_str()) {
+                    Some("") => Some(None),
+                    Some(sub) => sub.strip_suffix('.').map(|b| Some(b.to_string())),
+                    None => None,
+                })
+        });
+    let (bucket, key) = match virtual_hosted {
+        Some(Some(bucket)) => (bucket, path),
+        Some(None) => split_bucket(path),
+        None if is_api_call(req) => split_bucket(path),
+        None => return None,
+    };
+    match bucket.is_empty() || BUCKET_NAME.is_match(&bucket) {
+        true => Some((bucket, key)),
+        false => None,
+    }
+}
+
+// path-style addressing, as `/bucket/key`
+fn split_bucket(path: &str) -> (String, &str) {
+    let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+    (bucket.to_string(), key)
+}
+
+// whether the request is signed, or queried, like an S3 API call
+fn is_api_call(req: &Request) -> bool {
+    let signed = req.headers.iter().any(|(k, v)| {
+        k.to_lowercase().starts_with("x-amz-")
+            || (k.eq_ignore_ascii_case("Authorization")
+                && v.iter()
+                    .any(|v| v.starts_with("AWS4-HMAC-SHA256") || v.starts_with("AWS ")))
+    });
+    signed || req.query().get("list-type").is_some()
+}
+
+// what the bucket is for the seed
+fn bucket(seed: &str, name: &str) -> Bucket {
+    match hash_seed(seed, name) % 10 {
+        0..=2 => Bucket::Public,
+        3 | 4 => Bucket::Private,
+        _ => Bucket::Missing,
+    }
+}
+
+// a public bucket's objects, sorted by key as S3 lists them
+fn objects(seed: &str, bucket: &str) -> Vec<Object> {
+    let mut rng = StdRng::seed_from_u64(hash_seed(seed, &format!("s3/{}", bucket)));
+    let before: DateTime<Utc> = MODIFIED_BEFORE.parse().unwrap();
+    let count = rng.gen_range(3..=8);
+
+    let mut objects = KEYS
+        .choose_multiple(&mut rng, count)
+        .map(|pattern| {
+            let modified_at = before - Duration::minutes(rng.gen_range(0..60 * 24 * 365));
+            let key = pattern.replace("{date}", &modified_at.format("%Y-%m-%d").to_string());
+            let body = object_body(seed, bucket, &key, rng.gen_range(512..64 * 1024));
+            Object {
+                key,
+                modified_at,
+                body,
+            }
+        })
+        .collect::<Vec<_>>();
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+    objects
+}
+
+fn object_body(seed: &str, bucket: &str, key: &str, size: usize) -> Vec<u8> {
+    let path = format!("/{}/{}", bucket, key);
+    if let Some((_, body)) = bait::file(seed, &path) {
+        return body;
+    }
+    let tokens = Honeytokens::generate(seed, &path);
+    match key.rsplit('/').next().unwrap_or_default() {
+        "credentials" => format!(
+            "[default]\naws_access_key_id = {}\naws_secret_access_key = {}\nregion = us-east-1\n",
+            tokens.aws_access_key_id, tokens.aws_secret_access_key
+        )
+        .into_bytes(),
+        "terraform.tfstate" => serde_json::to_vec_pretty(&serde_json::json!({
+            "version": 4,
+            "terraform_version": "1.5.7",
+            "serial": 42,
+            "resources": [{
+                "mode": "managed",
+                "type": "aws_db_instance",
+                "name": "main",
+                "instances": [{"attributes": {
+                    "username": tokens.username,
+                    "password": tokens.db_password,
+                    "endpoint": format!("{}.c9akciq32.rds.amazonaws.com:5432", bucket),
+                }}],
+            }, {
+                "mode": "managed",
+                "type": "aws_iam_access_key",
+                "name": "deploy",
+                "instances": [{"attributes": {
+                    "id": tokens.aws_access_key_id,
+                    "secret": tokens.aws_secret_access_key,
+                }}],
+            }],
+        }))
+        .unwrap_or_default(),
+        _ => gen_fake_content(seed, &path, size),
+    }
+}
+
+/// Middleware which serves the fake object store.
+#[derive(Debug, Clone)]
+pub struct ObjectStore {
+    config: S3Config,
+    seeder: Seeder,
+}
+
+/// An object store response, before it's bound to a connection.
+#[derive(Debug)]
+struct Reply {
+    status: StatusCode,
+    content_type: &'static str,
+    body: Vec<u8>,
+    etag: Option<String>,
+}
+
+impl Reply {
+    fn xml(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "application/xml",
+            body: body.into_bytes(),
+            etag: None,
+        }
+    }
+
+    fn error(status: StatusCode, code: &str, message: &str, bucket: &str) -> Self {
+        Self {
+            status,
+            ..Self::xml(format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code>\
+                 <Message>{}</Message><BucketName>{}</BucketName></Error>",
+                code,
+                message,
+                escape_html(bucket)
+            ))
+        }
+    }
+
+    fn access_denied(bucket: &str) -> Self {
+        Self::error(
+            StatusCode::Forbidden,
+            "AccessDenied",
+            "Access Denied",
+            bucket,
+        )
+    }
+}
+
+impl ObjectStore {
+    pub fn new(config: S3Config, seeder: Seeder) -> Self {
+        Self { config, seeder }
+    }
+
+    fn respond(&self, req: &Request, name: &str, key: &str) -> Reply {
+        // the service lists buckets only for their owner
+        if name.is_empty() {
+            return Reply::access_denied("");
+        }
+        let seed = self.seeder.for_request(req);
+        let found = bucket(&seed, name);
+        if key.is_empty() {
+            info!(
+                "{: <8} probed S3 bucket {:?}: {:?}",
+                req.requester(),
+                name,
+                found
+            );
+        }
+
+        match (found, &req.method) {
+            (Bucket::Missing, _) => Reply::error(
+                StatusCode::NotFound,
+                "NoSuchBucket",
+                "The specified bucket does not exist",
+                name,
+            ),
+            (Bucket::Private, _) => Reply::access_denied(name),
+            (Bucket::Public, Method::GET | Method::HEAD) => self.read(req, &seed, name, key),
+            (Bucket::Public, _) => {
+                info!(
+                    "{: <8} tried to {:?} {:?} in S3 bucket {:?}",
+                    req.requester(),
+                    req.method,
+                    key,
+                    name
+                );
+                Reply::access_denied(name)
+            }
+        }
+    }
+
+    fn read(&self, req: &Request, seed: &str, name: &str, key: &str) -> Reply {
+        let objects = objects(seed, name);
+        if key.is_empty() {
+            return Reply::xml(list(req, name, &objects));
+        }
+        match objects.into_iter().find(|o| o.key == key) {
+            Some(object) => {
+                if object.is_leak() {
+                    info!(
+                        "{: <8} downloaded leaked {:?} from S3 bucket {:?}",
+                        req.requester(),
+                        key,
+                        name
+                    );
+                }
+                Reply {
+                    status: StatusCode::Ok,
+                    content_type: "binary/octet-stream",
+                    etag: Some(object.etag()),
+                    body: object.body,
+                }
+            }
+            None => Reply::error(
+                StatusCode::NotFound,
+                "NoSuchKey",
+                "The specified key does not exist.",
+                name,
+            ),
+        }
+    }
+}
+
+// ListBucketResult, for ListObjects and ListObjectsV2
+fn list(req: &Request, name: &str, objects: &[Object]) -> String {
+    let query = req.query();
+    let prefix = query.get("prefix").unwrap_or_default();
+    let v2 = query.get("list-type") == Some("2");
+    let contents = objects
+        .iter()
+        .filter(|o| o.key.starts_with(prefix))
+        .collect::<Vec<_>>();
+
+    let entries = contents
+        .iter()
+        .map(|o| {
+            format!(
+                "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag>\
+                 <Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                escape_html(&o.key),
+                o.modified_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                escape_html(&o.etag()),
+                o.body.len()
+            )
+        })
+        .collect::<String>();
+    let count = match v2 {
+        true => format!("<KeyCount>{}</KeyCount>", contents.len()),
+        false => "<Marker></Marker>".to_string(),
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+         <Name>{}</Name><Prefix>{}</Prefix>{}<MaxKeys>1000</MaxKeys>\
+         <IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+        escape_html(name),
+        escape_html(prefix),
+        count,
+        entries
+    )
+}
+
+impl Middleware for ObjectStore {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let (bucket, key) = match address(req, &self.config.endpoints) {
+                Some(address) => address,
+                None => return next.run(req).await,
+            };
+            let reply = self.respond(req, &bucket, key);
+
+            let request_id = hash_seed(&req.target, "s3 request");
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            builder
+                .status_code(reply.status)
+                .add_header("Content-Type", reply.content_type)
+                .add_header("x-amz-request-id", format!("{:016X}", request_id))
+                .add_header("x-amz-bucket-region", &self.config.region);
+            if let Some(etag) = reply.etag {
+                builder.add_header("ETag", etag);
+            }
+            Ok(builder.body(reply.body).build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("s3")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stub_request(host: &str, target: &str) -> Request {
+        let mut req = Request::stub(Method::GET, target, "");
+        req.url = format!("http://{}{}", host, target).parse().unwrap();
+        req
+    }
+
+    #[test]
+    fn test_address() {
+        let endpoints = vec!["s3.example.com".to_string()];
+        let cases = vec![
+            (
+                "acme-backups.s3.amazonaws.com",
+                "/",
+                Some(("acme-backups", "")),
+            ),
+            (
+                "acme.logs.s3.us-west-2.amazonaws.com",
+                "/2024/a.log",
+                Some(("acme.logs", "2024/a.log")),
+            ),
+            (
+                "s3.amazonaws.com",
+                "/acme-dev/.env",
+                Some(("acme-dev", ".env")),
+            ),
+            ("s3.amazonaws.com", "/", Some(("", ""))),
+            ("media.s3.example.com", "/", Some(("media", ""))),
+            ("s3.example.com", "/media/x.png", Some(("media", "x.png"))),
+            ("www.example.com", "/media/x.png", None),
+            ("s3.amazonaws.com", "/Not_A_Bucket", None),
+        ];
+        for (host, target, expected) in cases {
+            let req = stub_request(host, target);
+            assert_eq!(
+                expected,
+                address(&req, &endpoints)
+                    .as_ref()
+                    .map(|(b, k)| (b.as_str(), *k)),
+                "{}{}",
+                host,
+                target
+            );
+        }
+
+        let req = stub_request("www.example.com", "/media?list-type=2");
+        assert_eq!(Some(("media".to_string(), "")), address(&req, &endpoints));
+    }
+
+    #[test]
+    fn test_objects() {
+        let public = (0..100)
+            .map(|i| format!("bucket-{}", i))
+            .find(|b| bucket("seed", b) == Bucket::Public)
+            .unwrap();
+        let objects = objects("seed", &public);
+        assert_eq!(objects, super::objects("seed", &public));
+        assert!(objects.windows(2).all(|o| o[0].key < o[1].key));
+
+        let body = object_body("seed", "acme", "config/credentials", 0);
+        let tokens = Honeytokens::generate("seed", "/acme/config/credentials");
+        assert!(String::from_utf8_lossy(&body).contains(&tokens.aws_secret_access_key));
+    }
+}
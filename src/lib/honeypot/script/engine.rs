@@ -0,0 +1,300 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+
+use super::ScriptConfig;
+use crate::{
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::path_match,
+};
+
+// bounds scripts so a bad one can't hang a connection forever
+const MAX_OPERATIONS: u64 = 500_000;
+const MAX_STRING_SIZE: usize = 1024 * 1024;
+
+struct Script {
+    config: ScriptConfig,
+    // modified time of the file the AST was compiled from
+    compiled: RwLock<Option<(Option<SystemTime>, AST)>>,
+}
+
+impl Script {
+    /// returns the compiled script, recompiling if the file changed.
+    fn ast(&self, engine: &Engine) -> Result<AST> {
+        let modified = match &self.config.file {
+            Some(f) => Some(std::fs::metadata(f)?.modified()?),
+            None => None,
+        };
+
+        if let Some((at, ast)) = self.compiled.read().unwrap().as_ref() {
+            if *at == modified {
+                return Ok(ast.clone());
+            }
+        }
+
+        let ast = match (&self.config.file, &self.config.source) {
+            (Some(f), _) => {
+                debug!("compiling script {}", f.display());
+                engine.compile_file(f.clone()).map_err(|e| e.to_string())
+            }
+            (None, Some(src)) => engine.compile(src).map_err(|e| e.to_string()),
+            (None, None) => bail!("script for {} has no file or source", self.config.path),
+        }
+        .map_err(|e| anyhow!("failed to compile script for {}: {}", self.config.path, e))?;
+
+        *self.compiled.write().unwrap() = Some((modified, ast.clone()));
+        Ok(ast)
+    }
+}
+
+/// Middleware which answers requests matching a script's path with the
+/// script's response. Requests are passed on if no script matches or the
+/// script declines.
+#[derive(Clone)]
+pub struct Scripts {
+    engine: Arc<Engine>,
+    scripts: Arc<Vec<Script>>,
+}
+
+impl Scripts {
+    pub fn new(configs: Vec<ScriptConfig>) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_string_size(MAX_STRING_SIZE);
+
+        let s = Self {
+            engine: Arc::new(engine),
+            scripts: Arc::new(
+                configs
+                    .into_iter()
+                    .map(|config| Script {
+                        config,
+                        compiled: RwLock::new(None),
+                    })
+                    .collect(),
+            ),
+        };
+
+        // fail early on scripts which don't compile at startup
+        for script in s.scripts.iter() {
+            script.ast(&s.engine)?;
+        }
+
+        Ok(s)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scripts.is_empty()
+    }
+
+    /// evaluates the first matching script, returning its response map.
+    fn eval(&self, req: &Request) -> Result<Option<Map>> {
+        let (script, params) = match self
+            .scripts
+            .iter()
+            .find_map(|s| Some((s, path_match(&s.config.path, req.url.path())?)))
+        {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        let ast = script.ast(&self.engine)?;
+        let mut scope = Scope::new();
+        scope.push_constant("request", request_map(req, params));
+
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| anyhow!("script for {} failed: {}", script.config.path, e))?;
+
+        if result.is_unit() {
+            return Ok(None);
+        }
+
+        result.try_cast::<Map>().map(Some).ok_or_else(|| {
+            anyhow!(
+                "script for {} returned something other than a map or ()",
+                script.config.path
+            )
+        })
+    }
+}
+
+impl Middleware for Scripts {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.eval(req)? {
+                Some(resp) => build_response(next.into_conn(), resp),
+                None => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("script")
+    }
+}
+
+fn request_map(req: &Request, params: BTreeMap<String, String>) -> Map {
+    let mut headers = Map::new();
+    for (k, values) in req.headers.iter() {
+        headers.insert(
+            k.to_lowercase().into(),
+            values
+                .iter()
+                .cloned()
+                .map(Dynamic::from)
+                .collect::<Vec<_>>()
+                .into(),
+        );
+    }
+
+    let mut m = Map::new();
+    m.insert("method".into(), req.method.to_string().into());
+    m.insert("path".into(), req.url.path().into());
+    m.insert("query".into(), req.url.query().unwrap_or_default().into());
+    m.insert("version".into(), req.version.clone().into());
+    m.insert("remote".into(), req.requester().into());
+    m.insert(
+        "body".into(),
+        String::from_utf8_lossy(&req.body).into_owned().into(),
+    );
+    m.insert("headers".into(), headers.into());
+    m.insert(
+        "params".into(),
+        params
+            .into_iter()
+            .map(|(k, v)| (k.into(), v.into()))
+            .collect::<Map>()
+            .into(),
+    );
+    m
+}
+
+fn build_response(conn: tokio::net::TcpStream, mut resp: Map) -> Result<Response> {
+    let mut builder = ResponseBuilder::default(Arc::new(conn));
+
+    let status = match resp.remove("status") {
+        Some(s) => s
+            .as_int()
+            .map_err(|t| anyhow!("script status must be an integer, got {}", t))?,
+        None => 200,
+    };
+    builder.status_code(status);
+
+    if let Some(headers) = resp.remove("headers") {
+        let headers = headers
+            .try_cast::<Map>()
+            .ok_or_else(|| anyhow!("script headers must be a map"))?;
+        for (k, v) in headers {
+            if v.is_array() {
+                builder.add_headers(k.as_str(), v.into_array().unwrap_or_default());
+            } else if k.eq_ignore_ascii_case("Content-Length") {
+                // a script's Content-Length is taken as declared, lying or not
+                builder.content_length(v);
+            } else {
+                builder.add_header(k.as_str(), v);
+            }
+        }
+    }
+
+    match resp.remove("body") {
+        Some(b) if b.is_blob() => builder.body(b.into_blob().unwrap_or_default()),
+        Some(b) => builder.body(b.to_string()),
+        None => builder.body(""),
+    };
+
+    builder
+        .build()
+        .map_err(|e| anyhow!("failed to build script response: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(path: &str) -> Request {
+        let mut req = Request::stub(Method::GET, path, "");
+        req.headers.add("User-Agent", "zgrab/0.x");
+        req
+    }
+
+    fn inline(path: &str, source: &str) -> ScriptConfig {
+        ScriptConfig {
+            path: path.to_string(),
+            file: None,
+            source: Some(source.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_script_response() {
+        let scripts = Scripts::new(vec![inline(
+            "/cgi-bin/*",
+            r#"
+            if request.headers["user-agent"][0].contains("zgrab") {
+                #{ status: 403, headers: #{ "X-Seen": "zgrab" }, body: "go away " + request.path }
+            }
+            "#,
+        )])
+        .unwrap();
+
+        let resp = scripts
+            .eval(&stub_request("/cgi-bin/luci"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(403, resp["status"].as_int().unwrap());
+        assert_eq!("go away /cgi-bin/luci", resp["body"].to_string());
+
+        assert!(scripts
+            .eval(&stub_request("/index.html"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_script_declines() {
+        let scripts = Scripts::new(vec![inline("/exact", "()")]).unwrap();
+        assert!(scripts.eval(&stub_request("/exact")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_script_params() {
+        let scripts = Scripts::new(vec![inline(
+            "/api/v1/users/:id",
+            r#"#{ body: "user " + request.params.id }"#,
+        )])
+        .unwrap();
+        let resp = scripts
+            .eval(&stub_request("/api/v1/users/42"))
+            .unwrap()
+            .unwrap();
+        assert_eq!("user 42", resp["body"].clone().into_string().unwrap());
+        assert!(scripts
+            .eval(&stub_request("/api/v1/users/"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_runaway_script() {
+        let scripts = Scripts::new(vec![inline("/loop", "loop {}")]).unwrap();
+        assert!(scripts.eval(&stub_request("/loop")).is_err());
+    }
+
+    #[test]
+    fn test_bad_script_fails_early() {
+        assert!(Scripts::new(vec![inline("/bad", "let = ;")]).is_err());
+    }
+}
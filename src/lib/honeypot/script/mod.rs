@@ -0,0 +1,68 @@
+//! Scripted bait routes. Operators provide a small Rhai script per route
+//! which inspects the request and returns a response, covering the long
+//! tail of probes without a new release.
+//!
+//! Scripts see a `request` object map:
+//! ```text
+//! #{ method, path, query, version, remote, body, headers: #{ "lowercase-name": [values] }, params: #{} }
+//! ```
+//! where `params` holds those extracted by the route's path, such as `id`
+//! for `/users/:id`.
+//! and evaluate to either `()`, declining the request, or a response map:
+//! ```text
+//! #{ status: 200, headers: #{ "Content-Type": "text/html" }, body: "..." }
+//! ```
+//!
+//! Scripts read from files are reloaded whenever their modification time
+//! changes. Running them requires the `scripting` feature.
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+#[cfg(feature = "scripting")]
+mod engine;
+#[cfg(not(feature = "scripting"))]
+pub use disabled::Scripts;
+#[cfg(feature = "scripting")]
+pub use engine::Scripts;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// exact request path, or a prefix when ending in '*'
+    pub path: String,
+    /// script file, reloaded on change
+    pub file: Option<PathBuf>,
+    /// inline script source, used when no file is provided
+    pub source: Option<String>,
+}
+
+// stands in for the Rhai engine in builds without the `scripting` feature,
+// refusing to start with scripts configured.
+#[cfg(not(feature = "scripting"))]
+mod disabled {
+    use super::ScriptConfig;
+    use crate::{
+        http::{request::Request, response::Response},
+        middleware::{BoxFuture, Middleware, Next},
+        prelude::*,
+    };
+
+    pub enum Scripts {}
+
+    impl Scripts {
+        pub fn new(_configs: Vec<ScriptConfig>) -> Result<Self> {
+            bail!("scripts are configured but httpot was built without the scripting feature")
+        }
+    }
+
+    impl Middleware for Scripts {
+        fn call<'a>(
+            &'a self,
+            _req: &'a Request,
+            _next: Next<'a>,
+        ) -> BoxFuture<'a, Result<Response>> {
+            match *self {}
+        }
+    }
+}
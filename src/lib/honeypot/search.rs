@@ -0,0 +1,174 @@
+//! A fake site search at `/search?q=`, which reflects the query into a
+//! page of generated results as reflected-input scanners hope for. The
+//! query always comes back HTML-escaped, so payloads are inert, and those
+//! which look like XSS are logged and tagged by `classify`.
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use rand::prelude::*;
+use regex::Regex;
+
+use crate::{
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    seed::Seeder,
+    util::{escape_html, hash_seed},
+};
+
+const PATHS: &[&str] = &["/search", "/search/", "/search.php", "/search.html"];
+/// parameters carrying the query, as various CMSes name it
+const PARAMS: &[&str] = &["q", "query", "s", "search", "keyword"];
+const SECTIONS: &[&str] = &["blog", "docs", "support", "news", "products"];
+const TOPICS: &[&str] = &[
+    "Getting started with",
+    "Frequently asked questions about",
+    "Release notes:",
+    "How we use",
+    "Troubleshooting",
+    "Pricing for",
+    "A guide to",
+];
+
+lazy_static! {
+    static ref XSS: Regex = Regex::new(
+        r#"(?i)<\s*/?\s*(script|img|svg|iframe|body|details|object|embed|a)\b|javascript\s*:|\bon[a-z]+\s*=|\b(alert|prompt|confirm)\s*[(`]|document\s*\.\s*(cookie|domain)|["']\s*>"#
+    )
+    .unwrap();
+}
+
+/// whether the value looks like a cross-site scripting payload, such as
+/// `<script>alert(1)</script>` or `"><svg onload=...>`.
+pub fn is_xss_probe(value: &str) -> bool {
+    XSS.is_match(value)
+}
+
+/// Middleware which answers searches with generated results.
+#[derive(Clone)]
+pub struct Search {
+    seeder: Seeder,
+}
+
+impl Search {
+    pub fn new(seeder: Seeder) -> Self {
+        Self { seeder }
+    }
+}
+
+impl Middleware for Search {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            if req.method != Method::GET || !PATHS.contains(&req.url.path()) {
+                return next.run(req).await;
+            }
+
+            let params = req.query();
+            let query = PARAMS
+                .iter()
+                .find_map(|p| params.get(p))
+                .unwrap_or_default();
+            if is_xss_probe(query) {
+                info!(
+                    "{: <8} probed search for XSS with {:?}",
+                    req.requester(),
+                    query
+                );
+            }
+
+            let seed = self.seeder.for_request(req);
+            Ok(ResponseBuilder::ok(Arc::new(next.into_conn()))
+                .add_header("Content-Type", "text/html; charset=UTF-8")
+                .body(page(&seed, query))
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("search")
+    }
+}
+
+/// the results page for the query, the same for each seed and query.
+fn page(seed: &str, query: &str) -> String {
+    let escaped = escape_html(query);
+    let form = format!(
+        "<form action=\"/search\" method=\"get\"><input type=\"text\" name=\"q\" value=\"{}\">\
+         <button type=\"submit\">Search</button></form>",
+        escaped
+    );
+    if query.trim().is_empty() {
+        return format!(
+            "<!DOCTYPE html><html><head><title>Search</title></head><body>\
+             <h1>Search</h1>{}</body></html>",
+            form
+        );
+    }
+
+    let mut rng = StdRng::seed_from_u64(hash_seed(seed, &query.to_lowercase()));
+    let results = (0..rng.gen_range(0..=8))
+        .map(|_| {
+            let section = SECTIONS.choose(&mut rng).unwrap_or(&"blog");
+            let topic = TOPICS.choose(&mut rng).unwrap_or(&"A guide to");
+            format!(
+                "<li><a href=\"/{}/{}\">{} {}</a><p>&hellip; everything you need to know about \
+                 <b>{}</b>, updated {} days ago &hellip;</p></li>",
+                section,
+                rng.gen_range(100..10_000),
+                topic,
+                escaped,
+                escaped,
+                rng.gen_range(1..365)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let summary = match results.len() {
+        0 => format!("<p>No results found for <b>{}</b>.</p>", escaped),
+        n => format!("<p>{} results for <b>{}</b></p>", n, escaped),
+    };
+    format!(
+        "<!DOCTYPE html><html><head><title>{} - Search</title></head><body>\
+         <h1>Search</h1>{}{}<ol>{}</ol></body></html>",
+        escaped,
+        form,
+        summary,
+        results.concat()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_page() {
+        let page = page("seed", "<script>alert(1)</script>");
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert_eq!(
+            super::page("seed", "shoes"),
+            super::page("seed", "Shoes").replace("Shoes", "shoes")
+        );
+
+        assert!(super::page("seed", "").contains("<input type=\"text\" name=\"q\" value=\"\">"));
+    }
+
+    #[test]
+    fn test_is_xss_probe() {
+        for probe in [
+            "<script>alert(1)</script>",
+            "\"><svg/onload=alert`1`>",
+            "<img src=x onerror=prompt(document.domain)>",
+            "javascript:alert(1)",
+            "'>",
+        ] {
+            assert!(is_xss_probe(probe), "{}", probe);
+        }
+        for query in ["wireless headphones", "a < b", "1 > 0", "onboarding guide"] {
+            assert!(!is_xss_probe(query), "{}", query);
+        }
+    }
+}
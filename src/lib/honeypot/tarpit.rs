@@ -0,0 +1,176 @@
+//! Tarpits: fake backup archives and heap dumps hundreds of megabytes
+//! long, trickled out a few bytes a second. Scrapers which download
+//! everything they find hold a connection open for hours to get nothing.
+//! Only so many streams run at once, so the tarpit can't be turned
+//! against the honeypot.
+use std::{hash::Hash, sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use tokio::sync::Semaphore;
+
+use crate::{
+    fs::fake::gen_fake_file,
+    http::{
+        date,
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode, Trickle},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    persona::Persona,
+    prelude::*,
+    seed::Seeder,
+    util::hash_seed,
+};
+
+/// names of tarpit files, which are served under any directory.
+pub const FILES: &[&str] = &[
+    "backup.tar.gz",
+    "site-backup.zip",
+    "db_full.sql.gz",
+    "heapdump.hprof",
+];
+
+const MIN_SIZE: usize = 200 * 1024 * 1024;
+const MAX_SIZE: usize = 900 * 1024 * 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TarpitConfig {
+    /// how quickly files are sent
+    pub bytes_per_sec: u64,
+    /// streams sent at once. Requests beyond it are told the server is
+    /// busy.
+    pub max_streams: usize,
+    /// how long a stream lasts before the connection is closed
+    pub max_secs: u64,
+}
+
+impl Default for TarpitConfig {
+    fn default() -> Self {
+        Self {
+            bytes_per_sec: 16,
+            max_streams: 64,
+            max_secs: 6 * 60 * 60,
+        }
+    }
+}
+
+/// whether the path names a tarpit file.
+pub fn is_tarpit(path: &str) -> bool {
+    FILES.contains(&file_name(path))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+/// the size of the tarpit file at the path, as it's listed and sent.
+pub fn size<T: Hash>(seed: T, path: &str) -> usize {
+    MIN_SIZE + (hash_seed(&seed, path) % (MAX_SIZE - MIN_SIZE) as u64) as usize
+}
+
+/// the content type of the file and the bytes it starts with, so the
+/// first few seconds look like what was asked for.
+fn kind(name: &str) -> (&'static str, &'static [u8]) {
+    match name.rsplit_once('.').map(|(_, ext)| ext) {
+        Some("gz") => ("application/gzip", b"\x1f\x8b\x08\x00"),
+        Some("zip") => ("application/zip", b"PK\x03\x04\x14\x00\x00\x00\x08\x00"),
+        Some("hprof") => ("application/octet-stream", b"JAVA PROFILE 1.0.2\0"),
+        _ => ("application/octet-stream", b""),
+    }
+}
+
+/// Middleware trickling out tarpit files.
+#[derive(Debug, Clone)]
+pub struct Tarpit {
+    config: TarpitConfig,
+    seeder: Seeder,
+    persona: Persona,
+    streams: Arc<Semaphore>,
+}
+
+impl Tarpit {
+    pub fn new(config: TarpitConfig, seeder: Seeder, persona: Persona) -> Self {
+        Self {
+            streams: Arc::new(Semaphore::new(config.max_streams)),
+            config,
+            seeder,
+            persona,
+        }
+    }
+}
+
+impl Middleware for Tarpit {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let path = req.url.path();
+            if !matches!(req.method, Method::GET | Method::HEAD) || !is_tarpit(path) {
+                return next.run(req).await;
+            }
+
+            let seed = self.seeder.for_request(req);
+            let size = size(&seed, path);
+            let (content_type, magic) = kind(file_name(path));
+            let conn = next.into_conn();
+
+            // HEAD costs nothing, so it isn't counted against the streams
+            let permit = match req.method {
+                Method::HEAD => None,
+                _ => match self.streams.clone().try_acquire_owned() {
+                    Ok(permit) => Some(permit),
+                    Err(_) => {
+                        info!("{} tarpit is full, turning away", req.requester());
+                        return Ok(self
+                            .persona
+                            .error(conn, StatusCode::ServiceUnavailable, Some(req))
+                            .add_header("Retry-After", 120)
+                            .build()?);
+                    }
+                },
+            };
+
+            let mut resp = ResponseBuilder::ok(Arc::new(conn));
+            resp.body([])
+                .content_length(size)
+                .add_header("Content-Type", content_type);
+            // only some directories list one
+            if let Some(file) = gen_fake_file(&seed, path, self.seeder.tree()) {
+                resp.add_header("Last-Modified", date::format(file.modified_at));
+            }
+            if let Some(permit) = permit {
+                let mut trickle = Trickle::new(
+                    size as u64,
+                    self.config.bytes_per_sec,
+                    Duration::from_secs(self.config.max_secs),
+                    hash_seed(&seed, path),
+                );
+                trickle.prefix = magic.to_vec();
+                trickle.permit = Some(Arc::new(permit));
+
+                resp.set_header("Connection", "keep-alive")
+                    .add_header("Keep-Alive", format!("timeout={}", self.config.max_secs))
+                    .trickle(trickle);
+            }
+            Ok(resp.build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("tarpit")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_size() {
+        assert!(is_tarpit("/old/backup.tar.gz"));
+        assert!(!is_tarpit("/old/backup.tar"));
+
+        let size = size("seed", "/old/backup.tar.gz");
+        assert!((MIN_SIZE..MAX_SIZE).contains(&size));
+        assert_eq!(size, super::size("seed", "/old/backup.tar.gz"));
+    }
+}
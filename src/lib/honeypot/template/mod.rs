@@ -0,0 +1,74 @@
+//! Template-driven bait pages. Operators provide a directory of minijinja
+//! templates mirroring the URL space: `/admin/login.php` renders
+//! `admin/login.php` and `/admin/` renders `admin/index.html`. Paths
+//! without a template of their own are checked against `routes`, mapping
+//! patterns such as `/users/:id` to a template, and requests matching
+//! neither are passed on.
+//!
+//! Templates can use:
+//!  * `request`: method, path, query, version, remote, and lowercased headers
+//!  * `params`: parameters extracted by the matching route, if any
+//!  * `listing`: fake directory entries for the request's directory
+//!  * `honeytokens`: fake secrets unique to the request path and source
+//!
+//! Rendering them requires the `scripting` feature.
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+#[cfg(feature = "scripting")]
+mod render;
+#[cfg(not(feature = "scripting"))]
+pub use disabled::Templates;
+#[cfg(feature = "scripting")]
+pub use render::Templates;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplatesConfig {
+    /// directory of templates, mirroring request paths
+    pub dir: PathBuf,
+    /// templates rendered for paths matching patterns, checked in order
+    #[serde(default)]
+    pub routes: Vec<TemplateRoute>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateRoute {
+    /// path pattern, such as `/users/:id` or `/files/*path`
+    pub path: String,
+    /// template within the directory
+    pub template: String,
+}
+
+// stands in for minijinja in builds without the `scripting` feature,
+// refusing to start with templates configured.
+#[cfg(not(feature = "scripting"))]
+mod disabled {
+    use super::TemplatesConfig;
+    use crate::{
+        http::{request::Request, response::Response},
+        middleware::{BoxFuture, Middleware, Next},
+        prelude::*,
+        seed::Seeder,
+    };
+
+    pub enum Templates {}
+
+    impl Templates {
+        pub fn new(_config: &TemplatesConfig, _seeder: Seeder) -> Result<Self> {
+            bail!("templates are configured but httpot was built without the scripting feature")
+        }
+    }
+
+    impl Middleware for Templates {
+        fn call<'a>(
+            &'a self,
+            _req: &'a Request,
+            _next: Next<'a>,
+        ) -> BoxFuture<'a, Result<Response>> {
+            match *self {}
+        }
+    }
+}
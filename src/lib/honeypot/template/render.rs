@@ -0,0 +1,174 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use minijinja::{context, path_loader, Environment, ErrorKind, Value};
+
+use super::{TemplateRoute, TemplatesConfig};
+use crate::{
+    fs::fake::gen_fake_entries,
+    honeypot::honeytoken::Honeytokens,
+    http::{
+        request::Request,
+        response::{Response, ResponseBuilder},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    seed::Seeder,
+    util::{content_type_for_path, path_match},
+};
+
+/// Middleware which renders templates matching the request path.
+#[derive(Clone)]
+pub struct Templates {
+    env: Arc<Environment<'static>>,
+    routes: Arc<Vec<TemplateRoute>>,
+    seeder: Seeder,
+}
+
+impl Templates {
+    pub fn new(config: &TemplatesConfig, seeder: Seeder) -> Result<Self> {
+        let mut env = Environment::new();
+        env.set_loader(path_loader(&config.dir));
+
+        Ok(Self {
+            env: Arc::new(env),
+            routes: Arc::new(config.routes.clone()),
+            seeder,
+        })
+    }
+
+    /// renders the template for the request, if any, returning its name
+    /// and what it rendered.
+    fn render(&self, req: &Request) -> Result<Option<(String, String)>> {
+        let path = req.url.path();
+        let mut name = template_name(path);
+        let mut params = BTreeMap::new();
+        let tmpl = match self.env.get_template(&name) {
+            Ok(t) => t,
+            Err(e) if e.kind() == ErrorKind::TemplateNotFound => {
+                let route = self
+                    .routes
+                    .iter()
+                    .find_map(|r| Some((r, path_match(&r.path, path)?)));
+                match route {
+                    Some((route, matched)) => {
+                        name = route.template.clone();
+                        params = matched;
+                        self.env
+                            .get_template(&name)
+                            .map_err(|e| anyhow!("failed to load template {}: {}", name, e))?
+                    }
+                    None => return Ok(None),
+                }
+            }
+            Err(e) => bail!("failed to load template {}: {}", name, e),
+        };
+
+        let dir = &path[..=path.rfind('/').unwrap_or_default()];
+        let seed = self.seeder.for_request(req);
+        tmpl.render(context! {
+            request => request_value(req),
+            params => params,
+            listing => gen_fake_entries(&seed, dir, self.seeder.tree()),
+            honeytokens => Honeytokens::generate(&seed, path),
+        })
+        .map(|body| Some((name.clone(), body)))
+        .map_err(|e| anyhow!("failed to render template {}: {}", name, e))
+    }
+}
+
+impl Middleware for Templates {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match self.render(req)? {
+                Some((name, body)) => Ok(ResponseBuilder::ok(Arc::new(next.into_conn()))
+                    .add_header("Content-Type", content_type_for_path(&name))
+                    .body(body)
+                    .build()?),
+                None => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("template")
+    }
+}
+
+fn template_name(path: &str) -> String {
+    let name = path.trim_start_matches('/');
+    if name.is_empty() || name.ends_with('/') {
+        name.to_string() + "index.html"
+    } else {
+        name.to_string()
+    }
+}
+
+fn request_value(req: &Request) -> Value {
+    let headers = req
+        .headers
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.clone()))
+        .collect::<BTreeMap<_, _>>();
+
+    context! {
+        method => req.method.to_string(),
+        path => req.url.path(),
+        query => req.url.query().unwrap_or_default(),
+        version => req.version,
+        remote => req.requester(),
+        headers => headers,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(path: &str) -> Request {
+        Request::stub(Method::GET, path, "")
+    }
+
+    #[test]
+    fn test_render() {
+        let dir = std::env::temp_dir().join(format!("httpot-templates-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("admin")).unwrap();
+        std::fs::write(
+            dir.join("admin/index.html"),
+            "{{ request.path }} {{ honeytokens.aws_access_key_id }} {% if listing %}listing{% endif %}",
+        )
+        .unwrap();
+        let seeder = Seeder::new("seed", Default::default());
+        std::fs::write(dir.join("user.html"), "user {{ params.id }}").unwrap();
+        let config = TemplatesConfig {
+            dir,
+            routes: vec![TemplateRoute {
+                path: "/users/:id".to_string(),
+                template: "user.html".to_string(),
+            }],
+        };
+        let templates = Templates::new(&config, seeder.clone()).unwrap();
+
+        let req = stub_request("/admin/");
+        let (name, body) = templates.render(&req).unwrap().unwrap();
+        assert_eq!("admin/index.html", name);
+        let tokens = Honeytokens::generate(seeder.for_request(&req), "/admin/");
+        assert_eq!(
+            // html templates are escaped
+            format!("&#x2f;admin&#x2f; {} listing", tokens.aws_access_key_id),
+            body
+        );
+
+        let (name, body) = templates
+            .render(&stub_request("/users/7"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(("user.html", "user 7"), (name.as_str(), body.as_str()));
+
+        assert!(templates.render(&stub_request("/other")).unwrap().is_none());
+        assert!(templates
+            .render(&stub_request("/../../etc/passwd"))
+            .unwrap()
+            .is_none());
+    }
+}
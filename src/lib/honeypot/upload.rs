@@ -0,0 +1,249 @@
+//! PUT and DELETE answered like a misconfigured WebDAV share. Uploads are
+//! accepted and written to a quarantine directory named by their SHA-256,
+//! then served back to the source which uploaded them, so chains which
+//! upload a shell and then request it play out in full. Other sources
+//! don't see them, and deleting one only hides it; quarantined files are
+//! kept for analysis.
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    fetch::sha256_hex,
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    persona::Persona,
+    prelude::*,
+    util::content_type_for_path,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UploadConfig {
+    /// directory uploads are written to, named by their SHA-256
+    pub quarantine: PathBuf,
+    /// how long a source sees what it uploaded
+    pub session_secs: u64,
+    /// uploads remembered across every source
+    pub max_uploads: usize,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            quarantine: PathBuf::from("quarantine"),
+            session_secs: 60 * 60,
+            max_uploads: 10_000,
+        }
+    }
+}
+
+/// An upload as its source sees it.
+#[derive(Debug, Clone)]
+struct Upload {
+    sha256: String,
+    at: Instant,
+}
+
+/// Middleware accepting uploads.
+#[derive(Debug, Clone)]
+pub struct Uploads {
+    config: UploadConfig,
+    persona: Persona,
+    uploads: Arc<Mutex<HashMap<(IpAddr, String), Upload>>>,
+}
+
+impl Uploads {
+    pub fn new(config: UploadConfig, persona: Persona) -> Result<Self> {
+        std::fs::create_dir_all(&config.quarantine).map_err(|e| {
+            anyhow!(
+                "failed to create quarantine {}: {}",
+                config.quarantine.display(),
+                e
+            )
+        })?;
+
+        Ok(Self {
+            config,
+            persona,
+            uploads: Default::default(),
+        })
+    }
+
+    /// the upload the request's source made to its path, if it's still
+    /// visible to them.
+    fn find(&self, req: &Request) -> Option<Upload> {
+        let key = (req.requester_ip(), req.url.path().to_string());
+        let mut uploads = self.uploads.lock().unwrap();
+        match uploads.get(&key) {
+            Some(u) if u.at.elapsed() < Duration::from_secs(self.config.session_secs) => {
+                Some(u.clone())
+            }
+            Some(_) => {
+                uploads.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// quarantines the body, returning its hash and whether the source had
+    /// already uploaded to the path.
+    async fn put(&self, req: &Request) -> Result<(String, bool)> {
+        let sha256 = match &req.spooled {
+            Some(spooled) => spooled.sha256().to_string(),
+            None => sha256_hex(&req.body),
+        };
+        let path = self.config.quarantine.join(&sha256);
+        if !path.exists() {
+            match &req.spooled {
+                Some(spooled) => {
+                    tokio::fs::copy(spooled.path(), &path).await?;
+                }
+                None => tokio::fs::write(&path, &req.body).await?,
+            }
+        }
+
+        let replaced = self.find(req).is_some();
+        let mut uploads = self.uploads.lock().unwrap();
+        if uploads.len() >= self.config.max_uploads {
+            uploads.clear();
+        }
+        uploads.insert(
+            (req.requester_ip(), req.url.path().to_string()),
+            Upload {
+                sha256: sha256.clone(),
+                at: Instant::now(),
+            },
+        );
+        Ok((sha256, replaced))
+    }
+}
+
+impl Middleware for Uploads {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let path = req.url.path();
+            match req.method {
+                Method::PUT if !path.ends_with('/') => {
+                    let (sha256, replaced) = self.put(req).await?;
+                    info!(
+                        "{} put {} bytes to {} quarantined as {}",
+                        req.requester(),
+                        req.size,
+                        path,
+                        sha256
+                    );
+                    let status = match replaced {
+                        true => StatusCode::NoContent,
+                        false => StatusCode::Created,
+                    };
+                    Ok(ResponseBuilder::default(Arc::new(next.into_conn()))
+                        .status_code(status)
+                        .body([])
+                        .build()?)
+                }
+                Method::DELETE => match self.find(req) {
+                    Some(_) => {
+                        info!("{} deleted their upload {}", req.requester(), path);
+                        self.uploads
+                            .lock()
+                            .unwrap()
+                            .remove(&(req.requester_ip(), path.to_string()));
+                        Ok(ResponseBuilder::default(Arc::new(next.into_conn()))
+                            .status_code(StatusCode::NoContent)
+                            .body([])
+                            .build()?)
+                    }
+                    None => Ok(self
+                        .persona
+                        .error(next.into_conn(), StatusCode::NotFound, Some(req))
+                        .build()?),
+                },
+                Method::GET | Method::HEAD => match self.find(req) {
+                    Some(upload) => {
+                        // retention may have pruned it since
+                        let body = match tokio::fs::read(
+                            self.config.quarantine.join(&upload.sha256),
+                        )
+                        .await
+                        {
+                            Ok(body) => body,
+                            Err(_) => return next.run(req).await,
+                        };
+                        info!(
+                            "{} fetched their upload {} ({})",
+                            req.requester(),
+                            path,
+                            upload.sha256
+                        );
+                        let len = body.len();
+                        let body = match req.method {
+                            Method::HEAD => vec![],
+                            _ => body,
+                        };
+                        Ok(ResponseBuilder::ok(Arc::new(next.into_conn()))
+                            .body(body)
+                            .content_length(len)
+                            .add_header("Content-Type", content_type_for_path(path))
+                            .build()?)
+                    }
+                    None => next.run(req).await,
+                },
+                _ => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("upload")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::persona::Family;
+
+    fn stub_request(method: Method, path: &str, ip: &str, body: &[u8]) -> Request {
+        let mut req = Request::stub(method, path, body);
+        req.remote_ip = format!("{}:62012", ip).parse().unwrap();
+        req
+    }
+
+    #[tokio::test]
+    async fn test_uploads() {
+        let dir = std::env::temp_dir().join(format!("httpot-quarantine-{}", std::process::id()));
+        let uploads = Uploads::new(
+            UploadConfig {
+                quarantine: dir.clone(),
+                ..Default::default()
+            },
+            Persona::from(Family::Nginx),
+        )
+        .unwrap();
+
+        let shell = b"<?php system($_GET['c']); ?>";
+        let put = stub_request(Method::PUT, "/uploads/x.php", "203.0.113.7", shell);
+        let (sha256, replaced) = uploads.put(&put).await.unwrap();
+        assert!(!replaced);
+        assert_eq!(shell.to_vec(), std::fs::read(dir.join(&sha256)).unwrap());
+        assert!(uploads.put(&put).await.unwrap().1);
+
+        let get = stub_request(Method::GET, "/uploads/x.php", "203.0.113.7", b"");
+        assert_eq!(Some(sha256), uploads.find(&get).map(|u| u.sha256));
+        let other = stub_request(Method::GET, "/uploads/x.php", "198.51.100.1", b"");
+        assert!(uploads.find(&other).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,310 @@
+//! UPnP device descriptions and control for IoT devices. Router
+//! exploitation bots read the description to find the WANIPConnection
+//! control URL, then open ports or reflect traffic with AddPortMapping.
+//! Descriptions match the device being emulated, and control actions are
+//! logged with their arguments and always succeed.
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    honeypot::iot::Device,
+    http::{request::Request, response::StatusCode},
+    prelude::*,
+    util::escape_html,
+};
+
+/// paths device descriptions are commonly served at.
+pub const DESCRIPTION_PATHS: &[&str] = &[
+    "/rootDesc.xml",
+    "/description.xml",
+    "/igd.xml",
+    "/gatedesc.xml",
+    "/upnp/IGD.xml",
+];
+const CONTROL_PREFIX: &str = "/upnp/control/";
+const WAN_IP_CONNECTION: &str = "urn:schemas-upnp-org:service:WANIPConnection:1";
+/// the address routers report as their public one
+const EXTERNAL_IP: &str = "203.0.113.1";
+
+lazy_static! {
+    static ref ARGUMENT: Regex = Regex::new(r"<(New\w+)>([^<]*)</New\w+>").unwrap();
+}
+
+struct Model {
+    device_type: &'static str,
+    friendly_name: &'static str,
+    manufacturer: &'static str,
+    manufacturer_url: &'static str,
+    model_name: &'static str,
+    model_number: &'static str,
+    /// whether it's a gateway exposing WANIPConnection
+    gateway: bool,
+}
+
+fn model(device: Device) -> Model {
+    match device {
+        Device::Dvr => Model {
+            device_type: "urn:schemas-upnp-org:device:Basic:1",
+            friendly_name: "NVR",
+            manufacturer: "XiongMai",
+            manufacturer_url: "http://www.xiongmaitech.com",
+            model_name: "NBD80N16S-KL",
+            model_number: "V4.02.R11",
+            gateway: false,
+        },
+        Device::Camera => Model {
+            device_type: "urn:schemas-upnp-org:device:Basic:1",
+            friendly_name: "IPCAM",
+            manufacturer: "IPCAM",
+            manufacturer_url: "http://www.ipcam.com",
+            model_name: "IP Camera",
+            model_number: "V4.2.1",
+            gateway: false,
+        },
+        Device::Tplink => Model {
+            device_type: "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+            friendly_name: "Wireless N Router WR740N",
+            manufacturer: "TP-LINK",
+            manufacturer_url: "http://www.tp-link.com",
+            model_name: "TL-WR740N",
+            model_number: "5.0",
+            gateway: true,
+        },
+        Device::Mikrotik => Model {
+            device_type: "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+            friendly_name: "MikroTik Router",
+            manufacturer: "MikroTik",
+            manufacturer_url: "https://www.mikrotik.com/",
+            model_name: "Router OS",
+            model_number: "6.42.1",
+            gateway: true,
+        },
+        Device::Boa => Model {
+            device_type: "urn:schemas-upnp-org:device:InternetGatewayDevice:1",
+            friendly_name: "GPON Home Gateway",
+            manufacturer: "DASAN Networks",
+            manufacturer_url: "http://www.dasannetworks.com",
+            model_name: "H640GR",
+            model_number: "1.0",
+            gateway: true,
+        },
+        Device::Jetdirect => Model {
+            device_type: "urn:schemas-upnp-org:device:Printer:1",
+            friendly_name: "HP LaserJet MFP M426fdn (NPI4C1B2D)",
+            manufacturer: "HP",
+            manufacturer_url: "http://www.hp.com",
+            model_name: "HP LaserJet MFP M426fdn",
+            model_number: "F6W17A",
+            gateway: false,
+        },
+        Device::Cups => Model {
+            device_type: "urn:schemas-upnp-org:device:Printer:1",
+            friendly_name: "HP_LaserJet @ printserver",
+            manufacturer: "CUPS",
+            manufacturer_url: "https://www.cups.org/",
+            model_name: "CUPS",
+            model_number: "2.2.10",
+            gateway: false,
+        },
+        Device::Synology => Model {
+            device_type: "urn:schemas-upnp-org:device:Basic:1",
+            friendly_name: "DiskStation (DS918+)",
+            manufacturer: "Synology",
+            manufacturer_url: "http://www.synology.com",
+            model_name: "DS918+",
+            model_number: "DS918+ 6.2.3-25426",
+            gateway: false,
+        },
+        Device::Qnap => Model {
+            device_type: "urn:schemas-upnp-org:device:Basic:1",
+            friendly_name: "NAS5E2A1B",
+            manufacturer: "QNAP Systems, Inc.",
+            manufacturer_url: "http://www.qnap.com/",
+            model_name: "TS-451",
+            model_number: "4.3.6",
+            gateway: false,
+        },
+    }
+}
+
+/// the status and body of the device's UPnP response to the
+/// request, if it's for UPnP.
+pub fn respond(device: Device, req: &Request) -> Option<(StatusCode, String)> {
+    let path = req.url.path();
+    if DESCRIPTION_PATHS
+        .iter()
+        .any(|p| p.eq_ignore_ascii_case(path))
+    {
+        return Some((StatusCode::Ok, description(device)));
+    }
+    if path.starts_with(CONTROL_PREFIX) && model(device).gateway {
+        return Some(control(req));
+    }
+    None
+}
+
+fn description(device: Device) -> String {
+    let model = model(device);
+    let services = if model.gateway {
+        format!(
+            "<serviceList><service><serviceType>{}</serviceType><serviceId>urn:upnp-org:serviceId:WANIPConn1</serviceId>\
+             <controlURL>{}WANIPConn1</controlURL><eventSubURL>/upnp/event/WANIPConn1</eventSubURL><SCPDURL>/WANIPCn.xml</SCPDURL></service></serviceList>",
+            WAN_IP_CONNECTION, CONTROL_PREFIX
+        )
+    } else {
+        String::new()
+    };
+
+    format!(
+        r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+<specVersion><major>1</major><minor>0</minor></specVersion>
+<device>
+<deviceType>{}</deviceType>
+<friendlyName>{}</friendlyName>
+<manufacturer>{}</manufacturer>
+<manufacturerURL>{}</manufacturerURL>
+<modelDescription>{}</modelDescription>
+<modelName>{}</modelName>
+<modelNumber>{}</modelNumber>
+<serialNumber>00000000</serialNumber>
+<UDN>uuid:{}</UDN>
+{}
+<presentationURL>/</presentationURL>
+</device>
+</root>
+"#,
+        model.device_type,
+        escape_html(model.friendly_name),
+        escape_html(model.manufacturer),
+        model.manufacturer_url,
+        escape_html(model.friendly_name),
+        escape_html(model.model_name),
+        escape_html(model.model_number),
+        udn(device),
+        services,
+    )
+}
+
+/// a stable UDN for the device type.
+fn udn(device: Device) -> String {
+    let n = device as u32;
+    format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        0x3b2c_0000 + n,
+        0x1dd2,
+        0x11b2,
+        0xa000 + n,
+        0x00e0_4c00_0000u64 + n as u64
+    )
+}
+
+/// answers a control action, logging it and its arguments.
+fn control(req: &Request) -> (StatusCode, String) {
+    let body = String::from_utf8_lossy(&req.body);
+    let action = req
+        .headers
+        .get("SOAPAction")
+        .into_iter()
+        .flatten()
+        .find_map(|v| {
+            v.trim_matches('"')
+                .rsplit_once('#')
+                .map(|(_, a)| a.to_string())
+        })
+        .unwrap_or_default();
+    let arguments = ARGUMENT
+        .captures_iter(&body)
+        .map(|c| format!("{}={}", &c[1], &c[2]))
+        .collect::<Vec<_>>();
+
+    info!(
+        "{: <8} requested UPnP {:?} with {}",
+        req.requester(),
+        action,
+        arguments.join(" ")
+    );
+
+    let result = match action.as_str() {
+        "GetExternalIPAddress" => {
+            format!("<NewExternalIPAddress>{}</NewExternalIPAddress>", EXTERNAL_IP)
+        }
+        "GetStatusInfo" => "<NewConnectionStatus>Connected</NewConnectionStatus>\
+             <NewLastConnectionError>ERROR_NONE</NewLastConnectionError><NewUptime>1209600</NewUptime>"
+            .to_string(),
+        "" => {
+            return (
+                StatusCode::InternalServerError,
+                envelope("<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring>\
+                          <detail><UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\"><errorCode>401</errorCode>\
+                          <errorDescription>Invalid Action</errorDescription></UPnPError></detail></s:Fault>"),
+            )
+        }
+        _ => String::new(),
+    };
+
+    let action = escape_html(&action);
+    (
+        StatusCode::Ok,
+        envelope(&format!(
+            "<u:{}Response xmlns:u=\"{}\">{}</u:{}Response>",
+            action, WAN_IP_CONNECTION, result, action
+        )),
+    )
+}
+
+fn envelope(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\r\n<s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>{}</s:Body></s:Envelope>\r\n",
+        body
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    #[test]
+    fn test_description() {
+        let (_, body) = respond(
+            Device::Tplink,
+            &Request::stub(Method::GET, "/rootDesc.xml", ""),
+        )
+        .unwrap();
+        assert!(body.contains("<modelName>TL-WR740N</modelName>"));
+        assert!(body.contains("<controlURL>/upnp/control/WANIPConn1</controlURL>"));
+
+        let (_, body) = respond(
+            Device::Dvr,
+            &Request::stub(Method::GET, "/description.xml", ""),
+        )
+        .unwrap();
+        assert!(!body.contains("WANIPConnection"));
+        assert!(respond(
+            Device::Dvr,
+            &Request::stub(Method::POST, "/upnp/control/WANIPConn1", "")
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_control() {
+        let mut req = Request::stub(
+            Method::POST,
+            "/upnp/control/WANIPConn1",
+            "<s:Envelope><s:Body><u:AddPortMapping xmlns:u=\"urn:schemas-upnp-org:service:WANIPConnection:1\">\
+             <NewRemoteHost></NewRemoteHost><NewExternalPort>47000</NewExternalPort><NewProtocol>TCP</NewProtocol>\
+             <NewInternalPort>443</NewInternalPort><NewInternalClient>198.51.100.7</NewInternalClient>\
+             </u:AddPortMapping></s:Body></s:Envelope>",
+        );
+        req.headers.add(
+            "SOAPAction",
+            "\"urn:schemas-upnp-org:service:WANIPConnection:1#AddPortMapping\"",
+        );
+        let (status, body) = respond(Device::Boa, &req).unwrap();
+        assert_eq!(StatusCode::Ok, status);
+        assert!(body.contains("<u:AddPortMappingResponse"));
+    }
+}
@@ -0,0 +1,383 @@
+//! SSL-VPN portals, among the most exploited edge devices: Palo Alto's
+//! GlobalProtect and SonicWall's SMA and SonicOS SSL-VPN. Logins through
+//! their web forms and client APIs are logged and refused. Requests
+//! carrying the parameters of their well known RCEs and auth bypasses are
+//! named by their CVE, see `cve`, and what they smuggled is logged.
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+
+use crate::{
+    http::{
+        query::Query,
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+};
+
+/// the SMA 100 firmware the SonicWall claims, before CVE-2021-20038's fix
+const SMA_VERSION: &str = "10.2.0.7-34sv";
+
+/// the CVE a request exploits, if it carries a known VPN exploit's
+/// parameters.
+pub fn cve(req: &Request) -> Option<&'static str> {
+    let path = req.url.path().to_lowercase();
+    let target = req.target.to_lowercase();
+
+    // the session id is used as a file path by the telemetry job
+    let globalprotect = path.starts_with("/ssl-vpn/") || path.starts_with("/global-protect/");
+    if globalprotect && cookie(req, "SESSID").is_some_and(|s| s.contains("..")) {
+        return Some("CVE-2024-3400");
+    }
+    // a format string in the SCEP profile name
+    if path == "/sslmgr" && field(req, "scep-profile-name").is_some_and(|p| p.contains('%')) {
+        return Some("CVE-2019-1579");
+    }
+    // a doubly encoded traversal out of /unauth/ onto the management UI
+    if target.contains("/unauth/") && target.contains("%252e%252e") {
+        return Some("CVE-2025-0108");
+    }
+    // the SSL-VPN's CGI scripts run under a vulnerable bash
+    let shellshock = req
+        .headers
+        .iter()
+        .flat_map(|(_, v)| v.iter())
+        .any(|v| v.trim_start().starts_with("() {"));
+    if path == "/cgi-bin/jarrewrite.sh" && shellshock {
+        return Some("CVE-2014-6271");
+    }
+    // a swap cookie of nul bytes is taken as a valid session
+    let nul_session = cookie(req, "swap")
+        .and_then(|s| STANDARD.decode(s.trim()).ok())
+        .is_some_and(|s| s.first() == Some(&0));
+    if path == "/cgi-bin/sslvpnclient" && nul_session {
+        return Some("CVE-2024-53704");
+    }
+    None
+}
+
+// the value of a cookie
+fn cookie(req: &Request, name: &str) -> Option<String> {
+    req.headers
+        .get("Cookie")
+        .into_iter()
+        .flatten()
+        .flat_map(|v| v.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(k, _)| *k == name)
+        .map(|(_, v)| v.to_string())
+}
+
+// the value of a form or query field
+fn field(req: &Request, name: &str) -> Option<String> {
+    let query = Query::parse(req.url.query().unwrap_or_default());
+    let form = Query::parse(&String::from_utf8_lossy(&req.body));
+    form.get(name)
+        .or_else(|| query.get(name))
+        .map(str::to_string)
+}
+
+/// A portal response, before it's bound to a connection.
+#[derive(Debug)]
+struct Page {
+    status: StatusCode,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Page {
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "text/html; charset=UTF-8",
+            body,
+        }
+    }
+
+    fn xml(body: String) -> Self {
+        Self {
+            content_type: "application/xml; charset=UTF-8",
+            ..Self::html(body)
+        }
+    }
+}
+
+/// Middleware which serves fake GlobalProtect and SonicWall SSL-VPN
+/// portals at their usual paths.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VpnPortals;
+
+impl VpnPortals {
+    fn respond(&self, req: &Request) -> Option<Page> {
+        let path = req.url.path().to_lowercase();
+        if let Some(cve) = cve(req) {
+            let cookies: Vec<_> = req.headers.get("Cookie").into_iter().flatten().collect();
+            info!(
+                "{: <8} tried {} at {:?} with cookies {:?}",
+                req.requester(),
+                cve,
+                req.target,
+                cookies
+            );
+        }
+
+        match path.as_str() {
+            "/global-protect/login.esp" | "/global-protect/portal/portal.esp" => {
+                Some(match login(req, "GlobalProtect", "user", "passwd") {
+                    true => Page::html(globalprotect_login(Some(
+                        "Authentication failed: Invalid username or password",
+                    ))),
+                    false => Page::html(globalprotect_login(None)),
+                })
+            }
+            "/global-protect/prelogin.esp" | "/ssl-vpn/prelogin.esp" => {
+                Some(Page::xml(globalprotect_prelogin()))
+            }
+            "/ssl-vpn/login.esp" | "/global-protect/getconfig.esp" => {
+                login(req, "GlobalProtect", "user", "passwd");
+                Some(Page::xml(
+                    "<response status=\"error\">\n<error>Invalid username or password</error>\n</response>\n"
+                        .to_string(),
+                ))
+            }
+            "/ssl-vpn/hipreport.esp" | "/sslmgr" => Some(Page::xml(
+                "<response status=\"error\">\n<error>Invalid Cookie</error>\n</response>\n"
+                    .to_string(),
+            )),
+            "/cgi-bin/welcome" | "/sslvpnlogin.html" => Some(Page::html(sonicwall_login(None))),
+            "/cgi-bin/userlogin" => Some(match login(req, "SonicWall", "username", "password") {
+                true => Page::html(sonicwall_login(Some(
+                    "Incorrect name/password. Please try again.",
+                ))),
+                false => Page::html(sonicwall_login(None)),
+            }),
+            "/__api__/v1/logon" => {
+                let body = serde_json::from_slice::<Value>(&req.body).unwrap_or_default();
+                info!(
+                    "{: <8} tried to log into the SonicWall API as {:?} with {:?}",
+                    req.requester(),
+                    body["username"].as_str().unwrap_or_default(),
+                    body["password"].as_str().unwrap_or_default()
+                );
+                Some(Page {
+                    status: StatusCode::Unauthorized,
+                    content_type: "application/json",
+                    body: json!({"status": "fail", "message": "Invalid credentials"}).to_string(),
+                })
+            }
+            "/cgi-bin/jarrewrite.sh" => Some(Page {
+                content_type: "text/plain",
+                ..Page::html(String::new())
+            }),
+            "/cgi-bin/sslvpnclient" => Some(Page::html(
+                "<html><head><script>location.href='/cgi-bin/welcome';</script></head></html>\n"
+                    .to_string(),
+            )),
+            _ => None,
+        }
+    }
+}
+
+// logs a login to the portal, returning whether the request was one
+fn login(req: &Request, portal: &str, user_field: &str, pass_field: &str) -> bool {
+    if req.method != Method::POST {
+        return false;
+    }
+    match field(req, user_field) {
+        Some(user) => {
+            info!(
+                "{: <8} tried to log into {} as {:?} with {:?}",
+                req.requester(),
+                portal,
+                user,
+                field(req, pass_field).unwrap_or_default()
+            );
+            true
+        }
+        None => false,
+    }
+}
+
+impl Middleware for VpnPortals {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let page = match self.respond(req) {
+                Some(page) => page,
+                None => return next.run(req).await,
+            };
+            Ok(ResponseBuilder::default(Arc::new(next.into_conn()))
+                .status_code(page.status)
+                .add_header("Content-Type", page.content_type)
+                .body(page.body)
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("vpn")
+    }
+}
+
+fn globalprotect_login(error: Option<&str>) -> String {
+    let error = error
+        .map(|e| format!(r#"<p id="error" class="error">{}</p>"#, e))
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>GlobalProtect Portal</title>
+<link rel="stylesheet" type="text/css" href="/global-protect/portal/css/login.css">
+<script type="text/javascript" src="/global-protect/portal/js/jquery.min.js"></script>
+</head>
+<body class="login-page">
+<div id="content">
+<div id="heading"><img src="/global-protect/portal/images/logo-pan-48525a.svg" alt="paloalto"><h1>GlobalProtect Portal</h1></div>
+{error}
+<form name="login" method="post" action="/global-protect/login.esp">
+<input type="hidden" name="prot" value="https:">
+<input type="hidden" name="server" value="">
+<input type="hidden" name="inputStr" value="">
+<input type="hidden" name="action" value="getsoftware">
+<label for="user">Username</label><input type="text" id="user" name="user" autocomplete="username">
+<label for="passwd">Password</label><input type="password" id="passwd" name="passwd" autocomplete="current-password">
+<input type="submit" id="submit" name="ok" value="LOG IN">
+</form>
+</div>
+</body>
+</html>
+"#,
+        error = error,
+    )
+}
+
+fn globalprotect_prelogin() -> String {
+    r#"<?xml version="1.0" encoding="UTF-8" ?>
+<prelogin-response>
+<status>Success</status>
+<ccusrmsg></ccusrmsg>
+<autosubmit>false</autosubmit>
+<authentication-message>Enter login credentials</authentication-message>
+<username-label>Username</username-label>
+<password-label>Password</password-label>
+<panos-version>1</panos-version>
+<region>US</region>
+</prelogin-response>
+"#
+    .to_string()
+}
+
+fn sonicwall_login(error: Option<&str>) -> String {
+    let error = error
+        .map(|e| format!(r#"<div id="loginErrorMsg" class="errorMsg">{}</div>"#, e))
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=UTF-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Virtual Office</title>
+<link rel="stylesheet" type="text/css" href="/css/sonicui.css?v={version}">
+<script type="text/javascript" src="/js/login.js?v={version}"></script>
+</head>
+<body class="login">
+<div id="loginBox">
+<div class="logo"><img src="/images/sonicwall_logo.png" alt="SonicWall"></div>
+<h2>Secure Mobile Access</h2>
+{error}
+<form id="loginForm" name="Login" method="post" action="/cgi-bin/userLogin">
+<input type="hidden" name="portalname" value="VirtualOffice">
+<label for="username">Username</label><input type="text" id="username" name="username" autocomplete="off">
+<label for="password">Password</label><input type="password" id="password" name="password" autocomplete="off">
+<label for="domain">Domain</label><select id="domain" name="domain"><option value="LocalDomain" selected>LocalDomain</option></select>
+<input type="submit" id="submitBtn" name="login" value="Log In">
+</form>
+</div>
+<div id="footer">&copy; 2021 SonicWall. All Rights Reserved.</div>
+</body>
+</html>
+"#,
+        version = SMA_VERSION,
+        error = error,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn stub_request(method: Method, target: &str, cookie: Option<&str>) -> Request {
+        let mut req = Request::stub(method, target, "");
+        if let Some(cookie) = cookie {
+            req.headers.add("Cookie", cookie);
+        }
+        req
+    }
+
+    #[test]
+    fn test_cve() {
+        let cases = vec![
+            (
+                "/ssl-vpn/hipreport.esp",
+                Some("SESSID=/../../../opt/panlogs/tmp/device_telemetry/minute/x`id`"),
+                Some("CVE-2024-3400"),
+            ),
+            ("/ssl-vpn/hipreport.esp", Some("SESSID=abc"), None),
+            (
+                "/sslmgr?scep-profile-name=%25n%25n%25n",
+                None,
+                Some("CVE-2019-1579"),
+            ),
+            (
+                "/unauth/%252e%252e/php/ztp_gate.php/PAN_help/x.css",
+                None,
+                Some("CVE-2025-0108"),
+            ),
+            (
+                "/cgi-bin/sslvpnclient?launchplatform=mac",
+                Some("swap=AAAAAAAAAAAAAAAAAAAAAAAAAAAA"),
+                Some("CVE-2024-53704"),
+            ),
+            ("/cgi-bin/sslvpnclient", Some("swap=c2Vzc2lvbg=="), None),
+            ("/global-protect/login.esp", None, None),
+        ];
+        for (target, cookie, expected) in cases {
+            let req = stub_request(Method::GET, target, cookie);
+            assert_eq!(expected, cve(&req), "{}", target);
+        }
+
+        let mut req = stub_request(Method::GET, "/cgi-bin/jarrewrite.sh", None);
+        req.headers
+            .add("User-Agent", "() { :; }; echo ; /bin/bash -c 'id'");
+        assert_eq!(Some("CVE-2014-6271"), cve(&req));
+    }
+
+    #[test]
+    fn test_portals() {
+        let mut req = stub_request(Method::POST, "/global-protect/login.esp", None);
+        req.body = b"prot=https%3A&user=vpnuser&passwd=hunter2&ok=Log+In".to_vec();
+        let page = VpnPortals.respond(&req).unwrap();
+        assert!(page.body.contains("Invalid username or password"));
+
+        let page = VpnPortals
+            .respond(&stub_request(Method::GET, "/cgi-bin/welcome", None))
+            .unwrap();
+        assert!(page.body.contains(SMA_VERSION));
+        assert!(!page.body.contains("Incorrect"));
+
+        let page = VpnPortals
+            .respond(&stub_request(Method::POST, "/__api__/v1/logon", None))
+            .unwrap();
+        assert_eq!(StatusCode::Unauthorized, page.status);
+
+        assert!(VpnPortals
+            .respond(&stub_request(Method::GET, "/global-protect/", None))
+            .is_none());
+    }
+}
@@ -0,0 +1,492 @@
+//! Fake Roundcube and SquirrelMail webmail logins at their usual install
+//! paths, with configurable versions in their banners. Logins are refused,
+//! and their credentials captured like any other form's by
+//! `profile::credentials`. Endpoints of well known CVEs answer too, logging
+//! what's sent to them: Roundcube's installer (CVE-2020-12641), its upload
+//! `_from` deserialization (CVE-2025-49113), and sendmail arguments in
+//! SquirrelMail's options (CVE-2017-7692).
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    display,
+    honeypot::{mailer, php::Php},
+    http::{
+        query::Query,
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::escape_html,
+};
+
+/// where webmail is installed; SquirrelMail's pages are under `src/`
+const ROUNDCUBE_PREFIXES: &[&str] = &["/roundcube", "/roundcubemail", "/webmail", "/rc", "/mail"];
+const SQUIRRELMAIL_PREFIXES: &[&str] = &["/squirrelmail", "/sqmail"];
+
+lazy_static! {
+    /// a serialized PHP object, as smuggled through `_from`
+    static ref PHP_OBJECT: Regex = Regex::new(r#"(^|[|;{])O:\d+:""#).unwrap();
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebmailConfig {
+    /// Roundcube version in banners
+    pub roundcube: String,
+    /// SquirrelMail version in banners
+    pub squirrelmail: String,
+}
+
+impl Default for WebmailConfig {
+    fn default() -> Self {
+        Self {
+            roundcube: "1.6.6".to_string(),
+            squirrelmail: "1.4.22".to_string(),
+        }
+    }
+}
+
+/// A webmail page, before it's bound to a connection.
+#[derive(Debug)]
+struct Page {
+    status: StatusCode,
+    content_type: &'static str,
+    location: Option<String>,
+    body: String,
+}
+
+impl Page {
+    fn html(body: String) -> Self {
+        Self {
+            status: StatusCode::Ok,
+            content_type: "text/html; charset=UTF-8",
+            location: None,
+            body,
+        }
+    }
+
+    fn text(body: String) -> Self {
+        Self {
+            content_type: "text/plain; charset=UTF-8",
+            ..Self::html(body)
+        }
+    }
+
+    fn redirect(location: String) -> Self {
+        Self {
+            status: StatusCode::Found,
+            location: Some(location),
+            ..Self::html(String::new())
+        }
+    }
+}
+
+/// Middleware which serves the fake webmail.
+#[derive(Debug, Clone, Default)]
+pub struct Webmail {
+    config: WebmailConfig,
+    php: Php,
+}
+
+impl Webmail {
+    pub fn new(config: WebmailConfig, php: Php) -> Self {
+        Self { config, php }
+    }
+
+    fn respond(&self, req: &Request) -> Option<Page> {
+        let lower = req.url.path().to_lowercase();
+        match (
+            split_prefix(&lower, SQUIRRELMAIL_PREFIXES),
+            split_prefix(&lower, ROUNDCUBE_PREFIXES),
+        ) {
+            (Some((prefix, rest)), _) => Some(self.squirrelmail(req, prefix, rest)),
+            (None, Some((prefix, rest))) if rest == "/src" || rest.starts_with("/src/") => {
+                Some(self.squirrelmail(req, prefix, rest))
+            }
+            (None, Some((prefix, rest))) => Some(self.roundcube(req, prefix, rest)),
+            (None, None) => None,
+        }
+    }
+
+    fn roundcube(&self, req: &Request, prefix: &str, rest: &str) -> Page {
+        let fields = fields(req);
+        let field = |name: &str| fields.get(name).unwrap_or_default();
+
+        match rest {
+            "" => return Page::redirect(format!("{}/", prefix)),
+            "/changelog" | "/changelog.md" => {
+                return Page::text(format!(
+                    "# Changelog Roundcube Webmail\n\n## Release {}\n\n\
+                     - Fix bug where HTML messages could be displayed with unsafe content\n\
+                     - Fix various PHP warnings\n",
+                    self.config.roundcube
+                ))
+            }
+            "/installer" => return Page::redirect(format!("{}/installer/", prefix)),
+            rest if rest.starts_with("/installer/") => {
+                if req.method == Method::POST {
+                    info!(
+                        "{: <8} configured Roundcube's installer with {}",
+                        req.requester(),
+                        display::truncate(&String::from_utf8_lossy(&req.body), 200)
+                    );
+                }
+                return Page::html(roundcube_installer(&self.config.roundcube));
+            }
+            _ => {}
+        }
+
+        if PHP_OBJECT.is_match(field("_from")) {
+            info!(
+                "{: <8} sent Roundcube a serialized object in _from: {:?}",
+                req.requester(),
+                field("_from")
+            );
+        }
+        if req.method == Method::POST && !field("_user").is_empty() {
+            info!(
+                "{: <8} tried to log into Roundcube as {:?}",
+                req.requester(),
+                field("_user")
+            );
+            return Page {
+                status: StatusCode::Unauthorized,
+                ..Page::html(roundcube_login(
+                    &self.config.roundcube,
+                    Some("Login failed."),
+                ))
+            };
+        }
+        Page::html(roundcube_login(&self.config.roundcube, None))
+    }
+
+    fn squirrelmail(&self, req: &Request, prefix: &str, rest: &str) -> Page {
+        let fields = fields(req);
+        if let Some(p) = fields.iter().find(|p| mailer::has_sendmail_args(&p.value)) {
+            info!(
+                "{: <8} smuggled sendmail arguments into SquirrelMail's {:?}: {:?}",
+                req.requester(),
+                p.name,
+                p.value
+            );
+        }
+
+        let version = &self.config.squirrelmail;
+        match rest {
+            "" | "/" | "/index.php" | "/src" | "/src/" => {
+                Page::redirect(format!("{}/src/login.php", prefix))
+            }
+            "/changelog" => Page::text(format!(
+                "Version {} - 12 July 2011\n----------------------------\n  \
+                 - Fixed XSS vulnerabilities in the SquirrelSpell plugin\n",
+                version
+            )),
+            "/src/redirect.php" if req.method == Method::POST => {
+                info!(
+                    "{: <8} tried to log into SquirrelMail as {:?}",
+                    req.requester(),
+                    fields.get("login_username").unwrap_or_default()
+                );
+                Page::html(squirrelmail_error(
+                    version,
+                    "Unknown user or password incorrect.",
+                ))
+            }
+            // everything else needs a session first
+            _ if rest.starts_with("/src/") && rest != "/src/login.php" => Page::html(
+                squirrelmail_error(version, "You must be logged in to access this page."),
+            ),
+            _ => Page::html(squirrelmail_login(version)),
+        }
+    }
+}
+
+impl Middleware for Webmail {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let page = match self.respond(req) {
+                Some(page) => page,
+                None => return next.run(req).await,
+            };
+
+            let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+            self.php
+                .expose(&mut builder)
+                .status_code(page.status)
+                .add_header("Content-Type", page.content_type);
+            if let Some(location) = page.location {
+                builder.add_header("Location", location);
+            }
+            Ok(builder.body(page.body).build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("webmail")
+    }
+}
+
+// the install prefix the path is under, and the rest of the path
+fn split_prefix<'a>(path: &'a str, prefixes: &[&'static str]) -> Option<(&'static str, &'a str)> {
+    prefixes.iter().find_map(|p| match path.strip_prefix(p) {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => Some((*p, rest)),
+        _ => None,
+    })
+}
+
+// query and form fields together, as PHP's $_REQUEST has them
+fn fields(req: &Request) -> Query {
+    Query::parse(&format!(
+        "{}&{}",
+        req.url.query().unwrap_or_default(),
+        String::from_utf8_lossy(&req.body)
+    ))
+}
+
+// Roundcube's version as the number its client environment carries, such
+// as 10606 for 1.6.6
+fn rcversion(version: &str) -> u64 {
+    version
+        .split('.')
+        .take(3)
+        .map(|part| {
+            part.chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse::<u64>()
+                .unwrap_or(0)
+        })
+        .chain(std::iter::repeat(0))
+        .take(3)
+        .fold(0, |acc, part| acc * 100 + part)
+}
+
+fn roundcube_login(version: &str, error: Option<&str>) -> String {
+    let message = error
+        .map(|e| {
+            format!(
+                "<div id=\"messagestack\"><div class=\"error\">{}</div></div>",
+                e
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Roundcube Webmail :: Welcome to Roundcube Webmail</title>
+<meta name="viewport" content="width=device-width, initial-scale=1.0, shrink-to-fit=no, maximum-scale=1.0">
+<meta name="referrer" content="same-origin">
+<link rel="shortcut icon" href="skins/elastic/images/favicon.ico">
+<link rel="stylesheet" href="skins/elastic/deps/bootstrap.min.css">
+<link rel="stylesheet" href="skins/elastic/styles/styles.min.css">
+<script src="program/js/jquery.min.js"></script>
+<script src="program/js/common.min.js"></script>
+<script src="program/js/app.min.js"></script>
+<script>
+var rcmail = new rcube_webmail();
+rcmail.set_env({{"task":"login","standard_windows":false,"locale":"en_US","devel_mode":null,"rcversion":{rcversion},"cookie_domain":"","cookie_path":"/","cookie_secure":false,"skin":"elastic","compose_extwin":false,"request_token":"4n3YjdOl8OnRc91s7wmBr8yJBWIbg4o9","session_lifetime":600,"action":"","comm_path":"./?_task=login"}});
+</script>
+</head>
+<body class="task-login action-none">
+<div id="layout">
+<h1 class="voice">Roundcube Webmail Login</h1>
+<div id="layout-content" class="selected no-navbar" role="main">
+<img src="skins/elastic/images/logo.svg" id="logo" alt="Logo">
+{message}
+<form id="login-form" name="login-form" method="post" class="propform" action="./?_task=login">
+<input type="hidden" name="_token" value="4n3YjdOl8OnRc91s7wmBr8yJBWIbg4o9">
+<input type="hidden" name="_task" value="login"><input type="hidden" name="_action" value="login"><input type="hidden" name="_timezone" id="rcmlogintz" value="_default_"><input type="hidden" name="_url" id="rcmloginurl" value="">
+<table><tbody>
+<tr><td class="title"><label for="rcmloginuser">Username</label></td><td class="input"><input name="_user" id="rcmloginuser" required size="40" autocapitalize="off" autocomplete="off" type="text"></td></tr>
+<tr><td class="title"><label for="rcmloginpwd">Password</label></td><td class="input"><input name="_pass" id="rcmloginpwd" required size="40" autocapitalize="off" autocomplete="off" type="password"></td></tr>
+</tbody></table>
+<p class="formbuttons"><button type="submit" id="rcmloginsubmit" class="button mainaction submit">Login</button></p>
+</form>
+</div>
+<div id="login-footer" role="contentinfo">Roundcube Webmail <span class="version">{version}</span></div>
+</div>
+</body>
+</html>
+"#,
+        rcversion = rcversion(version),
+        message = message,
+        version = escape_html(version),
+    )
+}
+
+fn roundcube_installer(version: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<title>Roundcube Webmail Installer</title>
+<meta http-equiv="Content-Type" content="text/html; charset=UTF-8">
+<link rel="stylesheet" type="text/css" href="styles.css">
+</head>
+<body>
+<div id="banner"><div class="banner-bg"></div><div class="banner-logo"><a href="http://roundcube.net"><img src="images/roundcube_logo.png" width="210" height="55" border="0" alt="Roundcube - open source webmail software"></a></div></div>
+<div id="topnav"><a href="https://github.com/roundcube/roundcubemail/wiki/Installation">How-to Wiki</a></div>
+<div id="content">
+<h1>Roundcube Webmail Installer</h1>
+<ol id="progress"><li class="current">Check environment</li><li>Create config</li><li>Test config</li></ol>
+<form action="index.php?_step=2" method="post">
+<h3>Checking PHP version</h3><p>Version: <span class="success">OK</span>(PHP 7.4.33 detected)</p>
+<h3>Checking PHP extensions</h3><p>PCRE: <span class="success">OK</span><br>DOM: <span class="success">OK</span><br>Session: <span class="success">OK</span><br>OpenSSL: <span class="success">OK</span></p>
+<h3>Checking available databases</h3><p>MySQL: <span class="success">OK</span><br>SQLite: <span class="success">OK</span></p>
+<p><input type="submit" value="NEXT"></p>
+</form>
+</div>
+<div id="footer">Installer by the Roundcube Dev Team. Copyright &copy; 2008-2021 - Published under the GNU Public License;&nbsp; Icons by <a href="http://famfamfam.com">famfamfam</a><br>Roundcube Webmail {}</div>
+</body>
+</html>
+"#,
+        escape_html(version)
+    )
+}
+
+fn squirrelmail_login(version: &str) -> String {
+    format!(
+        r##"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">
+<html>
+<head>
+<meta name="robots" content="noindex,nofollow">
+<title>SquirrelMail - Login</title>
+<script language="JavaScript" type="text/javascript">
+function squirrelmail_loginpage_onload() {{
+  document.login_form.js_autodetect_results.value = '1';
+  document.login_form.login_username.focus();
+}}
+</script>
+</head>
+<body text="#000000" bgcolor="#ffffff" link="#0000cc" vlink="#0000cc" alink="#0000cc" onload="squirrelmail_loginpage_onload();">
+<form action="redirect.php" method="post" name="login_form">
+<table bgcolor="#ffffff" border="0" cellspacing="0" cellpadding="0" width="100%"><tr><td align="center">
+<center><img src="../images/sm_logo.png" alt="SquirrelMail Logo" width="308" height="111" /><br />
+<small>SquirrelMail version {version}<br />By the SquirrelMail Project Team<br /></small>
+<table bgcolor="#ffffff" border="0" width="350"><tr><td bgcolor="#dcdcdc" align="center"><b>SquirrelMail Login</b></td></tr>
+<tr><td bgcolor="#ffffff" align="left">
+<table bgcolor="#ffffff" align="center" border="0" width="100%">
+<tr><td align="right" width="30%">Name:</td><td align="left" width="70%"><input type="text" name="login_username" value="" onfocus="alreadyFocused=true;" /></td></tr>
+<tr><td align="right" width="30%">Password:</td><td align="left" width="70%"><input type="password" name="secretkey" onfocus="alreadyFocused=true;" />
+<input type="hidden" name="js_autodetect_results" value="0" /><input type="hidden" name="just_logged_in" value="1" /></td></tr>
+</table></td></tr>
+<tr><td align="left"><center><input type="submit" value="Login" /></center></td></tr>
+</table></center></td></tr></table>
+</form>
+</body>
+</html>
+"##,
+        version = escape_html(version),
+    )
+}
+
+fn squirrelmail_error(version: &str, error: &str) -> String {
+    format!(
+        r##"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01 Transitional//EN">
+<html>
+<head>
+<meta name="robots" content="noindex,nofollow">
+<title>SquirrelMail - Error</title>
+</head>
+<body text="#000000" bgcolor="#ffffff" link="#0000cc" vlink="#0000cc" alink="#0000cc">
+<center><img src="../images/sm_logo.png" alt="SquirrelMail Logo" width="308" height="111" /><br />
+<small>SquirrelMail version {}<br />By the SquirrelMail Project Team<br /></small>
+<table cellspacing="1" cellpadding="0" bgcolor="#800000" width="70%"><tr><td>
+<table width="100%" border="0" bgcolor="#ffffff" align="center"><tr><td bgcolor="#dcdcdc" align="center"><font color="#cc0000"><b>ERROR</b></font></td></tr>
+<tr><td align="center">{}</td></tr>
+<tr><td bgcolor="#dcdcdc" align="center"><font color="#cc0000"><b><a href="login.php" target="_top">Go to the login page</a></b></font></td></tr>
+</table></td></tr></table></center>
+</body>
+</html>
+"##,
+        escape_html(version),
+        error
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::profile;
+
+    #[test]
+    fn test_roundcube() {
+        let webmail = Webmail::default();
+        let page = webmail
+            .respond(&Request::stub(Method::GET, "/roundcube/", ""))
+            .unwrap();
+        assert!(page.body.contains("\"rcversion\":10606"));
+        assert_eq!(
+            Some("/webmail/".to_string()),
+            webmail
+                .respond(&Request::stub(Method::GET, "/webmail", ""))
+                .unwrap()
+                .location
+        );
+
+        let login = Request::stub(
+            Method::POST,
+            "/webmail/?_task=login",
+            "_token=x&_task=login&_action=login&_user=admin%40example.com&_pass=hunter2",
+        );
+        let page = webmail.respond(&login).unwrap();
+        assert_eq!(StatusCode::Unauthorized, page.status);
+        assert!(page.body.contains("Login failed."));
+        assert_eq!(
+            vec![("admin@example.com".to_string(), "hunter2".to_string())],
+            profile::credentials(&login)
+        );
+
+        let page = webmail
+            .respond(&Request::stub(Method::GET, "/roundcube/installer/", ""))
+            .unwrap();
+        assert!(page.body.contains("Roundcube Webmail Installer"));
+        assert!(webmail
+            .respond(&Request::stub(Method::GET, "/mailer.php", ""))
+            .is_none());
+    }
+
+    #[test]
+    fn test_squirrelmail() {
+        let webmail = Webmail::default();
+        assert_eq!(
+            Some("/squirrelmail/src/login.php".to_string()),
+            webmail
+                .respond(&Request::stub(Method::GET, "/squirrelmail/", ""))
+                .unwrap()
+                .location
+        );
+        // SquirrelMail's pages are recognized under any webmail path
+        let page = webmail
+            .respond(&Request::stub(Method::GET, "/webmail/src/login.php", ""))
+            .unwrap();
+        assert!(page.body.contains("SquirrelMail version 1.4.22"));
+
+        let login = Request::stub(
+            Method::POST,
+            "/squirrelmail/src/redirect.php",
+            "login_username=root&secretkey=toor&js_autodetect_results=1&just_logged_in=1",
+        );
+        let page = webmail.respond(&login).unwrap();
+        assert!(page.body.contains("Unknown user or password incorrect."));
+        assert_eq!(
+            vec![("root".to_string(), "toor".to_string())],
+            profile::credentials(&login)
+        );
+    }
+
+    #[test]
+    fn test_rcversion() {
+        assert_eq!(10606, rcversion("1.6.6"));
+        assert_eq!(10410, rcversion("1.4.10"));
+        assert_eq!(10500, rcversion("1.5"));
+        assert_eq!(10600, rcversion("1.6-rc"));
+    }
+}
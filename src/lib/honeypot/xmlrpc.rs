@@ -0,0 +1,594 @@
+//! WordPress's XML-RPC endpoint at `/xmlrpc.php`. Calls are parsed as real
+//! XML-RPC, including those packed into `system.multicall`, which brute
+//! forcers use to try hundreds of passwords per request. `wp.getUsersBlogs`
+//! and the other authenticated methods have their credentials captured by
+//! `profile::credentials`, and are always refused. `pingback.ping` sources
+//! are logged, as probing for a pingback reflector is how WordPress sites
+//! are recruited into DDoS and SSRF; none are ever fetched.
+use std::sync::Arc;
+
+use roxmltree::{Document, Node, ParsingOptions};
+
+use crate::{
+    honeypot::php::Php,
+    http::{
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::escape_html,
+};
+
+const PATH: &str = "/xmlrpc.php";
+// bounds the tree built from a body
+const MAX_NODES: u32 = 100_000;
+// calls answered from a single system.multicall
+const MAX_MULTICALL: usize = 1_000;
+
+/// the methods WordPress lists, in its order
+const METHODS: &[&str] = &[
+    "system.multicall",
+    "system.listMethods",
+    "system.getCapabilities",
+    "demo.addTwoNumbers",
+    "demo.sayHello",
+    "pingback.extensions.getPingbacks",
+    "pingback.ping",
+    "mt.publishPost",
+    "mt.getTrackbackPings",
+    "mt.supportedTextFilters",
+    "mt.supportedMethods",
+    "mt.setPostCategories",
+    "mt.getPostCategories",
+    "mt.getRecentPostTitles",
+    "mt.getCategoryList",
+    "metaWeblog.getUsersBlogs",
+    "metaWeblog.deletePost",
+    "metaWeblog.newMediaObject",
+    "metaWeblog.getCategories",
+    "metaWeblog.getRecentPosts",
+    "metaWeblog.getPost",
+    "metaWeblog.editPost",
+    "metaWeblog.newPost",
+    "blogger.deletePost",
+    "blogger.editPost",
+    "blogger.newPost",
+    "blogger.getRecentPosts",
+    "blogger.getPost",
+    "blogger.getUserInfo",
+    "blogger.getUsersBlogs",
+    "wp.restoreRevision",
+    "wp.getRevisions",
+    "wp.getPostTypes",
+    "wp.getPostType",
+    "wp.getPostFormats",
+    "wp.getMediaLibrary",
+    "wp.getMediaItem",
+    "wp.getCommentStatusList",
+    "wp.newComment",
+    "wp.editComment",
+    "wp.deleteComment",
+    "wp.getComments",
+    "wp.getComment",
+    "wp.setOptions",
+    "wp.getOptions",
+    "wp.getPageTemplates",
+    "wp.getPageStatusList",
+    "wp.getPostStatusList",
+    "wp.getCommentCount",
+    "wp.deleteFile",
+    "wp.uploadFile",
+    "wp.suggestCategories",
+    "wp.deleteCategory",
+    "wp.newCategory",
+    "wp.getTags",
+    "wp.getCategories",
+    "wp.getAuthors",
+    "wp.getPageList",
+    "wp.editPage",
+    "wp.deletePage",
+    "wp.newPage",
+    "wp.getPages",
+    "wp.getPage",
+    "wp.editProfile",
+    "wp.getProfile",
+    "wp.getUsers",
+    "wp.getUser",
+    "wp.getTaxonomies",
+    "wp.getTaxonomy",
+    "wp.getTerms",
+    "wp.getTerm",
+    "wp.deleteTerm",
+    "wp.editTerm",
+    "wp.newTerm",
+    "wp.getPosts",
+    "wp.getPost",
+    "wp.deletePost",
+    "wp.editPost",
+    "wp.newPost",
+    "wp.getUsersBlogs",
+];
+
+/// An XML-RPC value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    String(String),
+    Double(f64),
+    DateTime(String),
+    /// base64 as sent, which is left undecoded
+    Base64(String),
+    Array(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+    Nil,
+}
+
+impl Value {
+    fn parse(value: Node) -> Result<Self> {
+        let typed = match value.children().find(Node::is_element) {
+            Some(t) => t,
+            // untyped values are strings
+            None => return Ok(Value::String(value.text().unwrap_or_default().to_string())),
+        };
+        let text = typed.text().unwrap_or_default().trim();
+        Ok(match typed.tag_name().name() {
+            "i4" | "int" | "i8" => Value::Int(text.parse()?),
+            "boolean" => Value::Bool(match text {
+                "1" => true,
+                "0" => false,
+                _ => bail!("invalid boolean {:?}", text),
+            }),
+            "string" => Value::String(typed.text().unwrap_or_default().to_string()),
+            "double" => Value::Double(text.parse()?),
+            "dateTime.iso8601" => Value::DateTime(text.to_string()),
+            "base64" => Value::Base64(text.to_string()),
+            "array" => Value::Array(
+                element(typed, "data")
+                    .into_iter()
+                    .flat_map(|data| elements(data, "value"))
+                    .map(Value::parse)
+                    .collect::<Result<_>>()?,
+            ),
+            "struct" => Value::Struct(
+                elements(typed, "member")
+                    .map(|member| {
+                        let name = element(member, "name")
+                            .and_then(|n| n.text())
+                            .unwrap_or_default();
+                        let value = element(member, "value")
+                            .ok_or_else(|| anyhow!("struct member {:?} has no value", name))?;
+                        Ok((name.to_string(), Value::parse(value)?))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+            "nil" => Value::Nil,
+            other => bail!("unknown value type {:?}", other),
+        })
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// the member of a struct with the name, if any.
+    pub fn member(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(members) => members.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push_str("<value>");
+        match self {
+            Value::Int(i) => out.push_str(&format!("<int>{}</int>", i)),
+            Value::Bool(b) => out.push_str(&format!("<boolean>{}</boolean>", *b as u8)),
+            Value::String(s) => out.push_str(&format!("<string>{}</string>", escape_html(s))),
+            Value::Double(d) => out.push_str(&format!("<double>{}</double>", d)),
+            Value::DateTime(d) => out.push_str(&format!(
+                "<dateTime.iso8601>{}</dateTime.iso8601>",
+                escape_html(d)
+            )),
+            Value::Base64(b) => out.push_str(&format!("<base64>{}</base64>", escape_html(b))),
+            Value::Array(values) => {
+                out.push_str("<array><data>");
+                for value in values {
+                    value.write(out);
+                }
+                out.push_str("</data></array>");
+            }
+            Value::Struct(members) => {
+                out.push_str("<struct>");
+                for (name, value) in members {
+                    out.push_str(&format!("<member><name>{}</name>", escape_html(name)));
+                    value.write(out);
+                    out.push_str("</member>");
+                }
+                out.push_str("</struct>");
+            }
+            Value::Nil => out.push_str("<nil/>"),
+        }
+        out.push_str("</value>");
+    }
+}
+
+// the first child element with the name
+fn element<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    elements(node, name).next()
+}
+
+fn elements<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// An XML-RPC method call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub method: String,
+    pub params: Vec<Value>,
+}
+
+impl Call {
+    /// parses a `methodCall` document. DTDs are refused, so entities are
+    /// never expanded.
+    pub fn parse(body: &[u8]) -> Result<Self> {
+        let options = ParsingOptions {
+            allow_dtd: false,
+            nodes_limit: MAX_NODES,
+        };
+        let doc = Document::parse_with_options(std::str::from_utf8(body)?, options)?;
+        let root = doc.root_element();
+        ensure!(
+            root.tag_name().name() == "methodCall",
+            "expected a methodCall, not {:?}",
+            root.tag_name().name()
+        );
+
+        let method = element(root, "methodName")
+            .and_then(|n| n.text())
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .ok_or_else(|| anyhow!("methodCall has no methodName"))?;
+        let params = element(root, "params")
+            .into_iter()
+            .flat_map(|params| elements(params, "param"))
+            .map(|param| {
+                element(param, "value")
+                    .ok_or_else(|| anyhow!("param has no value"))
+                    .and_then(Value::parse)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            method: method.to_string(),
+            params,
+        })
+    }
+
+    /// the calls packed into a `system.multicall`, which aren't themselves
+    /// multicalls.
+    pub fn multicalls(&self) -> Vec<Call> {
+        if self.method != "system.multicall" {
+            return vec![];
+        }
+        let calls = match self.params.first() {
+            Some(Value::Array(calls)) => calls,
+            _ => return vec![],
+        };
+        calls
+            .iter()
+            .take(MAX_MULTICALL)
+            .filter_map(|call| {
+                let method = call.member("methodName")?.as_str()?;
+                let params = match call.member("params") {
+                    Some(Value::Array(params)) => params.clone(),
+                    _ => vec![],
+                };
+                Some(Call {
+                    method: method.to_string(),
+                    params,
+                })
+            })
+            .filter(|call| call.method != "system.multicall")
+            .collect()
+    }
+
+    /// the username and password the call authenticates with, if it's one
+    /// of WordPress's authenticated methods.
+    pub fn credentials(&self) -> Option<(String, String)> {
+        let (family, name) = self.method.split_once('.')?;
+        // most methods take a blog or post ID first
+        let at = match (family, name) {
+            ("wp", "getUsersBlogs") => 0,
+            ("wp" | "blogger" | "metaWeblog" | "mt", _) => 1,
+            _ => return None,
+        };
+        let user = self.params.get(at)?.as_str()?;
+        let password = self.params.get(at + 1)?.as_str()?;
+        Some((user.to_string(), password.to_string()))
+    }
+}
+
+/// the XML-RPC call a request makes, if it's one to `/xmlrpc.php`.
+pub fn call(req: &Request) -> Option<Call> {
+    match req.method == Method::POST && req.url.path() == PATH {
+        true => Call::parse(&req.body).ok(),
+        false => None,
+    }
+}
+
+/// every username and password an XML-RPC request tries, including each
+/// in a `system.multicall`.
+pub fn credentials(req: &Request) -> Vec<(String, String)> {
+    let call = match call(req) {
+        Some(c) => c,
+        None => return vec![],
+    };
+    std::iter::once(call.clone())
+        .chain(call.multicalls())
+        .filter_map(|c| c.credentials())
+        .collect()
+}
+
+/// An XML-RPC fault, with WordPress's codes.
+#[derive(Debug, Clone, PartialEq)]
+struct Fault {
+    code: i64,
+    message: String,
+}
+
+impl Fault {
+    fn new(code: i64, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Struct(vec![
+            ("faultCode".to_string(), Value::Int(self.code)),
+            (
+                "faultString".to_string(),
+                Value::String(self.message.clone()),
+            ),
+        ])
+    }
+}
+
+/// Middleware which serves WordPress's XML-RPC endpoint.
+#[derive(Debug, Clone, Default)]
+pub struct XmlRpc {
+    php: Php,
+}
+
+impl XmlRpc {
+    pub fn new(php: Php) -> Self {
+        Self { php }
+    }
+}
+
+impl Middleware for XmlRpc {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            if req.url.path() != PATH {
+                return next.run(req).await;
+            }
+            if req.method != Method::POST {
+                let mut builder = ResponseBuilder::default(Arc::new(next.into_conn()));
+                return Ok(self
+                    .php
+                    .expose(&mut builder)
+                    .status_code(StatusCode::MethodNotAllowed)
+                    .add_header("Allow", "POST")
+                    .add_header("Content-Type", "text/plain;charset=UTF-8")
+                    .body("XML-RPC server accepts POST requests only.")
+                    .build()?);
+            }
+
+            let result = match Call::parse(&req.body) {
+                Ok(call) => answer(req, &call),
+                Err(e) => {
+                    debug!("{: <8} sent unparsable XML-RPC: {}", req.requester(), e);
+                    Err(Fault::new(-32700, "parse error. not well formed"))
+                }
+            };
+            let mut builder = ResponseBuilder::ok(Arc::new(next.into_conn()));
+            Ok(self
+                .php
+                .expose(&mut builder)
+                .add_header("Content-Type", "text/xml; charset=UTF-8")
+                .body(method_response(&result))
+                .build()?)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("xmlrpc")
+    }
+}
+
+fn answer(req: &Request, call: &Call) -> std::result::Result<Value, Fault> {
+    match call.method.as_str() {
+        "system.listMethods" => Ok(Value::Array(
+            METHODS
+                .iter()
+                .map(|m| Value::String(m.to_string()))
+                .collect(),
+        )),
+        "system.multicall" => {
+            let calls = call.multicalls();
+            info!(
+                "{: <8} multicalled {} XML-RPC methods",
+                req.requester(),
+                calls.len()
+            );
+            Ok(Value::Array(
+                calls
+                    .iter()
+                    .map(|call| match answer(req, call) {
+                        Ok(value) => Value::Array(vec![value]),
+                        Err(fault) => fault.to_value(),
+                    })
+                    .collect(),
+            ))
+        }
+        "demo.sayHello" => Ok(Value::String("Hello!".to_string())),
+        "demo.addTwoNumbers" => match call.params.as_slice() {
+            [Value::Int(a), Value::Int(b)] => Ok(Value::Int(a.wrapping_add(*b))),
+            _ => Err(Fault::new(
+                -32602,
+                "server error. invalid method parameters",
+            )),
+        },
+        "pingback.ping" => {
+            let source = call.params.first().and_then(Value::as_str);
+            let target = call.params.get(1).and_then(Value::as_str);
+            info!(
+                "{: <8} asked for a pingback from {:?} to {:?}",
+                req.requester(),
+                source.unwrap_or_default(),
+                target.unwrap_or_default()
+            );
+            // as if the source was fetched, without fetching it
+            Err(Fault::new(
+                17,
+                "The source URL does not contain a link to the target URL, and so cannot be used as a source.",
+            ))
+        }
+        method if METHODS.contains(&method) => {
+            if let Some((user, _)) = call.credentials() {
+                info!(
+                    "{: <8} tried XML-RPC {} as {:?}",
+                    req.requester(),
+                    method,
+                    user
+                );
+            }
+            Err(Fault::new(403, "Incorrect username or password."))
+        }
+        method => Err(Fault::new(
+            -32601,
+            &format!("server error. requested method {} does not exist.", method),
+        )),
+    }
+}
+
+fn method_response(result: &std::result::Result<Value, Fault>) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<methodResponse>");
+    match result {
+        Ok(value) => {
+            out.push_str("<params><param>");
+            value.write(&mut out);
+            out.push_str("</param></params>");
+        }
+        Err(fault) => {
+            out.push_str("<fault>");
+            fault.to_value().write(&mut out);
+            out.push_str("</fault>");
+        }
+    }
+    out.push_str("</methodResponse>\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GET_USERS_BLOGS: &str = r#"<?xml version="1.0"?>
+<methodCall><methodName>wp.getUsersBlogs</methodName><params>
+<param><value><string>admin</string></value></param>
+<param><value>hunter2 &amp; more</value></param>
+</params></methodCall>"#;
+
+    #[test]
+    fn test_parse() {
+        let call = Call::parse(GET_USERS_BLOGS.as_bytes()).unwrap();
+        assert_eq!("wp.getUsersBlogs", call.method);
+        assert_eq!(
+            Some(("admin".to_string(), "hunter2 & more".to_string())),
+            call.credentials()
+        );
+
+        let call = Call::parse(
+            br#"<methodCall><methodName>demo.addTwoNumbers</methodName><params>
+            <param><value><i4>2</i4></value></param>
+            <param><value><struct><member><name>a</name><value><array><data>
+              <value><boolean>1</boolean></value><value><double>1.5</double></value>
+            </data></array></value></member></struct></value></param>
+            </params></methodCall>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Value::Int(2),
+                Value::Struct(vec![(
+                    "a".to_string(),
+                    Value::Array(vec![Value::Bool(true), Value::Double(1.5)])
+                )])
+            ],
+            call.params
+        );
+
+        assert!(Call::parse(b"<methodCall></methodCall>").is_err());
+        assert!(Call::parse(b"<?xml version=\"1.0\"?><!DOCTYPE x [<!ENTITY e SYSTEM \"file:///etc/passwd\">]><methodCall><methodName>&e;</methodName></methodCall>").is_err());
+    }
+
+    #[test]
+    fn test_multicalls() {
+        let call = Call::parse(
+            br#"<methodCall><methodName>system.multicall</methodName><params><param><value><array><data>
+            <value><struct>
+              <member><name>methodName</name><value><string>wp.getUsersBlogs</string></value></member>
+              <member><name>params</name><value><array><data>
+                <value><string>admin</string></value><value><string>123456</string></value>
+              </data></array></value></member>
+            </struct></value>
+            <value><struct>
+              <member><name>methodName</name><value><string>wp.getPosts</string></value></member>
+              <member><name>params</name><value><array><data>
+                <value><int>1</int></value><value><string>editor</string></value><value><string>password</string></value>
+              </data></array></value></member>
+            </struct></value>
+            </data></array></value></param></params></methodCall>"#,
+        )
+        .unwrap();
+        let credentials = call
+            .multicalls()
+            .iter()
+            .filter_map(Call::credentials)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("admin".to_string(), "123456".to_string()),
+                ("editor".to_string(), "password".to_string())
+            ],
+            credentials
+        );
+    }
+
+    #[test]
+    fn test_method_response() {
+        let ok = method_response(&Ok(Value::String("<b>".to_string())));
+        assert!(ok
+            .contains("<params><param><value><string>&lt;b&gt;</string></value></param></params>"));
+
+        let fault = method_response(&Err(Fault::new(403, "Incorrect username or password.")));
+        assert!(fault.contains(
+            "<fault><value><struct><member><name>faultCode</name><value><int>403</int></value>"
+        ));
+        // responses are XML-RPC themselves
+        let doc = Document::parse(&fault).unwrap();
+        assert_eq!("methodResponse", doc.root_element().tag_name().name());
+    }
+}
@@ -0,0 +1,55 @@
+//! HTTP dates, as real servers write them: RFC 7231's IMF-fixdate, e.g.
+//! `Sun, 06 Nov 1994 08:49:37 GMT`. The current date changes once a
+//! second, so it's formatted once a second and shared across responses,
+//! as Apache and nginx do.
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+
+lazy_static! {
+    // the second last formatted, and its date
+    static ref NOW: Mutex<(i64, String)> = Mutex::new((i64::MIN, String::new()));
+}
+
+/// formats the time as an IMF-fixdate.
+pub fn format(at: DateTime<Utc>) -> String {
+    at.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// the current time as an IMF-fixdate, for Date headers.
+pub fn now() -> String {
+    let at = Utc::now();
+    let mut cached = NOW.lock().unwrap();
+    if cached.0 != at.timestamp() {
+        *cached = (at.timestamp(), format(at));
+    }
+    cached.1.clone()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_format() {
+        let at = |secs| Utc.timestamp_opt(secs, 0).single().unwrap();
+        // RFC 7231's example, and Date headers captured from Apache, nginx,
+        // and IIS, which all zero-pad days and hours
+        for (secs, real) in [
+            (784111777, "Sun, 06 Nov 1994 08:49:37 GMT"),
+            (1673889546, "Mon, 16 Jan 2023 17:19:06 GMT"),
+            (1420070400, "Thu, 01 Jan 2015 00:00:00 GMT"),
+            (1709251199, "Thu, 29 Feb 2024 23:59:59 GMT"),
+        ] {
+            assert_eq!(real.as_bytes(), format(at(secs)).as_bytes());
+        }
+
+        let before = Utc::now().timestamp();
+        let date = now();
+        let parsed = DateTime::parse_from_rfc2822(&date).unwrap().timestamp();
+        assert!((before..=before + 1).contains(&parsed), "{}", date);
+        assert_eq!(29, date.len());
+    }
+}
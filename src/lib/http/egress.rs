@@ -0,0 +1,253 @@
+//! Outbound bandwidth shaping. Responses are written through token
+//! buckets, one shared by every connection and one per connection, so a
+//! burst of scanners downloading large bait files can't saturate the
+//! host's uplink. What's written is metered either way, for the egress
+//! rate gauge, which counts every honeypot in the process.
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tokio::time::sleep;
+
+use crate::prelude::*;
+
+lazy_static! {
+    static ref METER: Mutex<Meter> = Mutex::new(Meter::default());
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EgressConfig {
+    /// bytes a second written across every connection, unlimited when 0
+    pub bytes_per_sec: u64,
+    /// bytes a second written to each connection, unlimited when 0
+    pub connection_bytes_per_sec: u64,
+    /// bytes written at once before either limit applies
+    pub burst_bytes: u64,
+}
+
+impl Default for EgressConfig {
+    fn default() -> Self {
+        Self {
+            bytes_per_sec: 0,
+            connection_bytes_per_sec: 0,
+            burst_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// A deployment's limits, shared by its connections.
+#[derive(Debug, Clone)]
+pub struct Egress {
+    global: Option<Arc<TokenBucket>>,
+    connection_bytes_per_sec: u64,
+    burst_bytes: u64,
+}
+
+impl Egress {
+    pub fn new(config: &EgressConfig) -> Result<Self> {
+        ensure!(
+            config.burst_bytes > 0,
+            "egress burst_bytes must be positive"
+        );
+        Ok(Self {
+            global: (config.bytes_per_sec > 0)
+                .then(|| Arc::new(TokenBucket::new(config.bytes_per_sec, config.burst_bytes))),
+            connection_bytes_per_sec: config.connection_bytes_per_sec,
+            burst_bytes: config.burst_bytes,
+        })
+    }
+}
+
+/// the bytes a second written recently, across every connection.
+pub fn rate() -> f64 {
+    METER.lock().unwrap().rate(Instant::now())
+}
+
+/// A token bucket refilled at a rate, holding up to a burst.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    // tokens available as of when
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(bytes_per_sec: u64, burst: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            burst: burst as f64,
+            state: Mutex::new((burst as f64, Instant::now())),
+        }
+    }
+
+    /// takes n tokens, returning how long to wait before they're
+    /// available. Tokens are owed rather than refused, so waiters are
+    /// served in the order they asked.
+    fn take(&self, n: usize, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, at) = *state;
+        let tokens = (tokens + now.saturating_duration_since(at).as_secs_f64() * self.rate)
+            .min(self.burst)
+            - n as f64;
+        *state = (tokens, now);
+        match tokens < 0.0 {
+            true => Duration::from_secs_f64(-tokens / self.rate),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+/// Shapes writes to one connection.
+pub struct Shaper {
+    egress: Option<Egress>,
+    connection: Option<TokenBucket>,
+}
+
+impl Shaper {
+    /// shapes a new connection by the deployment's limits, or only meters
+    /// it without any.
+    pub fn new(egress: Option<&Egress>) -> Self {
+        let egress = egress.cloned();
+        let connection = egress
+            .as_ref()
+            .filter(|e| e.connection_bytes_per_sec > 0)
+            .map(|e| TokenBucket::new(e.connection_bytes_per_sec, e.burst_bytes));
+        Self { egress, connection }
+    }
+
+    /// how much to write at once.
+    pub fn chunk_len(&self) -> usize {
+        match &self.egress {
+            Some(egress) => egress.burst_bytes as usize,
+            None => usize::MAX,
+        }
+    }
+
+    /// waits until n bytes may be written, then counts them as written.
+    pub async fn wait(&self, n: usize) {
+        let now = Instant::now();
+        let global = self.egress.as_ref().and_then(|e| e.global.as_ref());
+        let delay = [
+            global.map(|g| g.take(n, now)),
+            self.connection.as_ref().map(|c| c.take(n, now)),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or_default();
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+        METER.lock().unwrap().record(n, Instant::now());
+    }
+}
+
+/// Bytes written over the last full second.
+#[derive(Debug)]
+struct Meter {
+    window: Instant,
+    bytes: u64,
+    last: f64,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            window: Instant::now(),
+            bytes: 0,
+            last: 0.0,
+        }
+    }
+}
+
+impl Meter {
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.window);
+        if elapsed >= Duration::from_secs(1) {
+            // a quiet second after the window ended means nothing's flowing
+            self.last = match elapsed < Duration::from_secs(2) {
+                true => self.bytes as f64 / elapsed.as_secs_f64(),
+                false => 0.0,
+            };
+            self.window = now;
+            self.bytes = 0;
+        }
+    }
+
+    fn record(&mut self, n: usize, now: Instant) {
+        self.roll(now);
+        self.bytes += n as u64;
+    }
+
+    fn rate(&mut self, now: Instant) -> f64 {
+        self.roll(now);
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let bucket = TokenBucket::new(1000, 500);
+        // the burst is free, then writes wait for the rate
+        assert_eq!(Duration::ZERO, bucket.take(500, start));
+        assert_eq!(Duration::from_millis(250), bucket.take(250, start));
+        assert_eq!(Duration::from_millis(500), bucket.take(250, start));
+        // and refills, but only up to the burst
+        let later = start + Duration::from_secs(10);
+        assert_eq!(Duration::ZERO, bucket.take(500, later));
+        assert_eq!(Duration::from_millis(250), bucket.take(250, later));
+    }
+
+    #[test]
+    fn test_shaper() {
+        let config = EgressConfig {
+            bytes_per_sec: 1000,
+            connection_bytes_per_sec: 100,
+            burst_bytes: 10,
+        };
+        let egress = Egress::new(&config).unwrap();
+        assert_eq!(10, Shaper::new(Some(&egress)).chunk_len());
+        assert_eq!(usize::MAX, Shaper::new(None).chunk_len());
+
+        // connections share the deployment's bucket, but not each other's
+        let (a, b) = (Shaper::new(Some(&egress)), Shaper::new(Some(&egress)));
+        let now = Instant::now();
+        a.connection.as_ref().unwrap().take(10, now);
+        assert_eq!(Duration::ZERO, b.connection.as_ref().unwrap().take(10, now));
+        assert!(Arc::ptr_eq(
+            a.egress.unwrap().global.as_ref().unwrap(),
+            b.egress.unwrap().global.as_ref().unwrap()
+        ));
+
+        let config = EgressConfig {
+            burst_bytes: 0,
+            ..Default::default()
+        };
+        assert!(Egress::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_meter() {
+        let start = Instant::now();
+        let mut meter = Meter {
+            window: start,
+            bytes: 0,
+            last: 0.0,
+        };
+        meter.record(1000, start);
+        meter.record(500, start + Duration::from_millis(500));
+        assert_eq!(0.0, meter.rate(start + Duration::from_millis(900)));
+        assert_eq!(1500.0, meter.rate(start + Duration::from_secs(1)));
+        assert_eq!(0.0, meter.rate(start + Duration::from_secs(3)));
+    }
+}
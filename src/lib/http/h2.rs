@@ -0,0 +1,836 @@
+//! HTTP/2 over cleartext, h2c, which scanners increasingly try first:
+//! spoken from the start, opening with the connection preface, or
+//! upgraded to from an HTTP/1.1 request. Only enough is spoken to answer
+//! one request: its stream is the only one served, nothing is pushed,
+//! and the connection is closed after. How clients set up the connection
+//! differs between HTTP/2 implementations, so it's kept as a fingerprint.
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+};
+
+use crate::{
+    http::{
+        egress::Shaper,
+        headers::Headers,
+        hpack,
+        request::{self, Method, ParserConfig, Request, Syntax},
+        response::{write_all, BaseResponse},
+        spool::Spooler,
+    },
+    prelude::*,
+    session::Ending,
+};
+
+/// what clients open HTTP/2 connections with
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// frame types
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// frame flags
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+// the largest frame either side sends, as neither advertises more
+const MAX_FRAME_SIZE: usize = 16 * 1024;
+const DEFAULT_WINDOW: i64 = 65_535;
+const HEADER_TABLE_SIZE: usize = 4096;
+const MAX_HEADER_BLOCK: usize = 64 * 1024;
+// advertised as nginx does: 128 concurrent streams, a 64 KiB window for
+// each, and the rest of the connection's window opened at once
+const SERVER_SETTINGS: &[(u16, u32)] = &[(0x3, 128), (SETTINGS_INITIAL_WINDOW_SIZE, 65_536)];
+const SERVER_WINDOW_UPDATE: u32 = 2_147_418_112;
+const LINGER: Duration = Duration::from_secs(1);
+
+// hop-by-hop headers, which HTTP/2 forbids
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// How a client set up its connection, written as Akamai's HTTP/2
+/// fingerprint: its settings, its first connection window update, its
+/// PRIORITY frames, and the order of its pseudo-headers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub settings: Vec<(u16, u32)>,
+    pub window_update: Option<u32>,
+    /// the stream, whether it's exclusive, what it depends on, and its
+    /// weight
+    pub priorities: Vec<(u32, bool, u32, u8)>,
+    /// pseudo-header names without their colons
+    pub pseudo_headers: Vec<String>,
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let settings = self
+            .settings
+            .iter()
+            .map(|(id, value)| format!("{}:{}", id, value))
+            .collect::<Vec<_>>()
+            .join(";");
+        let window_update = self
+            .window_update
+            .map_or("00".to_string(), |w| w.to_string());
+        let priorities = match self.priorities.is_empty() {
+            true => "0".to_string(),
+            false => self
+                .priorities
+                .iter()
+                .map(|(stream, exclusive, depends, weight)| {
+                    format!(
+                        "{}:{}:{}:{}",
+                        stream,
+                        *exclusive as u8,
+                        depends,
+                        *weight as u16 + 1
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+        };
+        let pseudo_headers = self
+            .pseudo_headers
+            .iter()
+            .filter_map(|h| h.get(..1))
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(
+            f,
+            "{}|{}|{}|{}",
+            settings, window_update, priorities, pseudo_headers
+        )
+    }
+}
+
+/// An HTTP/2 connection serving one stream.
+#[derive(Debug)]
+pub struct Session {
+    pub stream: u32,
+    /// whether the client upgraded to HTTP/2 rather than starting with it
+    pub upgraded: bool,
+    pub fingerprint: Fingerprint,
+    settled: bool,
+    // how much of the response the client will accept, on the connection
+    // and the stream
+    window: i64,
+    stream_window: i64,
+    initial_window: i64,
+}
+
+struct Frame {
+    kind: u8,
+    flags: u8,
+    stream: u32,
+    payload: Vec<u8>,
+}
+
+impl Frame {
+    /// the payload without its padding.
+    fn unpadded(&self) -> Result<&[u8]> {
+        if self.flags & PADDED == 0 {
+            return Ok(&self.payload);
+        }
+        let pad = *self.payload.first().unwrap_or(&0) as usize;
+        ensure!(
+            pad < self.payload.len(),
+            "frame padded by {} of {} bytes",
+            pad,
+            self.payload.len()
+        );
+        Ok(&self.payload[1..self.payload.len() - pad])
+    }
+}
+
+fn frame(kind: u8, flags: u8, stream: u32, payload: &[u8]) -> Vec<u8> {
+    let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+    frame.extend([kind, flags]);
+    frame.extend(stream.to_be_bytes());
+    frame.extend(payload);
+    frame
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame> {
+    let mut head = [0; 9];
+    reader.read_exact(&mut head).await?;
+    let len = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+    ensure!(
+        len <= MAX_FRAME_SIZE,
+        "frame of {} bytes over the {} allowed",
+        len,
+        MAX_FRAME_SIZE
+    );
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Frame {
+        kind: head[3],
+        flags: head[4],
+        stream: u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7fff_ffff,
+        payload,
+    })
+}
+
+fn server_preface() -> Vec<u8> {
+    let settings: Vec<u8> = SERVER_SETTINGS
+        .iter()
+        .flat_map(|(id, value)| [id.to_be_bytes().as_slice(), &value.to_be_bytes()].concat())
+        .collect();
+    [
+        frame(SETTINGS, 0, 0, &settings),
+        frame(WINDOW_UPDATE, 0, 0, &SERVER_WINDOW_UPDATE.to_be_bytes()),
+    ]
+    .concat()
+}
+
+/// whether what's been read opens an HTTP/2 connection.
+pub fn is_preface(peeked: &[u8]) -> bool {
+    peeked.starts_with(&PREFACE[..16])
+}
+
+/// whether the request asks to upgrade to h2c.
+pub fn is_upgrade(req: &Request) -> bool {
+    let (mut h2c, mut settings) = (false, false);
+    for (name, values) in req.headers.iter() {
+        if name.eq_ignore_ascii_case("Upgrade") {
+            h2c |= values
+                .iter()
+                .flat_map(|v| v.split(','))
+                .any(|p| p.trim().eq_ignore_ascii_case("h2c"));
+        }
+        settings |= name.eq_ignore_ascii_case("HTTP2-Settings");
+    }
+    h2c && settings
+}
+
+/// reads a request from a client speaking HTTP/2 from the start, preface
+/// and all.
+pub async fn accept<R, W>(
+    addr: &SocketAddr,
+    reader: &mut R,
+    writer: &mut W,
+    config: &ParserConfig,
+) -> Result<(Request, Session)>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    read_preface(reader).await?;
+    writer.write_all(&server_preface()).await?;
+
+    let mut session = Session::new(false);
+    let mut decoder = hpack::Decoder::new(HEADER_TABLE_SIZE);
+    let mut spooler = Spooler::new(config.spool.as_ref(), request::MAX_BODY_SIZE);
+    let mut block = vec![];
+    let mut stream = None;
+    let mut fields = None;
+    let mut ended = false;
+    while fields.is_none() || !ended {
+        let frame = read_frame(reader).await?;
+        // header blocks can't be interleaved with anything
+        ensure!(
+            stream.is_none() || fields.is_some() || frame.kind == CONTINUATION,
+            "header block interrupted by a frame of type {}",
+            frame.kind
+        );
+        match frame.kind {
+            HEADERS if stream.is_none() => {
+                ensure!(
+                    frame.stream % 2 == 1,
+                    "request on server stream {}",
+                    frame.stream
+                );
+                let mut fragment = frame.unpadded()?;
+                if frame.flags & PRIORITY_FLAG != 0 {
+                    fragment = fragment
+                        .get(5..)
+                        .ok_or_else(|| anyhow!("HEADERS too short for its priority"))?;
+                }
+                stream = Some(frame.stream);
+                session.stream = frame.stream;
+                ended = frame.flags & END_STREAM != 0;
+                block.extend(fragment);
+            }
+            CONTINUATION if fields.is_none() && stream == Some(frame.stream) => {
+                block.extend(&frame.payload)
+            }
+            CONTINUATION => bail!("CONTINUATION outside a header block"),
+            DATA => {
+                let data = frame.unpadded()?;
+                // the window's reopened for whatever was sent
+                if !frame.payload.is_empty() {
+                    let increment = (frame.payload.len() as u32).to_be_bytes();
+                    writer
+                        .write_all(
+                            &[
+                                self::frame(WINDOW_UPDATE, 0, 0, &increment),
+                                self::frame(WINDOW_UPDATE, 0, frame.stream, &increment),
+                            ]
+                            .concat(),
+                        )
+                        .await?;
+                }
+                if fields.is_some() && stream == Some(frame.stream) {
+                    spooler.write(data).await?;
+                    ended = frame.flags & END_STREAM != 0;
+                }
+            }
+            _ => session.control(&frame, writer).await?,
+        }
+        ensure!(
+            block.len() <= MAX_HEADER_BLOCK,
+            "header block over the {} bytes allowed",
+            MAX_HEADER_BLOCK
+        );
+        if fields.is_none()
+            && stream == Some(frame.stream)
+            && matches!(frame.kind, HEADERS | CONTINUATION)
+            && frame.flags & END_HEADERS != 0
+        {
+            fields = Some(decoder.decode(&block)?);
+        }
+    }
+    writer.flush().await?;
+
+    let (body, spooled) = spooler.finish().await?;
+    let req = build_request(
+        addr,
+        fields.unwrap_or_default(),
+        body,
+        &mut session.fingerprint,
+    )?;
+    Ok((
+        Request {
+            size: spooled.as_ref().map_or(req.size, |s| s.len()),
+            spooled,
+            via_trusted_proxy: config.trusts(addr),
+            ..req
+        },
+        session,
+    ))
+}
+
+/// answers a request asking to upgrade to h2c with 101, then reads the
+/// client's preface. The request is stream 1's, to be answered in HTTP/2.
+pub async fn upgrade<R, W>(req: &Request, reader: &mut R, writer: &mut W) -> Result<Session>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut session = Session::new(true);
+    let settings = req.headers.get_first("HTTP2-Settings");
+    if let Some(settings) = settings {
+        let settings = URL_SAFE_NO_PAD
+            .decode(settings.trim().trim_end_matches('='))
+            .map_err(|e| anyhow!("invalid HTTP2-Settings {:?}: {}", settings, e))?;
+        session.apply_settings(&settings)?;
+    }
+    writer
+        .write_all(
+            b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n",
+        )
+        .await?;
+    writer.write_all(&server_preface()).await?;
+
+    read_preface(reader).await?;
+    loop {
+        let frame = read_frame(reader).await?;
+        session.control(&frame, writer).await?;
+        if frame.kind == SETTINGS && frame.flags & ACK == 0 {
+            break;
+        }
+    }
+    writer.flush().await?;
+    Ok(session)
+}
+
+async fn read_preface<R: AsyncRead + Unpin>(reader: &mut R) -> Result<()> {
+    let mut preface = [0; PREFACE.len()];
+    reader.read_exact(&mut preface).await?;
+    ensure!(
+        preface == PREFACE,
+        "invalid HTTP/2 preface {:?}",
+        String::from_utf8_lossy(&preface)
+    );
+    Ok(())
+}
+
+fn build_request(
+    addr: &SocketAddr,
+    fields: Vec<(String, String)>,
+    body: Vec<u8>,
+    fingerprint: &mut Fingerprint,
+) -> Result<Request> {
+    let has_host = fields.iter().any(|(name, _)| name == "host");
+    let mut headers = Headers::default();
+    let mut syntax = Syntax::default();
+    let (mut method, mut path) = (None, None);
+    for (name, value) in fields {
+        let pseudo = match name.strip_prefix(':') {
+            Some(pseudo) => pseudo,
+            None => {
+                syntax.raw_headers.push((name.clone(), value.clone()));
+                let values = request::header_values(&name, &value);
+                headers.extend(&name, values);
+                continue;
+            }
+        };
+        fingerprint.pseudo_headers.push(pseudo.to_string());
+        match pseudo {
+            "method" => method = Some(value.parse::<Method>()?),
+            "path" => path = Some(value),
+            // the authority is the host, as servers pass it on
+            "authority" if !has_host => {
+                headers.add("host", value);
+            }
+            "authority" | "scheme" | "protocol" => (),
+            other => bail!("unknown pseudo-header :{:?}", other),
+        }
+    }
+
+    let target = path.ok_or_else(|| anyhow!("did not get path"))?;
+    let host = headers
+        .get("host")
+        .and_then(|v| v.first())
+        .ok_or_else(|| anyhow!("failed to get host header"))?;
+    let url = request::request_url(&format!("http://{}{}", host, target))?;
+    Ok(Request {
+        headers,
+        size: body.len(),
+        body,
+        method: method.ok_or_else(|| anyhow!("request lacked method"))?,
+        url,
+        target,
+        version: "HTTP/2.0".to_string(),
+        remote_ip: *addr,
+        via_trusted_proxy: false,
+        syntax,
+        spooled: None,
+        raw_head: vec![],
+    })
+}
+
+impl Session {
+    fn new(upgraded: bool) -> Self {
+        Self {
+            stream: 1,
+            upgraded,
+            fingerprint: Fingerprint::default(),
+            settled: false,
+            window: DEFAULT_WINDOW,
+            stream_window: DEFAULT_WINDOW,
+            initial_window: DEFAULT_WINDOW,
+        }
+    }
+
+    /// applies the client's settings, keeping the first it sent.
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<()> {
+        ensure!(
+            payload.len().is_multiple_of(6),
+            "SETTINGS of {} bytes",
+            payload.len()
+        );
+        let settings: Vec<(u16, u32)> = payload
+            .chunks(6)
+            .map(|s| {
+                (
+                    u16::from_be_bytes([s[0], s[1]]),
+                    u32::from_be_bytes([s[2], s[3], s[4], s[5]]),
+                )
+            })
+            .collect();
+        for (id, value) in &settings {
+            if *id == SETTINGS_INITIAL_WINDOW_SIZE {
+                ensure!(
+                    *value <= i32::MAX as u32,
+                    "initial window size {} too large",
+                    value
+                );
+                self.stream_window += *value as i64 - self.initial_window;
+                self.initial_window = *value as i64;
+            }
+        }
+        if !self.settled {
+            self.fingerprint.settings = settings;
+            self.settled = true;
+        }
+        Ok(())
+    }
+
+    /// handles a frame about the connection rather than the request.
+    async fn control<W: AsyncWrite + Unpin>(
+        &mut self,
+        frame: &Frame,
+        writer: &mut W,
+    ) -> Result<()> {
+        match frame.kind {
+            SETTINGS if frame.flags & ACK == 0 => {
+                self.apply_settings(&frame.payload)?;
+                writer
+                    .write_all(&self::frame(SETTINGS, ACK, 0, &[]))
+                    .await?;
+            }
+            PING if frame.flags & ACK == 0 => {
+                writer
+                    .write_all(&self::frame(PING, ACK, 0, &frame.payload))
+                    .await?;
+            }
+            WINDOW_UPDATE => {
+                let increment = match frame.payload[..] {
+                    [a, b, c, d] => u32::from_be_bytes([a, b, c, d]) & 0x7fff_ffff,
+                    _ => bail!("WINDOW_UPDATE of {} bytes", frame.payload.len()),
+                };
+                match frame.stream {
+                    0 => {
+                        self.window += increment as i64;
+                        self.fingerprint.window_update.get_or_insert(increment);
+                    }
+                    s if s == self.stream => self.stream_window += increment as i64,
+                    _ => (),
+                }
+            }
+            PRIORITY => {
+                if let [a, b, c, d, weight] = frame.payload[..] {
+                    self.fingerprint.priorities.push((
+                        frame.stream,
+                        a & 0x80 != 0,
+                        u32::from_be_bytes([a, b, c, d]) & 0x7fff_ffff,
+                        weight,
+                    ));
+                }
+            }
+            GOAWAY => bail!("client went away"),
+            RST_STREAM if frame.stream == self.stream => {
+                return Err(anyhow!("client reset stream {}", frame.stream).context(Ending::Reset))
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// the response's head as HEADERS, continued if it doesn't fit in one
+    /// frame, ending the stream if nothing follows it.
+    pub fn head<T: fmt::Debug>(&self, resp: &BaseResponse<T>, end: bool) -> Vec<u8> {
+        let mut fields = vec![(":status".to_string(), resp.code().to_string())];
+        for (name, values) in resp.headers().iter() {
+            let name = name.to_lowercase();
+            if !CONNECTION_HEADERS.contains(&name.as_str()) {
+                fields.extend(values.iter().map(|v| (name.clone(), v.clone())));
+            }
+        }
+        let block = hpack::encode(&fields);
+        let fragments: Vec<&[u8]> = match block.is_empty() {
+            true => vec![&[]],
+            false => block.chunks(MAX_FRAME_SIZE).collect(),
+        };
+        let last = fragments.len() - 1;
+        fragments
+            .iter()
+            .enumerate()
+            .flat_map(|(i, fragment)| {
+                let (kind, mut flags) = match i {
+                    0 if end => (HEADERS, END_STREAM),
+                    0 => (HEADERS, 0),
+                    _ => (CONTINUATION, 0),
+                };
+                if i == last {
+                    flags |= END_HEADERS;
+                }
+                frame(kind, flags, self.stream, fragment)
+            })
+            .collect()
+    }
+
+    /// the response's frames, without regard to flow control, as they're
+    /// captured.
+    pub fn frames<T: fmt::Debug>(&self, resp: &BaseResponse<T>) -> Vec<u8> {
+        let mut frames = self.head(resp, resp.body().is_empty());
+        let chunks: Vec<&[u8]> = resp.body().chunks(MAX_FRAME_SIZE).collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let flags = if i == chunks.len() - 1 { END_STREAM } else { 0 };
+            frames.extend(frame(DATA, flags, self.stream, chunk));
+        }
+        frames.extend(self.goaway());
+        frames
+    }
+
+    /// writes data on the stream as fast as the client's windows and the
+    /// shaper allow, ending the stream after if asked.
+    pub async fn data(
+        &mut self,
+        output: &TcpStream,
+        data: &[u8],
+        end: bool,
+        shaper: &Shaper,
+    ) -> Result<()> {
+        let mut rest = data;
+        loop {
+            let room = self
+                .window
+                .min(self.stream_window)
+                .clamp(0, MAX_FRAME_SIZE as i64) as usize;
+            let n = rest.len().min(room);
+            if n == 0 && !rest.is_empty() {
+                // wait for the client to make room
+                let frame = read_frame(&mut Shared(output)).await?;
+                self.control(&frame, &mut Shared(output)).await?;
+                continue;
+            }
+            let last = n == rest.len();
+            let flags = if end && last { END_STREAM } else { 0 };
+            write_all(output, &frame(DATA, flags, self.stream, &rest[..n]), shaper).await?;
+            self.window -= n as i64;
+            self.stream_window -= n as i64;
+            rest = &rest[n..];
+            if last {
+                return Ok(());
+            }
+        }
+    }
+
+    /// tells the client the connection's closing, with nothing after this
+    /// session's stream served.
+    pub fn goaway(&self) -> Vec<u8> {
+        let mut payload = self.stream.to_be_bytes().to_vec();
+        payload.extend(0u32.to_be_bytes());
+        frame(GOAWAY, 0, 0, &payload)
+    }
+
+    /// reads whatever else the client sends until it hangs up, briefly.
+    /// Clients keep acknowledging frames, and closing with those unread
+    /// resets the connection, which can cost them the end of the response.
+    pub async fn linger(&self, output: &TcpStream) {
+        let drain = async {
+            let mut buf = [0; 4096];
+            while let Ok(1..) = Shared(output).read(&mut buf).await {}
+        };
+        let _ = tokio::time::timeout(LINGER, drain).await;
+    }
+}
+
+/// A connection read and written through a shared reference, as
+/// responses hold theirs.
+struct Shared<'a>(&'a TcpStream);
+
+impl AsyncRead for Shared<'_> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            ready!(self.0.poll_read_ready(cx))?;
+            match self.0.try_read(buf.initialize_unfilled()) {
+                Ok(n) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for Shared<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            ready!(self.0.poll_write_ready(cx))?;
+            match self.0.try_write(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                written => return Poll::Ready(written),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::response::BaseResponseBuilder;
+
+    fn settings(settings: &[(u16, u32)]) -> Vec<u8> {
+        settings
+            .iter()
+            .flat_map(|(id, value)| [id.to_be_bytes().as_slice(), &value.to_be_bytes()].concat())
+            .collect()
+    }
+
+    async fn frames(mut written: &[u8]) -> Vec<(u8, u8, u32, Vec<u8>)> {
+        let mut frames = vec![];
+        while !written.is_empty() {
+            let f = read_frame(&mut written).await.unwrap();
+            frames.push((f.kind, f.flags, f.stream, f.payload));
+        }
+        frames
+    }
+
+    #[tokio::test]
+    async fn test_accept() {
+        // as curl opens, with RFC 7541 C.4.1's Huffman-coded headers
+        let block: Vec<u8> = [
+            vec![0x82, 0x86, 0x84, 0x41, 0x8c],
+            vec![
+                0xf1, 0xe3, 0xc2, 0xe5, 0xf2, 0x3a, 0x6b, 0xa0, 0xab, 0x90, 0xf4, 0xff,
+            ],
+            hpack::encode(&[("user-agent", "curl/8.5.0"), ("content-type", "text/plain")]),
+        ]
+        .concat();
+        let sent = [
+            PREFACE.to_vec(),
+            frame(
+                SETTINGS,
+                0,
+                0,
+                &settings(&[(3, 100), (4, 10485760), (2, 0)]),
+            ),
+            frame(WINDOW_UPDATE, 0, 0, &1048510465u32.to_be_bytes()),
+            frame(PRIORITY, 0, 3, &[0, 0, 0, 0, 200]),
+            frame(HEADERS, 0, 1, &block[..10]),
+            frame(CONTINUATION, END_HEADERS, 1, &block[10..]),
+            frame(DATA, PADDED, 1, &[2, b'a', b'=', 0, 0]),
+            frame(DATA, END_STREAM, 1, b"b"),
+        ]
+        .concat();
+
+        let mut written = vec![];
+        let addr = "1.1.1.1:62012".parse().unwrap();
+        let (req, session) = accept(
+            &addr,
+            &mut sent.as_slice(),
+            &mut written,
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (Method::GET, "/", "HTTP/2.0", b"a=b".as_slice(), 3),
+            (
+                req.method.clone(),
+                req.target.as_str(),
+                req.version.as_str(),
+                req.body.as_slice(),
+                req.size
+            )
+        );
+        assert_eq!("http://www.example.com/", req.url.as_str());
+        assert_eq!(
+            vec!["host", "user-agent", "content-type"],
+            req.headers
+                .iter()
+                .map(|(k, _)| k.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "3:100;4:10485760;2:0|1048510465|3:0:0:201|m,s,p,a",
+            session.fingerprint.to_string()
+        );
+        assert_eq!(10485760, session.stream_window);
+
+        let written = frames(&written).await;
+        assert_eq!((SETTINGS, 0), (written[0].0, written[0].1));
+        assert!(written.contains(&(SETTINGS, ACK, 0, vec![])));
+        // the padded DATA's whole length is given back
+        assert!(written.contains(&(WINDOW_UPDATE, 0, 1, 5u32.to_be_bytes().to_vec())));
+
+        // pseudo-headers are required, and headers can't be interrupted
+        let sent = [
+            PREFACE.to_vec(),
+            frame(HEADERS, END_STREAM, 1, &block[..10]),
+            frame(PING, 0, 0, &[0; 8]),
+        ]
+        .concat();
+        assert!(accept(
+            &addr,
+            &mut sent.as_slice(),
+            &mut vec![],
+            &Default::default()
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_upgrade() {
+        let mut req = crate::http::request::parse_request(
+            &"1.1.1.1:62012".parse().unwrap(),
+            &mut tokio::io::BufReader::new(
+                b"GET / HTTP/1.1\r\nHost: a\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAoAAAAAIAAAAA\r\n\r\n"
+                    .as_slice(),
+            ),
+        )
+        .await
+        .unwrap();
+        assert!(is_upgrade(&req));
+
+        let sent = [PREFACE.to_vec(), frame(SETTINGS, 0, 0, &[])].concat();
+        let mut written = vec![];
+        let session = upgrade(&req, &mut sent.as_slice(), &mut written)
+            .await
+            .unwrap();
+        assert!(written.starts_with(b"HTTP/1.1 101 Switching Protocols\r\n"));
+        assert_eq!(
+            "3:100;4:10485760;2:0|00|0|",
+            session.fingerprint.to_string()
+        );
+
+        req.headers.get_mut("Upgrade").unwrap()[0] = "websocket".to_string();
+        assert!(!is_upgrade(&req));
+    }
+
+    #[tokio::test]
+    async fn test_frames() {
+        let resp = BaseResponseBuilder::not_found(())
+            .body(vec![b'x'; MAX_FRAME_SIZE + 1])
+            .build()
+            .unwrap();
+
+        let session = Session::new(false);
+        let written = frames(&session.frames(&resp)).await;
+        assert_eq!(
+            vec![HEADERS, DATA, DATA, GOAWAY],
+            written.iter().map(|f| f.0).collect::<Vec<_>>()
+        );
+        let fields = hpack::Decoder::new(4096).decode(&written[0].3).unwrap();
+        assert_eq!((":status".to_string(), "404".to_string()), fields[0]);
+        assert!(fields.iter().all(|(name, _)| name != "connection"));
+        assert_eq!((END_STREAM, 1), (written[2].1, written[2].3.len()));
+    }
+}
@@ -1,47 +1,87 @@
-use std::collections::{
-    hash_map::{Entry, Iter},
-    HashMap,
-};
+use std::collections::HashMap;
 
+use indexmap::{
+    map::{Iter, IterMut},
+    IndexMap,
+};
 use lazy_static::lazy_static;
 
 /// Headers are key-value with multiple values. Adding a new header
 /// does not overwrite existing values, it only appends.
+///
+/// Names are matched case-insensitively. Headers keep the case they were
+/// first added with and are ordered by when each name was first added, so
+/// responses serialize deterministically and request header order can be
+/// inspected.
 #[derive(Debug, Default, Clone)]
-pub struct Headers(HashMap<String, Vec<String>>);
+pub struct Headers {
+    map: IndexMap<String, Vec<String>>,
+    // where each header is in map, by its lowercased name, so lookups
+    // don't scan every header
+    names: HashMap<String, usize>,
+    // list headers written a line per value rather than joined
+    repeated: &'static [&'static str],
+}
+
+/// How a header with several values is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Serialization {
+    /// on one line, the values joined by the delimiter
+    Join(&'static str),
+    /// a line per value
+    Repeat,
+}
 
 impl Headers {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn entry(&mut self, key: String) -> Entry<String, Vec<String>> {
-        self.0.entry(key)
+    // where the header is, whatever the case it was added with
+    fn index(&self, key: &str) -> Option<usize> {
+        self.names.get(&key.to_ascii_lowercase()).copied()
+    }
+
+    // rebuilds names after headers have moved
+    fn reindex(&mut self) {
+        self.names = (self.map.keys().enumerate())
+            .map(|(i, k)| (k.to_ascii_lowercase(), i))
+            .collect();
+    }
+
+    /// appends the values to the header's, under the name it was first
+    /// added with if it was.
+    pub fn extend<I: IntoIterator<Item = String>>(&mut self, key: &str, values: I) {
+        let i = match self.index(key) {
+            Some(i) => i,
+            None => {
+                let i = self.map.len();
+                self.map.insert(key.to_string(), vec![]);
+                self.names.insert(key.to_ascii_lowercase(), i);
+                i
+            }
+        };
+        self.map[i].extend(values);
     }
 
     pub fn get(&self, key: &str) -> Option<&Vec<String>> {
-        self.0.get(key)
+        self.map.get_index(self.index(key)?).map(|(_, v)| v)
     }
 
-    /// get_all returns a new vector of all headers in the provided keys
-    pub fn get_all<S: AsRef<str>>(&self, keys: &Vec<S>) -> Vec<&String> {
-        keys.iter()
-            .flat_map(|k| {
-                self.get(k.as_ref())
-                    .map(|v| v.iter().collect())
-                    .unwrap_or_else(|| vec![])
-            })
-            .collect()
+    /// the header's first value.
+    pub fn get_first(&self, key: &str) -> Option<&str> {
+        self.get(key)?.first().map(|v| v.as_str())
     }
 
     #[allow(dead_code)]
     pub fn get_mut(&mut self, key: &str) -> Option<&mut Vec<String>> {
-        self.0.get_mut(key)
+        let i = self.index(key)?;
+        self.map.get_index_mut(i).map(|(_, v)| v)
     }
 
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.map.len()
     }
 
     /// renders the HTTP request/response format header listing
@@ -51,38 +91,95 @@ impl Headers {
     }
 
     pub fn into_string(self) -> String {
-        self.0
-            .into_iter()
-            .fold(vec![], |mut acc: Vec<String>, (key, values)| {
-                if values.len() == 0 {
-                    // ???
-                } else if values.len() > 1 {
-                    match KNOWN_LIST_HEADERS.get(key.to_lowercase().as_str()) {
-                        Some(delim) => {
-                            acc.push(format!("{}: {}", key, &values.as_slice().join(delim)))
-                        }
-                        None => acc.extend(values.iter().map(|v| format!("{}: {}", key, v))),
-                    }
-                } else {
-                    acc.push(format!("{}: {}", key, &values.first().unwrap()));
+        self.lines(false).join("\n")
+    }
+
+    /// the header lines, with several values of a header joined or
+    /// repeated as its `serialization` says. `spaced` follows delimiters
+    /// with a space, as servers write responses.
+    pub fn lines(&self, spaced: bool) -> Vec<String> {
+        let mut lines = vec![];
+        for (key, values) in self.map.iter().filter(|(_, v)| !v.is_empty()) {
+            match self.serialization(key) {
+                Serialization::Join(delim) if spaced => {
+                    lines.push(format!("{}: {}", key, values.join(&format!("{} ", delim))))
+                }
+                Serialization::Join(delim) => {
+                    lines.push(format!("{}: {}", key, values.join(delim)))
                 }
+                Serialization::Repeat => {
+                    lines.extend(values.iter().map(|v| format!("{}: {}", key, v)))
+                }
+            }
+        }
+        lines
+    }
+
+    /// how several values of the header are written. Only list headers are
+    /// joined, unless they're repeated by `repeat`. Others, such as
+    /// Set-Cookie, whose dates hold commas, are always repeated.
+    pub fn serialization(&self, name: &str) -> Serialization {
+        let name = name.to_lowercase();
+        let repeated = self.repeated.iter().any(|r| r.eq_ignore_ascii_case(&name));
+        match KNOWN_LIST_HEADERS.get(name.as_str()) {
+            Some(delim) if !repeated => Serialization::Join(delim),
+            _ => Serialization::Repeat,
+        }
+    }
 
-                acc
-            })
-            .as_slice()
-            .join("\n")
+    /// writes the named list headers a line per value, as some servers do,
+    /// rather than joining them.
+    pub fn repeat(&mut self, names: &'static [&'static str]) -> &mut Self {
+        self.repeated = names;
+        self
     }
 
     pub fn add<S: ToString>(&mut self, k: &str, v: S) -> &mut Self {
-        self.0
-            .entry(k.to_string())
-            .and_modify(|values| values.push(v.to_string()))
-            .or_insert_with(|| vec![v.to_string()]);
+        self.extend(k, [v.to_string()]);
         self
     }
 
+    /// removes all values for the header, returning them. Remaining
+    /// headers keep their order.
+    pub fn remove(&mut self, k: &str) -> Option<Vec<String>> {
+        let i = self.index(k)?;
+        let removed = self.map.shift_remove_index(i).map(|(_, v)| v);
+        self.reindex();
+        removed
+    }
+
+    /// header names in the order they were first added.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(|k| k.as_str())
+    }
+
+    /// moves the headers named in `order`, matched case-insensitively,
+    /// into its order and casing. The rest follow in the order they were
+    /// first added.
+    pub fn arrange(&mut self, order: &[&str]) {
+        let rank = |k: &str| {
+            order
+                .iter()
+                .position(|o| o.eq_ignore_ascii_case(k))
+                .unwrap_or(order.len())
+        };
+        let mut entries = std::mem::take(&mut self.map)
+            .into_iter()
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(k, _)| rank(k));
+        for (k, values) in entries {
+            let k = order.get(rank(&k)).map_or(k, |name| name.to_string());
+            self.map.entry(k).or_default().extend(values);
+        }
+        self.reindex();
+    }
+
     pub fn iter(&self) -> Iter<String, Vec<String>> {
-        self.0.iter()
+        self.map.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, String, Vec<String>> {
+        self.map.iter_mut()
     }
 }
 
@@ -96,6 +193,7 @@ lazy_static! {
         ("accept-encoding", ","),
         ("accept-language", ","),
         ("access-control-request-headers", ","),
+        ("allow", ","),
         ("cache-control", ","),
         ("cookie", ";"),
         ("connection", ","),
@@ -111,6 +209,7 @@ lazy_static! {
         ("trailer", ","),
         ("transfer-encoding", ","),
         ("upgrade", ","),
+        ("vary", ","),
         ("via", ","),
         ("warning", ","),
         ("x-forwarded-for", ","),
@@ -145,4 +244,90 @@ mod test {
 
         assert_eq!(2, count, "expected to read both headers added");
     }
+
+    #[test]
+    fn test_headers_ordered() {
+        let mut h = Headers::default();
+        h.add("Host", "example.com")
+            .add("user-agent", "curl/7.83.1")
+            .add("Accept", "text/html")
+            .add("X-Forwarded-For", "10.0.0.1")
+            .add("Accept", "*/*");
+        h.remove("X-Forwarded-For");
+
+        assert_eq!(
+            vec!["Host", "user-agent", "Accept"],
+            h.names().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "Host: example.com\nuser-agent: curl/7.83.1\nAccept: text/html,*/*",
+            h.into_string()
+        );
+    }
+
+    #[test]
+    fn test_arrange() {
+        let mut h = Headers::default();
+        h.add("Server", "nginx")
+            .add("X-App", "1")
+            .add("content-type", "text/html")
+            .add("Date", "today")
+            .add("Content-Type", "text/plain");
+        h.arrange(&["Date", "Content-Type", "Server", "Content-Length"]);
+
+        assert_eq!(
+            vec!["Date", "Content-Type", "Server", "X-App"],
+            h.names().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(&vec!["text/html".to_string(), "text/plain".to_string()]),
+            h.get("Content-Type")
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let mut h = Headers::default();
+        h.add("user-agent", "curl/7.83.1")
+            .add("User-Agent", "Mozilla/5.0")
+            .add("Host", "example.com");
+
+        assert_eq!(Some("curl/7.83.1"), h.get_first("USER-AGENT"));
+        assert_eq!(2, h.get("User-Agent").unwrap().len());
+        assert_eq!(vec!["user-agent", "Host"], h.names().collect::<Vec<_>>());
+
+        h.get_mut("HOST").unwrap()[0] = "example.org".to_string();
+        assert_eq!(Some(vec!["example.org".to_string()]), h.remove("host"));
+        assert_eq!(None, h.get_first("Host"));
+
+        // headers after a removed one are still found where they moved
+        h.add("Accept", "*/*");
+        h.remove("USER-AGENT");
+        assert_eq!(Some("*/*"), h.get_first("accept"));
+    }
+
+    #[test]
+    fn test_serialization() {
+        let mut h = Headers::default();
+        h.add("Set-Cookie", "a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT")
+            .add("Cache-Control", "no-store")
+            .add("Set-Cookie", "b=2")
+            .add("Cache-Control", "no-cache");
+
+        assert_eq!(
+            vec![
+                "Set-Cookie: a=1; Expires=Wed, 21 Oct 2026 07:28:00 GMT",
+                "Set-Cookie: b=2",
+                "Cache-Control: no-store, no-cache",
+            ],
+            h.lines(true)
+        );
+
+        h.repeat(&["cache-control"]);
+        assert_eq!(Serialization::Repeat, h.serialization("Cache-Control"));
+        assert_eq!(
+            vec!["Cache-Control: no-store", "Cache-Control: no-cache"],
+            h.lines(true)[2..]
+        );
+    }
 }
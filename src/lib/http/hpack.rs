@@ -0,0 +1,400 @@
+//! HPACK, HTTP/2's header compression (RFC 7541). Requests' header blocks
+//! are decoded in full, Huffman coding and the dynamic table included, as
+//! every client uses them. Responses are encoded as plain literals, which
+//! every decoder accepts.
+use std::collections::VecDeque;
+
+use lazy_static::lazy_static;
+
+use crate::prelude::*;
+
+// the entries every table starts with, indexed from 1
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// the Huffman code's length for each byte, then end-of-string. The code
+// is canonical, so the codes themselves follow from their lengths.
+const HUFFMAN_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, 30,
+];
+const EOS: u16 = 256;
+const MAX_CODE_LEN: usize = 30;
+
+lazy_static! {
+    static ref HUFFMAN: Huffman = Huffman::new();
+}
+
+/// The canonical Huffman code, as the first code of each length, how many
+/// codes have it, and their symbols.
+struct Huffman {
+    first: [u32; MAX_CODE_LEN + 1],
+    count: [u32; MAX_CODE_LEN + 1],
+    offset: [usize; MAX_CODE_LEN + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new() -> Self {
+        let mut symbols: Vec<u16> = (0..=EOS).collect();
+        symbols.sort_by_key(|s| (HUFFMAN_LENGTHS[*s as usize], *s));
+        let mut count = [0; MAX_CODE_LEN + 1];
+        for len in HUFFMAN_LENGTHS {
+            count[len as usize] += 1;
+        }
+        let (mut first, mut offset) = ([0; MAX_CODE_LEN + 1], [0; MAX_CODE_LEN + 1]);
+        for len in 1..=MAX_CODE_LEN {
+            first[len] = (first[len - 1] + count[len - 1]) << 1;
+            offset[len] = offset[len - 1] + count[len - 1] as usize;
+        }
+        Self {
+            first,
+            count,
+            offset,
+            symbols,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(bytes.len() * 8 / 5);
+        let (mut code, mut len) = (0u32, 0usize);
+        for byte in bytes {
+            for shift in (0..8).rev() {
+                code = (code << 1) | ((byte >> shift) & 1) as u32;
+                len += 1;
+                ensure!(len <= MAX_CODE_LEN, "invalid Huffman code");
+                let index = code.wrapping_sub(self.first[len]);
+                if code >= self.first[len] && index < self.count[len] {
+                    let symbol = self.symbols[self.offset[len] + index as usize];
+                    ensure!(symbol != EOS, "Huffman-coded string contains end-of-string");
+                    out.push(symbol as u8);
+                    (code, len) = (0, 0);
+                }
+            }
+        }
+        // padding is the start of end-of-string, all ones
+        ensure!(len < 8 && code == (1 << len) - 1, "invalid Huffman padding");
+        Ok(out)
+    }
+}
+
+/// Decodes a connection's header blocks, keeping the dynamic table
+/// between them.
+#[derive(Debug)]
+pub struct Decoder {
+    table: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+    // what the client may set max_size to
+    limit: usize,
+}
+
+impl Decoder {
+    /// decodes with a dynamic table of up to max_size, as advertised to
+    /// the client.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            table: VecDeque::new(),
+            size: 0,
+            max_size,
+            limit: max_size,
+        }
+    }
+
+    /// the header fields of a whole block, in order.
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>> {
+        let mut fields = vec![];
+        let mut pos = 0;
+        while pos < block.len() {
+            let byte = block[pos];
+            if byte & 0x80 != 0 {
+                let index = integer(block, &mut pos, 7)?;
+                fields.push(self.get(index)?);
+            } else if byte & 0xc0 == 0x40 {
+                let field = self.literal(block, &mut pos, 6)?;
+                self.insert(field.clone());
+                fields.push(field);
+            } else if byte & 0xe0 == 0x20 {
+                let size = integer(block, &mut pos, 5)?;
+                ensure!(
+                    size <= self.limit,
+                    "dynamic table size {} over the {} allowed",
+                    size,
+                    self.limit
+                );
+                self.max_size = size;
+                self.evict(0);
+            } else {
+                // without indexing, or never indexed
+                fields.push(self.literal(block, &mut pos, 4)?);
+            }
+        }
+        Ok(fields)
+    }
+
+    fn get(&self, index: usize) -> Result<(String, String)> {
+        match index {
+            0 => bail!("header field index 0"),
+            i if i <= STATIC_TABLE.len() => {
+                let (name, value) = STATIC_TABLE[i - 1];
+                Ok((name.to_string(), value.to_string()))
+            }
+            i => self
+                .table
+                .get(i - STATIC_TABLE.len() - 1)
+                .cloned()
+                .ok_or_else(|| anyhow!("header field index {} out of range", i)),
+        }
+    }
+
+    fn literal(&self, block: &[u8], pos: &mut usize, prefix: u8) -> Result<(String, String)> {
+        let name = match integer(block, pos, prefix)? {
+            0 => string(block, pos)?,
+            index => self.get(index)?.0,
+        };
+        Ok((name, string(block, pos)?))
+    }
+
+    fn insert(&mut self, field: (String, String)) {
+        let size = entry_size(&field);
+        self.evict(size);
+        // entries larger than the whole table just empty it
+        if size <= self.max_size {
+            self.size += size;
+            self.table.push_front(field);
+        }
+    }
+
+    /// evicts the oldest entries until there's room for another of size.
+    fn evict(&mut self, size: usize) {
+        while self.size + size > self.max_size {
+            match self.table.pop_back() {
+                Some(field) => self.size -= entry_size(&field),
+                None => break,
+            }
+        }
+    }
+}
+
+fn entry_size((name, value): &(String, String)) -> usize {
+    name.len() + value.len() + 32
+}
+
+/// an integer with an N-bit prefix, starting at pos.
+fn integer(block: &[u8], pos: &mut usize, prefix: u8) -> Result<usize> {
+    let max = (1usize << prefix) - 1;
+    let first = *block
+        .get(*pos)
+        .ok_or_else(|| anyhow!("header block ended in an integer"))? as usize
+        & max;
+    *pos += 1;
+    if first < max {
+        return Ok(first);
+    }
+    let mut value = max;
+    for shift in (0..28).step_by(7) {
+        let byte = *block
+            .get(*pos)
+            .ok_or_else(|| anyhow!("header block ended in an integer"))?;
+        *pos += 1;
+        value += ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("header block integer too large")
+}
+
+fn string(block: &[u8], pos: &mut usize) -> Result<String> {
+    let huffman = block.get(*pos).is_some_and(|b| b & 0x80 != 0);
+    let len = integer(block, pos, 7)?;
+    let bytes = block
+        .get(*pos..*pos + len)
+        .ok_or_else(|| anyhow!("header block ended in a string"))?;
+    *pos += len;
+    let bytes = match huffman {
+        true => HUFFMAN.decode(bytes)?,
+        false => bytes.to_vec(),
+    };
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// a header block of the fields as literals, neither indexed nor Huffman
+/// coded.
+pub fn encode<S: AsRef<str>>(fields: &[(S, S)]) -> Vec<u8> {
+    let mut block = vec![];
+    for (name, value) in fields {
+        block.push(0);
+        for s in [name.as_ref(), value.as_ref()] {
+            encode_integer(&mut block, s.len(), 7, 0);
+            block.extend(s.as_bytes());
+        }
+    }
+    block
+}
+
+fn encode_integer(block: &mut Vec<u8>, value: usize, prefix: u8, flags: u8) {
+    let max = (1usize << prefix) - 1;
+    if value < max {
+        block.push(flags | value as u8);
+        return;
+    }
+    block.push(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 0x80 {
+        block.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    block.push(rest as u8);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn fields(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode() {
+        // RFC 7541 C.4, three requests over one connection, Huffman coded
+        let mut decoder = Decoder::new(4096);
+        assert_eq!(
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ]),
+            decoder
+                .decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"))
+                .unwrap()
+        );
+        decoder
+            .decode(&hex("8286 84be 5886 a8eb 1064 9cbf"))
+            .unwrap();
+        assert_eq!(
+            fields(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ]),
+            decoder
+                .decode(&hex(
+                    "8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf"
+                ))
+                .unwrap()
+        );
+        assert_eq!(164, decoder.size);
+        assert_eq!(
+            ("cache-control".to_string(), "no-cache".to_string()),
+            decoder.table[1]
+        );
+
+        // a table too small for new entries evicts everything
+        let mut decoder = Decoder::new(4096);
+        decoder.decode(&hex("20")).unwrap();
+        assert_eq!(
+            fields(&[("custom-key", "custom-header")]),
+            decoder
+                .decode(&hex(
+                    "400a 6375 7374 6f6d 2d6b 6579 0d63 7573 746f 6d2d 6865 6164 6572"
+                ))
+                .unwrap()
+        );
+        assert!(decoder.table.is_empty());
+
+        assert!(Decoder::new(4096).decode(&hex("be")).is_err());
+        assert!(Decoder::new(4096).decode(&hex("3fe21f")).is_err());
+        assert!(HUFFMAN.decode(&hex("ff ff ff ff")).is_err());
+    }
+
+    #[test]
+    fn test_encode() {
+        let fields = fields(&[(":status", "200"), ("server", &"x".repeat(300))]);
+        assert_eq!(fields, Decoder::new(4096).decode(&encode(&fields)).unwrap());
+    }
+}
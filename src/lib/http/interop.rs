@@ -0,0 +1,293 @@
+//! Conversions between httpot's http types and the `http` crate's, so
+//! tower/hyper middleware and fixtures can be reused with the honeypot.
+//!
+//! Conversions towards the `http` crate are fallible as httpot is far more
+//! lenient with what it accepts in header names and values.
+use std::net::{Ipv4Addr, SocketAddr};
+
+use url::Url;
+
+use crate::{
+    http::{
+        headers::Headers,
+        request::{Method, Request},
+        response::{BaseResponse, BaseResponseBuilder, StatusCode},
+    },
+    prelude::*,
+};
+
+impl TryFrom<&Headers> for ::http::HeaderMap {
+    type Error = Error;
+
+    fn try_from(headers: &Headers) -> Result<Self> {
+        let mut map = ::http::HeaderMap::new();
+        for (k, values) in headers.iter() {
+            let name = ::http::header::HeaderName::from_bytes(k.as_bytes())
+                .map_err(|e| anyhow!("invalid header name {:?}: {}", k, e))?;
+            for v in values {
+                let value = ::http::header::HeaderValue::from_str(v)
+                    .map_err(|e| anyhow!("invalid value for header {:?}: {}", k, e))?;
+                map.append(name.clone(), value);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl TryFrom<Headers> for ::http::HeaderMap {
+    type Error = Error;
+
+    fn try_from(headers: Headers) -> Result<Self> {
+        (&headers).try_into()
+    }
+}
+
+/// non-utf8 header values are lossily converted
+impl From<&::http::HeaderMap> for Headers {
+    fn from(map: &::http::HeaderMap) -> Self {
+        let mut headers = Headers::new();
+        for (k, v) in map.iter() {
+            headers.add(k.as_str(), String::from_utf8_lossy(v.as_bytes()));
+        }
+
+        headers
+    }
+}
+
+impl From<::http::HeaderMap> for Headers {
+    fn from(map: ::http::HeaderMap) -> Self {
+        (&map).into()
+    }
+}
+
+impl From<StatusCode> for ::http::StatusCode {
+    fn from(status: StatusCode) -> Self {
+        // every StatusCode variant is a valid 3 digit code
+        ::http::StatusCode::from_u16(status as u16).unwrap()
+    }
+}
+
+impl TryFrom<::http::StatusCode> for StatusCode {
+    type Error = Error;
+
+    fn try_from(status: ::http::StatusCode) -> Result<Self> {
+        use num::traits::FromPrimitive;
+        StatusCode::from_u16(status.as_u16())
+            .ok_or_else(|| anyhow!("unsupported status code: {}", status))
+    }
+}
+
+impl From<&Method> for ::http::Method {
+    fn from(method: &Method) -> Self {
+        use Method::*;
+        match method {
+            GET => ::http::Method::GET,
+            HEAD => ::http::Method::HEAD,
+            POST => ::http::Method::POST,
+            PUT => ::http::Method::PUT,
+            DELETE => ::http::Method::DELETE,
+            CONNECT => ::http::Method::CONNECT,
+            OPTIONS => ::http::Method::OPTIONS,
+            TRACE => ::http::Method::TRACE,
+        }
+    }
+}
+
+impl TryFrom<&::http::Method> for Method {
+    type Error = Error;
+
+    fn try_from(method: &::http::Method) -> Result<Self> {
+        method.as_str().parse()
+    }
+}
+
+fn version_to_http(version: &str) -> Result<::http::Version> {
+    Ok(match version {
+        "HTTP/0.9" => ::http::Version::HTTP_09,
+        "HTTP/1.0" => ::http::Version::HTTP_10,
+        "HTTP/1.1" | "" => ::http::Version::HTTP_11,
+        "HTTP/2" | "HTTP/2.0" => ::http::Version::HTTP_2,
+        "HTTP/3" | "HTTP/3.0" => ::http::Version::HTTP_3,
+        other => bail!("unknown http version: {:?}", other),
+    })
+}
+
+fn version_from_http(version: ::http::Version) -> String {
+    format!("{:?}", version)
+}
+
+/// The request's remote address is carried as a SocketAddr extension.
+impl TryFrom<&Request> for ::http::Request<Vec<u8>> {
+    type Error = Error;
+
+    fn try_from(req: &Request) -> Result<Self> {
+        let mut builder = ::http::Request::builder()
+            .method(&req.method)
+            .uri(req.url.as_str())
+            .version(version_to_http(&req.version)?);
+
+        *builder
+            .headers_mut()
+            .ok_or_else(|| anyhow!("failed to build http request"))? = (&req.headers).try_into()?;
+
+        builder
+            .extension(req.remote_ip)
+            .body(req.body.clone())
+            .map_err(|e| anyhow!("failed to build http request: {}", e))
+    }
+}
+
+/// The remote address is read from a SocketAddr extension if present,
+/// otherwise it is unspecified. Relative URIs are resolved against the
+/// Host header.
+impl<B: AsRef<[u8]>> TryFrom<::http::Request<B>> for Request {
+    type Error = Error;
+
+    fn try_from(req: ::http::Request<B>) -> Result<Self> {
+        let headers: Headers = req.headers().into();
+        let url = match req.uri().scheme() {
+            Some(_) => Url::parse(&req.uri().to_string()),
+            None => Url::parse(&format!(
+                "http://{}{}",
+                headers
+                    .get_first("Host")
+                    .ok_or_else(|| anyhow!("failed to get host header"))?,
+                req.uri()
+            )),
+        }
+        .map_err(|e| anyhow!("failed to construct url: {}", e))?;
+
+        let body = req.body().as_ref().to_vec();
+        Ok(Request {
+            size: body.len(),
+            body,
+            method: req.method().try_into()?,
+            url,
+            target: req.uri().to_string(),
+            version: version_from_http(req.version()),
+            remote_ip: req
+                .extensions()
+                .get::<SocketAddr>()
+                .cloned()
+                .unwrap_or_else(|| (Ipv4Addr::UNSPECIFIED, 0).into()),
+            via_trusted_proxy: false,
+            headers,
+            syntax: Default::default(),
+            spooled: None,
+            raw_head: vec![],
+        })
+    }
+}
+
+impl<T: std::fmt::Debug> TryFrom<BaseResponse<T>> for ::http::Response<Vec<u8>> {
+    type Error = Error;
+
+    fn try_from(resp: BaseResponse<T>) -> Result<Self> {
+        let mut builder = ::http::Response::builder()
+            .status(::http::StatusCode::from_u16(resp.code())?)
+            .version(version_to_http(resp.version().unwrap_or_default())?);
+
+        *builder
+            .headers_mut()
+            .ok_or_else(|| anyhow!("failed to build http response"))? =
+            resp.headers().try_into()?;
+
+        builder
+            .body(resp.body().to_vec())
+            .map_err(|e| anyhow!("failed to build http response: {}", e))
+    }
+}
+
+/// Converted responses have no output; use BaseResponse::with_output to
+/// attach one.
+impl<B: AsRef<[u8]>> TryFrom<::http::Response<B>> for BaseResponse<()> {
+    type Error = Error;
+
+    fn try_from(resp: ::http::Response<B>) -> Result<Self> {
+        // body sets its own Content-Length
+        let mut headers = resp.headers().clone();
+        headers.remove(::http::header::CONTENT_LENGTH);
+
+        let mut builder = BaseResponseBuilder::default(());
+        builder
+            .headers(headers.into())
+            .status_code(resp.status().as_u16())
+            .version(version_from_http(resp.version()))
+            .body(resp.body());
+
+        Ok(builder.build()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_request_round_trip() {
+        let mut req = Request::stub(Method::POST, "/foo?bar=baz", "foo");
+        req.headers
+            .add("Host", "127.0.0.1:8080")
+            .add("User-Agent", "curl/7.83.1")
+            .add("Accept", "text/html")
+            .add("Accept", "*/*");
+        req.remote_ip = "1.2.3.4:5678".parse().unwrap();
+
+        let converted: ::http::Request<Vec<u8>> = (&req).try_into().unwrap();
+        assert_eq!(::http::Method::POST, converted.method());
+        assert_eq!("/foo", converted.uri().path());
+        assert_eq!(2, converted.headers().get_all("accept").iter().count());
+        assert_eq!(
+            Some(&req.remote_ip),
+            converted.extensions().get::<SocketAddr>()
+        );
+
+        let back: Request = converted.try_into().unwrap();
+        assert_eq!(req.method, back.method);
+        assert_eq!(req.url, back.url);
+        assert_eq!(req.version, back.version);
+        assert_eq!(req.body, back.body);
+        assert_eq!(req.remote_ip, back.remote_ip);
+        assert_eq!(
+            Some(&vec!["text/html".to_string(), "*/*".to_string()]),
+            back.headers.get("accept")
+        );
+    }
+
+    #[test]
+    fn test_relative_request_uses_host() {
+        let req = ::http::Request::builder()
+            .uri("/admin/")
+            .header("Host", "example.com")
+            .body(vec![])
+            .unwrap();
+
+        let req: Request = req.try_into().unwrap();
+        assert_eq!("http://example.com/admin/", req.url.as_str());
+        assert!(req.remote_ip.ip().is_unspecified());
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let resp = BaseResponseBuilder::not_found(())
+            .add_header("X-Powered-By", "PHP/4.0.1")
+            .body("nope")
+            .build()
+            .unwrap();
+
+        let converted: ::http::Response<Vec<u8>> = resp.try_into().unwrap();
+        assert_eq!(::http::StatusCode::NOT_FOUND, converted.status());
+        assert_eq!("PHP/4.0.1", converted.headers()["x-powered-by"]);
+        assert_eq!(b"nope", converted.body().as_slice());
+
+        let back: BaseResponse<()> = converted.try_into().unwrap();
+        assert_eq!(StatusCode::NotFound, back.status_code());
+        assert_eq!(b"nope", back.body());
+    }
+
+    #[test]
+    fn test_unsupported_status() {
+        assert!(StatusCode::try_from(::http::StatusCode::from_u16(599).unwrap()).is_err());
+    }
+}
@@ -1,4 +1,12 @@
+pub mod date;
+pub mod egress;
+pub mod h2;
 pub mod headers;
+pub mod hpack;
+pub mod interop;
+pub mod query;
 pub mod request;
 pub mod response;
+pub mod spool;
+#[cfg(feature = "server")]
 pub mod stock_responses;
@@ -0,0 +1,145 @@
+//! Query strings parsed into every parameter sent, rather than flattened.
+//! Servers disagree on repeated names (PHP keeps the last, ASP.NET joins
+//! them, most frameworks take the first), on `;` as a separator, and on
+//! `name[]` arrays, which parameter pollution plays them off against.
+use url::form_urlencoded;
+
+/// A query parameter, decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    /// the name without any array subscript, so `ids` for `ids[]`
+    pub name: String,
+    /// what was between the brackets of `name[...]`, if any: empty for
+    /// `ids[]` and `0` for `ids[0]`
+    pub subscript: Option<String>,
+    pub value: String,
+}
+
+/// Every parameter of a query, in the order sent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    params: Vec<Param>,
+    /// some parameters were separated by `;` rather than `&`
+    pub semicolons: bool,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let params = query
+            .split(['&', ';'])
+            .filter_map(|pair| form_urlencoded::parse(pair.as_bytes()).next())
+            .map(|(name, value)| {
+                let (name, subscript) = split_subscript(&name);
+                Param {
+                    name: name.to_string(),
+                    subscript: subscript.map(str::to_string),
+                    value: value.into_owned(),
+                }
+            })
+            .collect();
+
+        Self {
+            params,
+            semicolons: query.contains(';'),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Param> {
+        self.params.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// the first value sent under the name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).into_iter().next()
+    }
+
+    /// every value sent under the name, including as an array.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.params
+            .iter()
+            .filter(|p| p.name == name)
+            .map(|p| p.value.as_str())
+            .collect()
+    }
+
+    /// whether the name was sent at all.
+    pub fn contains(&self, name: &str) -> bool {
+        self.params.iter().any(|p| p.name == name)
+    }
+
+    /// names sent more than once outside of array syntax, under the same
+    /// key, or both as an array and not, which servers resolve differently.
+    /// In the order first sent.
+    pub fn polluted(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = vec![];
+        for (i, param) in self.params.iter().enumerate() {
+            let repeated = self.params[..i].iter().any(|prev| {
+                prev.name == param.name
+                    && match (&prev.subscript, &param.subscript) {
+                        (Some(prev), Some(key)) => !key.is_empty() && prev == key,
+                        _ => true,
+                    }
+            });
+            if repeated && !names.contains(&param.name.as_str()) {
+                names.push(&param.name);
+            }
+        }
+        names
+    }
+}
+
+// splits `ids[]` into `ids` and an empty subscript; names starting with a
+// bracket aren't arrays
+fn split_subscript(name: &str) -> (&str, Option<&str>) {
+    match (name.find('['), name.strip_suffix(']')) {
+        (Some(open), Some(rest)) if open > 0 => (&name[..open], Some(&rest[open + 1..])),
+        _ => (name, None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse("id=1&ids[]=2&ids[]=3&map[a]=b;q=a+b%21&flag&=PHPE9568F36");
+        let params = query
+            .iter()
+            .map(|p| (p.name.as_str(), p.subscript.as_deref(), p.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("id", None, "1"),
+                ("ids", Some(""), "2"),
+                ("ids", Some(""), "3"),
+                ("map", Some("a"), "b"),
+                ("q", None, "a b!"),
+                ("flag", None, ""),
+                ("", None, "PHPE9568F36"),
+            ],
+            params
+        );
+        assert!(query.semicolons);
+        assert_eq!(vec!["2", "3"], query.get_all("ids"));
+        assert_eq!(Some("a b!"), query.get("q"));
+        assert!(query.contains("flag"));
+        assert!(query.polluted().is_empty());
+
+        assert!(Query::parse("").is_empty());
+        assert_eq!("[x]", Query::parse("[x]=1").iter().next().unwrap().name);
+    }
+
+    #[test]
+    fn test_polluted() {
+        assert_eq!(vec!["id"], Query::parse("id=1&id=2").polluted());
+        assert_eq!(vec!["id"], Query::parse("id[]=1&id=2").polluted());
+        assert_eq!(vec!["a", "b"], Query::parse("a=1;b=1&a=2&b=2").polluted());
+        assert_eq!(vec!["map"], Query::parse("map[a]=1&map[a]=2").polluted());
+        assert!(Query::parse("id[]=1&id[]=2&id[x]=3").polluted().is_empty());
+    }
+}
@@ -1,19 +1,32 @@
 use std::string::ToString;
-use std::{fmt, io::ErrorKind as IOErrorKind, sync::Arc};
+use std::{
+    fmt,
+    io::ErrorKind as IOErrorKind,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use chrono::offset::Utc;
-use tokio::net::TcpStream;
+use rand::prelude::*;
+use tokio::{net::TcpStream, sync::OwnedSemaphorePermit, time::sleep};
 
-use crate::{http::headers::Headers, prelude::*};
+use crate::{
+    http::{date, egress::Shaper, h2::Session, headers::Headers},
+    prelude::*,
+};
 
 #[derive(Builder, Debug, Clone)]
-#[builder(setter(into))]
+#[builder(setter(into), build_fn(validate = "Self::validate"))]
 pub struct BaseResponse<T: fmt::Debug> {
     #[builder(setter(custom))]
     output: T,
 
     #[builder(setter(custom))]
     status_code: StatusCode,
+    // sent instead of status_code's when it isn't in the registry
+    #[builder(setter(custom), default)]
+    code: Option<u16>,
+    #[builder(setter(custom), default)]
+    reason: Option<String>,
     #[builder(setter(custom))]
     body: Vec<u8>,
     #[builder(setter(custom), default = "default_headers()")]
@@ -21,6 +34,64 @@ pub struct BaseResponse<T: fmt::Debug> {
 
     #[builder(setter(into, strip_option), default)]
     version: Option<String>,
+
+    #[builder(setter(strip_option), default)]
+    trickle: Option<Trickle>,
+
+    // what answered, for metrics
+    #[builder(setter(custom), default)]
+    route: Option<String>,
+    // Content-Length was declared rather than taken from the body
+    #[builder(setter(custom), default)]
+    declared_length: bool,
+}
+
+/// A body trickled out after the rest of the response, as tarpits send.
+/// It's generated as it's written, so it can be far larger than memory,
+/// and its Content-Length is up to the caller.
+#[derive(Debug, Clone)]
+pub struct Trickle {
+    /// how much is generated, unless the limit is hit first
+    pub len: u64,
+    pub bytes_per_sec: u64,
+    /// how long to trickle before giving up on the rest
+    pub limit: Duration,
+    /// sent before the generated bytes, such as an archive's magic
+    pub prefix: Vec<u8>,
+    pub seed: u64,
+    /// held while the response is, to bound concurrent streams
+    pub permit: Option<Arc<OwnedSemaphorePermit>>,
+    sent: u64,
+}
+
+impl Trickle {
+    pub fn new(len: u64, bytes_per_sec: u64, limit: Duration, seed: u64) -> Self {
+        Self {
+            len,
+            bytes_per_sec,
+            limit,
+            prefix: vec![],
+            seed,
+            permit: None,
+            sent: 0,
+        }
+    }
+
+    /// bytes written so far.
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    /// the next chunk of up to a second's worth of bytes.
+    fn chunk<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<u8> {
+        let n = self.bytes_per_sec.max(1).min(self.len - self.sent);
+        (self.sent..self.sent + n)
+            .map(|i| match self.prefix.get(i as usize) {
+                Some(b) => *b,
+                None => rng.gen(),
+            })
+            .collect()
+    }
 }
 
 pub type Response = BaseResponse<Arc<TcpStream>>;
@@ -33,14 +104,16 @@ fn default_headers() -> Headers {
         format!(
             "httpot{}",
             if let Ok(ver) = std::env::var("CARGO_PKG_VERSION") {
-                "/".to_owned() + &ver
+                // `+ &ver` is ambiguous with the scripting feature, as
+                // rhai's smartstring can also be added to a String
+                "/".to_owned() + ver.as_str()
             } else {
                 "".to_string()
             }
         ),
     );
-    headers.add("Date", Utc::now().format("%a, %d %b %Y %H:%M:%S GMT"));
-    headers.add("Connection", "Close");
+    headers.add("Date", date::now());
+    headers.add("Connection", "close");
 
     headers
 }
@@ -57,35 +130,111 @@ impl<T: fmt::Debug + Clone> BaseResponse<T> {
 
 impl<T: fmt::Debug> BaseResponse<T> {
     pub fn into_string(self) -> Result<String> {
+        let mut s = self.head();
+        s.push_str(
+            &String::from_utf8(self.body)
+                .map_err(|e| anyhow!("body failed to convert to utf8: {}", e))?,
+        );
+        Ok(s)
+    }
+
+    /// the status line and headers, through the blank line before the body.
+    pub fn head(&self) -> String {
         let mut lines: Vec<String> = vec![format!(
             "{} {} {}",
-            self.version.unwrap_or_else(|| "HTTP/1.1".to_string()),
-            self.status_code as i32,
-            self.status_code.to_string(),
+            self.version.as_deref().unwrap_or("HTTP/1.1"),
+            self.code(),
+            self.reason(),
         )];
 
-        lines.extend(
-            self.headers
-                .iter()
-                .map(|(k, v)| format!("{}: {}", k, v.as_slice().join(", ")))
-                .collect::<Vec<_>>(),
-        );
+        lines.extend(self.headers.lines(true));
+        lines.push("".to_string());
         lines.push("".to_string());
-        lines.push(
-            String::from_utf8(self.body)
-                .map_err(|e| anyhow!("body failed to convert to utf8: {}", e))?,
-        );
 
-        Ok(lines.as_slice().join("\r\n"))
+        lines.as_slice().join("\r\n")
     }
 
+    /// the registered status sent, or its class's generic status for
+    /// custom codes.
     pub fn status_code(&self) -> StatusCode {
         self.status_code
     }
 
+    /// the numeric code sent in the status line.
+    pub fn code(&self) -> u16 {
+        self.code.unwrap_or(self.status_code as u16)
+    }
+
+    /// the reason phrase sent in the status line. Custom codes without
+    /// one have an empty reason.
+    pub fn reason(&self) -> &str {
+        match (&self.reason, self.code) {
+            (Some(reason), _) => reason,
+            (None, Some(_)) => "",
+            (None, None) => self.status_code.reason(),
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.body.len()
     }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// the route which answered, if it was named.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+
+    /// names the route which answered, unless it already was.
+    pub fn with_route<S: ToString>(mut self, route: S) -> Self {
+        if self.route.is_none() {
+            self.route = Some(route.to_string());
+        }
+        self
+    }
+
+    /// drops the body for answering HEAD, keeping the Content-Length a GET
+    /// would have sent.
+    pub fn without_body(mut self) -> Self {
+        self.body.clear();
+        self
+    }
+
+    /// moves this response onto a new output, keeping everything else.
+    pub fn with_output<U: fmt::Debug>(self, output: U) -> BaseResponse<U> {
+        BaseResponse {
+            output,
+            status_code: self.status_code,
+            code: self.code,
+            reason: self.reason,
+            body: self.body,
+            headers: self.headers,
+            version: self.version,
+            trickle: self.trickle,
+            route: self.route,
+            declared_length: self.declared_length,
+        }
+    }
+
+    /// the trickled body, if there is one.
+    pub fn trickle(&self) -> Option<&Trickle> {
+        self.trickle.as_ref()
+    }
 }
 
 impl BaseResponse<Arc<TcpStream>> {
@@ -96,48 +245,114 @@ impl BaseResponse<Arc<TcpStream>> {
     /// entire response is written. Callers should time out after an unreasonable
     /// amount of time if desired.
     pub async fn send(&mut self) -> Result<()> {
-        let mut n = 0;
-        let buf = self.to_string()?;
-        let buf = buf.as_bytes();
-        loop {
-            self.output
-                .writable()
-                .await
-                .map_err(|e| anyhow!("write half failed to be writeable in write loop: {}", e))?;
-            match self.output.try_write(&buf[n..]) {
-                Ok(remainder) if remainder + n < buf.len() => {
-                    let new_n = n + remainder;
-                    trace!(
-                        "wrote only {} of remaining {} in metrics response, will retry",
-                        n,
-                        buf.len() - new_n
-                    );
-                    n = new_n;
-                }
-                Ok(remainder) => {
-                    trace!(
-                        "done writing metrics response with remainder {} (n={}, buf.len={})",
-                        remainder,
-                        n,
-                        buf.len()
-                    );
-                    break;
-                }
-                Err(e) if e.kind() == IOErrorKind::WouldBlock => {
-                    trace!("metrics response would block by writing, waiting");
-                }
-                Err(e) => bail!(
-                    "failed to write remaining buf remainder={}, n={}, buf.len()={}: {}",
+        self.send_shaped(&Shaper::new(None)).await
+    }
+
+    /// sends the response as send does, only as fast as the shaper
+    /// allows.
+    pub async fn send_shaped(&mut self, shaper: &Shaper) -> Result<()> {
+        // bodies are sent as-is, so binaries survive
+        let mut buf = self.head().into_bytes();
+        buf.extend(&self.body);
+        write_all(&self.output, &buf, shaper).await?;
+        self.send_trickle(None, shaper).await;
+        Ok(())
+    }
+
+    /// write all of the response as HTTP/2 frames on the session's stream,
+    /// then tell the client the connection's closing.
+    pub async fn send_h2(&mut self, session: &mut Session, shaper: &Shaper) -> Result<()> {
+        let trickled = self.trickle.is_some();
+        let end = self.body.is_empty() && !trickled;
+        write_all(&self.output, &session.head(self, end), shaper).await?;
+        if !self.body.is_empty() {
+            session
+                .data(&self.output, &self.body, !trickled, shaper)
+                .await?;
+        }
+        if trickled {
+            self.send_trickle(Some(session), shaper).await;
+            session.data(&self.output, &[], true, shaper).await?;
+        }
+        write_all(&self.output, &session.goaway(), shaper).await?;
+        session.linger(&self.output).await;
+        Ok(())
+    }
+
+    async fn send_trickle(&mut self, mut h2: Option<&mut Session>, shaper: &Shaper) {
+        let trickle = match self.trickle.as_mut() {
+            Some(trickle) => trickle,
+            None => return,
+        };
+        let started = Instant::now();
+        let mut rng = StdRng::seed_from_u64(trickle.seed);
+        while trickle.sent < trickle.len && started.elapsed() < trickle.limit {
+            let chunk = trickle.chunk(&mut rng);
+            let written = match h2.as_deref_mut() {
+                Some(session) => session.data(&self.output, &chunk, false, shaper).await,
+                None => write_all(&self.output, &chunk, shaper).await,
+            };
+            // the client hanging up ends the tarpit, it isn't an error
+            if let Err(e) = written {
+                debug!("trickle ended after {} bytes: {}", trickle.sent, e);
+                break;
+            }
+            trickle.sent += chunk.len() as u64;
+            sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
+
+/// writes all of the buffer, as fast as the shaper allows.
+pub(crate) async fn write_all(output: &TcpStream, buf: &[u8], shaper: &Shaper) -> Result<()> {
+    for chunk in buf.chunks(shaper.chunk_len()) {
+        shaper.wait(chunk.len()).await;
+        write_chunk(output, chunk).await?;
+    }
+    Ok(())
+}
+
+async fn write_chunk(output: &TcpStream, buf: &[u8]) -> Result<()> {
+    let mut n = 0;
+    loop {
+        output.writable().await.map_err(|e| {
+            Error::new(e).context("write half failed to be writeable in write loop")
+        })?;
+        match output.try_write(&buf[n..]) {
+            Ok(remainder) if remainder + n < buf.len() => {
+                let new_n = n + remainder;
+                trace!(
+                    "wrote only {} of remaining {} in metrics response, will retry",
+                    n,
+                    buf.len() - new_n
+                );
+                n = new_n;
+            }
+            Ok(remainder) => {
+                trace!(
+                    "done writing metrics response with remainder {} (n={}, buf.len={})",
+                    remainder,
+                    n,
+                    buf.len()
+                );
+                break;
+            }
+            Err(e) if e.kind() == IOErrorKind::WouldBlock => {
+                trace!("metrics response would block by writing, waiting");
+            }
+            // kept whole, so how the session ended can be told from it
+            Err(e) => {
+                return Err(Error::new(e).context(format!(
+                    "failed to write remaining buf remainder={}, n={}, buf.len()={}",
                     buf.len() - n,
                     n,
-                    buf.len(),
-                    e
-                ),
+                    buf.len()
+                )))
             }
         }
-
-        Ok(())
     }
+
+    Ok(())
 }
 
 impl<T: fmt::Debug> BaseResponseBuilder<T> {
@@ -145,9 +360,14 @@ impl<T: fmt::Debug> BaseResponseBuilder<T> {
         Self {
             output: Some(out),
             status_code: Default::default(),
+            code: None,
+            reason: None,
             body: Default::default(),
             headers: Some(default_headers()),
             version: None,
+            trickle: None,
+            route: None,
+            declared_length: None,
         }
     }
 
@@ -163,11 +383,37 @@ impl<T: fmt::Debug> BaseResponseBuilder<T> {
         s
     }
 
+    /// a redirect to the location with the status, which should be a 3xx.
+    pub fn redirect(out: T, status: StatusCode, location: &str) -> Self {
+        let mut s: Self = Self::default(out);
+        s.status_code = Some(status);
+        s.set_header("Location", location);
+        s
+    }
+
+    pub fn moved_permanently(out: T, location: &str) -> Self {
+        Self::redirect(out, StatusCode::MovedPermanently, location)
+    }
+
+    pub fn found(out: T, location: &str) -> Self {
+        Self::redirect(out, StatusCode::Found, location)
+    }
+
+    pub fn temporary_redirect(out: T, location: &str) -> Self {
+        Self::redirect(out, StatusCode::TemporaryRedirect, location)
+    }
+
     pub fn output(&mut self, out: T) -> &mut Self {
         self.output = Some(out);
         self
     }
 
+    /// replaces all headers, including the defaults.
+    pub fn headers(&mut self, headers: Headers) -> &mut Self {
+        self.headers = Some(headers);
+        self
+    }
+
     pub fn add_headers<S: ToString>(&mut self, name: &str, values: Vec<S>) -> &mut Self {
         for v in values {
             self.add_header(name, v);
@@ -175,6 +421,14 @@ impl<T: fmt::Debug> BaseResponseBuilder<T> {
         self
     }
 
+    /// sets the header, replacing any existing values.
+    pub fn set_header<S: ToString>(&mut self, name: &str, value: S) -> &mut Self {
+        if let Some(headers) = self.headers.as_mut() {
+            headers.remove(name);
+        }
+        self.add_header(name, value)
+    }
+
     pub fn add_header<S: ToString>(&mut self, name: &str, value: S) -> &mut Self {
         if self.headers.is_none() {
             self.headers = Some(default_headers());
@@ -184,92 +438,240 @@ impl<T: fmt::Debug> BaseResponseBuilder<T> {
         self
     }
 
+    /// sets the status from a StatusCode or any three digit code. Codes
+    /// outside of the registry are sent as-is with an empty reason unless
+    /// one is provided.
     pub fn status_code<I: num::traits::ToPrimitive>(&mut self, status: I) -> &mut Self {
         // globally, these interfere with derive macros used for StatusCode
         use num::traits::FromPrimitive;
-        self.status_code = status.to_i64().and_then(StatusCode::from_i64);
+        let code = status.to_i64();
+        self.status_code = code.and_then(StatusCode::from_i64);
+        self.code = Some(None);
+
+        if let (None, Some(code @ 100..=999)) = (self.status_code, code) {
+            self.status_code = Some(StatusCode::for_class(code as u16));
+            self.code = Some(Some(code as u16));
+        }
+        self
+    }
+
+    /// overrides the status's reason phrase.
+    pub fn reason<S: ToString>(&mut self, reason: S) -> &mut Self {
+        self.reason = Some(Some(reason.to_string()));
         self
     }
 
+    /// sets the body, and its length as Content-Length unless one was
+    /// declared.
     pub fn body<B: AsRef<[u8]>>(&mut self, body: B) -> &mut Self {
         let body = body.as_ref().iter().cloned().collect::<Vec<_>>();
         let len = body.len();
 
         self.body = Some(body);
-        self.add_header("Content-Length", len);
+        if self.declared_length != Some(true) {
+            self.set_header("Content-Length", len);
+        }
         self
     }
+
+    /// declares the Content-Length, even one the body doesn't have, as HEAD
+    /// answers, trickled bodies, and deliberately lying responses need.
+    /// Otherwise building fails unless it's the body's length.
+    pub fn content_length<L: ToString>(&mut self, len: L) -> &mut Self {
+        self.declared_length = Some(true);
+        self.set_header("Content-Length", len)
+    }
+
+    fn validate(&self) -> std::result::Result<(), String> {
+        let lengths = self
+            .headers
+            .as_ref()
+            .and_then(|h| h.get("Content-Length"))
+            .cloned()
+            .unwrap_or_default();
+        let body_len = self.body.as_ref().map_or(0, Vec::len);
+        match lengths.as_slice() {
+            [_, _, ..] => Err(format!("conflicting Content-Lengths {:?}", lengths)),
+            _ if self.declared_length == Some(true) => Ok(()),
+            [len] if len.trim() != body_len.to_string() => Err(format!(
+                "Content-Length {} isn't the body's {} bytes, declare it with content_length",
+                len, body_len
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
-/// Limited set of StatusCodes supported by httpot.
+/// StatusCodes in the IANA registry. Other codes may be sent with
+/// `BaseResponseBuilder::status_code`, which falls back to the class's
+/// generic status here.
 #[derive(Debug, PartialEq, Eq, FromPrimitive, ToPrimitive, Clone, Copy, Default)]
 pub enum StatusCode {
     // 100s
+    Continue = 100,
+    SwitchingProtocols = 101,
+    Processing = 102,
+    EarlyHints = 103,
 
     // 200s
     #[default]
     Ok = 200,
-    Created,
-    Accepted,
+    Created = 201,
+    Accepted = 202,
+    NonAuthoritativeInformation = 203,
     NoContent = 204,
+    ResetContent = 205,
+    PartialContent = 206,
+    MultiStatus = 207,
+    AlreadyReported = 208,
+    IMUsed = 226,
 
     // 300s
+    MultipleChoices = 300,
     MovedPermanently = 301,
-    Found,
-    SeeOther,
+    Found = 302,
+    SeeOther = 303,
+    NotModified = 304,
+    UseProxy = 305,
     TemporaryRedirect = 307,
-    PermanentRedirect,
+    PermanentRedirect = 308,
 
     // 400s
     BadRequest = 400,
-    Unauthorized,
+    Unauthorized = 401,
+    PaymentRequired = 402,
     Forbidden = 403,
-    NotFound,
-    MethodNotAllowed,
+    NotFound = 404,
+    MethodNotAllowed = 405,
+    NotAcceptable = 406,
+    ProxyAuthenticationRequired = 407,
     RequestTimeout = 408,
+    Conflict = 409,
     Gone = 410,
     LengthRequired = 411,
+    PreconditionFailed = 412,
     PayloadTooLarge = 413,
+    URITooLong = 414,
+    UnsupportedMediaType = 415,
+    RangeNotSatisfiable = 416,
+    ExpectationFailed = 417,
     ImATeapot = 418,
+    MisdirectedRequest = 421,
+    UnprocessableEntity = 422,
+    Locked = 423,
+    FailedDependency = 424,
+    TooEarly = 425,
+    UpgradeRequired = 426,
+    PreconditionRequired = 428,
+    TooManyRequests = 429,
+    RequestHeaderFieldsTooLarge = 431,
+    UnavailableForLegalReasons = 451,
 
     // 500s
     InternalServerError = 500,
     NotImplemented = 501,
+    BadGateway = 502,
+    ServiceUnavailable = 503,
+    GatewayTimeout = 504,
     HTTPVersionNotSupported = 505,
+    VariantAlsoNegotiates = 506,
+    InsufficientStorage = 507,
+    LoopDetected = 508,
+    NotExtended = 510,
+    NetworkAuthenticationRequired = 511,
 }
 
 impl StatusCode {
     pub fn to_string(&self) -> String {
+        self.reason().to_string()
+    }
+
+    /// the standard reason phrase
+    pub fn reason(&self) -> &'static str {
         use StatusCode::*;
 
         match self {
+            Continue => "Continue",
+            SwitchingProtocols => "Switching Protocols",
+            Processing => "Processing",
+            EarlyHints => "Early Hints",
+
             Ok => "OK",
             Created => "Created",
             Accepted => "Accepted",
+            NonAuthoritativeInformation => "Non-Authoritative Information",
             NoContent => "No Content",
+            ResetContent => "Reset Content",
+            PartialContent => "Partial Content",
+            MultiStatus => "Multi-Status",
+            AlreadyReported => "Already Reported",
+            IMUsed => "IM Used",
 
+            MultipleChoices => "Multiple Choices",
             MovedPermanently => "Moved Permanently",
             Found => "Found",
             SeeOther => "See Other",
+            NotModified => "Not Modified",
+            UseProxy => "Use Proxy",
             TemporaryRedirect => "Temporary Redirect",
             PermanentRedirect => "Permanent Redirect",
 
-            BadRequest => "BadRequest",
+            BadRequest => "Bad Request",
             Unauthorized => "Unauthorized",
+            PaymentRequired => "Payment Required",
             Forbidden => "Forbidden",
             NotFound => "Not Found",
             MethodNotAllowed => "Method Not Allowed",
+            NotAcceptable => "Not Acceptable",
+            ProxyAuthenticationRequired => "Proxy Authentication Required",
             RequestTimeout => "Request Timeout",
+            Conflict => "Conflict",
             Gone => "Gone",
             LengthRequired => "Length Required",
+            PreconditionFailed => "Precondition Failed",
             PayloadTooLarge => "Payload Too Large",
-            ImATeapot => "Im A Teapot",
+            URITooLong => "URI Too Long",
+            UnsupportedMediaType => "Unsupported Media Type",
+            RangeNotSatisfiable => "Range Not Satisfiable",
+            ExpectationFailed => "Expectation Failed",
+            ImATeapot => "I'm a teapot",
+            MisdirectedRequest => "Misdirected Request",
+            UnprocessableEntity => "Unprocessable Entity",
+            Locked => "Locked",
+            FailedDependency => "Failed Dependency",
+            TooEarly => "Too Early",
+            UpgradeRequired => "Upgrade Required",
+            PreconditionRequired => "Precondition Required",
+            TooManyRequests => "Too Many Requests",
+            RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            UnavailableForLegalReasons => "Unavailable For Legal Reasons",
 
             InternalServerError => "Internal Server Error",
             NotImplemented => "Not Implemented",
-            HTTPVersionNotSupported => "HTTP Version not Supported",
+            BadGateway => "Bad Gateway",
+            ServiceUnavailable => "Service Unavailable",
+            GatewayTimeout => "Gateway Timeout",
+            HTTPVersionNotSupported => "HTTP Version Not Supported",
+            VariantAlsoNegotiates => "Variant Also Negotiates",
+            InsufficientStorage => "Insufficient Storage",
+            LoopDetected => "Loop Detected",
+            NotExtended => "Not Extended",
+            NetworkAuthenticationRequired => "Network Authentication Required",
+        }
+    }
+
+    /// the generic status for a code's class, e.g. 400 for 499. Codes
+    /// outside of the standard classes are treated as server errors.
+    pub fn for_class(code: u16) -> Self {
+        use StatusCode::*;
+
+        match code / 100 {
+            1 => Continue,
+            2 => Ok,
+            3 => MultipleChoices,
+            4 => BadRequest,
+            _ => InternalServerError,
         }
-        .to_string()
     }
 }
 
@@ -283,6 +685,23 @@ impl fmt::Display for StatusCode {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_trickle_chunks() {
+        let mut trickle = Trickle::new(10, 4, Duration::from_secs(60), 1);
+        trickle.prefix = b"PK\x03\x04\x14".to_vec();
+        let mut rng = StdRng::seed_from_u64(trickle.seed);
+
+        let mut body = vec![];
+        while trickle.sent < trickle.len {
+            let chunk = trickle.chunk(&mut rng);
+            assert!(chunk.len() <= 4);
+            trickle.sent += chunk.len() as u64;
+            body.extend(chunk);
+        }
+        assert_eq!(10, body.len());
+        assert!(body.starts_with(b"PK\x03\x04\x14"));
+    }
+
     #[tokio::test]
     async fn basic_utf8_body() {
         let size = 1024 * 1024;
@@ -323,4 +742,91 @@ mod test {
 
         assert_eq!(len, size);
     }
+
+    #[test]
+    fn test_content_length() {
+        // a body set twice is sent once with the last length
+        let resp = BaseResponseBuilder::ok(())
+            .body("first")
+            .body("2nd")
+            .build()
+            .unwrap();
+        assert_eq!(
+            Some(&vec!["3".to_string()]),
+            resp.headers.get("Content-Length")
+        );
+
+        assert!(BaseResponseBuilder::ok(())
+            .body("abc")
+            .add_header("Content-Length", 5)
+            .build()
+            .is_err());
+        assert!(BaseResponseBuilder::ok(())
+            .add_header("Content-Length", 5)
+            .body("abc")
+            .set_header("Content-Length", 5)
+            .build()
+            .is_err());
+
+        // HEAD answers and lying responses declare theirs
+        let resp = BaseResponseBuilder::ok(())
+            .content_length(2985)
+            .body("")
+            .build()
+            .unwrap();
+        assert_eq!(Some("2985"), resp.headers.get_first("Content-Length"));
+    }
+
+    #[test]
+    fn test_status_line() {
+        let to_status_line = |resp: BaseResponse<()>| {
+            resp.into_string()
+                .unwrap()
+                .lines()
+                .next()
+                .unwrap()
+                .to_string()
+        };
+
+        let resp = BaseResponseBuilder::default(())
+            .status_code(StatusCode::RequestHeaderFieldsTooLarge)
+            .body("")
+            .build()
+            .unwrap();
+        assert_eq!(
+            "HTTP/1.1 431 Request Header Fields Too Large",
+            to_status_line(resp)
+        );
+
+        let resp = BaseResponseBuilder::default(())
+            .status_code(200)
+            .reason("Document Follows")
+            .body("")
+            .build()
+            .unwrap();
+        assert_eq!("HTTP/1.1 200 Document Follows", to_status_line(resp));
+
+        let resp = BaseResponseBuilder::default(())
+            .status_code(999)
+            .reason("Request denied")
+            .body("")
+            .build()
+            .unwrap();
+        assert_eq!(StatusCode::InternalServerError, resp.status_code());
+        assert_eq!("HTTP/1.1 999 Request denied", to_status_line(resp));
+
+        let resp = BaseResponseBuilder::default(())
+            .status_code(499)
+            .body("")
+            .build()
+            .unwrap();
+        assert_eq!(StatusCode::BadRequest, resp.status_code());
+        assert_eq!("HTTP/1.1 499 ", to_status_line(resp));
+
+        assert!(BaseResponseBuilder::default(())
+            .status_code(1000)
+            .body("")
+            .build()
+            .is_err());
+    }
 }
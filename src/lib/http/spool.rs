@@ -0,0 +1,256 @@
+//! Request bodies spooled to disk. Bodies over a threshold are streamed to
+//! a temp file as they're read rather than held in memory, so mass uploads
+//! of large archives cost a connection disk rather than RAM. The request
+//! keeps the first `threshold_bytes` as its body for anything inspecting
+//! it, and the whole body as a file with its hash.
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::{fs::File, io::AsyncWriteExt};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpoolConfig {
+    /// directory bodies are spooled to while their requests are handled
+    pub dir: PathBuf,
+    /// bodies larger than this are spooled
+    pub threshold_bytes: usize,
+    /// bodies larger than this are refused
+    pub max_bytes: usize,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("spool"),
+            threshold_bytes: 64 * 1024,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// A body spooled to a file, which is removed once the last request
+/// holding it is dropped.
+#[derive(Debug)]
+pub struct SpooledBody {
+    path: PathBuf,
+    len: usize,
+    sha256: String,
+}
+
+impl SpooledBody {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// the hex SHA-256 of the whole body.
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    /// opens the whole body for reading.
+    pub async fn reader(&self) -> Result<File> {
+        File::open(&self.path)
+            .await
+            .map_err(|e| anyhow!("failed to open spooled body {}: {}", self.path.display(), e))
+    }
+}
+
+impl Drop for SpooledBody {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(
+                "failed to remove spooled body {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Where a body is read into: memory, then a file once it outgrows the
+/// threshold, if spooling is enabled.
+pub(crate) struct Spooler<'a> {
+    config: Option<&'a SpoolConfig>,
+    max: usize,
+    memory: Vec<u8>,
+    file: Option<(File, PathBuf)>,
+    hasher: Sha256,
+    len: usize,
+}
+
+impl<'a> Spooler<'a> {
+    /// spools with the config, or holds up to max bytes in memory without
+    /// one.
+    pub fn new(config: Option<&'a SpoolConfig>, max: usize) -> Self {
+        Self {
+            config,
+            max: config.map_or(max, |c| c.max_bytes),
+            memory: vec![],
+            file: None,
+            hasher: Sha256::new(),
+            len: 0,
+        }
+    }
+
+    /// the largest body accepted.
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    /// whether a body of the length would be spooled.
+    pub fn spools(&self, len: usize) -> bool {
+        self.config.is_some_and(|c| len > c.threshold_bytes)
+    }
+
+    pub async fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.len += bytes.len();
+        ensure!(
+            self.len <= self.max,
+            "body len in excess of allowed size {}",
+            self.max
+        );
+        self.hasher.update(bytes);
+
+        let config = match self.config {
+            Some(config) if self.file.is_some() || self.len > config.threshold_bytes => config,
+            _ => {
+                self.memory.extend_from_slice(bytes);
+                return Ok(());
+            }
+        };
+        if self.file.is_none() {
+            tokio::fs::create_dir_all(&config.dir).await.map_err(|e| {
+                anyhow!(
+                    "failed to create spool directory {}: {}",
+                    config.dir.display(),
+                    e
+                )
+            })?;
+            let path = config.dir.join(format!("{:016x}", rand::random::<u64>()));
+            let mut file = File::create(&path)
+                .await
+                .map_err(|e| anyhow!("failed to spool body to {}: {}", path.display(), e))?;
+            file.write_all(&self.memory).await?;
+            self.file = Some((file, path));
+        }
+        if let Some((file, _)) = self.file.as_mut() {
+            file.write_all(bytes).await?;
+        }
+        // the body inspected in memory stops at the threshold
+        let room = config.threshold_bytes.saturating_sub(self.memory.len());
+        self.memory
+            .extend_from_slice(&bytes[..room.min(bytes.len())]);
+        Ok(())
+    }
+
+    /// the body in memory, and the spooled body if there is one.
+    pub async fn finish(&mut self) -> Result<(Vec<u8>, Option<Arc<SpooledBody>>)> {
+        let spooled = match self.file.take() {
+            Some((mut file, path)) => {
+                file.flush().await?;
+                Some(Arc::new(SpooledBody {
+                    path,
+                    len: self.len,
+                    sha256: format!("{:x}", self.hasher.finalize_reset()),
+                }))
+            }
+            None => None,
+        };
+        Ok((std::mem::take(&mut self.memory), spooled))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::{parse_request_with, ParserConfig};
+    use tokio::io::{AsyncReadExt, BufReader};
+
+    async fn parse(raw: &str, spool: &SpoolConfig) -> Result<crate::http::request::Request> {
+        let config = ParserConfig {
+            spool: Some(spool.clone()),
+            ..Default::default()
+        };
+        let mut reader = BufReader::new(raw.as_bytes());
+        parse_request_with(&"1.1.1.1:62012".parse().unwrap(), &mut reader, &config).await
+    }
+
+    #[tokio::test]
+    async fn test_spool() {
+        let dir = std::env::temp_dir().join(format!("httpot-spool-{}", std::process::id()));
+        let spool = SpoolConfig {
+            dir: dir.clone(),
+            threshold_bytes: 4,
+            max_bytes: 16,
+        };
+
+        let req = parse(
+            "PUT /shell.war HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\n0123456789",
+            &spool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(b"0123", req.body.as_slice());
+        assert_eq!(10, req.size);
+        let spooled = req.spooled.clone().unwrap();
+        assert_eq!(
+            "84d89877f0d4041efb6bf91a16f0248f2fd573e6af05c19f96bedb9f882f7882",
+            spooled.sha256()
+        );
+        let mut body = String::new();
+        spooled
+            .reader()
+            .await
+            .unwrap()
+            .read_to_string(&mut body)
+            .await
+            .unwrap();
+        assert_eq!("0123456789", body);
+
+        let path = spooled.path().to_path_buf();
+        drop((req, spooled));
+        assert!(!path.exists(), "removed with the request");
+
+        let req = parse(
+            "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n",
+            &spool,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (b"abcd".as_slice(), 6),
+            (req.body.as_slice(), req.spooled.as_ref().unwrap().len())
+        );
+
+        let req = parse(
+            "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\n\r\nabc",
+            &spool,
+        )
+        .await
+        .unwrap();
+        assert!(req.spooled.is_none(), "under the threshold");
+        assert!(parse(
+            "PUT / HTTP/1.1\r\nHost: a\r\nContent-Length: 17\r\n\r\n01234567890123456",
+            &spool
+        )
+        .await
+        .is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,605 @@
+//! Central management of a fleet of sensors. Sensors register with a hub,
+//! another httpot, stream their events up to it, and run whatever config
+//! the hub holds for them, so a fleet is managed in one place.
+//!
+//! This isn't the mTLS the fleet should eventually use: there's no TLS
+//! stack in httpot yet, so messages aren't encrypted. Configs may carry
+//! secrets, so hubs should be reached over a VPN or a TLS tunnel.
+//!
+//! Each sensor has its own key, which the hub lists by the sensor's name.
+//! A sensor and the hub prove to each other that they hold the sensor's
+//! key with an HMAC challenge, and every message after is MACed with a key
+//! derived for the session, so neither side accepts forged, replayed, or
+//! reordered messages. A sensor's key only lets its holder register as
+//! that sensor, or pose as the hub to that sensor alone. Sensors still
+//! refuse pushed configs naming local files or running code, such as
+//! overlays, scripts, and plugins, which must be configured locally.
+//!
+//! Messages are JSON lines of `{"mac": ..., "msg": ...}`.
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{SecondsFormat, Utc};
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+
+use crate::{
+    config::Config,
+    eventlog::Event,
+    fetch::sha256_hex,
+    prelude::*,
+    sink::EventSink,
+    util::{hmac_sha256, mac_eq},
+};
+
+// events waiting beyond this while the hub is unreachable are dropped
+const QUEUE_SIZE: usize = 1024;
+// bounds memory used reading a message; configs are the largest
+const MAX_MESSAGE: u64 = 1024 * 1024;
+// how long either side waits on a handshake
+const HANDSHAKE_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HubConfig {
+    /// each sensor's own secret, by the name it registers with. Sensors
+    /// which aren't listed are refused.
+    pub sensors: BTreeMap<String, String>,
+    /// directory of configs served to sensors: `<sensor>.toml`, falling
+    /// back to `default.toml`
+    pub configs: PathBuf,
+    /// JSON lines file events from sensors are appended to
+    pub events: PathBuf,
+    /// how often configs are checked for changes
+    pub poll_secs: u64,
+}
+
+impl Default for HubConfig {
+    fn default() -> Self {
+        Self {
+            sensors: BTreeMap::new(),
+            configs: PathBuf::from("configs"),
+            events: PathBuf::from("hub-events.jsonl"),
+            poll_secs: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensorConfig {
+    /// the hub, as host:port
+    pub hub: String,
+    /// this sensor's own secret, as the hub lists it
+    pub key: String,
+    /// name the hub knows this sensor by, by default its listen address
+    pub name: Option<String>,
+    /// how long to wait before reconnecting to the hub
+    pub retry_secs: u64,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self {
+            hub: String::new(),
+            key: String::new(),
+            name: None,
+            retry_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    Hello { sensor: String, nonce: String },
+    Challenge { nonce: String, proof: String },
+    Proof { proof: String },
+    Event { event: Event },
+    Config { toml: String },
+}
+
+/// A message as sent, so its MAC is checked against the exact bytes
+/// received rather than a re-serialization.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    mac: String,
+    msg: Box<RawValue>,
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn nonce() -> String {
+    hex(&thread_rng().gen::<[u8; 16]>())
+}
+
+/// The proofs and session key of a handshake, which both sides derive.
+struct Handshake {
+    hub_proof: String,
+    sensor_proof: String,
+    session: Vec<u8>,
+}
+
+impl Handshake {
+    fn new(key: &str, sensor: &str, sensor_nonce: &str, hub_nonce: &str) -> Self {
+        let derive = |label: &str| {
+            hmac_sha256(
+                key.as_bytes(),
+                format!("{}\n{}\n{}\n{}", label, sensor, sensor_nonce, hub_nonce).as_bytes(),
+            )
+        };
+        Self {
+            hub_proof: hex(&derive("hub")),
+            sensor_proof: hex(&derive("sensor")),
+            session: derive("session").to_vec(),
+        }
+    }
+}
+
+/// One side of a session, which MACs messages with a sequence number per
+/// direction.
+struct Channel<C> {
+    conn: C,
+    // the session key, once the handshake has derived one
+    session: Option<Vec<u8>>,
+    // which side this is, so messages can't be reflected back
+    hub: bool,
+    sent: u64,
+    received: u64,
+}
+
+impl<C: AsyncBufRead + AsyncWrite + Unpin> Channel<C> {
+    fn new(conn: C, hub: bool) -> Self {
+        Self {
+            conn,
+            session: None,
+            hub,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    fn mac(&self, from_hub: bool, seq: u64, msg: &str) -> String {
+        match &self.session {
+            Some(session) => {
+                let from = if from_hub { "hub" } else { "sensor" };
+                hex(&hmac_sha256(
+                    session,
+                    format!("{}\n{}\n{}", from, seq, msg).as_bytes(),
+                ))
+            }
+            // handshake messages carry their own proofs
+            None => String::new(),
+        }
+    }
+
+    async fn send(&mut self, msg: Message) -> Result<()> {
+        let json = serde_json::to_string(&msg)?;
+        let envelope = Envelope {
+            mac: self.mac(self.hub, self.sent, &json),
+            msg: RawValue::from_string(json)?,
+        };
+        self.sent += 1;
+        let mut line = serde_json::to_vec(&envelope)?;
+        line.push(b'\n');
+        self.conn.write_all(&line).await?;
+        self.conn.flush().await?;
+        Ok(())
+    }
+
+    /// the next message, or None if the connection was closed.
+    async fn recv(&mut self) -> Result<Option<Message>> {
+        let mut line = String::new();
+        (&mut self.conn)
+            .take(MAX_MESSAGE)
+            .read_line(&mut line)
+            .await?;
+        if line.is_empty() {
+            return Ok(None);
+        }
+        ensure!(line.ends_with('\n'), "message is too large or truncated");
+
+        let envelope: Envelope = serde_json::from_str(&line)?;
+        let expected = self.mac(!self.hub, self.received, envelope.msg.get());
+        ensure!(mac_eq(&expected, &envelope.mac), "message failed its MAC");
+        self.received += 1;
+        Ok(Some(serde_json::from_str(envelope.msg.get())?))
+    }
+}
+
+/// Streams events up to the hub from a sensor, and passes configs the hub
+/// sends down.
+#[derive(Debug, Clone)]
+pub struct Uplink {
+    tx: mpsc::Sender<Event>,
+}
+
+impl Uplink {
+    /// starts connecting to the hub on the current runtime. Configs the
+    /// hub sends are passed to `configs` as TOML.
+    pub fn spawn(config: SensorConfig, name: String, configs: mpsc::Sender<String>) -> Self {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        tokio::spawn(uplink(config, name, rx, configs));
+        Self { tx }
+    }
+
+    /// queues the event to be sent, dropping it if the queue is full.
+    pub fn send(&self, event: Event) {
+        if self.tx.try_send(event).is_err() {
+            debug!("dropped an event for the hub, which is behind");
+        }
+    }
+}
+
+impl EventSink for Uplink {
+    fn on_response(&self, event: &Event) {
+        self.send(event.clone());
+    }
+}
+
+async fn uplink(
+    config: SensorConfig,
+    name: String,
+    mut events: mpsc::Receiver<Event>,
+    configs: mpsc::Sender<String>,
+) {
+    loop {
+        match sensor_session(&config, &name, &mut events, &configs).await {
+            Ok(()) => warn!("hub {} closed the session", config.hub),
+            Err(e) => warn!("session with hub {} failed: {}", config.hub, e),
+        }
+        time::sleep(Duration::from_secs(config.retry_secs.max(1))).await;
+    }
+}
+
+async fn sensor_session(
+    config: &SensorConfig,
+    name: &str,
+    events: &mut mpsc::Receiver<Event>,
+    configs: &mpsc::Sender<String>,
+) -> Result<()> {
+    let conn = TcpStream::connect(&config.hub).await?;
+    let mut channel = Channel::new(BufReader::new(conn), false);
+    time::timeout(
+        Duration::from_secs(HANDSHAKE_SECS),
+        sensor_handshake(&mut channel, &config.key, name),
+    )
+    .await
+    .map_err(|_| anyhow!("handshake timed out"))??;
+    info!("registered with hub {} as {:?}", config.hub, name);
+
+    loop {
+        tokio::select!(
+            Some(event) = events.recv() => channel.send(Message::Event { event }).await?,
+            msg = channel.recv() => match msg? {
+                Some(Message::Config { toml }) => {
+                    info!("hub {} sent a config", config.hub);
+                    configs.send(toml).await?;
+                }
+                Some(msg) => bail!("unexpected message {:?}", msg),
+                None => return Ok(()),
+            },
+        );
+    }
+}
+
+async fn sensor_handshake<C>(channel: &mut Channel<C>, key: &str, name: &str) -> Result<()>
+where
+    C: AsyncBufRead + AsyncWrite + Unpin,
+{
+    let sensor_nonce = nonce();
+    channel
+        .send(Message::Hello {
+            sensor: name.to_string(),
+            nonce: sensor_nonce.clone(),
+        })
+        .await?;
+    let handshake = match channel.recv().await? {
+        Some(Message::Challenge { nonce, proof }) => {
+            let handshake = Handshake::new(key, name, &sensor_nonce, &nonce);
+            ensure!(
+                mac_eq(&handshake.hub_proof, &proof),
+                "hub doesn't hold this sensor's key"
+            );
+            handshake
+        }
+        msg => bail!("expected a challenge, got {:?}", msg),
+    };
+    channel
+        .send(Message::Proof {
+            proof: handshake.sensor_proof,
+        })
+        .await?;
+    channel.session = Some(handshake.session);
+    Ok(())
+}
+
+/// the sensor's name, once it's proven it holds that sensor's key.
+async fn hub_handshake<C>(
+    channel: &mut Channel<C>,
+    sensors: &BTreeMap<String, String>,
+) -> Result<String>
+where
+    C: AsyncBufRead + AsyncWrite + Unpin,
+{
+    let (sensor, sensor_nonce) = match channel.recv().await? {
+        Some(Message::Hello { sensor, nonce }) => (sensor, nonce),
+        msg => bail!("expected a hello, got {:?}", msg),
+    };
+    let key = match sensors.get(&sensor) {
+        Some(key) => key,
+        None => bail!("{:?} isn't one of the hub's sensors", sensor),
+    };
+    let hub_nonce = nonce();
+    let handshake = Handshake::new(key, &sensor, &sensor_nonce, &hub_nonce);
+    channel
+        .send(Message::Challenge {
+            nonce: hub_nonce,
+            proof: handshake.hub_proof,
+        })
+        .await?;
+    match channel.recv().await? {
+        Some(Message::Proof { proof }) if mac_eq(&handshake.sensor_proof, &proof) => (),
+        Some(Message::Proof { .. }) => bail!("{:?} doesn't hold its key", sensor),
+        msg => bail!("expected a proof, got {:?}", msg),
+    }
+    channel.session = Some(handshake.session);
+    Ok(sensor)
+}
+
+/// An event as the hub stores it.
+#[derive(Debug, Serialize)]
+struct Received<'a> {
+    sensor: &'a str,
+    at: String,
+    event: &'a Event,
+}
+
+/// serves sensors forever, storing their events and sending them their
+/// configs whenever they change.
+pub async fn serve(config: HubConfig, addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("hub listening on {}", addr);
+    let events = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.events)
+        .map_err(|e| anyhow!("failed to open {}: {}", config.events.display(), e))?;
+    let events = Arc::new(Mutex::new(events));
+    let config = Arc::new(config);
+
+    loop {
+        let (conn, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("failed to accept sensor conn: {}", e);
+                continue;
+            }
+        };
+        let (config, events) = (config.clone(), events.clone());
+        tokio::spawn(async move {
+            if let Err(e) = hub_session(&config, conn, &events).await {
+                warn!("session with sensor at {} ended: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn hub_session(config: &HubConfig, conn: TcpStream, events: &Mutex<fs::File>) -> Result<()> {
+    let peer = conn.peer_addr()?;
+    let mut channel = Channel::new(BufReader::new(conn), true);
+    let sensor = time::timeout(
+        Duration::from_secs(HANDSHAKE_SECS),
+        hub_handshake(&mut channel, &config.sensors),
+    )
+    .await
+    .map_err(|_| anyhow!("handshake timed out"))??;
+    info!("sensor {:?} registered from {}", sensor, peer);
+
+    let mut sent = None;
+    let mut poll = time::interval(Duration::from_secs(config.poll_secs.max(1)));
+    loop {
+        tokio::select!(
+            _ = poll.tick() => {
+                let toml = match sensor_config(config, &sensor) {
+                    Ok(Some(toml)) => toml,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!("not sending sensor {:?} its config: {}", sensor, e);
+                        continue;
+                    }
+                };
+                let hash = sha256_hex(toml.as_bytes());
+                if sent.as_ref() != Some(&hash) {
+                    channel.send(Message::Config { toml }).await?;
+                    info!("sent sensor {:?} config sha256={}", sensor, hash);
+                    sent = Some(hash);
+                }
+            },
+            msg = channel.recv() => match msg? {
+                Some(Message::Event { event }) => {
+                    let received = Received {
+                        sensor: &sensor,
+                        at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                        event: &event,
+                    };
+                    let line = serde_json::to_string(&received)?;
+                    writeln!(events.lock().unwrap(), "{}", line)?;
+                }
+                Some(msg) => bail!("unexpected message {:?}", msg),
+                None => {
+                    info!("sensor {:?} disconnected", sensor);
+                    return Ok(());
+                }
+            },
+        );
+    }
+}
+
+/// the sensor's config, if the hub has one for it. Configs which don't
+/// parse aren't sent.
+fn sensor_config(config: &HubConfig, sensor: &str) -> Result<Option<String>> {
+    // names come from sensors, so they can't pick files outside the dir
+    let safe = !sensor.is_empty()
+        && sensor
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+        && !sensor.starts_with('.');
+    let candidates = safe
+        .then(|| config.configs.join(format!("{}.toml", sensor)))
+        .into_iter()
+        .chain([config.configs.join("default.toml")]);
+
+    for path in candidates {
+        match fs::read_to_string(&path) {
+            Ok(toml) => {
+                Config::parse(&toml).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+                return Ok(Some(toml));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(anyhow!("failed to read {}: {}", path.display(), e)),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sensors(keys: &[(&str, &str)]) -> BTreeMap<String, String> {
+        keys.iter()
+            .map(|(name, key)| (name.to_string(), key.to_string()))
+            .collect()
+    }
+
+    /// whether the sensor registers with a hub holding the keys, and
+    /// whether the hub accepts it.
+    async fn register(keys: &[(&str, &str)], key: &str, name: &str) -> (bool, bool) {
+        let (sensor, hub) = tokio::io::duplex(64 * 1024);
+        let mut sensor = Channel::new(BufReader::new(sensor), false);
+        let mut hub = Channel::new(BufReader::new(hub), true);
+        let (registered, handshake) = tokio::join!(
+            async {
+                // a hub which refuses the sensor hangs up
+                let registered = hub_handshake(&mut hub, &sensors(keys)).await;
+                drop(hub);
+                registered
+            },
+            async {
+                // as does a sensor which rejects the hub
+                let handshake = sensor_handshake(&mut sensor, key, name).await;
+                drop(sensor);
+                handshake
+            }
+        );
+        (handshake.is_ok(), registered.is_ok())
+    }
+
+    #[tokio::test]
+    async fn test_session() {
+        let (sensor, hub) = tokio::io::duplex(64 * 1024);
+        let mut sensor = Channel::new(BufReader::new(sensor), false);
+        let mut hub = Channel::new(BufReader::new(hub), true);
+
+        let keys = sensors(&[("edge-1", "edge-1's key"), ("edge-2", "edge-2's key")]);
+        let (registered, handshake) = tokio::join!(
+            hub_handshake(&mut hub, &keys),
+            sensor_handshake(&mut sensor, "edge-1's key", "edge-1"),
+        );
+        assert_eq!("edge-1", registered.unwrap());
+        handshake.unwrap();
+
+        let toml = "persona = \"nginx\"\n".to_string();
+        hub.send(Message::Config { toml: toml.clone() })
+            .await
+            .unwrap();
+        assert_eq!(Some(Message::Config { toml }), sensor.recv().await.unwrap());
+
+        // the MAC covers the bytes sent, not what they parse to
+        let json = serde_json::to_string(&Message::Proof {
+            proof: String::new(),
+        })
+        .unwrap();
+        let mac = hub.mac(true, hub.sent, &json);
+        let line = format!(
+            "{{\"mac\":\"{}\",\"msg\":{}}}\n",
+            mac,
+            json.replace(':', ": ")
+        );
+        hub.conn.write_all(line.as_bytes()).await.unwrap();
+        assert!(sensor.recv().await.is_err());
+
+        // a message MACed for the other direction is a reflection
+        hub.send(Message::Proof {
+            proof: String::new(),
+        })
+        .await
+        .unwrap();
+        sensor.hub = true;
+        assert!(sensor.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sensor_keys() {
+        let keys = [("edge-1", "edge-1's key"), ("edge-2", "edge-2's key")];
+        assert_eq!(
+            (true, true),
+            register(&keys, "edge-2's key", "edge-2").await
+        );
+        // one sensor's key can't register as another
+        assert_eq!(
+            (false, false),
+            register(&keys, "edge-2's key", "edge-1").await
+        );
+        assert_eq!(
+            (false, false),
+            register(&keys, "edge-1's key", "edge-3").await
+        );
+        // nor pose as the hub to another, which checks the hub's proof
+        let posing = [("edge-1", "edge-2's key")];
+        assert_eq!(
+            (false, false),
+            register(&posing, "edge-1's key", "edge-1").await
+        );
+    }
+
+    #[test]
+    fn test_sensor_config() {
+        let dir = std::env::temp_dir().join(format!("httpot-hub-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = HubConfig {
+            configs: dir.clone(),
+            ..Default::default()
+        };
+        assert_eq!(None, sensor_config(&config, "edge-1").unwrap());
+
+        fs::write(dir.join("default.toml"), "persona = \"iis\"\n").unwrap();
+        fs::write(dir.join("edge-1.toml"), "persona = \"nginx\"\n").unwrap();
+        let persona = |sensor| sensor_config(&config, sensor).unwrap().unwrap();
+        assert_eq!("persona = \"nginx\"\n", persona("edge-1"));
+        assert_eq!("persona = \"iis\"\n", persona("edge-2"));
+        assert_eq!("persona = \"iis\"\n", persona("../edge-1"));
+
+        fs::write(dir.join("edge-1.toml"), "persona = 1\n").unwrap();
+        assert!(sensor_config(&config, "edge-1").is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
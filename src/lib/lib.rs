@@ -4,12 +4,71 @@ extern crate derive_builder;
 #[macro_use]
 extern crate num_derive;
 
+#[cfg(feature = "server")]
+pub use server::{Honeypot, HoneypotBuilder};
+
 pub mod prelude {
     pub use anyhow::{anyhow, bail, ensure, Error, Result};
     pub use log::{debug, error, info, trace, warn};
 }
 
+#[cfg(feature = "server")]
+pub mod canonical;
+#[cfg(feature = "server")]
+pub mod capture;
+#[cfg(feature = "server")]
+pub mod classify;
+#[cfg(feature = "server")]
+pub mod cluster;
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod dedup;
+pub mod display;
+#[cfg(feature = "server")]
+pub mod enrich;
+#[cfg(feature = "server")]
+pub mod eventlog;
+#[cfg(feature = "server")]
+pub mod fetch;
+pub mod fingerprint;
+#[cfg(feature = "server")]
 pub mod fs;
+pub mod har;
+#[cfg(feature = "server")]
 pub mod honeypot;
 pub mod http;
+#[cfg(feature = "server")]
+pub mod hub;
+#[cfg(feature = "server")]
+pub mod locale;
+#[cfg(feature = "server")]
+pub mod metrics;
+#[cfg(feature = "server")]
+pub mod middleware;
+#[cfg(feature = "server")]
+pub mod persona;
+pub mod privacy;
+#[cfg(feature = "server")]
+pub mod probe;
+#[cfg(feature = "server")]
+pub mod profile;
+#[cfg(feature = "server")]
+pub mod replay;
+#[cfg(feature = "server")]
+pub mod report;
+#[cfg(feature = "server")]
+pub mod retention;
+#[cfg(feature = "server")]
+pub mod router;
+#[cfg(feature = "server")]
+pub mod seed;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod session;
+#[cfg(feature = "server")]
+pub mod sink;
+pub mod tls;
 pub mod util;
+#[cfg(feature = "server")]
+pub mod vhost;
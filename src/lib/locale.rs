@@ -0,0 +1,108 @@
+//! Languages fake content is shown in, negotiated from Accept-Language.
+//! Servers configured with translated error documents answer people in
+//! their own language, so human operators reviewing scan results see
+//! what they'd expect from a real deployment. Translations of each
+//! persona's pages live with the persona.
+use crate::http::request::Request;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    En,
+    De,
+    Fr,
+    Es,
+}
+
+impl Language {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Language::En => "en",
+            Language::De => "de",
+            Language::Fr => "fr",
+            Language::Es => "es",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        // only the primary subtag matters, so de-AT is de
+        let primary = tag.split('-').next().unwrap_or_default();
+        [Language::En, Language::De, Language::Fr, Language::Es]
+            .into_iter()
+            .find(|l| primary.eq_ignore_ascii_case(l.tag()))
+    }
+
+    /// the supported language the request prefers most, by quality, or
+    /// English.
+    pub fn negotiate(req: &Request) -> Self {
+        let header = req
+            .headers
+            .get("Accept-Language")
+            .into_iter()
+            .flatten()
+            .flat_map(|v| v.split(','))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        let mut best: Option<(Language, f32)> = None;
+        for range in &header {
+            let mut parts = range.split(';').map(str::trim);
+            let tag = parts.next().unwrap_or_default();
+            let quality = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match Language::from_tag(tag) {
+                Some(lang) if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) => {
+                    best = Some((lang, quality));
+                }
+                _ => (),
+            }
+        }
+
+        best.map(|(lang, _)| lang).unwrap_or_default()
+    }
+
+    /// the heading of a directory listing.
+    pub fn index_of(&self) -> &'static str {
+        match self {
+            Language::En => "Index of",
+            Language::De => "Index von",
+            Language::Fr => "Index de",
+            Language::Es => "Índice de",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(accept_language: &str) -> Request {
+        let mut req = Request::stub(Method::GET, "/", "");
+        req.headers.add("accept-language", accept_language);
+        req
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let cases = vec![
+            ("", Language::En),
+            ("de-DE,de;q=0.9,en;q=0.8", Language::De),
+            ("ja,fr;q=0.5,en;q=0.4", Language::Fr),
+            ("en;q=0.5, es-MX", Language::Es),
+            ("de;q=0, *", Language::En),
+            ("zh-CN", Language::En),
+        ];
+
+        for (header, expected) in cases {
+            assert_eq!(
+                expected,
+                Language::negotiate(&stub_request(header)),
+                "{}",
+                header
+            );
+        }
+    }
+}
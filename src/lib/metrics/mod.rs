@@ -0,0 +1,181 @@
+// heavy inspiration from:
+// https://romankudryashov.com/blog/2021/11/monitoring-rust-web-application/
+
+mod request;
+mod response;
+mod storage;
+
+pub use request::*;
+pub use response::*;
+pub use storage::*;
+
+use std::{sync::Arc, time::Duration};
+
+use prometheus::TextEncoder;
+use tokio::{
+    io::{AsyncReadExt, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::Semaphore,
+    time::{sleep, timeout},
+};
+
+use crate::{
+    classify::Tag,
+    config::MetricsConfig,
+    http::{
+        egress,
+        request::{parse_request, Method, Request},
+        response::{ResponseBuilder, StatusCode},
+        stock_responses,
+    },
+    prelude::*,
+    sink::{EventSink, Payload},
+    util::mac_eq,
+};
+
+/// Counts what sinks are told about.
+pub struct Metrics;
+
+impl EventSink for Metrics {
+    fn on_request(&self, req: &Request) {
+        observe_host(req);
+    }
+
+    fn on_anomaly(&self, _req: &Request, tag: Tag) {
+        observe_tag(tag);
+    }
+
+    fn on_credential(&self, _req: &Request, _user: &str, _secret: &str) {
+        observe_credential();
+    }
+
+    fn on_payload(&self, _req: &Request, payload: &Payload) {
+        observe_payload(payload);
+    }
+}
+
+/// self-disables and sleeps indefintiely on None. Otherwise listens
+/// for incoming requests and returns prometheus metrics to those the
+/// config allows.
+pub async fn run(addr: Option<std::net::SocketAddr>, config: MetricsConfig) -> Result<()> {
+    if addr.is_none() {
+        sleep(Duration::MAX).await;
+    }
+
+    let addr = addr.unwrap();
+    let l = TcpListener::bind(&addr).await?;
+    info!("metrics listening on: {}", addr);
+    let config = Arc::new(config);
+    let permits = Arc::new(Semaphore::new(config.max_connections));
+
+    loop {
+        let (socket, peer) = match l.accept().await {
+            Err(e) => {
+                warn!("error when accepting metrics conn: {}", e);
+                continue;
+            }
+            Ok(accepted) => accepted,
+        };
+        if !config.allow.is_empty() && !config.allow.iter().any(|c| c.contains(peer.ip())) {
+            warn!("metrics conn from {} isn't allowed, dropped", peer);
+            continue;
+        }
+        let permit = match permits.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) => {
+                warn!(
+                    "metrics conn from {} is over the connection limit, dropped",
+                    peer
+                );
+                continue;
+            }
+        };
+
+        let config = config.clone();
+        tokio::spawn(async move {
+            let limit = Duration::from_secs(config.timeout_secs);
+            match timeout(limit, process_req(socket, &config)).await {
+                Ok(Err(e)) => warn!("failed to process metrics req: {}", e),
+                Err(_) => warn!("metrics req from {} timed out after {:?}", peer, limit),
+                Ok(Ok(())) => (),
+            }
+            drop(permit);
+        });
+    }
+}
+
+async fn process_req(mut s: TcpStream, config: &MetricsConfig) -> Result<()> {
+    let addr = s.peer_addr()?;
+    debug!("metrics conn from {}", addr);
+
+    s.readable().await?;
+
+    let mut reader = BufReader::new(&mut s).take(config.max_request_bytes);
+    let req = parse_request(&addr, &mut reader).await?;
+    // the parser takes the end of the limit as the end of the request
+    ensure!(
+        reader.limit() > 0,
+        "metrics req from {} is over {} bytes",
+        addr,
+        config.max_request_bytes
+    );
+    if let Some(token) = &config.token {
+        let sent = req
+            .headers
+            .get("Authorization")
+            .into_iter()
+            .flatten()
+            .find_map(|v| v.strip_prefix("Bearer "));
+        if !sent.is_some_and(|sent| mac_eq(sent.trim(), token)) {
+            warn!("from {} => metrics req without a valid token", addr);
+            return stock_responses::generic_status(s, StatusCode::Unauthorized)
+                .add_header("WWW-Authenticate", "Bearer realm=\"metrics\"")
+                .build()?
+                .send()
+                .await;
+        }
+    }
+    if (req.url.path() != "/" && req.url.path() != "/metrics") || req.method != Method::GET {
+        warn!(
+            "from {} => only reqs to / and /metrics are supported, got {} {}",
+            addr,
+            req.method.to_string(),
+            req.url
+        );
+
+        return four_hundred(s).await;
+    }
+
+    s.writable().await?;
+
+    EGRESS_RATE.set(egress::rate());
+    let resp = TextEncoder::new()
+        .encode_to_string(&prometheus::gather())
+        .map_err(|e| anyhow!("failed to convert metrics to string: {}", e))?;
+
+    let mut resp = ResponseBuilder::ok(std::sync::Arc::new(s))
+        .add_header("Content-Type", "text/plain")
+        .body(resp)
+        .build()?;
+
+    match resp.send().await {
+        Ok(_) => info!("{}: wrote {} metrics bytes", addr, resp.len()),
+        Err(e) => {
+            bail!(
+                "{}: failed to write {} metrics bytes: {}",
+                addr,
+                resp.len(),
+                e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn four_hundred(w: TcpStream) -> Result<()> {
+    stock_responses::generic_status(w, StatusCode::BadRequest)
+        .build()?
+        .send()
+        .await
+}
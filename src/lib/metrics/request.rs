@@ -0,0 +1,199 @@
+use lazy_static::lazy_static;
+use std::{collections::HashSet, sync::Mutex, time::Instant};
+
+use prometheus::{
+    self as prom, register_counter_vec, register_histogram, register_histogram_vec,
+    register_int_counter, register_int_counter_vec, register_int_gauge,
+};
+use std::future::Future;
+
+use crate::{
+    classify::Tag,
+    fingerprint::Fingerprint,
+    http::request::Request,
+    prelude::*,
+    profile::{Activity, Profile},
+    session::{BannerGrab, Ending},
+    sink::Payload,
+    tls::client_hello::ClientHello,
+};
+
+// bounds memory used tracking distinct hosts; the gauge stops growing here
+const MAX_TRACKED_HOSTS: usize = 100_000;
+
+lazy_static! {
+    pub static ref HTTP_REQUEST: prom::HistogramVec = register_histogram_vec!(
+        "httpot_http_request",
+        "Incoming HTTP request read and parse time",
+        &["method", "remote_addr", "user_agent", "version"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_PARSE_FAILURES: prom::Histogram = register_histogram!(
+        "httpot_http_request_parse_failures",
+        "Incoming HTTP request parse failures time",
+    )
+    .unwrap();
+    pub static ref CREDENTIALS_SUBMITTED: prom::IntCounter = register_int_counter!(
+        "httpot_credentials_submitted",
+        "User and secret pairs submitted in requests",
+    )
+    .unwrap();
+    pub static ref PAYLOADS: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_payloads",
+        "Uploads, injected commands, and XML entities found in requests",
+        &["kind"]
+    )
+    .unwrap();
+    pub static ref HTTP_PIPELINED_REQUESTS: prom::Histogram = register_histogram!(
+        "httpot_http_pipelined_requests",
+        "Requests pipelined behind the first by clients which pipeline",
+        vec![1.0, 2.0, 4.0, 8.0, 16.0, 32.0]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_BODY: prom::CounterVec = register_counter_vec!(
+        "httpot_http_request_body_size",
+        "Incoming HTTP request cumulative body size",
+        &["method", "remote_addr", "user_agent", "version"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_PATH_LENGTH: prom::CounterVec = register_counter_vec!(
+        "httpot_http_request_path_length",
+        "Incoming HTTP request cumulative request path length",
+        &["method", "remote_addr", "user_agent", "version"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_TAGS: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_http_request_tags",
+        "Incoming HTTP requests by classification tag",
+        &["tag"]
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_DISTINCT_HOSTS: prom::IntGauge = register_int_gauge!(
+        "httpot_http_request_distinct_hosts",
+        "Distinct Host headers seen in incoming HTTP requests",
+    )
+    .unwrap();
+    pub static ref HTTP_REQUEST_FINGERPRINTS: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_http_request_fingerprints",
+        "Incoming HTTP requests by client fingerprint bucket",
+        &["fingerprint"]
+    )
+    .unwrap();
+    pub static ref ENGAGEMENT_SCORE: prom::HistogramVec = register_histogram_vec!(
+        "httpot_engagement_score",
+        "Engagement scores of sessions, and of sources as of each of their sessions",
+        &["scope"],
+        vec![0.0, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0]
+    )
+    .unwrap();
+    pub static ref TLS_CLIENT_HELLOS: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_tls_client_hellos",
+        "TLS ClientHellos received by the protocol, TLS version, and SNI flag their JA4 starts with",
+        &["ja4_prefix"]
+    )
+    .unwrap();
+    pub static ref SESSIONS_ENDED: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_sessions_ended",
+        "Sessions by how they ended",
+        &["ending"]
+    )
+    .unwrap();
+    pub static ref BANNER_GRABS: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_banner_grabs",
+        "Connections which never sent a request, by what they did instead",
+        &["kind"]
+    )
+    .unwrap();
+    static ref SEEN_HOSTS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+pub async fn observe_request<R: Future<Output = Result<Request>>>(req: R) -> Result<Request> {
+    let start = Instant::now();
+    let req = req.await;
+    let elapsed = start.elapsed().as_secs_f64();
+
+    if req.is_err() {
+        HTTP_REQUEST_PARSE_FAILURES.observe(elapsed);
+        return req;
+    }
+
+    let req = req?;
+    let ip = req.requester().to_string();
+    let meth = req.method.to_string();
+
+    let common_labels: Vec<&str> = vec![
+        &meth,
+        &ip,
+        req.headers.get_first("User-Agent").unwrap_or("unknown"),
+        &req.version,
+    ];
+
+    HTTP_REQUEST
+        .with_label_values(common_labels.as_slice())
+        .observe(elapsed);
+
+    HTTP_REQUEST_BODY
+        .with_label_values(common_labels.as_slice())
+        .inc_by(req.size as f64);
+
+    HTTP_REQUEST_PATH_LENGTH
+        .with_label_values(common_labels.as_slice())
+        .inc_by(req.url.path().len() as f64);
+
+    Ok(req)
+}
+
+pub fn observe_tag(tag: Tag) {
+    HTTP_REQUEST_TAGS.with_label_values(&[tag.as_str()]).inc();
+}
+
+pub fn observe_host(req: &Request) {
+    if let Some(host) = req.host_header() {
+        let mut seen = SEEN_HOSTS.lock().unwrap();
+        if seen.len() < MAX_TRACKED_HOSTS && seen.insert(host.to_lowercase()) {
+            HTTP_REQUEST_DISTINCT_HOSTS.set(seen.len() as i64);
+        }
+    }
+}
+
+pub fn observe_fingerprint(fingerprint: &Fingerprint) {
+    HTTP_REQUEST_FINGERPRINTS
+        .with_label_values(&[fingerprint.bucket()])
+        .inc();
+}
+
+pub fn observe_credential() {
+    CREDENTIALS_SUBMITTED.inc();
+}
+
+pub fn observe_payload(payload: &Payload) {
+    PAYLOADS.with_label_values(&[payload.as_str()]).inc();
+}
+
+pub fn observe_pipelined(requests: usize) {
+    HTTP_PIPELINED_REQUESTS.observe(requests as f64);
+}
+
+pub fn observe_client_hello(hello: &ClientHello) {
+    // the rest of JA4's first section counts ciphers, extensions, and the
+    // first ALPN, which a forged hello picks freely, so isn't a label
+    let ja4 = hello.ja4();
+    TLS_CLIENT_HELLOS.with_label_values(&[&ja4[..4]]).inc();
+}
+
+pub fn observe_engagement(activity: &Activity, profile: &Profile) {
+    ENGAGEMENT_SCORE
+        .with_label_values(&["session"])
+        .observe(activity.score() as f64);
+    ENGAGEMENT_SCORE
+        .with_label_values(&["source"])
+        .observe(profile.score() as f64);
+}
+
+pub fn observe_session(ending: Ending) {
+    SESSIONS_ENDED.with_label_values(&[ending.as_str()]).inc();
+}
+
+pub fn observe_banner_grab(grab: BannerGrab) {
+    BANNER_GRABS.with_label_values(&[grab.as_str()]).inc();
+}
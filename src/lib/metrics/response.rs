@@ -0,0 +1,145 @@
+use lazy_static::lazy_static;
+use std::time::{Duration, Instant};
+
+use prometheus::{self as prom, register_counter_vec, register_histogram_vec};
+
+use crate::{
+    http::{request::Request, response::Response},
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+};
+
+lazy_static! {
+    pub static ref HTTP_RESPONSE: prom::HistogramVec = register_histogram_vec!(
+        "httpot_http_response",
+        "Outgoing HTTP response render and write time",
+        &[
+            "method",
+            "remote_addr",
+            "user_agent",
+            "version",
+            "persona",
+            "route"
+        ]
+    )
+    .unwrap();
+    pub static ref HTTP_RESPONSE_RENDER_FAILURES: prom::HistogramVec = register_histogram_vec!(
+        "httpot_http_response_render_failures",
+        "Outgoing HTTP response render failures time",
+        &[
+            "method",
+            "path",
+            "remote_addr",
+            "user_agent",
+            "version",
+            "persona",
+            "route"
+        ]
+    )
+    .unwrap();
+    pub static ref HTTP_RESPONSE_BODY: prom::CounterVec = register_counter_vec!(
+        "httpot_http_response_body_size",
+        "Outoing HTTP response cumulative body size",
+        &[
+            "method",
+            "remote_addr",
+            "user_agent",
+            "version",
+            "persona",
+            "route"
+        ]
+    )
+    .unwrap();
+    pub static ref EGRESS_RATE: prom::Gauge = prom::register_gauge!(
+        "httpot_egress_bytes_per_second",
+        "Bytes a second written to every connection over the last second"
+    )
+    .unwrap();
+    pub static ref TARPIT_BYTES: prom::CounterVec = register_counter_vec!(
+        "httpot_tarpit_bytes",
+        "Bytes trickled to tarpitted connections",
+        &["remote_addr"]
+    )
+    .unwrap();
+    pub static ref TARPIT_DURATION: prom::Histogram = prom::register_histogram!(
+        "httpot_tarpit_duration",
+        "How long tarpitted connections were held",
+        prom::exponential_buckets(1.0, 4.0, 9).unwrap()
+    )
+    .unwrap();
+}
+
+/// records a finished tarpit stream.
+pub fn observe_tarpit(req: &Request, sent: u64, held: Duration) {
+    TARPIT_BYTES
+        .with_label_values(&[&req.requester()])
+        .inc_by(sent as f64);
+    TARPIT_DURATION.observe(held.as_secs_f64());
+}
+
+/// Middleware which records response render time and size, labeled with
+/// the listener's persona and the route which answered.
+pub struct ObserveResponse {
+    persona: String,
+}
+
+impl ObserveResponse {
+    /// observes responses of the persona, panel, or device.
+    pub fn new<T: std::fmt::Debug>(persona: T) -> Self {
+        Self {
+            persona: format!("{:?}", persona).to_lowercase(),
+        }
+    }
+}
+
+impl Middleware for ObserveResponse {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(self.observe(req, next))
+    }
+}
+
+impl ObserveResponse {
+    async fn observe<'a>(&'a self, req: &'a Request, next: Next<'a>) -> Result<Response> {
+        let start = Instant::now();
+        let resp = next.run(req).await;
+        let elapsed = start.elapsed().as_secs_f64();
+
+        let ip = req.requester();
+        let meth = req.method.to_string();
+        let user_agent = req.headers.get_first("User-Agent").unwrap_or("unknown");
+        let resp = match resp {
+            Err(e) => {
+                HTTP_RESPONSE_RENDER_FAILURES
+                    .with_label_values(&[
+                        &meth,
+                        req.url.path(),
+                        &ip,
+                        user_agent,
+                        &req.version,
+                        &self.persona,
+                        "",
+                    ])
+                    .observe(elapsed);
+                return Err(e);
+            }
+            Ok(resp) => resp,
+        };
+
+        let common_labels: Vec<&str> = vec![
+            &meth,
+            &ip,
+            user_agent,
+            &req.version,
+            &self.persona,
+            resp.route().unwrap_or_default(),
+        ];
+        HTTP_RESPONSE
+            .with_label_values(common_labels.as_slice())
+            .observe(elapsed);
+        HTTP_RESPONSE_BODY
+            .with_label_values(common_labels.as_slice())
+            .inc_by(resp.len() as f64);
+
+        Ok(resp)
+    }
+}
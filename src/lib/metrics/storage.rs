@@ -0,0 +1,30 @@
+use lazy_static::lazy_static;
+
+use prometheus::{self as prom, register_int_counter_vec};
+
+use crate::retention::Pruned;
+
+lazy_static! {
+    pub static ref PRUNED_RECORDS: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_pruned_records",
+        "Files, or lines of logs, removed from stores by retention",
+        &["store"]
+    )
+    .unwrap();
+    pub static ref PRUNED_BYTES: prom::IntCounterVec = register_int_counter_vec!(
+        "httpot_pruned_bytes",
+        "Bytes removed from stores by retention",
+        &["store"]
+    )
+    .unwrap();
+}
+
+/// records what a store's pruning removed.
+pub fn observe_pruned(store: &str, pruned: &Pruned) {
+    PRUNED_RECORDS
+        .with_label_values(&[store])
+        .inc_by(pruned.records);
+    PRUNED_BYTES
+        .with_label_values(&[store])
+        .inc_by(pruned.bytes);
+}
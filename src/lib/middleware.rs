@@ -0,0 +1,386 @@
+//! Middleware wraps router dispatch so cross-cutting behavior (metrics,
+//! latency injection, header rewriting) doesn't need to live in every handler.
+//!
+//! Layers run in the order they were added. Each receives the request and a
+//! `Next` which runs the remainder of the chain, ending at the endpoint.
+//! A layer may short-circuit by taking the connection from `Next` and
+//! building its own response.
+use std::{future::Future, ops::Range, pin::Pin, sync::Arc, time::Duration};
+
+use rand::{thread_rng, Rng};
+use serde::Deserialize;
+use tokio::{net::TcpStream, time::sleep};
+
+use crate::{
+    http::{
+        request::{Method, Request},
+        response::Response,
+    },
+    prelude::*,
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The final handler of a chain, usually the router.
+pub type Endpoint = dyn Fn(TcpStream, &Request) -> Result<Response> + Send + Sync;
+
+pub trait Middleware: Send + Sync {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>>;
+
+    /// the route responses this layer answers itself are counted under in
+    /// metrics, such as "tarpit". Layers which only pass requests on have
+    /// none.
+    fn route(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+impl<F> Middleware for F
+where
+    F: for<'a> Fn(&'a Request, Next<'a>) -> BoxFuture<'a, Result<Response>> + Send + Sync,
+{
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        self(req, next)
+    }
+}
+
+/// The remainder of a chain.
+pub struct Next<'a> {
+    conn: TcpStream,
+    endpoint: &'a Endpoint,
+    layers: &'a [Arc<dyn Middleware>],
+}
+
+impl<'a> Next<'a> {
+    pub async fn run(self, req: &Request) -> Result<Response> {
+        match self.layers.split_first() {
+            Some((layer, layers)) => {
+                let resp = layer
+                    .call(
+                        req,
+                        Next {
+                            conn: self.conn,
+                            endpoint: self.endpoint,
+                            layers,
+                        },
+                    )
+                    .await?;
+                // responses from further down were named there
+                Ok(match layer.route() {
+                    Some(route) => resp.with_route(route),
+                    None => resp,
+                })
+            }
+            None => (self.endpoint)(self.conn, req),
+        }
+    }
+
+    /// runs the provided chain's layers before the rest of this chain.
+    pub async fn run_nested(self, chain: &Chain, req: &'a Request) -> Result<Response> {
+        let layers = chain
+            .layers
+            .iter()
+            .chain(self.layers)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Next {
+            conn: self.conn,
+            endpoint: self.endpoint,
+            layers: &layers,
+        }
+        .run(req)
+        .await
+    }
+
+    /// gives up on the rest of the chain, returning the connection so
+    /// a response can be built directly.
+    pub fn into_conn(self) -> TcpStream {
+        self.conn
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Chain {
+    layers: Vec<Arc<dyn Middleware>>,
+}
+
+impl Chain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn layer<M: Middleware + 'static>(mut self, m: M) -> Self {
+        self.layers.push(Arc::new(m));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub async fn run(
+        &self,
+        conn: TcpStream,
+        req: &Request,
+        endpoint: &Endpoint,
+    ) -> Result<Response> {
+        Next {
+            conn,
+            endpoint,
+            layers: &self.layers,
+        }
+        .run(req)
+        .await
+    }
+}
+
+/// Chains are layers themselves, running their layers before the rest of
+/// the outer chain.
+impl Middleware for Chain {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(next.run_nested(self, req))
+    }
+}
+
+/// Delays responses by a random duration in the provided range, making
+/// the honeypot look less like an instant responder.
+#[derive(Debug, Clone)]
+pub struct Delay(Range<Duration>);
+
+impl Delay {
+    pub fn new(range: Range<Duration>) -> Self {
+        Self(range)
+    }
+
+    pub fn up_to(max: Duration) -> Self {
+        Self(Duration::ZERO..max)
+    }
+}
+
+impl Middleware for Delay {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            if !self.0.is_empty() {
+                let delay = thread_rng().gen_range(self.0.clone());
+                trace!("delaying response by {:?}", delay);
+                sleep(delay).await;
+            }
+
+            next.run(req).await
+        })
+    }
+}
+
+/// Sets, replacing any existing values, and removes response headers.
+#[derive(Debug, Clone, Default)]
+pub struct RewriteHeaders {
+    set: Vec<(String, String)>,
+    remove: Vec<String>,
+}
+
+impl RewriteHeaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set<S: ToString>(mut self, name: &str, value: S) -> Self {
+        self.set.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn remove(mut self, name: &str) -> Self {
+        self.remove.push(name.to_string());
+        self
+    }
+}
+
+impl Middleware for RewriteHeaders {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let mut resp = next.run(req).await?;
+            let headers = resp.headers_mut();
+            for name in &self.remove {
+                headers.remove(name);
+            }
+            for (name, value) in &self.set {
+                headers.remove(name);
+                headers.add(name, value);
+            }
+
+            Ok(resp)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MethodOverrideConfig {
+    /// route on the tunneled method rather than the request line's
+    pub route_tunneled: bool,
+}
+
+/// Routes requests on the method tunneled through an override header or
+/// `_method` field, as frameworks honoring them would. Unknown tunneled
+/// methods are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct MethodOverride;
+
+impl Middleware for MethodOverride {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let method = req
+                .tunneled_method()
+                .and_then(|m| m.to_uppercase().parse::<Method>().ok());
+
+            match method {
+                Some(method) if method != req.method => {
+                    trace!("routing {:?} as tunneled {:?}", req.method, method);
+                    let mut tunneled = req.clone();
+                    tunneled.method = method;
+                    next.run(&tunneled).await
+                }
+                _ => next.run(req).await,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::{
+        request::Method,
+        response::{ResponseBuilder, StatusCode},
+    };
+    use tokio::net::TcpListener;
+
+    async fn conn() -> TcpStream {
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = l.local_addr().unwrap();
+        let (_, accepted) = tokio::join!(TcpStream::connect(addr), l.accept());
+        accepted.unwrap().0
+    }
+
+    fn stub_request() -> Request {
+        Request::stub(Method::GET, "/", "")
+    }
+
+    fn endpoint(conn: TcpStream, _: &Request) -> Result<Response> {
+        Ok(ResponseBuilder::ok(Arc::new(conn))
+            .add_header("X-Order", "endpoint")
+            .add_header("X-Remove-Me", "1")
+            .body("")
+            .build()?)
+    }
+
+    fn tag<'a>(req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let mut resp = next.run(req).await?;
+            resp.headers_mut().add("X-Order", "tag");
+            Ok(resp)
+        })
+    }
+
+    fn short_circuit<'a>(_: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            Ok(ResponseBuilder::not_found(Arc::new(next.into_conn()))
+                .body("")
+                .build()?)
+        })
+    }
+
+    #[tokio::test]
+    async fn test_chain_order() {
+        let chain = Chain::new()
+            .layer(
+                RewriteHeaders::new()
+                    .remove("X-Remove-Me")
+                    .set("Server", "Apache"),
+            )
+            .layer(tag)
+            .layer(Delay::up_to(Duration::from_millis(1)));
+
+        let resp = chain
+            .run(conn().await, &stub_request(), &endpoint)
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(&vec!["endpoint".to_string(), "tag".to_string()]),
+            resp.headers().get("X-Order")
+        );
+        assert_eq!(None, resp.headers().get("X-Remove-Me"));
+        assert_eq!(
+            Some(&vec!["Apache".to_string()]),
+            resp.headers().get("Server")
+        );
+    }
+
+    fn echo_method(conn: TcpStream, req: &Request) -> Result<Response> {
+        Ok(ResponseBuilder::ok(Arc::new(conn))
+            .body(req.method.to_string())
+            .build()?)
+    }
+
+    #[tokio::test]
+    async fn test_method_override() {
+        let chain = Chain::new().layer(MethodOverride);
+        let mut req = stub_request();
+        req.method = Method::POST;
+        req.headers.add("X-HTTP-Method-Override", "delete");
+
+        let resp = chain.run(conn().await, &req, &echo_method).await.unwrap();
+        assert_eq!(b"DELETE", resp.body());
+    }
+
+    #[tokio::test]
+    async fn test_short_circuit() {
+        let chain = Chain::new().layer(short_circuit).layer(tag);
+
+        let resp = chain
+            .run(conn().await, &stub_request(), &endpoint)
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NotFound, resp.status_code());
+        assert_eq!(None, resp.headers().get("X-Order"));
+    }
+
+    struct Named<M>(&'static str, M);
+
+    impl<M: Middleware> Middleware for Named<M> {
+        fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+            self.1.call(req, next)
+        }
+
+        fn route(&self) -> Option<&'static str> {
+            Some(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routes() {
+        // the layer which answered names the response
+        let chain = Chain::new()
+            .layer(Named("outer", tag))
+            .layer(Named("short", short_circuit));
+        let resp = chain
+            .run(conn().await, &stub_request(), &endpoint)
+            .await
+            .unwrap();
+        assert_eq!(Some("short"), resp.route());
+
+        // and layers it passed through leave it be
+        let chain = Chain::new().layer(Named("outer", tag)).layer(tag);
+        let resp = chain
+            .run(conn().await, &stub_request(), &|conn, req| {
+                Ok(endpoint(conn, req)?.with_route("endpoint"))
+            })
+            .await
+            .unwrap();
+        assert_eq!(Some("endpoint"), resp.route());
+    }
+}
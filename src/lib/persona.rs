@@ -0,0 +1,1007 @@
+//! Personas are the web servers httpot pretends to be. Everything a
+//! persona emits, such as its Server header and error pages, must agree,
+//! since a mismatch is an easy tell for scanners.
+use std::sync::Arc;
+
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use tokio::net::TcpStream;
+
+use crate::{
+    http::{
+        headers::Headers,
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+    },
+    locale::Language,
+    middleware::{BoxFuture, Middleware, Next},
+    prelude::*,
+    util::{escape_html, hash_seed},
+};
+
+/// The web servers a persona can be.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    /// Apache on an old Ubuntu, matching the PHP easter eggs' era
+    #[default]
+    Apache,
+    Nginx,
+    Iis,
+}
+
+/// A web server as a deployment presents it. Configs name only its
+/// family; a banner, if the deployment has one, picks which of the
+/// family's versions it claims.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "Family")]
+pub struct Persona {
+    pub family: Family,
+    banner: Option<Banner>,
+}
+
+impl From<Family> for Persona {
+    fn from(family: Family) -> Self {
+        Self {
+            family,
+            banner: None,
+        }
+    }
+}
+
+impl Persona {
+    /// the persona, claiming the versions the banner picks rather than the
+    /// newest.
+    pub fn with_banner(self, banner: Option<Banner>) -> Self {
+        Self { banner, ..self }
+    }
+
+    pub fn server(&self) -> String {
+        let version = self.version();
+        match self.family {
+            Family::Apache => format!("Apache/{} (Ubuntu)", version),
+            Family::Nginx => format!("nginx/{} (Ubuntu)", version),
+            Family::Iis => format!("Microsoft-IIS/{}", version),
+        }
+    }
+
+    /// versions the persona can claim, oldest first. The newest is claimed
+    /// unless a banner picks another.
+    fn versions(&self) -> &'static [&'static str] {
+        match self.family {
+            // what Ubuntu LTS and interim releases shipped
+            Family::Apache => &["2.2.14", "2.2.16", "2.2.17", "2.2.20", "2.2.22"],
+            Family::Nginx => &["1.10.3", "1.14.0", "1.18.0"],
+            Family::Iis => &["7.5", "8.0", "8.5", "10.0"],
+        }
+    }
+
+    fn version(&self) -> &'static str {
+        self.versions()[self.pick()]
+    }
+
+    // the index of the version claimed
+    fn pick(&self) -> usize {
+        let versions = self.versions();
+        match &self.banner {
+            Some(banner) => banner.pick(self.family, versions.len(), Utc::now().date_naive()),
+            None => versions.len() - 1,
+        }
+    }
+
+    /// PHP versions the persona can claim, paired with its versions. IIS
+    /// serves ASP.NET rather than PHP.
+    pub fn php_versions(&self) -> &'static [&'static str] {
+        match self.family {
+            // what the same Ubuntu releases shipped
+            Family::Apache => &["5.3.2", "5.3.3", "5.3.5", "5.3.6", "5.3.10"],
+            Family::Nginx => &["7.0.33", "7.2.24", "7.4.3"],
+            Family::Iis => &[],
+        }
+    }
+
+    /// the PHP version the persona claims, if it runs PHP.
+    pub fn php_version(&self) -> Option<&'static str> {
+        self.php_versions().get(self.pick()).copied()
+    }
+
+    /// the methods the persona lists in Allow, if it lists them.
+    pub fn allow(&self) -> Option<&'static str> {
+        match self.family {
+            Family::Apache => Some("GET,HEAD,POST,OPTIONS"),
+            // nginx refuses OPTIONS and never says what it allows
+            Family::Nginx => None,
+            Family::Iis => Some("OPTIONS, TRACE, GET, HEAD, POST"),
+        }
+    }
+
+    /// the persona's answer to OPTIONS, for a resource or for the server
+    /// itself with `OPTIONS *`.
+    pub fn options(&self, out: TcpStream, req: &Request) -> ResponseBuilder {
+        let allow = match (self.family, self.allow()) {
+            (Family::Nginx, _) if req.is_asterisk_form() => {
+                return self.error(out, StatusCode::BadRequest, Some(req))
+            }
+            (_, Some(allow)) => allow,
+            (_, None) => return self.error(out, StatusCode::MethodNotAllowed, Some(req)),
+        };
+
+        let mut resp = ResponseBuilder::ok(Arc::new(out));
+        resp.body([]).add_header("Allow", allow);
+        match self.family {
+            Family::Apache => {
+                resp.add_header("Content-Type", "text/html");
+            }
+            Family::Iis => {
+                resp.add_header("Public", allow);
+            }
+            Family::Nginx => {}
+        }
+        resp
+    }
+
+    /// builds the persona's redirect to the location, with the page its
+    /// server sends along.
+    pub fn redirect(
+        &self,
+        out: TcpStream,
+        status: StatusCode,
+        location: &str,
+        req: &Request,
+    ) -> ResponseBuilder {
+        let (content_type, body) = match self.family {
+            Family::Apache => (
+                "text/html; charset=iso-8859-1",
+                apache_redirect_page(&self.server(), status, req, location),
+            ),
+            Family::Nginx => ("text/html", nginx_error_page(&self.server(), status)),
+            Family::Iis => (
+                "text/html; charset=UTF-8",
+                format!(
+                    "<head><title>Document Moved</title></head>\n<body><h1>Object Moved</h1>This document may be found <a HREF=\"{}\">here</a></body>",
+                    escape_html(location)
+                ),
+            ),
+        };
+
+        let mut resp = ResponseBuilder::redirect(Arc::new(out), status, location);
+        resp.set_header("Server", self.server())
+            .add_header("Content-Type", content_type)
+            .body(body);
+        resp
+    }
+
+    /// whether the persona's server echoes TRACE out of the box. Apache's
+    /// TraceEnable defaults to on, while nginx and IIS refuse it.
+    pub fn traces(&self) -> bool {
+        self.family == Family::Apache
+    }
+
+    /// the persona's answer to TRACE: the request echoed back when it's
+    /// enabled, otherwise the server's refusal.
+    pub fn trace(&self, out: TcpStream, req: &Request, enabled: bool) -> ResponseBuilder {
+        if !enabled {
+            let mut resp = self.error(out, StatusCode::MethodNotAllowed, Some(req));
+            if let Some(allow) = self.allow() {
+                resp.add_header("Allow", allow);
+            }
+            return resp;
+        }
+
+        let mut resp = ResponseBuilder::ok(Arc::new(out));
+        resp.add_header("Content-Type", "message/http")
+            .body(echo(req));
+        resp
+    }
+
+    /// how the persona writes response headers.
+    pub fn template(&self) -> HeaderTemplate {
+        match self.family {
+            // mod_php and CGI headers land between Server and the core's
+            Family::Apache => HeaderTemplate {
+                order: &[
+                    "Date",
+                    "Server",
+                    "X-Powered-By",
+                    "Set-Cookie",
+                    "Expires",
+                    "Cache-Control",
+                    "Pragma",
+                    "Location",
+                    "WWW-Authenticate",
+                    "Allow",
+                    "Last-Modified",
+                    "ETag",
+                    "Accept-Ranges",
+                    "Vary",
+                    "Content-Language",
+                    "Content-Length",
+                    "Keep-Alive",
+                    "Connection",
+                    "Transfer-Encoding",
+                    "Content-Type",
+                ],
+                extra: &[],
+                repeated: &[],
+            },
+            // ngx_http_header_filter writes these, then everything else
+            Family::Nginx => HeaderTemplate {
+                order: &[
+                    "Server",
+                    "Date",
+                    "Content-Type",
+                    "Content-Length",
+                    "Last-Modified",
+                    "Location",
+                    "Connection",
+                    "Keep-Alive",
+                    "Transfer-Encoding",
+                ],
+                extra: &[],
+                // every headers_out entry is a line of its own
+                repeated: &[
+                    "Cache-Control",
+                    "Connection",
+                    "Content-Encoding",
+                    "Transfer-Encoding",
+                    "Via",
+                    "Warning",
+                ],
+            },
+            // http.sys writes Server and Date after the application's
+            Family::Iis => HeaderTemplate {
+                order: &[
+                    "Cache-Control",
+                    "Transfer-Encoding",
+                    "Content-Type",
+                    "Content-Encoding",
+                    "Content-Language",
+                    "Last-Modified",
+                    "Location",
+                    "Accept-Ranges",
+                    "ETag",
+                    "Vary",
+                    "Allow",
+                    "Server",
+                    "Public",
+                    "WWW-Authenticate",
+                    "X-Powered-By",
+                    "Date",
+                    "Connection",
+                    "Content-Length",
+                ],
+                extra: &[("X-Powered-By", "ASP.NET")],
+                repeated: &[],
+            },
+        }
+    }
+
+    /// dresses response headers as the persona's: its Server and extra
+    /// headers, in its order and casing, and its list headers joined or
+    /// repeated as it writes them.
+    pub fn dress(&self, headers: &mut Headers) {
+        let template = self.template();
+        headers.remove("Server");
+        headers.add("Server", self.server());
+        for (name, value) in template.extra {
+            if headers.get(name).is_none() {
+                headers.add(name, value);
+            }
+        }
+        headers.arrange(template.order);
+        headers.repeat(template.repeated);
+    }
+
+    /// the language of the persona's pages for the request. nginx ships
+    /// no translated error pages, so it's always English.
+    pub fn language(&self, req: Option<&Request>) -> Language {
+        match (self.family, req) {
+            (Family::Nginx, _) | (_, None) => Language::En,
+            (_, Some(req)) => Language::negotiate(req),
+        }
+    }
+
+    /// the content type and body of the persona's error page. The request
+    /// is absent when it couldn't be parsed.
+    pub fn error_page(&self, status: StatusCode, req: Option<&Request>) -> (&'static str, String) {
+        let lang = self.language(req);
+        match self.family {
+            Family::Apache if lang == Language::En => (
+                "text/html; charset=iso-8859-1",
+                apache_error_page(&self.server(), status, req, lang),
+            ),
+            Family::Apache => (
+                "text/html; charset=utf-8",
+                apache_error_page(&self.server(), status, req, lang),
+            ),
+            Family::Nginx => ("text/html", nginx_error_page(&self.server(), status)),
+            Family::Iis => ("text/html", iis_error_page(status, lang)),
+        }
+    }
+
+    /// builds the persona's error response, which callers may add to.
+    pub fn error(
+        &self,
+        out: TcpStream,
+        status: StatusCode,
+        req: Option<&Request>,
+    ) -> ResponseBuilder {
+        let (content_type, body) = self.error_page(status, req);
+
+        let mut resp = ResponseBuilder::default(Arc::new(out));
+        resp.status_code(status)
+            .set_header("Server", self.server())
+            .add_header("Content-Type", content_type)
+            .body(body);
+        let lang = self.language(req);
+        if lang != Language::En {
+            resp.add_header("Content-Language", lang.tag())
+                .add_header("Vary", "Accept-Language");
+        }
+        for (name, value) in self.template().extra {
+            resp.add_header(name, value);
+        }
+        resp
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BannerConfig {
+    /// seeds the versions picked, so instances sharing a seed claim the
+    /// same ones. Without one, each start picks anew.
+    pub seed: Option<String>,
+    /// the date versions were first picked, as YYYY-MM-DD. Without one,
+    /// they're never upgraded.
+    pub since: Option<String>,
+    /// how often versions are upgraded, on average
+    pub upgrade_days: u64,
+}
+
+impl Default for BannerConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            since: None,
+            upgrade_days: 180,
+        }
+    }
+}
+
+impl BannerConfig {
+    pub fn since(&self) -> Result<Option<NaiveDate>> {
+        self.since
+            .as_deref()
+            .map(|since| {
+                NaiveDate::parse_from_str(since, "%Y-%m-%d")
+                    .map_err(|e| anyhow!("invalid banner since {:?}: {}", since, e))
+            })
+            .transpose()
+    }
+}
+
+/// Versions picked for a deployment, so a fleet of instances doesn't share
+/// one fingerprint. Each persona starts at a version picked by the seed
+/// and upgrades to the next every so often, never past the newest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Banner {
+    seed: u64,
+    since: Option<NaiveDate>,
+    upgrade_days: u64,
+}
+
+impl Banner {
+    pub fn new(config: &BannerConfig) -> Result<Self> {
+        Ok(Self {
+            seed: match &config.seed {
+                Some(seed) => hash_seed(seed, "banner seed"),
+                None => rand::random(),
+            },
+            since: config.since()?,
+            upgrade_days: config.upgrade_days,
+        })
+    }
+
+    fn pick(&self, family: Family, versions: usize, today: NaiveDate) -> usize {
+        let hash = hash_seed((self.seed, family as u8), "banner");
+        let start = hash as usize % versions;
+        let upgrades = match self.since {
+            Some(since) if self.upgrade_days > 0 => {
+                // deployments are a random way into their first period, so
+                // a fleet picked at once doesn't upgrade in lockstep
+                let days = (today - since).num_days().max(0) as u64;
+                (days + (hash >> 32) % self.upgrade_days) / self.upgrade_days
+            }
+            _ => 0,
+        };
+        (start + upgrades as usize).min(versions - 1)
+    }
+}
+
+/// How a server writes response headers. Servers write them in a fixed
+/// order and casing, which fingerprinting tools compare.
+#[derive(Debug, Clone, Copy)]
+pub struct HeaderTemplate {
+    /// names in the order the server writes them, in its casing. Others,
+    /// such as an application's, follow in the order they were added.
+    pub order: &'static [&'static str],
+    /// headers the server adds to every response, besides Server
+    pub extra: &'static [(&'static str, &'static str)],
+    /// list headers the server writes a line per value, rather than
+    /// joining them, see `Headers::repeat`
+    pub repeated: &'static [&'static str],
+}
+
+fn apache_error_page(
+    server: &str,
+    status: StatusCode,
+    req: Option<&Request>,
+    lang: Language,
+) -> String {
+    let path = escape_html(req.map(|r| r.url.path()).unwrap_or("/"));
+    let method = req.map(|r| r.method.to_string()).unwrap_or_default();
+    let message = apache_message(status, lang)
+        .map(|m| m.replace("{path}", &path).replace("{method}", &method))
+        .unwrap_or_else(|| status.reason().to_string());
+    apache_page(server, status, req, lang, &message)
+}
+
+/// Apache's redirect page, which isn't localized.
+fn apache_redirect_page(server: &str, status: StatusCode, req: &Request, location: &str) -> String {
+    let message = format!(
+        "The document has moved <a href=\"{}\">here</a>.",
+        escape_html(location)
+    );
+    apache_page(server, status, Some(req), Language::En, &message)
+}
+
+fn apache_page(
+    server: &str,
+    status: StatusCode,
+    req: Option<&Request>,
+    lang: Language,
+    message: &str,
+) -> String {
+    let host = req
+        .and_then(|r| r.url.host_str())
+        .unwrap_or("localhost")
+        .to_string();
+    let port = req
+        .and_then(|r| r.url.port_or_known_default())
+        .unwrap_or(80);
+
+    format!(
+        "<!DOCTYPE HTML PUBLIC \"-//IETF//DTD HTML 2.0//EN\">\n\
+        <html><head>\n\
+        <title>{code} {reason}</title>\n\
+        </head><body>\n\
+        <h1>{reason}</h1>\n\
+        <p>{message}</p>\n\
+        <hr>\n\
+        <address>{server} Server at {host} Port {port}</address>\n\
+        </body></html>\n",
+        code = status as u16,
+        reason = reason(status, lang),
+        message = message,
+        server = server,
+        host = escape_html(&host),
+        port = port,
+    )
+}
+
+/// the body of Apache's error page for the status, with `{path}` and
+/// `{method}` placeholders.
+fn apache_message(status: StatusCode, lang: Language) -> Option<&'static str> {
+    use Language::*;
+    let message = match (status, lang) {
+        (StatusCode::BadRequest, En) => {
+            "Your browser sent a request that this server could not understand.<br />\n"
+        }
+        (StatusCode::BadRequest, De) => {
+            "Ihr Browser hat eine Anfrage gesendet, die dieser Server nicht verstehen konnte.<br />\n"
+        }
+        (StatusCode::BadRequest, Fr) => {
+            "Votre navigateur a envoyé une requête que ce serveur n'a pas pu comprendre.<br />\n"
+        }
+        (StatusCode::BadRequest, Es) => {
+            "Su navegador ha enviado una solicitud que este servidor no pudo entender.<br />\n"
+        }
+        (StatusCode::Unauthorized, En) => "This server could not verify that you\n\
+            are authorized to access the document\n\
+            requested.  Either you supplied the wrong\n\
+            credentials (e.g., bad password), or your\n\
+            browser doesn't understand how to supply\n\
+            the credentials required.",
+        (StatusCode::Unauthorized, De) => "Dieser Server konnte nicht verifizieren, dass Sie\n\
+            berechtigt sind, auf das angeforderte Dokument\n\
+            zuzugreifen. Entweder haben Sie falsche\n\
+            Anmeldedaten angegeben (z. B. ein falsches\n\
+            Passwort), oder Ihr Browser weiß nicht, wie\n\
+            die erforderlichen Anmeldedaten zu übermitteln sind.",
+        (StatusCode::Unauthorized, Fr) => "Ce serveur n'a pas pu vérifier que vous\n\
+            êtes autorisé à accéder au document\n\
+            demandé. Soit vous avez fourni de mauvais\n\
+            identifiants (par exemple un mot de passe\n\
+            erroné), soit votre navigateur ne sait pas\n\
+            comment fournir les identifiants requis.",
+        (StatusCode::Unauthorized, Es) => "Este servidor no pudo verificar que usted\n\
+            está autorizado para acceder al documento\n\
+            solicitado. O bien ha proporcionado credenciales\n\
+            incorrectas (por ejemplo, una contraseña\n\
+            errónea), o su navegador no sabe cómo\n\
+            proporcionar las credenciales requeridas.",
+        (StatusCode::Forbidden, En) => "You don't have permission to access {path}\non this server.",
+        (StatusCode::Forbidden, De) => {
+            "Sie haben keine Berechtigung, auf {path}\nauf diesem Server zuzugreifen."
+        }
+        (StatusCode::Forbidden, Fr) => "Vous n'avez pas la permission d'accéder à {path}\nsur ce serveur.",
+        (StatusCode::Forbidden, Es) => "No tiene permiso para acceder a {path}\nen este servidor.",
+        (StatusCode::NotFound, En) => "The requested URL {path} was not found on this server.",
+        (StatusCode::NotFound, De) => {
+            "Die angeforderte URL {path} wurde auf diesem Server nicht gefunden."
+        }
+        (StatusCode::NotFound, Fr) => "L'URL demandée {path} n'a pas été trouvée sur ce serveur.",
+        (StatusCode::NotFound, Es) => "La URL solicitada {path} no se encontró en este servidor.",
+        (StatusCode::MethodNotAllowed, En) => {
+            "The requested method {method} is not allowed for the URL {path}."
+        }
+        (StatusCode::MethodNotAllowed, De) => {
+            "Die angeforderte Methode {method} ist für die URL {path} nicht erlaubt."
+        }
+        (StatusCode::MethodNotAllowed, Fr) => {
+            "La méthode demandée {method} n'est pas autorisée pour l'URL {path}."
+        }
+        (StatusCode::MethodNotAllowed, Es) => {
+            "El método solicitado {method} no está permitido para la URL {path}."
+        }
+        (StatusCode::InternalServerError, En) => "The server encountered an internal error or\n\
+            misconfiguration and was unable to complete\n\
+            your request.",
+        (StatusCode::InternalServerError, De) => "Der Server hat einen internen Fehler oder\n\
+            eine Fehlkonfiguration festgestellt und konnte\n\
+            Ihre Anfrage nicht abschließen.",
+        (StatusCode::InternalServerError, Fr) => "Le serveur a rencontré une erreur interne ou\n\
+            une mauvaise configuration et n'a pas pu\n\
+            traiter votre requête.",
+        (StatusCode::InternalServerError, Es) => "El servidor encontró un error interno o\n\
+            una mala configuración y no pudo completar\n\
+            su solicitud.",
+        _ => return None,
+    };
+    Some(message)
+}
+
+/// the status's reason phrase as a translated page shows it. Status
+/// lines keep the English phrase.
+fn reason(status: StatusCode, lang: Language) -> &'static str {
+    use Language::*;
+    match (status, lang) {
+        (_, En) => status.reason(),
+        (StatusCode::BadRequest, De) => "Ungültige Anfrage",
+        (StatusCode::BadRequest, Fr) => "Requête incorrecte",
+        (StatusCode::BadRequest, Es) => "Solicitud incorrecta",
+        (StatusCode::Unauthorized, De) => "Nicht autorisiert",
+        (StatusCode::Unauthorized, Fr) => "Non autorisé",
+        (StatusCode::Unauthorized, Es) => "No autorizado",
+        (StatusCode::Forbidden, De) => "Zugriff verweigert",
+        (StatusCode::Forbidden, Fr) => "Accès interdit",
+        (StatusCode::Forbidden, Es) => "Acceso prohibido",
+        (StatusCode::NotFound, De) => "Nicht gefunden",
+        (StatusCode::NotFound, Fr) => "Non trouvé",
+        (StatusCode::NotFound, Es) => "No encontrado",
+        (StatusCode::MethodNotAllowed, De) => "Methode nicht erlaubt",
+        (StatusCode::MethodNotAllowed, Fr) => "Méthode non autorisée",
+        (StatusCode::MethodNotAllowed, Es) => "Método no permitido",
+        (StatusCode::InternalServerError, De) => "Interner Serverfehler",
+        (StatusCode::InternalServerError, Fr) => "Erreur interne du serveur",
+        (StatusCode::InternalServerError, Es) => "Error interno del servidor",
+        _ => status.reason(),
+    }
+}
+
+fn nginx_error_page(server: &str, status: StatusCode) -> String {
+    format!(
+        "<html>\r\n\
+        <head><title>{code} {reason}</title></head>\r\n\
+        <body>\r\n\
+        <center><h1>{code} {reason}</h1></center>\r\n\
+        <hr><center>{server}</center>\r\n\
+        </body>\r\n\
+        </html>\r\n",
+        code = status as u16,
+        reason = status.reason(),
+        server = server,
+    )
+}
+
+fn iis_error_page(status: StatusCode, lang: Language) -> String {
+    let (title, detail) = iis_message(status, lang);
+    let (heading, charset) = match lang {
+        Language::En => ("Server Error", "iso-8859-1"),
+        Language::De => ("Serverfehler", "utf-8"),
+        Language::Fr => ("Erreur de serveur", "utf-8"),
+        Language::Es => ("Error del servidor", "utf-8"),
+    };
+
+    format!(
+        r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Strict//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset={charset}"/>
+<title>{title}</title>
+<style type="text/css">
+<!--
+body{{margin:0;font-size:.7em;font-family:Verdana, Arial, Helvetica, sans-serif;background:#EEEEEE;}}
+fieldset{{padding:0 15px 10px 15px;}}
+h1{{font-size:2.4em;margin:0;color:#FFF;}}
+h2{{font-size:1.7em;margin:0;color:#CC0000;}}
+h3{{font-size:1.2em;margin:10px 0 0 0;color:#000000;}}
+#header{{width:96%;margin:0 0 0 0;padding:6px 2% 6px 2%;font-family:"trebuchet MS", Verdana, sans-serif;color:#FFF;
+background-color:#555555;}}
+#content{{margin:0 0 0 2%;position:relative;}}
+.content-container{{background:#FFF;width:96%;margin-top:8px;padding:10px;position:relative;}}
+-->
+</style>
+</head>
+<body>
+<div id="header"><h1>{heading}</h1></div>
+<div id="content">
+ <div class="content-container"><fieldset>
+  <h2>{title}</h2>
+  <h3>{detail}</h3>
+ </fieldset></div>
+</div>
+</body>
+</html>
+"#,
+        title = title,
+        detail = detail,
+        heading = heading,
+        charset = charset,
+    )
+}
+
+fn iis_message(status: StatusCode, lang: Language) -> (&'static str, &'static str) {
+    use Language::*;
+    let denied = match lang {
+        En => "You do not have permission to view this directory or page using the credentials that you supplied.",
+        De => "Sie verfügen nicht über die Berechtigung zum Anzeigen dieses Verzeichnisses bzw. dieser Seite unter Verwendung der angegebenen Anmeldeinformationen.",
+        Fr => "Vous n'êtes pas autorisé à afficher ce répertoire ou cette page avec les informations d'identification que vous avez fournies.",
+        Es => "No tiene permiso para ver este directorio o esta página con las credenciales que ha proporcionado.",
+    };
+    match (status, lang) {
+        (StatusCode::Unauthorized, En) => (
+            "401 - Unauthorized: Access is denied due to invalid credentials.",
+            denied,
+        ),
+        (StatusCode::Unauthorized, De) => (
+            "401 - Nicht autorisiert: Der Zugriff wurde aufgrund ungültiger Anmeldeinformationen verweigert.",
+            denied,
+        ),
+        (StatusCode::Unauthorized, Fr) => (
+            "401 - Non autorisé : accès refusé en raison d'informations d'identification non valides.",
+            denied,
+        ),
+        (StatusCode::Unauthorized, Es) => (
+            "401 - No autorizado: acceso denegado debido a credenciales no válidas.",
+            denied,
+        ),
+        (StatusCode::Forbidden, En) => ("403 - Forbidden: Access is denied.", denied),
+        (StatusCode::Forbidden, De) => ("403 - Verboten: Zugriff verweigert.", denied),
+        (StatusCode::Forbidden, Fr) => ("403 - Interdit : accès refusé.", denied),
+        (StatusCode::Forbidden, Es) => ("403 - Prohibido: acceso denegado.", denied),
+        (StatusCode::NotFound, En) => (
+            "404 - File or directory not found.",
+            "The resource you are looking for might have been removed, had its name changed, or is temporarily unavailable.",
+        ),
+        (StatusCode::NotFound, De) => (
+            "404 - Datei oder Verzeichnis nicht gefunden.",
+            "Die gesuchte Ressource wurde möglicherweise entfernt, umbenannt oder ist vorübergehend nicht verfügbar.",
+        ),
+        (StatusCode::NotFound, Fr) => (
+            "404 - Fichier ou répertoire introuvable.",
+            "La ressource que vous recherchez a peut-être été supprimée ou renommée, ou elle est temporairement indisponible.",
+        ),
+        (StatusCode::NotFound, Es) => (
+            "404 - No se encontró el archivo o directorio.",
+            "Es posible que el recurso que está buscando se haya quitado, que se le haya cambiado el nombre o que no esté disponible temporalmente.",
+        ),
+        (StatusCode::MethodNotAllowed, En) => (
+            "405 - HTTP verb used to access this page is not allowed.",
+            "The page you are looking for cannot be displayed because an invalid method (HTTP verb) was used to attempt access.",
+        ),
+        (StatusCode::MethodNotAllowed, De) => (
+            "405 - Das für den Zugriff auf diese Seite verwendete HTTP-Verb ist nicht zulässig.",
+            "Die gesuchte Seite kann nicht angezeigt werden, da eine ungültige Methode (HTTP-Verb) für den Zugriff verwendet wurde.",
+        ),
+        (StatusCode::MethodNotAllowed, Fr) => (
+            "405 - Le verbe HTTP utilisé pour accéder à cette page n'est pas autorisé.",
+            "La page que vous recherchez ne peut pas être affichée, car une méthode non valide (verbe HTTP) a été utilisée pour tenter d'y accéder.",
+        ),
+        (StatusCode::MethodNotAllowed, Es) => (
+            "405 - No se permite el verbo HTTP usado para obtener acceso a esta página.",
+            "La página que está buscando no se puede mostrar porque se usó un método no válido (verbo HTTP) para intentar obtener acceso.",
+        ),
+        (_, En) => (
+            "500 - Internal server error.",
+            "There is a problem with the resource you are looking for, and it cannot be displayed.",
+        ),
+        (_, De) => (
+            "500 - Interner Serverfehler.",
+            "Es gibt ein Problem mit der gesuchten Ressource, sodass sie nicht angezeigt werden kann.",
+        ),
+        (_, Fr) => (
+            "500 - Erreur interne du serveur.",
+            "Il y a un problème avec la ressource que vous recherchez, elle ne peut pas être affichée.",
+        ),
+        (_, Es) => (
+            "500 - Error interno del servidor.",
+            "Hay un problema con el recurso que busca y no se puede mostrar.",
+        ),
+    }
+}
+
+/// Dresses every response's headers as the persona's, replacing the
+/// defaults.
+impl Middleware for Persona {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            // OPTIONS * is about the server, so nothing behind it answers
+            let mut resp = match req.is_asterisk_form() {
+                true => self.options(next.into_conn(), req).build()?,
+                false => next.run(req).await?,
+            };
+            self.dress(resp.headers_mut());
+
+            Ok(resp)
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("options")
+    }
+}
+
+/// the request's head, as TRACE echoes it.
+fn echo(req: &Request) -> String {
+    let mut echo = format!(
+        "{} {} {}\r\n",
+        req.method.to_string(),
+        req.target,
+        req.version
+    );
+    for (name, values) in req.headers.iter() {
+        // cookies are split as they're parsed, other repeats are joined
+        let sep = match name.eq_ignore_ascii_case("Cookie") {
+            true => "; ",
+            false => ", ",
+        };
+        echo.push_str(&format!("{}: {}\r\n", name, values.join(sep)));
+    }
+    echo + "\r\n"
+}
+
+/// Answers TRACE as the persona's server does, before anything else can.
+/// Cross-site tracing scanners check whether requests are echoed.
+#[derive(Debug, Clone, Copy)]
+pub struct Trace {
+    persona: Persona,
+    enabled: bool,
+}
+
+impl Trace {
+    /// enabled overrides whether the persona's server echoes TRACE.
+    pub fn new(persona: Persona, enabled: Option<bool>) -> Self {
+        Self {
+            persona,
+            enabled: enabled.unwrap_or_else(|| persona.traces()),
+        }
+    }
+}
+
+impl Middleware for Trace {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            match req.method {
+                Method::TRACE => Ok(self
+                    .persona
+                    .trace(next.into_conn(), req, self.enabled)
+                    .build()?),
+                _ => next.run(req).await,
+            }
+        })
+    }
+
+    fn route(&self) -> Option<&'static str> {
+        Some("trace")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::headers::Serialization;
+
+    fn stub_request(path: &str) -> Request {
+        let mut req = Request::stub(Method::POST, path, "");
+        req.url = format!("http://example.com{}", path).parse().unwrap();
+        req
+    }
+
+    #[test]
+    fn test_apache_error_page() {
+        let req = stub_request("/<script>");
+        let (content_type, body) =
+            Persona::from(Family::Apache).error_page(StatusCode::NotFound, Some(&req));
+        assert_eq!("text/html; charset=iso-8859-1", content_type);
+        assert!(body.contains("<title>404 Not Found</title>"));
+        assert!(body.contains("The requested URL /%3Cscript%3E was not found on this server."));
+        assert!(body
+            .contains("<address>Apache/2.2.22 (Ubuntu) Server at example.com Port 80</address>"));
+
+        let (_, body) =
+            Persona::from(Family::Apache).error_page(StatusCode::MethodNotAllowed, Some(&req));
+        assert!(body.contains("The requested method POST is not allowed"));
+    }
+
+    #[test]
+    fn test_error_pages_name_their_server() {
+        let (_, body) = Persona::from(Family::Nginx).error_page(StatusCode::Forbidden, None);
+        assert!(body.contains("<center><h1>403 Forbidden</h1></center>"));
+        assert!(body.contains(&Persona::from(Family::Nginx).server()));
+
+        let (_, body) = Persona::from(Family::Iis).error_page(StatusCode::NotFound, None);
+        assert!(body.contains("404 - File or directory not found."));
+    }
+
+    #[test]
+    fn test_localized_error_pages() {
+        let mut req = stub_request("/admin");
+        req.headers
+            .add("Accept-Language", "de-DE,de;q=0.9,en;q=0.8");
+
+        let (content_type, body) =
+            Persona::from(Family::Apache).error_page(StatusCode::NotFound, Some(&req));
+        assert_eq!("text/html; charset=utf-8", content_type);
+        assert!(body.contains("<title>404 Nicht gefunden</title>"));
+        assert!(
+            body.contains("Die angeforderte URL /admin wurde auf diesem Server nicht gefunden.")
+        );
+
+        let (_, body) = Persona::from(Family::Iis).error_page(StatusCode::NotFound, Some(&req));
+        assert!(body.contains("<h1>Serverfehler</h1>"));
+        assert!(body.contains("404 - Datei oder Verzeichnis nicht gefunden."));
+
+        let (_, body) = Persona::from(Family::Nginx).error_page(StatusCode::NotFound, Some(&req));
+        assert!(body.contains("<center><h1>404 Not Found</h1></center>"));
+    }
+
+    #[test]
+    fn test_trace_echo() {
+        let mut req = stub_request("/a?b=c");
+        req.method = Method::TRACE;
+        req.headers
+            .add("Host", "example.com")
+            .add("Cookie", "a=1")
+            .add("Cookie", "b=2");
+        assert_eq!(
+            "TRACE /a?b=c HTTP/1.1\r\nHost: example.com\r\nCookie: a=1; b=2\r\n\r\n",
+            echo(&req)
+        );
+        assert!(Persona::from(Family::Apache).traces());
+        assert!(!Trace::new(Persona::from(Family::Apache), Some(false)).enabled);
+        assert!(Trace::new(Persona::from(Family::Nginx), Some(true)).enabled);
+    }
+
+    #[test]
+    fn test_dress() {
+        let mut headers = Headers::new();
+        headers
+            .add("Server", "httpot")
+            .add("Date", "Thu, 01 Jan 2015 00:00:00 GMT")
+            .add("Connection", "close")
+            .add("content-type", "text/html")
+            .add("Content-Length", 0)
+            .add("X-Custom", "1");
+
+        let mut apache = headers.clone();
+        Persona::from(Family::Apache).dress(&mut apache);
+        assert_eq!(
+            vec![
+                "Date",
+                "Server",
+                "Content-Length",
+                "Connection",
+                "Content-Type",
+                "X-Custom"
+            ],
+            apache.names().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Persona::from(Family::Apache).server(),
+            apache.get("Server").unwrap()[0]
+        );
+
+        let mut nginx = headers.clone();
+        Persona::from(Family::Nginx).dress(&mut nginx);
+        assert_eq!(
+            vec![
+                "Server",
+                "Date",
+                "Content-Type",
+                "Content-Length",
+                "Connection",
+                "X-Custom"
+            ],
+            nginx.names().collect::<Vec<_>>()
+        );
+        assert_eq!(Serialization::Repeat, nginx.serialization("cache-control"));
+        assert_eq!(
+            Serialization::Join(","),
+            apache.serialization("Cache-Control")
+        );
+
+        Persona::from(Family::Iis).dress(&mut headers);
+        assert_eq!(
+            vec![
+                "Content-Type",
+                "Server",
+                "X-Powered-By",
+                "Date",
+                "Connection",
+                "Content-Length",
+                "X-Custom"
+            ],
+            headers.names().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_banner_drift() {
+        let date = |s| NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap();
+        let banner = |seed| Banner {
+            seed,
+            since: Some(date("2024-01-01")),
+            upgrade_days: 180,
+        };
+
+        let mut starts = std::collections::HashSet::new();
+        for seed in 0..64 {
+            let banner = banner(seed);
+            let start = banner.pick(Family::Apache, 5, date("2024-01-01"));
+            starts.insert(start);
+            // versions only ever upgrade, by about one a period
+            let mut last = start;
+            for days in (0..3650).step_by(30) {
+                let today = date("2024-01-01") + chrono::Duration::days(days);
+                let i = banner.pick(Family::Apache, 5, today);
+                assert!(i >= last && i < 5, "{} after {}", i, last);
+                assert!(i as i64 <= start as i64 + days / 180 + 1);
+                last = i;
+            }
+            assert_eq!(4, last);
+        }
+        assert!(starts.len() > 1, "every deployment started alike");
+
+        let fixed = Banner {
+            since: None,
+            ..banner(7)
+        };
+        assert_eq!(
+            fixed.pick(Family::Nginx, 3, date("2024-01-01")),
+            fixed.pick(Family::Nginx, 3, date("2034-01-01"))
+        );
+
+        // banners are each persona's own, so one without claims the newest
+        let newest = Persona::from(Family::Apache).server();
+        assert_eq!("Apache/2.2.22 (Ubuntu)", newest);
+        assert!((0..64).any(|seed| {
+            let banner = Banner {
+                since: None,
+                ..banner(seed)
+            };
+            let picked = Persona::from(Family::Apache).with_banner(Some(banner));
+            picked.server() != newest
+        }));
+    }
+}
@@ -0,0 +1,269 @@
+//! Anonymization of source addresses, for deployments which can't store
+//! attackers' full addresses. Addresses are replaced as connections are
+//! accepted, so logs, metrics, archives, captures, and reports only ever
+//! see the replacement. Addresses in Forwarded and X-Forwarded-For are
+//! replaced too, as requesters are read from them, in requests and in
+//! captures' inbound bytes; other headers and bodies are left as sent, as
+//! are HTTP/2's compressed headers in captures.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use serde::Deserialize;
+
+use crate::{http::request::Request, prelude::*, util::hmac_sha256};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    pub mode: Mode,
+    /// secret keying pseudonyms, required by the hmac mode. Pseudonyms are
+    /// stable for as long as the key is.
+    pub key: Option<String>,
+    /// bits of IPv4 addresses kept when truncating
+    pub ipv4_prefix: u8,
+    /// bits of IPv6 addresses kept when truncating
+    pub ipv6_prefix: u8,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            mode: Mode::default(),
+            key: None,
+            ipv4_prefix: 24,
+            ipv6_prefix: 48,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// addresses are masked to their network, so sources in the same
+    /// network can't be told apart
+    #[default]
+    Truncate,
+    /// addresses are replaced by keyed pseudonyms in reserved ranges:
+    /// 240.0.0.0/4 for IPv4 and fd00::/8 for IPv6. Each /24 or /48 maps to
+    /// its own pseudonymous network, and each address within it to its
+    /// own pseudonym, so sources and their networks can still be
+    /// correlated.
+    Hmac,
+}
+
+#[derive(Debug, Clone)]
+pub struct Anonymizer {
+    config: PrivacyConfig,
+    key: Vec<u8>,
+}
+
+impl Anonymizer {
+    pub fn new(config: PrivacyConfig) -> Result<Self> {
+        let key = match (config.mode, &config.key) {
+            (Mode::Hmac, None) => bail!("privacy mode hmac requires a key"),
+            (_, key) => key.clone().unwrap_or_default().into_bytes(),
+        };
+        Ok(Self { config, key })
+    }
+
+    pub fn ip(&self, ip: IpAddr) -> IpAddr {
+        match (self.config.mode, ip) {
+            (Mode::Truncate, IpAddr::V4(ip)) => {
+                let prefix = self.config.ipv4_prefix.min(32) as u32;
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & mask).into())
+            }
+            (Mode::Truncate, IpAddr::V6(ip)) => {
+                let prefix = self.config.ipv6_prefix.min(128) as u32;
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & mask).into())
+            }
+            (Mode::Hmac, IpAddr::V4(ip)) => {
+                let [a, b, c, host] = ip.octets();
+                let net = hmac_sha256(&self.key, &[b"v4/".as_slice(), &[a, b, c]].concat());
+                // 20 bits name the network, and the host byte is permuted
+                // within it so addresses in the network stay distinct
+                IpAddr::V4(Ipv4Addr::new(
+                    0xf0 | (net[0] >> 4),
+                    net[1],
+                    net[2],
+                    host ^ net[3],
+                ))
+            }
+            (Mode::Hmac, IpAddr::V6(ip)) => {
+                let octets = ip.octets();
+                let net = hmac_sha256(&self.key, &[b"v6/".as_slice(), &octets[..6]].concat());
+                let host = hmac_sha256(&self.key, &[b"v6host/".as_slice(), &octets].concat());
+                let mut pseudonym = [0xfd; 16];
+                pseudonym[1..6].copy_from_slice(&net[..5]);
+                pseudonym[6..].copy_from_slice(&host[..10]);
+                IpAddr::V6(Ipv6Addr::from(pseudonym))
+            }
+        }
+    }
+
+    pub fn addr(&self, addr: SocketAddr) -> SocketAddr {
+        SocketAddr::new(self.ip(addr.ip()), addr.port())
+    }
+
+    /// replaces the request's addresses, including those in the headers
+    /// its requester is read from.
+    pub fn request(&self, req: &mut Request) {
+        req.remote_ip = self.addr(req.remote_ip);
+        for (name, values) in req.headers.iter_mut() {
+            for value in values.iter_mut() {
+                self.forwarding(name, value);
+            }
+        }
+        for (name, value) in req.syntax.raw_headers.iter_mut() {
+            self.forwarding(name, value);
+        }
+        req.raw_head = self.head(&req.raw_head);
+    }
+
+    /// the bytes with the addresses in any lines which are forwarding
+    /// headers replaced, as a request's head or a capture's inbound bytes.
+    pub fn head(&self, head: &[u8]) -> Vec<u8> {
+        head.split_inclusive(|b| *b == b'\n')
+            .flat_map(|line| self.head_line(line))
+            .collect()
+    }
+
+    /// a line of a request's head with any forwarding header's addresses
+    /// replaced, keeping its spacing and line ending.
+    fn head_line(&self, line: &[u8]) -> Vec<u8> {
+        let (name, rest) = match std::str::from_utf8(line).map(|l| l.split_once(':')) {
+            Ok(Some(header)) => header,
+            _ => return line.to_vec(),
+        };
+        let value = rest.trim();
+        let mut anonymized = value.to_string();
+        self.forwarding(name, &mut anonymized);
+        if anonymized == value {
+            return line.to_vec();
+        }
+        format!("{}:{}", name, rest.replacen(value, &anonymized, 1)).into_bytes()
+    }
+
+    /// replaces the addresses in a value of a forwarding header, leaving
+    /// other headers alone.
+    fn forwarding(&self, name: &str, value: &mut String) {
+        let forwarded = name.eq_ignore_ascii_case("Forwarded");
+        if !forwarded && !name.eq_ignore_ascii_case("X-Forwarded-For") {
+            return;
+        }
+        *value = value
+            .split(',')
+            .map(|hop| match forwarded {
+                true => hop
+                    .split(';')
+                    .map(|pair| match pair.split_once('=') {
+                        Some((k, v)) if k.trim().eq_ignore_ascii_case("for") => {
+                            format!("{}={}", k, self.node(v))
+                        }
+                        _ => pair.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(";"),
+                false => self.node(hop),
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+    }
+
+    /// an address in a forwarding header, which may have a port, brackets,
+    /// or quotes, or not be an address at all.
+    fn node(&self, node: &str) -> String {
+        let trimmed = node.trim().trim_matches('"');
+        let anonymized = match trimmed.parse::<SocketAddr>() {
+            Ok(addr) => self.addr(addr).to_string(),
+            Err(_) => match trimmed.trim_matches(|c| c == '[' || c == ']').parse() {
+                Ok(ip) => self.ip(ip).to_string(),
+                Err(_) => return node.to_string(),
+            },
+        };
+        let lead = &node[..node.len() - node.trim_start().len()];
+        match trimmed.len() == node.trim().len() {
+            true => format!("{}{}", lead, anonymized),
+            false => format!("{}\"{}\"", lead, anonymized),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::{Method, Syntax};
+
+    #[test]
+    fn test_anonymize() {
+        let truncate = Anonymizer::new(PrivacyConfig::default()).unwrap();
+        assert_eq!(
+            "203.0.113.0:5000".parse::<SocketAddr>().unwrap(),
+            truncate.addr("203.0.113.77:5000".parse().unwrap())
+        );
+        assert_eq!(
+            "2001:db8:1::".parse::<IpAddr>().unwrap(),
+            truncate.ip("2001:db8:1:2::7".parse().unwrap())
+        );
+
+        assert!(Anonymizer::new(PrivacyConfig {
+            mode: Mode::Hmac,
+            ..Default::default()
+        })
+        .is_err());
+        let hmac = Anonymizer::new(PrivacyConfig {
+            mode: Mode::Hmac,
+            key: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let ip = |s: &str| hmac.ip(s.parse().unwrap());
+        let (a, b, other) = (ip("203.0.113.7"), ip("203.0.113.8"), ip("198.51.100.7"));
+        assert_eq!(a, ip("203.0.113.7"));
+        assert_ne!(a, b);
+        let octets = |ip: IpAddr| match ip {
+            IpAddr::V4(v4) => v4.octets(),
+            _ => unreachable!(),
+        };
+        assert_eq!(0xf0, octets(a)[0] & 0xf0, "not in 240.0.0.0/4");
+        assert_eq!(octets(a)[..3], octets(b)[..3], "network wasn't kept");
+        assert_ne!(octets(a)[..3], octets(other)[..3]);
+        let v6 = ip("2001:db8:1:2::7");
+        assert!(v6.to_string().starts_with("fd"), "{}", v6);
+
+        let mut req = Request::stub(Method::GET, "/", "");
+        req.headers.add(
+            "Forwarded",
+            "for=\"[2001:db8:1:2::7]:4711\";proto=http, for=unknown",
+        );
+        req.headers
+            .add("X-Forwarded-For", "203.0.113.7, 198.51.100.1");
+        req.remote_ip = "192.0.2.77:62012".parse().unwrap();
+        req.syntax = Syntax {
+            raw_headers: vec![(
+                "x-forwarded-for".to_string(),
+                "203.0.113.7, 198.51.100.1".to_string(),
+            )],
+            ..Default::default()
+        };
+        req.raw_head = b"GET / HTTP/1.1\r\nHost: 127.0.0.1:8080\r\n\
+            x-forwarded-for:203.0.113.7, 198.51.100.1\r\n\r\n"
+            .to_vec();
+        truncate.request(&mut req);
+        assert_eq!("192.0.2.0:62012", req.remote_ip.to_string());
+        assert_eq!(
+            "for=\"[2001:db8:1::]:4711\";proto=http, for=unknown",
+            req.headers.get("Forwarded").unwrap()[0]
+        );
+        assert_eq!(
+            "203.0.113.0, 198.51.100.0",
+            req.headers.get("X-Forwarded-For").unwrap()[0]
+        );
+        assert_eq!("203.0.113.0, 198.51.100.0", req.syntax.raw_headers[0].1);
+        assert_eq!(
+            "GET / HTTP/1.1\r\nHost: 127.0.0.1:8080\r\n\
+                x-forwarded-for:203.0.113.0, 198.51.100.0\r\n\r\n",
+            String::from_utf8_lossy(&req.raw_head)
+        );
+    }
+}
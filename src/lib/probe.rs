@@ -0,0 +1,513 @@
+//! Probes a running instance the way Shodan, Nmap's http-enum, and
+//! WhatWeb fingerprint web servers, and reports the tells they'd find:
+//! responses which disagree about what server they came from, headers out
+//! of the server's order, or anything naming httpot. Operators run it to
+//! check a persona config before scanners do.
+use std::{fmt, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+
+use crate::{
+    persona::{Family, Persona},
+    prelude::*,
+};
+
+// Nmap's NSE User-Agent, which http-enum sends
+const USER_AGENT: &str =
+    "Mozilla/5.0 (compatible; Nmap Scripting Engine; https://nmap.org/book/nse.html)";
+// responses larger than this are cut off
+const MAX_REPLY: u64 = 1024 * 1024;
+// how far a Date may be from the local clock
+const MAX_SKEW_SECS: i64 = 5 * 60;
+
+/// A response to a probe.
+#[derive(Debug, Clone, Default)]
+pub struct Reply {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    /// parses a response read until the connection closed.
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let end = raw
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| anyhow!("response has no end of headers"))?;
+        let head = String::from_utf8_lossy(&raw[..end]);
+        let mut lines = head.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .and_then(|code| code.parse().ok())
+            .ok_or_else(|| anyhow!("response has no status line"))?;
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.to_string(), v.trim().to_string()))
+            .collect();
+
+        Ok(Self {
+            status,
+            headers,
+            body: raw[end + 4..].to_vec(),
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.headers.iter().map(|(k, _)| k.as_str()).collect()
+    }
+}
+
+/// What a probe found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Ok,
+    /// something scanners read, which gives nothing away by itself
+    Info,
+    /// something which gives the honeypot away
+    Tell,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub probe: &'static str,
+    pub verdict: Verdict,
+    pub detail: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = match self.verdict {
+            Verdict::Ok => "ok",
+            Verdict::Info => "info",
+            Verdict::Tell => "TELL",
+        };
+        write!(f, "{:<5} {:<8} {}", verdict, self.probe, self.detail)
+    }
+}
+
+/// The responses to every probe. Probes which weren't answered have none.
+#[derive(Debug, Clone, Default)]
+pub struct Replies {
+    /// `GET /`
+    pub root: Option<Reply>,
+    /// `HEAD /`
+    pub head: Option<Reply>,
+    /// GET of a random path which can't exist
+    pub missing: Option<Reply>,
+    /// `GET /favicon.ico`
+    pub favicon: Option<Reply>,
+    /// `OPTIONS *`
+    pub options: Option<Reply>,
+    /// a method no server implements
+    pub method: Option<Reply>,
+    /// a request line which isn't HTTP
+    pub garbage: Option<Reply>,
+}
+
+fn request(method: &str, target: &str, host: &str) -> Vec<u8> {
+    format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        method, target, host, USER_AGENT
+    )
+    .into_bytes()
+}
+
+/// sends the raw request and reads the response until the connection
+/// closes.
+async fn send(addr: &str, raw: &[u8], timeout: Duration) -> Result<Reply> {
+    let exchange = async {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(raw).await?;
+        let mut buf = vec![];
+        stream.take(MAX_REPLY).read_to_end(&mut buf).await?;
+        Reply::parse(&buf)
+    };
+    time::timeout(timeout, exchange)
+        .await
+        .map_err(|_| anyhow!("timed out"))?
+}
+
+/// probes the instance listening at the address, such as 127.0.0.1:8080.
+pub async fn probe(addr: &str, timeout: Duration) -> Result<Replies> {
+    let host = addr;
+    let missing = format!("/{:016x}.html", rand::thread_rng().gen::<u64>());
+    let probe = |raw: Vec<u8>| async move {
+        match send(addr, &raw, timeout).await {
+            Ok(reply) => Some(reply),
+            Err(e) => {
+                info!("probe of {} went unanswered: {}", addr, e);
+                None
+            }
+        }
+    };
+
+    let root = send(addr, &request("GET", "/", host), timeout)
+        .await
+        .map_err(|e| anyhow!("{} didn't answer GET /: {}", addr, e))?;
+    Ok(Replies {
+        root: Some(root),
+        head: probe(request("HEAD", "/", host)).await,
+        missing: probe(request("GET", &missing, host)).await,
+        favicon: probe(request("GET", "/favicon.ico", host)).await,
+        options: probe(request("OPTIONS", "*", host)).await,
+        method: probe(request("FOOBAR", "/", host)).await,
+        garbage: probe(b"GARBAGE\r\n\r\n".to_vec()).await,
+    })
+}
+
+/// the server family a Server header names.
+fn family(server: &str) -> Option<Family> {
+    let server = server.to_lowercase();
+    if server.contains("apache") {
+        Some(Family::Apache)
+    } else if server.contains("nginx") {
+        Some(Family::Nginx)
+    } else if server.contains("iis") {
+        Some(Family::Iis)
+    } else {
+        None
+    }
+}
+
+/// the server family whose stock error page the body is.
+fn page_family(body: &[u8]) -> Option<Family> {
+    let body = String::from_utf8_lossy(body);
+    if body.contains("<!DOCTYPE HTML PUBLIC \"-//IETF//DTD HTML 2.0//EN\">") {
+        Some(Family::Apache)
+    } else if body.contains("<center>nginx") {
+        Some(Family::Nginx)
+    } else if body.contains("content-container") {
+        Some(Family::Iis)
+    } else {
+        None
+    }
+}
+
+/// whether the names appear in the order the template writes them.
+/// Names the template doesn't order are skipped.
+fn in_order(names: &[&str], order: &[&str]) -> bool {
+    let positions = names
+        .iter()
+        .filter_map(|name| order.iter().position(|o| o.eq_ignore_ascii_case(name)))
+        .collect::<Vec<_>>();
+    positions.windows(2).all(|w| w[0] <= w[1])
+}
+
+/// Shodan's favicon hash: MurmurHash3 of the favicon's base64, wrapped at
+/// 76 characters as Python's base64.encodebytes does.
+pub fn favicon_hash(data: &[u8]) -> i32 {
+    let encoded = STANDARD.encode(data);
+    let mut wrapped = String::new();
+    for line in encoded.as_bytes().chunks(76) {
+        wrapped.push_str(std::str::from_utf8(line).unwrap_or_default());
+        wrapped.push('\n');
+    }
+    murmur3_32(wrapped.as_bytes(), 0) as i32
+}
+
+fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+    let scramble = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut h = seed;
+    let mut blocks = data.chunks_exact(4);
+    for block in &mut blocks {
+        let k = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        h = (h ^ scramble(k))
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe6546b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, b| (k << 8) | *b as u32);
+        h ^= scramble(k);
+    }
+
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^ (h >> 16)
+}
+
+/// checks the replies against each other and the server they claim to be.
+pub fn check(replies: &Replies, now: DateTime<Utc>) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut find = |probe, verdict, detail: String| {
+        findings.push(Finding {
+            probe,
+            verdict,
+            detail,
+        })
+    };
+    let answered = [
+        ("root", &replies.root),
+        ("head", &replies.head),
+        ("missing", &replies.missing),
+        ("favicon", &replies.favicon),
+        ("options", &replies.options),
+        ("method", &replies.method),
+        ("garbage", &replies.garbage),
+    ]
+    .into_iter()
+    .filter_map(|(probe, reply)| reply.as_ref().map(|r| (probe, r)))
+    .collect::<Vec<_>>();
+
+    let server = replies
+        .root
+        .as_ref()
+        .and_then(|r| r.header("Server"))
+        .unwrap_or_default();
+    let persona = family(server);
+    match persona {
+        Some(_) => find("root", Verdict::Info, format!("Server: {}", server)),
+        None => find(
+            "root",
+            Verdict::Tell,
+            format!("Server {:?} isn't a server httpot knows", server),
+        ),
+    }
+
+    for (probe, reply) in &answered {
+        let names = reply.names();
+        if reply.headers.iter().any(|(k, v)| {
+            k.to_lowercase().contains("httpot") || v.to_lowercase().contains("httpot")
+        }) || String::from_utf8_lossy(&reply.body)
+            .to_lowercase()
+            .contains("httpot")
+        {
+            find(probe, Verdict::Tell, "response names httpot".to_string());
+        }
+        match reply.header("Server") {
+            Some(s) if s == server => {}
+            other => find(
+                probe,
+                Verdict::Tell,
+                format!(
+                    "Server {:?} differs from / {:?}",
+                    other.unwrap_or_default(),
+                    server
+                ),
+            ),
+        }
+        if let Some(persona) = persona {
+            if !in_order(&names, Persona::from(persona).template().order) {
+                find(
+                    probe,
+                    Verdict::Tell,
+                    format!("headers {:?} aren't in {:?}'s order", names, persona),
+                );
+            }
+        }
+        match reply.header("Date").map(DateTime::parse_from_rfc2822) {
+            Some(Ok(date)) if (date.timestamp() - now.timestamp()).abs() <= MAX_SKEW_SECS => {}
+            Some(Ok(date)) => find(
+                probe,
+                Verdict::Tell,
+                format!("Date {} is far from the local clock", date),
+            ),
+            Some(Err(_)) => find(
+                probe,
+                Verdict::Tell,
+                format!("Date {:?} isn't an HTTP date", reply.header("Date")),
+            ),
+            None if reply.status < 500 => find(probe, Verdict::Tell, "no Date header".to_string()),
+            None => {}
+        }
+    }
+
+    if let Some(missing) = &replies.missing {
+        match (persona, page_family(&missing.body)) {
+            (_, _) if missing.status != 404 => find(
+                "missing",
+                Verdict::Tell,
+                format!("a path which can't exist was {}, not 404", missing.status),
+            ),
+            (Some(claimed), Some(page)) if claimed != page => find(
+                "missing",
+                Verdict::Tell,
+                format!("{:?} Server header with {:?}'s 404 page", claimed, page),
+            ),
+            (_, None) => find(
+                "missing",
+                Verdict::Info,
+                "404 page isn't any known server's stock page".to_string(),
+            ),
+            _ => find("missing", Verdict::Ok, "404 page matches".to_string()),
+        }
+    }
+
+    if let (Some(root), Some(head)) = (&replies.root, &replies.head) {
+        if !head.body.is_empty() {
+            find(
+                "head",
+                Verdict::Tell,
+                format!("HEAD answered with a {} byte body", head.body.len()),
+            );
+        }
+        if root.status == head.status
+            && root.header("Content-Length") != head.header("Content-Length")
+        {
+            find(
+                "head",
+                Verdict::Tell,
+                format!(
+                    "HEAD's Content-Length {:?} differs from GET's {:?}",
+                    head.header("Content-Length"),
+                    root.header("Content-Length")
+                ),
+            );
+        }
+    }
+
+    if let Some(favicon) = &replies.favicon {
+        match favicon.status {
+            200 => find(
+                "favicon",
+                Verdict::Info,
+                format!("Shodan favicon hash {}", favicon_hash(&favicon.body)),
+            ),
+            status => find("favicon", Verdict::Info, format!("no favicon, {}", status)),
+        }
+    }
+    if let Some(options) = &replies.options {
+        find(
+            "options",
+            Verdict::Info,
+            format!(
+                "{}, Allow: {}",
+                options.status,
+                options.header("Allow").unwrap_or("-")
+            ),
+        );
+    }
+    if let Some(method) = &replies.method {
+        find(
+            "method",
+            Verdict::Info,
+            format!("unknown method answered {}", method.status),
+        );
+    }
+    match &replies.garbage {
+        Some(garbage) if garbage.status != 400 => find(
+            "garbage",
+            Verdict::Tell,
+            format!("a request which isn't HTTP was {}, not 400", garbage.status),
+        ),
+        Some(_) => {}
+        None => find(
+            "garbage",
+            Verdict::Tell,
+            "a request which isn't HTTP went unanswered".to_string(),
+        ),
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn reply(status: u16, headers: &[(&str, &str)], body: &str) -> Option<Reply> {
+        Some(Reply {
+            status,
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+        })
+    }
+
+    #[test]
+    fn test_favicon_hash() {
+        assert_eq!(0, murmur3_32(b"", 0));
+        assert_eq!(613153351, murmur3_32(b"hello", 0) as i32);
+        assert_eq!(murmur3_32(b"aGVsbG8=\n", 0) as i32, favicon_hash(b"hello"));
+    }
+
+    #[test]
+    fn test_check() {
+        let now = Utc::now();
+        let date = crate::http::date::format(now);
+        let apache = [
+            ("Date", date.as_str()),
+            ("Server", "Apache/2.2.22 (Ubuntu)"),
+            ("Content-Length", "5"),
+            ("Connection", "close"),
+            ("Content-Type", "text/html"),
+        ];
+        let apache_404 = "<!DOCTYPE HTML PUBLIC \"-//IETF//DTD HTML 2.0//EN\">\n<html>";
+        let mut replies = Replies {
+            root: reply(200, &apache, "hello"),
+            head: reply(200, &apache, ""),
+            missing: reply(404, &apache, apache_404),
+            garbage: reply(400, &apache, apache_404),
+            ..Default::default()
+        };
+        let tells = |replies: &Replies| {
+            check(replies, now)
+                .into_iter()
+                .filter(|f| f.verdict == Verdict::Tell)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(Vec::<Finding>::new(), tells(&replies));
+
+        replies.missing = reply(404, &apache, "<hr><center>nginx</center>");
+        replies.head = reply(
+            200,
+            &[
+                ("Server", "Apache/2.2.22 (Ubuntu)"),
+                ("Date", date.as_str()),
+                ("Content-Length", "5"),
+            ],
+            "",
+        );
+        replies.garbage = reply(500, &[("Server", "httpot/0.1.0")], "");
+        let found = tells(&replies)
+            .into_iter()
+            .map(|f| (f.probe, f.detail))
+            .collect::<Vec<_>>();
+        assert!(found.contains(&(
+            "missing",
+            "Apache Server header with Nginx's 404 page".to_string()
+        )));
+        assert!(found
+            .iter()
+            .any(|(p, d)| *p == "head" && d.contains("order")));
+        assert!(found.contains(&("garbage", "response names httpot".to_string())));
+        assert!(found
+            .iter()
+            .any(|(p, d)| *p == "garbage" && d.contains("not 400")));
+    }
+
+    #[test]
+    fn test_parse() {
+        let reply =
+            Reply::parse(b"HTTP/1.1 404 Not Found\r\nServer: nginx\r\nX-A:b\r\n\r\nbody").unwrap();
+        assert_eq!(404, reply.status);
+        assert_eq!(Some("b"), reply.header("x-a"));
+        assert_eq!(b"body".to_vec(), reply.body);
+        assert!(Reply::parse(b"HTTP/1.1 200 OK\r\n").is_err());
+    }
+}
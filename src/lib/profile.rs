@@ -0,0 +1,324 @@
+//! Per-source profiles of what requesters did, scored by how far they
+//! engaged with the honeypot. Scores show which baits actually hook
+//! attackers rather than just being crawled.
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    honeypot::xmlrpc,
+    http::request::{Method, Request},
+};
+
+// bounds memory used by profiles; the least recently seen is evicted
+const MAX_PROFILES: usize = 100_000;
+// paths remembered per profile for counting pages visited
+const MAX_PATHS: usize = 1_000;
+// bodies at least this large are considered uploads
+const UPLOAD_SIZE: usize = 4 * 1024;
+
+const PAGE_WEIGHT: u64 = 1;
+const CREDENTIAL_WEIGHT: u64 = 10;
+const UPLOAD_WEIGHT: u64 = 10;
+const MINUTE_WEIGHT: u64 = 2;
+// time spent only counts this far, so idle revisits don't dominate
+const MAX_MINUTES: u64 = 60;
+
+// matched as substrings of lowercased field names, in order of preference,
+// so a password is taken over a CSRF token sent with it
+const CREDENTIAL_FIELDS: &[&str] = &["pass", "pwd", "secret", "token"];
+// fields naming who the credentials are for, matched the same way
+const USER_FIELDS: &[&str] = &["user", "log", "email"];
+
+/// What a single request did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Activity {
+    pub credentials: bool,
+    pub upload: bool,
+}
+
+impl Activity {
+    pub fn of(req: &Request) -> Self {
+        Self {
+            credentials: submits_credentials(req),
+            upload: uploads(req),
+        }
+    }
+
+    /// the engagement score of a session consisting of only this activity.
+    pub fn score(&self) -> u64 {
+        CREDENTIAL_WEIGHT * self.credentials as u64 + UPLOAD_WEIGHT * self.upload as u64
+    }
+}
+
+fn submits_credentials(req: &Request) -> bool {
+    let is_credential = |k: &str| {
+        let k = k.to_lowercase();
+        CREDENTIAL_FIELDS.iter().any(|f| k.contains(f))
+    };
+
+    if req.headers.get("Authorization").is_some() {
+        return true;
+    }
+
+    if req.url.query_pairs().any(|(k, _)| is_credential(&k)) {
+        return true;
+    }
+    if !xmlrpc::credentials(req).is_empty() {
+        return true;
+    }
+
+    if let Ok(serde_json::Value::Object(fields)) = serde_json::from_slice(&req.body) {
+        return fields.keys().any(|k| is_credential(k));
+    }
+
+    url::form_urlencoded::parse(&req.body).any(|(k, v)| !v.is_empty() && is_credential(&k))
+}
+
+/// the user and secret pairs the request submitted: Basic authorization,
+/// then form, JSON, or query fields, then any tried over XML-RPC. A secret
+/// without a user field is paired with an empty user.
+pub fn credentials(req: &Request) -> Vec<(String, String)> {
+    let matches = |fields: &[&str], k: &str| {
+        let k = k.to_lowercase();
+        fields.iter().any(|f| k.contains(f))
+    };
+
+    let mut pairs: Vec<(String, String)> = req.url.query_pairs().into_owned().collect();
+    match serde_json::from_slice(&req.body) {
+        Ok(serde_json::Value::Object(fields)) => pairs.extend(
+            fields
+                .into_iter()
+                .filter_map(|(k, v)| Some((k, v.as_str()?.to_string()))),
+        ),
+        _ => pairs.extend(url::form_urlencoded::parse(&req.body).into_owned()),
+    }
+
+    let secret = CREDENTIAL_FIELDS
+        .iter()
+        .find_map(|f| {
+            pairs
+                .iter()
+                .find(|(k, v)| !v.is_empty() && matches(&[f], k))
+        })
+        .map(|(_, v)| v.clone());
+    let user = pairs
+        .iter()
+        .find(|(k, v)| !v.is_empty() && matches(USER_FIELDS, k) && !matches(CREDENTIAL_FIELDS, k))
+        .map(|(_, v)| v.clone())
+        .unwrap_or_default();
+
+    req.basic_auth()
+        .into_iter()
+        .chain(secret.map(|secret| (user, secret)))
+        .chain(xmlrpc::credentials(req))
+        .collect()
+}
+
+fn uploads(req: &Request) -> bool {
+    let multipart = req
+        .headers
+        .get("Content-Type")
+        .into_iter()
+        .flatten()
+        .any(|v| v.to_lowercase().starts_with("multipart/form-data"));
+
+    req.method == Method::PUT || multipart || req.body.len() >= UPLOAD_SIZE
+}
+
+/// Everything seen from a single source.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub ip: IpAddr,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub requests: u64,
+    pub paths: HashSet<String>,
+    pub credentials_submitted: u64,
+    pub uploads: u64,
+}
+
+impl Profile {
+    fn new(ip: IpAddr, now: DateTime<Utc>) -> Self {
+        Self {
+            ip,
+            first_seen: now,
+            last_seen: now,
+            requests: 0,
+            paths: HashSet::new(),
+            credentials_submitted: 0,
+            uploads: 0,
+        }
+    }
+
+    fn record(&mut self, req: &Request, activity: Activity, now: DateTime<Utc>) {
+        self.last_seen = now;
+        self.requests += 1;
+        if self.paths.len() < MAX_PATHS {
+            self.paths.insert(req.url.path().to_string());
+        }
+        self.credentials_submitted += activity.credentials as u64;
+        self.uploads += activity.upload as u64;
+    }
+
+    /// engagement score: pages beyond the landing page, credentials
+    /// submitted, payloads uploaded, and minutes spent.
+    pub fn score(&self) -> u64 {
+        engagement(
+            self.paths.len() as u64,
+            self.credentials_submitted,
+            self.uploads,
+            self.last_seen - self.first_seen,
+        )
+    }
+}
+
+/// the engagement score of a source which visited the distinct paths,
+/// submitted credentials and uploads, and was seen over the duration.
+pub fn engagement(paths: u64, credentials: u64, uploads: u64, seen: chrono::Duration) -> u64 {
+    let pages = paths.saturating_sub(1);
+    let minutes = (seen.num_minutes().max(0) as u64).min(MAX_MINUTES);
+
+    PAGE_WEIGHT * pages
+        + CREDENTIAL_WEIGHT * credentials
+        + UPLOAD_WEIGHT * uploads
+        + MINUTE_WEIGHT * minutes
+}
+
+/// In-memory profiles keyed by requester address.
+#[derive(Debug, Default)]
+pub struct ProfileStore {
+    profiles: Mutex<HashMap<IpAddr, Profile>>,
+}
+
+impl ProfileStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// records the request against its source's profile, returning the
+    /// updated profile.
+    pub fn record(&self, req: &Request, activity: Activity) -> Profile {
+        self.record_at(req, activity, Utc::now())
+    }
+
+    fn record_at(&self, req: &Request, activity: Activity, now: DateTime<Utc>) -> Profile {
+        let ip = req.requester_ip();
+        let mut profiles = self.profiles.lock().unwrap();
+
+        if !profiles.contains_key(&ip) && profiles.len() >= MAX_PROFILES {
+            if let Some(oldest) = profiles.values().min_by_key(|p| p.last_seen).map(|p| p.ip) {
+                profiles.remove(&oldest);
+            }
+        }
+
+        let profile = profiles.entry(ip).or_insert_with(|| Profile::new(ip, now));
+        profile.record(req, activity, now);
+        profile.clone()
+    }
+
+    pub fn get(&self, ip: &IpAddr) -> Option<Profile> {
+        self.profiles.lock().unwrap().get(ip).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use chrono::Duration;
+
+    #[test]
+    fn test_credentials() {
+        let login = Request::stub(Method::POST, "/wp-login.php", "log=admin&pwd=hunter2");
+        assert_eq!(
+            vec![("admin".to_string(), "hunter2".to_string())],
+            credentials(&login)
+        );
+
+        let json = Request::stub(Method::POST, "/api/login", r#"{"password": "root"}"#);
+        assert_eq!(
+            vec![(String::new(), "root".to_string())],
+            credentials(&json)
+        );
+
+        let xmlrpc = Request::stub(
+            Method::POST,
+            "/xmlrpc.php",
+            "<methodCall><methodName>wp.getUsersBlogs</methodName><params>\
+             <param><value>admin</value></param><param><value>letmein</value></param>\
+             </params></methodCall>",
+        );
+        assert_eq!(
+            vec![("admin".to_string(), "letmein".to_string())],
+            credentials(&xmlrpc)
+        );
+        assert!(Activity::of(&xmlrpc).credentials);
+
+        let mut basic = Request::stub(Method::GET, "/?token=", "");
+        basic.headers.add("Authorization", "Basic YWRtaW46YWRtaW4=");
+        assert_eq!(
+            vec![("admin".to_string(), "admin".to_string())],
+            credentials(&basic)
+        );
+    }
+
+    #[test]
+    fn test_activity() {
+        let login = Request::stub(Method::POST, "/wp-login.php", "log=admin&pwd=hunter2");
+        assert_eq!(
+            Activity {
+                credentials: true,
+                upload: false
+            },
+            Activity::of(&login)
+        );
+
+        let json = Request::stub(
+            Method::POST,
+            "/api/login",
+            r#"{"username":"a","password":"b"}"#,
+        );
+        assert!(Activity::of(&json).credentials);
+
+        let put = Request::stub(Method::PUT, "/shell.jsp", "<% Runtime.exec() %>");
+        assert!(Activity::of(&put).upload);
+
+        let get = Request::stub(Method::GET, "/", "");
+        assert_eq!(Activity::default(), Activity::of(&get));
+    }
+
+    #[test]
+    fn test_engagement_score() {
+        let store = ProfileStore::new();
+        let start = Utc::now();
+
+        let landing = Request::stub(Method::GET, "/", "");
+        let profile = store.record_at(&landing, Activity::of(&landing), start);
+        assert_eq!(0, profile.score());
+
+        let admin = Request::stub(Method::GET, "/admin/", "");
+        store.record_at(&admin, Activity::of(&admin), start);
+        let login = Request::stub(Method::POST, "/admin/login", "user=root&password=toor");
+        let profile = store.record_at(&login, Activity::of(&login), start + Duration::minutes(3));
+
+        assert_eq!(3, profile.requests);
+        assert_eq!(
+            2 * PAGE_WEIGHT + CREDENTIAL_WEIGHT + 3 * MINUTE_WEIGHT,
+            profile.score()
+        );
+        assert_eq!(1, store.len());
+    }
+}
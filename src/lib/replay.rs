@@ -0,0 +1,315 @@
+//! Stored traffic read back for replay: pcap captures and HAR archives are
+//! turned into the raw requests they carried and the responses recorded
+//! for them, so the current configuration's responses can be diffed
+//! against what was sent at the time.
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::Path,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::{capture, http::response::Response, prelude::*};
+
+/// headers which differ between otherwise identical responses.
+const VOLATILE: &[&str] = &["date"];
+
+/// A request as it was received, and the response recorded for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exchange {
+    /// where the exchange was read from
+    pub source: String,
+    pub remote: SocketAddr,
+    /// when the request arrived, if it was recorded
+    pub at: Option<DateTime<Utc>>,
+    pub request: Vec<u8>,
+    pub response: Option<Recorded>,
+}
+
+/// A response as it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recorded {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// reads the exchanges stored at the path: pcaps by their extension,
+/// anything else as a HAR archive.
+pub fn load(path: &Path) -> Result<Vec<Exchange>> {
+    let data =
+        std::fs::read(path).map_err(|e| anyhow!("failed to read {}: {}", path.display(), e))?;
+    let name = path.display().to_string();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("pcap" | "cap") => from_pcap(&name, &data),
+        _ => from_har(&name, &data),
+    }
+    .map_err(|e| anyhow!("failed to load {}: {}", name, e))
+}
+
+/// an exchange per connection which sent anything.
+fn from_pcap(name: &str, data: &[u8]) -> Result<Vec<Exchange>> {
+    Ok(capture::read_pcap(data)?
+        .into_iter()
+        .filter(|c| !c.inbound.is_empty())
+        .enumerate()
+        .map(|(i, c)| Exchange {
+            source: format!("{}#{}", name, i),
+            remote: c.client,
+            at: Some(c.opened),
+            request: c.inbound,
+            response: parse_response(&c.outbound),
+        })
+        .collect())
+}
+
+fn from_har(name: &str, data: &[u8]) -> Result<Vec<Exchange>> {
+    let har: Value = serde_json::from_slice(data)?;
+    let entries = har["log"]["entries"]
+        .as_array()
+        .ok_or_else(|| anyhow!("no log entries"))?;
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let source = format!("{}#{}", name, i);
+            let ip = entry["_clientIPAddress"]
+                .as_str()
+                .and_then(|ip| ip.parse().ok())
+                .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+            Ok(Exchange {
+                request: har_request(&entry["request"])
+                    .map_err(|e| anyhow!("{}: {}", source, e))?,
+                response: har_response(&entry["response"]),
+                remote: SocketAddr::new(ip, 0),
+                at: entry["startedDateTime"]
+                    .as_str()
+                    .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+                    .map(|at| at.with_timezone(&Utc)),
+                source,
+            })
+        })
+        .collect()
+}
+
+/// the request an entry describes, as it would have arrived.
+fn har_request(req: &Value) -> Result<Vec<u8>> {
+    let method = req["method"]
+        .as_str()
+        .ok_or_else(|| anyhow!("request has no method"))?;
+    let url: url::Url = req["url"]
+        .as_str()
+        .ok_or_else(|| anyhow!("request has no url"))?
+        .parse()?;
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+
+    let mut raw = format!(
+        "{} {} {}\r\n",
+        method,
+        target,
+        req["httpVersion"].as_str().unwrap_or("HTTP/1.1")
+    );
+    for (name, value) in name_values(&req["headers"]) {
+        raw.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    raw.push_str("\r\n");
+    raw.push_str(req["postData"]["text"].as_str().unwrap_or_default());
+    Ok(raw.into_bytes())
+}
+
+fn har_response(resp: &Value) -> Option<Recorded> {
+    let content = &resp["content"];
+    let text = content["text"].as_str().unwrap_or_default();
+    let body = match content["encoding"].as_str() {
+        Some("base64") => STANDARD.decode(text).ok()?,
+        _ => text.as_bytes().to_vec(),
+    };
+
+    Some(Recorded {
+        status: resp["status"].as_u64()? as u16,
+        headers: name_values(&resp["headers"]),
+        body,
+    })
+}
+
+fn name_values(list: &Value) -> Vec<(String, String)> {
+    list.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|nv| {
+            Some((
+                nv["name"].as_str()?.to_string(),
+                nv["value"].as_str()?.to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// the first response in bytes sent to a client, if they hold one.
+pub fn parse_response(raw: &[u8]) -> Option<Recorded> {
+    let end = raw.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&raw[..end]).ok()?;
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    let headers = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+        .collect();
+
+    Some(Recorded {
+        status,
+        headers,
+        body: raw[end + 4..].to_vec(),
+    })
+}
+
+/// how the response differs from the one recorded, a line per difference.
+/// Volatile headers like Date are ignored.
+pub fn diff(recorded: &Recorded, resp: &Response) -> Vec<String> {
+    let mut diffs = vec![];
+    if recorded.status != resp.code() {
+        diffs.push(format!("status {} => {}", recorded.status, resp.code()));
+    }
+
+    // repeated headers are sent as one, so they're compared that way
+    let normalize = |headers: &mut dyn Iterator<Item = (&String, &String)>| {
+        let mut joined = BTreeMap::<String, Vec<String>>::new();
+        for (k, v) in headers {
+            joined.entry(k.to_lowercase()).or_default().push(v.clone());
+        }
+        joined
+            .into_iter()
+            .filter(|(k, _)| !VOLATILE.contains(&k.as_str()))
+            .map(|(k, values)| (k, values.join(", ")))
+            .collect::<Vec<_>>()
+    };
+    let before = normalize(&mut recorded.headers.iter().map(|(k, v)| (k, v)));
+    let after = normalize(
+        &mut resp
+            .headers()
+            .iter()
+            .flat_map(|(k, values)| values.iter().map(move |v| (k, v))),
+    );
+    for header in before.iter().filter(|h| !after.contains(h)) {
+        diffs.push(format!("- {}: {}", header.0, header.1));
+    }
+    for header in after.iter().filter(|h| !before.contains(h)) {
+        diffs.push(format!("+ {}: {}", header.0, header.1));
+    }
+
+    if recorded.body != resp.body() {
+        let at = recorded
+            .body
+            .iter()
+            .zip(resp.body())
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| recorded.body.len().min(resp.body().len()));
+        diffs.push(format!(
+            "body {} => {} bytes, first differing at byte {}",
+            recorded.body.len(),
+            resp.body().len(),
+            at
+        ));
+    }
+    diffs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{capture::Capture, http::response::ResponseBuilder};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_load() {
+        let har = serde_json::json!({"log": {"entries": [{
+            "request": {
+                "method": "POST",
+                "url": "http://example.com/login?next=%2Fadmin",
+                "httpVersion": "HTTP/1.1",
+                "headers": [{"name": "Content-Length", "value": "9"}],
+                "postData": {"mimeType": "", "text": "user=root"},
+            },
+            "response": {
+                "status": 200,
+                "headers": [{"name": "Server", "value": "nginx"}],
+                "content": {"text": "//4=", "encoding": "base64"},
+            },
+            "_clientIPAddress": "203.0.113.7",
+            "startedDateTime": "2026-10-17T08:00:00.000Z",
+        }]}});
+        let exchanges = from_har("a.har", &serde_json::to_vec(&har).unwrap()).unwrap();
+        assert_eq!(
+            vec![Exchange {
+                source: "a.har#0".to_string(),
+                remote: "203.0.113.7:0".parse().unwrap(),
+                at: Some("2026-10-17T08:00:00Z".parse().unwrap()),
+                request:
+                    b"POST /login?next=%2Fadmin HTTP/1.1\r\nContent-Length: 9\r\n\r\nuser=root"
+                        .to_vec(),
+                response: Some(Recorded {
+                    status: 200,
+                    headers: vec![("Server".to_string(), "nginx".to_string())],
+                    body: vec![0xff, 0xfe],
+                }),
+            }],
+            exchanges
+        );
+
+        let capture = Capture::new(
+            "203.0.113.7:51234".parse().unwrap(),
+            "192.0.2.1:80".parse().unwrap(),
+            1024,
+        );
+        capture.inbound(b"GET / HTTP/1.1\r\n\r\n");
+        capture.outbound(b"HTTP/1.1 404 Not Found\r\nServer: nginx\r\n\r\ngone");
+        let exchanges = from_pcap("a.pcap", &capture.to_pcap()).unwrap();
+        assert_eq!(1, exchanges.len());
+        assert_eq!(b"GET / HTTP/1.1\r\n\r\n".to_vec(), exchanges[0].request);
+        let recorded = exchanges[0].response.as_ref().unwrap();
+        assert_eq!(404, recorded.status);
+        assert_eq!(b"gone".to_vec(), recorded.body);
+    }
+
+    #[tokio::test]
+    async fn test_diff() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let conn = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let resp = ResponseBuilder::ok(Arc::new(conn))
+            .add_header("X-Powered-By", "PHP/8.1")
+            .add_header("Date", "Sat, 17 Oct 2026 00:00:00 GMT")
+            .body("hello")
+            .build()
+            .unwrap();
+
+        let recorded = parse_response(&resp.as_bytes().unwrap()).unwrap();
+        assert_eq!(Vec::<String>::new(), diff(&recorded, &resp));
+
+        let raw = resp
+            .to_string()
+            .unwrap()
+            .replace("200 OK", "404 Not Found")
+            .replace("PHP/8.1", "PHP/7.4")
+            .replace("hello", "hallo");
+        let recorded = parse_response(raw.as_bytes()).unwrap();
+        assert_eq!(
+            vec![
+                "status 404 => 200",
+                "- x-powered-by: PHP/7.4",
+                "+ x-powered-by: PHP/8.1",
+                "body 5 => 5 bytes, first differing at byte 1",
+            ],
+            diff(&recorded, &resp)
+        );
+    }
+}
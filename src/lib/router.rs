@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+
+use crate::{
+    fs::{
+        self,
+        fake::{FakeFile, Tree},
+    },
+    honeypot::{bait, php::Php},
+    http::{
+        date,
+        request::{Method, Request},
+        response::{Response, ResponseBuilder, StatusCode},
+        stock_responses::*,
+    },
+    locale::Language,
+    persona::Persona,
+    prelude::*,
+};
+
+/// routes the request. Generated content is derived from the seed, and
+/// fake directory trees are shaped by the tree, and served only with
+/// fake_fs.
+pub fn router(
+    conn: TcpStream,
+    r: &Request,
+    persona: Persona,
+    php: &Php,
+    seed: &str,
+    tree: Tree,
+    fake_fs: bool,
+) -> Result<Response> {
+    // invalid methods
+    match r.method {
+        Method::GET => (),
+        Method::HEAD => (),
+        Method::OPTIONS => return Ok(persona.options(conn, r).build()?.with_route("options")),
+        _ => {
+            let mut resp = persona.error(conn, StatusCode::MethodNotAllowed, Some(r));
+            if let Some(allow) = persona.allow() {
+                resp.add_header("Allow", allow);
+            }
+            return Ok(resp.build()?.with_route("method_not_allowed"));
+        }
+    };
+
+    let (route, resp) = if php.is_easter_egg(r) {
+        ("php_easter_egg", php.easter_egg(conn, r)?)
+    } else {
+        match r.url.path() {
+            "/hello" => ("hello", hello_world(conn)),
+            "/favicon.ico" => (
+                "favicon",
+                persona.error(conn, StatusCode::NotFound, Some(r)).build()?,
+            ),
+            _ if !fake_fs => (
+                "not_found",
+                persona.error(conn, StatusCode::NotFound, Some(r)).build()?,
+            ),
+            path if path.ends_with("/") => (
+                "listing",
+                fake_directory_tree(conn, r, persona, seed, tree)?,
+            ),
+            path if bait::is_bait(path) => ("bait", bait_file(conn, path, seed, tree)?),
+            path if checksum_of(path).is_some() => {
+                ("checksum", checksum_file(conn, r, persona, seed, tree)?)
+            }
+            path => match fs::fake::gen_fake_file(seed, path, tree) {
+                Some(file) => ("file", fake_file(conn, r, seed, file)?),
+                // directories are redirected to with their slash, as
+                // Apache's mod_dir does
+                None if fs::fake::is_fake_dir(seed, path, tree) => {
+                    let mut location = r.url.clone();
+                    location.set_path(&format!("{}/", path));
+                    let resp = persona
+                        .redirect(conn, StatusCode::MovedPermanently, location.as_str(), r)
+                        .build()?;
+                    ("directory", resp)
+                }
+                None => (
+                    "not_found",
+                    persona.error(conn, StatusCode::NotFound, Some(r)).build()?,
+                ),
+            },
+        }
+    };
+
+    let resp = resp.with_route(route);
+    match r.method {
+        Method::HEAD => Ok(resp.without_body()),
+        _ => Ok(resp),
+    }
+}
+
+pub const SEED: &str = "seedv1";
+
+fn bait_file(conn: TcpStream, path: &str, seed: &str, tree: Tree) -> Result<Response> {
+    let (content_type, body) =
+        bait::file(seed, path).ok_or_else(|| anyhow!("{} is not a bait file", path))?;
+
+    let mut resp = ResponseBuilder::ok(Arc::new(conn));
+    resp.body(body).add_header("Content-Type", content_type);
+    // bait is served anywhere, but is only listed in some directories
+    if let Some(file) = fs::fake::gen_fake_file(seed, path, tree) {
+        resp.add_header("Last-Modified", date::format(file.modified_at));
+    }
+    Ok(resp.build()?)
+}
+
+/// Serves a listed file. HEAD is answered without generating the
+/// contents, which can be tens of megabytes.
+fn fake_file(conn: TcpStream, req: &Request, seed: &str, file: FakeFile) -> Result<Response> {
+    let body = match req.method {
+        Method::HEAD => vec![],
+        _ => fs::fake::gen_fake_content(seed, req.url.path(), file.size),
+    };
+
+    Ok(ResponseBuilder::ok(Arc::new(conn))
+        .body(body)
+        .content_length(file.size)
+        .add_header("Content-Type", "application/octet-stream")
+        .add_header("Last-Modified", date::format(file.modified_at))
+        .build()?)
+}
+
+/// the file a checksum sidecar path is for and the digest it holds.
+fn checksum_of(path: &str) -> Option<(&str, &'static str)> {
+    [".md5", ".sha256"]
+        .into_iter()
+        .find_map(|ext| Some((path.strip_suffix(ext)?, ext)))
+}
+
+/// Serves a `.md5` or `.sha256` sidecar in the format of md5sum and
+/// sha256sum, so mirrors which verify downloads find them intact.
+fn checksum_file(
+    conn: TcpStream,
+    req: &Request,
+    persona: Persona,
+    seed: &str,
+    tree: Tree,
+) -> Result<Response> {
+    let (path, ext) = checksum_of(req.url.path())
+        .ok_or_else(|| anyhow!("{} is not a checksum", req.url.path()))?;
+    let listed = fs::fake::gen_fake_file(seed, path, tree);
+    let contents = match (bait::file(seed, path), &listed) {
+        (Some((_, body)), _) => body,
+        (None, Some(file)) => fs::fake::gen_fake_content(seed, path, file.size),
+        (None, None) => {
+            return Ok(persona
+                .error(conn, StatusCode::NotFound, Some(req))
+                .build()?)
+        }
+    };
+
+    let digest = match ext {
+        ".md5" => format!("{:x}", md5::compute(&contents)),
+        _ => format!("{:x}", Sha256::digest(&contents)),
+    };
+    let name = path.rsplit('/').next().unwrap_or_default();
+
+    let mut resp = ResponseBuilder::ok(Arc::new(conn));
+    resp.body(format!("{}  {}\n", digest, name))
+        .add_header("Content-Type", "text/plain");
+    if let Some(file) = listed {
+        resp.add_header("Last-Modified", date::format(file.modified_at));
+    }
+    Ok(resp.build()?)
+}
+
+pub fn fake_directory_tree(
+    conn: TcpStream,
+    req: &Request,
+    persona: Persona,
+    seed: &str,
+    tree: Tree,
+) -> Result<Response> {
+    let lang = persona.language(Some(req));
+    let body = fs::fake::gen_fake_listing(seed, req.url.path(), lang, tree);
+
+    let mut resp = ResponseBuilder::ok(Arc::new(conn));
+    resp.body(body).add_header("Content-Type", "text/html");
+    if lang != Language::En {
+        resp.add_header("Content-Language", lang.tag())
+            .add_header("Vary", "Accept-Language");
+    }
+    Ok(resp.build()?)
+}
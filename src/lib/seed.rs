@@ -0,0 +1,187 @@
+//! Content seeds. Fake listings, honeytokens, and pages are generated from
+//! a seed, so mixing the requester's network into it shows each source a
+//! consistent site of its own. Content showing up from a different
+//! network than the one it was generated for reveals actors sharing data.
+use std::net::IpAddr;
+
+use serde::Deserialize;
+use url::Host;
+
+use crate::{
+    fs::fake::{Theme, Timeline, Tree},
+    http::request::Request,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VariationConfig {
+    /// prefix length of the IPv4 networks which see the same content, or
+    /// 0 for every source to see the same content
+    pub ipv4_prefix: u8,
+    /// prefix length of the IPv6 networks which see the same content
+    pub ipv6_prefix: u8,
+    /// hours between changes to fake directory trees, or 0 for trees
+    /// which never change
+    pub cadence_hours: u64,
+    /// mix the requested hostname in too, so each name pointed at the
+    /// honeypot is a different site
+    pub per_host: bool,
+}
+
+impl Default for VariationConfig {
+    fn default() -> Self {
+        Self {
+            ipv4_prefix: 24,
+            ipv6_prefix: 48,
+            cadence_hours: 24,
+            per_host: false,
+        }
+    }
+}
+
+/// Derives the seed used for content shown to a request.
+#[derive(Debug, Clone)]
+pub struct Seeder {
+    seed: String,
+    config: VariationConfig,
+    theme: Theme,
+    tarpit: bool,
+}
+
+impl Seeder {
+    pub fn new(seed: &str, config: VariationConfig) -> Self {
+        Self {
+            seed: seed.to_string(),
+            config,
+            theme: Theme::default(),
+            tarpit: false,
+        }
+    }
+
+    /// generates fake trees in the theme.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// lists tarpit files in fake trees.
+    pub fn with_tarpit(mut self, tarpit: bool) -> Self {
+        self.tarpit = tarpit;
+        self
+    }
+
+    /// the global seed, shared by every source.
+    pub fn seed(&self) -> &str {
+        &self.seed
+    }
+
+    /// how fake directory trees look and change over time.
+    pub fn tree(&self) -> Tree {
+        Tree {
+            theme: self.theme,
+            tarpit: self.tarpit,
+            timeline: Timeline {
+                cadence_hours: self.config.cadence_hours,
+            },
+        }
+    }
+
+    /// the network content is varied by for the address.
+    pub fn network(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(ip) => {
+                let prefix = self.config.ipv4_prefix.min(32) as u32;
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & mask).into())
+            }
+            IpAddr::V6(ip) => {
+                let prefix = self.config.ipv6_prefix.min(128) as u32;
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & mask).into())
+            }
+        }
+    }
+
+    /// the seed for content shown to the request.
+    pub fn for_request(&self, req: &Request) -> String {
+        let seed = format!("{}/{}", self.seed, self.network(req.requester_ip()));
+        match (self.config.per_host, req.url.host()) {
+            // addresses aren't sites, so requests by IP share the default
+            (true, Some(Host::Domain(host))) => {
+                format!("{}/{}", seed, host.trim_end_matches('.'))
+            }
+            _ => seed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::http::request::Method;
+
+    #[test]
+    fn test_network() {
+        let seeder = Seeder::new("seed", VariationConfig::default());
+        let cases = vec![
+            ("203.0.113.77", "203.0.113.0"),
+            ("2001:db8:1234:5678::1", "2001:db8:1234::"),
+        ];
+        for (ip, network) in cases {
+            assert_eq!(
+                network.parse::<IpAddr>().unwrap(),
+                seeder.network(ip.parse().unwrap())
+            );
+        }
+
+        let global = Seeder::new(
+            "seed",
+            VariationConfig {
+                ipv4_prefix: 0,
+                ipv6_prefix: 0,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            global.network("203.0.113.77".parse().unwrap()),
+            global.network("198.51.100.1".parse().unwrap())
+        );
+    }
+
+    fn stub_request(url: &str) -> Request {
+        let mut req = Request::stub(Method::GET, "/", "");
+        req.url = url.parse().unwrap();
+        req.remote_ip = "203.0.113.77:62012".parse().unwrap();
+        req
+    }
+
+    #[test]
+    fn test_per_host() {
+        let seeder = Seeder::new(
+            "seed",
+            VariationConfig {
+                per_host: true,
+                ..Default::default()
+            },
+        );
+        let admin = seeder.for_request(&stub_request("http://admin.example.com/"));
+        assert_ne!(
+            admin,
+            seeder.for_request(&stub_request("http://files.example.com/"))
+        );
+        assert_eq!(
+            admin,
+            seeder.for_request(&stub_request("http://ADMIN.example.com.:8080/"))
+        );
+        assert_eq!(
+            seeder.for_request(&stub_request("http://192.0.2.1/")),
+            seeder.for_request(&stub_request("http://[2001:db8::1]/"))
+        );
+
+        let shared = Seeder::new("seed", VariationConfig::default());
+        assert_eq!(
+            shared.for_request(&stub_request("http://admin.example.com/")),
+            shared.for_request(&stub_request("http://files.example.com/"))
+        );
+    }
+}
@@ -0,0 +1,1177 @@
+//! Serving a config: the chain every listener shares, and the loop which
+//! answers each of their connections. The binary serves through
+//! `Honeypot`, as can services embedding a honeypot port beside their own.
+use std::{
+    future::Future,
+    iter,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use chrono::Utc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+#[cfg(feature = "cluster")]
+use crate::cluster::Cluster;
+#[cfg(feature = "cluster")]
+use std::net::IpAddr;
+
+use crate::{
+    canonical::Canonicalize,
+    capture::{Capture, CaptureConfig, Tee},
+    classify,
+    config::{Config, ListenerConfig},
+    dedup::{Dedup, Verdict},
+    display,
+    enrich::Enricher,
+    eventlog::{Event, EventLog},
+    fetch::{self, Fetcher},
+    fingerprint::Fingerprint,
+    fs::overlay::Overlays,
+    har::HarRecorder,
+    honeypot::{
+        api::FakeApi,
+        appserver::AppServers,
+        c2::C2Decoys,
+        dbadmin::DbAdmins,
+        deserialize::Deserialization,
+        favicon::Favicon,
+        forge::GitHosting,
+        frameworks::FrameworkExploits,
+        mailer::Mailers,
+        monitoring::{Grafana, Nagios, Prometheus, Zabbix},
+        php::Php,
+        redirect::Redirects,
+        route::Routes,
+        s3::ObjectStore,
+        script::Scripts,
+        search::Search,
+        shell::{self, ShellEmulation},
+        soap::Soap,
+        ssrf::{self, Ssrf},
+        tarpit::Tarpit,
+        template::Templates,
+        upload::Uploads,
+        vpn::VpnPortals,
+        webmail::Webmail,
+        xmlrpc::XmlRpc,
+    },
+    http::{
+        egress::{Egress, Shaper},
+        h2,
+        request::{self, ParserConfig, Request},
+        response::{Response, StatusCode},
+    },
+    hub::Uplink,
+    metrics,
+    middleware::{Chain, Delay, MethodOverride},
+    persona::{Banner, Persona, Trace},
+    prelude::*,
+    privacy::Anonymizer,
+    profile::{self, Activity, ProfileStore},
+    report,
+    retention::{self, RetentionConfig},
+    router,
+    seed::Seeder,
+    session::{self, BannerGrab, Ending},
+    sink::{EventSink, OnEvent, Payload},
+    tls::client_hello,
+    vhost::VirtualHosts,
+};
+
+/// A config ready to be served.
+pub struct Honeypot {
+    config: Config,
+    chain: Chain,
+    ctx: Arc<Context>,
+}
+
+/// Builds a `Honeypot`, by default of the empty config.
+#[derive(Default)]
+pub struct HoneypotBuilder {
+    config: Config,
+    max_response_delay: Duration,
+    uplink: Option<Uplink>,
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl HoneypotBuilder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// responses are delayed by a random amount of time up to this.
+    pub fn max_response_delay(mut self, delay: Duration) -> Self {
+        self.max_response_delay = delay;
+        self
+    }
+
+    /// the hub events are sent to, when this is a sensor.
+    pub fn uplink(mut self, uplink: Uplink) -> Self {
+        self.uplink = Some(uplink);
+        self
+    }
+
+    /// tells sink about every request, after the built-in sinks.
+    pub fn sink<S: EventSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Arc::new(sink));
+        self
+    }
+
+    /// calls hook with every exchange recorded. Repeats collapsed by
+    /// dedup aren't recorded.
+    pub fn on_event<F: Fn(&Event) + Send + Sync + 'static>(self, hook: F) -> Self {
+        self.sink(OnEvent(hook))
+    }
+
+    /// builds the chain and everything its connections share. Fetchers
+    /// are spawned, so this must be called within a tokio runtime.
+    pub fn build(self) -> Result<Honeypot> {
+        let config = self.config.clone();
+        let (chain, ctx) = build(
+            self.config,
+            self.max_response_delay,
+            self.uplink,
+            self.sinks,
+        )?;
+        Ok(Honeypot { config, chain, ctx })
+    }
+}
+
+impl Honeypot {
+    pub fn builder() -> HoneypotBuilder {
+        HoneypotBuilder::default()
+    }
+
+    /// serves on addr and the config's other listeners until one exits.
+    pub async fn run(self, addr: SocketAddr) -> Result<()> {
+        self.run_until(addr, std::future::pending()).await
+    }
+
+    /// serves as run does until stop completes, returning once every
+    /// listener and background task has stopped, so their addresses can
+    /// be bound again.
+    pub async fn run_until<F: Future<Output = ()>>(self, addr: SocketAddr, stop: F) -> Result<()> {
+        let Honeypot {
+            mut config,
+            chain,
+            ctx,
+        } = self;
+        let extra_listeners = std::mem::take(&mut config.listeners);
+        let favicons = config.favicons.clone();
+        let family = ctx.persona.family;
+        let mut tasks = JoinSet::new();
+
+        if let Some(report) = config.report.clone() {
+            std::fs::create_dir_all(&report.dir).map_err(|e| {
+                anyhow!(
+                    "failed to create report directory {}: {}",
+                    report.dir.display(),
+                    e
+                )
+            })?;
+            tasks.spawn(report::schedule(
+                report,
+                stored_exchanges(&config)?,
+                config.parser.clone(),
+            ));
+        }
+        if let Some(retention) = config.retention.clone() {
+            tasks.spawn(prune(retention, retained_stores(&config)));
+        }
+        if let Some(dedup) = ctx.dedup.clone() {
+            tasks.spawn(sweep_repeats(dedup));
+        }
+        if let Some(events) = ctx.events.clone() {
+            let anchor_every = Duration::from_secs(
+                config
+                    .event_log
+                    .as_ref()
+                    .map_or(0, |e| e.anchor_secs)
+                    .max(1),
+            );
+            tasks.spawn(anchor_events(events, anchor_every));
+        }
+
+        let primary = ListenerConfig {
+            addr,
+            panel: None,
+            device: None,
+        };
+        let mut listeners = JoinSet::new();
+        for listener in iter::once(primary).chain(extra_listeners) {
+            // panels answer before the persona can dress their responses
+            let observed = match (listener.panel, listener.device) {
+                (Some(panel), _) => metrics::ObserveResponse::new(panel),
+                (None, Some(device)) => metrics::ObserveResponse::new(device),
+                (None, None) => metrics::ObserveResponse::new(family),
+            };
+            let mut outer = Chain::new().layer(observed);
+            if let Some(panel) = listener.panel {
+                outer = outer
+                    .layer(Favicon::panel(panel, favicons.as_ref())?)
+                    .layer(panel);
+            }
+            if let Some(device) = listener.device {
+                outer = outer
+                    .layer(Favicon::device(device, favicons.as_ref())?)
+                    .layer(device);
+            }
+            listeners.spawn(listen_loop(
+                listener.addr,
+                outer.layer(chain.clone()),
+                ctx.clone(),
+            ));
+        }
+
+        tokio::select!(
+            Some(res) = listeners.join_next() => {
+                error!("a listen loop exited unexpectedly");
+                res??;
+                Ok(())
+            },
+            () = stop => {
+                // listeners are rebound as soon as they're stopped
+                listeners.shutdown().await;
+                tasks.shutdown().await;
+                Ok(())
+            }
+        )
+    }
+
+    /// answers a request as a listener would, without sending the
+    /// response.
+    pub async fn respond(&self, conn: TcpStream, req: &Request) -> Result<Response> {
+        respond(&self.chain, &self.ctx, conn, req).await
+    }
+}
+
+/// a store, by name, and whether it's a log rather than a directory.
+struct Store {
+    name: &'static str,
+    path: PathBuf,
+    log: bool,
+}
+
+/// the stores retention applies to.
+fn retained_stores(config: &Config) -> Vec<Store> {
+    let dir = |name, path: Option<&PathBuf>| {
+        path.map(|p| Store {
+            name,
+            path: p.clone(),
+            log: false,
+        })
+    };
+    [
+        dir("har", config.har.as_ref().map(|h| &h.dir)),
+        dir("captures", config.capture.as_ref().map(|c| &c.dir)),
+        dir("quarantine", config.uploads.as_ref().map(|u| &u.quarantine)),
+        dir("reports", config.report.as_ref().map(|r| &r.dir)),
+        config
+            .fetch
+            .as_ref()
+            .and_then(|f| f.store.clone())
+            .map(|path| Store {
+                name: "samples",
+                path,
+                log: true,
+            }),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// prunes the stores periodically, forever.
+async fn prune(config: RetentionConfig, stores: Vec<Store>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.every_secs.max(1)));
+    loop {
+        interval.tick().await;
+        for store in &stores {
+            let policy = config.policy(store.name);
+            let pruned = match store.log {
+                true => retention::prune_lines(&store.path, &policy),
+                false => retention::prune_dir(&store.path, &policy, SystemTime::now()),
+            };
+            match pruned {
+                Ok(pruned) => {
+                    if pruned.records > 0 {
+                        info!(
+                            "pruned {} records, {} bytes, from {}",
+                            pruned.records, pruned.bytes, store.name
+                        );
+                    }
+                    metrics::observe_pruned(store.name, &pruned);
+                }
+                Err(e) => warn!("failed to prune {}: {}", store.name, e),
+            }
+        }
+    }
+}
+
+/// logs how often probes were repeated once their windows end.
+async fn sweep_repeats(dedup: Dedup) {
+    let mut interval = tokio::time::interval(dedup.window().max(Duration::from_secs(1)));
+    loop {
+        interval.tick().await;
+        for collapsed in dedup.sweep() {
+            info!(
+                "{: <8} repeated {:?} {} more times in {:?} ua={:?}",
+                collapsed.probe.ip,
+                collapsed.probe.path,
+                collapsed.repeats,
+                collapsed.window,
+                collapsed.probe.user_agent
+            );
+        }
+    }
+}
+
+#[cfg(feature = "cluster")]
+/// records the request in the fleet's profile of its source, logging
+/// sources already seen by other sensors the first time they're seen here.
+async fn share_profile(cluster: Cluster, ip: IpAddr, path: String, activity: Activity) {
+    match cluster.record(ip, &path, activity).await {
+        Ok(shared) if shared.new_here && shared.sensors.len() > 1 => {
+            let others = shared
+                .sensors
+                .iter()
+                .filter(|s| *s != cluster.sensor())
+                .cloned()
+                .collect::<Vec<_>>();
+            info!(
+                "{: <8} was already seen by sensors {}, fleet score {}",
+                ip,
+                others.join(","),
+                shared.score()
+            );
+        }
+        Ok(_) => (),
+        Err(e) => warn!("failed to share the profile of {}: {}", ip, e),
+    }
+}
+
+/// anchors the event log's head periodically, forever.
+async fn anchor_events(events: EventLog, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        match events.anchor() {
+            Ok(Some(anchor)) => info!(
+                "event log anchored at record {} head {}",
+                anchor.seq, anchor.head
+            ),
+            Ok(None) => (),
+            Err(e) => warn!("failed to anchor event log: {}", e),
+        }
+    }
+}
+
+/// where exchanges are stored: HAR archives when they're written, since
+/// they're read without reassembly, otherwise captures.
+pub fn stored_exchanges(config: &Config) -> Result<Vec<PathBuf>> {
+    match (&config.har, &config.capture) {
+        (Some(har), _) => Ok(vec![har.dir.clone()]),
+        (None, Some(capture)) => Ok(vec![capture.dir.clone()]),
+        (None, None) => {
+            bail!("reports are read from [har] or [capture], and neither is configured")
+        }
+    }
+}
+
+/// the chain every listener shares, and the context every connection does.
+fn build(
+    config: Config,
+    max_response_delay: Duration,
+    uplink: Option<Uplink>,
+    extra_sinks: Vec<Arc<dyn EventSink>>,
+) -> Result<(Chain, Arc<Context>)> {
+    let banner = config.banner.as_ref().map(Banner::new).transpose()?;
+    let persona = config.persona.with_banner(banner);
+    let php = Php::new(persona, &config.php)?;
+    let mut chain = Chain::new()
+        .layer(persona)
+        .layer(Trace::new(persona, config.trace))
+        .layer(Delay::up_to(max_response_delay));
+    if let Some(canonical) = config.canonical.clone() {
+        chain = chain.layer(Canonicalize::new(canonical, persona));
+    }
+
+    if config.method_override.route_tunneled {
+        chain = chain.layer(MethodOverride);
+    }
+    let decoys = config.decoys();
+    if decoys {
+        chain = chain.layer(Deserialization);
+    }
+    if config.command_injection.emulate {
+        chain = chain.layer(ShellEmulation);
+    }
+
+    let parser = config.parser.clone();
+    let seeder = Seeder::new(router::SEED, config.variation)
+        .with_theme(config.filesystem.theme)
+        .with_tarpit(config.tarpit.is_some());
+    if let Some(uploads) = config.uploads.clone() {
+        chain = chain.layer(Uploads::new(uploads, persona)?);
+    }
+
+    let vhosts = VirtualHosts::new(config.vhosts, seeder.clone())?;
+    if !vhosts.is_empty() {
+        chain = chain.layer(vhosts);
+    }
+
+    let routes = Routes::new(config.routes, persona, seeder.clone())?;
+    if !routes.is_empty() {
+        chain = chain.layer(routes);
+    }
+
+    if !config.scripts.is_empty() {
+        chain = chain.layer(Scripts::new(config.scripts)?);
+    }
+
+    if let Some(templates) = &config.templates {
+        chain = chain.layer(Templates::new(templates, seeder.clone())?);
+    }
+
+    if !config.overlays.is_empty() {
+        chain = chain.layer(Overlays::new(&config.overlays, persona)?);
+    }
+
+    if let Some(tarpit) = &config.tarpit {
+        chain = chain.layer(Tarpit::new(tarpit.clone(), seeder.clone(), persona));
+    }
+
+    if let Some(api) = &config.api {
+        chain = chain.layer(FakeApi::new(api, seeder.clone()));
+    }
+
+    if !config.plugins.is_empty() {
+        #[cfg(feature = "wasm")]
+        {
+            chain = chain.layer(crate::honeypot::plugin::Plugins::new(config.plugins)?);
+        }
+        #[cfg(not(feature = "wasm"))]
+        bail!("plugins are configured but httpot was built without the wasm feature");
+    }
+    #[cfg(not(feature = "cluster"))]
+    if config.cluster.is_some() {
+        bail!("cluster is configured but httpot was built without the cluster feature");
+    }
+
+    let app_servers = AppServers::new(config.app_servers);
+    chain = chain.layer(Favicon::persona(persona, config.favicons.as_ref())?);
+    if decoys {
+        chain = chain
+            .layer(ObjectStore::new(config.s3, seeder.clone()))
+            .layer(Soap)
+            .layer(Search::new(seeder.clone()))
+            .layer(Redirects::new(config.redirects, persona)?)
+            .layer(Ssrf::new(config.ssrf))
+            .layer(XmlRpc::new(php.clone()))
+            .layer(Mailers::new(php.clone()))
+            .layer(app_servers.clone())
+            .layer(Webmail::new(config.webmail, php.clone()))
+            .layer(DbAdmins::new(php.clone()))
+            .layer(FrameworkExploits::new(
+                config.command_injection.emulate,
+                php.clone(),
+            ))
+            .layer(GitHosting::new(config.forge, seeder.clone()))
+            .layer(Grafana)
+            .layer(Prometheus)
+            .layer(Zabbix::new(php.clone()))
+            .layer(Nagios::new(php.clone()))
+            .layer(VpnPortals)
+            .layer(C2Decoys::new(php.clone()));
+    }
+
+    if let Some(capture) = &config.capture {
+        std::fs::create_dir_all(&capture.dir).map_err(|e| {
+            anyhow!(
+                "failed to create capture directory {}: {}",
+                capture.dir.display(),
+                e
+            )
+        })?;
+    }
+    let enricher = config.enrich.map(Enricher::spawn);
+    let fetcher = config.fetch.map(|f| Fetcher::spawn(f, enricher.clone()));
+    let events = config.event_log.as_ref().map(EventLog::open).transpose()?;
+    let mut sinks: Vec<Arc<dyn EventSink>> = vec![Arc::new(metrics::Metrics)];
+    if let Some(fetcher) = fetcher {
+        sinks.push(Arc::new(fetcher));
+    }
+    if let Some(enricher) = enricher {
+        sinks.push(Arc::new(enricher));
+    }
+    if let Some(events) = events.clone() {
+        sinks.push(Arc::new(events));
+    }
+    if let Some(uplink) = uplink {
+        sinks.push(Arc::new(uplink));
+    }
+    sinks.extend(extra_sinks);
+    let ctx = Arc::new(Context {
+        parser,
+        persona,
+        php,
+        decoys,
+        app_servers,
+        profiles: ProfileStore::new(),
+        seeder,
+        har: config.har.map(HarRecorder::new).transpose()?,
+        egress: config.egress.as_ref().map(Egress::new).transpose()?,
+        capture: config.capture,
+        dedup: config.dedup.map(Dedup::new),
+        privacy: config.privacy.map(Anonymizer::new).transpose()?,
+        #[cfg(feature = "cluster")]
+        cluster: config.cluster.map(Cluster::new).transpose()?,
+        events,
+        sinks,
+    });
+
+    Ok((chain, ctx))
+}
+
+/// Shared by every connection.
+struct Context {
+    parser: ParserConfig,
+    persona: Persona,
+    php: Php,
+    // decoy applications, credential capture, and the fake filesystem
+    decoys: bool,
+    app_servers: AppServers,
+    profiles: ProfileStore,
+    seeder: Seeder,
+    har: Option<HarRecorder>,
+    egress: Option<Egress>,
+    capture: Option<CaptureConfig>,
+    dedup: Option<Dedup>,
+    privacy: Option<Anonymizer>,
+    #[cfg(feature = "cluster")]
+    cluster: Option<Cluster>,
+    // anchored in the background, and appended to as a sink
+    events: Option<EventLog>,
+    sinks: Vec<Arc<dyn EventSink>>,
+}
+
+impl Context {
+    /// the address as it may be seen.
+    fn anonymize(&self, addr: SocketAddr) -> SocketAddr {
+        match &self.privacy {
+            Some(privacy) => privacy.addr(addr),
+            None => addr,
+        }
+    }
+}
+
+async fn listen_loop(addr: SocketAddr, chain: Chain, ctx: Arc<Context>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("listening on {}", &addr);
+    let chain = Arc::new(chain);
+
+    loop {
+        let socket = listener.accept().await;
+        match socket {
+            Err(e) => {
+                warn!("failed to accept conn: {}", e);
+                continue;
+            }
+            Ok((socket, _)) => {
+                let chain = chain.clone();
+                let ctx = ctx.clone();
+                tokio::spawn(async move {
+                    let remote = socket
+                        .peer_addr()
+                        .map(|s| ctx.anonymize(s).to_string())
+                        .unwrap_or_else(|e| format!("'unknown addr {}'", e));
+
+                    let capture = ctx.capture.as_ref().and_then(|c| {
+                        Some(
+                            Capture::new(
+                                ctx.anonymize(socket.peer_addr().ok()?),
+                                socket.local_addr().ok()?,
+                                c.max_bytes,
+                            )
+                            .anonymized(ctx.privacy.clone()),
+                        )
+                    });
+
+                    let ending = match process_socket(socket, &chain, &ctx, capture.as_ref()).await
+                    {
+                        Ok(ending) => {
+                            info!("session with {} ended {}", remote, ending);
+                            ending
+                        }
+                        Err(e) => {
+                            // parse errors quote what the requester sent
+                            let ending = Ending::of(&e);
+                            let e = display::escape(&format!("{:#}", e));
+                            info!("session with {} ended {}: {}", remote, ending, e);
+                            ending
+                        }
+                    };
+                    metrics::observe_session(ending);
+                    let capture = capture.filter(|c| !c.is_discarded());
+                    if let (Some(capture), Some(config)) = (capture, &ctx.capture) {
+                        if let Err(e) = capture.write(&config.dir) {
+                            warn!("failed to write capture of {}: {}", remote, e);
+                        }
+                    }
+                })
+            }
+        };
+    }
+}
+
+/// reads the TLS record the client started, keeping what arrived if it
+/// doesn't all.
+async fn read_record(s: &mut TcpStream, record: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let want = client_hello::record_len(record).unwrap_or(5);
+        if record.len() >= want {
+            return Ok(());
+        }
+        let mut buf = vec![0; want - record.len()];
+        let n = s.read(&mut buf).await?;
+        ensure!(n > 0, "closed after {} of {} bytes", record.len(), want);
+        record.extend(&buf[..n]);
+    }
+}
+
+async fn process_socket(
+    mut s: TcpStream,
+    chain: &Chain,
+    ctx: &Context,
+    capture: Option<&Capture>,
+) -> Result<Ending> {
+    let peer = s.peer_addr()?;
+    let addr = ctx.anonymize(peer);
+
+    debug!("get socket start...");
+    // no TLS listener yet, but scanners often send a ClientHello to any
+    // port; fingerprint it and log what it offers before giving up on the
+    // connection. Without a handshake nothing is negotiated, and no client
+    // certificate is ever sent.
+    let mut peeked = vec![0; 16 * 1024];
+    let peek = session::within(ctx.parser.timeout_secs, async {
+        s.readable().await?;
+        Ok(s.peek(&mut peeked).await?)
+    })
+    .await;
+    // scanners connect to see what answers, and send nothing themselves
+    let (n, grab) = match peek {
+        Ok(0) => (0, Some(BannerGrab::Closed)),
+        Ok(n) => (n, None),
+        Err(e) => (0, Some(BannerGrab::idle(&e).ok_or(e)?)),
+    };
+    if let Some(grab) = grab {
+        info!("{: <8} banner grab: {}", addr, grab.describe());
+        metrics::observe_banner_grab(grab);
+        return Ok(Ending::Silent);
+    }
+    if client_hello::is_handshake(&peeked[..n]) {
+        // a hello can outgrow the first segment, and the connection's
+        // given up on, so its whole record is read rather than peeked
+        let mut record = vec![];
+        let read = session::within(ctx.parser.timeout_secs, read_record(&mut s, &mut record)).await;
+        if let Some(capture) = capture {
+            capture.inbound(&record);
+        }
+        match read.and_then(|()| client_hello::ClientHello::parse(&record)) {
+            Ok(hello) => {
+                metrics::observe_client_hello(&hello);
+                let event = Event::client_hello(addr.to_string(), &hello);
+                for sink in &ctx.sinks {
+                    sink.on_response(&event);
+                }
+                info!(
+                    "{: <8} sent a TLS ClientHello for {:?} offering {} alpn={} ciphers={} ja3={} ja4={}",
+                    addr,
+                    hello.sni.as_deref().unwrap_or_default(),
+                    client_hello::version_name(hello.max_version()),
+                    display::escape(&hello.alpn_list()),
+                    hello.cipher_suites.len(),
+                    hello.ja3(),
+                    hello.ja4()
+                );
+            }
+            Err(e) => info!(
+                "{: <8} banner grab: {}, which failed to parse: {}",
+                addr,
+                BannerGrab::TlsHello.describe(),
+                display::escape(&format!("{:#}", e))
+            ),
+        }
+        metrics::observe_banner_grab(BannerGrab::TlsHello);
+        return Ok(Ending::Closed);
+    }
+    if AppServers::is_t3_handshake(&peeked[..n]) {
+        let header = String::from_utf8_lossy(&peeked[..n]);
+        info!(
+            "{: <8} sent a WebLogic T3 handshake {:?}",
+            addr,
+            header.lines().next().unwrap_or_default()
+        );
+        let response = ctx.app_servers.t3_response();
+        s.write_all(response.as_bytes()).await?;
+        if let Some(capture) = capture {
+            capture.inbound(&peeked[..n]);
+            capture.outbound(response.as_bytes());
+        }
+        return Ok(Ending::Closed);
+    }
+
+    let prior_knowledge = ctx.parser.h2c && h2::is_preface(&peeked[..n]);
+    let mut http2 = None;
+    let mut pipelined = vec![];
+    let req = metrics::observe_request(async {
+        let (read, write) = s.split();
+        let (mut read, mut write) = (Tee::new(read, capture), Tee::new(write, capture));
+        // anonymized with its forwarding headers below
+        let mut req = session::within(ctx.parser.timeout_secs, async {
+            if prior_knowledge {
+                let (req, session) = h2::accept(&peer, &mut read, &mut write, &ctx.parser).await?;
+                http2 = Some(session);
+                return Ok(req);
+            }
+            let mut reader = BufReader::new(read);
+            let req = request::parse_request_with(&peer, &mut reader, &ctx.parser).await?;
+            if ctx.parser.h2c && h2::is_upgrade(&req) {
+                http2 = Some(h2::upgrade(&req, &mut reader, &mut write).await?);
+            } else {
+                pipelined = request::pipelined(&peer, reader.buffer(), &ctx.parser).await;
+            }
+            Ok(req)
+        })
+        .await?;
+        if let Some(privacy) = &ctx.privacy {
+            privacy.request(&mut req);
+        }
+        Ok(req)
+    })
+    .await;
+    let req = match req {
+        Ok(req) => req,
+        Err(e) => {
+            let e = match session::hung_up(&s) {
+                Some(ending) => e.context(ending),
+                None => e,
+            };
+            // which HTTP/2 clients wouldn't understand
+            if prior_knowledge {
+                return Err(e);
+            }
+            // answer as the persona would before giving up
+            let mut resp = ctx.persona.error(s, StatusCode::BadRequest, None).build()?;
+            ctx.persona.dress(resp.headers_mut());
+            resp.send_shaped(&Shaper::new(ctx.egress.as_ref())).await?;
+            if let Some(capture) = capture {
+                capture.outbound(&[resp.head().as_bytes(), resp.body()].concat());
+            }
+            return Err(e);
+        }
+    };
+
+    if let Some(session) = &http2 {
+        info!(
+            "{: <8} spoke HTTP/2{} h2={}",
+            req.requester(),
+            if session.upgraded {
+                " upgraded from HTTP/1.1"
+            } else {
+                ""
+            },
+            session.fingerprint
+        );
+    }
+
+    // there's no keep-alive, so only the first is answered, and its
+    // Connection: close tells the client to send the rest again; clients
+    // which pipeline anyway are rare enough to be worth telling apart
+    if !pipelined.is_empty() {
+        metrics::observe_pipelined(pipelined.len());
+        info!(
+            "{: <8} pipelined {} more requests: {}",
+            req.requester(),
+            pipelined.len(),
+            pipelined
+                .iter()
+                .map(|r| format!(
+                    "{} {}",
+                    r.method.to_string(),
+                    display::truncate(r.url.path(), 40)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let tags = classify::classify(&req);
+    for sink in &ctx.sinks {
+        sink.on_request(&req);
+        for tag in &tags {
+            sink.on_anomaly(&req, *tag);
+        }
+    }
+    let fingerprint = Fingerprint::new(&req);
+    metrics::observe_fingerprint(&fingerprint);
+    let activity = Activity::of(&req);
+    let profile = ctx.profiles.record(&req, activity);
+    metrics::observe_engagement(&activity, &profile);
+    #[cfg(feature = "cluster")]
+    if let Some(cluster) = ctx.cluster.clone() {
+        tokio::spawn(share_profile(
+            cluster,
+            req.requester_ip(),
+            req.url.path().to_string(),
+            activity,
+        ));
+    }
+
+    // repeats are only counted, so they're logged quietly and not stored
+    let verdict = ctx.dedup.as_ref().map_or(Verdict::First, |d| d.check(&req));
+    if !verdict.is_recorded() {
+        if let Some(capture) = capture {
+            capture.discard();
+        }
+    }
+    let level = match verdict {
+        Verdict::Repeat => log::Level::Debug,
+        _ => log::Level::Info,
+    };
+
+    log::log!(
+        level,
+        "{: <8} {} ==> {: <8} {} bytes {} fp={}",
+        req.requester(),
+        display::column(req.headers.get_first("User-Agent").unwrap_or("Unknown"), 20),
+        req.method.to_string(),
+        req.body.len(),
+        display::truncate(req.url.path(), 20),
+        fingerprint,
+    );
+    if !tags.is_empty() {
+        let level = match (classify::severity(&tags), verdict) {
+            (_, Verdict::Repeat) => log::Level::Debug,
+            (classify::Severity::High, _) => log::Level::Warn,
+            (classify::Severity::Low, _) => log::Level::Info,
+        };
+        log::log!(
+            level,
+            "{: <8} host {:?} target {:?} tagged {}",
+            req.requester(),
+            req.host_header().unwrap_or_default(),
+            req.target,
+            classify::tag_list(&tags)
+        );
+    }
+    if activity != Activity::default() {
+        info!(
+            "{: <8} submitted credentials: {}, uploaded: {}, source score now {}",
+            req.requester(),
+            activity.credentials,
+            activity.upload,
+            profile.score()
+        );
+    }
+    if activity.credentials && ctx.decoys {
+        for (user, secret) in profile::credentials(&req) {
+            for sink in &ctx.sinks {
+                sink.on_credential(&req, &user, &secret);
+            }
+        }
+    }
+    let mut payloads = vec![];
+    if activity.upload && !req.body.is_empty() {
+        let sha256 = fetch::sha256_hex(&req.body);
+        info!("{: <8} uploaded sha256={}", req.requester(), sha256);
+        payloads.push(Payload::Upload { sha256 });
+    }
+    if tags.contains(&classify::Tag::Xxe) {
+        let references = classify::external_references(&req.body);
+        info!(
+            "{: <8} XML body declares entities referencing {:?}",
+            req.requester(),
+            references
+        );
+        payloads.push(Payload::Entities(references));
+    }
+    if let Some(injection) = shell::injection(&req) {
+        info!(
+            "{: <8} injected {:?} in {:?} downloading {:?}",
+            req.requester(),
+            injection.command,
+            injection.field,
+            injection.download_urls()
+        );
+        payloads.push(Payload::Command(injection));
+    }
+    if let Some(url) = ssrf::target(&req) {
+        payloads.push(Payload::Ssrf(url));
+    }
+    for payload in &payloads {
+        for sink in &ctx.sinks {
+            sink.on_payload(&req, payload);
+        }
+    }
+    if let Some(tunneled) = req.tunneled_method() {
+        info!(
+            "{: <8} {} tunneled {:?}",
+            req.requester(),
+            req.method.to_string(),
+            tunneled
+        );
+    }
+
+    let received = (Utc::now(), Instant::now());
+    let mut resp = respond(chain, ctx, s, &req).await?;
+    let started = Instant::now();
+    // what was sent is still recorded if the client went away mid-response
+    let shaper = Shaper::new(ctx.egress.as_ref());
+    let sent = match http2.as_mut() {
+        Some(session) => resp.send_h2(session, &shaper).await,
+        None => resp.send_shaped(&shaper).await,
+    }
+    .map_err(|e| e.context(Ending::WriteFailed));
+    let ending = match &sent {
+        Ok(()) => Ending::Closed,
+        Err(e) => Ending::of(e),
+    };
+    if let Some(capture) = capture {
+        capture.outbound(&match &http2 {
+            Some(session) => session.frames(&resp),
+            None => [resp.head().as_bytes(), resp.body()].concat(),
+        });
+    }
+
+    log::log!(
+        level,
+        "{: <8} <== {: <4} {: >8} bytes",
+        req.requester(),
+        resp.code(),
+        resp.len(),
+    );
+    if let Some(trickle) = resp.trickle() {
+        info!(
+            "{: <8} tarpitted for {:?}, trickled {} of {} bytes",
+            req.requester(),
+            started.elapsed(),
+            trickle.sent(),
+            trickle.len
+        );
+        metrics::observe_tarpit(&req, trickle.sent(), started.elapsed());
+    }
+    if let Some(har) = ctx.har.as_ref().filter(|_| verdict.is_recorded()) {
+        if let Err(e) = har.record(&req, &resp, received.0, received.1.elapsed()) {
+            warn!("failed to record {} in HAR: {}", req.requester(), e);
+        }
+    }
+    if verdict.is_recorded() {
+        let tags = tags.iter().map(|t| t.to_string()).collect();
+        let event = Event::new(&req, &resp, tags, ending);
+        for sink in &ctx.sinks {
+            sink.on_response(&event);
+        }
+    }
+
+    // close conn
+    sent.map(|()| ending)
+}
+
+/// runs the request through the chain, ending at the router.
+async fn respond(chain: &Chain, ctx: &Context, conn: TcpStream, req: &Request) -> Result<Response> {
+    let persona = ctx.persona;
+    let seed = ctx.seeder.for_request(req);
+    let tree = ctx.seeder.tree();
+    let fake_fs = ctx.decoys;
+    let php = ctx.php.clone();
+    let endpoint =
+        move |conn, req: &Request| router::router(conn, req, persona, &php, &seed, tree, fake_fs);
+    chain.run(conn, req, &endpoint).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::AsyncReadExt;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl EventSink for Recorder {
+        fn on_anomaly(&self, _req: &Request, tag: classify::Tag) {
+            self.0.lock().unwrap().push(format!("anomaly {}", tag));
+        }
+
+        fn on_credential(&self, _req: &Request, user: &str, secret: &str) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("credential {}:{}", user, secret));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_honeypot() {
+        let seen = Arc::new(Mutex::new(vec![]));
+        let recorder = Arc::new(Recorder::default());
+        let honeypot = Honeypot::builder()
+            .on_event({
+                let seen = seen.clone();
+                move |event| seen.lock().unwrap().push(event.target.clone())
+            })
+            .sink(recorder.clone())
+            .build()
+            .unwrap();
+
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let (done, stop) = tokio::sync::oneshot::channel::<()>();
+        let running = tokio::spawn(honeypot.run_until(addr, async {
+            stop.await.ok();
+        }));
+
+        let mut conn = loop {
+            match TcpStream::connect(addr).await {
+                Ok(conn) => break conn,
+                Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        conn.write_all(b"GET /hello?user=root&pass=toor HTTP/1.1\r\nHost: 10.0.0.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut resp = vec![];
+        conn.read_to_end(&mut resp).await.unwrap();
+        assert!(resp.starts_with(b"HTTP/1.1 200"), "{:?}", resp);
+
+        done.send(()).unwrap();
+        running.await.unwrap().unwrap();
+        assert_eq!(
+            vec!["/hello?user=root&pass=toor".to_string()],
+            *seen.lock().unwrap()
+        );
+        assert_eq!(
+            vec!["anomaly ip_literal_host", "credential root:toor"],
+            *recorder.0.lock().unwrap()
+        );
+        // stopped listeners free their address
+        TcpListener::bind(addr).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_split_client_hello() {
+        let (chain, ctx) = build(Config::default(), Duration::ZERO, None, vec![]).unwrap();
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = l.local_addr().unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(addr), l.accept());
+        let (mut client, (server, peer)) = (client.unwrap(), accepted.unwrap());
+
+        // a record too large for one peek, arriving in two segments, which
+        // doesn't parse as a hello
+        let mut record = vec![0x16, 0x03, 0x01, 0x50, 0x00];
+        record.extend(vec![0; 0x5000]);
+        let capture = Capture::new(peer, addr, usize::MAX);
+        let send = async {
+            client.write_all(&record[..100]).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            client.write_all(&record[100..]).await.unwrap();
+        };
+        let (ending, ()) = tokio::join!(process_socket(server, &chain, &ctx, Some(&capture)), send);
+        assert_eq!(Ending::Closed, ending.unwrap());
+        let captured = crate::capture::read_pcap(&capture.to_pcap()).unwrap();
+        assert_eq!(record, captured[0].inbound);
+    }
+
+    #[tokio::test]
+    async fn test_pipelined() {
+        let (chain, ctx) = build(Config::default(), Duration::ZERO, None, vec![]).unwrap();
+        let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = l.local_addr().unwrap();
+        let (client, accepted) = tokio::join!(TcpStream::connect(addr), l.accept());
+        let mut client = client.unwrap();
+        client
+            .write_all(b"GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: x\r\n\r\n")
+            .await
+            .unwrap();
+
+        let ending = process_socket(accepted.unwrap().0, &chain, &ctx, None).await;
+        assert_eq!(Ending::Closed, ending.unwrap());
+        let mut answered = vec![];
+        client.read_to_end(&mut answered).await.unwrap();
+        let answered = String::from_utf8_lossy(&answered);
+        assert_eq!(1, answered.matches("HTTP/1.1 ").count(), "{}", answered);
+        assert!(answered.contains("Connection: close\r\n"), "{}", answered);
+    }
+
+    #[tokio::test]
+    async fn test_low_interaction() {
+        async fn route(honeypot: &Honeypot, target: &str) -> String {
+            let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = l.local_addr().unwrap();
+            let (_, accepted) = tokio::join!(TcpStream::connect(addr), l.accept());
+            let req = Request::stub(request::Method::GET, target, "");
+            let resp = honeypot.respond(accepted.unwrap().0, &req).await.unwrap();
+            resp.route().unwrap_or_default().to_string()
+        }
+
+        let medium = Honeypot::builder()
+            .config(Config::parse("interaction = \"medium\"").unwrap())
+            .build()
+            .unwrap();
+        assert_eq!("listing", route(&medium, "/").await);
+        assert_ne!("not_found", route(&medium, "/adminer.php").await);
+
+        let low = Honeypot::builder()
+            .config(Config::parse("interaction = \"low\"").unwrap())
+            .build()
+            .unwrap();
+        assert_eq!("not_found", route(&low, "/").await);
+        assert_eq!("not_found", route(&low, "/adminer.php").await);
+    }
+
+    #[tokio::test]
+    async fn test_honeypots_kept_apart() {
+        async fn headers(honeypot: &Honeypot) -> (String, String) {
+            let l = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = l.local_addr().unwrap();
+            let (_, accepted) = tokio::join!(TcpStream::connect(addr), l.accept());
+            let req = Request::stub(request::Method::GET, "/xmlrpc.php", "");
+            let resp = honeypot.respond(accepted.unwrap().0, &req).await.unwrap();
+            let headers = resp.headers();
+            (
+                headers.get_first("Server").unwrap_or_default().to_string(),
+                headers
+                    .get_first("X-Powered-By")
+                    .unwrap_or_default()
+                    .to_string(),
+            )
+        }
+
+        // built one after the other, neither sees the other's persona or PHP
+        let apache = Honeypot::builder()
+            .config(Config::parse("[php]\nversion = \"5.3.10\"").unwrap())
+            .build()
+            .unwrap();
+        let nginx = Honeypot::builder()
+            .config(Config::parse("persona = \"nginx\"\n[php]\nversion = \"7.4.33\"").unwrap())
+            .build()
+            .unwrap();
+        let (server, php) = headers(&apache).await;
+        assert!(server.starts_with("Apache"), "{}", server);
+        assert_eq!("PHP/5.3.10", php);
+        let (server, php) = headers(&nginx).await;
+        assert!(server.starts_with("nginx"), "{}", server);
+        assert_eq!("PHP/7.4.33", php);
+    }
+}
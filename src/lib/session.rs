@@ -0,0 +1,187 @@
+//! How sessions end. Scanners that connect and send nothing, hang up
+//! mid-request, or reset the connection once they've seen the banner are
+//! telling us something, so endings are classified rather than lumped
+//! together as errors.
+use std::{fmt, future::Future, io, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpStream, time::error::Elapsed};
+
+use crate::prelude::*;
+
+/// How a session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Ending {
+    /// answered, then closed by us
+    Closed,
+    /// the client sent nothing, closing or idling until the timeout
+    Silent,
+    /// closed by the client partway through a request
+    Incomplete,
+    /// reset or aborted by the client
+    Reset,
+    /// the client took too long to finish its request
+    TimedOut,
+    /// the response couldn't be written
+    WriteFailed,
+    /// anything else going wrong
+    Failed,
+}
+
+impl Ending {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Ending::Closed => "closed",
+            Ending::Silent => "silent",
+            Ending::Incomplete => "incomplete",
+            Ending::Reset => "reset",
+            Ending::TimedOut => "timed_out",
+            Ending::WriteFailed => "write_failed",
+            Ending::Failed => "failed",
+        }
+    }
+
+    /// how a session erroring with e ended. What the socket reported wins
+    /// over where the error was tagged with an ending.
+    pub fn of(e: &Error) -> Self {
+        for cause in e.chain() {
+            if let Some(e) = cause.downcast_ref::<io::Error>() {
+                match e.kind() {
+                    io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::BrokenPipe => return Ending::Reset,
+                    io::ErrorKind::TimedOut => return Ending::TimedOut,
+                    io::ErrorKind::UnexpectedEof => return Ending::Incomplete,
+                    _ => (),
+                }
+            }
+            if cause.is::<Elapsed>() {
+                return Ending::TimedOut;
+            }
+        }
+        match e.downcast_ref::<Ending>() {
+            Some(ending) => *ending,
+            None => Ending::Failed,
+        }
+    }
+}
+
+impl fmt::Display for Ending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A connection that never got as far as a request, as port scanners and
+/// banner grabbers open: they connect and wait to be told what's listening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BannerGrab {
+    /// connected, then closed without sending anything
+    Closed,
+    /// connected, then sent nothing within the timeout
+    Idle,
+    /// sent a TLS ClientHello, which is all it'll get to
+    TlsHello,
+}
+
+impl BannerGrab {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BannerGrab::Closed => "closed",
+            BannerGrab::Idle => "idle",
+            BannerGrab::TlsHello => "tls_hello",
+        }
+    }
+
+    /// what the client did, for logging.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            BannerGrab::Closed => "connected and closed without sending anything",
+            BannerGrab::Idle => "connected and sent nothing",
+            BannerGrab::TlsHello => "sent only a TLS ClientHello",
+        }
+    }
+
+    /// the grab, if the error came from waiting on a client that sent
+    /// nothing.
+    pub fn idle(e: &Error) -> Option<Self> {
+        e.chain()
+            .any(|cause| cause.is::<Elapsed>())
+            .then_some(BannerGrab::Idle)
+    }
+}
+
+/// how the client left the socket, if it's gone: closed, or reset. Reads
+/// a byte if one's waiting, so only for sockets done with.
+pub fn hung_up(s: &TcpStream) -> Option<Ending> {
+    match s.try_read(&mut [0; 1]) {
+        Ok(0) => Some(Ending::Incomplete),
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted
+            ) =>
+        {
+            Some(Ending::Reset)
+        }
+        _ => None,
+    }
+}
+
+/// runs f, giving up after secs unless they're 0.
+pub async fn within<T, F: Future<Output = Result<T>>>(secs: u64, f: F) -> Result<T> {
+    if secs == 0 {
+        return f.await;
+    }
+    tokio::time::timeout(Duration::from_secs(secs), f)
+        .await
+        .map_err(|e| Error::new(e).context(format!("no request within {}s", secs)))?
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ending_of() {
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer");
+        assert_eq!(Ending::Reset, Ending::of(&Error::new(reset)));
+        // the socket's say wins over where the error was tagged
+        let pipe = io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe");
+        assert_eq!(
+            Ending::Reset,
+            Ending::of(
+                &Error::new(pipe)
+                    .context("writing")
+                    .context(Ending::WriteFailed)
+            )
+        );
+        assert_eq!(
+            Ending::WriteFailed,
+            Ending::of(&anyhow!("out of disk").context(Ending::WriteFailed))
+        );
+        assert_eq!(Ending::Failed, Ending::of(&anyhow!("did not get path")));
+
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Ending::TimedOut,
+            Ending::of(&Error::new(elapsed).context("no request within 0s"))
+        );
+        assert_eq!("timed_out", serde_json::to_value(Ending::TimedOut).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_banner_grab() {
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(BannerGrab::Idle),
+            BannerGrab::idle(&Error::new(elapsed).context("no request within 0s"))
+        );
+        assert_eq!(None, BannerGrab::idle(&anyhow!("connection reset")));
+    }
+}
@@ -0,0 +1,98 @@
+//! Sinks observe what clients do as their requests are answered. The event
+//! log, the hub uplink, fetches, enrichment, and metrics are all sinks, and
+//! embedders add their own through `HoneypotBuilder::sink`.
+use std::sync::Arc;
+
+use crate::{classify::Tag, eventlog::Event, honeypot::shell::Injection, http::request::Request};
+
+/// Observes requests and what was found in them. Every method does nothing
+/// by default, so sinks implement only what they need.
+///
+/// All but `on_response` are called for every request, repeats included,
+/// before it's answered. Sinks are called on the connection's task, so
+/// anything slow belongs on a task of its own.
+pub trait EventSink: Send + Sync {
+    /// a request was read.
+    fn on_request(&self, _req: &Request) {}
+
+    /// the request was tagged, see `classify::Tag`.
+    fn on_anomaly(&self, _req: &Request, _tag: Tag) {}
+
+    /// the request submitted credentials, see `profile::credentials`. The
+    /// user is empty when only a secret was found.
+    fn on_credential(&self, _req: &Request, _user: &str, _secret: &str) {}
+
+    /// the request carried something meant to run or be fetched.
+    fn on_payload(&self, _req: &Request, _payload: &Payload) {}
+
+    /// an exchange was answered and recorded, or a session sent a TLS
+    /// ClientHello, see `Event::client_hello`. Repeats collapsed by dedup
+    /// aren't recorded.
+    fn on_response(&self, _event: &Event) {}
+}
+
+// so embedders can keep a handle on a sink they've added
+impl<S: EventSink + ?Sized> EventSink for Arc<S> {
+    fn on_request(&self, req: &Request) {
+        (**self).on_request(req)
+    }
+
+    fn on_anomaly(&self, req: &Request, tag: Tag) {
+        (**self).on_anomaly(req, tag)
+    }
+
+    fn on_credential(&self, req: &Request, user: &str, secret: &str) {
+        (**self).on_credential(req, user, secret)
+    }
+
+    fn on_payload(&self, req: &Request, payload: &Payload) {
+        (**self).on_payload(req, payload)
+    }
+
+    fn on_response(&self, event: &Event) {
+        (**self).on_response(event)
+    }
+}
+
+/// Something a request carried for the server to run or fetch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// a body uploaded, by its SHA-256
+    Upload { sha256: String },
+    /// a shell command injected into a query or form field
+    Command(Injection),
+    /// the URLs an XML body's entities reference
+    Entities(Vec<String>),
+    /// the URL an SSRF bait was asked to fetch
+    Ssrf(String),
+}
+
+impl Payload {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Payload::Upload { .. } => "upload",
+            Payload::Command(_) => "command",
+            Payload::Entities(_) => "entities",
+            Payload::Ssrf(_) => "ssrf",
+        }
+    }
+
+    /// the URLs running the payload would fetch.
+    pub fn download_urls(&self) -> Vec<String> {
+        match self {
+            Payload::Upload { .. } => vec![],
+            Payload::Command(injection) => injection.download_urls(),
+            Payload::Entities(urls) => urls.clone(),
+            Payload::Ssrf(url) => vec![url.clone()],
+        }
+    }
+}
+
+/// A sink calling a closure with every recorded exchange.
+pub(crate) struct OnEvent<F>(pub(crate) F);
+
+impl<F: Fn(&Event) + Send + Sync> EventSink for OnEvent<F> {
+    fn on_response(&self, event: &Event) {
+        (self.0)(event)
+    }
+}
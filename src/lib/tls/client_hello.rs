@@ -0,0 +1,420 @@
+//! TLS ClientHello parsing and the JA3 and JA4 fingerprints derived from
+//! it. Scan frameworks pick their TLS library and its settings far less
+//! often than their User-Agent, so these identify them more reliably.
+use sha2::{Digest, Sha256};
+
+use crate::prelude::*;
+
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_SUPPORTED_GROUPS: u16 = 0x000a;
+const EXT_EC_POINT_FORMATS: u16 = 0x000b;
+const EXT_SIGNATURE_ALGORITHMS: u16 = 0x000d;
+const EXT_ALPN: u16 = 0x0010;
+const EXT_SUPPORTED_VERSIONS: u16 = 0x002b;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientHello {
+    /// legacy_version from the hello, 0x0303 for TLS 1.2 and 1.3
+    pub version: u16,
+    pub cipher_suites: Vec<u16>,
+    /// extension types in the order sent
+    pub extensions: Vec<u16>,
+    pub sni: Option<String>,
+    pub alpn: Vec<String>,
+    pub supported_groups: Vec<u16>,
+    pub ec_point_formats: Vec<u8>,
+    pub signature_algorithms: Vec<u16>,
+    pub supported_versions: Vec<u16>,
+}
+
+/// whether the bytes start like a TLS handshake record, as opposed to
+/// plaintext HTTP.
+pub fn is_handshake(buf: &[u8]) -> bool {
+    matches!(buf, [CONTENT_TYPE_HANDSHAKE, 0x03, 0x00..=0x04, ..])
+}
+
+/// the length of the record the bytes start, its 5-byte header included,
+/// once the header's all there.
+pub fn record_len(buf: &[u8]) -> Option<usize> {
+    match buf {
+        [_, _, _, hi, lo, ..] => Some(5 + usize::from(u16::from_be_bytes([*hi, *lo]))),
+        _ => None,
+    }
+}
+
+/// GREASE values (RFC 8701) are random placeholders clients send to keep
+/// servers tolerant; fingerprints ignore them.
+fn is_grease(v: u16) -> bool {
+    v & 0x0f0f == 0x0a0a && v >> 8 == v & 0xff
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        ensure!(
+            self.0.len() >= n,
+            "client hello truncated, wanted {} bytes but {} remain",
+            n,
+            self.0.len()
+        );
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Result<usize> {
+        let b = self.bytes(3)?;
+        Ok(u32::from_be_bytes([0, b[0], b[1], b[2]]) as usize)
+    }
+
+    /// a sub-reader over a length prefixed vector.
+    fn vec8(&mut self) -> Result<Reader<'a>> {
+        let len = self.u8()? as usize;
+        Ok(Reader(self.bytes(len)?))
+    }
+
+    fn vec16(&mut self) -> Result<Reader<'a>> {
+        let len = self.u16()? as usize;
+        Ok(Reader(self.bytes(len)?))
+    }
+
+    fn u16s(mut self) -> Result<Vec<u16>> {
+        let mut out = Vec::with_capacity(self.0.len() / 2);
+        while !self.0.is_empty() {
+            out.push(self.u16()?);
+        }
+        Ok(out)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl ClientHello {
+    /// parses a ClientHello from the start of a TLS record. The hello must
+    /// fit within the first record, which is true of nearly every client.
+    pub fn parse(buf: &[u8]) -> Result<Self> {
+        ensure!(is_handshake(buf), "not a TLS handshake record");
+
+        let mut record = Reader(buf);
+        record.bytes(3)?;
+        let mut handshake = record.vec16()?;
+
+        ensure!(
+            handshake.u8()? == HANDSHAKE_CLIENT_HELLO,
+            "handshake is not a client hello"
+        );
+        let len = handshake.u24()?;
+        let mut body = Reader(handshake.bytes(len)?);
+
+        let mut hello = ClientHello {
+            version: body.u16()?,
+            ..Default::default()
+        };
+        body.bytes(32)?; // random
+        body.vec8()?; // session id
+        hello.cipher_suites = body.vec16()?.u16s()?;
+        body.vec8()?; // compression methods
+
+        // extensions are optional in old clients
+        if body.is_empty() {
+            return Ok(hello);
+        }
+
+        let mut extensions = body.vec16()?;
+        while !extensions.is_empty() {
+            let ty = extensions.u16()?;
+            let mut data = extensions.vec16()?;
+            hello.extensions.push(ty);
+
+            match ty {
+                EXT_SERVER_NAME => {
+                    let mut names = data.vec16()?;
+                    while !names.is_empty() {
+                        let name_type = names.u8()?;
+                        let name = names.vec16()?;
+                        if name_type == 0 {
+                            hello.sni = Some(String::from_utf8_lossy(name.0).into_owned());
+                        }
+                    }
+                }
+                EXT_ALPN => {
+                    let mut protos = data.vec16()?;
+                    while !protos.is_empty() {
+                        let proto = protos.vec8()?;
+                        hello
+                            .alpn
+                            .push(String::from_utf8_lossy(proto.0).into_owned());
+                    }
+                }
+                EXT_SUPPORTED_GROUPS => hello.supported_groups = data.vec16()?.u16s()?,
+                EXT_EC_POINT_FORMATS => hello.ec_point_formats = data.vec8()?.0.to_vec(),
+                EXT_SIGNATURE_ALGORITHMS => hello.signature_algorithms = data.vec16()?.u16s()?,
+                EXT_SUPPORTED_VERSIONS => hello.supported_versions = data.vec8()?.u16s()?,
+                _ => (),
+            }
+        }
+
+        Ok(hello)
+    }
+
+    /// the raw JA3 string: version, ciphers, extensions, groups, and point
+    /// formats in decimal.
+    pub fn ja3_string(&self) -> String {
+        fn join<T: ToString>(values: impl Iterator<Item = T>) -> String {
+            values.map(|v| v.to_string()).collect::<Vec<_>>().join("-")
+        }
+
+        format!(
+            "{},{},{},{},{}",
+            self.version,
+            join(self.cipher_suites.iter().filter(|c| !is_grease(**c))),
+            join(self.extensions.iter().filter(|e| !is_grease(**e))),
+            join(self.supported_groups.iter().filter(|g| !is_grease(**g))),
+            join(self.ec_point_formats.iter()),
+        )
+    }
+
+    /// md5 hex digest of the JA3 string.
+    pub fn ja3(&self) -> String {
+        format!("{:x}", md5::compute(self.ja3_string()))
+    }
+
+    /// the newest TLS version offered: the highest supported_versions
+    /// entry, else the legacy version.
+    pub fn max_version(&self) -> u16 {
+        self.supported_versions
+            .iter()
+            .filter(|v| !is_grease(**v))
+            .max()
+            .copied()
+            .unwrap_or(self.version)
+    }
+
+    /// the ALPN protocols offered, comma separated, or "-" for none.
+    pub fn alpn_list(&self) -> String {
+        match self.alpn.is_empty() {
+            true => "-".to_string(),
+            false => self.alpn.join(","),
+        }
+    }
+
+    /// the JA4 fingerprint for a hello received over TCP, e.g.
+    /// `t13d1516h2_8daaf6152771_b186095e22b6`.
+    pub fn ja4(&self) -> String {
+        let version = match self.max_version() {
+            0x0304 => "13",
+            0x0303 => "12",
+            0x0302 => "11",
+            0x0301 => "10",
+            0x0300 => "s3",
+            0x0002 => "s2",
+            _ => "00",
+        };
+
+        let ciphers = self
+            .cipher_suites
+            .iter()
+            .filter(|c| !is_grease(**c))
+            .copied()
+            .collect::<Vec<_>>();
+        let extensions = self
+            .extensions
+            .iter()
+            .filter(|e| !is_grease(**e))
+            .copied()
+            .collect::<Vec<_>>();
+
+        let alpn = match self.alpn.first().map(|a| a.as_bytes()) {
+            Some([first, .., last]) | Some([first @ last]) => {
+                if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+                    format!("{}{}", *first as char, *last as char)
+                } else {
+                    let (first, last) = (format!("{:02x}", first), format!("{:02x}", last));
+                    format!("{}{}", &first[..1], &last[1..])
+                }
+            }
+            _ => "00".to_string(),
+        };
+
+        let mut sorted_ciphers = ciphers.clone();
+        sorted_ciphers.sort_unstable();
+        let mut sorted_extensions = extensions
+            .iter()
+            .filter(|e| **e != EXT_SERVER_NAME && **e != EXT_ALPN)
+            .copied()
+            .collect::<Vec<_>>();
+        sorted_extensions.sort_unstable();
+
+        let mut extension_str = hex_list(&sorted_extensions);
+        if !self.signature_algorithms.is_empty() {
+            extension_str = extension_str + "_" + hex_list(&self.signature_algorithms).as_str();
+        }
+
+        format!(
+            "t{}{}{:02}{:02}{}_{}_{}",
+            version,
+            if self.sni.is_some() { 'd' } else { 'i' },
+            ciphers.len().min(99),
+            extensions.len().min(99),
+            alpn,
+            truncated_sha256(&hex_list(&sorted_ciphers), sorted_ciphers.is_empty()),
+            truncated_sha256(&extension_str, sorted_extensions.is_empty()),
+        )
+    }
+}
+
+/// the name of a TLS protocol version, as OpenSSL logs them.
+pub fn version_name(version: u16) -> String {
+    match version {
+        0x0304 => "TLSv1.3".to_string(),
+        0x0303 => "TLSv1.2".to_string(),
+        0x0302 => "TLSv1.1".to_string(),
+        0x0301 => "TLSv1".to_string(),
+        0x0300 => "SSLv3".to_string(),
+        0x0002 => "SSLv2".to_string(),
+        v => format!("0x{:04x}", v),
+    }
+}
+
+fn hex_list(values: &[u16]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{:04x}", v))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn truncated_sha256(s: &str, empty: bool) -> String {
+    if empty {
+        return "000000000000".to_string();
+    }
+
+    Sha256::digest(s.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>()[..12]
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ext(ty: u16, data: &[u8]) -> Vec<u8> {
+        let mut out = ty.to_be_bytes().to_vec();
+        out.extend((data.len() as u16).to_be_bytes());
+        out.extend(data);
+        out
+    }
+
+    fn vec16(data: &[u8]) -> Vec<u8> {
+        let mut out = (data.len() as u16).to_be_bytes().to_vec();
+        out.extend(data);
+        out
+    }
+
+    fn vec8(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![data.len() as u8];
+        out.extend(data);
+        out
+    }
+
+    fn hello() -> Vec<u8> {
+        let mut sni = vec![0];
+        sni.extend(vec16(b"example.com"));
+
+        let mut exts = vec![];
+        exts.extend(ext(0x0a0a, &[])); // GREASE
+        exts.extend(ext(EXT_SERVER_NAME, &vec16(&sni)));
+        exts.extend(ext(EXT_SUPPORTED_GROUPS, &vec16(&[0x00, 0x1d, 0x00, 0x17])));
+        exts.extend(ext(EXT_EC_POINT_FORMATS, &vec8(&[0])));
+        exts.extend(ext(
+            EXT_SIGNATURE_ALGORITHMS,
+            &vec16(&[0x04, 0x03, 0x08, 0x04]),
+        ));
+        exts.extend(ext(
+            EXT_ALPN,
+            &vec16(&[vec8(b"h2"), vec8(b"http/1.1")].concat()),
+        ));
+        exts.extend(ext(
+            EXT_SUPPORTED_VERSIONS,
+            &vec8(&[0x03, 0x04, 0x03, 0x03]),
+        ));
+
+        let mut body = vec![0x03, 0x03];
+        body.extend([0; 32]);
+        body.extend(vec8(&[]));
+        body.extend(vec16(&[0x1a, 0x1a, 0x13, 0x01, 0xc0, 0x2b]));
+        body.extend(vec8(&[0]));
+        body.extend(vec16(&exts));
+
+        let mut handshake = vec![HANDSHAKE_CLIENT_HELLO];
+        handshake.extend(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend(body);
+
+        let mut record = vec![CONTENT_TYPE_HANDSHAKE, 0x03, 0x01];
+        record.extend(vec16(&handshake));
+        record
+    }
+
+    #[test]
+    fn test_parse() {
+        let hello = ClientHello::parse(&hello()).unwrap();
+        assert_eq!(0x0303, hello.version);
+        assert_eq!(vec![0x1a1a, 0x1301, 0xc02b], hello.cipher_suites);
+        assert_eq!(Some("example.com".to_string()), hello.sni);
+        assert_eq!(vec!["h2", "http/1.1"], hello.alpn);
+        assert_eq!(vec![0x0304, 0x0303], hello.supported_versions);
+        assert_eq!("TLSv1.3", version_name(hello.max_version()));
+        assert_eq!("h2,http/1.1", hello.alpn_list());
+    }
+
+    #[test]
+    fn test_ja3() {
+        let hello = ClientHello::parse(&hello()).unwrap();
+        assert_eq!(
+            "771,4865-49195,0-10-11-13-16-43,29-23,0",
+            hello.ja3_string()
+        );
+        assert_eq!(32, hello.ja3().len());
+    }
+
+    #[test]
+    fn test_ja4() {
+        let hello = ClientHello::parse(&hello()).unwrap();
+        let ja4 = hello.ja4();
+        assert!(ja4.starts_with("t13d0206h2_"), "{}", ja4);
+        assert_eq!(
+            truncated_sha256("1301,c02b", false),
+            ja4.split('_').nth(1).unwrap()
+        );
+        assert_eq!(
+            truncated_sha256("000a,000b,000d,002b_0403,0804", false),
+            ja4.split('_').nth(2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_not_tls() {
+        assert!(!is_handshake(b"GET / HTTP/1.1\r\n"));
+        assert!(ClientHello::parse(b"GET / HTTP/1.1\r\n").is_err());
+        assert!(ClientHello::parse(&hello()[..20]).is_err());
+        assert_eq!(Some(hello().len()), record_len(&hello()));
+        assert_eq!(None, record_len(&hello()[..4]));
+    }
+}
@@ -1,5 +1,12 @@
+use std::collections::{hash_map::DefaultHasher, BTreeMap};
+use std::hash::{Hash, Hasher};
+use std::net::IpAddr;
+use std::str::FromStr;
+
 use super::prelude::*;
 use pretty_env_logger::env_logger::Target;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 
 pub fn logtarget_parse(s: &str) -> Result<Target> {
     Ok(match s {
@@ -8,3 +15,316 @@ pub fn logtarget_parse(s: &str) -> Result<Target> {
         _ => bail!("unknown target: {}", s),
     })
 }
+
+/// matches a request path against an exact path, or a prefix when the
+/// pattern ends in '*'. See [`path_match`] for parameters.
+pub fn path_glob_match(pattern: &str, path: &str) -> bool {
+    path_match(pattern, path).is_some()
+}
+
+/// matches a request path against a pattern, returning the parameters it
+/// extracted. Segments of the pattern are matched literally, except:
+///  * `:name` matches one non-empty segment, extracted as `name`
+///  * `*name` as the last segment matches the rest, extracted as `name`
+///  * a trailing `*` matches anything after it, as a prefix
+///
+/// Parameters are percent-decoded.
+pub fn path_match(pattern: &str, path: &str) -> Option<BTreeMap<String, String>> {
+    let patterns = pattern.split('/').collect::<Vec<_>>();
+    let segments = path.split('/').collect::<Vec<_>>();
+    let decode = |s: &str| String::from_utf8_lossy(&percent_decode(s)).into_owned();
+
+    let mut params = BTreeMap::new();
+    for (i, pattern) in patterns.iter().enumerate() {
+        let last = i == patterns.len() - 1;
+        let segment = segments.get(i);
+        match (pattern.strip_prefix(':'), pattern.strip_prefix('*')) {
+            (_, Some(name)) if last && !name.is_empty() && segment.is_some() => {
+                params.insert(name.to_string(), decode(&segments[i..].join("/")));
+                return Some(params);
+            }
+            (Some(name), _) if !name.is_empty() => match segment {
+                Some(segment) if !segment.is_empty() => {
+                    params.insert(name.to_string(), decode(segment));
+                }
+                _ => return None,
+            },
+            _ => match (pattern.strip_suffix('*'), segment) {
+                (Some(prefix), Some(segment)) if last && segment.starts_with(prefix) => {
+                    return Some(params)
+                }
+                (_, Some(segment)) if segment == pattern => {}
+                _ => return None,
+            },
+        }
+    }
+    (segments.len() == patterns.len()).then_some(params)
+}
+
+/// decodes %XX escapes, leaving malformed escapes as-is.
+pub fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// whether a byte is an unreserved URI character, which percent-encoding
+/// doesn't change the meaning of.
+pub fn is_unreserved(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"-._~".contains(&b)
+}
+
+/// normalizes a path's percent-encoding as RFC 3986 does: escaped
+/// unreserved characters are decoded, so `/%61dmin` is `/admin`, and the
+/// hex of other escapes is uppercased.
+pub fn normalize_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) if is_unreserved(b) => out.push(b),
+            (b'%', Some(b)) => out.extend_from_slice(format!("%{:02X}", b).as_bytes()),
+            (b, _) => {
+                out.push(b);
+                i += 1;
+                continue;
+            }
+        }
+        i += 3;
+    }
+    // only ASCII escapes were replaced, with ASCII
+    String::from_utf8(out).unwrap_or_else(|_| path.to_string())
+}
+
+/// hashes a seed and context (usually a path) together, for seeding
+/// deterministic generators.
+pub fn hash_seed<T: Hash>(seed: T, context: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    context.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// guesses a Content-Type from a path's extension, defaulting to html as
+/// most bait pages are.
+pub fn content_type_for_path(path: &str) -> &'static str {
+    let ext = path
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "txt" | "env" | "log" | "ini" | "conf" => "text/plain",
+        "xml" | "wsdl" => "application/xml",
+        "json" => "application/json",
+        "js" => "application/javascript",
+        "css" => "text/css",
+        "gif" => "image/gif",
+        "png" => "image/png",
+        "ico" => "image/x-icon",
+        _ => "text/html",
+    }
+}
+
+/// escapes text for inclusion in HTML, as servers do when echoing the
+/// requested path in error pages.
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// HMAC-SHA256 of the message.
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    const BLOCK: usize = 64;
+    let mut block = [0u8; BLOCK];
+    match key.len() > BLOCK {
+        true => block[..32].copy_from_slice(&Sha256::digest(key)),
+        false => block[..key.len()].copy_from_slice(key),
+    }
+
+    let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<_>>();
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+/// compares MACs and tokens in time independent of where they differ.
+pub fn mac_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// A block of addresses, such as `10.0.0.0/8` or `2001:db8::/32`. A bare
+/// address is a block of just itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // v4 peers of dual-stack listeners are seen as v4-mapped v6
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(block), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(block) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(block), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(block) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>()?, Some(prefix.parse::<u8>()?)),
+            None => (s.parse::<IpAddr>()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        ensure!(prefix <= max, "prefix of {} is longer than {} bits", s, max);
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!("/admin", normalize_path("/%61dmin"));
+        assert_eq!("/Admin/login.php", normalize_path("/%41dmin/login%2Ephp"));
+        assert_eq!("/a%2Fb/%C3%A9", normalize_path("/a%2fb/%c3%a9"));
+        assert_eq!("/100%/%zz/%+1", normalize_path("/100%/%zz/%+1"));
+        assert_eq!("/plain", normalize_path("/plain"));
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?"))
+        );
+        assert!(mac_eq("5bdc", "5bdc"));
+        assert!(!mac_eq("5bdc", "5bdd"));
+        assert!(!mac_eq("5bdc", "5bdc00"));
+    }
+
+    #[test]
+    fn test_path_match() {
+        let params = |pattern, path| {
+            path_match(pattern, path).map(|p| {
+                p.into_iter()
+                    .map(|(k, v)| format!("{}={}", k, v))
+                    .collect::<Vec<_>>()
+                    .join("&")
+            })
+        };
+        assert_eq!(Some("".into()), params("/admin", "/admin"));
+        assert_eq!(None, params("/admin", "/admin/"));
+        assert_eq!(Some("".into()), params("/cgi-bin/*", "/cgi-bin/a/b"));
+        assert_eq!(None, params("/cgi-bin/*", "/cgi-bin"));
+        assert_eq!(Some("".into()), params("/.env*", "/.env.bak"));
+        assert_eq!(Some("".into()), params("*", "/"));
+
+        assert_eq!(
+            Some("id=42".into()),
+            params("/api/v1/users/:id", "/api/v1/users/42")
+        );
+        assert_eq!(None, params("/api/v1/users/:id", "/api/v1/users/"));
+        assert_eq!(None, params("/api/v1/users/:id", "/api/v1/users/42/x"));
+        assert_eq!(
+            Some("path=a b/c.zip".into()),
+            params("/downloads/*path", "/downloads/a%20b/c.zip")
+        );
+        assert_eq!(
+            Some("path=".into()),
+            params("/downloads/*path", "/downloads/")
+        );
+        assert_eq!(None, params("/downloads/*path", "/downloads"));
+        assert_eq!(Some("id=7".into()), params("/:id/*", "/7/x"));
+    }
+
+    #[test]
+    fn test_cidr() {
+        let block: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(block.contains("10.1.200.3".parse().unwrap()));
+        assert!(block.contains("::ffff:10.1.0.9".parse().unwrap()));
+        assert!(!block.contains("10.2.0.1".parse().unwrap()));
+        assert!(!block.contains("2001:db8::1".parse().unwrap()));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:ffff::1".parse().unwrap()));
+        let one: Cidr = "192.0.2.7".parse().unwrap();
+        assert!(one.contains("192.0.2.7".parse().unwrap()));
+        assert!(!one.contains("192.0.2.8".parse().unwrap()));
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains("203.0.113.1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("example.com/8".parse::<Cidr>().is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Virtual hosts let one listener impersonate several sites, dispatching on
+//! the requested Host to a site's own scripts and templates. Requests for
+//! hosts without a site fall through to the default routes.
+use serde::Deserialize;
+
+use crate::{
+    honeypot::{
+        script::{ScriptConfig, Scripts},
+        template::{Templates, TemplatesConfig},
+    },
+    http::{request::Request, response::Response},
+    middleware::{BoxFuture, Chain, Middleware, Next},
+    prelude::*,
+    seed::Seeder,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VhostConfig {
+    /// logged with requests to this vhost
+    pub name: String,
+    /// exact hostnames, or '*.example.com' for any subdomain
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub scripts: Vec<ScriptConfig>,
+    pub templates: Option<TemplatesConfig>,
+}
+
+struct Vhost {
+    name: String,
+    hosts: Vec<String>,
+    chain: Chain,
+}
+
+/// Middleware which runs the matching vhost's routes before the defaults.
+pub struct VirtualHosts {
+    vhosts: Vec<Vhost>,
+}
+
+impl VirtualHosts {
+    pub fn new(configs: Vec<VhostConfig>, seeder: Seeder) -> Result<Self> {
+        let vhosts = configs
+            .into_iter()
+            .map(|config| {
+                let mut chain = Chain::new();
+                if !config.scripts.is_empty() {
+                    chain = chain.layer(Scripts::new(config.scripts)?);
+                }
+                if let Some(templates) = &config.templates {
+                    chain = chain.layer(Templates::new(templates, seeder.clone())?);
+                }
+
+                Ok(Vhost {
+                    name: config.name,
+                    hosts: config.hosts.iter().map(|h| h.to_lowercase()).collect(),
+                    chain,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { vhosts })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vhosts.is_empty()
+    }
+
+    fn find(&self, host: &str) -> Option<&Vhost> {
+        self.vhosts
+            .iter()
+            .find(|v| v.hosts.iter().any(|pattern| host_matches(pattern, host)))
+    }
+}
+
+impl Middleware for VirtualHosts {
+    fn call<'a>(&'a self, req: &'a Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        Box::pin(async move {
+            let host = req.url.host_str().unwrap_or_default();
+            match self.find(host) {
+                Some(vhost) => {
+                    info!("{} host {} => vhost {}", req.requester(), host, vhost.name);
+                    next.run_nested(&vhost.chain, req).await
+                }
+                None => {
+                    info!("{} host {} => no vhost", req.requester(), host);
+                    next.run(req).await
+                }
+            }
+        })
+    }
+}
+
+/// matches a lowercase hostname against an exact pattern, '*.suffix' for
+/// any subdomain of suffix, or '*' for anything.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix('*') {
+        Some("") => true,
+        Some(suffix) if suffix.starts_with('.') => {
+            host.len() > suffix.len() && host.ends_with(suffix)
+        }
+        _ => pattern == host,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_host_matches() {
+        let cases = vec![
+            ("example.com", "example.com", true),
+            ("example.com", "www.example.com", false),
+            ("*.example.com", "admin.example.com", true),
+            ("*.example.com", "a.b.example.com", true),
+            ("*.example.com", "example.com", false),
+            ("*.example.com", "badexample.com", false),
+            ("*", "10.0.0.1", true),
+        ];
+
+        for (pattern, host, expected) in cases {
+            assert_eq!(
+                expected,
+                host_matches(pattern, host),
+                "pattern {} host {}",
+                pattern,
+                host
+            );
+        }
+    }
+
+    #[test]
+    fn test_first_vhost_wins() {
+        let vhosts = VirtualHosts::new(
+            vec![
+                VhostConfig {
+                    name: "admin".to_string(),
+                    hosts: vec!["Admin.Example.com".to_string()],
+                    scripts: vec![],
+                    templates: None,
+                },
+                VhostConfig {
+                    name: "public".to_string(),
+                    hosts: vec!["*.example.com".to_string()],
+                    scripts: vec![],
+                    templates: None,
+                },
+            ],
+            Seeder::new("seed", Default::default()),
+        )
+        .unwrap();
+
+        assert_eq!("admin", vhosts.find("admin.example.com").unwrap().name);
+        assert_eq!("public", vhosts.find("www.example.com").unwrap().name);
+        assert!(vhosts.find("example.org").is_none());
+    }
+}
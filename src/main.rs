@@ -1,16 +1,18 @@
-mod metrics;
-mod router;
+mod replay;
 mod runtime;
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
 
+use chrono::Utc;
 use log::LevelFilter;
 use pretty_env_logger::env_logger::Target;
 use structopt::StructOpt;
-use tokio::io::BufReader;
-use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
 
-use httpot::{http::request, prelude::*};
+use httpot::{
+    config::Config, eventlog, fetch, hub, hub::Uplink, metrics, prelude::*, probe, report,
+    server::stored_exchanges, Honeypot,
+};
 
 #[derive(Debug, Clone, StructOpt)]
 #[structopt(name = "httpot", about = "HTTP [honeyp]ot")]
@@ -21,11 +23,74 @@ struct Opt {
     #[structopt(long = "log-target", default_value = "stderr", parse(try_from_str = httpot::util::logtarget_parse))]
     log_target: Target,
 
+    #[structopt(long = "config", short = "c")]
+    /// TOML config file
+    config: Option<PathBuf>,
+
     #[structopt(long = "metrics-addr")]
     /// prometheus metrics addr
     metrics_addr: Option<SocketAddr>,
 
-    listen_addr: SocketAddr,
+    #[structopt(long = "max-response-delay-ms", default_value = "0")]
+    /// responses are delayed by a random amount of time up to this value
+    max_response_delay_ms: u64,
+
+    /// required unless a subcommand is given
+    listen_addr: Option<SocketAddr>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+enum Command {
+    /// Replays stored pcap captures and HAR archives through the router
+    /// without listening, printing how each response differs from the one
+    /// recorded
+    Replay {
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Summarizes stored HAR archives or pcap captures, by default those
+    /// the config writes, as a Markdown report
+    Report {
+        #[structopt(long = "hours", default_value = "24")]
+        /// hours before now the report covers
+        hours: i64,
+
+        #[structopt(long = "html")]
+        /// render HTML rather than Markdown
+        html: bool,
+
+        #[structopt(long = "output", short = "o")]
+        /// file written instead of stdout
+        output: Option<PathBuf>,
+
+        /// archives, captures, or directories of them
+        inputs: Vec<PathBuf>,
+    },
+    /// Manages a fleet of sensors, serving their configs and storing their
+    /// events
+    Hub {
+        /// address sensors connect to
+        addr: SocketAddr,
+    },
+    /// Probes a running instance as scanners fingerprint web servers,
+    /// reporting anything which gives it away
+    Fingerprint {
+        #[structopt(long = "timeout-secs", default_value = "10")]
+        /// how long each probe waits for an answer
+        timeout_secs: u64,
+
+        /// address of the instance, such as 127.0.0.1:8080
+        addr: String,
+    },
+    /// Verifies the event log's chain and anchors, by default the log the
+    /// config writes, with the config's key
+    Verify {
+        /// event log to verify
+        path: Option<PathBuf>,
+    },
 }
 
 #[tokio::main]
@@ -33,17 +98,127 @@ async fn main() -> Result<()> {
     let opt = Opt::from_args();
     runtime::logging(&opt.log_level, &opt.log_target);
 
-    tokio::select!(
-        res = listen_loop(opt.listen_addr) => {
-            error!("primary listen loop exited unexpectedly");
-            res?;
+    let mut config = match &opt.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    let listen_addr = match (opt.command, opt.listen_addr) {
+        (Some(Command::Replay { inputs }), _) => return replay::run(&inputs, config).await,
+        (
+            Some(Command::Report {
+                hours,
+                html,
+                output,
+                mut inputs,
+            }),
+            _,
+        ) => {
+            if inputs.is_empty() {
+                inputs = stored_exchanges(&config)?;
+            }
+            let until = Utc::now();
+            let since = until - chrono::Duration::hours(hours);
+            let format = match html {
+                true => report::Format::Html,
+                false => report::Format::Markdown,
+            };
+            let report = report::gather(&inputs, since, until, &config.parser).await?;
+            let top = config.report.unwrap_or_default().top;
+            let rendered = report.render(format, top);
+            match output {
+                Some(path) => std::fs::write(path, rendered)?,
+                None => print!("{}", rendered),
+            }
+            return Ok(());
+        }
+        (Some(Command::Verify { path }), _) => {
+            let event_log = config.event_log.unwrap_or_default();
+            let path = path.unwrap_or(event_log.path);
+            let verified = eventlog::verify(&path, event_log.key.as_deref())
+                .map_err(|e| anyhow!("{} failed verification: {}", path.display(), e))?;
+            println!(
+                "{}: {} records and {} anchors verified, head {}",
+                path.display(),
+                verified.records,
+                verified.anchors,
+                verified.head
+            );
+            return Ok(());
+        }
+        (Some(Command::Fingerprint { timeout_secs, addr }), _) => {
+            let replies = probe::probe(&addr, Duration::from_secs(timeout_secs)).await?;
+            let findings = probe::check(&replies, Utc::now());
+            for finding in &findings {
+                println!("{}", finding);
+            }
+            let tells = findings
+                .iter()
+                .filter(|f| f.verdict == probe::Verdict::Tell)
+                .count();
+            ensure!(tells == 0, "{} found {} tells", addr, tells);
+            return Ok(());
+        }
+        (Some(Command::Hub { addr }), _) => {
+            let hub = match config.hub {
+                Some(hub) => hub,
+                None => bail!("a hub requires a [hub] config"),
+            };
+            std::fs::create_dir_all(&hub.configs)?;
+            tokio::select!(
+                res = hub::serve(hub, addr) => res?,
+                res = runtime::interrupt() => {
+                    warn!("signal received");
+                    res?;
+                }
+            );
+            return Ok(());
+        }
+        (None, Some(addr)) => addr,
+        (None, None) => bail!("a listen address is required"),
+    };
+
+    let (tx, rx) = mpsc::channel(1);
+    let uplink = config.sensor.clone().map(|sensor| {
+        let name = sensor
+            .name
+            .clone()
+            .unwrap_or_else(|| listen_addr.to_string());
+        Uplink::spawn(sensor, name, tx)
+    });
+    let mut updates = Updates {
+        rx,
+        applied: None,
+        dir: match opt.config.as_ref().and_then(|p| p.parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::from("."),
         },
+    };
+    let max_response_delay = Duration::from_millis(opt.max_response_delay_ms);
+    let metrics_config = config.metrics.clone();
+    let serving = async {
+        while let Some(next) = serve(
+            config,
+            listen_addr,
+            max_response_delay,
+            uplink.clone(),
+            &mut updates,
+        )
+        .await?
+        {
+            config = next;
+        }
+        Ok::<_, anyhow::Error>(())
+    };
+
+    tokio::select!(
+        res = serving => res?,
         res = runtime::interrupt() => {
             warn!("signal received");
             res?;
             return Ok(());
         }
-        res = metrics::run(opt.metrics_addr) => {
+        res = metrics::run(opt.metrics_addr, metrics_config) => {
             error!("metrics loop exited unexpectedly");
             res?;
         },
@@ -52,80 +227,76 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-async fn listen_loop(addr: SocketAddr) -> Result<()> {
-    let listener = TcpListener::bind(addr).await?;
-    info!("listening on {}", &addr);
+/// Configs sent by the hub, when this is a sensor.
+struct Updates {
+    rx: mpsc::Receiver<String>,
+    // hash of the config last applied, which the hub resends on reconnect
+    applied: Option<String>,
+    // where relative paths in configs are resolved from
+    dir: PathBuf,
+}
 
-    loop {
-        let socket = listener.accept().await;
-        match socket {
-            Err(e) => {
-                warn!("failed to accept conn: {}", e);
+impl Updates {
+    /// the next config which differs from the current one. Its sensor
+    /// section is the current one's, so the hub can't strand the sensor,
+    /// and configs naming local files or running code are ignored.
+    async fn next(&mut self, current: &Config) -> Config {
+        loop {
+            let raw = match self.rx.recv().await {
+                Some(raw) => raw,
+                None => std::future::pending().await,
+            };
+            let hash = fetch::sha256_hex(raw.as_bytes());
+            if self.applied.as_ref() == Some(&hash) {
                 continue;
             }
-            Ok((socket, _)) => tokio::spawn(async move {
-                let remote = socket
-                    .peer_addr()
-                    .map(|s| s.to_string())
-                    .unwrap_or_else(|e| format!("'unknown addr {}'", e));
-
-                match process_socket(socket).await {
-                    Ok(_) => info!("session with {} ended successfully", remote),
-                    Err(e) => info!("session with {} errored: {}", remote, e),
+            match Config::parse(&raw).and_then(|config| {
+                config.ensure_pushable()?;
+                Ok(config)
+            }) {
+                Ok(mut config) => {
+                    config.resolve_paths(&self.dir);
+                    config.sensor = current.sensor.clone();
+                    info!("applying config sha256={} from the hub", hash);
+                    self.applied = Some(hash);
+                    return config;
                 }
-            }),
-        };
+                Err(e) => warn!("ignoring config sha256={} from the hub: {}", hash, e),
+            }
+        }
     }
 }
 
-async fn process_socket(mut s: TcpStream) -> Result<()> {
-    let addr = s.peer_addr()?;
-
-    debug!("get socket start...");
-    s.readable().await?;
-
-    let req = metrics::observe_request(request::parse_request(&addr, &mut BufReader::new(&mut s)))
-        .await?;
-
-    info!(
-        "{: <8} {: <20} ==> {: <8} {} bytes {}",
-        req.requester(),
-        truncate(
-            &req.headers
-                .get_all(&vec!["User-Agent", "user-agent"])
-                .into_iter()
-                .next()
-                .cloned()
-                .unwrap_or_else(|| "Unknown".to_string()),
-            20
-        ),
-        req.method.to_string(),
-        req.body.len(),
-        truncate(req.url.path(), 20),
-    );
-
-    let mut resp = router::router(s, &req)?;
-    resp.send().await?;
-
-    info!(
-        "{: <8} <== {: <4} {: >8} bytes",
-        req.requester(),
-        resp.status_code().to_string(),
-        resp.len(),
-    );
-
-    // close conn
-    Ok(())
-}
-
-fn truncate(s: &str, max_chars: usize) -> String {
-    if s.len() <= max_chars - 3 {
-        return s.to_string();
+/// serves the config until the hub sends another, which is returned once
+/// everything serving this one has stopped. Returns None if a listener
+/// exits.
+async fn serve(
+    mut config: Config,
+    listen_addr: SocketAddr,
+    max_response_delay: Duration,
+    uplink: Option<Uplink>,
+    updates: &mut Updates,
+) -> Result<Option<Config>> {
+    let current = config.clone();
+    if let Some(cluster) = config.cluster.as_mut() {
+        let sensor = cluster
+            .sensor
+            .get_or_insert_with(|| listen_addr.to_string());
+        info!("sharing state with the fleet as sensor {:?}", sensor);
+    }
+    let mut builder = Honeypot::builder()
+        .config(config)
+        .max_response_delay(max_response_delay);
+    if let Some(uplink) = uplink {
+        builder = builder.uplink(uplink);
     }
 
-    format!(
-        "{}...{}",
-        s[0..(max_chars - 3) / 2].to_string(),
-        s[(s.len() - (max_chars - 3) / 2)..s.len()].to_string()
-    )
+    let mut next = None;
+    builder
+        .build()?
+        .run_until(listen_addr, async {
+            next = Some(updates.next(&current).await);
+        })
+        .await?;
+    Ok(next)
 }
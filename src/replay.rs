@@ -0,0 +1,87 @@
+use std::path::PathBuf;
+
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+
+use httpot::{
+    config::Config,
+    http::{request, response::StatusCode},
+    prelude::*,
+    replay, Honeypot,
+};
+
+/// replays the stored exchanges through the configured chain, printing
+/// responses which differ from those recorded. Errors if any do, so corpora
+/// can gate persona changes.
+pub(crate) async fn run(inputs: &[PathBuf], mut config: Config) -> Result<()> {
+    // nothing is fetched or recorded on a replay's behalf
+    config.fetch = None;
+    config.har = None;
+    config.event_log = None;
+    config.cluster = None;
+    config.capture = None;
+    let (parser, persona) = (config.parser.clone(), config.persona);
+    let honeypot = Honeypot::builder().config(config).build()?;
+
+    // responses need a connection, but are never sent on it
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let (mut replayed, mut differ, mut unrecorded) = (0, 0, 0);
+    for input in inputs {
+        for exchange in replay::load(input)? {
+            let (conn, accepted) = tokio::join!(TcpStream::connect(addr), listener.accept());
+            let (conn, _peer) = (conn?, accepted?);
+
+            let req = request::parse_request_with(
+                &exchange.remote,
+                &mut BufReader::new(&exchange.request[..]),
+                &parser,
+            )
+            .await;
+            let (summary, resp) = match req {
+                Ok(req) => (
+                    format!("{} {}", req.method.to_string(), req.target),
+                    honeypot.respond(conn, &req).await?,
+                ),
+                Err(e) => {
+                    let mut resp = persona.error(conn, StatusCode::BadRequest, None).build()?;
+                    persona.dress(resp.headers_mut());
+                    (format!("unparseable request ({})", e), resp)
+                }
+            };
+            replayed += 1;
+
+            let recorded = match &exchange.response {
+                Some(recorded) => recorded,
+                None => {
+                    unrecorded += 1;
+                    println!(
+                        "{}: {} => {}, nothing recorded",
+                        exchange.source,
+                        summary,
+                        resp.code()
+                    );
+                    continue;
+                }
+            };
+            let diffs = replay::diff(recorded, &resp);
+            if !diffs.is_empty() {
+                differ += 1;
+                println!("{}: {}", exchange.source, summary);
+                for diff in diffs {
+                    println!("    {}", diff);
+                }
+            }
+        }
+    }
+
+    println!(
+        "replayed {} exchanges: {} differ, {} had no recorded response",
+        replayed, differ, unrecorded
+    );
+    if differ > 0 {
+        bail!("{} of {} responses differ", differ, replayed);
+    }
+    Ok(())
+}